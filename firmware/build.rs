@@ -1,5 +1,15 @@
+use std::path::PathBuf;
+use std::{env, fs};
+
+// The certificate parser the firmware verifies the server with, to check
+// `TLS_CA` at build time
+#[allow(dead_code)]
+#[path = "src/x509.rs"]
+mod x509;
+
 fn main() {
     linker_be_nice();
+    embed_tls_ca();
     // make sure linkall.x is the last linker script (otherwise might cause problems with flip-link)
     println!("cargo:rustc-link-arg=-Tlinkall.x");
 }
@@ -68,3 +78,53 @@ fn linker_be_nice() {
         std::env::current_exe().unwrap().display()
     );
 }
+
+/// Write the root certificate from `TLS_CA` (a PEM or DER file) to `OUT_DIR`
+/// as DER for `src/tls.rs` to embed, or an empty file without one
+fn embed_tls_ca() {
+    println!("cargo:rerun-if-env-changed=TLS_CA");
+    let der = match env::var("TLS_CA") {
+        Ok(path) => {
+            println!("cargo:rerun-if-changed={path}");
+            let file = fs::read(&path).unwrap_or_else(|e| panic!("TLS_CA: can't read {path}: {e}"));
+            let der = pem_to_der(&file).unwrap_or(file);
+            if let Err(e) = x509::Certificate::parse(&der) {
+                panic!("TLS_CA: {path} isn't a usable certificate ({e:?})");
+            }
+            der
+        }
+        Err(_) => Vec::new(),
+    };
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("tls_ca.der");
+    fs::write(out, der).unwrap();
+}
+
+/// Decode the first certificate in a PEM file, or None if it isn't PEM
+fn pem_to_der(pem: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(pem).ok()?;
+    let body = text
+        .split("-----BEGIN CERTIFICATE-----")
+        .nth(1)?
+        .split("-----END CERTIFICATE-----")
+        .next()?;
+    let mut der = Vec::new();
+    let (mut bits, mut count) = (0u32, 0);
+    for c in body.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => return None,
+        };
+        bits = (bits << 6 | u32::from(value)) & 0xffff;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            der.push((bits >> count) as u8);
+        }
+    }
+    Some(der)
+}
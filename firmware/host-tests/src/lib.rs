@@ -0,0 +1,64 @@
+//! Host build of the firmware's hardware-free modules
+//!
+//! The firmware only builds for the ESP32-S3, so its unit tests can't run
+//! there. The modules below have no hardware dependencies, or like the panel
+//! driver only use `embedded-hal` traits, and are compiled here straight from
+//! the firmware sources, keeping their `crate::` paths, so their tests, and the
+//! property tests in [`proptests`], run with a plain `cargo test`.
+
+#![no_std]
+// Items the firmware uses elsewhere look unused in this subset
+#![allow(dead_code)]
+
+extern crate alloc;
+#[cfg(test)]
+extern crate std;
+
+#[path = "../../src/cache_layout.rs"]
+pub mod cache_layout;
+#[path = "../../src/demo.rs"]
+pub mod demo;
+#[path = "../../src/dns_cache.rs"]
+pub mod dns_cache;
+#[path = "../../src/error_screen.rs"]
+pub mod error_screen;
+#[path = "../../src/framebuffer.rs"]
+pub mod framebuffer;
+#[path = "../../src/freeze.rs"]
+pub mod freeze;
+#[path = "../../src/inflate.rs"]
+pub mod inflate;
+#[path = "../../src/log_ring.rs"]
+pub mod log_ring;
+#[path = "../../src/pin_state.rs"]
+pub mod pin_state;
+#[path = "../../src/png.rs"]
+pub mod png;
+#[path = "../../src/render.rs"]
+pub mod render;
+#[path = "../../src/resume.rs"]
+pub mod resume;
+#[path = "../../src/screenshot.rs"]
+pub mod screenshot;
+#[path = "../../src/shtc3.rs"]
+pub mod shtc3;
+#[path = "../../src/telemetry.rs"]
+pub mod telemetry;
+#[path = "../../src/text.rs"]
+pub mod text;
+#[path = "../../src/thermal.rs"]
+pub mod thermal;
+#[path = "../../src/wake.rs"]
+pub mod wake;
+#[path = "../../src/watchdog.rs"]
+pub mod watchdog;
+#[path = "../../src/widget.rs"]
+pub mod widget;
+#[path = "../../src/x509.rs"]
+pub mod x509;
+
+#[path = "../../src/epd/mod.rs"]
+pub mod epd;
+
+#[cfg(test)]
+mod proptests;
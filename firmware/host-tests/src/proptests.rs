@@ -0,0 +1,201 @@
+//! Property tests over the firmware modules
+//!
+//! These sit beside the modules rather than under `tests/` so they can reach
+//! `pub(crate)` items such as the cache layout.
+
+use alloc::format;
+use alloc::string::String as StdString;
+use alloc::vec::Vec;
+
+use heapless::String;
+use proptest::prelude::*;
+
+use crate::cache_layout::{
+    IMAGE_EXTENSION, KEY_EXTENSION, LruIndex, bucket_name, cache_filename, cache_id, id_bucket,
+    image_footer, parse_bucket_dir, parse_cache_filename, verify_footer,
+};
+use crate::epd::{HEIGHT, Rect, WIDTH};
+use crate::framebuffer::Framebuffer;
+use crate::widget::{
+    ITEM_JSON_LEN, MAX_PATH_LEN, MAX_TITLE_LEN, WidgetItem, WidgetWidth, parse_widget_data,
+    write_item_json,
+};
+
+/// EPD color code for each PNG palette index, anything past 5 showing white
+fn epd_code(palette_idx: u8) -> u8 {
+    [0, 1, 3, 2, 5, 6]
+        .get(palette_idx as usize)
+        .copied()
+        .unwrap_or(1)
+}
+
+/// 4-bit color code of the pixel at (x, y)
+fn pixel(framebuffer: &Framebuffer, x: u32, y: u32) -> u8 {
+    let byte = framebuffer.as_slice()[(y * WIDTH / 2 + x / 2) as usize];
+    if x.is_multiple_of(2) {
+        byte >> 4
+    } else {
+        byte & 0x0F
+    }
+}
+
+/// Path or title text without control characters, which the parser turns into spaces
+fn text(max_chars: usize) -> impl Strategy<Value = StdString> {
+    proptest::string::string_regex(&format!("[^\\p{{Cc}}]{{0,{}}}", max_chars)).unwrap()
+}
+
+/// An item as the server lists it
+fn widget_item() -> impl Strategy<Value = WidgetItem> {
+    (
+        text(MAX_PATH_LEN / 4).prop_filter("paths are never empty", |path| !path.is_empty()),
+        any::<bool>(),
+        proptest::option::of(text(MAX_PATH_LEN / 4)),
+        proptest::option::of(any::<u32>()),
+        text(MAX_TITLE_LEN / 4),
+    )
+        .prop_map(|(path, full, cache_key, ttl, title)| {
+            let path: String<MAX_PATH_LEN> = path.as_str().try_into().unwrap();
+            WidgetItem {
+                cache_key: match cache_key {
+                    Some(key) => key.as_str().try_into().unwrap(),
+                    None => path.clone(),
+                },
+                path,
+                width: if full {
+                    WidgetWidth::Full
+                } else {
+                    WidgetWidth::Half
+                },
+                ttl,
+                title: title.as_str().try_into().unwrap(),
+            }
+        })
+}
+
+/// Serialize items the way the server and the SD card cache list them
+fn widget_json(items: &[WidgetItem]) -> StdString {
+    let objects: Vec<StdString> = items
+        .iter()
+        .map(|item| {
+            let mut json = String::<ITEM_JSON_LEN>::new();
+            write_item_json(item, &mut json).unwrap();
+            json.as_str().into()
+        })
+        .collect();
+    format!("[{}]", objects.join(","))
+}
+
+proptest! {
+    #[test]
+    fn write_row_round_trips(
+        x_offset in (0..WIDTH / 2).prop_map(|x| x * 2),
+        y in 0..HEIGHT,
+        pixels in proptest::collection::vec(0u8..8, 0..=WIDTH as usize),
+    ) {
+        let pixels = &pixels[..pixels.len().min((WIDTH - x_offset) as usize)];
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.write_row(x_offset, y, pixels);
+
+        for x in 0..WIDTH {
+            let expected = match x.checked_sub(x_offset) {
+                Some(i) if (i as usize) < pixels.len() => epd_code(pixels[i as usize]),
+                // Untouched pixels stay white
+                _ => 1,
+            };
+            prop_assert_eq!(pixel(&framebuffer, x, y), expected, "x = {}", x);
+        }
+    }
+
+    #[test]
+    fn packed_rows_round_trip(
+        (x, width) in (0..WIDTH / 2).prop_flat_map(|x| (Just(x * 2), (1..=WIDTH / 2 - x).prop_map(|w| w * 2))),
+        height in 1..=HEIGHT,
+        seed in any::<u8>(),
+    ) {
+        let data: Vec<u8> = (0..(width * height / 2) as usize)
+            .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+            .collect();
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.write_packed_rows(x, width, &data);
+
+        let rect = Rect::new(x as u16, 0, width as u16, height as u16);
+        prop_assert!(rect.is_valid());
+        let mut output = alloc::vec![0; rect.buffer_size()];
+        framebuffer.extract_rect(&rect, &mut output);
+        prop_assert_eq!(output, data);
+    }
+
+    #[test]
+    fn widget_items_round_trip(items in proptest::collection::vec(widget_item(), 0..8)) {
+        let parsed = parse_widget_data(&widget_json(&items)).unwrap();
+        prop_assert_eq!(parsed.as_slice(), items.as_slice());
+    }
+
+    #[test]
+    fn parse_never_panics(json in "\\PC*") {
+        let _ = parse_widget_data(&json);
+    }
+
+    #[test]
+    fn parse_adversarial_json(
+        items in proptest::collection::vec(widget_item(), 1..4),
+        cut in any::<prop::sample::Index>(),
+        depth in 0usize..64,
+        junk in "[\\[\\]{}\",:\\\\u0-9a-f ]{0,16}",
+    ) {
+        let json = widget_json(&items);
+
+        // Truncated mid-download, at any character
+        let boundaries: Vec<usize> = json.char_indices().map(|(i, _)| i).collect();
+        let _ = parse_widget_data(&json[..boundaries[cut.index(boundaries.len())]]);
+
+        // Deeply nested or unbalanced objects, and stray escapes and quotes
+        let nested = format!("[{}{}{}]", "{\"path\":".repeat(depth), junk, "}".repeat(depth / 2));
+        let _ = parse_widget_data(&nested);
+        let spliced = format!("[{},{}]", &json[1..json.len() - 1], junk);
+        if let Ok(parsed) = parse_widget_data(&spliced) {
+            // The junk is at most one more item
+            prop_assert!(parsed.len() <= items.len() + 1);
+        }
+    }
+
+    #[test]
+    fn cache_filenames_round_trip(key in "\\PC{1,48}") {
+        let id = cache_id(&key);
+        let bucket = id_bucket(id);
+        prop_assert_eq!(parse_bucket_dir(&bucket_name(bucket)), Some(bucket));
+        for extension in [IMAGE_EXTENSION, KEY_EXTENSION] {
+            let filename = cache_filename(id, extension);
+            prop_assert_eq!(parse_cache_filename(bucket, &filename), Some((id, extension)));
+            // FAT 8.3 names
+            prop_assert_eq!(filename.len(), 12);
+        }
+    }
+
+    #[test]
+    fn image_footer_catches_corruption(
+        data in proptest::collection::vec(any::<u8>(), 0..512),
+        flip in any::<prop::sample::Index>(),
+        bit in 0u8..8,
+    ) {
+        let mut file = data.clone();
+        file.extend_from_slice(&image_footer(&data));
+        prop_assert_eq!(verify_footer(&file), Some(data.len()));
+
+        let flip = flip.index(file.len());
+        file[flip] ^= 1 << bit;
+        prop_assert_eq!(verify_footer(&file), None);
+    }
+
+    #[test]
+    fn lru_index_round_trips(accesses in proptest::collection::vec((0u8..2, 0u64..1 << 40), 0..64)) {
+        let mut lru = LruIndex::default();
+        for &(orient, id) in &accesses {
+            lru.touch(orient, id);
+        }
+        let bytes = lru.to_bytes();
+        prop_assert_eq!(LruIndex::parse(&bytes), lru);
+        // Torn writes start over rather than misreading records
+        let _ = LruIndex::parse(&bytes[..bytes.len() / 2]);
+    }
+}
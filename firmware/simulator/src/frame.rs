@@ -0,0 +1,270 @@
+//! One simulated frame: fetches from the server like the device and composes
+//! the panel image with the firmware's rendering code
+
+use std::io::Read;
+
+use log::{info, warn};
+
+use crate::battery::{self, BatteryStatus};
+use crate::epd::{Color, HEIGHT, WIDTH};
+use crate::framebuffer::Framebuffer;
+use crate::render::{self, RenderError};
+use crate::screenshot::EPD_PALETTE;
+use crate::text;
+use crate::widget::{Orientation, WidgetData, parse_widget_data};
+
+/// Sent as `X-Firmware-Version`, so the server can tell simulated frames apart
+const FIRMWARE_VERSION: &str = concat!("simulator-", env!("CARGO_PKG_VERSION"));
+
+/// `Accept` header for images in the packed 4bpp format, with PNG as a fallback
+const RAW_IMAGE_ACCEPT: &str = "application/x-epd-4bpp, image/png;q=0.5";
+
+/// Largest response body read, matching the firmware's PNG receive buffer
+const MAX_BODY_SIZE: u64 = 256 * 1024;
+
+/// Where and how a simulated frame fetches
+pub struct Settings {
+    /// Base URL of the server, without a trailing slash
+    pub server_url: String,
+    pub widget: String,
+    pub device_id: String,
+    pub battery: BatteryStatus,
+    /// Ask for the packed 4bpp format, like `RAW_IMAGES=1` firmware builds
+    pub raw_images: bool,
+}
+
+/// Frame state: the widget items and which ones are on screen
+pub struct Frame {
+    settings: Settings,
+    agent: ureq::Agent,
+    items: Box<WidgetData>,
+    index: usize,
+    pub orientation: Orientation,
+    pub framebuffer: Framebuffer,
+}
+
+impl Frame {
+    pub fn new(settings: Settings, orientation: Orientation) -> Self {
+        Self {
+            settings,
+            agent: ureq::AgentBuilder::new().build(),
+            items: Box::default(),
+            index: 0,
+            orientation,
+            framebuffer: Framebuffer::new(),
+        }
+    }
+
+    /// Fetch the widget data again and redraw from the first item
+    pub fn reload(&mut self) {
+        let path = format!("/{}", self.settings.widget);
+        let items = self
+            .get(&path, None)
+            .and_then(|body| Ok(parse_widget_data(std::str::from_utf8(&body)?)?));
+        match items {
+            Ok(items) => {
+                info!("Fetched {} items", items.len());
+                self.items = items;
+                self.index = 0;
+                self.draw();
+            }
+            Err(e) => {
+                warn!("Widget data fetch failed: {}", e);
+                self.items.clear();
+                text::draw_message(
+                    &mut self.framebuffer,
+                    self.orientation,
+                    "Can't reach server",
+                    Some(&e.to_string()),
+                );
+            }
+        }
+    }
+
+    /// Show the next items
+    pub fn advance(&mut self) {
+        if !self.items.is_empty() {
+            self.index = (self.index + self.items_shown()) % self.items.len();
+            self.draw();
+        }
+    }
+
+    /// Switch orientation and redraw the current items
+    pub fn flip(&mut self) {
+        self.orientation = self.orientation.toggle();
+        self.draw();
+    }
+
+    /// Number of items on screen: two halves in horizontal mode, unless a
+    /// full-width item takes the whole panel
+    fn items_shown(&self) -> usize {
+        let full_width = |i: usize| self.items[i % self.items.len()].is_full_width();
+        match self.orientation {
+            Orientation::Horizontal if self.items.len() > 1 => {
+                if full_width(self.index) || full_width(self.index + 1) {
+                    1
+                } else {
+                    2
+                }
+            }
+            _ => 1,
+        }
+    }
+
+    /// Fetch and render the items on screen, then draw the battery indicator
+    pub fn draw(&mut self) {
+        self.framebuffer.clear(Color::White);
+        if self.items.is_empty() {
+            text::draw_message(
+                &mut self.framebuffer,
+                self.orientation,
+                "No items",
+                Some("The widget listed nothing to show"),
+            );
+            return;
+        }
+
+        for slot in 0..self.items_shown() {
+            let item = self.items[(self.index + slot) % self.items.len()].clone();
+            info!("Rendering {} in slot {}", item.label(), slot);
+            let result = render::image_path(&self.settings.widget, item.as_str(), self.orientation)
+                .ok_or_else(|| "image path too long".into())
+                .and_then(|path| {
+                    let accept = self.settings.raw_images.then_some(RAW_IMAGE_ACCEPT);
+                    self.get(&path, accept)
+                })
+                .and_then(|body| {
+                    render::render_image_to_framebuffer(
+                        &body,
+                        &mut self.framebuffer,
+                        slot as u8,
+                        self.orientation,
+                    )
+                    .map_err(|e| match e {
+                        RenderError::Png(msg) | RenderError::Epd(msg) => msg.into(),
+                    })
+                });
+            if let Err(e) = result {
+                warn!("Image {} failed: {}", item.label(), e);
+                render::fill_half(&mut self.framebuffer, if slot == 0 { 0 } else { 400 });
+            }
+        }
+
+        // Placed where the firmware places it in panel coordinates
+        let vertical = self.orientation == Orientation::Vertical;
+        let (bat_w, _bat_h) = battery::battery_dimensions(vertical);
+        let battery_x = if vertical {
+            WIDTH as u16 - bat_w - 8
+        } else {
+            (WIDTH as u16 - bat_w) / 2
+        };
+        battery::draw_battery(
+            self.framebuffer.as_mut_slice(),
+            battery_x,
+            8,
+            self.settings.battery,
+            vertical,
+        );
+    }
+
+    /// GET a path from the server with the headers the firmware sends
+    fn get(&self, path: &str, accept: Option<&str>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let url = format!("{}{}", self.settings.server_url, path);
+        info!("GET {}", url);
+        let mut request = self
+            .agent
+            .get(&url)
+            .set("X-Device-Id", &self.settings.device_id)
+            .set("X-Firmware-Version", FIRMWARE_VERSION)
+            .set(
+                "X-Battery-Percent",
+                &self.settings.battery.percentage.to_string(),
+            );
+        if let Some(accept) = accept {
+            request = request.set("Accept", accept);
+        }
+        let mut body = Vec::new();
+        request
+            .call()?
+            .into_reader()
+            .take(MAX_BODY_SIZE)
+            .read_to_end(&mut body)?;
+        Ok(body)
+    }
+
+    /// The panel as it looks on the wall, as `0RGB` pixels
+    ///
+    /// Horizontal images are drawn upside down on the panel, and vertical ones
+    /// rotated a quarter turn, so the view turns them back: 800x480 in
+    /// horizontal mode and 480x800 in vertical mode.
+    pub fn view(&self) -> (Vec<u32>, usize, usize) {
+        let (width, height) = (WIDTH as usize, HEIGHT as usize);
+        let fb = self.framebuffer.as_slice();
+        let panel_pixel = |x: usize, y: usize| {
+            let byte = fb[y * width / 2 + x / 2];
+            let code = if x.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+            let [r, g, b] = EPD_PALETTE
+                .get(code as usize)
+                .copied()
+                .unwrap_or(EPD_PALETTE[Color::White as usize]);
+            u32::from_be_bytes([0, r, g, b])
+        };
+        match self.orientation {
+            Orientation::Horizontal => {
+                let pixels = (0..width * height)
+                    .map(|i| panel_pixel(width - 1 - i % width, height - 1 - i / width))
+                    .collect();
+                (pixels, width, height)
+            }
+            // Undoes the server's counter-clockwise rotation: panel (x, y)
+            // holds image pixel (479 - y, x)
+            Orientation::Vertical => {
+                let pixels = (0..width * height)
+                    .map(|i| panel_pixel(i / height, height - 1 - i % height))
+                    .collect();
+                (pixels, height, width)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view() {
+        let settings = Settings {
+            server_url: String::new(),
+            widget: "concerts".into(),
+            device_id: "simulator".into(),
+            battery: BatteryStatus {
+                percentage: 100,
+                low: false,
+                charging: false,
+            },
+            raw_images: false,
+        };
+        let mut frame = Frame::new(settings, Orientation::Horizontal);
+        frame.framebuffer.set_pixel(0, 0, Color::Red);
+        let red = u32::from_be_bytes([0, 135, 19, 0]);
+
+        // Upside down on the horizontal panel
+        let (pixels, width, height) = frame.view();
+        assert_eq!((width, height), (800, 480));
+        assert_eq!(pixels[799 + 479 * 800], red);
+        assert_eq!(pixels.iter().filter(|&&px| px == red).count(), 1);
+
+        // The top left of a vertical image is the panel's bottom left
+        frame.orientation = Orientation::Vertical;
+        frame.framebuffer.clear(Color::White);
+        frame.framebuffer.set_pixel(0, 479, Color::Red);
+        let (pixels, width, height) = frame.view();
+        assert_eq!((width, height), (480, 800));
+        assert_eq!(pixels[0], red);
+    }
+}
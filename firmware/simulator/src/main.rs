@@ -0,0 +1,159 @@
+//! Frame simulator
+//!
+//! Runs a frame on the desktop against a real server: widget data and images
+//! are fetched the way the device fetches them, then parsed, decoded and drawn
+//! by the firmware's own modules (compiled here straight from `../src`) into
+//! the 800x480 4bpp framebuffer, which is shown in a window in the panel's
+//! colors. Layout bugs can be chased without flashing the device.
+//!
+//! Keys: `N` or `Space` shows the next items, `F` flips the orientation, `R`
+//! fetches the widget data again and `Esc` quits.
+//!
+//! Configured from the environment:
+//! - `SERVER_URL`: server to fetch from (`http://localhost:3000`)
+//! - `WIDGET`: widget to show (`concerts`)
+//! - `ORIENTATION`: `horiz` or `vert` (`horiz`)
+//! - `DEVICE_ID`: sent as `X-Device-Id` (`simulator`), for per-device settings
+//! - `BATTERY_PERCENT`: battery level drawn and reported (100)
+//! - `CHARGING=1`: draw the charging bolt
+//! - `RAW_IMAGES=1`: fetch the packed 4bpp format instead of PNGs
+//!
+//! With a path argument, the first frame is written there as a PNG (the format
+//! of device screenshots, in panel layout) instead of opening a window.
+
+// Items the firmware uses elsewhere look unused in this subset
+#![allow(dead_code)]
+
+extern crate alloc;
+
+use std::{env, fs, process};
+
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+
+use crate::battery::BatteryStatus;
+use crate::epd::{HEIGHT, WIDTH};
+use crate::frame::{Frame, Settings};
+use crate::widget::Orientation;
+
+mod frame;
+
+#[path = "../../src/battery.rs"]
+mod battery;
+#[path = "../../src/framebuffer.rs"]
+mod framebuffer;
+#[path = "../../src/inflate.rs"]
+mod inflate;
+#[path = "../../src/png.rs"]
+mod png;
+#[path = "../../src/render.rs"]
+mod render;
+#[path = "../../src/screenshot.rs"]
+mod screenshot;
+#[path = "../../src/text.rs"]
+mod text;
+#[path = "../../src/widget.rs"]
+mod widget;
+
+#[path = "../../src/epd/color.rs"]
+mod epd_color;
+#[path = "../../src/epd/geometry.rs"]
+mod epd_geometry;
+
+/// The panel types from the firmware's `epd` module, without its SPI driver
+mod epd {
+    pub use crate::epd_color::Color;
+    pub use crate::epd_geometry::{BUFFER_SIZE, HEIGHT, Rect, WIDTH};
+}
+
+/// An environment variable, or `default` if unset
+fn var(name: &str, default: &str) -> String {
+    env::var(name).unwrap_or_else(|_| default.into())
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let orientation = match var("ORIENTATION", "horiz").as_str() {
+        "horiz" => Orientation::Horizontal,
+        "vert" => Orientation::Vertical,
+        other => {
+            eprintln!("ORIENTATION must be horiz or vert, not {}", other);
+            process::exit(2);
+        }
+    };
+    let percentage = battery::parse_percent(env::var("BATTERY_PERCENT").ok().as_deref(), 100);
+    let settings = Settings {
+        server_url: var("SERVER_URL", "http://localhost:3000")
+            .trim_end_matches('/')
+            .into(),
+        widget: var("WIDGET", "concerts"),
+        device_id: var("DEVICE_ID", "simulator"),
+        battery: BatteryStatus {
+            percentage,
+            low: percentage <= 20,
+            charging: var("CHARGING", "0") == "1",
+        },
+        raw_images: var("RAW_IMAGES", "0") == "1",
+    };
+
+    let mut frame = Frame::new(settings, orientation);
+    frame.reload();
+
+    if let Some(path) = env::args().nth(1) {
+        let mut png = Vec::with_capacity(screenshot::PNG_SIZE);
+        let _ = screenshot::encode_png::<()>(&frame.framebuffer, |data| {
+            png.extend_from_slice(data);
+            Ok(())
+        });
+        if let Err(e) = fs::write(&path, png) {
+            eprintln!("Can't write {}: {}", path, e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let mut window = open_window(frame.orientation);
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        let (pixels, width, height) = frame.view();
+        if let Err(e) = window.update_with_buffer(&pixels, width, height) {
+            eprintln!("Can't draw the frame: {}", e);
+            process::exit(1);
+        }
+        for key in window.get_keys_pressed(KeyRepeat::No) {
+            match key {
+                Key::N | Key::Space => frame.advance(),
+                Key::F => {
+                    frame.flip();
+                    // The view turns with the frame
+                    window = open_window(frame.orientation);
+                }
+                Key::R => frame.reload(),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Open a window the shape of the frame in `orientation`
+fn open_window(orientation: Orientation) -> Window {
+    let (width, height) = match orientation {
+        Orientation::Horizontal => (WIDTH, HEIGHT),
+        Orientation::Vertical => (HEIGHT, WIDTH),
+    };
+    let title = format!("SawThat Frame ({})", orientation.as_str());
+    match Window::new(
+        &title,
+        width as usize,
+        height as usize,
+        WindowOptions::default(),
+    ) {
+        Ok(mut window) => {
+            window.set_target_fps(30);
+            window
+        }
+        Err(e) => {
+            eprintln!("Can't open a window: {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! AXP2101 PMIC driver
+//!
+//! Typed access to the parts of the PMIC the frame uses: the ALDO rails feeding
+//! the panel and SD card, the fuel gauge, the battery voltage and die
+//! temperature ADCs and the charger state.
+
+use core::ops::BitOr;
+use embedded_hal::i2c::I2c;
+
+/// AXP2101 I2C address
+pub const AXP2101_ADDR: u8 = 0x34;
+
+/// PMU status 1 (VBUS good, battery present)
+const PMU_STATUS1: u8 = 0x00;
+/// PMU status 2 (battery current direction, charger state)
+const PMU_STATUS2: u8 = 0x01;
+/// ADC channel enables
+const ADC_CHANNEL_CTRL: u8 = 0x30;
+/// Battery voltage ADC result, high 5 bits
+const ADC_VBAT_H: u8 = 0x34;
+/// Battery voltage ADC result, low 8 bits
+const ADC_VBAT_L: u8 = 0x35;
+/// Die temperature ADC result, high 6 bits
+const ADC_TDIE_H: u8 = 0x3C;
+/// Die temperature ADC result, low 8 bits
+const ADC_TDIE_L: u8 = 0x3D;
+/// LDO enable bits (ALDO1-4, BLDO1-2, CPUSLDO, DLDO1)
+const LDO_ONOFF_CTRL0: u8 = 0x90;
+/// ALDO1 voltage (ALDO2-4 follow)
+const ALDO1_VOLTAGE: u8 = 0x92;
+/// Battery percentage (0-100)
+const BAT_PERCENT: u8 = 0xA4;
+
+/// VBUS good bit in PMU status 1
+const VBUS_GOOD: u8 = 1 << 5;
+/// Battery voltage channel bit in the ADC channel enables
+const ADC_VBAT_ENABLE: u8 = 1 << 0;
+/// Die temperature channel bit in the ADC channel enables
+const ADC_TDIE_ENABLE: u8 = 1 << 4;
+
+/// Lowest and highest ALDO voltages (100mV steps)
+const ALDO_MIN_MV: u16 = 500;
+const ALDO_MAX_MV: u16 = 3500;
+
+/// Voltage of the panel and SD card rails
+pub const RAIL_MV: u16 = 3300;
+
+/// A set of LDOs, as laid out in the LDO enable register
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ldos(u8);
+
+impl Ldos {
+    pub const NONE: Ldos = Ldos(0);
+    pub const ALDO1: Ldos = Ldos(1 << 0);
+    pub const ALDO2: Ldos = Ldos(1 << 1);
+    pub const ALDO3: Ldos = Ldos(1 << 2);
+    pub const ALDO4: Ldos = Ldos(1 << 3);
+    /// All four ALDOs
+    pub const ALDOS: Ldos = Ldos(0x0F);
+    /// ALDO3 and ALDO4, the panel supply
+    pub const PANEL: Ldos = Ldos(0x0C);
+
+    /// Raw register bits
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// LDOs from raw register bits
+    pub fn from_bits(bits: u8) -> Self {
+        Ldos(bits)
+    }
+
+    /// Whether any LDO in `other` is in this set
+    pub fn intersects(self, other: Ldos) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Whether the set is empty
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// This set without the LDOs in `other`
+    pub fn without(self, other: Ldos) -> Ldos {
+        Ldos(self.0 & !other.0)
+    }
+}
+
+impl BitOr for Ldos {
+    type Output = Ldos;
+
+    fn bitor(self, rhs: Ldos) -> Ldos {
+        Ldos(self.0 | rhs.0)
+    }
+}
+
+/// What the charger is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChargeStatus {
+    /// Battery is being charged
+    Charging,
+    /// Running from the battery
+    Discharging,
+    /// Charge complete, running from USB
+    Full,
+    /// Neither charging nor discharging (e.g. no battery, or charging disabled)
+    Standby,
+}
+
+impl ChargeStatus {
+    /// Decode the PMU status registers
+    pub fn from_status(status1: u8, status2: u8) -> Self {
+        let charge_done = status2 & 0x07 == 0b100;
+        match (status2 >> 5) & 0x03 {
+            0b01 => ChargeStatus::Charging,
+            0b10 => ChargeStatus::Discharging,
+            _ if charge_done && status1 & VBUS_GOOD != 0 => ChargeStatus::Full,
+            _ => ChargeStatus::Standby,
+        }
+    }
+
+    /// Whether the frame is running from USB power
+    pub fn is_external_power(self) -> bool {
+        matches!(self, ChargeStatus::Charging | ChargeStatus::Full)
+    }
+}
+
+/// AXP2101 on an I2C bus
+pub struct Axp2101<I> {
+    i2c: I,
+}
+
+impl<I: I2c> Axp2101<I> {
+    pub fn new(i2c: I) -> Self {
+        Self { i2c }
+    }
+
+    /// Power up the ALDO rails (and only those), with the panel rails at 3.3V,
+    /// and start the battery voltage and die temperature ADCs
+    pub fn init(&mut self) -> Result<(), I::Error> {
+        self.set_aldo_voltage(3, RAIL_MV)?;
+        self.set_aldo_voltage(4, RAIL_MV)?;
+        self.set_enabled_ldos(Ldos::ALDOS)?;
+        let channels = self.read(ADC_CHANNEL_CTRL)?;
+        self.write(
+            ADC_CHANNEL_CTRL,
+            channels | ADC_VBAT_ENABLE | ADC_TDIE_ENABLE,
+        )
+    }
+
+    /// Set the output voltage of ALDO1-4, clamped to 0.5-3.5V
+    pub fn set_aldo_voltage(&mut self, aldo: u8, millivolts: u16) -> Result<(), I::Error> {
+        let register = ALDO1_VOLTAGE + aldo.clamp(1, 4) - 1;
+        let step = (millivolts.clamp(ALDO_MIN_MV, ALDO_MAX_MV) - ALDO_MIN_MV) / 100;
+        self.write(register, step as u8)
+    }
+
+    /// LDOs currently enabled
+    pub fn enabled_ldos(&mut self) -> Result<Ldos, I::Error> {
+        self.read(LDO_ONOFF_CTRL0).map(Ldos)
+    }
+
+    /// Enable exactly `ldos`
+    pub fn set_enabled_ldos(&mut self, ldos: Ldos) -> Result<(), I::Error> {
+        self.write(LDO_ONOFF_CTRL0, ldos.bits())
+    }
+
+    /// Switch on `ldos`, leaving the others as they are
+    pub fn enable_ldos(&mut self, ldos: Ldos) -> Result<(), I::Error> {
+        let enabled = self.enabled_ldos()?;
+        self.write(LDO_ONOFF_CTRL0, (enabled | ldos).bits())
+    }
+
+    /// Switch off `ldos`, leaving the others as they are
+    ///
+    /// Returns the LDOs still enabled, as read back afterwards.
+    pub fn disable_ldos(&mut self, ldos: Ldos) -> Result<Ldos, I::Error> {
+        let enabled = self.enabled_ldos()?;
+        self.write(LDO_ONOFF_CTRL0, enabled.without(ldos).bits())?;
+        self.enabled_ldos()
+    }
+
+    /// Battery percentage from the fuel gauge
+    pub fn battery_percent(&mut self) -> Result<u8, I::Error> {
+        self.read(BAT_PERCENT)
+    }
+
+    /// Battery voltage in millivolts
+    pub fn battery_voltage_mv(&mut self) -> Result<u16, I::Error> {
+        let high = self.read(ADC_VBAT_H)?;
+        let low = self.read(ADC_VBAT_L)?;
+        Ok(((high & 0x1F) as u16) << 8 | low as u16)
+    }
+
+    /// PMIC die temperature in whole degrees Celsius
+    ///
+    /// The frame does little work between wakes, so at wake this tracks the
+    /// temperature inside the frame closely enough to predict panel timing.
+    pub fn die_temperature_c(&mut self) -> Result<i8, I::Error> {
+        let high = self.read(ADC_TDIE_H)?;
+        let low = self.read(ADC_TDIE_L)?;
+        Ok(die_temperature_from_raw(
+            ((high & 0x3F) as u16) << 8 | low as u16,
+        ))
+    }
+
+    /// What the charger is doing
+    pub fn charge_status(&mut self) -> Result<ChargeStatus, I::Error> {
+        let status1 = self.read(PMU_STATUS1)?;
+        let status2 = self.read(PMU_STATUS2)?;
+        Ok(ChargeStatus::from_status(status1, status2))
+    }
+
+    fn read(&mut self, register: u8) -> Result<u8, I::Error> {
+        let mut buf = [0u8; 1];
+        self.i2c.write_read(AXP2101_ADDR, &[register], &mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn write(&mut self, register: u8, value: u8) -> Result<(), I::Error> {
+        self.i2c.write(AXP2101_ADDR, &[register, value])
+    }
+}
+
+/// Convert a die temperature ADC reading (22°C at 7274, -0.05°C per step)
+fn die_temperature_from_raw(raw: u16) -> i8 {
+    let celsius = 22 + (7274 - raw as i32) / 20;
+    celsius.clamp(i8::MIN as i32, i8::MAX as i32) as i8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_charge_status() {
+        const VBUS: u8 = VBUS_GOOD;
+        assert_eq!(
+            ChargeStatus::from_status(VBUS, 0b0010_0010),
+            ChargeStatus::Charging
+        );
+        assert_eq!(
+            ChargeStatus::from_status(0, 0b0100_0101),
+            ChargeStatus::Discharging
+        );
+        assert_eq!(
+            ChargeStatus::from_status(VBUS, 0b0000_0100),
+            ChargeStatus::Full
+        );
+        assert_eq!(
+            ChargeStatus::from_status(0, 0b0000_0100),
+            ChargeStatus::Standby
+        );
+        assert!(ChargeStatus::Full.is_external_power());
+        assert!(!ChargeStatus::Discharging.is_external_power());
+    }
+
+    #[test]
+    fn test_die_temperature() {
+        assert_eq!(die_temperature_from_raw(7274), 22);
+        assert_eq!(die_temperature_from_raw(7074), 32);
+        assert_eq!(die_temperature_from_raw(7674), 2);
+        assert_eq!(die_temperature_from_raw(0x3FFF), i8::MIN);
+    }
+
+    #[test]
+    fn test_ldos() {
+        assert_eq!(Ldos::ALDO3 | Ldos::ALDO4, Ldos::PANEL);
+        assert_eq!(Ldos::ALDOS.without(Ldos::PANEL), Ldos::ALDO1 | Ldos::ALDO2);
+        assert!(
+            Ldos::from_bits(0x30)
+                .without(Ldos::PANEL)
+                .intersects(Ldos::from_bits(0x10))
+        );
+        assert!(Ldos::NONE.is_empty());
+    }
+}
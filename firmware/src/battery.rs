@@ -2,6 +2,10 @@
 //!
 //! Draws a battery icon with fill level and color based on percentage.
 //! Copies background from framebuffer for transparency.
+//!
+//! Also classifies the charge into [`BatteryLevel`]s: a low battery stretches
+//! the sleep interval and gets a red-outlined icon, a critical one parks the
+//! frame on a "charge me" screen.
 
 use crate::epd::{Color, WIDTH};
 
@@ -26,6 +30,114 @@ pub fn battery_dimensions(vertical: bool) -> (u16, u16) {
     }
 }
 
+/// Parse a percentage at compile time, falling back to `default` if it's
+/// missing, not a number or above 100
+pub const fn parse_percent(value: Option<&str>, default: u8) -> u8 {
+    let bytes = match value {
+        Some(value) => value.as_bytes(),
+        None => return default,
+    };
+    if bytes.is_empty() || bytes.len() > 3 {
+        return default;
+    }
+    let mut percent: u16 = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_digit() {
+            return default;
+        }
+        percent = percent * 10 + (bytes[i] - b'0') as u16;
+        i += 1;
+    }
+    if percent > 100 {
+        default
+    } else {
+        percent as u8
+    }
+}
+
+/// How much charge is left, relative to the low and critical thresholds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatteryLevel {
+    /// Above the low threshold
+    Normal,
+    /// At or below the low threshold: sleep twice as long, skip prefetching
+    Low,
+    /// At or below halfway from critical to low: sleep four times as long
+    VeryLow,
+    /// At or below the critical threshold: show "charge me" and sleep until
+    /// the button is pressed
+    Critical,
+}
+
+impl BatteryLevel {
+    /// Classify a battery percentage
+    pub fn from_percent(percent: u8, low: u8, critical: u8) -> Self {
+        if percent <= critical {
+            BatteryLevel::Critical
+        } else if percent <= critical + low.saturating_sub(critical) / 2 {
+            BatteryLevel::VeryLow
+        } else if percent <= low {
+            BatteryLevel::Low
+        } else {
+            BatteryLevel::Normal
+        }
+    }
+
+    /// Whether power-saving behavior applies
+    pub fn is_low(self) -> bool {
+        self != BatteryLevel::Normal
+    }
+
+    /// Factor applied to the refresh interval
+    pub fn sleep_multiplier(self) -> u64 {
+        match self {
+            BatteryLevel::Normal => 1,
+            BatteryLevel::Low => 2,
+            BatteryLevel::VeryLow | BatteryLevel::Critical => 4,
+        }
+    }
+}
+
+/// What the battery indicator shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryStatus {
+    /// Battery level 0-100
+    pub percentage: u8,
+    /// Outline the icon in red as a low battery warning
+    pub low: bool,
+    /// Overlay a lightning bolt while charging
+    pub charging: bool,
+}
+
+/// Lightning bolt overlay, 10x16 with the leftmost pixel in bit 9. Symmetric
+/// under 180° rotation, so it reads the same on the upside-down landscape panel.
+const BOLT_WIDTH: u16 = 10;
+const BOLT_HEIGHT: u16 = 16;
+const BOLT: [u16; BOLT_HEIGHT as usize] = [
+    0b0000001110,
+    0b0000011100,
+    0b0000111000,
+    0b0001110000,
+    0b0011100000,
+    0b0111000000,
+    0b0111111110,
+    0b0111111110,
+    0b0111111110,
+    0b0111111110,
+    0b0000001110,
+    0b0000011100,
+    0b0000111000,
+    0b0001110000,
+    0b0011100000,
+    0b0111000000,
+];
+
+/// Whether the bolt covers (`col`, `row`)
+fn bolt_pixel(col: u16, row: u16) -> bool {
+    BOLT[row as usize] >> (BOLT_WIDTH - 1 - col) & 1 != 0
+}
+
 /// Get fill color based on battery percentage
 pub fn percentage_color(percentage: u8) -> Color {
     match percentage {
@@ -39,11 +151,19 @@ pub fn percentage_color(percentage: u8) -> Color {
 ///
 /// - `framebuffer`: The main display framebuffer to draw into
 /// - `fb_x`, `fb_y`: Position in framebuffer where icon will be drawn
-/// - `percentage`: Battery level 0-100
+/// - `status`: Battery level, low warning and charging state
 /// - `vertical`: If true, draw vertical battery (tip on top), else horizontal (tip on right)
-pub fn draw_battery(framebuffer: &mut [u8], fb_x: u16, fb_y: u16, percentage: u8, vertical: bool) {
+pub fn draw_battery(
+    framebuffer: &mut [u8],
+    fb_x: u16,
+    fb_y: u16,
+    status: BatteryStatus,
+    vertical: bool,
+) {
     let (buf_width, buf_height) = battery_dimensions(vertical);
+    let percentage = status.percentage;
     let fill_color = percentage_color(percentage);
+    let outline = if status.low { Color::Red } else { Color::Black };
 
     // Helper to set a pixel in the framebuffer
     let set_pixel = |fb: &mut [u8], x: u16, y: u16, color: Color| {
@@ -69,6 +189,7 @@ pub fn draw_battery(framebuffer: &mut [u8], fb_x: u16, fb_y: u16, percentage: u8
             &set_pixel,
             buf_width,
             buf_height,
+            outline,
             fill_color,
             percentage,
         );
@@ -78,10 +199,34 @@ pub fn draw_battery(framebuffer: &mut [u8], fb_x: u16, fb_y: u16, percentage: u8
             &set_pixel,
             buf_width,
             buf_height,
+            outline,
             fill_color,
             percentage,
         );
     }
+
+    if status.charging {
+        // Centered on the body, rotated a quarter turn for the vertical icon
+        for row in 0..BOLT_HEIGHT {
+            for col in 0..BOLT_WIDTH {
+                if !bolt_pixel(col, row) {
+                    continue;
+                }
+                let (x, y) = if vertical {
+                    (
+                        (BATTERY_WIDTH_V - BOLT_HEIGHT) / 2 + (BOLT_HEIGHT - 1 - row),
+                        6 + (42 - BOLT_WIDTH) / 2 + col,
+                    )
+                } else {
+                    (
+                        (42 - BOLT_WIDTH) / 2 + col,
+                        (BATTERY_HEIGHT_H - BOLT_HEIGHT) / 2 + row,
+                    )
+                };
+                set_pixel(framebuffer, x, y, Color::Black);
+            }
+        }
+    }
 }
 
 fn draw_battery_vertical<F>(
@@ -89,6 +234,7 @@ fn draw_battery_vertical<F>(
     set_pixel: &F,
     _buf_width: u16,
     _buf_height: u16,
+    outline: Color,
     fill_color: Color,
     percentage: u8,
 ) where
@@ -106,16 +252,7 @@ fn draw_battery_vertical<F>(
                 || y >= body_y_start + body_height - 2
                 || x < 2
                 || x >= body_width - 2;
-            set_pixel(
-                fb,
-                x,
-                y,
-                if is_border {
-                    Color::Black
-                } else {
-                    Color::White
-                },
-            );
+            set_pixel(fb, x, y, if is_border { outline } else { Color::White });
         }
     }
 
@@ -127,16 +264,7 @@ fn draw_battery_vertical<F>(
     for x in tip_x_start..(tip_x_start + tip_width) {
         for y in 0..tip_height {
             let is_border = x < tip_x_start + 2 || x >= tip_x_start + tip_width - 2 || y < 2;
-            set_pixel(
-                fb,
-                x,
-                y,
-                if is_border {
-                    Color::Black
-                } else {
-                    Color::White
-                },
-            );
+            set_pixel(fb, x, y, if is_border { outline } else { Color::White });
         }
     }
 
@@ -160,6 +288,7 @@ fn draw_battery_horizontal<F>(
     set_pixel: &F,
     _buf_width: u16,
     _buf_height: u16,
+    outline: Color,
     fill_color: Color,
     percentage: u8,
 ) where
@@ -173,16 +302,7 @@ fn draw_battery_horizontal<F>(
     for x in 0..body_width {
         for y in 0..body_height {
             let is_border = y < 2 || y >= body_height - 2 || x < 2 || x >= body_width - 2;
-            set_pixel(
-                fb,
-                x,
-                y,
-                if is_border {
-                    Color::Black
-                } else {
-                    Color::White
-                },
-            );
+            set_pixel(fb, x, y, if is_border { outline } else { Color::White });
         }
     }
 
@@ -197,16 +317,7 @@ fn draw_battery_horizontal<F>(
             let is_border = y < tip_y_start + 2
                 || y >= tip_y_start + tip_height - 2
                 || x >= tip_x + tip_width - 2;
-            set_pixel(
-                fb,
-                x,
-                y,
-                if is_border {
-                    Color::Black
-                } else {
-                    Color::White
-                },
-            );
+            set_pixel(fb, x, y, if is_border { outline } else { Color::White });
         }
     }
 
@@ -413,6 +524,70 @@ mod tests {
         assert_eq!(percentage_color(100), Color::Green);
     }
 
+    #[test]
+    fn test_battery_level() {
+        assert_eq!(parse_percent(Some("25"), 20), 25);
+        assert_eq!(parse_percent(Some("101"), 20), 20);
+        assert_eq!(parse_percent(Some("2x"), 20), 20);
+        assert_eq!(parse_percent(None, 20), 20);
+
+        let level = |percent| BatteryLevel::from_percent(percent, 20, 5);
+        assert_eq!(level(21), BatteryLevel::Normal);
+        assert_eq!(level(20), BatteryLevel::Low);
+        assert_eq!(level(13), BatteryLevel::Low);
+        assert_eq!(level(12), BatteryLevel::VeryLow);
+        assert_eq!(level(6), BatteryLevel::VeryLow);
+        assert_eq!(level(5), BatteryLevel::Critical);
+        assert_eq!(level(0).sleep_multiplier(), 4);
+        assert!(!level(50).is_low());
+    }
+
+    #[test]
+    fn test_charging_bolt() {
+        // Symmetric under 180° rotation
+        for row in 0..BOLT_HEIGHT {
+            for col in 0..BOLT_WIDTH {
+                assert_eq!(
+                    bolt_pixel(col, row),
+                    bolt_pixel(BOLT_WIDTH - 1 - col, BOLT_HEIGHT - 1 - row)
+                );
+            }
+        }
+
+        let pixel = |fb: &[u8], x: usize, y: usize| {
+            let byte = fb[y * (WIDTH as usize / 2) + x / 2];
+            if x.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            }
+        };
+        let status = BatteryStatus {
+            percentage: 100,
+            low: false,
+            charging: false,
+        };
+        for vertical in [false, true] {
+            let mut plain = [Color::White.to_dual_pixel(); BUFFER_SIZE];
+            let mut charging = plain;
+            draw_battery(&mut plain, 0, 0, status, vertical);
+            draw_battery(
+                &mut charging,
+                0,
+                0,
+                BatteryStatus {
+                    charging: true,
+                    ..status
+                },
+                vertical,
+            );
+            // The bolt's middle bar crosses the center of the body
+            let (x, y) = if vertical { (12, 27) } else { (21, 12) };
+            assert_eq!(pixel(&plain, x, y), Color::Green.to_4bit());
+            assert_eq!(pixel(&charging, x, y), Color::Black.to_4bit());
+        }
+    }
+
     #[test]
     fn test_buffer_size_vertical() {
         let fb = [Color::White.to_dual_pixel(); BUFFER_SIZE];
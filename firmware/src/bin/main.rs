@@ -1,9 +1,11 @@
 //! SawThat Frame Firmware - ESP32-S3 E-Paper Photo Frame
 //!
 //! Environment variables required:
+//! - SERVER_URL: Edge service URL (e.g., http://192.168.1.100:7676)
+//!
+//! Optional (fallback when no credentials were provisioned to the SD card):
 //! - WIFI_SSID: WiFi network name
 //! - WIFI_PASS: WiFi password
-//! - SERVER_URL: Edge service URL (e.g., http://192.168.1.100:7676)
 
 #![no_std]
 #![no_main]
@@ -11,50 +13,92 @@
 extern crate alloc;
 
 use alloc::boxed::Box;
-use core::sync::atomic::{AtomicU8, Ordering};
+use core::cell::RefCell;
+use core::fmt::Write as _;
+use core::net::Ipv4Addr;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, Ordering};
 use core::time::Duration as CoreDuration;
-use log::info;
+use log::{info, warn};
 
 use embassy_executor::Spawner;
 use embassy_net::{
-    Runner, Stack, StackResources,
+    Ipv4Cidr, Runner, Stack, StackResources, StaticConfigV4,
     dns::DnsSocket,
-    tcp::client::{TcpClient, TcpClientState},
+    tcp::client::{TcpClient, TcpClientState, TcpConnection},
 };
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
-use embassy_time::{Delay, Duration, Timer};
+use embassy_time::{Delay, Duration, Instant, Timer};
 use embedded_hal::delay::DelayNs;
-use embedded_hal_bus::spi::ExclusiveDevice;
+use embedded_hal_bus::i2c::RefCellDevice;
+use embedded_hal_bus::spi::{ExclusiveDevice, NoDelay};
 use esp_alloc as _;
-use esp_backtrace as _;
+use esp_bootloader_esp_idf::partitions::{self, DataPartitionSubType, PartitionType};
 use esp_hal::{
+    Blocking,
     clock::CpuClock,
-    gpio::{Input, InputConfig, Level, Output, OutputConfig, Pull},
+    gpio::{AnyPin, Flex, Input, InputConfig, Level, Output, OutputConfig, Pull},
     i2c::master::{Config as I2cConfig, I2c},
+    peripherals::TIMG1,
     ram,
     rng::Rng,
     rtc_cntl::{
-        Rtc,
+        Rtc, SocResetReason,
         sleep::{Ext0WakeupSource, TimerWakeupSource, WakeupLevel},
     },
     spi::{
         Mode,
         master::{Config as SpiConfig, Spi},
     },
+    system::SleepSource,
     time::Rate,
-    timer::timg::TimerGroup,
+    timer::timg::{MwdtStage, TimerGroup, Wdt},
 };
 use esp_radio::{
     Controller,
-    wifi::{ClientConfig, Config as WifiConfig, ModeConfig, WifiController, WifiDevice},
+    wifi::{
+        AccessPointConfig, ClientConfig, Config as WifiConfig, ModeConfig, WifiController,
+        WifiDevice,
+    },
 };
-use sawthat_frame_firmware::TimestampLogger;
-use sawthat_frame_firmware::battery;
-use sawthat_frame_firmware::cache::SdCache;
-use sawthat_frame_firmware::display::{self, TLS_READ_BUF_SIZE, TLS_WRITE_BUF_SIZE};
-use sawthat_frame_firmware::epd::{Epd7in3e, Rect, RefreshMode, WIDTH};
-use sawthat_frame_firmware::framebuffer::Framebuffer;
-use sawthat_frame_firmware::widget::{Orientation, WidgetData};
+use esp_storage::FlashStorage;
+use sawthat_frame_firmware::axp2101::{Axp2101, ChargeStatus, Ldos};
+use sawthat_frame_firmware::battery::{self, BatteryLevel, BatteryStatus};
+use sawthat_frame_firmware::cache::{SdCache, SettingsStore};
+use sawthat_frame_firmware::cache_policy::{CachePolicy, Freshness, WidgetFreshness};
+use sawthat_frame_firmware::clock::{self, WallClock};
+use sawthat_frame_firmware::config::{
+    self, DEVICE_ID_LEN, DeviceCommand, DeviceConfig, HeldSlot, MAX_COMMANDS,
+};
+#[cfg(feature = "demo")]
+use sawthat_frame_firmware::demo;
+use sawthat_frame_firmware::display::{self, CancelSignal, FallbackDns, Fetched};
+use sawthat_frame_firmware::dns_cache::ResolvedHost;
+use sawthat_frame_firmware::epd::{Epd7in3e, HEIGHT, Rect, RefreshMode, WIDTH};
+use sawthat_frame_firmware::error_screen::{self, ErrorState};
+use sawthat_frame_firmware::framebuffer::{Framebuffer, TileHashes, changed_region};
+use sawthat_frame_firmware::freeze::{self, Freeze, FrozenWake};
+use sawthat_frame_firmware::layout::Layout;
+use sawthat_frame_firmware::log_ring::LogShipping;
+#[cfg(feature = "mqtt")]
+use sawthat_frame_firmware::mqtt::{self, Command, FrameState};
+use sawthat_frame_firmware::nvs::NvsStore;
+use sawthat_frame_firmware::pin_state::{MAX_PIN_FAILURES, PinState};
+use sawthat_frame_firmware::power::PowerDownReport;
+use sawthat_frame_firmware::provision::{self, WifiCredentials};
+use sawthat_frame_firmware::refresh_timing::{RefreshKind, RefreshTimings};
+use sawthat_frame_firmware::render;
+use sawthat_frame_firmware::resume::{Interrupted, Pass, WakeProgress};
+use sawthat_frame_firmware::screenshot::Crc32;
+use sawthat_frame_firmware::shtc3::Shtc3;
+use sawthat_frame_firmware::telemetry::{BootReason, CrashLog, ResetReason, TelemetryReport};
+use sawthat_frame_firmware::text;
+use sawthat_frame_firmware::thermal::{self, Limit, Thermal, ThermalState};
+use sawthat_frame_firmware::tls::{self, ServerConnector, ServerStream, TlsBuffers};
+use sawthat_frame_firmware::wake::{Event, NextPass, Phase, WakeCycle};
+use sawthat_frame_firmware::watchdog::{self, Heartbeat};
+use sawthat_frame_firmware::widget::{MAX_PATH_LEN, Orientation, WidgetData};
+use sawthat_frame_firmware::{LOG_RING_SIZE, TimestampLogger};
 
 esp_bootloader_esp_idf::esp_app_desc!();
 
@@ -69,30 +113,159 @@ macro_rules! mk_static {
 }
 
 // Environment configuration
-const SSID: &str = env!("WIFI_SSID");
-const PASSWORD: &str = env!("WIFI_PASS");
+const SSID: Option<&str> = option_env!("WIFI_SSID");
+const PASSWORD: &str = match option_env!("WIFI_PASS") {
+    Some(password) => password,
+    None => "",
+};
 const SERVER_URL: &str = env!("SERVER_URL");
+// A root CA or key pin to verify the server with is no use over plain http
+const _: () = assert!(
+    !tls::VERIFIES_SERVER || is_https(SERVER_URL),
+    "TLS_CA and TLS_SPKI_SHA256 need an https SERVER_URL"
+);
+/// Battery percentage at or below which the frame saves power
+const LOW_BATTERY_PERCENT: u8 = battery::parse_percent(option_env!("LOW_BATTERY_PERCENT"), 20);
+/// Battery percentage at or below which the frame stops refreshing
+const CRITICAL_BATTERY_PERCENT: u8 =
+    battery::parse_percent(option_env!("CRITICAL_BATTERY_PERCENT"), 5);
+/// Keep each wake's log in /LOG.TXT on the SD card (`SD_LOG=1` at build time)
+const SD_LOG: bool = match option_env!("SD_LOG") {
+    Some(value) => value.len() == 1 && value.as_bytes()[0] == b'1',
+    None => false,
+};
+/// Upload each wake's log to the server after the refresh (`REMOTE_LOG=1` at
+/// build time, or `REMOTE_LOG=warn` for warnings and errors only)
+const REMOTE_LOG: Option<LogShipping> = LogShipping::parse(option_env!("REMOTE_LOG"));
 
-/// Refresh interval between display updates (15 minutes)
-const REFRESH_INTERVAL_SECS: u64 = 15 * 60;
 /// Button hold threshold in milliseconds
 const HOLD_THRESHOLD_MS: u32 = 500;
+/// Button hold at boot that enters the installer layout preview
+const INSTALLER_HOLD_MS: u32 = 2000;
+/// Time without a tap after which the installer preview keeps the layout shown
+const INSTALLER_IDLE_MS: u32 = 30_000;
+/// Button hold at boot that enters WiFi provisioning mode
+const PROVISION_HOLD_MS: u32 = 5000;
+/// Window after a tap in which a second tap counts as a double-tap (a
+/// screenshot during an update, freeze mode at wake)
+const DOUBLE_TAP_MS: u32 = 400;
 /// Button polling interval in milliseconds
 const BUTTON_POLL_MS: u64 = 50;
-/// Display busy polling interval in milliseconds (display refresh takes seconds)
-const DISPLAY_BUSY_POLL_MS: u64 = 200;
+/// Longest sleep after the server reported stale widget data
+const STALE_DATA_RETRY_SECS: u64 = 15 * 60;
+/// Most bytes of images kept in the SD cache before the oldest are evicted
+const SD_CACHE_BUDGET_BYTES: u64 = 256 * 1024 * 1024;
+/// Buffer for partial updates (400x480 = 96000 bytes)
+const HALF_BUFFER_SIZE: usize = 400 * 480 / 2;
+/// Largest changed region (in bytes) refreshed partially in vertical mode;
+/// anything bigger gets a full refresh
+const VERTICAL_PARTIAL_MAX_SIZE: usize = HALF_BUFFER_SIZE;
+/// WiFi connection attempts (5s apart) before reporting failure
+const WIFI_CONNECT_ATTEMPTS: u32 = 6;
 /// Magic number to validate RTC memory state
-const SLEEP_STATE_MAGIC: u32 = 0xCAFE_F00D;
+const SLEEP_STATE_MAGIC: u32 = 0x5354_4154;
+/// Magic number of the unversioned state written by older firmware
+const LEGACY_SLEEP_STATE_MAGIC: u32 = 0xCAFE_F00D;
+/// `SleepState` layout version, bump whenever its fields change
+const SLEEP_STATE_VERSION: u16 = 4;
 
 /// RTC fast memory state - persists across deep sleep
 #[esp_hal::ram(unstable(rtc_fast))]
 static mut SLEEP_STATE: SleepState = SleepState::new();
 
+/// Learned panel refresh durations - persist across deep sleep
+#[esp_hal::ram(unstable(rtc_fast))]
+static mut REFRESH_TIMINGS: RefreshTimings = RefreshTimings::new();
+
+/// When the cached widget data was last fetched - persists across deep sleep
+#[esp_hal::ram(unstable(rtc_fast))]
+static mut WIDGET_FRESHNESS: WidgetFreshness = WidgetFreshness::new();
+
+/// Failed wakes in a row and the error on the panel - persist across deep sleep
+#[esp_hal::ram(unstable(rtc_fast))]
+static mut ERROR_STATE: ErrorState = ErrorState::new();
+
+/// Failures of the device config's SPKI pin - persist across deep sleep
+#[esp_hal::ram(unstable(rtc_fast))]
+static mut PIN_STATE: PinState = PinState::new();
+
+/// Wall-clock time at the last SNTP sync - persists across deep sleep
+#[esp_hal::ram(unstable(rtc_fast))]
+static mut WALL_CLOCK: WallClock = WallClock::new();
+
+/// Refreshes skipped for the panel's temperature - persist across deep sleep
+#[esp_hal::ram(unstable(rtc_fast))]
+static mut THERMAL_STATE: ThermalState = ThermalState::new();
+
+/// Position in the baked-in playlist - persists across deep sleep
+#[cfg(feature = "demo")]
+#[esp_hal::ram(unstable(rtc_fast))]
+static mut DEMO_INDEX: usize = 0;
+
+/// Crashes not reported yet - persist across resets as well as deep sleep
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut CRASH_LOG: Persistent<CrashLog> = Persistent(CrashLog::new());
+
+/// The display pass in progress - persists across resets as well as deep sleep
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut WAKE_PROGRESS: Persistent<WakeProgress> = Persistent(WakeProgress::new());
+
+/// State in RTC memory that isn't initialized at boot
+#[repr(transparent)]
+struct Persistent<T>(T);
+
+// Every field of these is an integer or an array of them, so any bit pattern is valid
+unsafe impl esp_hal::Persistable for Persistent<CrashLog> {}
+unsafe impl esp_hal::Persistable for Persistent<WakeProgress> {}
+
+/// SNTP sync succeeded this wake, so the clock is saved before deep sleep
+static CLOCK_SYNCED: AtomicBool = AtomicBool::new(false);
+
+/// Progress of the main loop, watched by `watchdog_task`
+static HEARTBEAT: Heartbeat = Heartbeat::new();
+
+/// The panel driver as wired on the PhotoPainter board
+type Panel = Epd7in3e<
+    ExclusiveDevice<Spi<'static, Blocking>, Output<'static>, NoDelay>,
+    Input<'static>,
+    Output<'static>,
+    Output<'static>,
+>;
+
+/// TCP client the server connections are made over
+type Tcp = TcpClient<'static, 1, 1024, 1024>;
+
+/// Session to the server over the connector's TCP (and TLS) connection
+type ServerSession =
+    display::Session<'static, ServerStream<'static, TcpConnection<'static, 1, 1024, 1024>>>;
+
+/// SD card cache as wired on the PhotoPainter board
+type Sd = SdCache<ExclusiveDevice<Spi<'static, Blocking>, Output<'static>, NoDelay>, Delay>;
+
+/// Device on the I2C bus the PMIC and SHTC3 share
+type I2cDevice = RefCellDevice<'static, I2c<'static, Blocking>>;
+
+/// Settings store in the flash NVS partition
+type Nvs = NvsStore<FlashStorage<'static>>;
+
+/// Panel and framebuffer the panic handler draws on, once both are set up
+struct CrashScreen {
+    epd: *mut Panel,
+    framebuffer: *mut Framebuffer,
+}
+
+/// Set once the display is up; taken by the first panic
+static CRASH_SCREEN: AtomicPtr<CrashScreen> = AtomicPtr::new(core::ptr::null_mut());
+
 /// State persisted in RTC memory across deep sleep
 #[repr(C)]
 struct SleepState {
     /// Magic number to validate state
     magic: u32,
+    /// Layout version (`SLEEP_STATE_VERSION`)
+    version: u16,
+    /// CRC32 over the fields below
+    crc: u32,
     /// Current index into widget items (next item to fetch)
     index: usize,
     /// Total number of items
@@ -107,12 +280,22 @@ struct SleepState {
     slot_items: [usize; 2],
     /// Hash of all items (to detect data changes)
     data_hash: u32,
+    /// Tile hashes of the image currently on the panel (None if unknown)
+    panel_tiles: Option<TileHashes>,
+    /// RTC time (us) the held right slot was last rendered (0 if never)
+    held_refreshed_at: u64,
+    /// Refreshes since the panel was last fully cleared
+    refreshes_since_clear: u16,
+    /// Item the frame is frozen on (see `freeze`)
+    freeze: Option<Freeze>,
 }
 
 impl SleepState {
     const fn new() -> Self {
         Self {
             magic: 0,
+            version: 0,
+            crc: 0,
             index: 0,
             total_items: 0,
             shuffle_seed: 0,
@@ -120,18 +303,99 @@ impl SleepState {
             next_slot: 0,
             slot_items: [0, 0],
             data_hash: 0,
+            panel_tiles: None,
+            held_refreshed_at: 0,
+            refreshes_since_clear: 0,
+            freeze: None,
+        }
+    }
+
+    /// Check the state survived intact, migrating legacy state in place
+    ///
+    /// Invalid state is cleared so the wake is treated as a fresh start.
+    fn validate(&mut self) -> bool {
+        if self.magic == LEGACY_SLEEP_STATE_MAGIC {
+            // The legacy layout is smaller than this one, so it can be read from the same memory
+            let legacy =
+                unsafe { core::ptr::read((self as *const Self).cast::<LegacySleepState>()) };
+            info!("Migrating legacy sleep state");
+            *self = Self::from_legacy(&legacy);
+        }
+
+        if self.magic != SLEEP_STATE_MAGIC {
+            return false;
+        }
+        if self.version != SLEEP_STATE_VERSION || self.crc != self.checksum() {
+            warn!(
+                "Discarding sleep state (version {}, crc {:08x})",
+                self.version, self.crc
+            );
+            self.invalidate();
+            return false;
         }
+        true
     }
 
-    fn is_valid(&self) -> bool {
-        self.magic == SLEEP_STATE_MAGIC
+    /// Convert state written by firmware without versioning (tile hashes are dropped)
+    fn from_legacy(legacy: &LegacySleepState) -> Self {
+        let mut state = Self {
+            magic: SLEEP_STATE_MAGIC,
+            version: SLEEP_STATE_VERSION,
+            crc: 0,
+            index: legacy.index,
+            total_items: legacy.total_items,
+            shuffle_seed: legacy.shuffle_seed,
+            orientation: legacy.orientation,
+            next_slot: legacy.next_slot,
+            slot_items: legacy.slot_items,
+            data_hash: legacy.data_hash,
+            panel_tiles: None,
+            held_refreshed_at: 0,
+            refreshes_since_clear: 0,
+            freeze: None,
+        };
+        state.crc = state.checksum();
+        state
     }
 
-    #[allow(dead_code)]
     fn invalidate(&mut self) {
         self.magic = 0;
     }
 
+    /// CRC32 over the versioned fields (excluding padding)
+    fn checksum(&self) -> u32 {
+        let mut crc = Crc32::new();
+        crc.update(&self.version.to_le_bytes());
+        crc.update(&(self.index as u32).to_le_bytes());
+        crc.update(&(self.total_items as u32).to_le_bytes());
+        crc.update(&self.shuffle_seed.to_le_bytes());
+        crc.update(&[self.orientation, self.next_slot]);
+        for item in self.slot_items {
+            crc.update(&(item as u32).to_le_bytes());
+        }
+        crc.update(&self.data_hash.to_le_bytes());
+        match &self.panel_tiles {
+            Some(tiles) => {
+                crc.update(&[1]);
+                for tile in tiles {
+                    crc.update(&tile.to_le_bytes());
+                }
+            }
+            None => crc.update(&[0]),
+        }
+        crc.update(&self.held_refreshed_at.to_le_bytes());
+        crc.update(&self.refreshes_since_clear.to_le_bytes());
+        match &self.freeze {
+            Some(freeze) => {
+                crc.update(&[1]);
+                crc.update(freeze.item.as_bytes());
+                crc.update(&freeze.redrawn_at.to_le_bytes());
+            }
+            None => crc.update(&[0]),
+        }
+        crc.finish()
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn save(
         &mut self,
@@ -141,16 +405,32 @@ impl SleepState {
         orientation: Orientation,
         next_slot: u8,
         slot_items: [usize; 2],
+        panel_tiles: Option<TileHashes>,
+        held_refreshed_at: u64,
+        refreshes_since_clear: u16,
+        freeze: Option<Freeze>,
         items: &WidgetData,
     ) {
         self.magic = SLEEP_STATE_MAGIC;
+        self.version = SLEEP_STATE_VERSION;
         self.index = index;
         self.total_items = total_items;
         self.shuffle_seed = shuffle_seed;
         self.orientation = orientation as u8;
         self.next_slot = next_slot;
         self.slot_items = slot_items;
+        self.panel_tiles = panel_tiles;
+        self.held_refreshed_at = held_refreshed_at;
+        self.refreshes_since_clear = refreshes_since_clear;
+        self.freeze = freeze;
         self.data_hash = hash_data(items);
+        self.crc = self.checksum();
+    }
+
+    /// Freeze or unfreeze a wake that leaves the rest of the state as it is
+    fn set_freeze(&mut self, freeze: Option<Freeze>) {
+        self.freeze = freeze;
+        self.crc = self.checksum();
     }
 
     fn get_orientation(&self) -> Orientation {
@@ -170,12 +450,29 @@ impl SleepState {
     }
 }
 
+/// Sleep state layout written by firmware before versioning was added
+///
+/// Firmware from before tile hashes were added wrote only these same leading fields.
+#[repr(C)]
+struct LegacySleepState {
+    magic: u32,
+    index: usize,
+    total_items: usize,
+    shuffle_seed: u64,
+    orientation: u8,
+    next_slot: u8,
+    slot_items: [usize; 2],
+    data_hash: u32,
+}
+
 /// Button monitor state
 static BUTTON_STATE: AtomicU8 = AtomicU8::new(BUTTON_CANCELLED);
 const BUTTON_CANCELLED: u8 = 0;
 const BUTTON_POLLING: u8 = 1;
 const BUTTON_NEXT: u8 = 2;
 const BUTTON_FLIP: u8 = 3;
+const BUTTON_SCREENSHOT: u8 = 4;
+const BUTTON_FREEZE: u8 = 5;
 
 /// LED command sent via signal
 #[derive(Clone, Copy)]
@@ -282,8 +579,12 @@ fn flash_green(count: u8) {
 /// Signal to wake button monitor task
 static BUTTON_MONITOR_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
 
+/// Raised by the button monitor once it has an action, cutting background work short
+static BACKGROUND_CANCEL: CancelSignal = Signal::new();
+
 /// Start button monitoring (signals the persistent task)
 fn start_button_monitor() {
+    BACKGROUND_CANCEL.reset();
     BUTTON_STATE.store(BUTTON_POLLING, Ordering::Relaxed);
     BUTTON_MONITOR_SIGNAL.signal(());
 }
@@ -317,6 +618,7 @@ async fn button_monitor_task(key_input: &'static Input<'static>) {
                         {
                             // Request 3 flashes for flip
                             flash_green(3);
+                            BACKGROUND_CANCEL.signal(());
                         }
                         break;
                     }
@@ -330,18 +632,35 @@ async fn button_monitor_task(key_input: &'static Input<'static>) {
                     break;
                 }
 
-                // Otherwise, tap detected, set the action state
+                // Otherwise, tap detected - wait briefly for a second tap
+                let mut wait_time: u32 = 0;
+                let mut double_tap = false;
+                while wait_time < DOUBLE_TAP_MS {
+                    Timer::after(Duration::from_millis(BUTTON_POLL_MS)).await;
+                    wait_time += BUTTON_POLL_MS as u32;
+                    if key_input.is_low() {
+                        double_tap = true;
+                        break;
+                    }
+                }
+
+                let (action, flashes) = if double_tap {
+                    (BUTTON_SCREENSHOT, 2)
+                } else {
+                    (BUTTON_NEXT, 1)
+                };
                 if BUTTON_STATE
-                    .compare_exchange(
-                        BUTTON_POLLING,
-                        BUTTON_NEXT,
-                        Ordering::Relaxed,
-                        Ordering::Relaxed,
-                    )
+                    .compare_exchange(BUTTON_POLLING, action, Ordering::Relaxed, Ordering::Relaxed)
                     .is_ok()
                 {
-                    // Request 1 flash for next
-                    flash_green(1);
+                    // Request 1 flash for next, 2 for screenshot
+                    flash_green(flashes);
+                    BACKGROUND_CANCEL.signal(());
+                }
+
+                // Let the second tap go before monitoring resumes
+                while key_input.is_low() {
+                    Timer::after(Duration::from_millis(BUTTON_POLL_MS)).await;
                 }
                 break;
             }
@@ -355,13 +674,14 @@ async fn button_monitor_task(key_input: &'static Input<'static>) {
 async fn main(spawner: Spawner) -> ! {
     // Init timestamped logger for all log crate output (including ESP libs)
     TimestampLogger::init(log::LevelFilter::Info);
+    TimestampLogger::capture(SD_LOG || REMOTE_LOG.is_some());
 
     let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
     let peripherals = esp_hal::init(config);
 
     // Check wake reason immediately
     let wake_reason = esp_hal::rtc_cntl::wakeup_cause();
-    let button_wake = matches!(wake_reason, esp_hal::system::SleepSource::Ext0);
+    let button_wake = matches!(wake_reason, SleepSource::Ext0);
 
     // ==================== Early Button Check (before heavy init) ====================
     // Set up button and LED GPIOs first for fast response to button wake
@@ -385,8 +705,8 @@ async fn main(spawner: Spawner) -> ! {
 
     // Check sleep state to get current orientation
     let (resuming, mut orientation) = unsafe {
-        let state = &raw const SLEEP_STATE;
-        let valid = (*state).is_valid();
+        let state = &raw mut SLEEP_STATE;
+        let valid = (*state).validate();
         let orient = if valid {
             (*state).get_orientation()
         } else {
@@ -394,38 +714,163 @@ async fn main(spawner: Spawner) -> ! {
         };
         (valid, orient)
     };
+    // A pass cut short by a reset carries on where it left off (see `resume`)
+    let wake_progress = unsafe {
+        let progress = &raw mut WAKE_PROGRESS;
+        (*progress).0.validate();
+        &mut (*progress).0
+    };
+    let interrupted = wake_progress.interrupted();
+    if let Some(interrupted) = &interrupted {
+        warn!(
+            "Last wake was cut short during {:?} of item {} (slot {})",
+            interrupted.stage, interrupted.pass.item, interrupted.pass.slot
+        );
+        orientation = interrupted.pass.orientation;
+    }
+    let crash_log = crash_log();
+    crash_log.validate();
+    crash_log.on_boot(unexpected_reset());
+    if let Some(crash) = crash_log.report() {
+        warn!("Unreported crash: {:?}", crash);
+    }
+
+    let mut provision_requested = false;
+    let mut installer_requested = false;
 
-    if button_wake {
-        // Button caused wake - poll every 50ms to detect hold vs tap
+    if button_wake || key_input.is_low() {
+        // Button caused wake (or is held through power-on) - poll every 50ms to detect hold vs tap
         let mut hold_time_ms: u32 = 0;
 
         // Poll button state every 50ms (async to let LED task run)
+        // Keep polling past the flip threshold to detect a provisioning hold
         while key_input.is_low() {
             Timer::after(Duration::from_millis(BUTTON_POLL_MS)).await;
             hold_time_ms += BUTTON_POLL_MS as u32;
-            if hold_time_ms >= HOLD_THRESHOLD_MS {
+            if hold_time_ms >= PROVISION_HOLD_MS {
                 break;
             }
         }
 
-        if hold_time_ms >= HOLD_THRESHOLD_MS {
+        if hold_time_ms >= PROVISION_HOLD_MS {
+            // Button held >= 5s - enter WiFi provisioning
+            info!("Button held {}ms, entering WiFi provisioning", hold_time_ms);
+            provision_requested = true;
+        } else if hold_time_ms >= INSTALLER_HOLD_MS {
+            // Button held >= 2s - preview layouts once the items are loaded
+            info!("Button held {}ms, entering installer preview", hold_time_ms);
+            installer_requested = true;
+            flash_green(2);
+        } else if button_wake && hold_time_ms >= HOLD_THRESHOLD_MS {
             // Button held >= 500ms - toggle orientation
             orientation = orientation.toggle();
             BUTTON_STATE.store(BUTTON_FLIP, Ordering::Relaxed);
             // Request 3 flashes for rotation
             flash_green(3);
-        } else {
-            // Button released before 500ms - advance to next item
-            BUTTON_STATE.store(BUTTON_NEXT, Ordering::Relaxed);
-            // Request 1 flash for next item
-            flash_green(1);
+        } else if button_wake {
+            // Button released before 500ms - wait briefly for a second press
+            let mut wait_ms: u32 = 0;
+            while key_input.is_high() && wait_ms < DOUBLE_TAP_MS {
+                Timer::after(Duration::from_millis(BUTTON_POLL_MS)).await;
+                wait_ms += BUTTON_POLL_MS as u32;
+            }
+            if key_input.is_low() {
+                // Double press - freeze on the current items, or unfreeze
+                BUTTON_STATE.store(BUTTON_FREEZE, Ordering::Relaxed);
+                // Request 4 flashes for freeze
+                flash_green(4);
+                while key_input.is_low() {
+                    Timer::after(Duration::from_millis(BUTTON_POLL_MS)).await;
+                }
+            } else {
+                // Single tap - advance to next item
+                BUTTON_STATE.store(BUTTON_NEXT, Ordering::Relaxed);
+                // Request 1 flash for next item
+                flash_green(1);
+            }
         }
     }
 
-    // ==================== Normal Boot Sequence ====================
-    // Now do the heavier initialization
+    // ==================== Wake Cycle ====================
+    // Each phase has its own handler, which ends with the event that picks the
+    // next one (see `wake`)
     info!("Boot! Wake reason: {:?}", wake_reason);
+    let mut wake_cycle = WakeCycle::new();
+    boot(
+        spawner,
+        peripherals.PSRAM,
+        peripherals.TIMG0,
+        peripherals.TIMG1,
+    );
+    advance(&mut wake_cycle, Event::Booted);
+
+    let board = Board {
+        sd_spi: peripherals.SPI2,
+        sd_cs: peripherals.GPIO38,
+        sd_sck: peripherals.GPIO39,
+        sd_miso: peripherals.GPIO40,
+        sd_mosi: peripherals.GPIO41,
+        panel_spi: peripherals.SPI3,
+        panel_dc: peripherals.GPIO8,
+        panel_cs: peripherals.GPIO9,
+        panel_sck: peripherals.GPIO10,
+        panel_mosi: peripherals.GPIO11,
+        panel_rst: peripherals.GPIO12,
+        panel_busy: peripherals.GPIO13,
+        i2c: peripherals.I2C0,
+        i2c_sda: peripherals.GPIO47,
+        i2c_scl: peripherals.GPIO48,
+        flash: peripherals.FLASH,
+        wifi: peripherals.WIFI,
+        lpwr: peripherals.LPWR,
+    };
+    let trigger = Trigger {
+        reason: wake_reason,
+        button_wake,
+        orientation,
+        resuming,
+        interrupted,
+        provision_requested,
+        installer_requested,
+    };
+    let (mut wake, event) = load_state(spawner, board, trigger, key_input, wake_progress).await;
+    advance(&mut wake_cycle, event);
 
+    loop {
+        let event = match wake_cycle.phase() {
+            Phase::AcquireContent => wake.acquire_content().await,
+            Phase::Render => wake.render().await,
+            Phase::Refresh => wake.refresh().await,
+            Phase::Persist => wake.persist().await,
+            Phase::Sleep => wake.power_down_and_sleep(),
+            phase @ (Phase::Boot | Phase::LoadState) => {
+                unreachable!("{:?} runs before the display loop", phase)
+            }
+        };
+        advance(&mut wake_cycle, event);
+    }
+}
+
+/// Move the wake cycle on with the event a phase ended with
+///
+/// Handlers only end with events their phase allows, so any other is a bug:
+/// the wake is abandoned, saving its state if a pass was under way, and the
+/// frame sleeps as usual.
+fn advance(wake_cycle: &mut WakeCycle, event: Event) {
+    if let Err(e) = wake_cycle.advance(event) {
+        warn!("Invalid wake transition: {:?}", e);
+        wake_cycle.abandon();
+    }
+}
+
+/// Boot: heap, PSRAM and the async runtime, with the watchdog over the rest of
+/// the wake
+fn boot(
+    spawner: Spawner,
+    psram: esp_hal::peripherals::PSRAM<'static>,
+    timg0: esp_hal::peripherals::TIMG0<'static>,
+    timg1: TIMG1<'static>,
+) {
     // Initialize internal RAM heap (for smaller allocations)
     info!("Initializing heap...");
     esp_alloc::heap_allocator!(#[ram(reclaimed)] size: 64 * 1024);
@@ -433,19 +878,111 @@ async fn main(spawner: Spawner) -> ! {
 
     // Initialize PSRAM for large allocations (framebuffer, PNG buffer)
     info!("Initializing PSRAM...");
-    esp_alloc::psram_allocator!(&peripherals.PSRAM, esp_hal::psram);
+    esp_alloc::psram_allocator!(&psram, esp_hal::psram);
     info!("PSRAM initialized");
 
     info!("Starting RTOS...");
-    let timg0 = TimerGroup::new(peripherals.TIMG0);
-    esp_rtos::start(
-        timg0.timer0,
-        #[cfg(target_arch = "riscv32")]
-        esp_hal::interrupt::software::SoftwareInterruptControl::new(peripherals.SW_INTERRUPT)
-            .software_interrupt0,
-    );
+    let timg0 = TimerGroup::new(timg0);
+    esp_rtos::start(timg0.timer0);
     info!("RTOS started");
 
+    // Restart if the main loop stalls (see `watchdog`)
+    let mut wdt = TimerGroup::new(timg1).wdt;
+    wdt.set_timeout(
+        MwdtStage::Stage0,
+        esp_hal::time::Duration::from_secs(watchdog::HARDWARE_TIMEOUT_SECS),
+    );
+    wdt.enable();
+    spawner.spawn(watchdog_task(wdt)).ok();
+}
+
+/// Peripherals the wake takes over once it has booted
+struct Board {
+    sd_spi: esp_hal::peripherals::SPI2<'static>,
+    sd_cs: esp_hal::peripherals::GPIO38<'static>,
+    sd_sck: esp_hal::peripherals::GPIO39<'static>,
+    sd_miso: esp_hal::peripherals::GPIO40<'static>,
+    sd_mosi: esp_hal::peripherals::GPIO41<'static>,
+    panel_spi: esp_hal::peripherals::SPI3<'static>,
+    panel_dc: esp_hal::peripherals::GPIO8<'static>,
+    panel_cs: esp_hal::peripherals::GPIO9<'static>,
+    panel_sck: esp_hal::peripherals::GPIO10<'static>,
+    panel_mosi: esp_hal::peripherals::GPIO11<'static>,
+    panel_rst: esp_hal::peripherals::GPIO12<'static>,
+    panel_busy: esp_hal::peripherals::GPIO13<'static>,
+    i2c: esp_hal::peripherals::I2C0<'static>,
+    i2c_sda: esp_hal::peripherals::GPIO47<'static>,
+    i2c_scl: esp_hal::peripherals::GPIO48<'static>,
+    flash: esp_hal::peripherals::FLASH<'static>,
+    wifi: esp_hal::peripherals::WIFI<'static>,
+    lpwr: esp_hal::peripherals::LPWR<'static>,
+}
+
+/// What started the wake, read before booting so the button responds fast
+struct Trigger {
+    reason: SleepSource,
+    button_wake: bool,
+    /// Orientation from RTC memory, or toggled by a hold at wake
+    orientation: Orientation,
+    /// Sleep state in RTC memory is valid
+    resuming: bool,
+    /// Pass the last wake didn't finish
+    interrupted: Option<Interrupted>,
+    /// Held into WiFi provisioning
+    provision_requested: bool,
+    /// Held into the installer layout preview
+    installer_requested: bool,
+}
+
+/// LoadState: SD card, sleep state, settings, PMIC, panel, WiFi credentials and
+/// clock, then whether this wake refreshes at all
+async fn load_state(
+    spawner: Spawner,
+    board: Board,
+    trigger: Trigger,
+    key_input: &'static Input<'static>,
+    wake_progress: &'static mut WakeProgress,
+) -> (Wake, Event) {
+    let Trigger {
+        reason,
+        button_wake,
+        mut orientation,
+        resuming,
+        interrupted,
+        provision_requested,
+        installer_requested,
+    } = trigger;
+    let refresh_timings = unsafe {
+        let timings = &raw mut REFRESH_TIMINGS;
+        (*timings).validate();
+        &mut *timings
+    };
+    let widget_freshness = unsafe {
+        let freshness = &raw mut WIDGET_FRESHNESS;
+        (*freshness).validate();
+        &mut *freshness
+    };
+    let error_state = unsafe {
+        let state = &raw mut ERROR_STATE;
+        (*state).validate();
+        &mut *state
+    };
+    let pin_state = unsafe {
+        let state = &raw mut PIN_STATE;
+        (*state).validate();
+        &mut *state
+    };
+    let wall_clock = unsafe {
+        let clock = &raw mut WALL_CLOCK;
+        (*clock).validate();
+        &mut *clock
+    };
+    let thermal_state = unsafe {
+        let state = &raw mut THERMAL_STATE;
+        (*state).validate();
+        &mut *state
+    };
+
     let mut delay = Delay;
 
     // ==================== SD Card Cache Initialization ====================
@@ -453,35 +990,55 @@ async fn main(spawner: Spawner) -> ! {
     info!("Initializing SD card cache...");
 
     let sd_spi = Spi::new(
-        peripherals.SPI2,
+        board.sd_spi,
         SpiConfig::default()
             .with_frequency(Rate::from_mhz(20))
             .with_mode(Mode::_0),
     )
     .expect("SD SPI init failed")
-    .with_sck(peripherals.GPIO39)
-    .with_mosi(peripherals.GPIO41)
-    .with_miso(peripherals.GPIO40);
+    .with_sck(board.sd_sck)
+    .with_mosi(board.sd_mosi)
+    .with_miso(board.sd_miso);
 
-    let sd_cs = Output::new(peripherals.GPIO38, Level::High, OutputConfig::default());
+    let sd_cs = Output::new(board.sd_cs, Level::High, OutputConfig::default());
     let sd_spi_device = ExclusiveDevice::new_no_delay(sd_spi, sd_cs).unwrap();
 
     let mut sd_cache = match SdCache::new(sd_spi_device, delay.clone()) {
         Ok(mut cache) => {
-            if let Err(e) = cache.init() {
-                info!("SD cache init error: {:?}", e);
+            match cache.init() {
+                Ok(report) if report.total() > 0 => info!(
+                    "SD cache scrub removed {} empty, {} leftover, {} corrupt files",
+                    report.empty, report.leftovers, report.corrupt
+                ),
+                Ok(_) => {}
+                Err(e) => info!("SD cache init error: {:?}", e),
             }
             Some(cache)
         }
         Err(e) => {
             info!("SD card init failed: {:?} (cache disabled)", e);
+            TimestampLogger::capture(REMOTE_LOG.is_some());
             None
         }
     };
 
+    // Without a card, keep settings in the flash NVS partition so orientation,
+    // device config and WiFi credentials still survive power cycles
+    let mut nvs_settings = if sd_cache.is_none() {
+        open_nvs_settings(board.flash)
+    } else {
+        None
+    };
+
     // Try to load widget data from cache (for cache-first boot)
     let cached_items = sd_cache.as_mut().and_then(|c| c.load_widget_data());
     let has_cached_data = cached_items.is_some();
+    // ETag of the cached widget data, so unchanged data comes back as a 304
+    let widget_etag = if has_cached_data {
+        sd_cache.as_mut().and_then(|c| c.load_widget_etag())
+    } else {
+        None
+    };
     info!(
         "Cached widget data: {}",
         if has_cached_data {
@@ -491,11 +1048,48 @@ async fn main(spawner: Spawner) -> ! {
         }
     );
 
+    // Load device config (refresh interval, default orientation) from cache
+    let device_config: DeviceConfig = settings_store(&mut sd_cache, &mut nvs_settings)
+        .and_then(|s| s.load_device_config())
+        .unwrap_or_default();
+
+    // The server's address from an earlier wake, in case DNS fails this one
+    let stored_server_addr = sd_cache.as_mut().and_then(|c| c.load_resolved_host());
+
+    // Freeze mode (see `freeze`), carried in RTC memory or restored from the
+    // settings store after a power cycle
+    let mut freeze: Option<Freeze> = if resuming {
+        unsafe {
+            let state = &raw const SLEEP_STATE;
+            (*state).freeze.clone()
+        }
+    } else {
+        settings_store(&mut sd_cache, &mut nvs_settings).and_then(|s| s.load_freeze())
+    };
+    // A tap moves a frozen frame on to the next item, taking the freeze with it
+    let tapped = BUTTON_STATE.load(Ordering::Relaxed) == BUTTON_NEXT;
+    // A double press at wake ends the freeze, or freezes the frame once the
+    // items on the panel are known
+    let mut freeze_requested = false;
+    if BUTTON_STATE.load(Ordering::Relaxed) == BUTTON_FREEZE {
+        if let Some(frozen) = freeze.take() {
+            info!("Unfreezing from {}", frozen.item);
+            if let Some(store) = settings_store(&mut sd_cache, &mut nvs_settings)
+                && let Err(e) = store.store_freeze(None)
+            {
+                info!("Failed to store freeze: {:?}", e);
+            }
+        } else {
+            freeze_requested = true;
+        }
+        BUTTON_STATE.store(BUTTON_CANCELLED, Ordering::Relaxed);
+    }
+
     // Handle orientation persistence
     if BUTTON_STATE.load(Ordering::Relaxed) == BUTTON_FLIP {
-        // Orientation was changed during boot button hold - save to SD card
-        if let Some(cache) = sd_cache.as_mut()
-            && let Err(e) = cache.store_orientation(orientation)
+        // Orientation was changed during boot button hold - save it
+        if let Some(store) = settings_store(&mut sd_cache, &mut nvs_settings)
+            && let Err(e) = store.store_orientation(orientation)
         {
             info!("Failed to store orientation: {:?}", e);
         }
@@ -504,43 +1098,39 @@ async fn main(spawner: Spawner) -> ! {
     } else if BUTTON_STATE.load(Ordering::Relaxed) == BUTTON_NEXT {
         // Button tap detected during boot - reset state, display loop will show next item
         BUTTON_STATE.store(BUTTON_CANCELLED, Ordering::Relaxed);
-    } else if let Some(cached_orient) = sd_cache.as_mut().and_then(|c| c.load_orientation()) {
-        // Load orientation from SD card (persistent across power cycles)
+    } else if let Some(cached_orient) =
+        settings_store(&mut sd_cache, &mut nvs_settings).and_then(|s| s.load_orientation())
+    {
+        // Load orientation from SD card or flash (persistent across power cycles)
         orientation = cached_orient;
         info!("Using cached orientation: {:?}", orientation);
+    } else if settings_store(&mut sd_cache, &mut nvs_settings).is_some()
+        || !(resuming || interrupted.is_some())
+    {
+        // No orientation chosen on the device - follow the server default
+        // (without any settings storage, keep the orientation carried in RTC memory)
+        orientation = device_config.default_orientation;
     }
 
     // ==================== Power Management (AXP2101) ====================
     // SawThat Frame uses AXP2101 PMIC to control display power
-    // I2C: SDA=GPIO47, SCL=GPIO48, Address=0x34
+    // I2C: SDA=GPIO47, SCL=GPIO48, Address=0x34 (shared with the SHTC3 at 0x70)
     info!("Initializing AXP2101 PMIC...");
 
-    let mut i2c = I2c::new(
-        peripherals.I2C0,
+    let i2c = I2c::new(
+        board.i2c,
         I2cConfig::default().with_frequency(Rate::from_khz(400)),
     )
     .expect("I2C init failed")
-    .with_sda(peripherals.GPIO47)
-    .with_scl(peripherals.GPIO48);
-
-    const AXP2101_ADDR: u8 = 0x34;
-    const LDO_ONOFF_CTRL0: u8 = 0x90; // ALDO enable bits
-    const LDO_VOL2_CTRL: u8 = 0x94; // ALDO3 voltage
-    const LDO_VOL3_CTRL: u8 = 0x95; // ALDO4 voltage
-    const BAT_PERCENT_REG: u8 = 0xA4; // Battery percentage (0-100)
+    .with_sda(board.i2c_sda)
+    .with_scl(board.i2c_scl);
+    let i2c_bus: &'static RefCell<I2c<'static, Blocking>> =
+        mk_static!(RefCell<I2c<'static, Blocking>>, RefCell::new(i2c));
+    let mut pmic = Axp2101::new(RefCellDevice::new(i2c_bus));
+    let mut shtc3 = Shtc3::new(RefCellDevice::new(i2c_bus));
 
     // Try to configure PMIC - may already be set by bootloader
-    let pmic_ok = (|| -> Result<(), esp_hal::i2c::master::Error> {
-        // Set ALDO3 voltage to 3.3V: (3300-500)/100 = 28 = 0x1C
-        i2c.write(AXP2101_ADDR, &[LDO_VOL2_CTRL, 0x1C])?;
-        // Set ALDO4 voltage to 3.3V
-        i2c.write(AXP2101_ADDR, &[LDO_VOL3_CTRL, 0x1C])?;
-        // Enable ALDO3 and ALDO4 (bits 2 and 3) - just set all common LDOs on
-        i2c.write(AXP2101_ADDR, &[LDO_ONOFF_CTRL0, 0x0F])?;
-        Ok(())
-    })();
-
-    match pmic_ok {
+    match pmic.init() {
         Ok(()) => info!("PMIC configured - ALDO3/ALDO4 enabled at 3.3V"),
         Err(e) => info!("PMIC config skipped (may be pre-configured): {:?}", e),
     }
@@ -555,24 +1145,21 @@ async fn main(spawner: Spawner) -> ! {
     info!("Initializing e-paper display (fast mode)...");
 
     let spi = Spi::new(
-        peripherals.SPI3,
+        board.panel_spi,
         SpiConfig::default()
             .with_frequency(Rate::from_mhz(10))
             .with_mode(Mode::_0),
     )
     .expect("SPI init failed")
-    .with_sck(peripherals.GPIO10)
-    .with_mosi(peripherals.GPIO11);
+    .with_sck(board.panel_sck)
+    .with_mosi(board.panel_mosi);
 
-    let cs = Output::new(peripherals.GPIO9, Level::High, OutputConfig::default());
+    let cs = Output::new(board.panel_cs, Level::High, OutputConfig::default());
     let spi_device = ExclusiveDevice::new_no_delay(spi, cs).unwrap();
 
-    let busy = Input::new(
-        peripherals.GPIO13,
-        InputConfig::default().with_pull(Pull::Up),
-    );
-    let dc = Output::new(peripherals.GPIO8, Level::Low, OutputConfig::default());
-    let mut rst = Output::new(peripherals.GPIO12, Level::High, OutputConfig::default());
+    let busy = Input::new(board.panel_busy, InputConfig::default().with_pull(Pull::Up));
+    let dc = Output::new(board.panel_dc, Level::Low, OutputConfig::default());
+    let mut rst = Output::new(board.panel_rst, Level::High, OutputConfig::default());
 
     // Manual hardware reset before init (matches C driver timing)
     rst.set_high();
@@ -582,755 +1169,2444 @@ async fn main(spawner: Spawner) -> ! {
     rst.set_high();
     delay.delay_ms(50);
 
-    let mut epd = Epd7in3e::new(spi_device, busy, dc, rst, &mut delay, RefreshMode::Fast)
-        .expect("EPD init failed");
+    // Boxed so the crash screen can point at it wherever the wake moves it
+    let mut epd: Box<Panel> = Box::new(
+        Epd7in3e::new(
+            spi_device,
+            busy,
+            dc,
+            rst,
+            &mut delay,
+            device_config.refresh_mode(),
+        )
+        .expect("EPD init failed"),
+    );
     info!("EPD initialized!");
 
-    // ==================== WiFi Setup (Deferred) ====================
-    // Keep WiFi peripheral for lazy initialization - saves ~500-1000ms on cached boots
-    let mut wifi_peripheral: Option<esp_hal::peripherals::WIFI<'static>> = Some(peripherals.WIFI);
-
-    // WiFi state - will be initialized on first network access
-    // Note: _esp_radio_ctrl is a reference because it's stored in static memory
-    // It's kept alive to ensure the radio stays initialized
-    let mut _esp_radio_ctrl: Option<&'static Controller<'static>> = None;
-    let mut wifi_controller: Option<WifiController<'static>> = None;
-    let mut wifi_connected = false;
+    // ==================== WiFi Provisioning ====================
+    // Prefer provisioned credentials (SD card or flash), fall back to compile-time ones
+    let credentials = if provision_requested {
+        None
+    } else {
+        settings_store(&mut sd_cache, &mut nvs_settings)
+            .and_then(|s| s.load_wifi_credentials())
+            .or_else(|| SSID.and_then(|ssid| WifiCredentials::new(ssid, PASSWORD)))
+    };
+    let Some(credentials) = credentials else {
+        // No credentials (or setup requested) - serve the captive portal, then reboot
+        let credentials = run_provisioning(spawner, board.wifi, random_u64(&Rng::new())).await;
+        match settings_store(&mut sd_cache, &mut nvs_settings) {
+            Some(store) => {
+                if let Err(e) = store.store_wifi_credentials(&credentials) {
+                    info!("Failed to store WiFi credentials: {:?}", e);
+                }
+            }
+            None => info!("No SD card or NVS partition, WiFi credentials cannot be stored"),
+        }
+        info!("Provisioning complete, restarting...");
+        esp_hal::system::software_reset()
+    };
 
     // ==================== RTC for Deep Sleep ====================
-    let mut rtc = Rtc::new(peripherals.LPWR);
+    let rtc = Rtc::new(board.lpwr);
+
+    // Carry the wall clock over from the last sync, resyncing once WiFi is up if
+    // it's unknown or due
+    if let Some(unix_secs) = wall_clock.now(rtc.current_time_us()) {
+        clock::set_unix_time(unix_secs);
+    }
+    let clock_sync_due = wall_clock.needs_sync(rtc.current_time_us());
+
+    // Cached widget data within its max-age isn't revalidated this wake
+    let cached_freshness = if has_cached_data {
+        widget_freshness.freshness(rtc.current_time_us())
+    } else {
+        Freshness::Expired
+    };
+    if has_cached_data {
+        info!("Cached widget data is {:?}", cached_freshness);
+    }
 
-    // ==================== Main Display Logic ====================
     info!("Starting display update...");
     info!("Server URL: {}", SERVER_URL);
-    info!("Refresh interval: {} seconds", REFRESH_INTERVAL_SECS);
+    info!(
+        "Refresh interval: {} seconds",
+        device_config.refresh_interval_secs()
+    );
 
     // Allocate framebuffer (uses PSRAM for the 192KB buffer)
     info!("Allocating framebuffer...");
-    let mut framebuffer = Framebuffer::new();
+    let mut framebuffer = Box::new(Framebuffer::new());
     info!("Framebuffer allocated!");
 
-    // Use RNG for shuffle seed
-    let rng = Rng::new();
-
-    // Allocate TLS buffers for HTTPS support (on heap to save stack)
-    let mut tls_read_buf: Box<[u8; TLS_READ_BUF_SIZE]> = Box::new([0u8; TLS_READ_BUF_SIZE]);
-    let mut tls_write_buf: Box<[u8; TLS_WRITE_BUF_SIZE]> = Box::new([0u8; TLS_WRITE_BUF_SIZE]);
-
-    // TCP client and DNS socket - created lazily after WiFi init
-    let mut tcp_client: Option<TcpClient<'static, 1, 1024, 1024>> = None;
-    let mut dns_socket: Option<DnsSocket<'static>> = None;
-
-    // Helper macro to ensure WiFi is initialized and connected
-    macro_rules! ensure_wifi {
-        () => {{
-            if !wifi_connected {
-                info!("Initializing WiFi (deferred)...");
-                start_fast_blink(); // Visual feedback during slow init
-
-                // Initialize esp-radio (this is the slow part ~500-1000ms)
-                let ctrl = esp_radio::init().unwrap();
-                let ctrl = mk_static!(Controller<'static>, ctrl);
-
-                // Create WiFi controller and interfaces
-                let wifi = wifi_peripheral.take().unwrap();
-                let (wifi_ctrl, ifaces) = esp_radio::wifi::new(
-                    ctrl,
-                    wifi,
-                    WifiConfig::default(),
-                )
-                .unwrap();
-
-                let net_config = embassy_net::Config::dhcpv4(Default::default());
-                let (stk, runner) = embassy_net::new(
-                    ifaces.sta,
-                    net_config,
-                    mk_static!(StackResources<3>, StackResources::<3>::new()),
-                    rng.random() as u64,
-                );
-                let stk = mk_static!(Stack<'static>, stk);
-                spawner.spawn(net_task(runner)).ok();
-
-                let tcp_state = mk_static!(TcpClientState<1, 1024, 1024>, TcpClientState::new());
-                tcp_client = Some(TcpClient::new(*stk, tcp_state));
-                dns_socket = Some(DnsSocket::new(*stk));
-                _esp_radio_ctrl = Some(ctrl);
-                wifi_controller = Some(wifi_ctrl);
-
-                // Connect to WiFi
-                wifi_connect(wifi_controller.as_mut().unwrap()).await;
-                wait_for_ip(*stk).await;
-                wifi_connected = true;
-                info!("WiFi ready!");
-            }
-        }};
-    }
-
-    // Fetch widget data (use cache if available, then refresh from network)
-    // Keep boxed to avoid 6KB on stack
-    info!("Fetching widget data...");
-    let mut items: Box<WidgetData> = if let Some(cached) = cached_items {
-        info!("Using cached widget data ({} items)", cached.len());
-        Box::new(cached)
-    } else {
-        // No cache - must fetch from network
-        ensure_wifi!();
-
-        loop {
-            start_blink();
-            let result = display::fetch_widget_data(
-                tcp_client.as_ref().unwrap(),
-                dns_socket.as_ref().unwrap(),
-                &mut *tls_read_buf,
-                &mut *tls_write_buf,
-                SERVER_URL,
-                "concerts",
-            )
-            .await;
-            stop_blink();
+    // From here on a panic can put itself on the panel
+    let crash_screen = mk_static!(
+        CrashScreen,
+        CrashScreen {
+            epd: &raw mut *epd,
+            framebuffer: &raw mut *framebuffer,
+        }
+    );
+    CRASH_SCREEN.store(crash_screen, Ordering::Release);
 
-            match result {
-                Ok(data) => {
-                    // Store in cache for next boot
-                    if let Some(cache) = sd_cache.as_mut()
-                        && let Err(e) = cache.store_widget_data(&data)
-                    {
-                        info!("Failed to cache widget data: {:?}", e);
-                    }
-                    break data;
-                }
-                Err(e) => {
-                    info!("Failed to fetch widget data: {:?}, retrying in 30s...", e);
-                    Timer::after(Duration::from_secs(30)).await;
-                }
-            }
+    // Tile hashes of the image left on the panel, so vertical mode can refresh only what changed
+    // (unknown after an interrupted wake, which may have left it half drawn)
+    let panel_tiles: Option<TileHashes> = if resuming && interrupted.is_none() {
+        unsafe {
+            let state = &raw const SLEEP_STATE;
+            (*state).panel_tiles
         }
+    } else {
+        None
     };
 
-    // Get saved state if resuming
-    let (shuffle_seed, saved_index, saved_next_slot, saved_slot_items) = if resuming {
+    // When the held right slot was last rendered, so it's only refreshed once its TTL expires
+    let held_refreshed_at: u64 = if resuming {
         unsafe {
             let state = &raw const SLEEP_STATE;
-            (
-                (*state).shuffle_seed,
-                (*state).index,
-                (*state).get_next_slot(),
-                (*state).get_slot_items(),
-            )
+            (*state).held_refreshed_at
         }
     } else {
-        // Fresh start with new shuffle seed
-        let seed = (rng.random() as u64) << 32 | rng.random() as u64;
-        (seed, 0, 0u8, [0usize, 0usize])
+        0
     };
 
-    // Shuffle items (same seed = same order)
-    display::shuffle_items(&mut items, shuffle_seed);
-
-    // Now check if data matches (after shuffling, so cache_keys are in same order)
-    // Also get saved orientation for partial refresh check
-    let (data_matches, saved_orientation) = if resuming {
+    // Refreshes since the panel was last cleared, to clear off ghosting every so often
+    let refreshes_since_clear: u16 = if resuming {
         unsafe {
             let state = &raw const SLEEP_STATE;
-            ((*state).matches_data(&items), (*state).get_orientation())
+            (*state).refreshes_since_clear
         }
     } else {
-        (false, Orientation::Horizontal)
+        0
     };
 
-    let can_partial = data_matches
-        && orientation == Orientation::Horizontal
-        && saved_orientation == Orientation::Horizontal
-        && saved_index >= 2; // At least one full refresh has happened
-
-    let (mut index, mut next_slot, mut slot_items, mut use_partial) = if can_partial {
+    // Identifies this frame to the server (per-device settings and telemetry)
+    let device_id: &'static str = mk_static!(
+        heapless::String<DEVICE_ID_LEN>,
+        config::device_id(esp_hal::efuse::Efuse::read_base_mac_address())
+    )
+    .as_str();
+    info!("Device ID: {}", device_id);
+
+    // ==================== Battery and Temperature ====================
+    // A low battery stretches the sleep interval and skips prefetching; a
+    // critical one parks the frame on a "charge me" screen until the button
+    // is pressed. Neither applies while on USB power.
+    let charge_status = match pmic.charge_status() {
+        Ok(status) => status,
+        Err(e) => {
+            info!("Failed to read charge status: {:?}", e);
+            ChargeStatus::Discharging
+        }
+    };
+    if let Ok(millivolts) = pmic.battery_voltage_mv() {
+        info!("Battery: {}mV, {:?}", millivolts, charge_status);
+    }
+    // The panel's rated range and refresh durations (learned per temperature
+    // band) go by the air in the frame, or the PMIC die if the sensor can't be read
+    let temperature = match shtc3.temperature_c(&mut delay) {
+        Ok(celsius) => {
+            info!("Temperature: {}C", celsius);
+            Some(celsius)
+        }
+        Err(e) => {
+            info!("Failed to read SHTC3: {:?}, using the PMIC", e);
+            let die = pmic.die_temperature_c().ok();
+            if let Some(celsius) = die {
+                info!("PMIC temperature: {}C", celsius);
+            }
+            die
+        }
+    };
+    let mut battery_reading = None;
+    let battery_level = match pmic.battery_percent() {
+        Ok(percent) => {
+            battery_reading = Some(percent);
+            if charge_status.is_external_power() {
+                BatteryLevel::Normal
+            } else {
+                BatteryLevel::from_percent(percent, LOW_BATTERY_PERCENT, CRITICAL_BATTERY_PERCENT)
+            }
+        }
+        Err(e) => {
+            info!("Failed to read battery: {:?}", e);
+            BatteryLevel::Normal
+        }
+    };
+    if battery_level.is_low() {
         info!(
-            "Resuming with partial update: slot={}, slot_items=[{}, {}], index={}",
-            saved_next_slot, saved_slot_items[0], saved_slot_items[1], saved_index
+            "Battery {:?} ({:?}%), sleeping {}x longer",
+            battery_level,
+            battery_reading,
+            battery_level.sleep_multiplier()
         );
-        (saved_index, saved_next_slot, saved_slot_items, true)
-    } else if data_matches {
-        info!("Resuming from index {} (full refresh)", saved_index);
-        (saved_index, 0u8, [0usize, 0usize], false)
-    } else {
-        info!("Fresh start or data changed");
-        (0, 0u8, [0usize, 0usize], false)
+    }
+
+    let net = Net {
+        spawner,
+        // Hardware RNG for shuffle, network stack and TLS seeds
+        rng: Rng::new(),
+        credentials,
+        device_id,
+        peripheral: Some(board.wifi),
+        _radio: None,
+        controller: None,
+        stack: None,
+        connected: false,
+        failed: false,
+        connector: None,
+        // On the heap to save stack
+        tls_buffers: Box::leak(Box::new(TlsBuffers::new())),
+        dns: None,
+        server_ip: device_config.server_ip(),
+        spki_pin: pin_state.pin(device_config.spki_pin()),
+        pin_state,
+        stored_server_addr,
+        clock_sync_due,
+        session: None,
+        battery_reading,
     };
+    let mut wake = Wake {
+        net,
+        key_input,
+        delay,
+        rtc,
+        pmic,
+        epd,
+        framebuffer,
+        sd_cache,
+        nvs_settings,
+        wake_progress,
+        refresh_timings,
+        widget_freshness,
+        error_state,
+        wall_clock,
+        reason,
+        button_wake,
+        device_config,
+        orientation,
+        resuming,
+        interrupted,
+        installer_requested,
+        tapped,
+        freeze,
+        freeze_requested,
+        deghost: false,
+        charge_status,
+        battery_level,
+        temperature,
+        cached_items,
+        has_cached_data,
+        widget_etag,
+        cached_freshness,
+        cache_policy: None,
+        data_stale: false,
+        status_shown: false,
+        config_fetched: false,
+        items: Box::new(WidgetData::new()),
+        shuffle_seed: 0,
+        data_hash: 0,
+        index: 0,
+        next_slot: 0,
+        slot_items: [0, 0],
+        use_partial: false,
+        panel_tiles,
+        held_refreshed_at,
+        refreshes_since_clear,
+        show_next: None,
+        shown_item: None,
+        shown_now: false,
+        refresh_ms: 0,
+        commands: heapless::Vec::new(),
+        #[cfg(feature = "mqtt")]
+        sleep_until_button: false,
+        #[cfg(feature = "demo")]
+        demo: false,
+        pass: PassState::new(0),
+        sleep_secs: None,
+    };
+    let event = wake.check_refresh(thermal_state);
+    (wake, event)
+}
 
-    let total_items = items.len();
-    info!("Displaying {} items in shuffled order", total_items);
+/// Settings store in use: the SD card when present, otherwise flash
+fn settings_store<'a>(
+    sd_cache: &'a mut Option<Sd>,
+    nvs_settings: &'a mut Option<Nvs>,
+) -> Option<&'a mut dyn SettingsStore> {
+    match sd_cache.as_mut() {
+        Some(cache) => Some(cache as &mut dyn SettingsStore),
+        None => nvs_settings
+            .as_mut()
+            .map(|nvs| nvs as &mut dyn SettingsStore),
+    }
+}
 
-    // Buffer for partial updates (400x480 = 96000 bytes)
-    const HALF_BUFFER_SIZE: usize = 400 * 480 / 2;
+/// The network side of a wake: WiFi, brought up on first use, and the session
+/// to the server
+struct Net {
+    spawner: Spawner,
+    rng: Rng,
+    credentials: WifiCredentials,
+    /// Sent as `X-Device-Id`
+    device_id: &'static str,
+    /// Kept for lazy initialization - saves ~500-1000ms on cached boots
+    peripheral: Option<esp_hal::peripherals::WIFI<'static>>,
+    /// Kept alive to ensure the radio stays initialized
+    _radio: Option<&'static Controller<'static>>,
+    controller: Option<WifiController<'static>>,
+    stack: Option<Stack<'static>>,
+    connected: bool,
+    /// Joining the network failed this wake, so later requests fail fast
+    failed: bool,
+    /// Server connections (TCP, and TLS for https), created with the stack
+    connector: Option<&'static ServerConnector<'static, Tcp>>,
+    tls_buffers: &'static TlsBuffers,
+    /// DNS resolver, created with the stack
+    dns: Option<&'static FallbackDns<'static, DnsSocket<'static>>>,
+    /// `server_ip` from the device config
+    server_ip: Option<Ipv4Addr>,
+    /// `spki_sha256` from the device config, unless set aside
+    spki_pin: Option<[u8; 32]>,
+    /// Failures of `spki_pin`
+    pin_state: &'static mut PinState,
+    /// The server's address from an earlier wake, in case DNS fails this one
+    stored_server_addr: Option<ResolvedHost>,
+    /// Sync the clock in the background once WiFi is up
+    clock_sync_due: bool,
+    /// Shared by every request until WiFi is dropped, so the TLS handshake is
+    /// only paid once per wake cycle
+    session: Option<ServerSession>,
+    /// Battery level reported on requests, once read
+    battery_reading: Option<u8>,
+}
 
-    // Display loop - allows re-display on orientation change
-    loop {
-        // If we've shown all items, start over
-        if index >= total_items {
-            info!("All items shown, starting over");
-            index = 0;
+impl Net {
+    /// Make sure WiFi is initialized and connected, returning false if the
+    /// network could not be joined
+    async fn ensure_wifi(&mut self) -> bool {
+        if self.controller.is_none() {
+            info!("Initializing WiFi (deferred)...");
+            start_fast_blink(); // Visual feedback during slow init
+
+            // Initialize esp-radio (this is the slow part ~500-1000ms)
+            let ctrl = esp_radio::init().unwrap();
+            let ctrl = mk_static!(Controller<'static>, ctrl);
+
+            // Create WiFi controller and interfaces
+            let wifi = self.peripheral.take().unwrap();
+            let (wifi_ctrl, ifaces) =
+                esp_radio::wifi::new(ctrl, wifi, WifiConfig::default()).unwrap();
+
+            let net_config = embassy_net::Config::dhcpv4(Default::default());
+            let (stk, runner) = embassy_net::new(
+                ifaces.sta,
+                net_config,
+                mk_static!(StackResources<4>, StackResources::<4>::new()),
+                random_u64(&self.rng),
+            );
+            let stk = mk_static!(Stack<'static>, stk);
+            self.spawner.spawn(net_task(runner)).ok();
+
+            let tcp_state = mk_static!(TcpClientState<1, 1024, 1024>, TcpClientState::new());
+            let tcp_client = mk_static!(Tcp, TcpClient::new(*stk, tcp_state));
+            self.connector = Some(mk_static!(
+                ServerConnector<'static, Tcp>,
+                // The radio is up, so the RNG is drawing on RF noise
+                ServerConnector::new(
+                    tcp_client,
+                    self.tls_buffers,
+                    random_seed(&self.rng),
+                    self.spki_pin,
+                )
+            ));
+            // Falls back on the server's last address when DNS fails
+            let socket = mk_static!(DnsSocket<'static>, DnsSocket::new(*stk));
+            self.dns = Some(mk_static!(
+                FallbackDns<'static, DnsSocket<'static>>,
+                FallbackDns::new(socket, self.server_ip, self.stored_server_addr.take())
+            ));
+            self._radio = Some(ctrl);
+            self.controller = Some(wifi_ctrl);
+            self.stack = Some(*stk);
         }
 
-        // Wake up display
-        info!("Waking up display...");
-        epd.wake_up(&mut delay).expect("Failed to wake display");
+        // Connect to WiFi (again, if it was dropped to save power)
+        // Skipped after a failed attempt this wake, so later requests fail fast
+        if !self.connected
+            && !self.failed
+            && wifi_connect(self.controller.as_mut().unwrap(), &self.credentials).await
+        {
+            wait_for_ip(self.stack.unwrap()).await;
+            self.connected = true;
+            info!("WiFi ready!");
+
+            // Sync the clock in the background, once per wake
+            if self.clock_sync_due {
+                self.spawner.spawn(sntp_task(self.stack.unwrap())).ok();
+                self.clock_sync_due = false;
+            }
+        } else if !self.connected {
+            self.failed = true;
+        }
+        self.connected
+    }
 
-        // Read battery percentage
-        let battery_percent = {
-            let mut buf = [0u8; 1];
-            match i2c.write_read(AXP2101_ADDR, &[BAT_PERCENT_REG], &mut buf) {
-                Ok(()) => {
-                    info!("Battery: {}%", buf[0]);
-                    buf[0]
+    /// The session to the server, connecting WiFi and opening it if needed
+    /// (`None` if the server is unreachable)
+    async fn session(&mut self) -> Option<&mut ServerSession> {
+        if self.ensure_wifi().await && self.session.is_none() {
+            self.session = match display::connect(
+                self.connector.unwrap(),
+                self.dns.unwrap(),
+                SERVER_URL,
+                self.device_id,
+            )
+            .await
+            {
+                Ok(mut s) => {
+                    if self.spki_pin.is_some() {
+                        self.pin_state.succeed();
+                    }
+                    if let Some(percent) = self.battery_reading {
+                        s.set_battery_percent(percent);
+                    }
+                    Some(s)
                 }
                 Err(e) => {
-                    info!("Failed to read battery: {:?}", e);
-                    50 // Default to 50% on error
+                    info!("Failed to open session: {:?}", e);
+                    let connector = self.connector.unwrap();
+                    if let Some(pin) = self.spki_pin
+                        && connector.take_config_pin_failure()
+                        && self.pin_state.fail(pin)
+                    {
+                        warn!(
+                            "Server failed the device config's SPKI pin {} times in a row, setting it aside",
+                            MAX_PIN_FAILURES
+                        );
+                        connector.set_aside_config_pin();
+                        self.spki_pin = None;
+                    }
+                    None
                 }
-            }
-        };
+            };
+        }
+        self.session.as_mut()
+    }
 
-        let display_result = if use_partial && orientation == Orientation::Horizontal {
-            // ==================== Partial Refresh Mode (Cache-Aware) ====================
-            // Only update one half of the display with a single new item
-            let item_idx = index % total_items;
-            let item_path = items[item_idx].as_str();
-            info!(
-                "Partial update: slot={}, item={} of {}",
-                next_slot, item_idx, total_items
-            );
+    /// Close the session (e.g. before dropping WiFi or after an error)
+    fn close_session(&mut self) {
+        if let Some(s) = self.session.take() {
+            info!("Closing session after {} requests", s.requests());
+        }
+    }
 
-            // PNG buffer for fetching/reading (256KB)
-            let mut png_buf: alloc::boxed::Box<[u8; 256 * 1024]> =
-                alloc::boxed::Box::new([0u8; 256 * 1024]);
+    /// Close the session and drop WiFi to save power
+    async fn disconnect(&mut self) {
+        self.close_session();
+        if self.connected {
+            if let Some(ctrl) = self.controller.as_mut() {
+                info!("Disconnecting WiFi...");
+                wifi_disconnect(ctrl).await;
+            }
+            self.connected = false;
+        }
+    }
+}
 
-            start_blink();
+/// One pass of the display loop: what it drew and the refresh it started
+struct PassState {
+    /// Index of the first item shown (items from here up to the advanced
+    /// index are the ones shown this pass)
+    start: usize,
+    /// First error fetching or rendering, shown if nothing could be
+    error: Option<u16>,
+    /// Slot rendered by a partial refresh (the other half is not in the framebuffer)
+    partial_slot: Option<u8>,
+    /// Widget held in the right slot
+    held_slot: Option<HeldSlot>,
+    battery_status: BatteryStatus,
+    /// Streamed into the panel, bypassing the framebuffer
+    streamed: bool,
+    refresh_kind: RefreshKind,
+    refresh_started: Instant,
+}
 
-            // Check cache first
-            let cache_hit = sd_cache
-                .as_mut()
-                .is_some_and(|c| c.has_image(item_path, Orientation::Horizontal));
-            let png_len = if cache_hit {
-                info!("Cache HIT: {}", item_path);
-                sd_cache
-                    .as_mut()
-                    .and_then(|c| {
-                        c.read_image(item_path, Orientation::Horizontal, &mut *png_buf)
-                            .ok()
-                    })
-                    .unwrap_or_default()
-            } else {
-                info!("Cache MISS: {}", item_path);
-                // Initialize and connect WiFi if not already connected
-                ensure_wifi!();
-                match display::fetch_png(
-                    tcp_client.as_ref().unwrap(),
-                    dns_socket.as_ref().unwrap(),
-                    &mut *tls_read_buf,
-                    &mut *tls_write_buf,
-                    &mut *png_buf,
-                    SERVER_URL,
-                    "concerts",
-                    item_path,
-                    Orientation::Horizontal,
-                )
-                .await
-                {
-                    Ok(len) => {
-                        if let Some(cache) = sd_cache.as_mut()
-                            && let Err(e) = cache.write_image(
-                                item_path,
-                                Orientation::Horizontal,
-                                &png_buf[..len],
-                            )
-                        {
-                            info!("Cache store failed: {:?}", e);
-                        }
-                        len
-                    }
-                    Err(e) => {
-                        info!("Fetch failed: {:?}", e);
-                        0
-                    }
+impl PassState {
+    fn new(start: usize) -> Self {
+        Self {
+            start,
+            error: None,
+            partial_slot: None,
+            held_slot: None,
+            battery_status: BatteryStatus {
+                percentage: 0,
+                low: false,
+                charging: false,
+            },
+            streamed: false,
+            refresh_kind: RefreshKind::Full,
+            refresh_started: Instant::now(),
+        }
+    }
+}
+
+/// Everything a wake works with, handed from phase to phase (see `wake`)
+struct Wake {
+    net: Net,
+    key_input: &'static Input<'static>,
+    delay: Delay,
+    rtc: Rtc<'static>,
+    pmic: Axp2101<I2cDevice>,
+    /// Boxed so the crash screen can point at it
+    epd: Box<Panel>,
+    /// Boxed so the crash screen can point at it
+    framebuffer: Box<Framebuffer>,
+    sd_cache: Option<Sd>,
+    /// Settings in flash, without an SD card
+    nvs_settings: Option<Nvs>,
+    wake_progress: &'static mut WakeProgress,
+    refresh_timings: &'static mut RefreshTimings,
+    widget_freshness: &'static mut WidgetFreshness,
+    error_state: &'static mut ErrorState,
+    wall_clock: &'static mut WallClock,
+    reason: SleepSource,
+    button_wake: bool,
+    device_config: DeviceConfig,
+    orientation: Orientation,
+    /// Sleep state in RTC memory was valid at wake
+    resuming: bool,
+    /// Pass the last wake didn't finish
+    interrupted: Option<Interrupted>,
+    installer_requested: bool,
+    /// A tap at wake moves a frozen frame on
+    tapped: bool,
+    /// Item the frame is frozen on (see `freeze`)
+    freeze: Option<Freeze>,
+    /// A double press at wake asked to freeze on the items shown
+    freeze_requested: bool,
+    /// Clear the panel before the next refresh (a frozen frame's weekly redraw)
+    deghost: bool,
+    charge_status: ChargeStatus,
+    battery_level: BatteryLevel,
+    /// Temperature in the frame (SHTC3, or PMIC die), if it could be read
+    temperature: Option<i8>,
+    /// Widget data from the SD card, until the rotation takes it
+    cached_items: Option<Box<WidgetData>>,
+    has_cached_data: bool,
+    /// ETag of the cached widget data, so unchanged data comes back as a 304
+    widget_etag: Option<u32>,
+    cached_freshness: Freshness,
+    /// Cache policy the server sent with the widget data fetched this wake
+    cache_policy: Option<CachePolicy>,
+    /// The server reported its widget data as stale (its upstream is failing)
+    data_stale: bool,
+    /// A status or error screen replaced both halves while fetching the items
+    status_shown: bool,
+    /// The device config was refreshed this wake
+    config_fetched: bool,
+    /// The rotation, in shuffled order (kept boxed to avoid ~20KB on the stack)
+    items: Box<WidgetData>,
+    shuffle_seed: u64,
+    data_hash: u32,
+    /// Next item to show
+    index: usize,
+    /// Next slot to update in horizontal mode (0 = left, 1 = right)
+    next_slot: u8,
+    /// Item indices currently displayed in each slot [left, right]
+    slot_items: [usize; 2],
+    /// Refresh only the next slot rather than the whole panel
+    use_partial: bool,
+    /// Tile hashes of the image on the panel, so vertical mode can refresh only
+    /// what changed
+    panel_tiles: Option<TileHashes>,
+    /// RTC time (us) the held right slot was last rendered (0 if never)
+    held_refreshed_at: u64,
+    refreshes_since_clear: u16,
+    /// Item the server asked to show next
+    show_next: Option<heapless::String<MAX_PATH_LEN>>,
+    /// The requested item shown this wake (reported in telemetry, which
+    /// completes the request)
+    shown_item: Option<heapless::String<MAX_PATH_LEN>>,
+    /// Only one extra pass per wake, so a failing render can't keep the frame awake
+    shown_now: bool,
+    /// How long the last panel refresh took (reported in telemetry)
+    refresh_ms: u32,
+    /// Commands queued on the server, run after each pass in place of a button press
+    commands: heapless::Vec<DeviceCommand, MAX_COMMANDS>,
+    /// A remote `sleep` command: sleep until the button rather than the timer
+    #[cfg(feature = "mqtt")]
+    sleep_until_button: bool,
+    /// Showing the baked-in playlist, as the server couldn't be reached
+    #[cfg(feature = "demo")]
+    demo: bool,
+    pass: PassState,
+    /// Deep sleep after the wake, `None` for until the button is pressed
+    sleep_secs: Option<u64>,
+}
+
+impl Wake {
+    /// Settings store in use: the SD card when present, otherwise flash
+    fn settings(&mut self) -> Option<&mut dyn SettingsStore> {
+        settings_store(&mut self.sd_cache, &mut self.nvs_settings)
+    }
+
+    /// The rest of LoadState: wakes in quiet hours, frozen ones, and ones on a
+    /// critical battery or outside the panel's temperature range skip the refresh
+    fn check_refresh(&mut self, thermal_state: &mut ThermalState) -> Event {
+        // ==================== Quiet Hours ====================
+        // A timer wake inside the quiet hours (e.g. after they were configured, or
+        // once the clock drifted) goes back to sleep until they end; a button press
+        // still refreshes
+        if !self.button_wake
+            && let Some(quiet_hours) = &self.device_config.quiet_hours
+            && let Some(unix_secs) = clock::unix_time()
+            && let Some(remaining_secs) = quiet_hours.remaining_secs(unix_secs)
+        {
+            info!(
+                "Quiet hours for another {}s, skipping refresh",
+                remaining_secs
+            );
+            return self.skip(Some(remaining_secs.max(60)));
+        }
+
+        // ==================== Freeze Mode ====================
+        // A frozen frame leaves the panel alone on timer wakes, except to clear it
+        // and redraw the frozen items once a week; the button still works
+        if let Some(frozen) = &self.freeze
+            && !self.tapped
+        {
+            match frozen.wake(self.rtc.current_time_us()) {
+                FrozenWake::Sleep(secs) if !self.button_wake => {
+                    info!("Frozen on {}, skipping refresh for {}s", frozen.item, secs);
+                    return self.skip(Some(secs));
                 }
-            };
+                FrozenWake::Sleep(_) => {}
+                FrozenWake::Deghost => {
+                    info!("Frozen on {}, clearing the panel", frozen.item);
+                    self.deghost = true;
+                }
+            }
+        }
 
-            // Render to framebuffer
-            let fetch_result = if png_len > 0 {
-                display::render_png_to_framebuffer(
-                    &png_buf[..png_len],
-                    &mut framebuffer,
-                    next_slot,
-                    Orientation::Horizontal,
-                )
-            } else {
-                Err(display::DisplayError::Network)
-            };
+        // ==================== Battery Check ====================
+        if self.battery_level == BatteryLevel::Critical {
+            self.show_status("Battery critical", Some("Charge me, then press the button"));
+            return self.skip(None);
+        }
 
-            // Draw battery indicator centered horizontally
-            if fetch_result.is_ok() {
-                let (bat_w, _bat_h) = battery::battery_dimensions(false);
-                let battery_x = (WIDTH as u16 - bat_w) / 2;
-                let battery_y = 8;
-                battery::draw_battery(
-                    framebuffer.as_mut_slice(),
-                    battery_x,
-                    battery_y,
-                    battery_percent,
-                    false,
+        // ==================== Temperature Check ====================
+        // The panel can be damaged by refreshing outside its rated temperature, so
+        // wakes skip their refresh and sleep longer until it's back in range, then
+        // show a one-time card saying refreshes were paused
+        match thermal_state.check(self.temperature) {
+            Thermal::Normal => Event::Loaded,
+            Thermal::Derate(limit) => {
+                let sleep_secs =
+                    thermal::derated_sleep_secs(self.device_config.refresh_interval_secs())
+                        * self.battery_level.sleep_multiplier();
+                warn!(
+                    "Panel {:?} at {:?}C, skipping refresh for {}s",
+                    limit, self.temperature, sleep_secs
                 );
+                self.skip(Some(sleep_secs))
             }
+            Thermal::Recovered(pause) => {
+                let (title, extreme) = match pause.limit {
+                    Limit::TooCold => ("Too cold to refresh", "down to"),
+                    Limit::TooHot => ("Too hot to refresh", "up to"),
+                };
+                let mut detail: heapless::String<64> = heapless::String::new();
+                let _ = write!(
+                    detail,
+                    "Paused {} refreshes, {} {}C",
+                    pause.skipped, extreme, pause.extreme_c
+                );
+                self.show_status(title, Some(detail.as_str()));
+                self.skip(Some(
+                    self.device_config.refresh_interval_secs()
+                        * self.battery_level.sleep_multiplier(),
+                ))
+            }
+        }
+    }
 
-            // Start partial update
-            let display_started = match fetch_result {
-                Ok(()) => {
-                    // Extract the half we need to update
-                    let mut half_buffer = [0u8; HALF_BUFFER_SIZE];
-                    framebuffer.extract_half(next_slot, &mut half_buffer);
-
-                    // Create rect for the half (left: x=0, right: x=400)
-                    let x_offset = if next_slot == 0 { 0 } else { 400 };
-                    let rect = Rect::new(x_offset, 0, 400, 480);
+    /// Put the panel to sleep and skip this wake's refresh, sleeping
+    /// `sleep_secs` (until the button if `None`)
+    fn skip(&mut self, sleep_secs: Option<u64>) -> Event {
+        if let Err(e) = self.epd.sleep(&mut self.delay) {
+            info!("Failed to sleep display: {:?}", e);
+        }
+        self.sleep_secs = sleep_secs;
+        Event::Skip
+    }
 
-                    info!("Partial refresh: x={}, w={}, h={}", x_offset, 400, 480);
+    /// AcquireContent: the widget list (cached or fetched) and where the
+    /// rotation left off, then a freeze or layout preview asked for at wake
+    async fn acquire_content(&mut self) -> Event {
+        heartbeat();
+
+        // Fetch widget data (use cache if available, then refresh from network)
+        info!("Fetching widget data...");
+        let mut items = match self.cached_items.take() {
+            Some(cached) => {
+                info!("Using cached widget data ({} items)", cached.len());
+                cached
+            }
+            None => match self.fetch_widget_data().await {
+                Some(items) => items,
+                // Showing the demo playlist instead
+                None => return Event::ContentReady,
+            },
+        };
 
-                    epd.partial_update_start(&rect, &half_buffer, &mut delay)
-                        .is_ok()
+        // Get saved state if resuming, or the interrupted pass's rotation
+        let (shuffle_seed, saved_index, saved_next_slot, saved_slot_items) =
+            if let Some(interrupted) = &self.interrupted {
+                (
+                    interrupted.pass.shuffle_seed,
+                    interrupted.resume_index(),
+                    0u8,
+                    [0usize, 0usize],
+                )
+            } else if self.resuming {
+                unsafe {
+                    let state = &raw const SLEEP_STATE;
+                    (
+                        (*state).shuffle_seed,
+                        (*state).index,
+                        (*state).get_next_slot(),
+                        (*state).get_slot_items(),
+                    )
                 }
-                Err(_) => false,
+            } else {
+                // Fresh start with new shuffle seed
+                (random_u64(&self.net.rng), 0, 0u8, [0usize, 0usize])
             };
 
-            // Update slot tracking early so prefetch uses correct next index
-            if display_started {
-                slot_items[next_slot as usize] = item_idx;
-                next_slot = (next_slot + 1) % 2;
-                index += 1; // Advance by 1 for partial updates
-            }
-
-            // Spawn button monitor task and do work while it runs
-            if display_started {
-                // Start button monitoring
-                start_button_monitor();
-
-                // Initialize and connect WiFi now if we deferred it
-                ensure_wifi!();
-
-                // Prefetch next image (only if cache is available)
-                if let Some(cache) = sd_cache.as_mut() {
-                    let prefetch_idx = index % total_items;
-                    let prefetch_path = items[prefetch_idx].as_str();
-                    if !cache.has_image(prefetch_path, Orientation::Horizontal) {
-                        info!("Prefetching next image: {}", prefetch_path);
-                        let mut prefetch_buf: Box<[u8; 256 * 1024]> = Box::new([0u8; 256 * 1024]);
-                        if let Ok(len) = display::fetch_png(
-                            tcp_client.as_ref().unwrap(),
-                            dns_socket.as_ref().unwrap(),
-                            &mut *tls_read_buf,
-                            &mut *tls_write_buf,
-                            &mut *prefetch_buf,
-                            SERVER_URL,
-                            "concerts",
-                            prefetch_path,
-                            Orientation::Horizontal,
-                        )
-                        .await
-                        {
-                            if let Err(e) = cache.write_image(
-                                prefetch_path,
-                                Orientation::Horizontal,
-                                &prefetch_buf[..len],
-                            ) {
-                                info!("Prefetch cache store failed: {:?}", e);
-                            } else {
-                                info!("Prefetched and cached: {}", prefetch_path);
-                            }
+        // Shuffle items (same seed = same order)
+        display::shuffle_items(&mut items, shuffle_seed);
+
+        // Now check if data matches (after shuffling, so cache_keys are in same order)
+        // Also get saved orientation for partial refresh check
+        let data_hash = hash_data(&items);
+        let (data_matches, saved_orientation) = if let Some(interrupted) = &self.interrupted {
+            (
+                interrupted.matches(items.len(), data_hash),
+                interrupted.pass.orientation,
+            )
+        } else if self.resuming {
+            unsafe {
+                let state = &raw const SLEEP_STATE;
+                ((*state).matches_data(&items), (*state).get_orientation())
+            }
+        } else {
+            (false, Orientation::Horizontal)
+        };
+
+        let can_partial = data_matches
+            && self.orientation == Orientation::Horizontal
+            && saved_orientation == Orientation::Horizontal
+            && saved_index >= 2 // At least one full refresh has happened
+            && !self.status_shown // A status screen replaced both halves
+            && self.interrupted.is_none() // The panel may be half drawn
+            && !self.error_state.screen_shown();
+
+        (
+            self.index,
+            self.next_slot,
+            self.slot_items,
+            self.use_partial,
+        ) = if can_partial {
+            info!(
+                "Resuming with partial update: slot={}, slot_items=[{}, {}], index={}",
+                saved_next_slot, saved_slot_items[0], saved_slot_items[1], saved_index
+            );
+            (saved_index, saved_next_slot, saved_slot_items, true)
+        } else if data_matches {
+            info!("Resuming from index {} (full refresh)", saved_index);
+            (saved_index, 0u8, [0usize, 0usize], false)
+        } else {
+            info!("Fresh start or data changed");
+            (0, 0u8, [0usize, 0usize], false)
+        };
+        self.items = items;
+        self.shuffle_seed = shuffle_seed;
+        self.data_hash = data_hash;
+
+        let total_items = self.items.len();
+        info!("Displaying {} items in shuffled order", total_items);
+
+        // Freezing takes the first item on the panel as the last wake left it, and
+        // goes back to sleep without a refresh; when that isn't known (a fresh
+        // start or changed data), it takes the next item and draws it
+        if self.freeze_requested && total_items > 0 {
+            let shown = if !data_matches
+                || saved_index == 0
+                || self.interrupted.is_some()
+                || self.status_shown
+                || self.orientation != saved_orientation
+            {
+                None
+            } else if self.orientation == Orientation::Horizontal {
+                Some(saved_slot_items[0])
+            } else {
+                Some(saved_index - 1)
+            };
+            let item = self.items[shown.unwrap_or(self.index) % total_items].as_str();
+            match Freeze::new(item) {
+                Some(mut frozen) => {
+                    info!("Freezing on {}", frozen.item);
+                    if let Some(store) = self.settings()
+                        && let Err(e) = store.store_freeze(Some(&frozen))
+                    {
+                        info!("Failed to store freeze: {:?}", e);
+                    }
+                    if shown.is_some() {
+                        // The weekly clear counts from now
+                        frozen.redrawn_at = self.rtc.current_time_us();
+                        unsafe {
+                            let state = &raw mut SLEEP_STATE;
+                            (*state).set_freeze(Some(frozen.clone()));
                         }
+                        self.freeze = Some(frozen);
+                        self.net.disconnect().await;
+                        return self.skip(Some(freeze::DEGHOST_INTERVAL_SECS));
                     }
+                    self.freeze = Some(frozen);
                 }
+                None => info!("Can't freeze on {}, path too long", item),
+            }
+        }
 
-                // Refresh widget data from server if we used cached data
-                if has_cached_data {
-                    info!("Refreshing widget data from server...");
-                    if let Ok(fresh_items) = display::fetch_widget_data(
-                        tcp_client.as_ref().unwrap(),
-                        dns_socket.as_ref().unwrap(),
-                        &mut *tls_read_buf,
-                        &mut *tls_write_buf,
-                        SERVER_URL,
-                        "concerts",
-                    )
-                    .await
-                        && (fresh_items.len() != items.len()
-                            || fresh_items
-                                .iter()
-                                .zip(items.iter())
-                                .any(|(a, b)| a.as_str() != b.as_str()))
+        // A frozen frame draws its item again (found by path, as the items may
+        // have changed) unless a tap moved it on
+        if let Some(frozen) = &self.freeze
+            && !self.tapped
+        {
+            match self
+                .items
+                .iter()
+                .position(|item| item.as_str() == frozen.item.as_str())
+            {
+                Some(position) => {
+                    self.index = position;
+                    self.use_partial = false;
+                    // Not cleared since it was frozen
+                    self.deghost |= frozen.redrawn_at == 0;
+                }
+                None => {
+                    info!("Frozen item {} is gone, unfreezing", frozen.item);
+                    self.freeze = None;
+                    if let Some(store) = self.settings()
+                        && let Err(e) = store.store_freeze(None)
                     {
-                        info!("Widget data changed, updating cache");
-                        if let Some(cache) = sd_cache.as_mut() {
-                            if let Err(e) = cache.store_widget_data(&fresh_items) {
-                                info!("Failed to update widget data cache: {:?}", e);
-                            }
-                            if let Ok(count) = cache.cleanup_stale(&fresh_items)
-                                && count > 0
-                            {
-                                info!("Invalidated {} stale cache entries", count);
+                        info!("Failed to store freeze: {:?}", e);
+                    }
+                }
+            }
+        }
+
+        if self.installer_requested {
+            self.preview_layouts().await;
+        }
+        Event::ContentReady
+    }
+
+    /// Fetch the widget data on a wake without a cached copy, retrying until
+    /// the server answers (demo builds show the baked-in playlist instead,
+    /// returning `None`)
+    async fn fetch_widget_data(&mut self) -> Option<Box<WidgetData>> {
+        loop {
+            heartbeat();
+            start_blink();
+            let result = match self.net.session().await {
+                Some(s) => {
+                    let result = s
+                        .fetch_widget_data("concerts", None)
+                        .await
+                        .and_then(Fetched::into_modified);
+                    self.cache_policy = Some(s.cache_policy());
+                    self.data_stale = result.is_ok() && s.data_stale();
+                    result
+                }
+                None => Err(display::DisplayError::Network),
+            };
+            if result.is_err() {
+                self.net.close_session();
+            }
+            stop_blink();
+
+            match result {
+                Ok((data, etag)) => {
+                    // Store in cache for next boot
+                    if let Some(cache) = self.sd_cache.as_mut() {
+                        match cache.store_widget_data(&data) {
+                            Ok(()) => {
+                                let _ = cache.store_widget_etag(etag);
+                                self.record_widget_freshness();
                             }
+                            Err(e) => info!("Failed to cache widget data: {:?}", e),
                         }
                     }
+                    return Some(data);
                 }
-
-                // Disconnect WiFi to save power during display refresh wait
-                if wifi_connected {
-                    if let Some(ctrl) = wifi_controller.as_mut() {
-                        info!("Disconnecting WiFi (display refreshing)...");
-                        wifi_disconnect(ctrl).await;
+                // Show the baked-in playlist rather than an error, and try the
+                // server again next wake
+                #[cfg(feature = "demo")]
+                Err(e) => {
+                    info!(
+                        "Failed to fetch widget data: {:?}, showing demo playlist",
+                        e
+                    );
+                    self.error_state.fail(if self.net.connected {
+                        e.code()
+                    } else {
+                        error_screen::WIFI_ERROR_CODE
+                    });
+                    self.demo = true;
+                    return None;
+                }
+                #[cfg(not(feature = "demo"))]
+                Err(e) => {
+                    info!("Failed to fetch widget data: {:?}, retrying in 30s...", e);
+                    // Explain the blank frame once, rather than refreshing the panel every retry
+                    if !self.status_shown {
+                        self.show_error(if self.net.connected {
+                            e.code()
+                        } else {
+                            error_screen::WIFI_ERROR_CODE
+                        });
+                        self.status_shown = true;
                     }
-                    wifi_connected = false;
+                    self.net.failed = false;
+                    Timer::after(Duration::from_secs(30)).await;
                 }
+            }
+        }
+    }
+
+    /// Record that the cached widget data was just fetched or revalidated, unless
+    /// the server served an expired copy (which is revalidated on the next wake)
+    fn record_widget_freshness(&mut self) {
+        if self.data_stale {
+            self.widget_freshness.clear();
+        } else {
+            self.widget_freshness.record(
+                self.rtc.current_time_us(),
+                &self.cache_policy.unwrap_or_default(),
+            );
+            self.cached_freshness = Freshness::Fresh;
+        }
+    }
+
+    /// Installer layout preview: cycle through the layouts with cached images,
+    /// using fast partial refreshes; a tap shows the next layout, a hold (or
+    /// leaving it alone) keeps the one shown
+    async fn preview_layouts(&mut self) {
+        let mut layout = Layout::from_orientation(self.orientation);
+        let full_panel = Rect::new(0, 0, WIDTH as u16, HEIGHT as u16);
+        let mut png_buf: Box<[u8; 256 * 1024]> = Box::new([0u8; 256 * 1024]);
+        let total_items = self.items.len();
+        self.epd
+            .wake_up(&mut self.delay)
+            .expect("Failed to wake display");
 
-                // Wait for display busy (button task handles button detection separately)
-                while epd.is_busy() {
-                    Timer::after(Duration::from_millis(DISPLAY_BUSY_POLL_MS)).await;
+        loop {
+            heartbeat();
+            let layout_orientation = layout.orientation();
+            self.framebuffer
+                .clear(sawthat_frame_firmware::epd::Color::White);
+            let mut rendered = 0;
+            let previewed = self
+                .items
+                .iter()
+                .cycle()
+                .skip(self.index)
+                .take(total_items)
+                .filter(|item| !item.is_full_width())
+                .take(layout.items_shown());
+            for (slot, item) in previewed.enumerate() {
+                let Some(len) = self.sd_cache.as_mut().and_then(|c| {
+                    c.read_image(item.cache_key(), layout_orientation, &mut *png_buf)
+                        .ok()
+                }) else {
+                    continue;
+                };
+                match render::render_image_to_framebuffer(
+                    &png_buf[..len],
+                    &mut self.framebuffer,
+                    slot as u8,
+                    layout_orientation,
+                ) {
+                    Ok(()) => rendered += 1,
+                    Err(e) => info!("Preview of {} failed: {:?}", item.label(), e),
                 }
             }
-
-            // Finish display
-            let result = if display_started {
-                epd.refresh_wait(&mut delay)
-                    .map_err(|_| display::DisplayError::Network)
+            if rendered == 0 {
+                text::draw_message(
+                    &mut self.framebuffer,
+                    layout_orientation,
+                    layout.name(),
+                    Some("No cached images to preview"),
+                );
             } else {
-                Err(display::DisplayError::Network)
+                text::draw_banner(&mut self.framebuffer, layout_orientation, layout.name());
+            }
+
+            info!(
+                "Previewing {} layout ({} cached images)",
+                layout.name(),
+                rendered
+            );
+            if self
+                .epd
+                .partial_update_start(&full_panel, self.framebuffer.as_slice(), &mut self.delay)
+                .and_then(|()| self.epd.refresh_wait(&mut self.delay))
+                .is_err()
+            {
+                info!("Preview refresh failed, keeping {} layout", layout.name());
+                break;
+            }
+
+            // Wait for a tap (next layout) or a hold (keep this one)
+            let mut idle_ms: u32 = 0;
+            let mut hold_ms: u32 = 0;
+            let next = loop {
+                Timer::after(Duration::from_millis(BUTTON_POLL_MS)).await;
+                if self.key_input.is_low() {
+                    hold_ms += BUTTON_POLL_MS as u32;
+                    if hold_ms >= HOLD_THRESHOLD_MS {
+                        break false;
+                    }
+                } else if hold_ms > 0 {
+                    break true;
+                } else {
+                    idle_ms += BUTTON_POLL_MS as u32;
+                    if idle_ms >= INSTALLER_IDLE_MS {
+                        break false;
+                    }
+                }
             };
-            stop_blink();
-            embassy_futures::yield_now().await;
+            if !next {
+                break;
+            }
+            flash_green(1);
+            layout = layout.next();
+        }
 
-            result
-        } else {
-            // ==================== Full Refresh Mode (Cache-Aware) ====================
-            // Update entire display with 2 items (horizontal) or 1 item (vertical)
+        // Wait out the hold so the display loop doesn't see it
+        while self.key_input.is_low() {
+            Timer::after(Duration::from_millis(BUTTON_POLL_MS)).await;
+        }
+        flash_green(3);
+        info!("Installer chose {} layout", layout.name());
+        let orientation = layout.orientation();
+        self.orientation = orientation;
+        if let Some(store) = self.settings()
+            && let Err(e) = store.store_orientation(orientation)
+        {
+            info!("Failed to store orientation: {:?}", e);
+        }
+        // The panel shows the preview, so the display loop starts with a full refresh
+        self.use_partial = false;
+        self.panel_tiles = None;
+        if let Err(e) = self.epd.sleep(&mut self.delay) {
+            info!("Failed to sleep display: {:?}", e);
+        }
+    }
+
+    /// Render: this pass's images fetched (SD or network) and drawn into the
+    /// framebuffer, ending with the panel refresh started (or, if nothing could
+    /// be drawn, the pass)
+    async fn render(&mut self) -> Event {
+        #[cfg(feature = "demo")]
+        if self.demo {
+            return self.render_demo();
+        }
+        heartbeat();
+
+        // If we've shown all items, start over
+        let total_items = self.items.len();
+        if self.index >= total_items {
+            info!("All items shown, starting over");
+            self.index = 0;
+        }
+
+        // Marked until the wake's state is saved, so a reset mid-pass resumes from it
+        self.wake_progress.begin(&Pass {
+            shuffle_seed: self.shuffle_seed,
+            data_hash: self.data_hash,
+            total_items,
+            orientation: self.orientation,
+            slot: self.next_slot,
+            item: self.index,
+        });
+        self.pass = PassState::new(self.index);
+
+        // In horizontal mode the right slot can hold another widget: the left slot
+        // then rotates every pass, and both are redrawn once the held one expires
+        self.pass.held_slot = match self.orientation {
+            Orientation::Horizontal => self.device_config.held_slot.clone(),
+            Orientation::Vertical => None,
+        };
+        if let Some(held) = &self.pass.held_slot {
+            let age_secs = self
+                .rtc
+                .current_time_us()
+                .saturating_sub(self.held_refreshed_at)
+                / 1_000_000;
+            if self.use_partial && (self.held_refreshed_at == 0 || age_secs >= held.ttl_secs()) {
+                info!("Held {} slot expired after {}s", held.widget, age_secs);
+                self.use_partial = false;
+            }
+        }
+
+        // Full-width items fill the whole panel, so neither they nor the item
+        // after one can be swapped into a single half
+        if self.use_partial
+            && self.orientation == Orientation::Horizontal
+            && (self.items[self.index % total_items].is_full_width()
+                || self
+                    .items
+                    .get(self.slot_items[0])
+                    .is_some_and(|item| item.is_full_width()))
+        {
+            info!("Full-width item, refreshing the whole panel");
+            self.use_partial = false;
+        }
+
+        // Wake up display in the configured mode for routine refreshes
+        let refresh_mode = self.device_config.refresh_mode();
+        if self.epd.refresh_mode() != refresh_mode {
+            info!("Switching to {:?} refresh", refresh_mode);
+            self.epd.set_refresh_mode(refresh_mode);
+        }
+        info!("Waking up display...");
+        self.epd
+            .wake_up(&mut self.delay)
+            .expect("Failed to wake display");
+
+        // Fast and partial refreshes leave ghosting behind, so every so often the
+        // panel is cleared with a standard refresh and then redrawn in full (as
+        // is a frozen frame's, once a week)
+        if self.deghost || self.device_config.clear_due(self.refreshes_since_clear) {
             info!(
-                "Full refresh: items {} and {} of {}",
-                index,
-                (index + 1).min(total_items - 1),
-                total_items
+                "Clearing panel after {} refreshes",
+                self.refreshes_since_clear
             );
+            self.epd.set_refresh_mode(RefreshMode::Standard);
+            let cleared = self.epd.reinit(&mut self.delay).and_then(|()| {
+                self.epd
+                    .clear(sawthat_frame_firmware::epd::Color::White, &mut self.delay)
+            });
+            self.epd.set_refresh_mode(refresh_mode);
+            let cleared = cleared.and_then(|()| self.epd.reinit(&mut self.delay));
+            match cleared {
+                Ok(()) => {
+                    self.refreshes_since_clear = 0;
+                    self.use_partial = false;
+                    self.panel_tiles = None;
+                    if self.deghost
+                        && let Some(frozen) = self.freeze.as_mut()
+                    {
+                        frozen.redrawn_at = self.rtc.current_time_us();
+                    }
+                }
+                Err(e) => info!("Failed to clear panel: {:?}", e),
+            }
+            self.deghost = false;
+        }
 
-            // Clear framebuffer
-            framebuffer.clear(sawthat_frame_firmware::epd::Color::White);
+        // Read battery percentage and charging state
+        let battery_percent = match self.pmic.battery_percent() {
+            Ok(percent) => {
+                info!("Battery: {}%", percent);
+                self.net.battery_reading = Some(percent);
+                if let Some(s) = self.net.session.as_mut() {
+                    s.set_battery_percent(percent);
+                }
+                percent
+            }
+            Err(e) => {
+                info!("Failed to read battery: {:?}", e);
+                50 // Default to 50% on error
+            }
+        };
+        self.pass.battery_status = BatteryStatus {
+            percentage: battery_percent,
+            low: self.battery_level.is_low(),
+            charging: matches!(self.pmic.charge_status(), Ok(ChargeStatus::Charging)),
+        };
 
-            // PNG buffer for fetching/reading (256KB)
-            let mut png_buf: alloc::boxed::Box<[u8; 256 * 1024]> =
-                alloc::boxed::Box::new([0u8; 256 * 1024]);
+        let started = if self.use_partial && self.orientation == Orientation::Horizontal {
+            self.render_half().await
+        } else {
+            self.render_full().await
+        };
+        if started {
+            self.wake_progress.refresh_started();
+            heartbeat();
+            Event::RefreshStarted
+        } else {
+            stop_blink();
+            self.end_pass(Err(display::DisplayError::Network)).await
+        }
+    }
 
-            start_blink();
+    /// Partial refresh (cache-aware): draw the next item into one half of the
+    /// display and start refreshing just that half, returning whether it started
+    ///
+    /// The framebuffer won't hold the other half, so panel contents are no
+    /// longer tracked.
+    async fn render_half(&mut self) -> bool {
+        self.panel_tiles = None;
+        let total_items = self.items.len();
+        let item_idx = self.index % total_items;
+        let slot = self.next_slot;
+        let item_path = self.items[item_idx].as_str();
+        let item_key = self.items[item_idx].cache_key();
+        info!(
+            "Partial update: slot={}, item={} of {} ({})",
+            slot,
+            item_idx,
+            total_items,
+            self.items[item_idx].label()
+        );
 
-            // Number of items to display
-            let items_per_screen = match orientation {
-                Orientation::Horizontal => 2,
-                Orientation::Vertical => 1,
-            };
+        // PNG buffer for fetching/reading (256KB)
+        let mut png_buf: Box<[u8; 256 * 1024]> = Box::new([0u8; 256 * 1024]);
 
-            let mut fetch_ok = true;
-            for slot in 0..items_per_screen {
-                let item_idx = (index + slot) % total_items;
-                let item_path = items[item_idx].as_str();
+        start_blink();
 
-                // Check cache first
-                let cache_hit = sd_cache
-                    .as_mut()
-                    .is_some_and(|c| c.has_image(item_path, orientation));
-                let png_len = if cache_hit {
-                    info!("Cache HIT: {}", item_path);
-                    sd_cache
-                        .as_mut()
-                        .and_then(|c| c.read_image(item_path, orientation, &mut *png_buf).ok())
-                        .unwrap_or_default()
-                } else {
-                    info!("Cache MISS: {}", item_path);
-                    // Initialize and connect WiFi if not already connected
-                    ensure_wifi!();
-                    // Fetch from network
-                    match display::fetch_png(
-                        tcp_client.as_ref().unwrap(),
-                        dns_socket.as_ref().unwrap(),
-                        &mut *tls_read_buf,
-                        &mut *tls_write_buf,
+        // Check cache first (missing or corrupt entries fall back to the network)
+        let cached_len = self.sd_cache.as_mut().and_then(|c| {
+            c.read_image(item_key, Orientation::Horizontal, &mut *png_buf)
+                .ok()
+        });
+        let png_len = if let Some(len) = cached_len {
+            info!("Cache HIT: {}", item_path);
+            len
+        } else {
+            info!("Cache MISS: {}", item_path);
+            // Open the session (connecting WiFi) if not already open
+            let result = match self.net.session().await {
+                Some(s) => s
+                    .fetch_png(
                         &mut *png_buf,
-                        SERVER_URL,
                         "concerts",
                         item_path,
-                        orientation,
+                        Orientation::Horizontal,
+                        None,
+                        None,
                     )
                     .await
+                    .and_then(Fetched::into_modified),
+                None => Err(display::DisplayError::Network),
+            };
+            match result {
+                Ok((len, _)) => {
+                    if let Some(cache) = self.sd_cache.as_mut()
+                        && let Err(e) =
+                            cache.write_image(item_key, Orientation::Horizontal, &png_buf[..len])
                     {
-                        Ok(len) => {
-                            // Store in cache
-                            if let Some(cache) = sd_cache.as_mut()
-                                && let Err(e) =
-                                    cache.write_image(item_path, orientation, &png_buf[..len])
-                            {
-                                info!("Cache store failed: {:?}", e);
-                            }
-                            len
-                        }
-                        Err(e) => {
-                            info!("Fetch failed: {:?}", e);
-                            0
-                        }
+                        info!("Cache store failed: {:?}", e);
                     }
-                };
+                    len
+                }
+                Err(e) => {
+                    info!("Fetch failed: {:?}", e);
+                    self.pass.error.get_or_insert(e.code());
+                    self.net.close_session();
+                    0
+                }
+            }
+        };
+
+        // Render to framebuffer
+        let rendered = if png_len > 0 {
+            render::render_image_to_framebuffer(
+                &png_buf[..png_len],
+                &mut self.framebuffer,
+                slot,
+                Orientation::Horizontal,
+            )
+            .map_err(display::DisplayError::from)
+        } else {
+            Err(display::DisplayError::Network)
+        };
+        if let Err(e) = rendered {
+            self.pass.error.get_or_insert(e.code());
+            return false;
+        }
+
+        // Draw battery indicator centered horizontally
+        let (bat_w, _bat_h) = battery::battery_dimensions(false);
+        let battery_x = (WIDTH as u16 - bat_w) / 2;
+        let battery_y = 8;
+        battery::draw_battery(
+            self.framebuffer.as_mut_slice(),
+            battery_x,
+            battery_y,
+            self.pass.battery_status,
+            false,
+        );
+
+        // Extract the half we need to update
+        let mut half_buffer = [0u8; HALF_BUFFER_SIZE];
+        self.framebuffer.extract_half(slot, &mut half_buffer);
+
+        // Create rect for the half (left: x=0, right: x=400)
+        let x_offset = if slot == 0 { 0 } else { 400 };
+        let rect = Rect::new(x_offset, 0, 400, 480);
+
+        info!("Partial refresh: x={}, w={}, h={}", x_offset, 400, 480);
+        if self
+            .epd
+            .partial_update_start(&rect, &half_buffer, &mut self.delay)
+            .is_err()
+        {
+            return false;
+        }
+        self.pass.refresh_started = Instant::now();
+        self.pass.refresh_kind = RefreshKind::Partial;
+
+        // Update slot tracking early so prefetch uses correct next index
+        self.slot_items[slot as usize] = item_idx;
+        self.pass.partial_slot = Some(slot);
+        // The left slot rotates alone while the right one is held
+        self.next_slot = if self.pass.held_slot.is_some() {
+            0
+        } else {
+            (slot + 1) % 2
+        };
+        self.index += 1; // Advance by 1 for partial updates
+        true
+    }
+
+    /// Full refresh (cache-aware): draw 2 items (horizontal) or 1 item
+    /// (vertical) and start refreshing the panel, returning whether it started
+    async fn render_full(&mut self) -> bool {
+        let total_items = self.items.len();
+        let orientation = self.orientation;
+        info!(
+            "Full refresh: items {} and {} of {}",
+            self.index,
+            (self.index + 1).min(total_items - 1),
+            total_items
+        );
 
-                // Decode and render to framebuffer
-                if png_len > 0 {
-                    if let Err(e) = display::render_png_to_framebuffer(
-                        &png_buf[..png_len],
-                        &mut framebuffer,
-                        slot as u8,
+        start_blink();
+
+        // Dumb-terminal builds stream full-screen items straight from the
+        // network into the panel, bypassing the framebuffer and SD cache
+        let streamed = if cfg!(feature = "dumb-terminal") && orientation == Orientation::Vertical {
+            let item_path = self.items[self.index % total_items].as_str();
+            let result = match self.net.session().await {
+                Some(s) => {
+                    s.stream_to_display(
+                        &mut *self.epd,
+                        &mut self.delay,
+                        "concerts",
+                        item_path,
                         orientation,
-                    ) {
-                        info!("Render failed: {:?}", e);
-                        fetch_ok = false;
+                    )
+                    .await
+                }
+                None => Err(display::DisplayError::Network),
+            };
+            if let Err(e) = &result {
+                info!("Streaming failed, falling back to PNG: {:?}", e);
+                self.net.close_session();
+            }
+            result.is_ok()
+        } else {
+            false
+        };
+        self.pass.streamed = streamed;
+
+        // Clear framebuffer
+        self.framebuffer
+            .clear(sawthat_frame_firmware::epd::Color::White);
+
+        // Number of items to display (none left to render once streamed)
+        let items_per_screen = match orientation {
+            _ if streamed => 0,
+            Orientation::Horizontal => 2,
+            Orientation::Vertical => 1,
+        };
+
+        // A full-width first item is shown alone, covering the right slot
+        let first_full = orientation == Orientation::Horizontal
+            && self.items[self.index % total_items].is_full_width();
+
+        // First item of the held widget, listed fresh since its content changes
+        let held_path: Option<heapless::String<MAX_PATH_LEN>> = match &self.pass.held_slot {
+            Some(held) if items_per_screen == 2 && !first_full => {
+                let result = match self.net.session().await {
+                    Some(s) => s
+                        .fetch_widget_data(held.widget.as_str(), None)
+                        .await
+                        .and_then(Fetched::into_modified),
+                    None => Err(display::DisplayError::Network),
+                };
+                match result {
+                    Ok((data, _)) => data.first().map(|item| item.path.clone()),
+                    Err(e) => {
+                        info!("Failed to fetch held {} data: {:?}", held.widget, e);
+                        self.net.close_session();
+                        None
                     }
-                } else {
+                }
+            }
+            _ => None,
+        };
+
+        let mut fetch_ok = true;
+        for slot in 0..items_per_screen {
+            // Nothing shares the panel with a full-width item, and one can't
+            // be squeezed into the right half (left blank, it's shown next)
+            if slot == 1
+                && (first_full
+                    || (self.pass.held_slot.is_none()
+                        && self.items[(self.index + 1) % total_items].is_full_width()))
+            {
+                break;
+            }
+
+            // PNG buffer for fetching/reading (256KB)
+            let mut png_buf: Box<[u8; 256 * 1024]> = Box::new([0u8; 256 * 1024]);
+
+            let item_idx = (self.index + slot) % total_items;
+            let item_path = self.items[item_idx].as_str();
+            let item_key = self.items[item_idx].cache_key();
+
+            // The held slot is fetched fresh, falling back to the copy cached
+            // under the widget's name when the server can't be reached
+            let held = self.pass.held_slot.as_ref().filter(|_| slot == 1);
+            let cached_len = match held {
+                Some(_) => None,
+                None => self
+                    .sd_cache
+                    .as_mut()
+                    .and_then(|c| c.read_image(item_key, orientation, &mut *png_buf).ok())
+                    // Vertical copies from before the server rotated them
+                    .filter(|&len| render::fits_panel(&png_buf[..len], orientation)),
+            };
+            let png_len = if let Some(held) = held {
+                let result = match held_path.as_deref() {
+                    Some(path) => match self.net.session().await {
+                        Some(s) => s
+                            .fetch_png(
+                                &mut *png_buf,
+                                held.widget.as_str(),
+                                path,
+                                orientation,
+                                None,
+                                None,
+                            )
+                            .await
+                            .and_then(Fetched::into_modified),
+                        None => Err(display::DisplayError::Network),
+                    },
+                    None => Err(display::DisplayError::Network),
+                };
+                match result {
+                    Ok((len, _)) => {
+                        if let Some(cache) = self.sd_cache.as_mut()
+                            && let Err(e) = cache.write_image(
+                                held.widget.as_str(),
+                                orientation,
+                                &png_buf[..len],
+                            )
+                        {
+                            info!("Cache store failed: {:?}", e);
+                        }
+                        len
+                    }
+                    Err(e) => {
+                        info!("Held {} fetch failed: {:?}", held.widget, e);
+                        self.pass.error.get_or_insert(e.code());
+                        self.net.close_session();
+                        self.sd_cache
+                            .as_mut()
+                            .and_then(|c| {
+                                c.read_image(held.widget.as_str(), orientation, &mut *png_buf)
+                                    .ok()
+                            })
+                            .unwrap_or(0)
+                    }
+                }
+            } else if let Some(len) = cached_len {
+                info!("Cache HIT: {}", self.items[item_idx].label());
+                len
+            } else {
+                info!("Cache MISS: {}", self.items[item_idx].label());
+                // Fetch from network (opening the session if not already open)
+                let result = match self.net.session().await {
+                    Some(s) => s
+                        .fetch_png(
+                            &mut *png_buf,
+                            "concerts",
+                            item_path,
+                            orientation,
+                            None,
+                            None,
+                        )
+                        .await
+                        .and_then(Fetched::into_modified),
+                    None => Err(display::DisplayError::Network),
+                };
+                match result {
+                    Ok((len, _)) => {
+                        // Store in cache
+                        if let Some(cache) = self.sd_cache.as_mut()
+                            && let Err(e) =
+                                cache.write_image(item_key, orientation, &png_buf[..len])
+                        {
+                            info!("Cache store failed: {:?}", e);
+                        }
+                        len
+                    }
+                    Err(e) => {
+                        info!("Fetch failed: {:?}", e);
+                        self.pass.error.get_or_insert(e.code());
+                        self.net.close_session();
+                        0
+                    }
+                }
+            };
+
+            // Decode and render to framebuffer
+            if png_len > 0 {
+                if let Err(e) = render::render_image_to_framebuffer(
+                    &png_buf[..png_len],
+                    &mut self.framebuffer,
+                    slot as u8,
+                    orientation,
+                ) {
+                    info!("Render failed: {:?}", e);
+                    self.pass
+                        .error
+                        .get_or_insert(display::DisplayError::from(e).code());
                     fetch_ok = false;
                 }
+            } else {
+                fetch_ok = false;
+            }
+        }
+        if !fetch_ok {
+            return false;
+        }
+
+        // Draw battery indicator into framebuffer
+        if !streamed {
+            let vertical = orientation == Orientation::Vertical;
+            let (bat_w, _bat_h) = battery::battery_dimensions(vertical);
+            // Centered horizontally in horizontal mode, right-aligned in vertical
+            let battery_x = if vertical {
+                WIDTH as u16 - bat_w - 8
+            } else {
+                (WIDTH as u16 - bat_w) / 2
+            };
+            let battery_y = 8;
+            battery::draw_battery(
+                self.framebuffer.as_mut_slice(),
+                battery_x,
+                battery_y,
+                self.pass.battery_status,
+                vertical,
+            );
+        }
+
+        // Start display update. In vertical mode, compare against the image on the panel
+        // and only refresh the changed region when it is small (e.g. battery or text band)
+        let tiles = self.framebuffer.tile_hashes();
+        let changed = match (orientation, self.panel_tiles) {
+            (Orientation::Vertical, Some(previous)) => changed_region(&previous, &tiles)
+                .filter(|rect| rect.buffer_size() <= VERTICAL_PARTIAL_MAX_SIZE),
+            _ => None,
+        };
+        let display_started = match changed {
+            // Already refreshing with the streamed frame
+            _ if streamed => true,
+            Some(rect) => {
+                info!(
+                    "Updating display (partial refresh {}x{} at {},{})...",
+                    rect.width, rect.height, rect.x, rect.y
+                );
+                let mut region = alloc::vec![0u8; rect.buffer_size()];
+                self.framebuffer.extract_rect(&rect, &mut region);
+                self.epd
+                    .partial_update_start(&rect, &region, &mut self.delay)
+                    .is_ok()
+            }
+            None => {
+                info!("Updating display (full refresh)...");
+                self.epd
+                    .display_start(self.framebuffer.as_slice(), &mut self.delay)
+                    .is_ok()
+            }
+        };
+        if !display_started {
+            return false;
+        }
+        self.pass.refresh_started = Instant::now();
+        self.pass.refresh_kind = match changed {
+            Some(_) if !streamed => RefreshKind::Partial,
+            _ => RefreshKind::Full,
+        };
+        // The framebuffer doesn't hold the streamed image
+        self.panel_tiles = if streamed { None } else { Some(tiles) };
+
+        // Update slot tracking for horizontal mode (enables partial updates next time)
+        if orientation == Orientation::Horizontal {
+            self.slot_items[0] = self.index % total_items;
+            if first_full {
+                // The held slot was covered, and is redrawn on the next pass
+                self.slot_items[1] = self.slot_items[0];
+                self.held_refreshed_at = 0;
+                self.index += 1;
+            } else if self.pass.held_slot.is_some() {
+                // Only the left slot took an item from the rotation
+                self.held_refreshed_at = self.rtc.current_time_us();
+                self.index += 1;
+            } else if self.items[(self.index + 1) % total_items].is_full_width() {
+                // Right half left blank, the full-width item is shown next
+                self.slot_items[1] = self.slot_items[0];
+                self.index += 1;
+            } else {
+                self.slot_items[1] = (self.index + 1) % total_items;
+                self.index += 2;
+            }
+            self.next_slot = 0;
+            self.use_partial = true; // Enable partial updates for subsequent refreshes
+        } else {
+            self.index += 1; // Vertical mode: advance by 1
+        }
+        true
+    }
+
+    /// Render for a wake showing the baked-in playlist (the server couldn't be
+    /// reached): draw its next screen and refresh the panel with it
+    #[cfg(feature = "demo")]
+    fn render_demo(&mut self) -> Event {
+        let demo_index = unsafe { DEMO_INDEX };
+        self.framebuffer
+            .clear(sawthat_frame_firmware::epd::Color::White);
+        for (slot, item) in demo::screen(demo_index, self.orientation).enumerate() {
+            info!("Showing demo item {}", item.title);
+            if let Err(e) = render::render_image_to_framebuffer(
+                item.image(self.orientation),
+                &mut self.framebuffer,
+                slot as u8,
+                self.orientation,
+            ) {
+                info!("Demo render failed: {:?}", e);
+            }
+        }
+        heartbeat();
+        if let Err(e) = self
+            .epd
+            .display(self.framebuffer.as_slice(), &mut self.delay)
+        {
+            info!("Failed to show demo: {:?}", e);
+        }
+        unsafe {
+            DEMO_INDEX = (demo_index + demo::items_shown(self.orientation)) % demo::PLAYLIST.len();
+        }
+        Event::RefreshStarted
+    }
+
+    /// Refresh: the panel refreshing, with prefetching and button monitoring
+    /// alongside, ending the pass once it's done
+    async fn refresh(&mut self) -> Event {
+        #[cfg(feature = "demo")]
+        if self.demo {
+            if let Err(e) = self.epd.sleep(&mut self.delay) {
+                info!("Failed to sleep display: {:?}", e);
+            }
+            return Event::PassEnded(NextPass::Done);
+        }
+
+        start_button_monitor();
+
+        // Prefetch next image (only if the battery isn't low, and not when the
+        // next one will be streamed too)
+        if !self.pass.streamed && !self.battery_level.is_low() {
+            self.prefetch().await;
+        }
+        embassy_futures::yield_now().await;
+
+        // A button action cuts the remaining background work short so
+        // the next refresh starts as soon as the panel is idle (the
+        // config and data refreshes are retried on the next pass)
+        let cancelled = BACKGROUND_CANCEL.signaled();
+        if cancelled {
+            info!("Button pressed, skipping background refresh");
+        } else {
+            // Evict the least recently used images once the cache
+            // outgrows its budget, so years of items can't fill the card
+            if let Some(cache) = self.sd_cache.as_mut() {
+                match cache.enforce_budget(SD_CACHE_BUDGET_BYTES) {
+                    Ok(0) => {}
+                    Ok(n) => info!("Evicted {} cached images over budget", n),
+                    Err(e) => info!("Cache budget check failed: {:?}", e),
+                }
             }
 
-            let fetch_result: Result<(), display::DisplayError> = if fetch_ok {
-                Ok(())
-            } else {
-                Err(display::DisplayError::Network)
-            };
+            // Refresh device config from server
+            self.refresh_device_config().await;
+        }
+
+        // Refresh widget data from server if we used cached data
+        if self.has_cached_data && self.cached_freshness != Freshness::Fresh && !cancelled {
+            self.revalidate_widget_data().await;
+        }
+        stop_blink();
+
+        // Disconnect WiFi to save power during display refresh wait
+        self.net.disconnect().await;
+
+        // Wait for display busy (button task handles button detection separately),
+        // sleeping through most of the refresh when its duration is known
+        let epd = &mut self.epd;
+        let waited = self
+            .refresh_timings
+            .wait_until_idle(
+                self.pass.refresh_kind,
+                self.temperature,
+                self.pass.refresh_started,
+                || epd.is_busy(),
+            )
+            .await;
+        // Already idle, so the refresh took at most this long
+        self.refresh_ms =
+            waited.unwrap_or_else(|| self.pass.refresh_started.elapsed().as_millis() as u32);
+
+        // Finish display (a partial refresh of one half waits for the panel itself)
+        let result = if self.pass.partial_slot.is_some() {
+            self.epd.refresh_wait(&mut self.delay)
+        } else {
+            self.epd.finish_display(&mut self.delay)
+        }
+        .map_err(|_| display::DisplayError::Network);
+        embassy_futures::yield_now().await;
+
+        self.end_pass(result).await
+    }
 
-            // Draw battery indicator into framebuffer
-            if fetch_result.is_ok() {
-                let vertical = orientation == Orientation::Vertical;
-                let (bat_w, _bat_h) = battery::battery_dimensions(vertical);
-                // Centered horizontally in horizontal mode, right-aligned in vertical
-                let battery_x = if vertical {
-                    WIDTH as u16 - bat_w - 8
+    /// Fetch the next image into the SD cache while the panel refreshes, or
+    /// revalidate a cached copy of an item that may change
+    async fn prefetch(&mut self) {
+        let Some(cache) = self.sd_cache.as_mut() else {
+            return;
+        };
+        let orientation = self.orientation;
+        let prefetch_item = &self.items[self.index % self.items.len()];
+        let prefetch_path = prefetch_item.as_str();
+        let prefetch_key = prefetch_item.cache_key();
+        // Cached copies of items that may change are revalidated, so
+        // server-side re-renders are picked up
+        let prefetch_etag = cache.image_etag(prefetch_key, orientation);
+        if prefetch_etag.is_some() && !prefetch_item.may_change() {
+            info!("Next image is cached: {}", prefetch_path);
+            return;
+        }
+        if prefetch_etag.is_some() {
+            info!("Revalidating next image: {}", prefetch_path);
+        } else {
+            info!("Prefetching next image: {}", prefetch_path);
+        }
+        let mut prefetch_buf: Box<[u8; 256 * 1024]> = Box::new([0u8; 256 * 1024]);
+        let result = match self.net.session().await {
+            Some(s) => {
+                s.fetch_png(
+                    &mut *prefetch_buf,
+                    "concerts",
+                    prefetch_path,
+                    orientation,
+                    prefetch_etag,
+                    Some(&BACKGROUND_CANCEL),
+                )
+                .await
+            }
+            None => Err(display::DisplayError::Network),
+        };
+        match result {
+            Ok(Fetched::NotModified) => info!("Cached image is current: {}", prefetch_path),
+            Ok(Fetched::Modified(len, _)) => {
+                if let Err(e) = cache.write_image(prefetch_key, orientation, &prefetch_buf[..len]) {
+                    info!("Prefetch cache store failed: {:?}", e);
                 } else {
-                    (WIDTH as u16 - bat_w) / 2
-                };
-                let battery_y = 8;
-                battery::draw_battery(
-                    framebuffer.as_mut_slice(),
-                    battery_x,
-                    battery_y,
-                    battery_percent,
-                    vertical,
-                );
+                    info!("Prefetched and cached: {}", prefetch_path);
+                }
             }
+            Err(_) => self.net.close_session(),
+        }
+    }
 
-            // Start display update
-            let display_started = match fetch_result {
-                Ok(()) => {
-                    info!("Updating display (full refresh)...");
-                    epd.display_start(framebuffer.as_slice(), &mut delay)
-                        .is_ok()
+    /// Refresh the device config once per wake (applied to the deep sleep timer
+    /// and cached for the next boot; a show request is taken out so it isn't
+    /// replayed from the cache)
+    async fn refresh_device_config(&mut self) {
+        if self.config_fetched {
+            return;
+        }
+        let result = match self.net.session().await {
+            Some(s) => s.fetch_config().await,
+            None => Err(display::DisplayError::Network),
+        };
+        match result {
+            Ok(mut fresh_config) => {
+                if let Some(path) = fresh_config.show_next.take() {
+                    info!("Server asked to show {} next", path);
+                    self.show_next = Some(path);
                 }
-                Err(_) => false,
-            };
+                if !fresh_config.commands.is_empty() {
+                    info!("Server queued commands: {:?}", fresh_config.commands);
+                    self.commands = core::mem::take(&mut fresh_config.commands);
+                }
+                if fresh_config != self.device_config
+                    && let Some(store) = self.settings()
+                    && let Err(e) = store.store_device_config(&fresh_config)
+                {
+                    info!("Failed to cache device config: {:?}", e);
+                }
+                self.device_config = fresh_config;
+            }
+            Err(e) => {
+                info!("Failed to fetch device config: {:?}", e);
+                self.net.close_session();
+            }
+        }
+        self.config_fetched = true;
+    }
 
-            // Update slot tracking for horizontal mode (enables partial updates next time)
-            if display_started && orientation == Orientation::Horizontal {
-                slot_items[0] = index % total_items;
-                slot_items[1] = (index + 1) % total_items;
-                next_slot = 0;
-                index += 2;
-                use_partial = true; // Enable partial updates for subsequent refreshes
-            } else if display_started {
-                index += 1; // Vertical mode: advance by 1
-            }
-
-            // Spawn button monitor task and do work while it runs
-            if display_started {
-                // Start button monitoring
-                start_button_monitor();
-
-                // Initialize and connect WiFi now if we deferred it (using cached data path)
-                ensure_wifi!();
-
-                // Prefetch next image (only if cache is available)
-                if let Some(cache) = sd_cache.as_mut() {
-                    let prefetch_idx = index % total_items;
-                    let prefetch_path = items[prefetch_idx].as_str();
-                    if !cache.has_image(prefetch_path, orientation) {
-                        info!("Prefetching next image: {}", prefetch_path);
-                        let mut prefetch_buf: Box<[u8; 256 * 1024]> = Box::new([0u8; 256 * 1024]);
-                        if let Ok(len) = display::fetch_png(
-                            tcp_client.as_ref().unwrap(),
-                            dns_socket.as_ref().unwrap(),
-                            &mut *tls_read_buf,
-                            &mut *tls_write_buf,
-                            &mut *prefetch_buf,
-                            SERVER_URL,
-                            "concerts",
-                            prefetch_path,
-                            orientation,
-                        )
-                        .await
+    /// Revalidate the cached widget data with the server, updating the cache
+    /// (and dropping images of items that are gone) when it changed
+    async fn revalidate_widget_data(&mut self) {
+        info!("Refreshing widget data from server...");
+        let result = match self.net.session().await {
+            Some(s) => {
+                let result = s.fetch_widget_data("concerts", self.widget_etag).await;
+                self.data_stale = result.is_ok() && s.data_stale();
+                self.cache_policy = Some(s.cache_policy());
+                result
+            }
+            None => Err(display::DisplayError::Network),
+        };
+        if result.is_err() {
+            self.net.close_session();
+        }
+        match result {
+            Ok(Fetched::NotModified) => {
+                info!("Widget data unchanged");
+                self.record_widget_freshness();
+            }
+            Ok(Fetched::Modified(fresh_items, etag)) => {
+                let changed = fresh_items.len() != self.items.len()
+                    || fresh_items
+                        .iter()
+                        .zip(self.items.iter())
+                        .any(|(a, b)| a.as_str() != b.as_str());
+                if let Some(cache) = self.sd_cache.as_mut() {
+                    let stored = if changed {
+                        info!("Widget data changed, updating cache");
+                        let stored = cache.store_widget_data(&fresh_items);
+                        // Invalidate stale image cache entries, unless the
+                        // list itself is an expired copy (the images of items
+                        // missing from it are likely still wanted)
+                        if self.data_stale {
+                            info!("Widget data is stale, keeping cached images");
+                        } else if let Ok(count) = cache.cleanup_stale(&fresh_items)
+                            && count > 0
                         {
-                            if let Err(e) =
-                                cache.write_image(prefetch_path, orientation, &prefetch_buf[..len])
-                            {
-                                info!("Prefetch cache store failed: {:?}", e);
-                            } else {
-                                info!("Prefetched and cached: {}", prefetch_path);
-                            }
+                            info!("Invalidated {} stale cache entries", count);
                         }
-                    }
-                }
-                embassy_futures::yield_now().await;
-
-                // Refresh widget data from server if we used cached data
-                if has_cached_data {
-                    info!("Refreshing widget data from server...");
-                    if let Ok(fresh_items) = display::fetch_widget_data(
-                        tcp_client.as_ref().unwrap(),
-                        dns_socket.as_ref().unwrap(),
-                        &mut *tls_read_buf,
-                        &mut *tls_write_buf,
-                        SERVER_URL,
-                        "concerts",
-                    )
-                    .await
-                    {
-                        // Check if data changed
-                        if fresh_items.len() != items.len()
-                            || fresh_items
-                                .iter()
-                                .zip(items.iter())
-                                .any(|(a, b)| a.as_str() != b.as_str())
-                        {
-                            info!("Widget data changed, updating cache");
-                            if let Some(cache) = sd_cache.as_mut() {
-                                if let Err(e) = cache.store_widget_data(&fresh_items) {
-                                    info!("Failed to update widget data cache: {:?}", e);
-                                }
-                                // Invalidate stale image cache entries
-                                if let Ok(count) = cache.cleanup_stale(&fresh_items)
-                                    && count > 0
-                                {
-                                    info!("Invalidated {} stale cache entries", count);
-                                }
-                            }
+                        stored
+                    } else {
+                        Ok(())
+                    };
+                    // Only record the ETag once the data it describes is cached
+                    match stored {
+                        Ok(()) => {
+                            let _ = cache.store_widget_etag(etag);
+                            self.record_widget_freshness();
                         }
+                        Err(e) => info!("Failed to update widget data cache: {:?}", e),
                     }
                 }
-                stop_blink();
+            }
+            Err(_) => {}
+        }
+    }
 
-                // Disconnect WiFi to save power during display refresh wait
-                if wifi_connected {
-                    if let Some(ctrl) = wifi_controller.as_mut() {
-                        info!("Disconnecting WiFi (display refreshing)...");
-                        wifi_disconnect(ctrl).await;
-                    }
-                    wifi_connected = false;
+    /// End a pass: say why on the panel if nothing new could be shown, then
+    /// take a button press, show request or queued command as the cue for
+    /// another pass
+    async fn end_pass(&mut self, result: Result<(), display::DisplayError>) -> Event {
+        match &result {
+            Ok(()) => {
+                info!("Display refresh successful!");
+                self.refreshes_since_clear = self.refreshes_since_clear.saturating_add(1);
+                let failures = self.error_state.succeed();
+                if failures > 0 {
+                    info!("Recovered after {} failed wakes", failures);
                 }
-
-                // Wait for display busy (button task handles button detection separately)
-                while epd.is_busy() {
-                    Timer::after(Duration::from_millis(DISPLAY_BUSY_POLL_MS)).await;
+            }
+            Err(e) => {
+                info!("Display refresh failed: {:?}", e);
+                // Nothing new could be shown: say why on the whole panel
+                self.show_error(if self.net.failed {
+                    error_screen::WIFI_ERROR_CODE
+                } else {
+                    self.pass.error.unwrap_or_else(|| e.code())
+                });
+                if self.error_state.screen_shown() {
+                    self.use_partial = false;
                 }
             }
-
-            // Finish display
-            let result = if display_started {
-                epd.finish_display(&mut delay)
-                    .map_err(|_| display::DisplayError::Network)
-            } else {
-                Err(display::DisplayError::Network)
-            };
-
-            embassy_futures::yield_now().await;
-
-            result
-        };
-
-        match display_result {
-            Ok(()) => info!("Display refresh successful!"),
-            Err(e) => info!("Display refresh failed: {:?}", e),
         }
 
         // Put display to sleep
         info!("Putting display to sleep...");
-        epd.sleep(&mut delay).expect("Failed to sleep display");
+        self.epd
+            .sleep(&mut self.delay)
+            .expect("Failed to sleep display");
 
         // Check button state and cancel task if still polling
         let button_state = BUTTON_STATE.swap(BUTTON_CANCELLED, Ordering::Relaxed);
+        let total_items = self.items.len();
+        let pass_start = self.pass.start;
+
+        // Handle a show request: done if the item is on the panel now, otherwise
+        // it becomes the next item (in the next slot when refreshing partially).
+        // On USB power it's shown straight away, on battery on the next wake
+        let mut show_now = false;
+        if let Some(path) = self.show_next.take() {
+            let displayed = result.is_ok()
+                && (pass_start..self.index)
+                    .any(|i| self.items[i % total_items].as_str() == path.as_str());
+            match self
+                .items
+                .iter()
+                .position(|item| item.as_str() == path.as_str())
+            {
+                _ if displayed => {
+                    info!("Requested item {} shown", path);
+                    self.shown_item = Some(path);
+                }
+                Some(position) => {
+                    info!("Requested item {} is next (index {})", path, position);
+                    self.index = position;
+                    if self.charge_status.is_external_power() && !self.shown_now {
+                        // Checked again after the next pass
+                        self.show_next = Some(path);
+                        show_now = true;
+                        self.shown_now = true;
+                    }
+                }
+                None => info!("Requested item {} is not in the rotation", path),
+            }
+        }
+
+        // Without a button press, run the next command queued on the server
+        let button_state = if matches!(button_state, BUTTON_POLLING | BUTTON_CANCELLED)
+            && !show_now
+            && !self.commands.is_empty()
+        {
+            match self.commands.remove(0) {
+                DeviceCommand::Next => BUTTON_NEXT,
+                DeviceCommand::Flip => BUTTON_FLIP,
+                DeviceCommand::Refresh => {
+                    info!("Redrawing the current items");
+                    self.index = pass_start;
+                    self.use_partial = false;
+                    self.panel_tiles = None;
+                    BUTTON_NEXT
+                }
+                DeviceCommand::Unknown => button_state,
+            }
+        } else {
+            button_state
+        };
+
+        // Without a button press, publish the frame's state and take a remote
+        // command in its place (after a command starting another pass, the
+        // broker is checked again, so commands can follow one another)
+        #[cfg(feature = "mqtt")]
+        let button_state = if matches!(button_state, BUTTON_POLLING | BUTTON_CANCELLED)
+            && !show_now
+            && self.net.ensure_wifi().await
+        {
+            let state = FrameState {
+                battery_percent: self.net.battery_reading,
+                charging: self.charge_status.is_external_power(),
+                orientation: self.orientation,
+                item: self.items.get(pass_start).map(|item| item.as_str()),
+                index: self.index,
+                total: total_items,
+            };
+            match mqtt::exchange(self.net.stack.unwrap(), self.net.device_id, &state).await {
+                Ok(Some(Command::Next)) => BUTTON_NEXT,
+                Ok(Some(Command::Flip)) => BUTTON_FLIP,
+                Ok(Some(Command::Refresh)) => {
+                    info!("Redrawing the current items");
+                    self.index = pass_start;
+                    self.use_partial = false;
+                    self.panel_tiles = None;
+                    BUTTON_NEXT
+                }
+                Ok(Some(Command::Sleep)) => {
+                    self.sleep_until_button = true;
+                    button_state
+                }
+                Ok(None) => button_state,
+                Err(e) => {
+                    info!("MQTT exchange failed: {:?}", e);
+                    button_state
+                }
+            }
+        } else {
+            button_state
+        };
 
         // Handle button action detected during display update
         // (LED feedback already provided by button monitor task)
         match button_state {
             BUTTON_FLIP => {
                 info!("Button held during update! Toggling orientation...");
-                orientation = orientation.toggle();
-                // Save to SD card
-                if let Some(cache) = sd_cache.as_mut()
-                    && let Err(e) = cache.store_orientation(orientation)
+                let orientation = self.orientation.toggle();
+                self.orientation = orientation;
+                // Save to SD card or flash
+                if let Some(store) = self.settings()
+                    && let Err(e) = store.store_orientation(orientation)
                 {
                     info!("Failed to store orientation: {:?}", e);
                 }
                 // Reset partial mode on orientation change
-                use_partial = false;
-                slot_items = [0, 0];
-                next_slot = 0;
+                self.use_partial = false;
+                self.slot_items = [0, 0];
+                self.next_slot = 0;
 
                 info!("Re-displaying with orientation: {:?}", orientation);
-                // Continue loop to re-display
+                Event::PassEnded(NextPass::Another)
             }
             BUTTON_NEXT => {
-                info!("Button tap during update, next item (index={})", index);
-                // Continue loop to show next item
+                info!("Button tap during update, next item (index={})", self.index);
+                Event::PassEnded(NextPass::Another)
+            }
+            BUTTON_SCREENSHOT => {
+                info!("Button double-tap during update! Saving screenshot...");
+                self.save_screenshot();
+                // Screenshot doesn't change the display, go to deep sleep
+                Event::PassEnded(NextPass::Done)
+            }
+            _ if show_now => {
+                info!("On USB power, showing requested item now");
+                Event::PassEnded(NextPass::Another)
             }
             _ => {
-                // No button press (POLLING or CANCELLED), exit loop and go to deep sleep
+                // No button press (POLLING or CANCELLED), go to deep sleep
                 info!("No button press, entering deep sleep");
-                break;
+                Event::PassEnded(NextPass::Done)
             }
         }
-        // Loop back to re-display
     }
 
-    // Save state for next wake (index already advanced in the loop)
-    unsafe {
-        let state = &raw mut SLEEP_STATE;
-        (*state).save(
-            index,
+    /// Save what the panel shows to the SD card
+    fn save_screenshot(&mut self) {
+        let Some(cache) = self.sd_cache.as_mut() else {
+            info!("No SD card, cannot save screenshot");
+            return;
+        };
+        // A partial refresh only rendered one half, restore the other from cache
+        if let Some(slot) = self.pass.partial_slot {
+            let other_slot = 1 - slot;
+            let other_key = match &self.pass.held_slot {
+                Some(held) if other_slot == 1 => held.widget.as_str(),
+                _ => self.items[self.slot_items[other_slot as usize]].cache_key(),
+            };
+            let mut png_buf: Box<[u8; 256 * 1024]> = Box::new([0u8; 256 * 1024]);
+            let restored = cache
+                .read_image(other_key, Orientation::Horizontal, &mut *png_buf)
+                .ok()
+                .and_then(|len| {
+                    render::render_image_to_framebuffer(
+                        &png_buf[..len],
+                        &mut self.framebuffer,
+                        other_slot,
+                        Orientation::Horizontal,
+                    )
+                    .ok()
+                });
+            if restored.is_some() {
+                // Redraw the battery indicator that spans both halves
+                let (bat_w, _bat_h) = battery::battery_dimensions(false);
+                battery::draw_battery(
+                    self.framebuffer.as_mut_slice(),
+                    (WIDTH as u16 - bat_w) / 2,
+                    8,
+                    self.pass.battery_status,
+                    false,
+                );
+            } else {
+                info!("Could not restore other half for screenshot: {}", other_key);
+            }
+        }
+        match cache.write_screenshot(&self.framebuffer) {
+            Ok(filename) => info!("Saved screenshot {}", filename),
+            Err(e) => info!("Failed to save screenshot: {:?}", e),
+        }
+    }
+
+    /// Persist: the rotation saved to RTC memory, the wake reported to the
+    /// server, and the sleep until the next one worked out
+    async fn persist(&mut self) -> Event {
+        #[cfg(feature = "demo")]
+        if self.demo {
+            self.net.disconnect().await;
+            self.sleep_secs = Some(
+                self.error_state
+                    .retry_secs(self.device_config.refresh_interval_secs())
+                    * self.battery_level.sleep_multiplier(),
+            );
+            return Event::Persisted;
+        }
+
+        // The freeze follows the items on the panel, which a tap moves on
+        let total_items = self.items.len();
+        if let Some(frozen) = self.freeze.as_mut()
+            && total_items > 0
+        {
+            let first = match self.orientation {
+                Orientation::Horizontal => self.slot_items[0],
+                Orientation::Vertical => self.index + total_items - 1,
+            };
+            let item = self.items[first % total_items].as_str();
+            if frozen.item.as_str() != item
+                && let Some(moved) = Freeze::new(item)
+            {
+                info!("Freeze moved on to {}", item);
+                frozen.item = moved.item;
+                let frozen = frozen.clone();
+                if let Some(store) = self.settings()
+                    && let Err(e) = store.store_freeze(Some(&frozen))
+                {
+                    info!("Failed to store freeze: {:?}", e);
+                }
+            }
+        }
+
+        // Save state for next wake (index already advanced in the loop)
+        unsafe {
+            let state = &raw mut SLEEP_STATE;
+            (*state).save(
+                self.index,
+                total_items,
+                self.shuffle_seed,
+                self.orientation,
+                self.next_slot,
+                self.slot_items,
+                self.panel_tiles,
+                self.held_refreshed_at,
+                self.refreshes_since_clear,
+                self.freeze.clone(),
+                &self.items,
+            );
+        }
+        self.wake_progress.finish();
+        info!(
+            "Saved state: index={}, total={}, orientation={:?}, next_slot={}, slot_items=[{}, {}]",
+            self.index,
             total_items,
-            shuffle_seed,
-            orientation,
-            next_slot,
-            slot_items,
-            &items,
+            self.orientation,
+            self.next_slot,
+            self.slot_items[0],
+            self.slot_items[1]
         );
+
+        // Report the wake over the open session (no reconnect just for telemetry)
+        if let Some(s) = self.net.session.as_mut() {
+            let report = TelemetryReport {
+                battery_percent: self.net.battery_reading,
+                boot_reason: match self.reason {
+                    SleepSource::Timer => BootReason::Timer,
+                    SleepSource::Ext0 => BootReason::Button,
+                    SleepSource::Undefined => BootReason::PowerOn,
+                    _ => BootReason::Other,
+                },
+                refresh_ms: self.refresh_ms,
+                shown_item: self.shown_item.take(),
+                crash: crash_log().report(),
+            };
+            match s.post_telemetry(&report).await {
+                // Reported crashes aren't sent again
+                Ok(()) => crash_log().clear(),
+                Err(e) => info!("Failed to post telemetry: {:?}", e),
+            }
+
+            if let Some(shipping) = REMOTE_LOG {
+                let mut logs: Box<[u8; LOG_RING_SIZE]> = Box::new([0u8; LOG_RING_SIZE]);
+                let len = TimestampLogger::copy_captured(&mut *logs, shipping);
+                if len > 0 {
+                    match s.post_logs(&logs[..len]).await {
+                        Ok(()) => info!("Uploaded {} bytes of log", len),
+                        Err(e) => info!("Failed to upload log: {:?}", e),
+                    }
+                }
+            }
+        }
+
+        // Disconnect WiFi before deep sleep (only if still connected)
+        self.net.disconnect().await;
+
+        #[cfg(feature = "mqtt")]
+        if self.sleep_until_button {
+            info!("Sleeping until the button is pressed");
+            self.sleep_secs = None;
+            return Event::Persisted;
+        }
+        let mut sleep_secs = self.device_config.refresh_interval_secs();
+        if let Some(policy) = self.cache_policy
+            && let Some(secs) = policy.suggested_sleep_secs
+        {
+            sleep_secs = policy.sleep_secs(sleep_secs);
+            info!(
+                "Server suggested sleeping {}s, waking within {}s",
+                secs, sleep_secs
+            );
+        } else if self.data_stale {
+            // Check back sooner for the fresh list (servers predating suggested-sleep)
+            sleep_secs = sleep_secs.min(STALE_DATA_RETRY_SECS);
+            info!("Widget data was stale, waking within {}s", sleep_secs);
+        } else if self.has_cached_data && self.cached_freshness == Freshness::Expired {
+            // Past stale-while-revalidate and the server couldn't be reached
+            sleep_secs = sleep_secs.min(STALE_DATA_RETRY_SECS);
+            info!("Cached widget data expired, waking within {}s", sleep_secs);
+        }
+        if self.error_state.failures() > 0 {
+            sleep_secs = self.error_state.retry_secs(sleep_secs);
+            info!(
+                "{} failed wakes in a row, retrying within {}s",
+                self.error_state.failures(),
+                sleep_secs
+            );
+        }
+        let mut sleep_secs = sleep_secs * self.battery_level.sleep_multiplier();
+        // A frozen frame sleeps until its weekly clear
+        if let Some(frozen) = &self.freeze
+            && let FrozenWake::Sleep(secs) = frozen.wake(self.rtc.current_time_us())
+        {
+            info!("Frozen on {}, sleeping {}s", frozen.item, secs);
+            sleep_secs = secs;
+        }
+        if let Some(quiet_hours) = &self.device_config.quiet_hours
+            && let Some(unix_secs) = clock::unix_time()
+        {
+            let adjusted = quiet_hours.adjust_sleep(unix_secs, sleep_secs);
+            if adjusted != sleep_secs {
+                info!("Next wake falls in quiet hours, sleeping {}s", adjusted);
+                sleep_secs = adjusted;
+            }
+        }
+        self.sleep_secs = Some(sleep_secs);
+        Event::Persisted
     }
-    info!(
-        "Saved state: index={}, total={}, orientation={:?}, next_slot={}, slot_items=[{}, {}]",
-        index, total_items, orientation, next_slot, slot_items[0], slot_items[1]
-    );
 
-    // Disconnect WiFi before deep sleep (only if still connected)
-    if wifi_connected {
-        if let Some(ctrl) = wifi_controller.as_mut() {
-            info!("Disconnecting WiFi for deep sleep...");
-            wifi_disconnect(ctrl).await;
+    /// Show a status message on the panel (blocking full refresh)
+    fn show_status(&mut self, title: &str, detail: Option<&str>) {
+        info!("Showing status: {}", title);
+        text::draw_message(&mut self.framebuffer, self.orientation, title, detail);
+        if let Err(e) = self
+            .epd
+            .display(self.framebuffer.as_slice(), &mut self.delay)
+        {
+            info!("Failed to show status: {:?}", e);
         }
-    } else {
-        info!("WiFi already disconnected, skipping");
+        self.panel_tiles = None;
+    }
+
+    /// Record a failed wake and put the error screen on the panel (blocking
+    /// full refresh), unless it already shows this error
+    fn show_error(&mut self, code: u16) {
+        if !self.error_state.fail(code) {
+            info!("Error {} already shown", code);
+            return;
+        }
+        info!("Showing error {}", code);
+        let diagnostics = error_screen::Diagnostics {
+            ssid: self.net.credentials.ssid.as_str(),
+            server_url: SERVER_URL,
+            battery_percent: self.net.battery_reading,
+            sd_card: self.sd_cache.is_some(),
+        };
+        error_screen::draw_error_screen(
+            &mut self.framebuffer,
+            self.orientation,
+            code,
+            &diagnostics,
+        );
+        match self
+            .epd
+            .display(self.framebuffer.as_slice(), &mut self.delay)
+        {
+            Ok(()) => self.error_state.set_shown(code),
+            Err(e) => info!("Failed to show error: {:?}", e),
+        }
+        self.panel_tiles = None;
     }
 
-    // Reclaim GPIO4 for deep sleep wake source
-    let key_pin = unsafe { esp_hal::peripherals::GPIO4::steal() };
+    /// Sleep: power down the board and enter deep sleep (never returns), waking
+    /// after `sleep_secs` (if any) or on a button press
+    fn power_down_and_sleep(mut self) -> ! {
+        // The panel is already asleep; release everything else that would keep
+        // drawing current through deep sleep
+        let mut report = PowerDownReport::default();
+
+        // De-init the SD card, then hold it deselected (an SD card in SPI mode
+        // with CS low or floating stays out of its low-power standby)
+        if let Some(mut cache) = self.sd_cache.take() {
+            // Remember the server's address for wakes where DNS fails
+            if let Some(resolved) = self.net.dns.and_then(|dns| dns.to_store())
+                && let Err(e) = cache.store_resolved_host(&resolved)
+            {
+                info!("Failed to store server address: {:?}", e);
+            }
 
-    // Enter deep sleep
-    info!(
-        "Entering deep sleep for {} seconds (press button to wake early)...",
-        REFRESH_INTERVAL_SECS
-    );
-    enter_deep_sleep(&mut rtc, key_pin, &mut delay, REFRESH_INTERVAL_SECS);
+            // Keep this wake's log (anything logged from here on only
+            // reaches the serial console)
+            if SD_LOG {
+                TimestampLogger::capture(false);
+                match cache.append_log(TimestampLogger::read_captured) {
+                    Ok(len) => info!("Wrote {} bytes of log to SD", len),
+                    Err(e) => info!("Failed to write log to SD: {:?}", e),
+                }
+            }
+            cache.power_down();
+            report.sd_released = true;
+        }
+        let _sd_cs = Output::new(
+            unsafe { esp_hal::peripherals::GPIO38::steal() },
+            Level::High,
+            OutputConfig::default(),
+        );
+
+        // Switch off the panel rails and stop driving the panel's pins, which
+        // would otherwise back-power it through its input protection
+        CRASH_SCREEN.store(core::ptr::null_mut(), Ordering::Release);
+        drop(self.epd);
+        report.ldo_rails = match self.pmic.disable_ldos(Ldos::PANEL) {
+            Ok(rails) => Some(rails),
+            Err(e) => {
+                info!("Failed to switch off panel rails: {:?}", e);
+                None
+            }
+        };
+        let floating: [(AnyPin<'static>, Pull); 9] = unsafe {
+            use esp_hal::peripherals::*;
+            [
+                // Panel DC, CS, SCK, MOSI, RST and BUSY
+                (GPIO8::steal().into(), Pull::None),
+                (GPIO9::steal().into(), Pull::None),
+                (GPIO10::steal().into(), Pull::None),
+                (GPIO11::steal().into(), Pull::None),
+                (GPIO12::steal().into(), Pull::None),
+                (GPIO13::steal().into(), Pull::None),
+                // SD SCK, MISO and MOSI, pulled up like the card's own lines
+                (GPIO39::steal().into(), Pull::Up),
+                (GPIO40::steal().into(), Pull::Up),
+                (GPIO41::steal().into(), Pull::Up),
+            ]
+        };
+        report.pins_floated = floating.len() as u8;
+        for (pin, pull) in floating {
+            float_pin(pin, pull);
+        }
+
+        let sleep_current = report.sleep_current();
+        info!(
+            "Power down: sd_released={}, ldo_rails={:02x?}, pins_floated={}, expected sleep current {:?} ({})",
+            report.sd_released,
+            report.ldo_rails.map(Ldos::bits),
+            report.pins_floated,
+            sleep_current,
+            sleep_current.estimate()
+        );
+
+        // Keep a clock synced this wake for the next ones
+        if CLOCK_SYNCED.load(Ordering::Relaxed)
+            && let Some(unix_secs) = clock::unix_time()
+        {
+            self.wall_clock
+                .record(unix_secs, self.rtc.current_time_us());
+        }
+
+        // Reclaim GPIO4 for deep sleep wake source
+        let key_pin = unsafe { esp_hal::peripherals::GPIO4::steal() };
+
+        match self.sleep_secs {
+            Some(secs) => info!(
+                "Entering deep sleep for {} seconds (press button to wake early)...",
+                secs
+            ),
+            None => info!("Entering deep sleep until the button is pressed..."),
+        }
+        enter_deep_sleep(&mut self.rtc, key_pin, &mut self.delay, self.sleep_secs)
+    }
+}
+
+/// Open the settings store in the flash NVS partition, if the partition table has one
+fn open_nvs_settings(
+    flash: esp_hal::peripherals::FLASH<'static>,
+) -> Option<NvsStore<FlashStorage<'static>>> {
+    let mut storage = FlashStorage::new(flash);
+    let mut table = [0u8; partitions::PARTITION_TABLE_MAX_LEN];
+    let nvs = partitions::read_partition_table(&mut storage, &mut table)
+        .ok()?
+        .find_partition(PartitionType::Data(DataPartitionSubType::Nvs))
+        .ok()
+        .flatten();
+    let Some(nvs) = nvs else {
+        info!("No NVS partition, settings won't persist");
+        return None;
+    };
+    let (offset, size) = (nvs.offset(), nvs.len());
+    NvsStore::new(storage, offset, size)
+        .inspect_err(|e| info!("NVS settings unavailable: {:?}", e))
+        .ok()
+}
+
+/// 64 bits from the hardware RNG
+fn random_u64(rng: &Rng) -> u64 {
+    (rng.random() as u64) << 32 | rng.random() as u64
+}
+
+/// 32 bytes from the hardware RNG
+fn random_seed(rng: &Rng) -> [u8; 32] {
+    let mut seed = [0u8; 32];
+    for chunk in seed.chunks_exact_mut(4) {
+        chunk.copy_from_slice(&rng.random().to_le_bytes());
+    }
+    seed
+}
+
+/// Whether a URL uses https (usable in constants)
+const fn is_https(url: &str) -> bool {
+    let url = url.as_bytes();
+    let scheme = b"https:";
+    if url.len() < scheme.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < scheme.len() {
+        if url[i].to_ascii_lowercase() != scheme[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
 }
 
 /// Compute a single hash for all widget data
@@ -1340,21 +3616,29 @@ fn hash_data(items: &WidgetData) -> u32 {
         for byte in item.as_bytes() {
             hash = hash.wrapping_mul(33).wrapping_add(*byte as u32);
         }
+        hash = hash.wrapping_mul(33).wrapping_add(item.width as u32);
         hash = hash.wrapping_mul(33).wrapping_add(0); // separator
     }
     hash
 }
 
-/// Enter deep sleep with timer and KEY button (GPIO4) wake sources
+/// Disconnect a pin (no output driver, no input buffer) so it doesn't leak while asleep
+fn float_pin(pin: AnyPin<'static>, pull: Pull) {
+    let mut pin = Flex::new(pin);
+    pin.set_output_enable(false);
+    pin.set_input_enable(false);
+    pin.apply_input_config(&InputConfig::default().with_pull(pull));
+    // Keep the configuration (the driver would reset the pin when dropped)
+    core::mem::forget(pin);
+}
+
+/// Enter deep sleep with KEY button (GPIO4) and, given `seconds`, timer wake sources
 fn enter_deep_sleep<P: esp_hal::gpio::RtcPinWithResistors>(
     rtc: &mut Rtc,
     key_pin: P,
     delay: &mut Delay,
-    seconds: u64,
+    seconds: Option<u64>,
 ) -> ! {
-    // Configure wake sources
-    let timer = TimerWakeupSource::new(CoreDuration::from_secs(seconds));
-
     // Enable internal pull-up on GPIO4 so it doesn't float and trigger spurious wakes
     key_pin.rtcio_pullup(true);
     key_pin.rtcio_pulldown(false);
@@ -1366,7 +3650,132 @@ fn enter_deep_sleep<P: esp_hal::gpio::RtcPinWithResistors>(
     delay.delay_ms(100);
 
     // Enter deep sleep (never returns - device reboots on wake)
-    rtc.sleep_deep(&[&timer, &ext0])
+    match seconds {
+        Some(seconds) => {
+            let timer = TimerWakeupSource::new(CoreDuration::from_secs(seconds));
+            rtc.sleep_deep(&[&timer, &ext0])
+        }
+        None => rtc.sleep_deep(&[&ext0]),
+    }
+}
+
+/// The crash log in RTC memory
+///
+/// Shared by `main`, the watchdog task and the panic handler, which only touch
+/// it at boot, after the telemetry report and right before a reset.
+fn crash_log() -> &'static mut CrashLog {
+    unsafe {
+        let log = &raw mut CRASH_LOG;
+        &mut (*log).0
+    }
+}
+
+/// The reason for this boot's reset, if the firmware didn't ask for it
+fn unexpected_reset() -> Option<ResetReason> {
+    use SocResetReason::*;
+    match esp_hal::rtc_cntl::reset_reason(esp_hal::system::Cpu::ProCpu)? {
+        // Power-on, deep sleep wakes, restarts after provisioning and flashing
+        ChipPowerOn | CoreDeepSleep | CoreSw | Cpu0Sw | CoreUsbUart | CoreUsbJtag => None,
+        CoreMwdt0 | CoreMwdt1 | CoreRtcWdt | Cpu0Mwdt0 | Cpu0Mwdt1 | Cpu0RtcWdt | SysRtcWdt
+        | SysSuperWdt => Some(ResetReason::Watchdog),
+        SysBrownOut => Some(ResetReason::Brownout),
+        _ => Some(ResetReason::Other),
+    }
+}
+
+/// Record that the main loop made progress
+fn heartbeat() {
+    HEARTBEAT.beat(Instant::now().as_secs() as u32);
+}
+
+/// Feed the hardware watchdog while the main loop keeps making progress
+#[embassy_executor::task]
+async fn watchdog_task(mut wdt: Wdt<TIMG1<'static>>) {
+    let mut stall_logged = false;
+    loop {
+        if !HEARTBEAT.stalled(Instant::now().as_secs() as u32) {
+            wdt.feed();
+        } else if !stall_logged {
+            crash_log().record(format_args!(
+                "main loop stalled for {}s",
+                watchdog::STALL_SECS
+            ));
+            warn!(
+                "Main loop stalled for {}s, restarting",
+                watchdog::STALL_SECS
+            );
+            stall_logged = true;
+        }
+        Timer::after(Duration::from_secs(1)).await;
+    }
+}
+
+/// Print the panic, put it on the panel if the display is set up, then restart
+///
+/// The main loop never resumes, so the panel and framebuffer are taken from
+/// under it. Only the first panic draws: one while drawing goes straight to
+/// the restart, and the hardware watchdog restarts the chip if the panel hangs.
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    esp_println::println!("{}", info);
+
+    // Kept for the telemetry report after the restart
+    let log = crash_log();
+    log.validate();
+    match info.location() {
+        Some(loc) => log.record(format_args!(
+            "{} at {}:{}",
+            info.message(),
+            loc.file(),
+            loc.line()
+        )),
+        None => log.record(format_args!("{}", info.message())),
+    }
+
+    let screen = CRASH_SCREEN.swap(core::ptr::null_mut(), Ordering::AcqRel);
+    if !screen.is_null() {
+        let (epd, framebuffer) = unsafe { (&mut *(*screen).epd, &mut *(*screen).framebuffer) };
+        show_crash(epd, framebuffer, info);
+    }
+
+    esp_hal::system::software_reset()
+}
+
+/// Draw the panic message with a fast partial refresh of the whole panel
+fn show_crash(epd: &mut Panel, framebuffer: &mut Framebuffer, info: &PanicInfo) {
+    // Overlong messages are cut off
+    let mut message: heapless::String<96> = heapless::String::new();
+    let _ = write!(message, "{}", info.message());
+    let mut location: heapless::String<64> = heapless::String::new();
+    if let Some(loc) = info.location() {
+        let _ = write!(location, "{}:{}", loc.file(), loc.line());
+    }
+
+    // The orientation saved at the last sleep, as the panel was last hung
+    let orientation = unsafe {
+        let state = &raw mut SLEEP_STATE;
+        if (*state).validate() {
+            (*state).get_orientation()
+        } else {
+            Orientation::default()
+        }
+    };
+    text::draw_lines(
+        framebuffer,
+        orientation,
+        "Firmware crashed",
+        &[message.as_str(), location.as_str(), "Restarting..."],
+    );
+
+    let mut delay = esp_hal::delay::Delay::new();
+    let full_panel = Rect::new(0, 0, WIDTH as u16, HEIGHT as u16);
+    let shown = epd
+        .wake_up(&mut delay)
+        .and_then(|()| epd.partial_update_start(&full_panel, framebuffer.as_slice(), &mut delay))
+        .and_then(|()| epd.refresh_wait(&mut delay));
+    if let Err(e) = shown {
+        esp_println::println!("Failed to show the crash: {:?}", e);
+    }
 }
 
 #[embassy_executor::task]
@@ -1374,16 +3783,72 @@ async fn net_task(mut runner: Runner<'static, WifiDevice<'static>>) {
     runner.run().await
 }
 
-/// Connect to WiFi network
-async fn wifi_connect(controller: &mut WifiController<'static>) {
+/// Sync the wall clock over SNTP (SD cache file times)
+#[embassy_executor::task]
+async fn sntp_task(stack: Stack<'static>) {
+    if clock::sync(stack).await.is_ok() {
+        CLOCK_SYNCED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Start the SoftAP and serve the provisioning portal until credentials are submitted
+async fn run_provisioning(
+    spawner: Spawner,
+    wifi: esp_hal::peripherals::WIFI<'static>,
+    seed: u64,
+) -> WifiCredentials {
+    // The portal waits on the user for as long as it takes
+    HEARTBEAT.pause();
+    start_fast_blink();
+
+    let ctrl = mk_static!(Controller<'static>, esp_radio::init().unwrap());
+    let (mut controller, ifaces) = esp_radio::wifi::new(ctrl, wifi, WifiConfig::default()).unwrap();
+
+    let ap_config =
+        ModeConfig::AccessPoint(AccessPointConfig::default().with_ssid(provision::AP_SSID.into()));
+    controller.set_config(&ap_config).unwrap();
+    info!("Starting access point...");
+    controller.start_async().await.unwrap();
+    info!("Access point '{}' started", provision::AP_SSID);
+
+    let net_config = embassy_net::Config::ipv4_static(StaticConfigV4 {
+        address: Ipv4Cidr::new(provision::AP_ADDRESS, provision::AP_PREFIX_LEN),
+        gateway: Some(provision::AP_ADDRESS),
+        dns_servers: Default::default(),
+    });
+    let (stack, runner) = embassy_net::new(
+        ifaces.ap,
+        net_config,
+        mk_static!(StackResources<4>, StackResources::<4>::new()),
+        seed,
+    );
+    spawner.spawn(net_task(runner)).ok();
+    stack.wait_config_up().await;
+
+    let credentials = provision::run_portal(stack).await;
+
+    // Give the confirmation page time to reach the client before the AP goes down
+    Timer::after(Duration::from_secs(1)).await;
+    if let Err(e) = controller.stop_async().await {
+        info!("Stop error: {:?}", e);
+    }
+    stop_blink();
+    credentials
+}
+
+/// Connect to WiFi network, returns false if every attempt failed
+async fn wifi_connect(
+    controller: &mut WifiController<'static>,
+    credentials: &WifiCredentials,
+) -> bool {
     start_fast_blink();
     info!("Device capabilities: {:?}", controller.capabilities());
 
     if !matches!(controller.is_started(), Ok(true)) {
         let client_config = ModeConfig::Client(
             ClientConfig::default()
-                .with_ssid(SSID.into())
-                .with_password(PASSWORD.into()),
+                .with_ssid(credentials.ssid.as_str().into())
+                .with_password(credentials.password.as_str().into()),
         );
         controller.set_config(&client_config).unwrap();
         info!("Starting WiFi...");
@@ -1391,20 +3856,25 @@ async fn wifi_connect(controller: &mut WifiController<'static>) {
         info!("WiFi started!");
     }
 
-    info!("Connecting to {}...", SSID);
-    loop {
+    info!("Connecting to {}...", credentials.ssid.as_str());
+    for attempt in 1..=WIFI_CONNECT_ATTEMPTS {
         match controller.connect_async().await {
             Ok(_) => {
                 info!("WiFi connected!");
                 stop_blink();
-                break;
+                return true;
             }
             Err(e) => {
-                info!("Failed to connect: {e:?}, retrying...");
+                info!(
+                    "Failed to connect (attempt {}/{}): {e:?}",
+                    attempt, WIFI_CONNECT_ATTEMPTS
+                );
                 Timer::after(Duration::from_secs(5)).await;
             }
         }
     }
+    stop_blink();
+    false
 }
 
 /// Disconnect and stop WiFi to save power
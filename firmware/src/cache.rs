@@ -1,23 +1,68 @@
 //! SD card-based image cache
 //!
-//! Stores PNG images directly on the SD card's FAT filesystem.
-//! Directory structure mirrors the API paths:
+//! Stores images directly on the SD card's FAT filesystem, as the server sent
+//! them: PNGs, or packed 4bpp bodies on `RAW_IMAGES` builds (still named `.png`).
+//! Directory structure:
 //!
 //! /concerts/
-//!   widget.json              - JSON array of item paths
+//!   widget.json              - JSON array of widget items
+//!   widget.tag               - ETag of the widget data (CRC-32, u32 LE)
 //!   horiz/
-//!     {item-path}.png        - horizontal orientation images
+//!     {AB}/{CDEF0123}.png    - horizontal orientation images
+//!     {AB}/{CDEF0123}.key    - cache key of the image beside it
 //!   vert/
-//!     {item-path}.png        - vertical orientation images
+//!     {AB}/{CDEF0123}.png    - vertical orientation images
+//!     {AB}/{CDEF0123}.key    - cache key of the image beside it
+//!
+//! Images are named by a 64-bit hash of their cache key, split into a bucket
+//! directory and an 8.3 filename. The key sidecar catches hash collisions,
+//! which are treated as a miss rather than showing another item's image.
+//!
+//! The on-card formats (filenames, footer, LRU index) live in [`crate::cache_layout`],
+//! free of SD card I/O so they can be tested on the host.
+//!
+//! Cached images end with a footer (magic, length, CRC-32) written after the
+//! image data. embedded-sdmmc can't rename files, so rather than writing to a
+//! temporary name and renaming, a file only counts as a cache hit once its
+//! footer validates; anything cut short by power loss is discarded on read.
+//! The footer CRC doubles as the image's ETag, matching the server's.
+//!
+//! `init` also scrubs debris that years of unattended operation can leave
+//! behind: zero-length images, temp-named leftovers from other tools, and
+//! images cached in both orientations whose footer is malformed.
+//!
+//! Total usage is bounded by `enforce_budget`, which evicts the least recently
+//! read or written images. Access order is tracked with a logical clock in an
+//! index file (`LRU.IDX`): file times only change on writes, and are only real
+//! once SNTP has synced (see [`clock`]), but they do date each image for debugging.
+
+extern crate alloc;
 
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 use core::fmt::Write as FmtWrite;
 
 use embedded_hal::spi::SpiDevice;
-use embedded_sdmmc::{Mode, SdCard, TimeSource, Timestamp, VolumeIdx, VolumeManager};
+use embedded_sdmmc::{DirEntry, Mode, SdCard, TimeSource, Timestamp, VolumeIdx, VolumeManager};
 use heapless::String;
 use log::info;
 
-use crate::widget::{Orientation, WidgetData};
+use crate::cache_layout::{
+    FOOTER_SIZE, IMAGE_EXTENSION, KEY_EXTENSION, LRU_HEADER_SIZE, LRU_RECORD_SIZE, LruIndex,
+    MAX_LRU_ENTRIES, bucket_name, cache_filename, cache_id, eviction_order, footer_crc, id_bucket,
+    image_footer, is_temp_filename, parse_bucket_dir, parse_cache_filename, verify_footer,
+};
+use crate::clock::{self, CivilTime};
+use crate::config::{self, CONFIG_JSON_SIZE, DeviceConfig};
+use crate::dns_cache::{self, RESOLVED_HOST_SIZE, ResolvedHost};
+use crate::framebuffer::Framebuffer;
+use crate::freeze::{self, Freeze};
+use crate::provision::WifiCredentials;
+use crate::screenshot;
+use crate::widget::{
+    ITEM_JSON_LEN, MAX_PATH_LEN, Orientation, WIDGET_JSON_SIZE, WidgetData, parse_widget_data,
+    write_item_json,
+};
 
 /// Root directory (mirrors API path)
 const ROOT_DIR: &str = "concerts";
@@ -28,25 +73,74 @@ const HORIZ_DIR: &str = "horiz";
 /// Vertical orientation subdirectory
 const VERT_DIR: &str = "vert";
 
-/// Widget data filename (JSON array of item paths) - 8.3 format
+/// Widget data filename (JSON array of widget items) - 8.3 format
 const WIDGET_FILE: &str = "WIDGET.JSN";
 
+/// Widget data ETag filename - 8.3 format
+const WIDGET_TAG_FILE: &str = "WIDGET.TAG";
+
+/// Image access index filename (LRU order) - 8.3 format
+const LRU_FILE: &str = "LRU.IDX";
+
 /// Orientation state filename - 8.3 format
 const ORIENT_FILE: &str = "ORIENT.DAT";
 
-/// Dummy time source (SD cards need timestamps but we don't care)
-pub struct DummyTimesource;
+/// Device config filename (JSON from the `/config` endpoint) - 8.3 format
+const CONFIG_FILE: &str = "CONFIG.JSN";
+
+/// WiFi credentials filename (SSID and password lines) - 8.3 format
+const WIFI_FILE: &str = "WIFI.CFG";
+
+/// Frozen item path (empty when not frozen) - 8.3 format
+const FREEZE_FILE: &str = "FREEZE.DAT";
+
+/// Last resolved server address (see `dns_cache`) - 8.3 format
+const DNS_FILE: &str = "DNS.DAT";
+
+/// Screenshot directory at the SD root - 8.3 format
+const SCREENSHOT_DIR: &str = "SCRNSHOT";
+
+/// Screenshot filename prefix (followed by a 4-digit sequence number)
+const SCREENSHOT_PREFIX: &str = "SHOT";
+
+/// Log file at the SD root - 8.3 format
+const LOG_FILE: &str = "LOG.TXT";
 
-impl TimeSource for DummyTimesource {
+/// The log before the last rotation, at the SD root - 8.3 format
+const OLD_LOG_FILE: &str = "LOG.OLD";
+
+/// Log size from which the next flush rotates it
+const LOG_MAX_BYTES: u32 = 256 * 1024;
+
+/// Largest sidecar read (cache keys are at most 48 bytes)
+const KEY_FILE_SIZE: usize = 64;
+
+/// Most files removed per directory in one scrub
+const MAX_SCRUB_DELETES: usize = 64;
+
+/// Stamps files with the wall-clock time once it's known (see [`clock`]),
+/// and 2025-01-01 before then
+pub struct ClockTimesource;
+
+impl TimeSource for ClockTimesource {
     fn get_timestamp(&self) -> Timestamp {
-        Timestamp {
-            year_since_1970: 55, // 2025
-            zero_indexed_month: 0,
-            zero_indexed_day: 0,
-            hours: 0,
-            minutes: 0,
-            seconds: 0,
-        }
+        fat_timestamp(clock::unix_time().unwrap_or(FALLBACK_UNIX_SECS))
+    }
+}
+
+/// Time files are stamped with while the clock is unknown (2025-01-01)
+const FALLBACK_UNIX_SECS: u64 = 1_735_689_600;
+
+/// FAT timestamp of a Unix time, clamped to the years FAT can store
+fn fat_timestamp(unix_secs: u64) -> Timestamp {
+    let time = CivilTime::from_unix(unix_secs);
+    Timestamp {
+        year_since_1970: time.year.clamp(1980, 2107).saturating_sub(1970) as u8,
+        zero_indexed_month: time.month - 1,
+        zero_indexed_day: time.day - 1,
+        hours: time.hours,
+        minutes: time.minutes,
+        seconds: time.seconds,
     }
 }
 
@@ -65,20 +159,41 @@ pub enum CacheError {
     Write,
     /// Read error
     Read,
+    /// Cached file failed validation (incomplete write)
+    Corrupt,
+    /// Cached file belongs to another cache key with the same hash
+    Collision,
+}
+
+/// Files removed by the init scrub
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ScrubReport {
+    /// Zero-length images
+    pub empty: u32,
+    /// Temp-named or unrecognized files
+    pub leftovers: u32,
+    /// Images with a malformed footer
+    pub corrupt: u32,
 }
 
-/// Generate cache filename for an image
-/// Format: 8-char hash + .PNG (FAT 8.3 compatible)
-/// Uses djb2 hash of the path to create a short unique filename
-fn cache_filename(path: &str) -> String<16> {
-    // djb2 hash
-    let mut hash: u32 = 5381;
-    for byte in path.as_bytes() {
-        hash = hash.wrapping_mul(33).wrapping_add(*byte as u32);
+impl ScrubReport {
+    /// Total files removed
+    pub fn total(&self) -> u32 {
+        self.empty + self.leftovers + self.corrupt
     }
+}
+
+/// Full 8.3 filename of a directory entry (e.g. "ABCD1234.PNG")
+fn entry_filename(entry: &DirEntry) -> Option<String<16>> {
+    let base = core::str::from_utf8(entry.name.base_name()).ok()?.trim();
+    let ext = core::str::from_utf8(entry.name.extension()).ok()?.trim();
     let mut name: String<16> = String::new();
-    let _ = write!(name, "{:08X}.PNG", hash);
-    name
+    if ext.is_empty() {
+        write!(name, "{}", base).ok()?;
+    } else {
+        write!(name, "{}.{}", base, ext).ok()?;
+    }
+    Some(name)
 }
 
 /// Get orientation subdirectory name
@@ -89,31 +204,42 @@ fn orientation_dir(orientation: Orientation) -> &'static str {
     }
 }
 
-/// Compute hash for a path (same algorithm as cache_filename)
-fn path_hash(path: &str) -> u32 {
-    let mut hash: u32 = 5381;
-    for byte in path.as_bytes() {
-        hash = hash.wrapping_mul(33).wrapping_add(*byte as u32);
-    }
-    hash
+/// Small settings that persist across power cycles
+///
+/// Kept on the SD card by [`SdCache`], or in flash by
+/// [`NvsStore`](crate::nvs::NvsStore) on frames without a card.
+pub trait SettingsStore {
+    /// Orientation chosen on the device
+    fn load_orientation(&mut self) -> Option<Orientation>;
+    fn store_orientation(&mut self, orientation: Orientation) -> Result<(), CacheError>;
+
+    /// Last device config received from the server
+    fn load_device_config(&mut self) -> Option<DeviceConfig>;
+    fn store_device_config(&mut self, device_config: &DeviceConfig) -> Result<(), CacheError>;
+
+    /// WiFi credentials from the provisioning portal
+    fn load_wifi_credentials(&mut self) -> Option<WifiCredentials>;
+    fn store_wifi_credentials(&mut self, credentials: &WifiCredentials) -> Result<(), CacheError>;
+
+    /// Item the frame is frozen on (see `freeze`), `None` clearing it
+    fn load_freeze(&mut self) -> Option<Freeze>;
+    fn store_freeze(&mut self, freeze: Option<&Freeze>) -> Result<(), CacheError>;
 }
 
-/// Parse cache filename to extract hash value
-/// Input: ABCD1234.PNG
-/// Output: hash value as u32
-fn parse_cache_filename(filename: &str) -> Option<u32> {
-    // Remove .png suffix (FAT filesystems uppercase extensions)
-    let name = filename
-        .strip_suffix(".PNG")
-        .or_else(|| filename.strip_suffix(".png"))?;
-
-    // Parse hex string
-    u32::from_str_radix(name.trim(), 16).ok()
+/// Parse stored WiFi credentials: the SSID, a newline, then the password
+pub(crate) fn parse_wifi_credentials(content: &[u8]) -> Option<WifiCredentials> {
+    let content = core::str::from_utf8(content).ok()?;
+    let mut lines = content.split('\n');
+    let ssid = lines.next()?;
+    let password = lines.next().unwrap_or("");
+    WifiCredentials::new(ssid, password)
 }
 
 /// SD card image cache
 pub struct SdCache<SPI: SpiDevice, DELAY: embedded_hal::delay::DelayNs> {
-    volume_mgr: VolumeManager<SdCard<SPI, DELAY>, DummyTimesource>,
+    volume_mgr: VolumeManager<SdCard<SPI, DELAY>, ClockTimesource>,
+    /// Image access order, loaded by `init` and saved by `enforce_budget`
+    lru: LruIndex,
 }
 
 impl<SPI, DELAY> SdCache<SPI, DELAY>
@@ -134,13 +260,152 @@ where
             }
         }
 
-        let volume_mgr = VolumeManager::new(sd_card, DummyTimesource);
+        let volume_mgr = VolumeManager::new(sd_card, ClockTimesource);
+
+        Ok(Self {
+            volume_mgr,
+            lru: LruIndex::default(),
+        })
+    }
 
-        Ok(Self { volume_mgr })
+    /// Release the card before deep sleep
+    ///
+    /// Closes the volume manager and marks the card uninitialized. Dropping the
+    /// SPI device leaves chip select to the caller, which should hold it high.
+    pub fn power_down(self) {
+        let (sd_card, _) = self.volume_mgr.free();
+        sd_card.mark_card_uninit();
     }
 
     /// Initialize cache directory structure: /concerts/horiz/ and /concerts/vert/
-    pub fn init(&mut self) -> Result<(), CacheError> {
+    ///
+    /// Then loads the image access index and scrubs the cache directories,
+    /// returning what was removed.
+    pub fn init(&mut self) -> Result<ScrubReport, CacheError> {
+        self.make_dirs()?;
+        self.lru = self.load_lru().unwrap_or_default();
+        self.scrub()
+    }
+
+    /// Read the image access index
+    fn load_lru(&mut self) -> Option<LruIndex> {
+        let mut volume = self.volume_mgr.open_volume(VolumeIdx(0)).ok()?;
+        let mut root_dir = volume.open_root_dir().ok()?;
+        let mut concerts_dir = root_dir.open_dir(ROOT_DIR).ok()?;
+        let mut file = concerts_dir
+            .open_file_in_dir(LRU_FILE, Mode::ReadOnly)
+            .ok()?;
+
+        let len = (file.length() as usize).min(LRU_HEADER_SIZE + MAX_LRU_ENTRIES * LRU_RECORD_SIZE);
+        let mut buf = alloc::vec![0u8; len];
+        let mut total_read = 0;
+        while total_read < len {
+            match file.read(&mut buf[total_read..]) {
+                Ok(0) => break,
+                Ok(n) => total_read += n,
+                Err(_) => return None,
+            }
+        }
+        Some(LruIndex::parse(&buf[..total_read]))
+    }
+
+    /// Write the image access index
+    fn store_lru(&mut self) -> Result<(), CacheError> {
+        let bytes = self.lru.to_bytes();
+
+        let mut volume = self
+            .volume_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(|_| CacheError::Filesystem)?;
+        let mut root_dir = volume.open_root_dir().map_err(|_| CacheError::Filesystem)?;
+        let mut concerts_dir = root_dir
+            .open_dir(ROOT_DIR)
+            .map_err(|_| CacheError::Filesystem)?;
+        let mut file = concerts_dir
+            .open_file_in_dir(LRU_FILE, Mode::ReadWriteCreateOrTruncate)
+            .map_err(|_| CacheError::Write)?;
+        file.write(&bytes).map_err(|_| CacheError::Write)?;
+        Ok(())
+    }
+
+    /// Evict the least recently used images until the cache fits in `max_bytes`
+    ///
+    /// Images are ordered by their last read or write; those cached before the
+    /// access index existed count as the oldest. Also saves the index, so it
+    /// should run once per wake. Returns the number of images evicted.
+    pub fn enforce_budget(&mut self, max_bytes: u64) -> Result<u32, CacheError> {
+        // Orientation, cache id and size of every cached image
+        let mut files: Vec<(u8, u64, u32)> = Vec::new();
+        {
+            let mut volume = self
+                .volume_mgr
+                .open_volume(VolumeIdx(0))
+                .map_err(|_| CacheError::Filesystem)?;
+            let mut root_dir = volume.open_root_dir().map_err(|_| CacheError::Filesystem)?;
+            let mut concerts_dir = root_dir
+                .open_dir(ROOT_DIR)
+                .map_err(|_| CacheError::Filesystem)?;
+            for (orient, dir) in [HORIZ_DIR, VERT_DIR].into_iter().enumerate() {
+                let Ok(mut orient_dir) = concerts_dir.open_dir(dir) else {
+                    continue;
+                };
+                let mut buckets: heapless::Vec<u8, 256> = heapless::Vec::new();
+                orient_dir
+                    .iterate_dir(|entry| {
+                        if entry.attributes.is_directory()
+                            && let Some(name) = entry_filename(entry)
+                            && let Some(bucket) = parse_bucket_dir(name.as_str())
+                        {
+                            let _ = buckets.push(bucket);
+                        }
+                    })
+                    .map_err(|_| CacheError::Filesystem)?;
+
+                for &bucket in buckets.iter() {
+                    let Ok(mut bucket_dir) = orient_dir.open_dir(bucket_name(bucket).as_str())
+                    else {
+                        continue;
+                    };
+                    bucket_dir
+                        .iterate_dir(|entry| {
+                            if !entry.attributes.is_directory()
+                                && let Some(name) = entry_filename(entry)
+                                && let Some((id, IMAGE_EXTENSION)) =
+                                    parse_cache_filename(bucket, name.as_str())
+                            {
+                                files.push((orient as u8, id, entry.size));
+                            }
+                        })
+                        .map_err(|_| CacheError::Filesystem)?;
+                }
+            }
+        }
+
+        let evict = eviction_order(&mut files, &self.lru, max_bytes);
+        let mut evicted = 0u32;
+        for &(orient, id, _) in &files[..evict] {
+            let dir = if orient == 0 { HORIZ_DIR } else { VERT_DIR };
+            if self.delete_image(dir, id) {
+                info!(
+                    "Evicted cache file: {}/{}/{}/{}",
+                    ROOT_DIR,
+                    dir,
+                    bucket_name(id_bucket(id)),
+                    cache_filename(id, IMAGE_EXTENSION)
+                );
+                evicted += 1;
+            }
+        }
+
+        // Forget images that are gone (evicted, scrubbed or cleaned up)
+        self.lru.retain(&files[evict..]);
+        self.store_lru()?;
+
+        Ok(evicted)
+    }
+
+    /// Create the cache directories if they don't exist
+    fn make_dirs(&mut self) -> Result<(), CacheError> {
         // Open volume (partition 0)
         let mut volume = self
             .volume_mgr
@@ -183,9 +448,13 @@ where
         Ok(())
     }
 
-    /// Check if an image is cached
+    /// Check if an image is cached (the footer is validated when it is read)
     pub fn has_image(&mut self, path: &str, orientation: Orientation) -> bool {
-        let filename = cache_filename(path);
+        let id = cache_id(path);
+        let orient = orientation_dir(orientation);
+        if self.stored_key(orient, id).as_deref() != Some(path) {
+            return false;
+        }
 
         let Ok(mut volume) = self.volume_mgr.open_volume(VolumeIdx(0)) else {
             return false;
@@ -199,25 +468,292 @@ where
             return false;
         };
 
-        let Ok(mut orient_dir) = concerts_dir.open_dir(orientation_dir(orientation)) else {
+        let Ok(mut orient_dir) = concerts_dir.open_dir(orient) else {
+            return false;
+        };
+
+        let Ok(mut bucket_dir) = orient_dir.open_dir(bucket_name(id_bucket(id)).as_str()) else {
             return false;
         };
 
         // Try to open the file - if it succeeds, it exists
-        orient_dir
-            .open_file_in_dir(filename.as_str(), Mode::ReadOnly)
+        bucket_dir
+            .open_file_in_dir(cache_filename(id, IMAGE_EXTENSION).as_str(), Mode::ReadOnly)
             .is_ok()
     }
 
-    /// Read cached image into buffer, returns bytes read
+    /// ETag (footer CRC-32) of a cached image, read without loading the image
+    pub fn image_etag(&mut self, path: &str, orientation: Orientation) -> Option<u32> {
+        let id = cache_id(path);
+        let orient = orientation_dir(orientation);
+        if self.stored_key(orient, id).as_deref() != Some(path) {
+            return None;
+        }
+        self.stored_footer_crc(orient, id)
+    }
+
+    /// Cache key in an image's sidecar, if there is one
+    fn stored_key(&mut self, orient: &str, id: u64) -> Option<String<KEY_FILE_SIZE>> {
+        let mut volume = self.volume_mgr.open_volume(VolumeIdx(0)).ok()?;
+        let mut root_dir = volume.open_root_dir().ok()?;
+        let mut concerts_dir = root_dir.open_dir(ROOT_DIR).ok()?;
+        let mut orient_dir = concerts_dir.open_dir(orient).ok()?;
+        let mut bucket_dir = orient_dir
+            .open_dir(bucket_name(id_bucket(id)).as_str())
+            .ok()?;
+        let mut file = bucket_dir
+            .open_file_in_dir(cache_filename(id, KEY_EXTENSION).as_str(), Mode::ReadOnly)
+            .ok()?;
+
+        let mut buf = [0u8; KEY_FILE_SIZE];
+        let mut total_read = 0;
+        while total_read < buf.len() {
+            match file.read(&mut buf[total_read..]) {
+                Ok(0) => break,
+                Ok(n) => total_read += n,
+                Err(_) => return None,
+            }
+        }
+
+        let key = core::str::from_utf8(&buf[..total_read]).ok()?;
+        String::try_from(key).ok()
+    }
+
+    /// CRC-32 from a cached image's footer, if the footer is well-formed
+    fn stored_footer_crc(&mut self, orient: &str, id: u64) -> Option<u32> {
+        let mut volume = self.volume_mgr.open_volume(VolumeIdx(0)).ok()?;
+        let mut root_dir = volume.open_root_dir().ok()?;
+        let mut concerts_dir = root_dir.open_dir(ROOT_DIR).ok()?;
+        let mut orient_dir = concerts_dir.open_dir(orient).ok()?;
+        let mut bucket_dir = orient_dir
+            .open_dir(bucket_name(id_bucket(id)).as_str())
+            .ok()?;
+        let mut file = bucket_dir
+            .open_file_in_dir(cache_filename(id, IMAGE_EXTENSION).as_str(), Mode::ReadOnly)
+            .ok()?;
+
+        let file_len = file.length();
+        if (file_len as usize) < FOOTER_SIZE {
+            return None;
+        }
+        file.seek_from_end(FOOTER_SIZE as u32).ok()?;
+        let mut footer = [0u8; FOOTER_SIZE];
+        let mut total_read = 0;
+        while total_read < FOOTER_SIZE {
+            match file.read(&mut footer[total_read..]) {
+                Ok(0) | Err(_) => return None,
+                Ok(n) => total_read += n,
+            }
+        }
+
+        footer_crc(&footer, file_len)
+    }
+
+    /// Remove empty images, temp-named leftovers and damaged images
+    ///
+    /// Only directory listings and footers are read, so this is cheap enough to
+    /// run on every boot. Images cached in both orientations are checked for a
+    /// well-formed footer; a damaged image in just one is discarded when read.
+    /// Files outside the bucket directories (including images from the old flat
+    /// layout) and sidecars without an image are leftovers.
+    pub fn scrub(&mut self) -> Result<ScrubReport, CacheError> {
+        let mut report = ScrubReport::default();
+        // Ids of the images found in each orientation directory
+        let mut ids: [Vec<u64>; 2] = Default::default();
+
+        {
+            let mut volume = self
+                .volume_mgr
+                .open_volume(VolumeIdx(0))
+                .map_err(|_| CacheError::Filesystem)?;
+
+            let mut root_dir = volume.open_root_dir().map_err(|_| CacheError::Filesystem)?;
+
+            let mut concerts_dir = root_dir
+                .open_dir(ROOT_DIR)
+                .map_err(|_| CacheError::Filesystem)?;
+
+            // Temp files next to the widget data and settings
+            let mut leftovers: heapless::Vec<String<16>, MAX_SCRUB_DELETES> = heapless::Vec::new();
+            concerts_dir
+                .iterate_dir(|entry| {
+                    if !entry.attributes.is_directory()
+                        && let Some(name) = entry_filename(entry)
+                        && is_temp_filename(name.as_str())
+                    {
+                        let _ = leftovers.push(name);
+                    }
+                })
+                .map_err(|_| CacheError::Filesystem)?;
+            for filename in leftovers.iter() {
+                if concerts_dir.delete_file_in_dir(filename.as_str()).is_ok() {
+                    info!("Removed leftover file: {}/{}", ROOT_DIR, filename);
+                    report.leftovers += 1;
+                }
+            }
+
+            for (orient, found) in [HORIZ_DIR, VERT_DIR].into_iter().zip(ids.iter_mut()) {
+                let Ok(mut orient_dir) = concerts_dir.open_dir(orient) else {
+                    continue;
+                };
+
+                // Only bucket directories belong here
+                let mut buckets: heapless::Vec<u8, 256> = heapless::Vec::new();
+                let mut leftovers: heapless::Vec<String<16>, MAX_SCRUB_DELETES> =
+                    heapless::Vec::new();
+                orient_dir
+                    .iterate_dir(|entry| {
+                        let Some(name) = entry_filename(entry) else {
+                            return;
+                        };
+                        if !entry.attributes.is_directory() {
+                            let _ = leftovers.push(name);
+                        } else if let Some(bucket) = parse_bucket_dir(name.as_str()) {
+                            let _ = buckets.push(bucket);
+                        }
+                    })
+                    .map_err(|_| CacheError::Filesystem)?;
+                for filename in leftovers.iter() {
+                    if orient_dir.delete_file_in_dir(filename.as_str()).is_ok() {
+                        info!(
+                            "Removed leftover file: {}/{}/{}",
+                            ROOT_DIR, orient, filename
+                        );
+                        report.leftovers += 1;
+                    }
+                }
+
+                for &bucket_id in buckets.iter() {
+                    let bucket = bucket_name(bucket_id);
+                    let Ok(mut bucket_dir) = orient_dir.open_dir(bucket.as_str()) else {
+                        continue;
+                    };
+
+                    // Anything that isn't a cache image or sidecar is a leftover
+                    let mut empty: heapless::Vec<String<16>, MAX_SCRUB_DELETES> =
+                        heapless::Vec::new();
+                    let mut leftovers: heapless::Vec<String<16>, MAX_SCRUB_DELETES> =
+                        heapless::Vec::new();
+                    let mut keys: Vec<u64> = Vec::new();
+                    let start = found.len();
+                    bucket_dir
+                        .iterate_dir(|entry| {
+                            if entry.attributes.is_directory() {
+                                return;
+                            }
+                            let Some(name) = entry_filename(entry) else {
+                                return;
+                            };
+                            match parse_cache_filename(bucket_id, name.as_str()) {
+                                None => {
+                                    let _ = leftovers.push(name);
+                                }
+                                Some((id, KEY_EXTENSION)) => keys.push(id),
+                                Some(_) if entry.size == 0 => {
+                                    let _ = empty.push(name);
+                                }
+                                Some((id, _)) => found.push(id),
+                            }
+                        })
+                        .map_err(|_| CacheError::Filesystem)?;
+
+                    // Sidecars of images that are gone or empty
+                    for &id in keys.iter().filter(|id| !found[start..].contains(id)) {
+                        let _ = leftovers.push(cache_filename(id, KEY_EXTENSION));
+                    }
+
+                    for filename in empty.iter() {
+                        if bucket_dir.delete_file_in_dir(filename.as_str()).is_ok() {
+                            info!(
+                                "Removed empty cache file: {}/{}/{}/{}",
+                                ROOT_DIR, orient, bucket, filename
+                            );
+                            report.empty += 1;
+                        }
+                    }
+                    for filename in leftovers.iter() {
+                        if bucket_dir.delete_file_in_dir(filename.as_str()).is_ok() {
+                            info!(
+                                "Removed leftover file: {}/{}/{}/{}",
+                                ROOT_DIR, orient, bucket, filename
+                            );
+                            report.leftovers += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Footers of images cached in both orientations
+        let [horiz, vert] = &ids;
+        for &id in horiz.iter().filter(|&id| vert.contains(id)) {
+            for orient in [HORIZ_DIR, VERT_DIR] {
+                if self.stored_footer_crc(orient, id).is_none() && self.delete_image(orient, id) {
+                    info!(
+                        "Removed corrupt cache file: {}/{}/{}/{}",
+                        ROOT_DIR,
+                        orient,
+                        bucket_name(id_bucket(id)),
+                        cache_filename(id, IMAGE_EXTENSION)
+                    );
+                    report.corrupt += 1;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Delete an image and its sidecar, returns whether the image was removed
+    fn delete_image(&mut self, orient: &str, id: u64) -> bool {
+        let Ok(mut volume) = self.volume_mgr.open_volume(VolumeIdx(0)) else {
+            return false;
+        };
+        let Ok(mut root_dir) = volume.open_root_dir() else {
+            return false;
+        };
+        let Ok(mut concerts_dir) = root_dir.open_dir(ROOT_DIR) else {
+            return false;
+        };
+        let Ok(mut orient_dir) = concerts_dir.open_dir(orient) else {
+            return false;
+        };
+        let Ok(mut bucket_dir) = orient_dir.open_dir(bucket_name(id_bucket(id)).as_str()) else {
+            return false;
+        };
+        let _ = bucket_dir.delete_file_in_dir(cache_filename(id, KEY_EXTENSION).as_str());
+        bucket_dir
+            .delete_file_in_dir(cache_filename(id, IMAGE_EXTENSION).as_str())
+            .is_ok()
+    }
+
+    /// Read cached image into buffer, returns the image length
+    ///
+    /// Files that fail footer validation are deleted and reported as `Corrupt`;
+    /// an image whose sidecar holds another cache key is reported as `Collision`
+    /// (and overwritten once the right image is fetched and written).
     pub fn read_image(
         &mut self,
         path: &str,
         orientation: Orientation,
         buf: &mut [u8],
     ) -> Result<usize, CacheError> {
-        let filename = cache_filename(path);
+        let id = cache_id(path);
         let orient = orientation_dir(orientation);
+        let bucket = bucket_name(id_bucket(id));
+        let filename = cache_filename(id, IMAGE_EXTENSION);
+
+        match self.stored_key(orient, id) {
+            Some(key) if key == path => {}
+            Some(key) => {
+                info!(
+                    "Cache collision: {}/{}/{}/{} holds {}, not {}",
+                    ROOT_DIR, orient, bucket, filename, key, path
+                );
+                return Err(CacheError::Collision);
+            }
+            None => return Err(CacheError::NotFound),
+        }
 
         let mut volume = self
             .volume_mgr
@@ -234,35 +770,56 @@ where
             .open_dir(orient)
             .map_err(|_| CacheError::Filesystem)?;
 
-        let mut file = orient_dir
+        let mut bucket_dir = orient_dir
+            .open_dir(bucket.as_str())
+            .map_err(|_| CacheError::NotFound)?;
+
+        let mut file = bucket_dir
             .open_file_in_dir(filename.as_str(), Mode::ReadOnly)
             .map_err(|_| CacheError::NotFound)?;
 
         let mut total_read = 0;
         loop {
+            if total_read == buf.len() {
+                return Err(CacheError::TooLarge);
+            }
             match file.read(&mut buf[total_read..]) {
                 Ok(0) => break,
                 Ok(n) => total_read += n,
                 Err(_) => return Err(CacheError::Read),
             }
         }
+        drop(file);
+
+        let Some(len) = verify_footer(&buf[..total_read]) else {
+            info!(
+                "Discarding corrupt cache file: {}/{}/{}/{}",
+                ROOT_DIR, orient, bucket, filename
+            );
+            let _ = bucket_dir.delete_file_in_dir(filename.as_str());
+            let _ = bucket_dir.delete_file_in_dir(cache_filename(id, KEY_EXTENSION).as_str());
+            return Err(CacheError::Corrupt);
+        };
 
         info!(
-            "Read {} bytes from cache: {}/{}/{}",
-            total_read, ROOT_DIR, orient, filename
+            "Read {} bytes from cache: {}/{}/{}/{}",
+            len, ROOT_DIR, orient, bucket, filename
         );
-        Ok(total_read)
+        self.lru.touch(orientation as u8, id);
+        Ok(len)
     }
 
-    /// Write image to cache
+    /// Write image to cache, with a sidecar holding its cache key
     pub fn write_image(
         &mut self,
         path: &str,
         orientation: Orientation,
         data: &[u8],
     ) -> Result<(), CacheError> {
-        let filename = cache_filename(path);
+        let id = cache_id(path);
         let orient = orientation_dir(orientation);
+        let bucket = bucket_name(id_bucket(id));
+        let filename = cache_filename(id, IMAGE_EXTENSION);
 
         let mut volume = self
             .volume_mgr
@@ -279,26 +836,54 @@ where
             .open_dir(orient)
             .map_err(|_| CacheError::Filesystem)?;
 
+        // Create the bucket directory on first use
+        if orient_dir.open_dir(bucket.as_str()).is_err() {
+            orient_dir
+                .make_dir_in_dir(bucket.as_str())
+                .map_err(|_| CacheError::Filesystem)?;
+        }
+        let mut bucket_dir = orient_dir
+            .open_dir(bucket.as_str())
+            .map_err(|_| CacheError::Filesystem)?;
+
+        // Remove any colliding image before its sidecar is replaced, so a write
+        // cut short never leaves a sidecar vouching for another key's image
+        let _ = bucket_dir.delete_file_in_dir(filename.as_str());
+        let mut key_file = bucket_dir
+            .open_file_in_dir(
+                cache_filename(id, KEY_EXTENSION).as_str(),
+                Mode::ReadWriteCreateOrTruncate,
+            )
+            .map_err(|_| CacheError::Write)?;
+        key_file
+            .write(path.as_bytes())
+            .map_err(|_| CacheError::Write)?;
+        drop(key_file);
+
         // Create/truncate file
-        let mut file = orient_dir
+        let mut file = bucket_dir
             .open_file_in_dir(filename.as_str(), Mode::ReadWriteCreateOrTruncate)
             .map_err(|_| CacheError::Write)?;
 
-        // Write data
+        // Write data, then the footer that marks the file complete
         file.write(data).map_err(|_| CacheError::Write)?;
+        file.write(&image_footer(data))
+            .map_err(|_| CacheError::Write)?;
 
         info!(
-            "Wrote {} bytes to cache: {}/{}/{}",
+            "Wrote {} bytes to cache: {}/{}/{}/{}",
             data.len(),
             ROOT_DIR,
             orient,
+            bucket,
             filename
         );
+        self.lru.touch(orientation as u8, id);
         Ok(())
     }
 
-    /// Load widget data from cache (JSON array of item paths)
-    pub fn load_widget_data(&mut self) -> Option<WidgetData> {
+    /// Load widget data from cache (JSON array of items, as served)
+    pub fn load_widget_data(&mut self) -> Option<Box<WidgetData>> {
         let mut volume = self.volume_mgr.open_volume(VolumeIdx(0)).ok()?;
         let mut root_dir = volume.open_root_dir().ok()?;
         let mut concerts_dir = root_dir.open_dir(ROOT_DIR).ok()?;
@@ -307,8 +892,8 @@ where
             .open_file_in_dir(WIDGET_FILE, Mode::ReadOnly)
             .ok()?;
 
-        // Read file into buffer (max ~6KB for 128 items)
-        let mut buf = [0u8; 6144];
+        // Read file into buffer (up to ~24KB for 128 items, heap allocated)
+        let mut buf: Box<[u8; WIDGET_JSON_SIZE]> = Box::new([0u8; WIDGET_JSON_SIZE]);
         let mut total_read = 0;
         loop {
             match file.read(&mut buf[total_read..]) {
@@ -320,7 +905,7 @@ where
 
         // Parse JSON
         let json_str = core::str::from_utf8(&buf[..total_read]).ok()?;
-        let data: WidgetData = serde_json_core::from_str(json_str).ok()?.0;
+        let data = parse_widget_data(json_str).ok()?;
 
         if data.is_empty() {
             None
@@ -330,7 +915,7 @@ where
         }
     }
 
-    /// Store widget data to cache (JSON array of item paths)
+    /// Store widget data to cache (JSON array of items, as served)
     pub fn store_widget_data(&mut self, items: &WidgetData) -> Result<(), CacheError> {
         let mut volume = self
             .volume_mgr
@@ -349,13 +934,14 @@ where
 
         // Write JSON array manually (simple format)
         file.write(b"[").map_err(|_| CacheError::Write)?;
+        let mut item_json: String<ITEM_JSON_LEN> = String::new();
         for (i, item) in items.iter().enumerate() {
             if i > 0 {
                 file.write(b",").map_err(|_| CacheError::Write)?;
             }
-            file.write(b"\"").map_err(|_| CacheError::Write)?;
-            file.write(item.as_bytes()).map_err(|_| CacheError::Write)?;
-            file.write(b"\"").map_err(|_| CacheError::Write)?;
+            write_item_json(item, &mut item_json).map_err(|_| CacheError::Write)?;
+            file.write(item_json.as_bytes())
+                .map_err(|_| CacheError::Write)?;
         }
         file.write(b"]").map_err(|_| CacheError::Write)?;
 
@@ -363,26 +949,64 @@ where
         Ok(())
     }
 
-    /// Load orientation from cache
-    pub fn load_orientation(&mut self) -> Option<Orientation> {
+    /// Load the ETag of the cached widget data
+    pub fn load_widget_etag(&mut self) -> Option<u32> {
         let mut volume = self.volume_mgr.open_volume(VolumeIdx(0)).ok()?;
         let mut root_dir = volume.open_root_dir().ok()?;
         let mut concerts_dir = root_dir.open_dir(ROOT_DIR).ok()?;
 
         let mut file = concerts_dir
-            .open_file_in_dir(ORIENT_FILE, Mode::ReadOnly)
+            .open_file_in_dir(WIDGET_TAG_FILE, Mode::ReadOnly)
             .ok()?;
 
-        let mut buf = [0u8; 1];
-        file.read(&mut buf).ok()?;
+        let mut buf = [0u8; 4];
+        match file.read(&mut buf) {
+            Ok(4) => Some(u32::from_le_bytes(buf)),
+            _ => None,
+        }
+    }
 
-        let orientation = Orientation::from_u8(buf[0]);
-        info!("Loaded orientation from cache: {:?}", orientation);
-        Some(orientation)
+    /// Store the ETag of the cached widget data (`None` clears it)
+    pub fn store_widget_etag(&mut self, etag: Option<u32>) -> Result<(), CacheError> {
+        let mut volume = self
+            .volume_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(|_| CacheError::Filesystem)?;
+
+        let mut root_dir = volume.open_root_dir().map_err(|_| CacheError::Filesystem)?;
+
+        let mut concerts_dir = root_dir
+            .open_dir(ROOT_DIR)
+            .map_err(|_| CacheError::Filesystem)?;
+
+        let mut file = concerts_dir
+            .open_file_in_dir(WIDGET_TAG_FILE, Mode::ReadWriteCreateOrTruncate)
+            .map_err(|_| CacheError::Write)?;
+
+        if let Some(etag) = etag {
+            file.write(&etag.to_le_bytes())
+                .map_err(|_| CacheError::Write)?;
+        }
+        Ok(())
     }
 
-    /// Store orientation to cache
-    pub fn store_orientation(&mut self, orientation: Orientation) -> Result<(), CacheError> {
+    /// Load the address the server's name last resolved to
+    pub fn load_resolved_host(&mut self) -> Option<ResolvedHost> {
+        let mut volume = self.volume_mgr.open_volume(VolumeIdx(0)).ok()?;
+        let mut root_dir = volume.open_root_dir().ok()?;
+        let mut concerts_dir = root_dir.open_dir(ROOT_DIR).ok()?;
+
+        let mut file = concerts_dir
+            .open_file_in_dir(DNS_FILE, Mode::ReadOnly)
+            .ok()?;
+
+        let mut buf = [0u8; RESOLVED_HOST_SIZE];
+        let len = file.read(&mut buf).ok()?;
+        dns_cache::parse_resolved_host(&buf[..len])
+    }
+
+    /// Store the address the server's name resolved to
+    pub fn store_resolved_host(&mut self, resolved: &ResolvedHost) -> Result<(), CacheError> {
         let mut volume = self
             .volume_mgr
             .open_volume(VolumeIdx(0))
@@ -395,23 +1019,146 @@ where
             .map_err(|_| CacheError::Filesystem)?;
 
         let mut file = concerts_dir
-            .open_file_in_dir(ORIENT_FILE, Mode::ReadWriteCreateOrTruncate)
+            .open_file_in_dir(DNS_FILE, Mode::ReadWriteCreateOrTruncate)
             .map_err(|_| CacheError::Write)?;
 
-        file.write(&[orientation as u8])
+        file.write(resolved.to_lines().as_bytes())
             .map_err(|_| CacheError::Write)?;
 
-        info!("Stored orientation to cache: {:?}", orientation);
+        info!("Stored {} address {}", resolved.host, resolved.addr);
         Ok(())
     }
 
+    /// Save the framebuffer as the next numbered PNG in /SCRNSHOT/, returns the filename
+    pub fn write_screenshot(
+        &mut self,
+        framebuffer: &Framebuffer,
+    ) -> Result<String<16>, CacheError> {
+        let mut volume = self
+            .volume_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(|_| CacheError::Filesystem)?;
+
+        let mut root_dir = volume.open_root_dir().map_err(|_| CacheError::Filesystem)?;
+
+        if root_dir.open_dir(SCREENSHOT_DIR).is_err() {
+            root_dir
+                .make_dir_in_dir(SCREENSHOT_DIR)
+                .map_err(|_| CacheError::Filesystem)?;
+            info!("Created {} directory", SCREENSHOT_DIR);
+        }
+
+        let mut shot_dir = root_dir
+            .open_dir(SCREENSHOT_DIR)
+            .map_err(|_| CacheError::Filesystem)?;
+
+        // Next sequence number after the highest existing screenshot
+        let mut last = 0u16;
+        shot_dir
+            .iterate_dir(|entry| {
+                let Ok(base) = core::str::from_utf8(entry.name.base_name()) else {
+                    return;
+                };
+                if let Some(n) = base
+                    .trim()
+                    .strip_prefix(SCREENSHOT_PREFIX)
+                    .and_then(|n| n.parse::<u16>().ok())
+                {
+                    last = last.max(n);
+                }
+            })
+            .map_err(|_| CacheError::Filesystem)?;
+
+        let mut filename: String<16> = String::new();
+        let _ = write!(
+            filename,
+            "{}{:04}.PNG",
+            SCREENSHOT_PREFIX,
+            (last + 1) % 10000
+        );
+
+        let mut file = shot_dir
+            .open_file_in_dir(filename.as_str(), Mode::ReadWriteCreateOrTruncate)
+            .map_err(|_| CacheError::Write)?;
+
+        screenshot::encode_png(framebuffer, |data| {
+            file.write(data).map_err(|_| CacheError::Write)
+        })?;
+
+        info!(
+            "Wrote {} byte screenshot: {}/{}",
+            screenshot::PNG_SIZE,
+            SCREENSHOT_DIR,
+            filename
+        );
+        Ok(filename)
+    }
+
+    /// Append log text to /LOG.TXT, rotating it once it has reached `LOG_MAX_BYTES`
+    ///
+    /// `read` fills a buffer with the next text and returns its length, 0 once
+    /// there's no more. embedded-sdmmc can't rename files, so rotating copies
+    /// the log over /LOG.OLD (replacing the one before) and starts it afresh.
+    /// Returns the number of bytes appended.
+    pub fn append_log(
+        &mut self,
+        mut read: impl FnMut(&mut [u8]) -> usize,
+    ) -> Result<usize, CacheError> {
+        let mut volume = self
+            .volume_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(|_| CacheError::Filesystem)?;
+
+        let mut root_dir = volume.open_root_dir().map_err(|_| CacheError::Filesystem)?;
+
+        let mut chunk = [0u8; 512];
+        let log_len = match root_dir.open_file_in_dir(LOG_FILE, Mode::ReadOnly) {
+            Ok(file) => file.length(),
+            Err(_) => 0,
+        };
+        let mode = if log_len >= LOG_MAX_BYTES {
+            let _ = root_dir.delete_file_in_dir(OLD_LOG_FILE);
+            let mut old = root_dir
+                .open_file_in_dir(OLD_LOG_FILE, Mode::ReadWriteCreateOrTruncate)
+                .map_err(|_| CacheError::Write)?;
+            let mut log = root_dir
+                .open_file_in_dir(LOG_FILE, Mode::ReadOnly)
+                .map_err(|_| CacheError::Read)?;
+            loop {
+                let n = log.read(&mut chunk).map_err(|_| CacheError::Read)?;
+                if n == 0 {
+                    break;
+                }
+                old.write(&chunk[..n]).map_err(|_| CacheError::Write)?;
+            }
+            info!("Rotated {} byte log to {}", log_len, OLD_LOG_FILE);
+            Mode::ReadWriteCreateOrTruncate
+        } else {
+            Mode::ReadWriteCreateOrAppend
+        };
+
+        let mut file = root_dir
+            .open_file_in_dir(LOG_FILE, mode)
+            .map_err(|_| CacheError::Write)?;
+        let mut written = 0;
+        loop {
+            let n = read(&mut chunk);
+            if n == 0 {
+                break;
+            }
+            file.write(&chunk[..n]).map_err(|_| CacheError::Write)?;
+            written += n;
+        }
+        Ok(written)
+    }
+
     /// Remove cache entries not in the valid items list
     pub fn cleanup_stale(&mut self, valid_items: &WidgetData) -> Result<u32, CacheError> {
-        // Pre-compute hashes of valid items
-        let mut valid_hashes: heapless::Vec<u32, 128> = heapless::Vec::new();
-        for item in valid_items.iter() {
-            let _ = valid_hashes.push(path_hash(item.as_str()));
-        }
+        // Pre-compute ids of valid items
+        let valid_ids: Vec<u64> = valid_items
+            .iter()
+            .map(|item| cache_id(item.cache_key()))
+            .collect();
 
         let mut volume = self
             .volume_mgr
@@ -432,41 +1179,50 @@ where
                 continue;
             };
 
-            let mut to_delete: heapless::Vec<heapless::String<16>, 64> = heapless::Vec::new();
-
-            // Find stale files
+            let mut buckets: heapless::Vec<u8, 256> = heapless::Vec::new();
             orient_dir
                 .iterate_dir(|entry| {
-                    if entry.attributes.is_archive() {
-                        let name = entry.name.base_name();
-                        if let Ok(name_str) = core::str::from_utf8(name) {
-                            let ext = entry.name.extension();
-                            let mut full_name: heapless::String<16> = heapless::String::new();
-                            if let Ok(ext_str) = core::str::from_utf8(ext) {
-                                if !ext_str.is_empty() && ext_str.trim() != "" {
-                                    let _ =
-                                        write!(full_name, "{}.{}", name_str.trim(), ext_str.trim());
-                                } else {
-                                    let _ = write!(full_name, "{}", name_str.trim());
-                                }
-                            }
-
-                            // Parse to get hash and check if valid
-                            if let Some(file_hash) = parse_cache_filename(full_name.as_str())
-                                && !valid_hashes.contains(&file_hash)
-                            {
-                                let _ = to_delete.push(full_name);
-                            }
-                        }
+                    if entry.attributes.is_directory()
+                        && let Some(name) = entry_filename(entry)
+                        && let Some(bucket) = parse_bucket_dir(name.as_str())
+                    {
+                        let _ = buckets.push(bucket);
                     }
                 })
                 .ok();
 
-            // Delete stale files from this orientation directory
-            for filename in to_delete.iter() {
-                if orient_dir.delete_file_in_dir(filename.as_str()).is_ok() {
-                    info!("Removed stale cache: {}/{}/{}", ROOT_DIR, orient, filename);
-                    removed += 1;
+            for &bucket_id in buckets.iter() {
+                let bucket = bucket_name(bucket_id);
+                let Ok(mut bucket_dir) = orient_dir.open_dir(bucket.as_str()) else {
+                    continue;
+                };
+
+                let mut to_delete: heapless::Vec<(heapless::String<16>, bool), 64> =
+                    heapless::Vec::new();
+
+                // Find stale images and their sidecars
+                bucket_dir
+                    .iterate_dir(|entry| {
+                        if entry.attributes.is_archive()
+                            && let Some(full_name) = entry_filename(entry)
+                            && let Some((id, ext)) =
+                                parse_cache_filename(bucket_id, full_name.as_str())
+                            && !valid_ids.contains(&id)
+                        {
+                            let _ = to_delete.push((full_name, ext == IMAGE_EXTENSION));
+                        }
+                    })
+                    .ok();
+
+                // Delete stale files from this bucket
+                for (filename, is_image) in to_delete.iter() {
+                    if bucket_dir.delete_file_in_dir(filename.as_str()).is_ok() && *is_image {
+                        info!(
+                            "Removed stale cache: {}/{}/{}/{}",
+                            ROOT_DIR, orient, bucket, filename
+                        );
+                        removed += 1;
+                    }
                 }
             }
         }
@@ -474,3 +1230,213 @@ where
         Ok(removed)
     }
 }
+
+impl<SPI, DELAY> SettingsStore for SdCache<SPI, DELAY>
+where
+    SPI: SpiDevice,
+    DELAY: embedded_hal::delay::DelayNs,
+{
+    /// Load orientation from cache
+    fn load_orientation(&mut self) -> Option<Orientation> {
+        let mut volume = self.volume_mgr.open_volume(VolumeIdx(0)).ok()?;
+        let mut root_dir = volume.open_root_dir().ok()?;
+        let mut concerts_dir = root_dir.open_dir(ROOT_DIR).ok()?;
+
+        let mut file = concerts_dir
+            .open_file_in_dir(ORIENT_FILE, Mode::ReadOnly)
+            .ok()?;
+
+        let mut buf = [0u8; 1];
+        file.read(&mut buf).ok()?;
+
+        let orientation = Orientation::from_u8(buf[0]);
+        info!("Loaded orientation from cache: {:?}", orientation);
+        Some(orientation)
+    }
+
+    /// Store orientation to cache
+    fn store_orientation(&mut self, orientation: Orientation) -> Result<(), CacheError> {
+        let mut volume = self
+            .volume_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(|_| CacheError::Filesystem)?;
+
+        let mut root_dir = volume.open_root_dir().map_err(|_| CacheError::Filesystem)?;
+
+        let mut concerts_dir = root_dir
+            .open_dir(ROOT_DIR)
+            .map_err(|_| CacheError::Filesystem)?;
+
+        let mut file = concerts_dir
+            .open_file_in_dir(ORIENT_FILE, Mode::ReadWriteCreateOrTruncate)
+            .map_err(|_| CacheError::Write)?;
+
+        file.write(&[orientation as u8])
+            .map_err(|_| CacheError::Write)?;
+
+        info!("Stored orientation to cache: {:?}", orientation);
+        Ok(())
+    }
+
+    /// Load device config from cache
+    fn load_device_config(&mut self) -> Option<DeviceConfig> {
+        let mut volume = self.volume_mgr.open_volume(VolumeIdx(0)).ok()?;
+        let mut root_dir = volume.open_root_dir().ok()?;
+        let mut concerts_dir = root_dir.open_dir(ROOT_DIR).ok()?;
+
+        let mut file = concerts_dir
+            .open_file_in_dir(CONFIG_FILE, Mode::ReadOnly)
+            .ok()?;
+
+        let mut buf = [0u8; CONFIG_JSON_SIZE];
+        let len = file.read(&mut buf).ok()?;
+        let json_str = core::str::from_utf8(&buf[..len]).ok()?;
+        let device_config = config::parse_device_config(json_str).ok()?;
+
+        info!(
+            "Loaded device config from cache: refresh={}s",
+            device_config.refresh_interval_secs
+        );
+        Some(device_config)
+    }
+
+    /// Store device config to cache
+    fn store_device_config(&mut self, device_config: &DeviceConfig) -> Result<(), CacheError> {
+        let mut buf = [0u8; CONFIG_JSON_SIZE];
+        let len =
+            config::serialize_device_config(device_config, &mut buf).ok_or(CacheError::Write)?;
+
+        let mut volume = self
+            .volume_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(|_| CacheError::Filesystem)?;
+
+        let mut root_dir = volume.open_root_dir().map_err(|_| CacheError::Filesystem)?;
+
+        let mut concerts_dir = root_dir
+            .open_dir(ROOT_DIR)
+            .map_err(|_| CacheError::Filesystem)?;
+
+        let mut file = concerts_dir
+            .open_file_in_dir(CONFIG_FILE, Mode::ReadWriteCreateOrTruncate)
+            .map_err(|_| CacheError::Write)?;
+
+        file.write(&buf[..len]).map_err(|_| CacheError::Write)?;
+
+        info!("Stored device config to cache");
+        Ok(())
+    }
+
+    /// Load WiFi credentials stored by the provisioning portal
+    fn load_wifi_credentials(&mut self) -> Option<WifiCredentials> {
+        let mut volume = self.volume_mgr.open_volume(VolumeIdx(0)).ok()?;
+        let mut root_dir = volume.open_root_dir().ok()?;
+        let mut concerts_dir = root_dir.open_dir(ROOT_DIR).ok()?;
+
+        let mut file = concerts_dir
+            .open_file_in_dir(WIFI_FILE, Mode::ReadOnly)
+            .ok()?;
+
+        let mut buf = [0u8; 128];
+        let len = file.read(&mut buf).ok()?;
+        let credentials = parse_wifi_credentials(&buf[..len])?;
+
+        info!(
+            "Loaded WiFi credentials for '{}'",
+            credentials.ssid.as_str()
+        );
+        Some(credentials)
+    }
+
+    /// Store WiFi credentials from the provisioning portal
+    fn store_wifi_credentials(&mut self, credentials: &WifiCredentials) -> Result<(), CacheError> {
+        let mut volume = self
+            .volume_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(|_| CacheError::Filesystem)?;
+
+        let mut root_dir = volume.open_root_dir().map_err(|_| CacheError::Filesystem)?;
+
+        let mut concerts_dir = root_dir
+            .open_dir(ROOT_DIR)
+            .map_err(|_| CacheError::Filesystem)?;
+
+        let mut file = concerts_dir
+            .open_file_in_dir(WIFI_FILE, Mode::ReadWriteCreateOrTruncate)
+            .map_err(|_| CacheError::Write)?;
+
+        file.write(credentials.ssid.as_bytes())
+            .map_err(|_| CacheError::Write)?;
+        file.write(b"\n").map_err(|_| CacheError::Write)?;
+        file.write(credentials.password.as_bytes())
+            .map_err(|_| CacheError::Write)?;
+
+        info!(
+            "Stored WiFi credentials for '{}'",
+            credentials.ssid.as_str()
+        );
+        Ok(())
+    }
+
+    /// Load the frozen item
+    fn load_freeze(&mut self) -> Option<Freeze> {
+        let mut volume = self.volume_mgr.open_volume(VolumeIdx(0)).ok()?;
+        let mut root_dir = volume.open_root_dir().ok()?;
+        let mut concerts_dir = root_dir.open_dir(ROOT_DIR).ok()?;
+
+        let mut file = concerts_dir
+            .open_file_in_dir(FREEZE_FILE, Mode::ReadOnly)
+            .ok()?;
+
+        let mut buf = [0u8; MAX_PATH_LEN];
+        let len = file.read(&mut buf).ok()?;
+        let freeze = freeze::parse_frozen_item(&buf[..len])?;
+
+        info!("Loaded frozen item: {}", freeze.item);
+        Some(freeze)
+    }
+
+    /// Store the frozen item, or clear it
+    fn store_freeze(&mut self, freeze: Option<&Freeze>) -> Result<(), CacheError> {
+        let mut volume = self
+            .volume_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(|_| CacheError::Filesystem)?;
+
+        let mut root_dir = volume.open_root_dir().map_err(|_| CacheError::Filesystem)?;
+
+        let mut concerts_dir = root_dir
+            .open_dir(ROOT_DIR)
+            .map_err(|_| CacheError::Filesystem)?;
+
+        let mut file = concerts_dir
+            .open_file_in_dir(FREEZE_FILE, Mode::ReadWriteCreateOrTruncate)
+            .map_err(|_| CacheError::Write)?;
+
+        if let Some(freeze) = freeze {
+            file.write(freeze.item.as_bytes())
+                .map_err(|_| CacheError::Write)?;
+        }
+
+        info!("Stored frozen item: {:?}", freeze.map(|f| f.item.as_str()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fat_timestamp() {
+        // 2024-02-29 12:34:56 UTC
+        let stamp = fat_timestamp(1_709_210_096);
+        assert_eq!(stamp.year_since_1970, 54);
+        assert_eq!((stamp.zero_indexed_month, stamp.zero_indexed_day), (1, 28));
+        assert_eq!((stamp.hours, stamp.minutes, stamp.seconds), (12, 34, 56));
+
+        assert_eq!(fat_timestamp(FALLBACK_UNIX_SECS).year_since_1970, 55);
+        // Before FAT's 1980 epoch
+        assert_eq!(fat_timestamp(0).year_since_1970, 10);
+    }
+}
@@ -0,0 +1,329 @@
+//! On-card format of the SD image cache, independent of the card itself
+//!
+//! File naming (a hash of the cache key split into a bucket directory and an
+//! 8.3 filename), the footer validating each cached image and the LRU access
+//! index. [`SdCache`](crate::cache::SdCache) does the I/O; everything here is
+//! plain data, so it is also tested on the host (see `host-tests`).
+
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::screenshot::Crc32;
+
+/// Magic at the start of the access index
+pub(crate) const LRU_MAGIC: [u8; 4] = *b"LRU2";
+
+/// Access index header: magic + clock (u32 LE)
+pub(crate) const LRU_HEADER_SIZE: usize = 8;
+
+/// Access index record: orientation (u8) + cache id (u64 LE) + tick (u32 LE)
+pub(crate) const LRU_RECORD_SIZE: usize = 13;
+
+/// Most images tracked in the access index (untracked images count as oldest)
+pub(crate) const MAX_LRU_ENTRIES: usize = 4096;
+
+/// Extension of cached images
+pub(crate) const IMAGE_EXTENSION: &str = "PNG";
+
+/// Extension of the sidecar holding a cached image's cache key
+pub(crate) const KEY_EXTENSION: &str = "KEY";
+
+/// Extension of temporary files (never written by the cache itself)
+pub(crate) const TEMP_EXTENSION: &str = "TMP";
+
+/// Magic marking the start of a cached image footer
+pub(crate) const FOOTER_MAGIC: [u8; 4] = *b"STF1";
+
+/// Cached image footer size: magic + length (u32 LE) + CRC-32 (u32 LE)
+pub(crate) const FOOTER_SIZE: usize = 12;
+
+/// Build the footer written after an image's data
+pub(crate) fn image_footer(data: &[u8]) -> [u8; FOOTER_SIZE] {
+    let mut crc = Crc32::new();
+    crc.update(data);
+
+    let mut footer = [0u8; FOOTER_SIZE];
+    footer[0..4].copy_from_slice(&FOOTER_MAGIC);
+    footer[4..8].copy_from_slice(&(data.len() as u32).to_le_bytes());
+    footer[8..12].copy_from_slice(&crc.finish().to_le_bytes());
+    footer
+}
+
+/// Validate a cached file (data followed by footer), returning the data length
+pub(crate) fn verify_footer(file: &[u8]) -> Option<usize> {
+    let len = file.len().checked_sub(FOOTER_SIZE)?;
+    let (data, footer) = file.split_at(len);
+    (footer == image_footer(data)).then_some(len)
+}
+
+/// CRC-32 recorded in a footer, if it is well-formed for a file of `file_len` bytes
+///
+/// Only the footer is checked (not the data), which is enough to revalidate with
+/// the server: a damaged file is replaced by the 200 response anyway.
+pub(crate) fn footer_crc(footer: &[u8; FOOTER_SIZE], file_len: u32) -> Option<u32> {
+    let len = u32::from_le_bytes([footer[4], footer[5], footer[6], footer[7]]);
+    let well_formed =
+        footer[0..4] == FOOTER_MAGIC && len as usize + FOOTER_SIZE == file_len as usize;
+    well_formed.then(|| u32::from_le_bytes([footer[8], footer[9], footer[10], footer[11]]))
+}
+
+/// Order in which cached images were last read or written
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct LruIndex {
+    /// Advances on every access
+    clock: u32,
+    /// Orientation (0 horizontal, 1 vertical), cache id and tick of the last access
+    entries: Vec<(u8, u64, u32)>,
+}
+
+impl LruIndex {
+    /// Parse an index file, starting over if it is malformed
+    pub(crate) fn parse(bytes: &[u8]) -> Self {
+        let Some(records) = bytes
+            .strip_prefix(&LRU_MAGIC)
+            .filter(|rest| rest.len() >= LRU_HEADER_SIZE - LRU_MAGIC.len())
+        else {
+            return Self::default();
+        };
+        let (clock, records) = records.split_at(LRU_HEADER_SIZE - LRU_MAGIC.len());
+        let u32_at = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        let u64_at = |b: &[u8]| u64::from_le_bytes(b[..8].try_into().unwrap_or_default());
+        Self {
+            clock: u32_at(clock),
+            entries: records
+                .chunks_exact(LRU_RECORD_SIZE)
+                .take(MAX_LRU_ENTRIES)
+                .map(|record| (record[0], u64_at(&record[1..9]), u32_at(&record[9..13])))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(LRU_HEADER_SIZE + self.entries.len() * LRU_RECORD_SIZE);
+        bytes.extend_from_slice(&LRU_MAGIC);
+        bytes.extend_from_slice(&self.clock.to_le_bytes());
+        for &(orient, id, tick) in &self.entries {
+            bytes.push(orient);
+            bytes.extend_from_slice(&id.to_le_bytes());
+            bytes.extend_from_slice(&tick.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Record an access, forgetting the oldest entry if the index is full
+    pub(crate) fn touch(&mut self, orient: u8, id: u64) {
+        self.clock = self.clock.wrapping_add(1);
+        let clock = self.clock;
+        match self
+            .entries
+            .iter_mut()
+            .find(|(o, i, _)| *o == orient && *i == id)
+        {
+            Some(entry) => entry.2 = clock,
+            None => {
+                if self.entries.len() >= MAX_LRU_ENTRIES
+                    && let Some(oldest) = (0..self.entries.len()).min_by_key(|&i| self.entries[i].2)
+                {
+                    self.entries.swap_remove(oldest);
+                }
+                self.entries.push((orient, id, clock));
+            }
+        }
+    }
+
+    /// Tick of the last access, 0 for images never accessed since the index existed
+    pub(crate) fn tick(&self, orient: u8, id: u64) -> u32 {
+        self.entries
+            .iter()
+            .find(|(o, i, _)| *o == orient && *i == id)
+            .map_or(0, |entry| entry.2)
+    }
+
+    /// Forget images not in `kept` (evicted, scrubbed or cleaned up)
+    pub(crate) fn retain(&mut self, kept: &[(u8, u64, u32)]) {
+        self.entries
+            .retain(|&(orient, id, _)| kept.iter().any(|&(o, i, _)| o == orient && i == id));
+    }
+}
+
+/// Images to evict, least recently used first, so the rest fit in `max_bytes`
+///
+/// `files` holds each cached image's orientation, cache id and size.
+pub(crate) fn eviction_order(
+    files: &mut [(u8, u64, u32)],
+    lru: &LruIndex,
+    max_bytes: u64,
+) -> usize {
+    let mut total: u64 = files.iter().map(|&(_, _, size)| size as u64).sum();
+    files.sort_unstable_by_key(|&(orient, id, _)| lru.tick(orient, id));
+    let mut count = 0;
+    for &(_, _, size) in files.iter() {
+        if total <= max_bytes {
+            break;
+        }
+        total -= size as u64;
+        count += 1;
+    }
+    count
+}
+
+/// Whether a filename looks like a temporary file (`*.TMP`, `~*`)
+pub(crate) fn is_temp_filename(filename: &str) -> bool {
+    filename.starts_with('~')
+        || filename
+            .rsplit_once('.')
+            .is_some_and(|(_, ext)| ext.eq_ignore_ascii_case(TEMP_EXTENSION))
+}
+
+/// Cache id of an image: the top 40 bits of a 64-bit FNV-1a hash of its cache key
+///
+/// The top 8 bits name a bucket directory and the next 32 the filename
+/// (`horiz/AB/CDEF0123.PNG`), so no directory grows past a few entries per
+/// 256 images. The image's sidecar (`CDEF0123.KEY`) holds the full cache key,
+/// so a collision is detected instead of showing another item's image.
+pub(crate) fn cache_id(key: &str) -> u64 {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    for byte in key.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+    hash >> 24
+}
+
+/// Bucket of a cache id (its top 8 bits)
+pub(crate) fn id_bucket(id: u64) -> u8 {
+    (id >> 32) as u8
+}
+
+/// Bucket directory name (2 hex chars)
+pub(crate) fn bucket_name(bucket: u8) -> String<4> {
+    let mut name: String<4> = String::new();
+    let _ = write!(name, "{:02X}", bucket);
+    name
+}
+
+/// Filename of a cache id within its bucket
+/// Format: 8-char hash + extension (FAT 8.3 compatible)
+pub(crate) fn cache_filename(id: u64, extension: &str) -> String<16> {
+    let mut name: String<16> = String::new();
+    let _ = write!(name, "{:08X}.{}", id as u32, extension);
+    name
+}
+
+/// Parse a bucket directory name
+/// Input: AB
+/// Output: 0xAB
+pub(crate) fn parse_bucket_dir(name: &str) -> Option<u8> {
+    if name.len() != 2 {
+        return None;
+    }
+    u8::from_str_radix(name, 16).ok()
+}
+
+/// Parse a filename within a bucket to extract the cache id and extension
+/// Input: bucket 0xAB, CDEF0123.PNG
+/// Output: (0xABCDEF0123, "PNG")
+pub(crate) fn parse_cache_filename(bucket: u8, filename: &str) -> Option<(u64, &'static str)> {
+    let (name, ext) = filename.split_once('.')?;
+    if name.len() != 8 {
+        return None;
+    }
+    let hash = u32::from_str_radix(name, 16).ok()?;
+    // FAT filesystems uppercase extensions, but other tools may not
+    let ext = [IMAGE_EXTENSION, KEY_EXTENSION]
+        .into_iter()
+        .find(|known| known.eq_ignore_ascii_case(ext))?;
+    Some(((bucket as u64) << 32 | hash as u64, ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_footer() {
+        let data = b"\x89PNG image data";
+        let mut file = alloc::vec::Vec::from(&data[..]);
+        file.extend_from_slice(&image_footer(data));
+        assert_eq!(verify_footer(&file), Some(data.len()));
+
+        // Truncated write (missing or partial footer)
+        assert_eq!(verify_footer(&file[..data.len()]), None);
+        assert_eq!(verify_footer(&file[..file.len() - 1]), None);
+        assert_eq!(verify_footer(&file[..4]), None);
+
+        // The footer CRC is the image's ETag
+        let footer: [u8; FOOTER_SIZE] = file[data.len()..].try_into().unwrap();
+        let mut crc = Crc32::new();
+        crc.update(data);
+        assert_eq!(footer_crc(&footer, file.len() as u32), Some(crc.finish()));
+        assert_eq!(footer_crc(&footer, file.len() as u32 + 1), None);
+
+        // Corrupted data
+        file[3] ^= 0xFF;
+        assert_eq!(verify_footer(&file), None);
+    }
+
+    #[test]
+    fn test_temp_filenames() {
+        assert!(is_temp_filename("ABCD1234.TMP"));
+        assert!(is_temp_filename("~WIDGET.JSN"));
+        assert!(!is_temp_filename("WIDGET.JSN"));
+        assert!(!is_temp_filename("ABCD1234.PNG"));
+    }
+
+    #[test]
+    fn test_cache_filenames() {
+        // 64-bit FNV-1a, top 40 bits
+        let id = cache_id("a");
+        assert_eq!(id, 0xAF_63DC_4C86);
+        assert_eq!(bucket_name(id_bucket(id)).as_str(), "AF");
+        assert_eq!(cache_filename(id, IMAGE_EXTENSION).as_str(), "63DC4C86.PNG");
+        assert_ne!(
+            cache_id("2024-06-15-band-id"),
+            cache_id("2024-06-15-band-if")
+        );
+
+        // Round-trips through the bucket and filename
+        assert_eq!(parse_bucket_dir("AF"), Some(0xAF));
+        assert_eq!(
+            parse_cache_filename(0xAF, "63DC4C86.PNG"),
+            Some((id, IMAGE_EXTENSION))
+        );
+        assert_eq!(
+            parse_cache_filename(0xAF, "63dc4c86.key"),
+            Some((id, KEY_EXTENSION))
+        );
+
+        // Anything else is a leftover
+        assert_eq!(parse_bucket_dir("."), None);
+        assert_eq!(parse_bucket_dir("ABC"), None);
+        assert_eq!(parse_cache_filename(0xAF, "63DC4C86.TMP"), None);
+        assert_eq!(parse_cache_filename(0xAF, "3DC4C86.PNG"), None);
+        assert_eq!(parse_cache_filename(0xAF, "WIDGET.JSN"), None);
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut lru = LruIndex::default();
+        lru.touch(0, 0xA);
+        lru.touch(1, 0xB);
+        lru.touch(0, 0xC);
+        lru.touch(0, 0xA);
+
+        // Round-trips through the index file, malformed files start over
+        let bytes = lru.to_bytes();
+        assert_eq!(LruIndex::parse(&bytes), lru);
+        assert_eq!(LruIndex::parse(b"LRU0\0\0\0\0"), LruIndex::default());
+        assert_eq!(LruIndex::parse(&bytes[..6]), LruIndex::default());
+
+        // Untracked images go first, then least recently used
+        let mut files = [(0, 0xA, 100), (0, 0xC, 100), (1, 0xB, 100), (0, 0xD, 100)];
+        assert_eq!(eviction_order(&mut files, &lru, 400), 0);
+        assert_eq!(eviction_order(&mut files, &lru, 250), 2);
+        assert_eq!(&files[..2], &[(0, 0xD, 100), (1, 0xB, 100)]);
+        assert_eq!(eviction_order(&mut files, &lru, 0), 4);
+    }
+}
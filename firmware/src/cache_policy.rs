@@ -0,0 +1,285 @@
+//! Widget data cache policy
+//!
+//! The server sends an `X-Cache-Policy` header with widget data, e.g.
+//! `max-age=86400, stale-while-revalidate=86400, suggested-sleep=900`. It
+//! decides how long the list cached on the SD card is trusted without asking the
+//! server again, and how soon the frame wakes after fetching it.
+//!
+//! A missing or malformed header falls back to [`CachePolicy::DEFAULT`]: the
+//! list is revalidated every wake and the refresh interval is kept, as before
+//! the header was parsed. When the list was fetched is kept in RTC memory, so a
+//! power cycle also means revalidating.
+
+use log::warn;
+
+/// Shortest sleep `suggested-sleep` can ask for, so a misconfigured server
+/// can't keep the frame waking every few seconds
+pub const MIN_SUGGESTED_SLEEP_SECS: u32 = 60;
+
+/// Magic number marking initialized freshness
+const FRESHNESS_MAGIC: u32 = 0x4643_5348;
+
+/// Typed `X-Cache-Policy` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachePolicy {
+    /// Seconds the list stays fresh, None if it never expires (`immutable`)
+    pub max_age_secs: Option<u32>,
+    /// Seconds past `max_age_secs` the cached list may still be shown while a
+    /// new one is fetched
+    pub stale_while_revalidate_secs: u32,
+    /// Seconds the server asked the frame to check back within
+    pub suggested_sleep_secs: Option<u32>,
+}
+
+/// How far a cached list is into its lifetime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness {
+    /// Within max-age: no need to ask the server
+    Fresh,
+    /// Past max-age but within stale-while-revalidate: show it, and revalidate
+    Stale,
+    /// Past both, or of unknown age: revalidate, and retry soon if that fails
+    Expired,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl CachePolicy {
+    /// Policy assumed when the header is missing or malformed
+    pub const DEFAULT: Self = Self {
+        max_age_secs: Some(0),
+        stale_while_revalidate_secs: 0,
+        suggested_sleep_secs: None,
+    };
+
+    /// Parse a header value, None if it's malformed
+    ///
+    /// Directives are comma-separated; unknown ones are skipped so newer servers
+    /// can add more. Either `max-age` or `immutable` is required.
+    pub fn parse(value: &[u8]) -> Option<Self> {
+        let mut max_age_secs = None;
+        let mut immutable = false;
+        let mut stale_while_revalidate_secs = 0;
+        let mut suggested_sleep_secs = None;
+        for directive in core::str::from_utf8(value).ok()?.split(',') {
+            let directive = directive.trim();
+            if directive == "immutable" {
+                immutable = true;
+                continue;
+            }
+            let Some((name, secs)) = directive.split_once('=') else {
+                continue;
+            };
+            match name {
+                "max-age" => max_age_secs = Some(secs.parse().ok()?),
+                "stale-while-revalidate" => stale_while_revalidate_secs = secs.parse().ok()?,
+                "suggested-sleep" => suggested_sleep_secs = Some(secs.parse().ok()?),
+                _ => {}
+            }
+        }
+        if max_age_secs.is_none() && !immutable {
+            return None;
+        }
+        Some(Self {
+            max_age_secs: max_age_secs.filter(|_| !immutable),
+            stale_while_revalidate_secs,
+            suggested_sleep_secs,
+        })
+    }
+
+    /// Policy from a response's header value, if it had one
+    pub fn from_header(value: Option<&[u8]>) -> Self {
+        let Some(value) = value else {
+            return Self::DEFAULT;
+        };
+        Self::parse(value).unwrap_or_else(|| {
+            warn!(
+                "Ignoring malformed X-Cache-Policy: {:?}",
+                core::str::from_utf8(value)
+            );
+            Self::DEFAULT
+        })
+    }
+
+    /// Freshness of a list fetched `age_secs` ago
+    pub fn freshness(&self, age_secs: u64) -> Freshness {
+        let Some(max_age) = self.max_age_secs else {
+            return Freshness::Fresh;
+        };
+        if age_secs < max_age as u64 {
+            Freshness::Fresh
+        } else if age_secs < max_age as u64 + self.stale_while_revalidate_secs as u64 {
+            Freshness::Stale
+        } else {
+            Freshness::Expired
+        }
+    }
+
+    /// Sleep before the next wake, given the configured `interval_secs`
+    ///
+    /// A suggested sleep can only shorten the interval, and no further than
+    /// [`MIN_SUGGESTED_SLEEP_SECS`].
+    pub fn sleep_secs(&self, interval_secs: u64) -> u64 {
+        match self.suggested_sleep_secs {
+            Some(secs) => interval_secs.min(secs.max(MIN_SUGGESTED_SLEEP_SECS) as u64),
+            None => interval_secs,
+        }
+    }
+}
+
+/// When the cached list was fetched and under which policy, kept in RTC memory
+#[repr(C)]
+pub struct WidgetFreshness {
+    magic: u32,
+    /// RTC time (us) of the last successful fetch or revalidation
+    fetched_at_us: u64,
+    /// `max-age`, or `u32::MAX` for `immutable`
+    max_age_secs: u32,
+    stale_while_revalidate_secs: u32,
+}
+
+impl Default for WidgetFreshness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WidgetFreshness {
+    pub const fn new() -> Self {
+        Self {
+            magic: 0,
+            fetched_at_us: 0,
+            max_age_secs: 0,
+            stale_while_revalidate_secs: 0,
+        }
+    }
+
+    /// Forget freshness that wasn't recorded (e.g. garbage after power loss)
+    pub fn validate(&mut self) {
+        if self.magic != FRESHNESS_MAGIC {
+            *self = Self::new();
+        }
+    }
+
+    /// Record a fetch (or 304 revalidation) at RTC time `now_us`
+    pub fn record(&mut self, now_us: u64, policy: &CachePolicy) {
+        *self = Self {
+            magic: FRESHNESS_MAGIC,
+            fetched_at_us: now_us,
+            max_age_secs: policy.max_age_secs.unwrap_or(u32::MAX),
+            stale_while_revalidate_secs: policy.stale_while_revalidate_secs,
+        };
+    }
+
+    /// Forget the last fetch, e.g. when the cached list is replaced without one
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Freshness of the cached list at RTC time `now_us`
+    pub fn freshness(&self, now_us: u64) -> Freshness {
+        // Unrecorded, or the RTC was reset since
+        if self.magic != FRESHNESS_MAGIC || now_us < self.fetched_at_us {
+            return Freshness::Expired;
+        }
+        let policy = CachePolicy {
+            max_age_secs: (self.max_age_secs != u32::MAX).then_some(self.max_age_secs),
+            stale_while_revalidate_secs: self.stale_while_revalidate_secs,
+            suggested_sleep_secs: None,
+        };
+        policy.freshness((now_us - self.fetched_at_us) / 1_000_000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        // As formatted by the server
+        assert_eq!(
+            CachePolicy::parse(b"max-age=900, stale-while-revalidate=900, suggested-sleep=600"),
+            Some(CachePolicy {
+                max_age_secs: Some(900),
+                stale_while_revalidate_secs: 900,
+                suggested_sleep_secs: Some(600),
+            })
+        );
+        assert_eq!(
+            CachePolicy::parse(b"immutable, suggested-sleep=60"),
+            Some(CachePolicy {
+                max_age_secs: None,
+                stale_while_revalidate_secs: 0,
+                suggested_sleep_secs: Some(60),
+            })
+        );
+        // Unknown directives and spacing are tolerated
+        assert_eq!(
+            CachePolicy::parse(b"max-age=86400,no-transform, future=1"),
+            Some(CachePolicy {
+                max_age_secs: Some(86400),
+                ..CachePolicy::DEFAULT
+            })
+        );
+
+        // Malformed headers fall back to revalidating every wake
+        assert_eq!(CachePolicy::parse(b"suggested-sleep=600"), None);
+        assert_eq!(CachePolicy::parse(b"max-age=soon"), None);
+        assert_eq!(CachePolicy::parse(b"max-age=-1"), None);
+        assert_eq!(CachePolicy::parse(b"max-age=900, suggested-sleep="), None);
+        assert_eq!(CachePolicy::parse(b"\xff"), None);
+        assert_eq!(CachePolicy::from_header(None), CachePolicy::DEFAULT);
+        assert_eq!(
+            CachePolicy::from_header(Some(b"max-age=9999999999")),
+            CachePolicy::DEFAULT
+        );
+    }
+
+    #[test]
+    fn test_scheduling() {
+        let policy = CachePolicy::parse(b"max-age=900, stale-while-revalidate=600").unwrap();
+        assert_eq!(policy.freshness(0), Freshness::Fresh);
+        assert_eq!(policy.freshness(900), Freshness::Stale);
+        assert_eq!(policy.freshness(1500), Freshness::Expired);
+        assert_eq!(CachePolicy::DEFAULT.freshness(0), Freshness::Expired);
+        assert_eq!(policy.sleep_secs(3600), 3600);
+
+        let stale = CachePolicy {
+            suggested_sleep_secs: Some(900),
+            ..policy
+        };
+        assert_eq!(stale.sleep_secs(3600), 900);
+        assert_eq!(stale.sleep_secs(300), 300);
+        let eager = CachePolicy {
+            suggested_sleep_secs: Some(0),
+            ..policy
+        };
+        assert_eq!(eager.sleep_secs(3600), MIN_SUGGESTED_SLEEP_SECS as u64);
+    }
+
+    #[test]
+    fn test_widget_freshness() {
+        let mut freshness = WidgetFreshness::new();
+        assert_eq!(freshness.freshness(0), Freshness::Expired);
+
+        let policy = CachePolicy::parse(b"max-age=900, stale-while-revalidate=600").unwrap();
+        freshness.record(5_000_000, &policy);
+        assert_eq!(freshness.freshness(904_999_999), Freshness::Fresh);
+        assert_eq!(freshness.freshness(905_000_000), Freshness::Stale);
+        assert_eq!(freshness.freshness(1_505_000_000), Freshness::Expired);
+        // RTC reset since the fetch
+        assert_eq!(freshness.freshness(1_000_000), Freshness::Expired);
+
+        freshness.record(0, &CachePolicy::parse(b"immutable").unwrap());
+        assert_eq!(freshness.freshness(u64::MAX), Freshness::Fresh);
+
+        freshness.clear();
+        freshness.validate();
+        assert_eq!(freshness.freshness(0), Freshness::Expired);
+    }
+}
@@ -0,0 +1,335 @@
+//! Wall-clock time from SNTP
+//!
+//! The frame has no battery-backed calendar, so the time of day comes from an
+//! SNTP server once WiFi is up. The result is kept as an offset from boot for
+//! the rest of the wake, and as a (Unix time, RTC time) pair in RTC memory so
+//! later wakes can carry it forward without asking again; the RTC keeps
+//! counting through deep sleep. It's resynced once a day to bound RTC drift,
+//! and relearned after a power cycle.
+//!
+//! The time is only used to stamp files on the SD card, so a failed sync just
+//! leaves them with the fallback date.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use embassy_net::Stack;
+use embassy_net::dns::DnsQueryType;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_time::{Duration, Instant, with_timeout};
+use log::{info, warn};
+
+/// Servers tried in order until one answers
+const NTP_SERVERS: [&str; 2] = ["pool.ntp.org", "time.google.com"];
+
+/// SNTP server port
+const NTP_PORT: u16 = 123;
+
+/// How long to wait for each server's reply
+const REPLY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Seconds between 1900-01-01 (NTP epoch) and 1970-01-01 (Unix epoch)
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Earliest plausible time (2025-01-01), anything before is a bogus reply
+const MIN_UNIX_SECS: u64 = 1_735_689_600;
+
+/// Resync after this long, so RTC drift across deep sleeps stays small
+const RESYNC_SECS: u64 = 24 * 60 * 60;
+
+/// SNTP packet length (no extension fields or authenticator)
+const PACKET_LEN: usize = 48;
+
+/// Magic number marking an initialized clock
+const CLOCK_MAGIC: u32 = 0x434c_4b53;
+
+/// Unix time at boot (`Instant` zero), once known
+static BOOT_UNIX_SECS: Mutex<Cell<Option<u64>>> = Mutex::new(Cell::new(None));
+
+/// Why a sync failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SntpError {
+    /// No server name resolved
+    Dns,
+    /// The UDP socket couldn't be used
+    Socket,
+    /// No reply in time
+    Timeout,
+    /// The reply wasn't a valid answer to our request
+    InvalidReply,
+}
+
+/// Set the current Unix time
+pub fn set_unix_time(unix_secs: u64) {
+    let boot = unix_secs.saturating_sub(Instant::now().as_secs());
+    critical_section::with(|cs| BOOT_UNIX_SECS.borrow(cs).set(Some(boot)));
+}
+
+/// Current Unix time, if synced this wake or carried over from the last one
+pub fn unix_time() -> Option<u64> {
+    let boot = critical_section::with(|cs| BOOT_UNIX_SECS.borrow(cs).get())?;
+    Some(boot + Instant::now().as_secs())
+}
+
+/// Wall-clock time as of the last sync, kept in RTC memory across deep sleep
+#[repr(C)]
+pub struct WallClock {
+    magic: u32,
+    /// Unix time at the last sync
+    unix_secs: u64,
+    /// RTC time (us) at the last sync
+    rtc_us: u64,
+}
+
+impl Default for WallClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WallClock {
+    pub const fn new() -> Self {
+        Self {
+            magic: 0,
+            unix_secs: 0,
+            rtc_us: 0,
+        }
+    }
+
+    /// Forget a clock that wasn't recorded (e.g. garbage after power loss)
+    pub fn validate(&mut self) {
+        if self.magic != CLOCK_MAGIC {
+            *self = Self::new();
+        }
+    }
+
+    /// Record a sync to `unix_secs` at RTC time `rtc_us`
+    pub fn record(&mut self, unix_secs: u64, rtc_us: u64) {
+        *self = Self {
+            magic: CLOCK_MAGIC,
+            unix_secs,
+            rtc_us,
+        };
+    }
+
+    /// Unix time at RTC time `rtc_us`, None if never synced or the RTC was reset
+    pub fn now(&self, rtc_us: u64) -> Option<u64> {
+        if self.magic != CLOCK_MAGIC || rtc_us < self.rtc_us {
+            return None;
+        }
+        Some(self.unix_secs + (rtc_us - self.rtc_us) / 1_000_000)
+    }
+
+    /// Whether to sync at RTC time `rtc_us`
+    pub fn needs_sync(&self, rtc_us: u64) -> bool {
+        self.now(rtc_us)
+            .is_none_or(|unix_secs| unix_secs - self.unix_secs >= RESYNC_SECS)
+    }
+}
+
+/// Query the SNTP servers in turn, setting the time from the first answer
+///
+/// Returns the Unix time received.
+pub async fn sync(stack: Stack<'_>) -> Result<u64, SntpError> {
+    let mut error = SntpError::Dns;
+    for server in NTP_SERVERS {
+        match query(stack, server).await {
+            Ok(unix_secs) => {
+                set_unix_time(unix_secs);
+                info!("Clock synced from {}: {}", server, unix_secs);
+                return Ok(unix_secs);
+            }
+            Err(e) => {
+                warn!("SNTP query to {} failed: {:?}", server, e);
+                error = e;
+            }
+        }
+    }
+    Err(error)
+}
+
+/// Ask one server for the time
+async fn query(stack: Stack<'_>, server: &str) -> Result<u64, SntpError> {
+    let address = *stack
+        .dns_query(server, DnsQueryType::A)
+        .await
+        .map_err(|_| SntpError::Dns)?
+        .first()
+        .ok_or(SntpError::Dns)?;
+
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buf = [0u8; PACKET_LEN];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buf = [0u8; PACKET_LEN];
+    let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buf, &mut tx_meta, &mut tx_buf);
+    socket.bind(0).map_err(|_| SntpError::Socket)?;
+
+    // The transmit timestamp is echoed back, so it doubles as a nonce
+    let nonce = Instant::now().as_ticks();
+    let request = request_packet(nonce);
+    socket
+        .send_to(&request, (address, NTP_PORT))
+        .await
+        .map_err(|_| SntpError::Socket)?;
+
+    let mut reply = [0u8; PACKET_LEN];
+    let (len, _) = with_timeout(REPLY_TIMEOUT, socket.recv_from(&mut reply))
+        .await
+        .map_err(|_| SntpError::Timeout)?
+        .map_err(|_| SntpError::Socket)?;
+    parse_reply(&reply[..len], nonce).ok_or(SntpError::InvalidReply)
+}
+
+/// SNTPv4 client request carrying `nonce` as its transmit timestamp
+fn request_packet(nonce: u64) -> [u8; PACKET_LEN] {
+    let mut packet = [0u8; PACKET_LEN];
+    // Leap indicator 0, version 4, mode 3 (client)
+    packet[0] = (4 << 3) | 3;
+    packet[40..48].copy_from_slice(&nonce.to_be_bytes());
+    packet
+}
+
+/// Unix time from a server reply to the request carrying `nonce`
+fn parse_reply(reply: &[u8], nonce: u64) -> Option<u64> {
+    if reply.len() < PACKET_LEN {
+        return None;
+    }
+    let leap = reply[0] >> 6;
+    let mode = reply[0] & 0x7;
+    let stratum = reply[1];
+    // Mode 4 (server); leap 3 means unsynchronized, stratum 0 a kiss-o'-death
+    if mode != 4 || leap == 3 || stratum == 0 || stratum > 15 {
+        return None;
+    }
+    let originate = u64::from_be_bytes(reply[24..32].try_into().ok()?);
+    if originate != nonce {
+        return None;
+    }
+    let ntp_secs = u32::from_be_bytes(reply[40..44].try_into().ok()?) as u64;
+    let unix_secs = ntp_secs.checked_sub(NTP_UNIX_OFFSET)?;
+    (unix_secs >= MIN_UNIX_SECS).then_some(unix_secs)
+}
+
+/// Calendar date and time (UTC)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CivilTime {
+    pub year: u16,
+    /// 1-12
+    pub month: u8,
+    /// 1-31
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+impl CivilTime {
+    /// Calendar time of a Unix timestamp
+    pub fn from_unix(unix_secs: u64) -> Self {
+        let days = (unix_secs / 86_400) as i64;
+        let secs = unix_secs % 86_400;
+
+        // Howard Hinnant's days-to-civil, with eras of 400 years from 0000-03-01
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hours: (secs / 3600) as u8,
+            minutes: (secs / 60 % 60) as u8,
+            seconds: (secs % 60) as u8,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reply() {
+        let nonce = 0x0123_4567_89ab_cdef;
+        let request = request_packet(nonce);
+        assert_eq!(request[0], 0x23);
+
+        // 2025-06-15 12:00:00 UTC
+        let unix_secs = 1_749_988_800u64;
+        let mut reply = [0u8; PACKET_LEN];
+        reply[0] = (4 << 3) | 4;
+        reply[1] = 2;
+        reply[24..32].copy_from_slice(&nonce.to_be_bytes());
+        reply[40..44].copy_from_slice(&((unix_secs + NTP_UNIX_OFFSET) as u32).to_be_bytes());
+        assert_eq!(parse_reply(&reply, nonce), Some(unix_secs));
+
+        // Replies to another request, truncated or from unsynchronized servers
+        assert_eq!(parse_reply(&reply, nonce + 1), None);
+        assert_eq!(parse_reply(&reply[..47], nonce), None);
+        let mut kiss = reply;
+        kiss[1] = 0;
+        assert_eq!(parse_reply(&kiss, nonce), None);
+        let mut unsynced = reply;
+        unsynced[0] |= 3 << 6;
+        assert_eq!(parse_reply(&unsynced, nonce), None);
+        let mut client = reply;
+        client[0] = (4 << 3) | 3;
+        assert_eq!(parse_reply(&client, nonce), None);
+        let mut epoch = reply;
+        epoch[40..44].copy_from_slice(&0u32.to_be_bytes());
+        assert_eq!(parse_reply(&epoch, nonce), None);
+    }
+
+    #[test]
+    fn test_wall_clock() {
+        let mut clock = WallClock::new();
+        assert_eq!(clock.now(5_000_000), None);
+        assert!(clock.needs_sync(5_000_000));
+
+        clock.record(MIN_UNIX_SECS, 5_000_000);
+        assert_eq!(clock.now(65_000_000), Some(MIN_UNIX_SECS + 60));
+        assert!(!clock.needs_sync(65_000_000));
+        assert!(clock.needs_sync(5_000_000 + RESYNC_SECS * 1_000_000));
+        // RTC reset since the sync
+        assert_eq!(clock.now(1_000_000), None);
+
+        let mut garbage = WallClock {
+            magic: 1,
+            ..WallClock::new()
+        };
+        garbage.validate();
+        assert_eq!(garbage.now(0), None);
+    }
+
+    #[test]
+    fn test_civil_time() {
+        let time = |year, month, day, hours, minutes, seconds| CivilTime {
+            year,
+            month,
+            day,
+            hours,
+            minutes,
+            seconds,
+        };
+        assert_eq!(CivilTime::from_unix(0), time(1970, 1, 1, 0, 0, 0));
+        assert_eq!(
+            CivilTime::from_unix(MIN_UNIX_SECS),
+            time(2025, 1, 1, 0, 0, 0)
+        );
+        assert_eq!(
+            CivilTime::from_unix(1_709_210_096),
+            time(2024, 2, 29, 12, 34, 56)
+        );
+        assert_eq!(
+            CivilTime::from_unix(4_107_542_399),
+            time(2100, 2, 28, 23, 59, 59)
+        );
+    }
+}
@@ -0,0 +1,420 @@
+//! Device configuration served by the edge service
+//!
+//! JSON format from `/config`:
+//! ```json
+//! {"refresh_interval_secs": 900, "default_orientation": "horiz", "widgets": ["concerts"]}
+//! ```
+//!
+//! An item the frame has been asked to show next is delivered with it as
+//! `"show_next": "<item path>"`, until the frame reports showing it. Remote
+//! commands queued on the server come once as `"commands": ["next", "flip",
+//! "refresh"]`, and run in order after the refresh like button presses.
+//!
+//! In horizontal mode the right slot can hold another widget on its own cadence,
+//! configured as `"held_slot": {"widget": "calendar", "ttl_secs": 3600}`: the
+//! left slot then rotates every wake while the right one is only refreshed once
+//! its TTL has expired.
+//!
+//! Quiet hours, e.g. `"quiet_hours": {"start": "23:00", "end": "07:00",
+//! "utc_offset_mins": -300}`, are slept through: a wake that would fall inside
+//! them is pushed back to their end. They need the wall clock (see
+//! [`crate::clock`]), so they're ignored until it's known.
+//!
+//! Every `"clear_every"` refreshes (default [`DEFAULT_CLEAR_EVERY`], 0 never),
+//! the panel is cleared with a standard refresh before the next image is drawn,
+//! wiping the ghosting fast and partial refreshes leave behind. Routine
+//! refreshes use `"refresh_mode"`, `"fast"` (default) or `"standard"`.
+//!
+//! `"server_ip": "192.168.1.20"` connects to the server at that address from the
+//! next wake on, without resolving its name (see [`crate::dns_cache`]).
+//!
+//! `"spki_sha256": "<64 hex digits>"` pins the https server's key from the next
+//! wake on: sessions with a server whose certificate carries another key fail
+//! (see [`crate::tls`]).
+
+use core::net::Ipv4Addr;
+
+use heapless::{String, Vec};
+use serde::{Deserialize, Serialize};
+
+use crate::epd::RefreshMode;
+use crate::widget::{MAX_PATH_LEN, Orientation};
+use crate::x509;
+
+/// Refresh interval used until the server provides one (15 minutes)
+pub const DEFAULT_REFRESH_INTERVAL_SECS: u32 = 15 * 60;
+
+/// Shortest accepted refresh interval (1 minute)
+const MIN_REFRESH_INTERVAL_SECS: u32 = 60;
+
+/// Longest accepted refresh interval (24 hours)
+const MAX_REFRESH_INTERVAL_SECS: u32 = 24 * 60 * 60;
+
+/// Maximum number of widgets in the rotation
+pub const MAX_WIDGETS: usize = 4;
+
+/// Maximum widget name length
+pub const MAX_WIDGET_NAME_LEN: usize = 16;
+
+/// Length of a device ID: `frame-` and 12 hex digits of the MAC address
+pub const DEVICE_ID_LEN: usize = 18;
+
+/// Maximum number of queued commands delivered at once
+pub const MAX_COMMANDS: usize = 4;
+
+/// Maximum serialized config size
+pub const CONFIG_JSON_SIZE: usize = 640;
+
+/// Refreshes between full clears when the server doesn't say
+pub const DEFAULT_CLEAR_EVERY: u16 = 24;
+
+/// Seconds in a day
+const DAY_SECS: u32 = 24 * 60 * 60;
+
+/// Device settings fetched from the server on each wake
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceConfig {
+    /// Seconds between display refreshes (deep sleep duration)
+    pub refresh_interval_secs: u32,
+    /// Orientation used until the user toggles it on the device
+    pub default_orientation: Orientation,
+    /// Widgets to rotate through, in order
+    pub widgets: Vec<String<MAX_WIDGET_NAME_LEN>, MAX_WIDGETS>,
+    /// Item to show on the next refresh (never cached)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub show_next: Option<String<MAX_PATH_LEN>>,
+    /// Commands to run after the refresh, oldest first (never cached)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<DeviceCommand, MAX_COMMANDS>,
+    /// Widget held in the right slot of horizontal mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub held_slot: Option<HeldSlot>,
+    /// Daily window slept through instead of refreshing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quiet_hours: Option<QuietHours>,
+    /// Refreshes between full clears of the panel, 0 to never clear
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clear_every: Option<u16>,
+    /// Panel waveform for routine refreshes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_mode: Option<RefreshMode>,
+    /// Server address used instead of resolving its name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_ip: Option<String<15>>,
+    /// SHA-256 of the server key's SubjectPublicKeyInfo, in hex
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spki_sha256: Option<String<64>>,
+}
+
+/// A remote-control command queued on the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceCommand {
+    /// Show the next item, like a button tap
+    Next,
+    /// Toggle the orientation, like a button hold
+    Flip,
+    /// Redraw the current items with a full refresh
+    Refresh,
+    /// A command from a newer server, ignored
+    #[serde(other)]
+    Unknown,
+}
+
+/// A daily window in local time the frame sleeps through
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+    /// Start of the window, `HH:MM`
+    pub start: String<5>,
+    /// End of the window, `HH:MM` (earlier than `start` if it spans midnight)
+    pub end: String<5>,
+    /// Local time's offset from UTC in minutes
+    #[serde(default)]
+    pub utc_offset_mins: i16,
+}
+
+impl QuietHours {
+    /// Seconds from Unix time `unix_secs` until the window ends, None outside
+    /// of it (or if the window is malformed or empty)
+    pub fn remaining_secs(&self, unix_secs: u64) -> Option<u64> {
+        let start = second_of_day(&self.start)?;
+        let end = second_of_day(&self.end)?;
+        let now = (unix_secs as i64 + self.utc_offset_mins as i64 * 60).rem_euclid(DAY_SECS as i64)
+            as u32;
+        let inside = if start <= end {
+            (start..end).contains(&now)
+        } else {
+            now >= start || now < end
+        };
+        inside.then(|| ((end + DAY_SECS - now) % DAY_SECS) as u64)
+    }
+
+    /// Sleep from Unix time `unix_secs`, pushed back to the end of the window
+    /// if waking after `sleep_secs` would fall inside it
+    pub fn adjust_sleep(&self, unix_secs: u64, sleep_secs: u64) -> u64 {
+        sleep_secs + self.remaining_secs(unix_secs + sleep_secs).unwrap_or(0)
+    }
+}
+
+/// Seconds since midnight of a `HH:MM` time
+fn second_of_day(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    (hours < 24 && minutes < 60).then_some((hours * 60 + minutes) * 60)
+}
+
+/// A widget held in the right slot, refreshed on its own cadence
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeldSlot {
+    /// Widget whose first item is shown
+    pub widget: String<MAX_WIDGET_NAME_LEN>,
+    /// Seconds the slot is kept before it's refreshed
+    pub ttl_secs: u32,
+}
+
+impl HeldSlot {
+    /// TTL clamped to the same range as the refresh interval
+    pub fn ttl_secs(&self) -> u64 {
+        self.ttl_secs
+            .clamp(MIN_REFRESH_INTERVAL_SECS, MAX_REFRESH_INTERVAL_SECS) as u64
+    }
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        let mut widgets = Vec::new();
+        let _ = widgets.push(String::try_from("concerts").unwrap_or_default());
+        Self {
+            refresh_interval_secs: DEFAULT_REFRESH_INTERVAL_SECS,
+            default_orientation: Orientation::default(),
+            widgets,
+            show_next: None,
+            commands: Vec::new(),
+            held_slot: None,
+            quiet_hours: None,
+            clear_every: None,
+            refresh_mode: None,
+            server_ip: None,
+            spki_sha256: None,
+        }
+    }
+}
+
+impl DeviceConfig {
+    /// Refresh interval clamped to a sane range (guards against a bad server config)
+    pub fn refresh_interval_secs(&self) -> u64 {
+        self.refresh_interval_secs
+            .clamp(MIN_REFRESH_INTERVAL_SECS, MAX_REFRESH_INTERVAL_SECS) as u64
+    }
+
+    /// Whether the panel is due a full clear after `refreshes` since the last one
+    pub fn clear_due(&self, refreshes: u16) -> bool {
+        let every = self.clear_every.unwrap_or(DEFAULT_CLEAR_EVERY);
+        every > 0 && refreshes >= every
+    }
+
+    /// Refresh mode for routine refreshes, fast unless the server asks otherwise
+    pub fn refresh_mode(&self) -> RefreshMode {
+        self.refresh_mode.unwrap_or(RefreshMode::Fast)
+    }
+
+    /// Server address to connect to without resolving its name, if set
+    pub fn server_ip(&self) -> Option<Ipv4Addr> {
+        self.server_ip.as_deref()?.parse().ok()
+    }
+
+    /// Hash the server's key must match, if set
+    pub fn spki_pin(&self) -> Option<[u8; 32]> {
+        x509::parse_spki_pin(self.spki_sha256.as_deref()?)
+    }
+}
+
+/// Device ID sent to the server, derived from the factory MAC address
+pub fn device_id(mac: [u8; 6]) -> String<DEVICE_ID_LEN> {
+    let mut id = String::new();
+    let _ = id.push_str("frame-");
+    for byte in mac {
+        let _ = core::fmt::Write::write_fmt(&mut id, format_args!("{:02x}", byte));
+    }
+    id
+}
+
+/// Parse device config JSON
+pub fn parse_device_config(json: &str) -> Result<DeviceConfig, &'static str> {
+    serde_json_core::from_str(json)
+        .map(|(config, _)| config)
+        .map_err(|_| "invalid config JSON")
+}
+
+/// Serialize device config to JSON, returning the number of bytes written
+pub fn serialize_device_config(config: &DeviceConfig, buf: &mut [u8]) -> Option<usize> {
+    serde_json_core::to_slice(config, buf).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_device_config() {
+        let json =
+            r#"{"refresh_interval_secs":1800,"default_orientation":"vert","widgets":["concerts"]}"#;
+        let config = parse_device_config(json).unwrap();
+        assert_eq!(config.refresh_interval_secs(), 1800);
+        assert_eq!(config.default_orientation, Orientation::Vertical);
+        assert_eq!(config.widgets[0].as_str(), "concerts");
+        assert_eq!(config.show_next, None);
+
+        let json = r#"{"refresh_interval_secs":900,"default_orientation":"horiz","widgets":["concerts"],"show_next":"2024-01-01-band-id"}"#;
+        let config = parse_device_config(json).unwrap();
+        assert_eq!(config.show_next.as_deref(), Some("2024-01-01-band-id"));
+        assert_eq!(config.held_slot, None);
+
+        let json = r#"{"refresh_interval_secs":900,"default_orientation":"horiz","widgets":["concerts"],"commands":["flip","refresh"]}"#;
+        let config = parse_device_config(json).unwrap();
+        assert_eq!(
+            config.commands.as_slice(),
+            [DeviceCommand::Flip, DeviceCommand::Refresh]
+        );
+        // Commands this firmware doesn't know don't spoil the config
+        let json = r#"{"refresh_interval_secs":900,"default_orientation":"horiz","widgets":["concerts"],"commands":["dance","next"]}"#;
+        let config = parse_device_config(json).unwrap();
+        assert_eq!(
+            config.commands.as_slice(),
+            [DeviceCommand::Unknown, DeviceCommand::Next]
+        );
+
+        let json = r#"{"refresh_interval_secs":900,"default_orientation":"horiz","widgets":["concerts"],"held_slot":{"widget":"calendar","ttl_secs":10}}"#;
+        let held = parse_device_config(json).unwrap().held_slot.unwrap();
+        assert_eq!(held.widget.as_str(), "calendar");
+        assert_eq!(held.ttl_secs(), 60);
+
+        let json = r#"{"refresh_interval_secs":900,"default_orientation":"horiz","widgets":["concerts"],"quiet_hours":{"start":"23:00","end":"07:30","utc_offset_mins":-300}}"#;
+        let quiet = parse_device_config(json).unwrap().quiet_hours.unwrap();
+        assert_eq!(quiet.start.as_str(), "23:00");
+        assert_eq!(quiet.utc_offset_mins, -300);
+    }
+
+    #[test]
+    fn test_clear_due() {
+        let mut config = DeviceConfig::default();
+        assert!(!config.clear_due(DEFAULT_CLEAR_EVERY - 1));
+        assert!(config.clear_due(DEFAULT_CLEAR_EVERY));
+
+        let json = r#"{"refresh_interval_secs":900,"default_orientation":"horiz","widgets":["concerts"],"clear_every":4}"#;
+        config = parse_device_config(json).unwrap();
+        assert!(config.clear_due(4));
+        config.clear_every = Some(0);
+        assert!(!config.clear_due(u16::MAX));
+    }
+
+    #[test]
+    fn test_refresh_mode() {
+        assert_eq!(DeviceConfig::default().refresh_mode(), RefreshMode::Fast);
+
+        let json = r#"{"refresh_interval_secs":900,"default_orientation":"horiz","widgets":["concerts"],"refresh_mode":"standard"}"#;
+        let config = parse_device_config(json).unwrap();
+        assert_eq!(config.refresh_mode(), RefreshMode::Standard);
+        // Unknown modes reject the config, keeping the cached one
+        let json = r#"{"refresh_interval_secs":900,"default_orientation":"horiz","widgets":["concerts"],"refresh_mode":"slow"}"#;
+        assert!(parse_device_config(json).is_err());
+    }
+
+    #[test]
+    fn test_server_ip() {
+        assert_eq!(DeviceConfig::default().server_ip(), None);
+
+        let json = r#"{"refresh_interval_secs":900,"default_orientation":"horiz","widgets":["concerts"],"server_ip":"192.168.1.20"}"#;
+        let mut config = parse_device_config(json).unwrap();
+        assert_eq!(config.server_ip(), Some(Ipv4Addr::new(192, 168, 1, 20)));
+        config.server_ip = Some(String::try_from("frame.local").unwrap());
+        assert_eq!(config.server_ip(), None);
+    }
+
+    #[test]
+    fn test_spki_pin() {
+        assert_eq!(DeviceConfig::default().spki_pin(), None);
+
+        let json = r#"{"refresh_interval_secs":900,"default_orientation":"horiz","widgets":["concerts"],"spki_sha256":"ef4828e2a577251914ecaee0c9d10c7f2084c96ad2b5a2f2275f0560f7f96c03"}"#;
+        let mut config = parse_device_config(json).unwrap();
+        let pin = config.spki_pin().unwrap();
+        assert_eq!((pin[0], pin[31]), (0xef, 0x03));
+        config.spki_sha256 = Some(String::try_from("ef4828e2").unwrap());
+        assert_eq!(config.spki_pin(), None);
+    }
+
+    #[test]
+    fn test_quiet_hours() {
+        let quiet = QuietHours {
+            start: String::try_from("23:00").unwrap(),
+            end: String::try_from("07:30").unwrap(),
+            utc_offset_mins: -300,
+        };
+        // 2025-01-01 in UTC-5: 22:59, 23:00, 03:00 and 07:30
+        let local = |hours: u64, minutes: u64| 1_735_689_600 + (hours * 60 + minutes + 300) * 60;
+        assert_eq!(quiet.remaining_secs(local(22, 59)), None);
+        assert_eq!(quiet.remaining_secs(local(23, 0)), Some(8 * 3600 + 1800));
+        assert_eq!(quiet.remaining_secs(local(27, 0)), Some(4 * 3600 + 1800));
+        assert_eq!(quiet.remaining_secs(local(31, 30)), None);
+
+        // A wake landing inside the window is pushed back to its end
+        assert_eq!(quiet.adjust_sleep(local(22, 50), 300), 300);
+        assert_eq!(quiet.adjust_sleep(local(22, 50), 900), 8 * 3600 + 2400);
+
+        // Windows within a day, empty and malformed windows
+        let daytime = QuietHours {
+            start: String::try_from("09:00").unwrap(),
+            end: String::try_from("17:00").unwrap(),
+            utc_offset_mins: 0,
+        };
+        assert_eq!(
+            daytime.remaining_secs(1_735_689_600 + 16 * 3600),
+            Some(3600)
+        );
+        assert_eq!(daytime.remaining_secs(1_735_689_600 + 17 * 3600), None);
+        let empty = QuietHours {
+            end: daytime.start.clone(),
+            ..daytime.clone()
+        };
+        assert_eq!(empty.remaining_secs(1_735_689_600 + 9 * 3600), None);
+        let malformed = QuietHours {
+            start: String::try_from("late").unwrap(),
+            ..daytime
+        };
+        assert_eq!(malformed.remaining_secs(1_735_689_600), None);
+    }
+
+    #[test]
+    fn test_device_id() {
+        let id = device_id([0x24, 0x0a, 0xc4, 0x00, 0xbe, 0xef]);
+        assert_eq!(id.as_str(), "frame-240ac400beef");
+    }
+
+    #[test]
+    fn test_roundtrip_and_clamp() {
+        let config = DeviceConfig {
+            refresh_interval_secs: 5,
+            show_next: Some(
+                String::try_from("2024-01-01-0123456789abcdef0123456789abcdef").unwrap(),
+            ),
+            held_slot: Some(HeldSlot {
+                widget: String::try_from("calendar").unwrap(),
+                ttl_secs: 3600,
+            }),
+            quiet_hours: Some(QuietHours {
+                start: String::try_from("23:00").unwrap(),
+                end: String::try_from("07:00").unwrap(),
+                utc_offset_mins: -720,
+            }),
+            clear_every: Some(u16::MAX),
+            refresh_mode: Some(RefreshMode::Standard),
+            ..DeviceConfig::default()
+        };
+        let mut buf = [0u8; CONFIG_JSON_SIZE];
+        let len = serialize_device_config(&config, &mut buf).unwrap();
+        let parsed = parse_device_config(core::str::from_utf8(&buf[..len]).unwrap()).unwrap();
+        assert_eq!(parsed, config);
+        assert_eq!(parsed.refresh_interval_secs(), 60);
+    }
+}
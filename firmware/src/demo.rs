@@ -0,0 +1,108 @@
+//! Default playlist baked into flash
+//!
+//! With the `demo` feature a few concert cards are compiled into the firmware
+//! image. A frame that can't get the widget list from its server and has none
+//! cached on the SD card shows these instead of the error screen, so a freshly
+//! flashed board always has something on the panel: handy for demos, and for
+//! checking a new board's panel before there is a server to point it at.
+//!
+//! The images in `firmware/demo/` are the server's README examples, already
+//! dithered to the panel palette the way the server serves them: vertical ones
+//! rotated to the panel's native geometry and both packed at 4 bits per pixel,
+//! so they render like any cached image and take little flash.
+
+use crate::widget::Orientation;
+
+/// A card from the baked-in playlist
+pub struct DemoItem {
+    pub title: &'static str,
+    /// 400x480 indexed PNG for one half of a horizontal frame
+    horizontal: &'static [u8],
+    /// 800x480 indexed PNG, pre-rotated, for a vertical frame
+    vertical: &'static [u8],
+}
+
+impl DemoItem {
+    /// The item's image for `orientation`
+    pub fn image(&self, orientation: Orientation) -> &'static [u8] {
+        match orientation {
+            Orientation::Horizontal => self.horizontal,
+            Orientation::Vertical => self.vertical,
+        }
+    }
+}
+
+macro_rules! demo_item {
+    ($title:expr, $name:literal) => {
+        DemoItem {
+            title: $title,
+            horizontal: include_bytes!(concat!("../demo/", $name, "_horiz.png")),
+            vertical: include_bytes!(concat!("../demo/", $name, "_vert.png")),
+        }
+    };
+}
+
+/// The baked-in playlist, shown in order
+pub const PLAYLIST: [DemoItem; 4] = [
+    demo_item!("Atmosphere 2025", "atmosphere_2025"),
+    demo_item!("Santana 2012", "santana_2012"),
+    demo_item!("Primus 2014", "primus_2014"),
+    demo_item!("Billy Strings 2017", "billy_strings_2017"),
+];
+
+/// Items shown at once in `orientation`
+pub fn items_shown(orientation: Orientation) -> usize {
+    match orientation {
+        Orientation::Horizontal => 2,
+        Orientation::Vertical => 1,
+    }
+}
+
+/// The items for the screen starting at `index`, wrapping around the playlist
+pub fn screen(index: usize, orientation: Orientation) -> impl Iterator<Item = &'static DemoItem> {
+    PLAYLIST
+        .iter()
+        .cycle()
+        .skip(index % PLAYLIST.len())
+        .take(items_shown(orientation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::Framebuffer;
+    use crate::render;
+
+    #[test]
+    fn test_images_render() {
+        let mut framebuffer = Framebuffer::new();
+        for item in &PLAYLIST {
+            for orientation in [Orientation::Horizontal, Orientation::Vertical] {
+                let image = item.image(orientation);
+                assert!(render::fits_panel(image, orientation), "{}", item.title);
+                for slot in 0..items_shown(orientation) as u8 {
+                    render::render_image_to_framebuffer(image, &mut framebuffer, slot, orientation)
+                        .unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_screen() {
+        let titles = |index, orientation| {
+            screen(index, orientation)
+                .map(|item| item.title)
+                .collect::<std::vec::Vec<_>>()
+        };
+        assert_eq!(
+            titles(0, Orientation::Horizontal),
+            ["Atmosphere 2025", "Santana 2012"]
+        );
+        assert_eq!(
+            titles(3, Orientation::Horizontal),
+            ["Billy Strings 2017", "Atmosphere 2025"]
+        );
+        assert_eq!(titles(6, Orientation::Vertical), ["Primus 2014"]);
+    }
+}
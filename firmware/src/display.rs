@@ -1,41 +1,99 @@
 //! Display manager for orchestrating edge service integration
 //!
 //! Handles the fetch → decode → display flow using a single HTTP connection:
-//! 1. Fetch widget data JSON from edge service
-//! 2. Parse and shuffle widget items
-//! 3. Fetch PNG images for each item (reusing connection)
+//! 1. Open one [`Session`] to the edge service per wake cycle
+//! 2. Fetch widget data JSON and shuffle widget items
+//! 3. Fetch PNG images for each item (reusing the session)
 //! 4. Decode and write to framebuffer
 //! 5. Refresh the e-paper display
+//!
+//! PNGs are decoded row by row with [`PngDecoder`](crate::png::PngDecoder), so
+//! no full-size pixel buffer is needed besides the fetched file.
+//!
+//! Responses to [`Session`] fetches may be gzipped (widget lists shrink a lot),
+//! and are inflated on the fly with [`Inflater`]. Bodies are read until the
+//! reader reports the end, so chunked responses without a `Content-Length`
+//! (as some proxies send) work the same as sized ones.
+//!
+//! Requests can carry an `If-None-Match` ETag (a CRC-32 of the body, as stored in
+//! the SD cache) so unchanged widget data and images come back as a bodyless 304.
+//!
+//! Every request identifies the frame with `X-Device-Id`, and reports its
+//! firmware version and (once read) battery level, so the server can serve
+//! per-device settings and show when each frame was last seen. A
+//! [`TelemetryReport`] is posted at the end of each wake.
+//!
+//! Full-screen items can also be requested in the server's EPD-native format
+//! and streamed chunk by chunk straight into the panel (see
+//! [`Session::stream_to_display`]), skipping the framebuffer and SD card.
+//!
+//! Built with `RAW_IMAGES=1`, the firmware asks for every image in that format
+//! (`Accept: application/x-epd-4bpp`) and copies it into the framebuffer as is,
+//! skipping the PNG decode. Bodies are cached on the SD card as received, so
+//! [`render_image_to_framebuffer`](crate::render::render_image_to_framebuffer)
+//! takes either format.
+//!
+//! The server's name is resolved through [`FallbackDns`], which falls back on
+//! the address it last resolved to when DNS fails (see [`crate::dns_cache`]).
+//! Sessions connect through a [`ServerConnector`], which makes the TLS
+//! handshake with an https server, verifying it if the build asks to (see
+//! [`crate::tls`]).
 
 extern crate alloc;
 
 use alloc::boxed::Box;
+use core::cell::RefCell;
 use core::fmt::Write as FmtWrite;
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
 use embedded_hal::delay::DelayNs;
 use embedded_hal::digital::{InputPin, OutputPin};
 use embedded_hal::spi::SpiDevice;
-use embedded_io_async::Read;
-use embedded_nal_async::{Dns, TcpConnect};
+use embedded_io_async::{Read, Write};
+use embedded_nal_async::{AddrType, Dns, TcpConnect};
 use heapless::String;
-use log::info;
-use reqwless::client::{HttpClient, TlsConfig, TlsVerify};
-use reqwless::request::Method;
-
-use crate::epd::{Color, Epd7in3e};
+use log::{info, warn};
+use reqwless::client::HttpResource;
+use reqwless::headers::ContentType;
+use reqwless::request::{Method, RequestBuilder};
+
+use crate::cache_policy::CachePolicy;
+use crate::clock;
+use crate::config::{CONFIG_JSON_SIZE, DEVICE_ID_LEN, DeviceConfig, parse_device_config};
+use crate::dns_cache::ResolvedHost;
+use crate::epd::{BUFFER_SIZE, Color, Epd7in3e, HEIGHT, WIDTH};
 use crate::framebuffer::Framebuffer;
-use crate::widget::{Orientation, WidgetData, parse_widget_data};
+use crate::inflate::{Format, Inflater};
+use crate::render::{
+    RenderError, copy_packed_to_framebuffer, decode_png_to_framebuffer, fill_half, image_path,
+    is_png,
+};
+use crate::telemetry::{TELEMETRY_JSON_SIZE, TelemetryReport, serialize_report};
+use crate::tls::{ServerConnector, ServerStream, TLS_READ_BUF_SIZE, TLS_WRITE_BUF_SIZE};
+use crate::widget::{Orientation, WIDGET_JSON_SIZE, WidgetData, parse_widget_data};
 
 /// Size of PNG receive buffer (256KB - enough for 480x800 processed e-paper images)
 const PNG_BUF_SIZE: usize = 256 * 1024;
-/// Size of decoded pixel buffer (480x800 * 4 bytes for RGBA - covers both orientations)
-const DECODE_BUF_SIZE: usize = 480 * 800 * 4;
-
-/// TLS buffer sizes
-pub const TLS_READ_BUF_SIZE: usize = 16640;
-pub const TLS_WRITE_BUF_SIZE: usize = 4096;
-
-/// TLS seed for random number generation
-const TLS_SEED: u64 = 0x1234567890abcdef;
+/// Size of the response header buffer
+const RX_BUF_SIZE: usize = 4096;
+/// Size of the chunks streamed from the network to the panel
+const STREAM_CHUNK_SIZE: usize = 1024;
+
+/// Request images in the panel's packed 4bpp format (`RAW_IMAGES=1` at build time)
+const RAW_IMAGES: bool = match option_env!("RAW_IMAGES") {
+    Some(value) => value.len() == 1 && value.as_bytes()[0] == b'1',
+    None => false,
+};
+/// `Accept` header for images in the packed 4bpp format, with PNG as a fallback
+const RAW_IMAGE_ACCEPT: &str = "application/x-epd-4bpp, image/png;q=0.5";
+
+/// Firmware version reported to the server
+const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Raised to abort in-flight network work, e.g. when a button tap needs the
+/// next refresh to start right away
+pub type CancelSignal = Signal<CriticalSectionRawMutex, ()>;
 
 /// Display manager error types
 #[derive(Debug)]
@@ -45,268 +103,775 @@ pub enum DisplayError {
     Png(&'static str),
     Json(&'static str),
     NoItems,
+    /// Response body did not fit in the receive buffer
+    TooLarge,
+    /// EPD-native body did not match the panel
+    Epd(&'static str),
+    /// Compressed body failed to inflate
+    Encoding(&'static str),
+    /// SPI error while writing to the panel
+    Display,
+    /// Aborted through a [`CancelSignal`]
+    Cancelled,
 }
 
-/// Fetch images and render to framebuffer (no display update).
+impl DisplayError {
+    /// Code shown on the [error screen](crate::error_screen): the status of HTTP errors, `1xx` for
+    /// failures on the frame's side
+    pub fn code(&self) -> u16 {
+        match self {
+            DisplayError::Http(status) => *status,
+            DisplayError::Network | DisplayError::Cancelled => 102,
+            DisplayError::Json(_) => 103,
+            DisplayError::NoItems => 104,
+            DisplayError::Png(_)
+            | DisplayError::TooLarge
+            | DisplayError::Epd(_)
+            | DisplayError::Encoding(_) => 105,
+            DisplayError::Display => 106,
+        }
+    }
+}
+
+impl From<RenderError> for DisplayError {
+    fn from(e: RenderError) -> Self {
+        match e {
+            RenderError::Png(msg) => DisplayError::Png(msg),
+            RenderError::Epd(msg) => DisplayError::Epd(msg),
+        }
+    }
+}
+
+/// Outcome of a conditional request
+#[derive(Debug)]
+pub enum Fetched<T> {
+    /// New content, with the server's ETag if it sent one
+    Modified(T, Option<u32>),
+    /// Content matches the ETag sent in `If-None-Match`
+    NotModified,
+}
+
+impl<T> Fetched<T> {
+    /// Content of an unconditional request, which is never answered with a 304
+    pub fn into_modified(self) -> Result<(T, Option<u32>), DisplayError> {
+        match self {
+            Fetched::Modified(content, etag) => Ok((content, etag)),
+            Fetched::NotModified => Err(DisplayError::Http(304)),
+        }
+    }
+}
+
+/// Format an ETag value (quoted 8-digit hex CRC-32)
+fn format_etag(etag: u32) -> String<10> {
+    let mut value = String::new();
+    let _ = write!(value, "\"{:08x}\"", etag);
+    value
+}
+
+/// Parse a strong ETag header value produced by the server
+fn parse_etag(value: &[u8]) -> Option<u32> {
+    let hex = value.strip_prefix(b"\"")?.strip_suffix(b"\"")?;
+    if hex.len() != 8 {
+        return None;
+    }
+    u32::from_str_radix(core::str::from_utf8(hex).ok()?, 16).ok()
+}
+
+/// Parse a numeric header value
+fn parse_dimension(value: &[u8]) -> Option<u32> {
+    core::str::from_utf8(value).ok()?.parse().ok()
+}
+
+/// Name resolution for server connections that copes with flaky DNS
 ///
-/// This function:
-/// 1. Establishes one HTTP(S) connection to the edge server
-/// 2. Fetches all PNG images (reusing the same connection)
-/// 3. Decodes and renders to framebuffer
+/// A static address (`server_ip` in the device config) is used without
+/// resolving at all. Otherwise names are resolved as usual, and when that fails
+/// the address last resolved (from the SD card) stands in, within its TTL.
+pub struct FallbackDns<'a, D> {
+    dns: &'a D,
+    /// Used for every name instead of resolving it
+    static_ip: Option<Ipv4Addr>,
+    /// Last address resolved on an earlier wake
+    stored: Option<ResolvedHost>,
+    /// Last address resolved this wake
+    resolved: RefCell<Option<ResolvedHost>>,
+}
+
+impl<'a, D: Dns> FallbackDns<'a, D> {
+    pub fn new(dns: &'a D, static_ip: Option<Ipv4Addr>, stored: Option<ResolvedHost>) -> Self {
+        if let Some(ip) = static_ip {
+            info!("Connecting to the server at {} without DNS", ip);
+        }
+        Self {
+            dns,
+            static_ip,
+            stored,
+            resolved: RefCell::new(None),
+        }
+    }
+
+    /// The address resolved this wake, if it should be stored
+    /// (see [`ResolvedHost::replaces`])
+    pub fn to_store(&self) -> Option<ResolvedHost> {
+        self.resolved
+            .borrow()
+            .clone()
+            .filter(|resolved| resolved.replaces(self.stored.as_ref()))
+    }
+}
+
+impl<D: Dns> Dns for FallbackDns<'_, D> {
+    type Error = D::Error;
+
+    async fn get_host_by_name(
+        &self,
+        host: &str,
+        addr_type: AddrType,
+    ) -> Result<IpAddr, Self::Error> {
+        if let Some(ip) = self.static_ip {
+            return Ok(IpAddr::V4(ip));
+        }
+        match self.dns.get_host_by_name(host, addr_type).await {
+            Ok(addr) => {
+                if let IpAddr::V4(ip) = addr {
+                    *self.resolved.borrow_mut() = ResolvedHost::new(host, ip, clock::unix_time());
+                }
+                Ok(addr)
+            }
+            Err(e) => {
+                let fallback = self
+                    .stored
+                    .as_ref()
+                    .and_then(|stored| stored.fallback_for(host, clock::unix_time()));
+                match fallback {
+                    Some(ip) => {
+                        warn!("DNS lookup of {} failed, using {} from last time", host, ip);
+                        Ok(IpAddr::V4(ip))
+                    }
+                    None => Err(e),
+                }
+            }
+        }
+    }
+
+    async fn get_host_by_address(
+        &self,
+        addr: IpAddr,
+        result: &mut [u8],
+    ) -> Result<usize, Self::Error> {
+        self.dns.get_host_by_address(addr, result).await
+    }
+}
+
+/// The parts of `SERVER_URL` a connection needs
+struct ServerUrl<'a> {
+    https: bool,
+    host: &'a str,
+    port: u16,
+    /// Prefix of every request's path (may be empty)
+    path: &'a str,
+}
+
+impl<'a> ServerUrl<'a> {
+    fn parse(url: &'a str) -> Option<Self> {
+        let (https, rest) = match url.strip_prefix("https://") {
+            Some(rest) => (true, rest),
+            None => (false, url.strip_prefix("http://")?),
+        };
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().ok()?),
+            None => (authority, if https { 443 } else { 80 }),
+        };
+        Some(Self {
+            https,
+            host,
+            port,
+            path,
+        })
+    }
+}
+
+/// Open a session to the edge server (TCP connect + TLS handshake, verifying
+/// the server if the build asks to; see [`crate::tls`]).
 ///
-/// Call `update_display()` separately after this to refresh the e-paper.
-#[allow(clippy::too_many_arguments)]
-pub async fn fetch_to_framebuffer<T, D>(
-    tcp: &T,
+/// embedded-tls has no client-side session resumption, so the handshake is the
+/// expensive part: keep the returned session for every request in the wake cycle
+/// and only reconnect after it has been dropped.
+pub async fn connect<'a, T, D>(
+    connector: &'a ServerConnector<'_, T>,
     dns: &D,
-    tls_read_buf: &mut [u8],
-    tls_write_buf: &mut [u8],
-    framebuffer: &mut Framebuffer,
-    server_url: &str,
-    widget_name: &str,
-    orientation: Orientation,
-    items: &WidgetData,
-    start_index: usize,
-) -> Result<(), DisplayError>
+    server_url: &'a str,
+    device_id: &'a str,
+) -> Result<Session<'a, ServerStream<'a, T::Connection<'a>>>, DisplayError>
 where
     T: TcpConnect,
     D: Dns,
 {
-    // Clear framebuffer to white
-    framebuffer.clear(Color::White);
-
-    let total_items = items.len();
-    info!(
-        "Fetching images starting at index {} (connection reuse enabled)",
-        start_index
-    );
-
-    // Create HTTP client with TLS - single connection for all requests
-    let tls_config = TlsConfig::new(TLS_SEED, tls_read_buf, tls_write_buf, TlsVerify::None);
-    let mut client = HttpClient::new_with_tls(tcp, dns, tls_config);
-
-    // Establish persistent connection to edge server
-    let mut resource = client
-        .resource(server_url)
+    let url = ServerUrl::parse(server_url).ok_or(DisplayError::Network)?;
+    info!("Opening session to {}", server_url);
+    let ip = dns
+        .get_host_by_name(url.host, AddrType::IPv4)
         .await
         .map_err(|_| DisplayError::Network)?;
+    let conn = connector
+        .connect(url.host, SocketAddr::new(ip, url.port), url.https)
+        .await
+        .map_err(|e| {
+            warn!("Connecting to the server failed: {:?}", e);
+            DisplayError::Network
+        })?;
+    info!("Session established");
+
+    Ok(Session {
+        resource: HttpResource {
+            conn,
+            host: url.host,
+            base_path: url.path,
+        },
+        rx_buf: Box::new([0u8; RX_BUF_SIZE]),
+        requests: 0,
+        data_stale: false,
+        cache_policy: CachePolicy::DEFAULT,
+        device_id,
+        battery_percent: None,
+    })
+}
 
-    // Allocate buffers from PSRAM heap (reused for each image)
-    let mut png_buf: Box<[u8; PNG_BUF_SIZE]> = Box::new([0u8; PNG_BUF_SIZE]);
-    let mut decode_buf: Box<[u8; DECODE_BUF_SIZE]> = Box::new([0u8; DECODE_BUF_SIZE]);
-    let mut rx_buf = [0u8; 2048];
+/// Headers identifying the device, with room for two more
+fn device_headers<'h>(
+    device_id: &'h str,
+    battery_percent: Option<&'h str>,
+) -> heapless::Vec<(&'h str, &'h str), 6> {
+    let mut headers = heapless::Vec::new();
+    let _ = headers.push(("X-Device-Id", device_id));
+    let _ = headers.push(("X-Firmware-Version", FIRMWARE_VERSION));
+    if let Some(percent) = battery_percent {
+        let _ = headers.push(("X-Battery-Percent", percent));
+    }
+    headers
+}
 
-    // In horizontal mode, display 2 items side by side (400px each)
-    // In vertical mode, display 1 fullscreen item (480x800)
-    let items_per_screen = match orientation {
-        Orientation::Horizontal => 2,
-        Orientation::Vertical => 1,
-    };
-    let items_to_display = total_items.min(items_per_screen);
+/// Long-lived HTTP(S) session to the edge server.
+///
+/// Every request drains its response body completely, so the connection stays
+/// in sync for the next one. Any error leaves the connection in an unknown state:
+/// callers should drop the session and [`connect`] again.
+pub struct Session<'a, C>
+where
+    C: Read + Write,
+{
+    resource: HttpResource<'a, C>,
+    rx_buf: Box<[u8; RX_BUF_SIZE]>,
+    /// Number of requests sent over this connection
+    requests: u32,
+    /// The last response flagged its data as stale (`X-Data-Stale: true`)
+    data_stale: bool,
+    /// The last response's `X-Cache-Policy`
+    cache_policy: CachePolicy,
+    /// Sent as `X-Device-Id`
+    device_id: &'a str,
+    /// Sent as `X-Battery-Percent` once known
+    battery_percent: Option<String<3>>,
+}
 
-    for display_slot in 0..items_to_display {
-        let item_idx = (start_index + display_slot) % total_items;
-        let item = &items[item_idx];
-        // In vertical mode, always use x_offset 0 (single fullscreen image)
-        let x_offset = if orientation == Orientation::Vertical || display_slot == 0 {
-            0
-        } else {
-            400
-        };
+impl<C> Session<'_, C>
+where
+    C: Read + Write,
+{
+    /// Number of requests sent over this session so far
+    pub fn requests(&self) -> u32 {
+        self.requests
+    }
 
-        info!("Fetching image {}: {}", item_idx, item.as_str());
+    /// Whether the last response was served from an expired copy because the
+    /// server's upstream is failing
+    pub fn data_stale(&self) -> bool {
+        self.data_stale
+    }
 
-        // Build relative path for image (includes orientation)
-        let mut path: String<256> = String::new();
-        if write!(
-            &mut path,
-            "/{}/{}/{}",
-            widget_name,
-            orientation.as_str(),
-            item.as_str()
-        )
-        .is_err()
-        {
-            info!("Path too long, skipping image");
-            fill_half(framebuffer, x_offset);
-            continue;
+    /// Cache policy of the last response, the default if it had none
+    pub fn cache_policy(&self) -> CachePolicy {
+        self.cache_policy
+    }
+
+    /// Report `percent` as the battery level on subsequent requests
+    pub fn set_battery_percent(&mut self, percent: u8) {
+        let mut value = String::new();
+        let _ = write!(&mut value, "{}", percent.min(100));
+        self.battery_percent = Some(value);
+    }
+
+    /// GET `path` and read the whole body into `buf`, returning its length
+    ///
+    /// Bodies may come back gzipped (`Accept-Encoding: gzip`), in which case
+    /// they are inflated as they arrive and `buf` receives the decoded body.
+    /// With `if_none_match`, a 304 response is returned as `NotModified`. With
+    /// `cancel`, the request is abandoned as soon as it is raised: before sending,
+    /// or between body chunks (leaving the session out of sync).
+    async fn get(
+        &mut self,
+        path: &str,
+        accept: Option<&str>,
+        if_none_match: Option<u32>,
+        buf: &mut [u8],
+        cancel: Option<&CancelSignal>,
+    ) -> Result<Fetched<usize>, DisplayError> {
+        let cancelled = || cancel.is_some_and(|signal| signal.signaled());
+        if cancelled() {
+            info!("GET {} cancelled", path);
+            return Err(DisplayError::Cancelled);
         }
+        self.requests += 1;
+        info!("GET {} (request {} on session)", path, self.requests);
 
-        // Fetch PNG using existing connection
-        let result: Result<usize, DisplayError> = async {
-            let response = resource
-                .request(Method::GET, path.as_str())
-                .send(&mut rx_buf)
-                .await
-                .map_err(|_| DisplayError::Network)?;
-
-            let status = response.status.0;
-            if status >= 400 {
-                return Err(DisplayError::Http(status));
-            }
+        let etag_value = if_none_match.map(format_etag);
+        let mut headers = device_headers(self.device_id, self.battery_percent.as_deref());
+        if let Some(value) = &etag_value {
+            let _ = headers.push(("If-None-Match", value.as_str()));
+        }
+        if let Some(value) = accept {
+            let _ = headers.push(("Accept", value));
+        }
+        let _ = headers.push(("Accept-Encoding", "gzip"));
 
-            // Read PNG body
-            let mut png_len = 0;
-            let mut body_reader = response.body().reader();
+        let response = self
+            .resource
+            .request(Method::GET, path)
+            .headers(&headers)
+            .send(&mut self.rx_buf[..])
+            .await
+            .map_err(|_| DisplayError::Network)?;
+
+        let status = response.status.0;
+        self.data_stale = response.headers().any(|(name, value)| {
+            name.eq_ignore_ascii_case("x-data-stale") && value.eq_ignore_ascii_case(b"true")
+        });
+        self.cache_policy = CachePolicy::from_header(
+            response
+                .headers()
+                .find(|(name, _)| name.eq_ignore_ascii_case("x-cache-policy"))
+                .map(|(_, value)| value),
+        );
+        if status == 304 {
+            // No body follows a 304
+            info!("{} not modified", path);
+            return Ok(Fetched::NotModified);
+        }
+        let etag = response
+            .headers()
+            .find(|(name, _)| name.eq_ignore_ascii_case("etag"))
+            .and_then(|(_, value)| parse_etag(value));
+        let gzip = response.headers().any(|(name, value)| {
+            name.eq_ignore_ascii_case("content-encoding") && value.eq_ignore_ascii_case(b"gzip")
+        });
+
+        // Drain the body even for error statuses so the next request lines up
+        let mut len = 0;
+        let mut body_reader = response.body().reader();
+        if gzip && status < 400 {
+            let mut inflater = Inflater::new(Format::Gzip);
+            let mut chunk = [0u8; STREAM_CHUNK_SIZE];
+            let mut received = 0;
             loop {
-                match body_reader.read(&mut png_buf[png_len..]).await {
+                if cancelled() {
+                    info!("GET {} cancelled after {} bytes", path, received);
+                    return Err(DisplayError::Cancelled);
+                }
+                let n = match body_reader.read(&mut chunk).await {
                     Ok(0) => break,
-                    Ok(n) => png_len += n,
-                    Err(_) => break,
+                    Ok(n) => n,
+                    Err(_) => return Err(DisplayError::Network),
+                };
+                received += n;
+                let mut overflow = false;
+                let pushed = inflater.push(&chunk[..n], &mut |data| {
+                    let Some(dest) = buf.get_mut(len..len + data.len()) else {
+                        overflow = true;
+                        return Err("body too large");
+                    };
+                    dest.copy_from_slice(data);
+                    len += data.len();
+                    Ok(())
+                });
+                if let Err(e) = pushed {
+                    return Err(if overflow {
+                        DisplayError::TooLarge
+                    } else {
+                        DisplayError::Encoding(e)
+                    });
                 }
             }
-
-            Ok(png_len)
+            if !inflater.is_done() {
+                return Err(DisplayError::Encoding("truncated gzip body"));
+            }
+            info!("Inflated {} gzipped bytes to {}", received, len);
+            return Ok(Fetched::Modified(len, etag));
         }
-        .await;
-
-        match result {
-            Ok(png_len) => {
-                info!("Received {} bytes of PNG data", png_len);
-                if let Err(e) = decode_png_to_framebuffer(
-                    &png_buf[..png_len],
-                    framebuffer,
-                    x_offset,
-                    &mut *decode_buf,
-                    orientation,
-                ) {
-                    info!("Error decoding PNG: {:?}", e);
-                    fill_half(framebuffer, x_offset);
+        loop {
+            if cancelled() {
+                info!("GET {} cancelled after {} bytes", path, len);
+                return Err(DisplayError::Cancelled);
+            }
+            if len == buf.len() {
+                // Buffer full - only OK if the body ends exactly here
+                let mut probe = [0u8; 1];
+                match body_reader.read(&mut probe).await {
+                    Ok(0) => break,
+                    _ => return Err(DisplayError::TooLarge),
                 }
             }
-            Err(e) => {
-                info!("Error fetching image {}: {:?}", item_idx, e);
-                fill_half(framebuffer, x_offset);
+            match body_reader.read(&mut buf[len..]).await {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(_) => return Err(DisplayError::Network),
             }
         }
-    }
 
-    // In horizontal mode with only one item, fill right half with white
-    if orientation == Orientation::Horizontal && items_to_display == 1 {
-        framebuffer.fill_right_half(Color::White);
+        if status >= 400 {
+            return Err(DisplayError::Http(status));
+        }
+
+        Ok(Fetched::Modified(len, etag))
     }
 
-    info!("Framebuffer ready for display");
-    Ok(())
-}
+    /// Fetch widget data from edge service
+    ///
+    /// `if_none_match` is the ETag of the cached widget data, if any.
+    pub async fn fetch_widget_data(
+        &mut self,
+        widget_name: &str,
+        if_none_match: Option<u32>,
+    ) -> Result<Fetched<Box<WidgetData>>, DisplayError> {
+        let mut path: String<256> = String::new();
+        write!(&mut path, "/{}", widget_name).map_err(|_| DisplayError::Network)?;
 
-/// Fetch a single image and render to one half of the framebuffer.
-///
-/// This is used for partial refresh in horizontal mode where we only
-/// update one side of the display at a time.
-///
-/// - `slot`: 0 for left half (x_offset=0), 1 for right half (x_offset=400)
-/// - `item_idx`: Index of the item in the items array to fetch
-#[allow(clippy::too_many_arguments)]
-pub async fn fetch_single_to_framebuffer<T, D>(
-    tcp: &T,
-    dns: &D,
-    tls_read_buf: &mut [u8],
-    tls_write_buf: &mut [u8],
-    framebuffer: &mut Framebuffer,
-    server_url: &str,
-    widget_name: &str,
-    items: &WidgetData,
-    item_idx: usize,
-    slot: u8,
-) -> Result<(), DisplayError>
-where
-    T: TcpConnect,
-    D: Dns,
-{
-    let total_items = items.len();
-    if item_idx >= total_items {
-        return Err(DisplayError::NoItems);
-    }
+        // Read response body (heap allocated to avoid stack overflow)
+        let mut json_buf: Box<[u8; WIDGET_JSON_SIZE]> = Box::new([0u8; WIDGET_JSON_SIZE]);
+        let (json_len, etag) = match self
+            .get(path.as_str(), None, if_none_match, &mut *json_buf, None)
+            .await?
+        {
+            Fetched::Modified(len, etag) => (len, etag),
+            Fetched::NotModified => return Ok(Fetched::NotModified),
+        };
 
-    let x_offset = if slot == 0 { 0 } else { 400 };
-    let item = &items[item_idx];
+        let json_str = core::str::from_utf8(&json_buf[..json_len])
+            .map_err(|_| DisplayError::Json("invalid utf8"))?;
+        info!("Received {} bytes of JSON", json_len);
 
-    info!(
-        "Fetching single image {} for slot {} (x_offset={})",
-        item_idx, slot, x_offset
-    );
+        let items = parse_widget_data(json_str).map_err(DisplayError::Json)?;
 
-    // Create HTTP client with TLS - single connection
-    let tls_config = TlsConfig::new(TLS_SEED, tls_read_buf, tls_write_buf, TlsVerify::None);
-    let mut client = HttpClient::new_with_tls(tcp, dns, tls_config);
+        if items.is_empty() {
+            return Err(DisplayError::NoItems);
+        }
 
-    // Establish connection to edge server
-    let mut resource = client
-        .resource(server_url)
-        .await
-        .map_err(|_| DisplayError::Network)?;
+        info!("Got {} widget items", items.len());
+        Ok(Fetched::Modified(items, etag))
+    }
 
-    // Allocate buffers from PSRAM heap
-    let mut png_buf: Box<[u8; PNG_BUF_SIZE]> = Box::new([0u8; PNG_BUF_SIZE]);
-    let mut decode_buf: Box<[u8; DECODE_BUF_SIZE]> = Box::new([0u8; DECODE_BUF_SIZE]);
-    let mut rx_buf = [0u8; 2048];
-
-    // Build relative path for image (horizontal orientation)
-    let mut path: String<256> = String::new();
-    if write!(
-        &mut path,
-        "/{}/{}/{}",
-        widget_name,
-        Orientation::Horizontal.as_str(),
-        item.as_str()
-    )
-    .is_err()
-    {
-        info!("Path too long, filling with white");
-        fill_half(framebuffer, x_offset);
-        return Ok(());
+    /// Fetch device configuration from edge service
+    pub async fn fetch_config(&mut self) -> Result<DeviceConfig, DisplayError> {
+        let mut json_buf = [0u8; CONFIG_JSON_SIZE];
+        let (json_len, _) = self
+            .get("/config", None, None, &mut json_buf, None)
+            .await?
+            .into_modified()?;
+
+        let json_str = core::str::from_utf8(&json_buf[..json_len])
+            .map_err(|_| DisplayError::Json("invalid utf8"))?;
+
+        let config = parse_device_config(json_str).map_err(DisplayError::Json)?;
+        info!(
+            "Got device config: refresh={}s, orientation={:?}",
+            config.refresh_interval_secs, config.default_orientation
+        );
+        Ok(config)
     }
 
-    // Fetch PNG
-    let result: Result<usize, DisplayError> = async {
-        let response = resource
-            .request(Method::GET, path.as_str())
-            .send(&mut rx_buf)
+    /// Post a wake telemetry report to `/telemetry`
+    pub async fn post_telemetry(&mut self, report: &TelemetryReport) -> Result<(), DisplayError> {
+        let mut json_buf = [0u8; TELEMETRY_JSON_SIZE];
+        let len = serialize_report(report, &mut json_buf)
+            .ok_or(DisplayError::Json("report too large"))?;
+
+        self.requests += 1;
+        info!("POST /telemetry (request {} on session)", self.requests);
+        self.post("/telemetry", ContentType::ApplicationJson, &json_buf[..len])
+            .await
+    }
+
+    /// Upload captured log lines to `/devices/{id}/logs`
+    pub async fn post_logs(&mut self, logs: &[u8]) -> Result<(), DisplayError> {
+        let mut path: String<{ DEVICE_ID_LEN + 14 }> = String::new();
+        write!(path, "/devices/{}/logs", self.device_id).map_err(|_| DisplayError::Network)?;
+
+        self.requests += 1;
+        info!(
+            "POST {} ({} bytes, request {} on session)",
+            path,
+            logs.len(),
+            self.requests
+        );
+        self.post(path.as_str(), ContentType::TextPlain, logs).await
+    }
+
+    /// Send a POST request, draining the response body
+    async fn post(
+        &mut self,
+        path: &str,
+        content_type: ContentType,
+        body: &[u8],
+    ) -> Result<(), DisplayError> {
+        let headers = device_headers(self.device_id, self.battery_percent.as_deref());
+        let response = self
+            .resource
+            .request(Method::POST, path)
+            .headers(&headers)
+            .content_type(content_type)
+            .body(body)
+            .send(&mut self.rx_buf[..])
             .await
             .map_err(|_| DisplayError::Network)?;
 
+        // Drain any body so the next request lines up
         let status = response.status.0;
+        let mut chunk = [0u8; 64];
+        let mut body_reader = response.body().reader();
+        loop {
+            match body_reader.read(&mut chunk).await {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(_) => return Err(DisplayError::Network),
+            }
+        }
+
         if status >= 400 {
             return Err(DisplayError::Http(status));
         }
+        Ok(())
+    }
+
+    /// Fetch a single PNG image (for caching).
+    ///
+    /// With `RAW_IMAGES`, the server may answer in the packed 4bpp format instead;
+    /// either way, render the body with [`crate::render::render_image_to_framebuffer`].
+    /// Returns the number of bytes written to `png_buf`. `if_none_match` is the
+    /// ETag of the cached copy (see `SdCache::image_etag`), if any. Background
+    /// fetches pass a `cancel` signal so a button press can cut them short; the
+    /// session must be dropped after a `Cancelled` error.
+    pub async fn fetch_png(
+        &mut self,
+        png_buf: &mut [u8],
+        widget_name: &str,
+        item_path: &str,
+        orientation: Orientation,
+        if_none_match: Option<u32>,
+        cancel: Option<&CancelSignal>,
+    ) -> Result<Fetched<usize>, DisplayError> {
+        let path = image_path(widget_name, item_path, orientation).ok_or(DisplayError::Network)?;
+
+        let fetched = self
+            .get(
+                path.as_str(),
+                RAW_IMAGES.then_some(RAW_IMAGE_ACCEPT),
+                if_none_match,
+                png_buf,
+                cancel,
+            )
+            .await?;
+        if let Fetched::Modified(png_len, _) = fetched {
+            info!("Fetched {} bytes from network", png_len);
+        }
+        Ok(fetched)
+    }
+
+    /// Stream a full-screen item in EPD-native format straight into the panel.
+    ///
+    /// The body is already packed in panel color codes and layout, so each chunk
+    /// is written to the display controller as it arrives, without touching the
+    /// framebuffer or SD card. On success the refresh has started: poll
+    /// `is_busy()` and call `finish_display()`. On error the panel keeps showing
+    /// its previous image, and any later full display overwrites the partial frame.
+    pub async fn stream_to_display<SPI, BUSY, DC, RST, DELAY>(
+        &mut self,
+        epd: &mut Epd7in3e<SPI, BUSY, DC, RST>,
+        delay: &mut DELAY,
+        widget_name: &str,
+        item_path: &str,
+        orientation: Orientation,
+    ) -> Result<(), DisplayError>
+    where
+        SPI: SpiDevice,
+        BUSY: InputPin,
+        DC: OutputPin,
+        RST: OutputPin,
+        DELAY: DelayNs,
+    {
+        let mut path: String<256> = String::new();
+        write!(
+            &mut path,
+            "/{}/{}/{}?format=epd",
+            widget_name,
+            orientation.as_str(),
+            item_path
+        )
+        .map_err(|_| DisplayError::Network)?;
+
+        self.requests += 1;
+        info!("GET {} (request {} on session)", path, self.requests);
+
+        let headers = device_headers(self.device_id, self.battery_percent.as_deref());
+        let response = self
+            .resource
+            .request(Method::GET, path.as_str())
+            .headers(&headers)
+            .send(&mut self.rx_buf[..])
+            .await
+            .map_err(|_| DisplayError::Network)?;
 
-        // Read PNG body
-        let mut png_len = 0;
+        let status = response.status.0;
+        let header = |name: &str| {
+            response
+                .headers()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .and_then(|(_, value)| parse_dimension(value))
+        };
+        let full_screen =
+            header("x-epd-width") == Some(WIDTH) && header("x-epd-height") == Some(HEIGHT);
+
+        let mut chunk = [0u8; STREAM_CHUNK_SIZE];
         let mut body_reader = response.body().reader();
+
+        // Only start a frame for a full-screen image, draining anything else
+        // so the next request lines up
+        let streaming = status < 400 && full_screen;
+        if streaming {
+            epd.begin_frame().map_err(|_| DisplayError::Display)?;
+        }
+
+        let mut len = 0;
         loop {
-            match body_reader.read(&mut png_buf[png_len..]).await {
+            match body_reader.read(&mut chunk).await {
                 Ok(0) => break,
-                Ok(n) => png_len += n,
-                Err(_) => break,
+                Ok(n) => {
+                    if streaming {
+                        let remaining = BUFFER_SIZE.saturating_sub(len);
+                        epd.write_frame(&chunk[..n.min(remaining)])
+                            .map_err(|_| DisplayError::Display)?;
+                    }
+                    len += n;
+                }
+                Err(_) => return Err(DisplayError::Network),
             }
         }
 
-        Ok(png_len)
+        if status >= 400 {
+            return Err(DisplayError::Http(status));
+        }
+        if !full_screen {
+            return Err(DisplayError::Epd("not a full-screen image"));
+        }
+        if len != BUFFER_SIZE {
+            return Err(DisplayError::Epd("frame size mismatch"));
+        }
+
+        info!("Streamed {} bytes to the panel", len);
+        epd.show_frame_start(delay)
+            .map_err(|_| DisplayError::Display)
     }
-    .await;
-
-    match result {
-        Ok(png_len) => {
-            info!("Received {} bytes of PNG data", png_len);
-            if let Err(e) = decode_png_to_framebuffer(
-                &png_buf[..png_len],
-                framebuffer,
-                x_offset,
-                &mut *decode_buf,
-                Orientation::Horizontal,
-            ) {
-                info!("Error decoding PNG: {:?}", e);
+}
+
+/// Fetch images and render to framebuffer (no display update).
+///
+/// All images are fetched over the given session, then decoded and
+/// rendered to the framebuffer.
+///
+/// Call `update_display()` separately after this to refresh the e-paper.
+pub async fn fetch_to_framebuffer<C>(
+    session: &mut Session<'_, C>,
+    framebuffer: &mut Framebuffer,
+    widget_name: &str,
+    orientation: Orientation,
+    items: &WidgetData,
+    start_index: usize,
+) -> Result<(), DisplayError>
+where
+    C: Read + Write,
+{
+    // Clear framebuffer to white
+    framebuffer.clear(Color::White);
+
+    let total_items = items.len();
+    info!("Fetching images starting at index {}", start_index);
+
+    // Allocate the receive buffer from PSRAM heap (reused for each image)
+    let mut png_buf: Box<[u8; PNG_BUF_SIZE]> = Box::new([0u8; PNG_BUF_SIZE]);
+
+    // In horizontal mode, display 2 items side by side (400px each)
+    // In vertical mode, display 1 fullscreen item (480x800)
+    let items_per_screen = match orientation {
+        Orientation::Horizontal => 2,
+        Orientation::Vertical => 1,
+    };
+    let items_to_display = total_items.min(items_per_screen);
+
+    for display_slot in 0..items_to_display {
+        let item_idx = (start_index + display_slot) % total_items;
+        let item = &items[item_idx];
+        // A full-width item takes the whole screen, and can't share it
+        if orientation == Orientation::Horizontal
+            && display_slot == 1
+            && (item.is_full_width() || items[start_index % total_items].is_full_width())
+        {
+            break;
+        }
+        // In vertical mode, always use x_offset 0 (single fullscreen image)
+        let x_offset = if orientation == Orientation::Vertical || display_slot == 0 {
+            0
+        } else {
+            400
+        };
+
+        info!("Fetching image {}: {}", item_idx, item.label());
+
+        match session
+            .fetch_png(
+                &mut *png_buf,
+                widget_name,
+                item.as_str(),
+                orientation,
+                None,
+                None,
+            )
+            .await
+            .and_then(Fetched::into_modified)
+        {
+            Ok((png_len, _)) => {
+                let data = &png_buf[..png_len];
+                let result = if is_png(data) {
+                    decode_png_to_framebuffer(data, framebuffer, x_offset, orientation)
+                } else {
+                    copy_packed_to_framebuffer(data, framebuffer, x_offset, orientation)
+                };
+                if let Err(e) = result {
+                    info!("Error decoding image: {:?}", e);
+                    fill_half(framebuffer, x_offset);
+                }
+            }
+            Err(e) => {
+                info!("Error fetching image {}: {:?}", item_idx, e);
                 fill_half(framebuffer, x_offset);
             }
         }
-        Err(e) => {
-            info!("Error fetching image {}: {:?}", item_idx, e);
-            fill_half(framebuffer, x_offset);
-        }
     }
 
-    info!("Single image fetch complete for slot {}", slot);
+    // In horizontal mode with only one item, fill right half with white
+    if orientation == Orientation::Horizontal && items_to_display == 1 {
+        framebuffer.fill_right_half(Color::White);
+    }
+
+    info!("Framebuffer ready for display");
     Ok(())
 }
 
@@ -330,74 +895,6 @@ where
     Ok(())
 }
 
-/// Fetch widget data from edge service
-pub async fn fetch_widget_data<T, D>(
-    tcp: &T,
-    dns: &D,
-    tls_read_buf: &mut [u8],
-    tls_write_buf: &mut [u8],
-    server_url: &str,
-    widget_name: &str,
-) -> Result<Box<WidgetData>, DisplayError>
-where
-    T: TcpConnect,
-    D: Dns,
-{
-    // Create HTTP client with TLS
-    let tls_config = TlsConfig::new(TLS_SEED, tls_read_buf, tls_write_buf, TlsVerify::None);
-    let mut client = HttpClient::new_with_tls(tcp, dns, tls_config);
-
-    // Build path
-    let mut path: String<256> = String::new();
-    write!(&mut path, "/{}", widget_name).map_err(|_| DisplayError::Network)?;
-
-    info!("Fetching widget data from {}{}", server_url, path.as_str());
-
-    // Establish connection and make request
-    let mut resource = client
-        .resource(server_url)
-        .await
-        .map_err(|_| DisplayError::Network)?;
-
-    let mut rx_buf = [0u8; 4096];
-    let response = resource
-        .request(Method::GET, path.as_str())
-        .send(&mut rx_buf)
-        .await
-        .map_err(|_| DisplayError::Network)?;
-
-    let status = response.status.0;
-    if status >= 400 {
-        return Err(DisplayError::Http(status));
-    }
-
-    // Read response body (heap allocated to avoid stack overflow)
-    let mut json_buf: Box<[u8; 16384]> = Box::new([0u8; 16384]);
-    let mut json_len = 0;
-
-    let mut body_reader = response.body().reader();
-    loop {
-        match body_reader.read(&mut json_buf[json_len..]).await {
-            Ok(0) => break,
-            Ok(n) => json_len += n,
-            Err(_) => break,
-        }
-    }
-
-    let json_str = core::str::from_utf8(&json_buf[..json_len])
-        .map_err(|_| DisplayError::Json("invalid utf8"))?;
-    info!("Received {} bytes of JSON", json_len);
-
-    let items = parse_widget_data(json_str).map_err(DisplayError::Json)?;
-
-    if items.is_empty() {
-        return Err(DisplayError::NoItems);
-    }
-
-    info!("Got {} widget items", items.len());
-    Ok(items)
-}
-
 /// Shuffle widget items in-place using a simple xorshift RNG
 pub fn shuffle_items(items: &mut WidgetData, seed: u64) {
     let len = items.len();
@@ -420,86 +917,6 @@ pub fn shuffle_items(items: &mut WidgetData, seed: u64) {
     info!("Shuffled {} items", len);
 }
 
-fn fill_half(framebuffer: &mut Framebuffer, x_offset: u32) {
-    if x_offset == 0 {
-        framebuffer.fill_left_half(Color::White);
-    } else {
-        framebuffer.fill_right_half(Color::White);
-    }
-}
-
-/// Decode a PNG image into the framebuffer
-/// For horizontal: image is 400x480, written directly with flip
-/// For vertical: image is 480x800, rotated 90° CCW to fit 800x480 framebuffer
-fn decode_png_to_framebuffer(
-    png_data: &[u8],
-    framebuffer: &mut Framebuffer,
-    x_offset: u32,
-    decode_buf: &mut [u8],
-    orientation: Orientation,
-) -> Result<(), DisplayError> {
-    let header = minipng::decode_png_header(png_data)
-        .map_err(|_| DisplayError::Png("invalid PNG header"))?;
-
-    info!(
-        "PNG: {}x{} {:?}",
-        header.width(),
-        header.height(),
-        header.color_type()
-    );
-
-    let image = minipng::decode_png(png_data, decode_buf).map_err(|e| {
-        info!("minipng error: {:?}", e);
-        DisplayError::Png("PNG decode failed")
-    })?;
-
-    let width = image.width() as usize;
-    let height = image.height() as usize;
-    let pixels = image.pixels();
-
-    match orientation {
-        Orientation::Horizontal => {
-            // Horizontal: 400x480 image, flip and write rows directly
-            let mut row_buf = [0u8; 480];
-            for y in 0..height {
-                let row_start = y * width;
-                let row_end = row_start + width;
-                if row_end <= pixels.len() {
-                    let row = &pixels[row_start..row_end];
-                    for (i, &px) in row.iter().enumerate() {
-                        if i < row_buf.len() {
-                            row_buf[width - 1 - i] = px;
-                        }
-                    }
-                    let flipped_y = (height - 1 - y) as u32;
-                    framebuffer.write_row(x_offset, flipped_y, &row_buf[..width]);
-                }
-            }
-        }
-        Orientation::Vertical => {
-            // Vertical: 480x800 image, rotate 90° CCW to fit 800x480 framebuffer
-            // After rotation: x_new = y_old, y_new = (width - 1 - x_old)
-            // This maps 480x800 -> 800x480
-            for y in 0..height {
-                for x in 0..width {
-                    let src_idx = y * width + x;
-                    if src_idx < pixels.len() {
-                        let px = pixels[src_idx];
-                        // Rotate 90° CCW: new_x = y, new_y = (width - 1 - x)
-                        let new_x = y as u32;
-                        let new_y = (width - 1 - x) as u32;
-                        framebuffer.set_pixel_indexed(new_x, new_y, px);
-                    }
-                }
-            }
-        }
-    }
-
-    info!("PNG decode complete, {}x{} processed", width, height);
-
-    Ok(())
-}
-
 /// TLS buffer size constants for external allocation
 pub const fn tls_read_buffer_size() -> usize {
     TLS_READ_BUF_SIZE
@@ -509,100 +926,30 @@ pub const fn tls_write_buffer_size() -> usize {
     TLS_WRITE_BUF_SIZE
 }
 
-/// Fetch a single PNG image from the network (for caching).
-///
-/// Returns the number of bytes written to `png_buf`.
-#[allow(clippy::too_many_arguments)]
-pub async fn fetch_png<T, D>(
-    tcp: &T,
-    dns: &D,
-    tls_read_buf: &mut [u8],
-    tls_write_buf: &mut [u8],
-    png_buf: &mut [u8],
-    server_url: &str,
-    widget_name: &str,
-    item_path: &str,
-    orientation: Orientation,
-) -> Result<usize, DisplayError>
-where
-    T: TcpConnect,
-    D: Dns,
-{
-    // Create HTTP client with TLS
-    let tls_config = TlsConfig::new(TLS_SEED, tls_read_buf, tls_write_buf, TlsVerify::None);
-    let mut client = HttpClient::new_with_tls(tcp, dns, tls_config);
-
-    // Establish connection
-    let mut resource = client
-        .resource(server_url)
-        .await
-        .map_err(|_| DisplayError::Network)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error_screen::error_title;
 
-    // Build path
-    let mut path: String<256> = String::new();
-    if write!(
-        &mut path,
-        "/{}/{}/{}",
-        widget_name,
-        orientation.as_str(),
-        item_path
-    )
-    .is_err()
-    {
-        return Err(DisplayError::Network);
-    }
+    #[test]
+    fn test_etag_round_trip() {
+        let value = format_etag(0x0badcafe);
+        assert_eq!(value.as_str(), "\"0badcafe\"");
+        assert_eq!(parse_etag(value.as_bytes()), Some(0x0badcafe));
 
-    let mut rx_buf = [0u8; 2048];
-    let response = resource
-        .request(Method::GET, path.as_str())
-        .send(&mut rx_buf)
-        .await
-        .map_err(|_| DisplayError::Network)?;
-
-    let status = response.status.0;
-    if status >= 400 {
-        return Err(DisplayError::Http(status));
+        assert_eq!(parse_etag(b"0badcafe"), None);
+        assert_eq!(parse_etag(b"W/\"0badcafe\""), None);
+        assert_eq!(parse_etag(b"\"badcafe\""), None);
     }
 
-    // Read PNG body
-    let mut png_len = 0;
-    let mut body_reader = response.body().reader();
-    loop {
-        match body_reader.read(&mut png_buf[png_len..]).await {
-            Ok(0) => break,
-            Ok(n) => png_len += n,
-            Err(_) => break,
-        }
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(DisplayError::Http(503).code(), 503);
+        assert_eq!(error_title(DisplayError::Http(503).code()), "Server error");
+        assert_eq!(
+            error_title(DisplayError::Network.code()),
+            "Server unreachable"
+        );
+        assert_eq!(error_title(DisplayError::TooLarge.code()), "Image failed");
     }
-
-    info!("Fetched {} bytes from network", png_len);
-    Ok(png_len)
-}
-
-/// Decode PNG data and render to framebuffer at the specified slot.
-///
-/// For horizontal mode: slot 0 = left (x_offset=0), slot 1 = right (x_offset=400)
-/// For vertical mode: full screen render
-pub fn render_png_to_framebuffer(
-    png_data: &[u8],
-    framebuffer: &mut Framebuffer,
-    slot: u8,
-    orientation: Orientation,
-) -> Result<(), DisplayError> {
-    // Allocate decode buffer from heap
-    let mut decode_buf: Box<[u8; DECODE_BUF_SIZE]> = Box::new([0u8; DECODE_BUF_SIZE]);
-
-    let x_offset = if orientation == Orientation::Vertical || slot == 0 {
-        0
-    } else {
-        400
-    };
-
-    decode_png_to_framebuffer(
-        png_data,
-        framebuffer,
-        x_offset,
-        &mut *decode_buf,
-        orientation,
-    )
 }
@@ -0,0 +1,176 @@
+//! Last resolved server address, for when DNS fails
+//!
+//! Some routers' DNS forwarders fail now and then, failing the whole wake even
+//! though the server's address rarely changes. The address the server's name
+//! last resolved to is kept on the SD card and used when a lookup fails, for up
+//! to [`RESOLVED_TTL_SECS`] after it was resolved. Servers on a fixed address
+//! can skip the lookup altogether with `server_ip` in the device config (see
+//! [`FallbackDns`](crate::display::FallbackDns)).
+//!
+//! Stored as three lines: the host name, the address and the Unix time it was
+//! resolved (0 if the clock wasn't known yet).
+
+use core::fmt::Write;
+use core::net::Ipv4Addr;
+
+use heapless::String;
+
+/// Longest host name kept
+pub const MAX_HOST_LEN: usize = 64;
+
+/// Size of the stored lines
+pub const RESOLVED_HOST_SIZE: usize = MAX_HOST_LEN + 40;
+
+/// How long a resolved address stands in for a failed lookup (1 week)
+pub const RESOLVED_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Age at which an unchanged address is stored again, restarting its TTL
+const RESTORE_AFTER_SECS: u64 = 24 * 60 * 60;
+
+/// The address a host name resolved to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedHost {
+    pub host: String<MAX_HOST_LEN>,
+    pub addr: Ipv4Addr,
+    /// Unix time it was resolved (0 if unknown)
+    pub resolved_at: u64,
+}
+
+impl ResolvedHost {
+    /// `host` resolved to `addr` at Unix time `unix_secs`, if known
+    ///
+    /// `None` if the name is too long to keep.
+    pub fn new(host: &str, addr: Ipv4Addr, unix_secs: Option<u64>) -> Option<Self> {
+        Some(Self {
+            host: String::try_from(host).ok()?,
+            addr,
+            resolved_at: unix_secs.unwrap_or(0),
+        })
+    }
+
+    /// Address to use for `host` when looking it up failed at Unix time `now`
+    ///
+    /// Nothing for another host, or once [`RESOLVED_TTL_SECS`] have passed.
+    /// Without the clock (before SNTP answers after a power cycle), or for an
+    /// address resolved without it, the age can't be told and the address is
+    /// used anyway: trying it costs no more than failing.
+    pub fn fallback_for(&self, host: &str, now: Option<u64>) -> Option<Ipv4Addr> {
+        if !self.host.eq_ignore_ascii_case(host) {
+            return None;
+        }
+        match now {
+            Some(now) if self.resolved_at != 0 => {
+                (now.saturating_sub(self.resolved_at) < RESOLVED_TTL_SECS).then_some(self.addr)
+            }
+            _ => Some(self.addr),
+        }
+    }
+
+    /// Whether this lookup should replace `stored` on the SD card
+    ///
+    /// Unchanged addresses are only stored again once a day, to keep their TTL
+    /// running without writing the card every wake.
+    pub fn replaces(&self, stored: Option<&ResolvedHost>) -> bool {
+        let Some(stored) = stored else {
+            return true;
+        };
+        stored.host != self.host
+            || stored.addr != self.addr
+            || (self.resolved_at != 0
+                && self.resolved_at.saturating_sub(stored.resolved_at) >= RESTORE_AFTER_SECS)
+    }
+
+    /// The stored lines
+    pub fn to_lines(&self) -> String<RESOLVED_HOST_SIZE> {
+        let mut lines = String::new();
+        let _ = write!(
+            lines,
+            "{}\n{}\n{}\n",
+            self.host, self.addr, self.resolved_at
+        );
+        lines
+    }
+}
+
+/// Parse the stored lines
+pub fn parse_resolved_host(content: &[u8]) -> Option<ResolvedHost> {
+    let mut lines = core::str::from_utf8(content).ok()?.lines();
+    let host = lines.next()?.trim();
+    let addr = lines.next()?.trim().parse().ok()?;
+    let resolved_at = lines.next()?.trim().parse().ok()?;
+    if host.is_empty() {
+        return None;
+    }
+    let mut resolved = ResolvedHost::new(host, addr, None)?;
+    resolved.resolved_at = resolved_at;
+    Some(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOST: &str = "frame.example.com";
+    const ADDR: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 20);
+    const NOW: u64 = 1_750_000_000;
+
+    #[test]
+    fn test_fallback_for() {
+        let resolved = ResolvedHost::new(HOST, ADDR, Some(NOW)).unwrap();
+        assert_eq!(resolved.fallback_for(HOST, Some(NOW + 3600)), Some(ADDR));
+        assert_eq!(
+            resolved.fallback_for("Frame.Example.com", Some(NOW)),
+            Some(ADDR)
+        );
+        assert_eq!(resolved.fallback_for("other.example.com", Some(NOW)), None);
+        assert_eq!(
+            resolved.fallback_for(HOST, Some(NOW + RESOLVED_TTL_SECS)),
+            None
+        );
+        // Without the clock the age is unknown
+        assert_eq!(resolved.fallback_for(HOST, None), Some(ADDR));
+        let unstamped = ResolvedHost::new(HOST, ADDR, None).unwrap();
+        assert_eq!(
+            unstamped.fallback_for(HOST, Some(NOW + RESOLVED_TTL_SECS)),
+            Some(ADDR)
+        );
+
+        assert!(ResolvedHost::new(&"a".repeat(MAX_HOST_LEN + 1), ADDR, None).is_none());
+    }
+
+    #[test]
+    fn test_replaces() {
+        let stored = ResolvedHost::new(HOST, ADDR, Some(NOW)).unwrap();
+        let again = |addr, at| ResolvedHost::new(HOST, addr, at).unwrap();
+
+        assert!(stored.replaces(None));
+        assert!(!again(ADDR, Some(NOW + 3600)).replaces(Some(&stored)));
+        assert!(!again(ADDR, None).replaces(Some(&stored)));
+        assert!(again(ADDR, Some(NOW + RESTORE_AFTER_SECS)).replaces(Some(&stored)));
+        assert!(again(Ipv4Addr::new(10, 0, 0, 2), None).replaces(Some(&stored)));
+        assert!(
+            ResolvedHost::new("other.example.com", ADDR, None)
+                .unwrap()
+                .replaces(Some(&stored))
+        );
+        // A stamp replaces an address stored without one
+        let unstamped = again(ADDR, None);
+        assert!(again(ADDR, Some(NOW)).replaces(Some(&unstamped)));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let resolved = ResolvedHost::new(HOST, ADDR, Some(NOW)).unwrap();
+        let lines = resolved.to_lines();
+        assert_eq!(
+            lines.as_str(),
+            "frame.example.com\n192.168.1.20\n1750000000\n"
+        );
+        assert_eq!(parse_resolved_host(lines.as_bytes()), Some(resolved));
+
+        assert_eq!(parse_resolved_host(b""), None);
+        assert_eq!(parse_resolved_host(b"\n192.168.1.20\n0\n"), None);
+        assert_eq!(parse_resolved_host(b"host\n192.168.1\n0\n"), None);
+        assert_eq!(parse_resolved_host(b"host\n192.168.1.20\n"), None);
+    }
+}
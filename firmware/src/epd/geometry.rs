@@ -0,0 +1,50 @@
+//! Panel dimensions and regions, free of the SPI driver so they build on the host
+
+/// Display width in pixels
+pub const WIDTH: u32 = 800;
+/// Display height in pixels
+pub const HEIGHT: u32 = 480;
+/// Buffer size: 4 bits per pixel, 2 pixels per byte
+pub const BUFFER_SIZE: usize = (WIDTH as usize * HEIGHT as usize) / 2;
+
+/// Rectangle defining a partial update region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    /// X coordinate of top-left corner (must be even for pixel alignment)
+    pub x: u16,
+    /// Y coordinate of top-left corner
+    pub y: u16,
+    /// Width in pixels (must be even for byte alignment)
+    pub width: u16,
+    /// Height in pixels
+    pub height: u16,
+}
+
+impl Rect {
+    /// Create a new rectangle
+    ///
+    /// `x` and `width` will be adjusted to even values for byte alignment.
+    pub const fn new(x: u16, y: u16, width: u16, height: u16) -> Self {
+        Self {
+            x: x & !1, // Round down to even
+            y,
+            width: (width + 1) & !1, // Round up to even
+            height,
+        }
+    }
+
+    /// Calculate buffer size needed for this region (bytes)
+    pub const fn buffer_size(&self) -> usize {
+        (self.width as usize * self.height as usize) / 2
+    }
+
+    /// Check if rectangle is within display bounds
+    pub const fn is_valid(&self) -> bool {
+        self.x < WIDTH as u16
+            && self.y < HEIGHT as u16
+            && self.x + self.width <= WIDTH as u16
+            && self.y + self.height <= HEIGHT as u16
+            && self.width > 0
+            && self.height > 0
+    }
+}
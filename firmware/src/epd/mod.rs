@@ -5,65 +5,20 @@
 
 mod color;
 mod command;
+mod geometry;
 
 pub use color::Color;
+pub use geometry::{BUFFER_SIZE, HEIGHT, Rect, WIDTH};
 
 use command::Command;
 use embedded_hal::delay::DelayNs;
 use embedded_hal::digital::{InputPin, OutputPin};
 use embedded_hal::spi::SpiDevice;
+use serde::{Deserialize, Serialize};
 
-/// Display width in pixels
-pub const WIDTH: u32 = 800;
-/// Display height in pixels
-pub const HEIGHT: u32 = 480;
-/// Buffer size: 4 bits per pixel, 2 pixels per byte
-pub const BUFFER_SIZE: usize = (WIDTH as usize * HEIGHT as usize) / 2;
-
-/// Rectangle defining a partial update region
-#[derive(Debug, Clone, Copy)]
-pub struct Rect {
-    /// X coordinate of top-left corner (must be even for pixel alignment)
-    pub x: u16,
-    /// Y coordinate of top-left corner
-    pub y: u16,
-    /// Width in pixels (must be even for byte alignment)
-    pub width: u16,
-    /// Height in pixels
-    pub height: u16,
-}
-
-impl Rect {
-    /// Create a new rectangle
-    ///
-    /// `x` and `width` will be adjusted to even values for byte alignment.
-    pub const fn new(x: u16, y: u16, width: u16, height: u16) -> Self {
-        Self {
-            x: x & !1, // Round down to even
-            y,
-            width: (width + 1) & !1, // Round up to even
-            height,
-        }
-    }
-
-    /// Calculate buffer size needed for this region (bytes)
-    pub const fn buffer_size(&self) -> usize {
-        (self.width as usize * self.height as usize) / 2
-    }
-
-    /// Check if rectangle is within display bounds
-    pub const fn is_valid(&self) -> bool {
-        self.x < WIDTH as u16
-            && self.y < HEIGHT as u16
-            && self.x + self.width <= WIDTH as u16
-            && self.y + self.height <= HEIGHT as u16
-            && self.width > 0
-            && self.height > 0
-    }
-}
-
-/// Initialization/refresh mode
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Initialization/refresh mode, `"standard"` or `"fast"` in the device config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum RefreshMode {
     /// Standard refresh (~15-20s) - best image quality
     #[default]
@@ -72,13 +27,32 @@ pub enum RefreshMode {
     Fast,
 }
 
+/// Driver errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EpdError<E> {
+    /// SPI transfer failed
+    Spi(E),
+    /// A refresh is still running: the controller ignores or garbles commands
+    /// sent before it finishes, and can stop responding until reset
+    Busy,
+}
+
 /// Driver for the 7.3" Spectra 6 e-paper display
+///
+/// Refreshes started with the `*_start` methods keep the driver busy until
+/// `refresh_wait()` or `finish_display()`; anything else sending commands
+/// meanwhile fails with [`EpdError::Busy`] rather than reaching the
+/// controller. Methods take `&mut self`, so the driver's owner is the only one
+/// who can drive the panel; tasks that need to share it should do so through
+/// a mutex around the whole driver.
 pub struct Epd7in3e<SPI, BUSY, DC, RST> {
     spi: SPI,
     busy: BUSY,
     dc: DC,
     rst: RST,
     refresh_mode: RefreshMode,
+    /// A refresh was started and hasn't been waited for
+    refreshing: bool,
 }
 
 impl<SPI, BUSY, DC, RST> Epd7in3e<SPI, BUSY, DC, RST>
@@ -98,13 +72,14 @@ where
         rst: RST,
         delay: &mut DELAY,
         refresh_mode: RefreshMode,
-    ) -> Result<Self, SPI::Error> {
+    ) -> Result<Self, EpdError<SPI::Error>> {
         let mut epd = Self {
             spi,
             busy,
             dc,
             rst,
             refresh_mode,
+            refreshing: false,
         };
 
         epd.hardware_reset(delay);
@@ -131,26 +106,37 @@ where
         }
     }
 
-    /// Send a command to the display
-    fn send_command(&mut self, command: Command) -> Result<(), SPI::Error> {
+    /// Send a command to the display, unless a refresh is still running
+    fn send_command(&mut self, command: Command) -> Result<(), EpdError<SPI::Error>> {
+        if self.refreshing {
+            return Err(EpdError::Busy);
+        }
         let _ = self.dc.set_low();
-        self.spi.write(&[command.addr()])
+        self.write(&[command.addr()])
     }
 
     /// Send data to the display
-    fn send_data(&mut self, data: &[u8]) -> Result<(), SPI::Error> {
+    fn send_data(&mut self, data: &[u8]) -> Result<(), EpdError<SPI::Error>> {
         let _ = self.dc.set_high();
-        self.spi.write(data)
+        self.write(data)
+    }
+
+    /// Write bytes over SPI with DC as it is
+    fn write(&mut self, bytes: &[u8]) -> Result<(), EpdError<SPI::Error>> {
+        self.spi.write(bytes).map_err(EpdError::Spi)
     }
 
     /// Send command followed by data
-    fn cmd_with_data(&mut self, command: Command, data: &[u8]) -> Result<(), SPI::Error> {
+    fn cmd_with_data(&mut self, command: Command, data: &[u8]) -> Result<(), EpdError<SPI::Error>> {
         self.send_command(command)?;
         self.send_data(data)
     }
 
     /// Initialize the display with standard mode settings
-    fn init_standard<DELAY: DelayNs>(&mut self, delay: &mut DELAY) -> Result<(), SPI::Error> {
+    fn init_standard<DELAY: DelayNs>(
+        &mut self,
+        delay: &mut DELAY,
+    ) -> Result<(), EpdError<SPI::Error>> {
         // Command header
         self.cmd_with_data(Command::CMDH, &[0x49, 0x55, 0x20, 0x08, 0x09, 0x18])?;
 
@@ -198,7 +184,7 @@ where
     }
 
     /// Initialize the display with fast mode settings
-    fn init_fast<DELAY: DelayNs>(&mut self, delay: &mut DELAY) -> Result<(), SPI::Error> {
+    fn init_fast<DELAY: DelayNs>(&mut self, delay: &mut DELAY) -> Result<(), EpdError<SPI::Error>> {
         // Command header
         self.cmd_with_data(Command::CMDH, &[0x49, 0x55, 0x20, 0x08, 0x09, 0x18])?;
 
@@ -264,19 +250,27 @@ where
     }
 
     /// Initialize the display
-    fn init<DELAY: DelayNs>(&mut self, delay: &mut DELAY) -> Result<(), SPI::Error> {
+    fn init<DELAY: DelayNs>(&mut self, delay: &mut DELAY) -> Result<(), EpdError<SPI::Error>> {
         match self.refresh_mode {
             RefreshMode::Standard => self.init_standard(delay),
             RefreshMode::Fast => self.init_fast(delay),
         }
     }
 
+    /// Re-initialize the awake panel, e.g. after changing the refresh mode
+    pub fn reinit<DELAY: DelayNs>(
+        &mut self,
+        delay: &mut DELAY,
+    ) -> Result<(), EpdError<SPI::Error>> {
+        self.init(delay)
+    }
+
     /// Clear the display to a single color
     pub fn clear<DELAY: DelayNs>(
         &mut self,
         color: Color,
         delay: &mut DELAY,
-    ) -> Result<(), SPI::Error> {
+    ) -> Result<(), EpdError<SPI::Error>> {
         self.clear_start(color, delay)?;
         self.refresh_wait(delay)
     }
@@ -287,7 +281,7 @@ where
         &mut self,
         color: Color,
         delay: &mut DELAY,
-    ) -> Result<(), SPI::Error> {
+    ) -> Result<(), EpdError<SPI::Error>> {
         let color_byte = color.to_dual_pixel();
 
         self.send_command(Command::DTM)?;
@@ -296,12 +290,12 @@ where
         // Send in chunks to avoid stack issues
         let chunk = [color_byte; 1000];
         for _ in 0..(BUFFER_SIZE / 1000) {
-            self.spi.write(&chunk)?;
+            self.write(&chunk)?;
         }
         // Remainder
         let remainder = BUFFER_SIZE % 1000;
         if remainder > 0 {
-            self.spi.write(&chunk[..remainder])?;
+            self.write(&chunk[..remainder])?;
         }
 
         self.refresh_start(delay)
@@ -312,7 +306,7 @@ where
         &mut self,
         buffer: &[u8],
         delay: &mut DELAY,
-    ) -> Result<(), SPI::Error> {
+    ) -> Result<(), EpdError<SPI::Error>> {
         self.send_command(Command::DTM)?;
         self.send_data(buffer)?;
         self.refresh(delay)
@@ -324,19 +318,49 @@ where
         &mut self,
         buffer: &[u8],
         delay: &mut DELAY,
-    ) -> Result<(), SPI::Error> {
+    ) -> Result<(), EpdError<SPI::Error>> {
         self.send_command(Command::DTM)?;
         self.send_data(buffer)?;
         self.refresh_start(delay)
     }
 
+    /// Begin streaming a frame in chunks (e.g. straight from the network).
+    ///
+    /// Follow with `write_frame()` calls totalling `BUFFER_SIZE` bytes, then
+    /// `show_frame_start()`.
+    pub fn begin_frame(&mut self) -> Result<(), EpdError<SPI::Error>> {
+        self.send_command(Command::DTM)
+    }
+
+    /// Send the next chunk of a frame started with `begin_frame()`
+    pub fn write_frame(&mut self, data: &[u8]) -> Result<(), EpdError<SPI::Error>> {
+        self.send_data(data)
+    }
+
+    /// Start displaying a streamed frame (non-blocking).
+    /// Call `is_busy()` to poll, then `finish_display()` when done.
+    pub fn show_frame_start<DELAY: DelayNs>(
+        &mut self,
+        delay: &mut DELAY,
+    ) -> Result<(), EpdError<SPI::Error>> {
+        self.refresh_start(delay)
+    }
+
     /// Check if display is still busy refreshing.
     pub fn is_busy(&mut self) -> bool {
         self.busy.is_low().unwrap_or(true)
     }
 
     /// Finish display refresh after polling `is_busy()` returns false.
-    pub fn finish_display<DELAY: DelayNs>(&mut self, delay: &mut DELAY) -> Result<(), SPI::Error> {
+    pub fn finish_display<DELAY: DelayNs>(
+        &mut self,
+        delay: &mut DELAY,
+    ) -> Result<(), EpdError<SPI::Error>> {
+        if self.is_busy() {
+            return Err(EpdError::Busy);
+        }
+        self.refreshing = false;
+
         // Power off
         self.cmd_with_data(Command::POF, &[0x00])?;
         self.wait_until_idle(delay);
@@ -344,7 +368,7 @@ where
     }
 
     /// Trigger display refresh (blocking)
-    fn refresh<DELAY: DelayNs>(&mut self, delay: &mut DELAY) -> Result<(), SPI::Error> {
+    fn refresh<DELAY: DelayNs>(&mut self, delay: &mut DELAY) -> Result<(), EpdError<SPI::Error>> {
         self.refresh_start(delay)?;
         self.refresh_wait(delay)
     }
@@ -352,7 +376,10 @@ where
     /// Start display refresh (non-blocking)
     /// Call `refresh_wait()` to complete the refresh before the next operation.
     /// Note: Display must already be powered on via init() before calling this.
-    fn refresh_start<DELAY: DelayNs>(&mut self, delay: &mut DELAY) -> Result<(), SPI::Error> {
+    fn refresh_start<DELAY: DelayNs>(
+        &mut self,
+        delay: &mut DELAY,
+    ) -> Result<(), EpdError<SPI::Error>> {
         // For standard mode, need to set BTST2 before refresh
         if self.refresh_mode == RefreshMode::Standard {
             self.cmd_with_data(Command::BTST2, &[0x6F, 0x1F, 0x17, 0x49])?;
@@ -363,6 +390,7 @@ where
 
         // Display refresh
         self.cmd_with_data(Command::DRF, &[0x00])?;
+        self.refreshing = true;
         delay.delay_ms(1); // Required delay (min 200us)
 
         // Returns immediately - display is now refreshing
@@ -371,8 +399,12 @@ where
 
     /// Wait for refresh to complete and power off
     /// Must be called after `refresh_start()` or `clear_start()` before the next display operation.
-    pub fn refresh_wait<DELAY: DelayNs>(&mut self, delay: &mut DELAY) -> Result<(), SPI::Error> {
+    pub fn refresh_wait<DELAY: DelayNs>(
+        &mut self,
+        delay: &mut DELAY,
+    ) -> Result<(), EpdError<SPI::Error>> {
         self.wait_until_idle(delay);
+        self.refreshing = false;
 
         // Power off
         self.cmd_with_data(Command::POF, &[0x00])?;
@@ -382,7 +414,7 @@ where
     }
 
     /// Put the display into sleep mode
-    pub fn sleep<DELAY: DelayNs>(&mut self, delay: &mut DELAY) -> Result<(), SPI::Error> {
+    pub fn sleep<DELAY: DelayNs>(&mut self, delay: &mut DELAY) -> Result<(), EpdError<SPI::Error>> {
         self.cmd_with_data(Command::POF, &[0x00])?;
         self.wait_until_idle(delay);
 
@@ -393,8 +425,14 @@ where
     }
 
     /// Wake the display from sleep (requires full re-init)
-    pub fn wake_up<DELAY: DelayNs>(&mut self, delay: &mut DELAY) -> Result<(), SPI::Error> {
+    ///
+    /// The hardware reset also abandons any refresh still running.
+    pub fn wake_up<DELAY: DelayNs>(
+        &mut self,
+        delay: &mut DELAY,
+    ) -> Result<(), EpdError<SPI::Error>> {
         self.hardware_reset(delay);
+        self.refreshing = false;
         self.init(delay)
     }
 
@@ -413,7 +451,7 @@ where
     /// Set the partial window region for subsequent partial updates.
     ///
     /// Coordinates are specified as inclusive start/end positions.
-    fn set_partial_window(&mut self, rect: &Rect) -> Result<(), SPI::Error> {
+    fn set_partial_window(&mut self, rect: &Rect) -> Result<(), EpdError<SPI::Error>> {
         let x_start = rect.x;
         let x_end = rect.x + rect.width - 1;
         let y_start = rect.y;
@@ -443,7 +481,7 @@ where
         rect: &Rect,
         buffer: &[u8],
         delay: &mut DELAY,
-    ) -> Result<(), SPI::Error> {
+    ) -> Result<(), EpdError<SPI::Error>> {
         debug_assert!(rect.is_valid(), "Partial update rect out of bounds");
         debug_assert_eq!(
             buffer.len(),
@@ -469,7 +507,7 @@ where
         rect: &Rect,
         color: Color,
         delay: &mut DELAY,
-    ) -> Result<(), SPI::Error> {
+    ) -> Result<(), EpdError<SPI::Error>> {
         debug_assert!(rect.is_valid(), "Partial fill rect out of bounds");
 
         // Set partial window
@@ -489,7 +527,7 @@ where
             let mut remaining = row_bytes;
             while remaining > 0 {
                 let send = remaining.min(100);
-                self.spi.write(&chunk[..send])?;
+                self.write(&chunk[..send])?;
                 remaining -= send;
             }
         }
@@ -505,7 +543,7 @@ where
         rect: &Rect,
         buffer: &[u8],
         delay: &mut DELAY,
-    ) -> Result<(), SPI::Error> {
+    ) -> Result<(), EpdError<SPI::Error>> {
         debug_assert!(rect.is_valid(), "Partial update rect out of bounds");
         debug_assert_eq!(
             buffer.len(),
@@ -526,7 +564,10 @@ where
     }
 
     /// Refresh after partial data transmission (blocking).
-    fn partial_refresh<DELAY: DelayNs>(&mut self, delay: &mut DELAY) -> Result<(), SPI::Error> {
+    fn partial_refresh<DELAY: DelayNs>(
+        &mut self,
+        delay: &mut DELAY,
+    ) -> Result<(), EpdError<SPI::Error>> {
         self.partial_refresh_start(delay)?;
         self.refresh_wait(delay)
     }
@@ -536,7 +577,7 @@ where
     fn partial_refresh_start<DELAY: DelayNs>(
         &mut self,
         delay: &mut DELAY,
-    ) -> Result<(), SPI::Error> {
+    ) -> Result<(), EpdError<SPI::Error>> {
         self.wait_until_idle(delay);
 
         // Booster settings (same as standard refresh)
@@ -548,6 +589,7 @@ where
 
         // Trigger display refresh
         self.cmd_with_data(Command::DRF, &[0x00])?;
+        self.refreshing = true;
         delay.delay_ms(1);
 
         Ok(())
@@ -561,7 +603,10 @@ where
     /// | Black  | White  | Yellow |
     /// | Red    | Blue   | Green  |
     /// ```
-    pub fn show_6block<DELAY: DelayNs>(&mut self, delay: &mut DELAY) -> Result<(), SPI::Error> {
+    pub fn show_6block<DELAY: DelayNs>(
+        &mut self,
+        delay: &mut DELAY,
+    ) -> Result<(), EpdError<SPI::Error>> {
         self.show_6block_internal(None, delay)
     }
 
@@ -577,7 +622,7 @@ where
         block_index: usize,
         new_color: Color,
         delay: &mut DELAY,
-    ) -> Result<(), SPI::Error> {
+    ) -> Result<(), EpdError<SPI::Error>> {
         self.show_6block_internal(Some((block_index, new_color)), delay)
     }
 
@@ -585,7 +630,7 @@ where
         &mut self,
         replace: Option<(usize, Color)>,
         delay: &mut DELAY,
-    ) -> Result<(), SPI::Error> {
+    ) -> Result<(), EpdError<SPI::Error>> {
         let mut colors = [
             Color::Black,
             Color::White,
@@ -619,10 +664,279 @@ where
                 let color2 = colors[color_row + ((pixel_col + 1) / block_width).min(2)];
 
                 let byte = (color1.to_4bit() << 4) | color2.to_4bit();
-                self.spi.write(&[byte])?;
+                self.write(&[byte])?;
             }
         }
 
         self.refresh(delay)
     }
 }
+
+/// Command sequences checked against mock SPI and GPIO (run by the host-tests crate)
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use embedded_hal_mock::eh1::delay::{CheckedDelay, NoopDelay, Transaction as DelayTransaction};
+    use embedded_hal_mock::eh1::digital::{Mock as PinMock, State, Transaction as PinTransaction};
+    use embedded_hal_mock::eh1::spi::{Mock as SpiMock, Transaction as SpiTransaction};
+
+    use super::*;
+
+    type MockEpd = Epd7in3e<SpiMock<u8>, PinMock, PinMock, PinMock>;
+
+    /// Traffic the driver should produce on each line, in order
+    #[derive(Default)]
+    struct Expected {
+        spi: Vec<SpiTransaction<u8>>,
+        busy: Vec<PinTransaction>,
+        dc: Vec<PinTransaction>,
+        rst: Vec<PinTransaction>,
+    }
+
+    impl Expected {
+        /// One SPI write with DC held at `dc`
+        fn write(&mut self, dc: State, bytes: &[u8]) -> &mut Self {
+            self.dc.push(PinTransaction::set(dc));
+            self.spi.extend([
+                SpiTransaction::transaction_start(),
+                SpiTransaction::write_vec(bytes.to_vec()),
+                SpiTransaction::transaction_end(),
+            ]);
+            self
+        }
+
+        fn command(&mut self, command: Command) -> &mut Self {
+            self.write(State::Low, &[command.addr()])
+        }
+
+        fn cmd_with_data(&mut self, command: Command, data: &[u8]) -> &mut Self {
+            self.command(command).write(State::High, data)
+        }
+
+        /// BUSY reads low `polls` times, then high
+        fn busy_for(&mut self, polls: usize) -> &mut Self {
+            self.busy
+                .extend(core::iter::repeat_n(PinTransaction::get(State::Low), polls));
+            self.busy.push(PinTransaction::get(State::High));
+            self
+        }
+
+        fn reset(&mut self) -> &mut Self {
+            self.rst
+                .extend([State::High, State::Low, State::High].map(PinTransaction::set));
+            self
+        }
+
+        /// Register setup shared by both modes' init
+        fn init(&mut self, mode: RefreshMode) -> &mut Self {
+            self.cmd_with_data(Command::CMDH, &[0x49, 0x55, 0x20, 0x08, 0x09, 0x18]);
+            match mode {
+                RefreshMode::Standard => self
+                    .cmd_with_data(Command::PWRR, &[0x3F])
+                    .cmd_with_data(Command::PSR, &[0x5F, 0x69])
+                    .cmd_with_data(Command::POFS, &[0x00, 0x54, 0x00, 0x44])
+                    .cmd_with_data(Command::BTST1, &[0x40, 0x1F, 0x1F, 0x2C])
+                    .cmd_with_data(Command::BTST2, &[0x6F, 0x1F, 0x17, 0x49])
+                    .cmd_with_data(Command::BTST3, &[0x6F, 0x1F, 0x1F, 0x22])
+                    .cmd_with_data(Command::PLL, &[0x08])
+                    .cmd_with_data(Command::CDI, &[0x3F])
+                    .cmd_with_data(Command::TCON, &[0x02, 0x00])
+                    .cmd_with_data(Command::TRES, &[0x03, 0x20, 0x01, 0xE0])
+                    .cmd_with_data(Command::T_VDCS, &[0x01])
+                    .cmd_with_data(Command::PWS, &[0x2F]),
+                RefreshMode::Fast => self
+                    .cmd_with_data(Command::PWRR, &[0x3F, 0x00, 0x32, 0x2A, 0x0E, 0x2A])
+                    .cmd_with_data(Command::PSR, &[0x5F, 0x69])
+                    .cmd_with_data(Command::POFS, &[0x00, 0x54, 0x00, 0x44])
+                    .cmd_with_data(Command::BTST1, &[0x40, 0x1F, 0x1F, 0x2C])
+                    .cmd_with_data(Command::BTST2, &[0x6F, 0x1F, 0x16, 0x25])
+                    .cmd_with_data(Command::BTST3, &[0x6F, 0x1F, 0x1F, 0x22])
+                    .cmd_with_data(Command::IPC, &[0x00, 0x04])
+                    .cmd_with_data(Command::PLL, &[0x02])
+                    .cmd_with_data(Command::TSE, &[0x00])
+                    .cmd_with_data(Command::CDI, &[0x3F])
+                    .cmd_with_data(Command::TCON, &[0x02, 0x00])
+                    .cmd_with_data(Command::TRES, &[0x03, 0x20, 0x01, 0xE0])
+                    .cmd_with_data(Command::VDCS, &[0x1E])
+                    .cmd_with_data(Command::T_VDCS, &[0x01])
+                    .cmd_with_data(Command::AGID, &[0x00])
+                    .cmd_with_data(Command::PWS, &[0x2F])
+                    .cmd_with_data(Command::CCSET, &[0x00])
+                    .cmd_with_data(Command::TSSET, &[0x00]),
+            };
+            self.command(Command::PON).busy_for(0)
+        }
+
+        /// The booster must be set again right before every refresh, or the
+        /// panel refreshes with washed-out colors
+        fn refresh_start(&mut self, mode: RefreshMode) -> &mut Self {
+            let btst2: &[u8] = match mode {
+                RefreshMode::Standard => &[0x6F, 0x1F, 0x17, 0x49],
+                RefreshMode::Fast => &[0x6F, 0x1F, 0x16, 0x25],
+            };
+            self.cmd_with_data(Command::BTST2, btst2)
+                .cmd_with_data(Command::DRF, &[0x00])
+        }
+
+        fn partial_window(&mut self, bytes: [u8; 8]) -> &mut Self {
+            self.command(Command::PTLW);
+            for byte in bytes {
+                self.write(State::High, &[byte]);
+            }
+            self.write(State::High, &[0x01])
+        }
+
+        /// A driver in `mode` whose lines expect this traffic
+        fn driver(&self, refresh_mode: RefreshMode) -> MockEpd {
+            Epd7in3e {
+                spi: SpiMock::new(&self.spi),
+                busy: PinMock::new(&self.busy),
+                dc: PinMock::new(&self.dc),
+                rst: PinMock::new(&self.rst),
+                refresh_mode,
+                refreshing: false,
+            }
+        }
+    }
+
+    /// Fail unless every expected transaction happened
+    fn done(mut epd: MockEpd) {
+        epd.spi.done();
+        epd.busy.done();
+        epd.dc.done();
+        epd.rst.done();
+    }
+
+    #[test]
+    fn test_new_resets_and_inits() {
+        for mode in [RefreshMode::Standard, RefreshMode::Fast] {
+            let mut expected = Expected::default();
+            expected.reset().init(mode);
+            let epd = Epd7in3e::new(
+                SpiMock::new(&expected.spi),
+                PinMock::new(&expected.busy),
+                PinMock::new(&expected.dc),
+                PinMock::new(&expected.rst),
+                &mut NoopDelay::new(),
+                mode,
+            )
+            .unwrap();
+            assert_eq!(epd.refresh_mode(), mode);
+            done(epd);
+        }
+    }
+
+    #[test]
+    fn test_reinit_after_mode_change() {
+        let mut expected = Expected::default();
+        expected.init(RefreshMode::Fast);
+        let mut epd = expected.driver(RefreshMode::Standard);
+        epd.set_refresh_mode(RefreshMode::Fast);
+        epd.reinit(&mut NoopDelay::new()).unwrap();
+        done(epd);
+    }
+
+    #[test]
+    fn test_partial_window() {
+        let mut expected = Expected::default();
+        // x 8..=407 and y 16..=255, split into 2-bit MSB and 8-bit LSB
+        expected.partial_window([0x00, 0x08, 0x01, 0x97, 0x00, 0x10, 0x00, 0xFF]);
+        // The right half: x 400..=799, y 0..=479
+        expected.partial_window([0x01, 0x90, 0x03, 0x1F, 0x00, 0x00, 0x01, 0xDF]);
+        let mut epd = expected.driver(RefreshMode::Standard);
+        epd.set_partial_window(&Rect::new(8, 16, 400, 240)).unwrap();
+        epd.set_partial_window(&Rect::new(400, 0, 400, 480))
+            .unwrap();
+        done(epd);
+    }
+
+    #[test]
+    fn test_refresh_start() {
+        for mode in [RefreshMode::Standard, RefreshMode::Fast] {
+            let mut expected = Expected::default();
+            expected
+                .command(Command::DTM)
+                .write(State::High, &[0x11; 4])
+                .write(State::High, &[0x22; 4])
+                .refresh_start(mode);
+            let mut epd = expected.driver(mode);
+            // At least 200us between the refresh command and polling BUSY
+            let mut delay = CheckedDelay::new(&[DelayTransaction::delay_ms(1)]);
+            epd.begin_frame().unwrap();
+            epd.write_frame(&[0x11; 4]).unwrap();
+            epd.write_frame(&[0x22; 4]).unwrap();
+            epd.show_frame_start(&mut delay).unwrap();
+            delay.done();
+            done(epd);
+        }
+    }
+
+    #[test]
+    fn test_refresh_wait() {
+        let mut expected = Expected::default();
+        expected
+            .busy_for(2)
+            .cmd_with_data(Command::POF, &[0x00])
+            .busy_for(1);
+        let mut epd = expected.driver(RefreshMode::Standard);
+        let mut delay = CheckedDelay::new(&alloc::vec![DelayTransaction::delay_ms(10); 3]);
+        epd.refresh_wait(&mut delay).unwrap();
+        delay.done();
+        done(epd);
+    }
+
+    #[test]
+    fn test_partial_update_start() {
+        let buffer = [0x12, 0x34, 0x56, 0x78];
+        for mode in [RefreshMode::Standard, RefreshMode::Fast] {
+            let mut expected = Expected::default();
+            expected
+                .partial_window([0x00, 0x00, 0x00, 0x03, 0x00, 0x0A, 0x00, 0x0B])
+                .busy_for(0)
+                .command(Command::DTM)
+                .write(State::High, &buffer)
+                .busy_for(0)
+                .refresh_start(mode);
+            let mut epd = expected.driver(mode);
+            epd.partial_update_start(&Rect::new(0, 10, 4, 2), &buffer, &mut NoopDelay::new())
+                .unwrap();
+            done(epd);
+        }
+    }
+
+    #[test]
+    fn test_busy_interlock() {
+        let buffer = [0x12, 0x34, 0x56, 0x78];
+        let rect = Rect::new(0, 10, 4, 2);
+        let mut expected = Expected::default();
+        expected
+            .command(Command::DTM)
+            .write(State::High, &buffer)
+            .refresh_start(RefreshMode::Standard)
+            // Still refreshing when first polled by finish_display
+            .busy_for(1)
+            .busy_for(0)
+            .cmd_with_data(Command::POF, &[0x00])
+            .busy_for(0)
+            .partial_window([0x00, 0x00, 0x00, 0x03, 0x00, 0x0A, 0x00, 0x0B]);
+        let mut epd = expected.driver(RefreshMode::Standard);
+        let mut delay = NoopDelay::new();
+        epd.display_start(&buffer, &mut delay).unwrap();
+
+        // Nothing reaches the controller until the refresh is finished
+        assert_eq!(
+            epd.partial_update_start(&rect, &buffer, &mut delay),
+            Err(EpdError::Busy)
+        );
+        assert_eq!(epd.begin_frame(), Err(EpdError::Busy));
+        assert_eq!(epd.sleep(&mut delay), Err(EpdError::Busy));
+        assert_eq!(epd.finish_display(&mut delay), Err(EpdError::Busy));
+        assert!(!epd.is_busy());
+        epd.finish_display(&mut delay).unwrap();
+
+        // Only the window is checked here
+        epd.set_partial_window(&rect).unwrap();
+        done(epd);
+    }
+}
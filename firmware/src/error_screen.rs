@@ -0,0 +1,238 @@
+//! Error screen and retry backoff for wakes that can't show anything
+//!
+//! Error codes and their headlines, the diagnostics drawn under them and the
+//! [`ErrorState`] kept in RTC memory across failed wakes. The fetch errors
+//! map onto these codes in [`DisplayError::code`](crate::display::DisplayError::code);
+//! nothing here touches the network, so it is also tested on the host (see
+//! `host-tests`).
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::framebuffer::Framebuffer;
+use crate::text;
+use crate::widget::Orientation;
+
+/// Sleep before retrying after the first failed wake, doubling with each one after
+const ERROR_RETRY_BASE_SECS: u64 = 30;
+/// Longest sleep between retries while wakes keep failing
+const ERROR_RETRY_MAX_SECS: u64 = 60 * 60;
+/// Magic number to validate [`ErrorState`] in RTC memory
+const ERROR_STATE_MAGIC: u32 = 0x4552_5253;
+
+/// Error code of a failed WiFi connection
+pub const WIFI_ERROR_CODE: u16 = 101;
+
+/// Headline of the error screen for an error code
+pub fn error_title(code: u16) -> &'static str {
+    match code {
+        WIFI_ERROR_CODE => "WiFi connection failed",
+        102 => "Server unreachable",
+        103 => "Bad widget data",
+        104 => "Nothing to show",
+        105 => "Image failed",
+        106 => "Display error",
+        400..=499 => "Request rejected",
+        500..=599 => "Server error",
+        _ => "Error",
+    }
+}
+
+/// Host of a URL, without scheme, credentials, port or path
+pub fn url_host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    match host.find(']') {
+        // IPv6 literal, e.g. `[fd00::1]:3000`
+        Some(end) if host.starts_with('[') => &host[..=end],
+        _ => host.split(':').next().unwrap_or(host),
+    }
+}
+
+/// What the error screen reports besides the error
+pub struct Diagnostics<'a> {
+    pub ssid: &'a str,
+    pub server_url: &'a str,
+    /// Battery percentage, if it could be read
+    pub battery_percent: Option<u8>,
+    pub sd_card: bool,
+}
+
+/// Draw the error screen: what went wrong and its code, the network and server
+/// the frame is using, and its battery level
+pub fn draw_error_screen(
+    framebuffer: &mut Framebuffer,
+    orientation: Orientation,
+    code: u16,
+    diagnostics: &Diagnostics,
+) {
+    let mut error: String<16> = String::new();
+    let _ = write!(error, "Error {}", code);
+    let mut wifi: String<64> = String::new();
+    let _ = write!(wifi, "WiFi: {}", diagnostics.ssid);
+    let mut server: String<128> = String::new();
+    let _ = write!(server, "Server: {}", url_host(diagnostics.server_url));
+    let mut battery: String<16> = String::new();
+    match diagnostics.battery_percent {
+        Some(percent) => write!(battery, "Battery: {}%", percent),
+        None => write!(battery, "Battery: unknown"),
+    }
+    .ok();
+
+    let mut details: heapless::Vec<&str, 5> = heapless::Vec::new();
+    let _ = details.extend_from_slice(&[
+        error.as_str(),
+        wifi.as_str(),
+        server.as_str(),
+        battery.as_str(),
+    ]);
+    if !diagnostics.sd_card {
+        let _ = details.push("No SD card");
+    }
+    text::draw_lines(framebuffer, orientation, error_title(code), &details);
+}
+
+/// Failed wakes in a row, kept in RTC memory so retries back off
+///
+/// A wake fails when it can't show any items. The first failure puts the
+/// error screen on the panel, and so does a failure with a different code;
+/// repeats leave the screen as it is rather than refreshing the panel on every
+/// retry. The next wake that shows items clears the state.
+#[repr(C)]
+pub struct ErrorState {
+    magic: u32,
+    /// Failed wakes in a row
+    failures: u16,
+    /// Code on the error screen the panel shows (0 if none)
+    shown_code: u16,
+}
+
+impl Default for ErrorState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ErrorState {
+    pub const fn new() -> Self {
+        Self {
+            magic: 0,
+            failures: 0,
+            shown_code: 0,
+        }
+    }
+
+    /// Forget state that wasn't recorded (e.g. garbage after power loss)
+    pub fn validate(&mut self) {
+        if self.magic != ERROR_STATE_MAGIC {
+            *self = Self::new();
+        }
+    }
+
+    /// Record a failed wake, returning whether the error screen should be drawn
+    pub fn fail(&mut self, code: u16) -> bool {
+        self.magic = ERROR_STATE_MAGIC;
+        self.failures = self.failures.saturating_add(1);
+        self.shown_code != code
+    }
+
+    /// Record that the error screen for `code` is on the panel
+    pub fn set_shown(&mut self, code: u16) {
+        self.shown_code = code;
+    }
+
+    /// Record a wake that showed items, returning the failures it ends
+    pub fn succeed(&mut self) -> u16 {
+        let failures = self.failures;
+        *self = Self::new();
+        failures
+    }
+
+    /// Failed wakes in a row
+    pub fn failures(&self) -> u16 {
+        self.failures
+    }
+
+    /// Whether the panel shows the error screen, so no half of it can be
+    /// swapped on its own
+    pub fn screen_shown(&self) -> bool {
+        self.shown_code != 0
+    }
+
+    /// Sleep before the next retry: `ERROR_RETRY_BASE_SECS` after the first
+    /// failure, doubling up to `ERROR_RETRY_MAX_SECS`, and never longer than
+    /// the usual `interval_secs`
+    pub fn retry_secs(&self, interval_secs: u64) -> u64 {
+        if self.failures == 0 {
+            return interval_secs;
+        }
+        let doublings = u32::from(self.failures - 1).min(16);
+        let backoff = (ERROR_RETRY_BASE_SECS << doublings).min(ERROR_RETRY_MAX_SECS);
+        interval_secs.min(backoff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_host() {
+        assert_eq!(url_host("https://frame.example.com"), "frame.example.com");
+        assert_eq!(url_host("http://192.168.1.10:3000/"), "192.168.1.10");
+        assert_eq!(
+            url_host("https://user:pw@example.com:8443/api?x=1"),
+            "example.com"
+        );
+        assert_eq!(url_host("http://[fd00::1]:3000"), "[fd00::1]");
+        assert_eq!(url_host("example.com/frame"), "example.com");
+    }
+
+    #[test]
+    fn test_error_titles() {
+        assert_eq!(error_title(WIFI_ERROR_CODE), "WiFi connection failed");
+        assert_eq!(error_title(102), "Server unreachable");
+        assert_eq!(error_title(404), "Request rejected");
+        assert_eq!(error_title(503), "Server error");
+        assert_eq!(error_title(999), "Error");
+    }
+
+    #[test]
+    fn test_error_state() {
+        let mut state = ErrorState::new();
+        assert_eq!(state.retry_secs(900), 900);
+
+        // The screen is drawn for the first failure and for a new error only
+        assert!(state.fail(102));
+        state.set_shown(102);
+        assert!(!state.fail(102));
+        assert!(state.fail(503));
+        state.set_shown(503);
+        assert_eq!(state.failures(), 3);
+        assert!(state.screen_shown());
+
+        // Backs off from 30s, capped at an hour and the usual interval
+        let delays: heapless::Vec<u64, 10> = (1..=9)
+            .map(|failures| {
+                state.failures = failures;
+                state.retry_secs(u64::MAX)
+            })
+            .collect();
+        assert_eq!(
+            delays.as_slice(),
+            [30, 60, 120, 240, 480, 960, 1920, 3600, 3600]
+        );
+        state.failures = u16::MAX;
+        assert_eq!(state.retry_secs(3600), 3600);
+        assert_eq!(state.retry_secs(900), 900);
+
+        assert_eq!(state.succeed(), u16::MAX);
+        assert_eq!(state.failures(), 0);
+        assert!(!state.screen_shown());
+        assert!(state.fail(503));
+    }
+}
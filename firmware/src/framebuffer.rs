@@ -5,11 +5,27 @@
 //!
 //! The framebuffer is allocated dynamically from PSRAM to avoid exhausting internal SRAM.
 
-use crate::epd::{BUFFER_SIZE, Color, HEIGHT, WIDTH};
+use crate::epd::{BUFFER_SIZE, Color, HEIGHT, Rect, WIDTH};
 use alloc::boxed::Box;
 
 extern crate alloc;
 
+/// Bytes per framebuffer row (two pixels per byte)
+const ROW_BYTES: usize = WIDTH as usize / 2;
+
+/// Side length of a change-detection tile in pixels
+const TILE_SIZE: u32 = 80;
+
+/// Tiles per row and column
+const TILE_COLS: usize = (WIDTH / TILE_SIZE) as usize;
+const TILE_ROWS: usize = (HEIGHT / TILE_SIZE) as usize;
+
+/// Number of change-detection tiles covering the display
+pub const TILE_COUNT: usize = TILE_COLS * TILE_ROWS;
+
+/// Per-tile content hashes, row-major, used to find the region that changed between frames
+pub type TileHashes = [u32; TILE_COUNT];
+
 /// Color index remapping table: PNG palette index -> EPD 4-bit value
 /// PNG: 0=Black, 1=White, 2=Red, 3=Yellow, 4=Blue, 5=Green
 /// EPD: 0=Black, 1=White, 2=Yellow, 3=Red, 5=Blue, 6=Green
@@ -73,24 +89,6 @@ impl Framebuffer {
         }
     }
 
-    /// Write a single pixel at (x, y) with a PNG palette index (0-5)
-    #[inline]
-    pub fn set_pixel_indexed(&mut self, x: u32, y: u32, palette_idx: u8) {
-        if x >= WIDTH || y >= HEIGHT {
-            return;
-        }
-
-        let color = remap_color(palette_idx);
-        let byte_idx = (y as usize * (WIDTH as usize / 2)) + (x as usize / 2);
-        let is_high_nibble = x.is_multiple_of(2);
-
-        if is_high_nibble {
-            self.buffer[byte_idx] = (self.buffer[byte_idx] & 0x0F) | (color << 4);
-        } else {
-            self.buffer[byte_idx] = (self.buffer[byte_idx] & 0xF0) | color;
-        }
-    }
-
     /// Write a row of pixels from PNG palette indices
     ///
     /// - `x_offset`: Starting x position (0 for left half, 400 for right half)
@@ -130,6 +128,28 @@ impl Framebuffer {
         }
     }
 
+    /// Copy rows of packed EPD color codes (two pixels per byte) into the buffer
+    ///
+    /// - `x_offset`: Starting x position (0 for left half, 400 for right half)
+    /// - `width`: Width of each row in pixels
+    /// - `data`: `width / 2` bytes per row, from the top row down
+    pub fn write_packed_rows(&mut self, x_offset: u32, width: u32, data: &[u8]) {
+        let row_bytes = width as usize / 2;
+        let byte_offset = x_offset as usize / 2;
+        if row_bytes == 0 || byte_offset + row_bytes > WIDTH as usize / 2 {
+            return;
+        }
+
+        for (y, row) in data
+            .chunks_exact(row_bytes)
+            .take(HEIGHT as usize)
+            .enumerate()
+        {
+            let start = y * (WIDTH as usize / 2) + byte_offset;
+            self.buffer[start..start + row_bytes].copy_from_slice(row);
+        }
+    }
+
     /// Fill a rectangular region with a color
     pub fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: Color) {
         for row in y..(y + height).min(HEIGHT) {
@@ -157,20 +177,75 @@ impl Framebuffer {
     /// - `slot`: 0 for left half (x 0-399), 1 for right half (x 400-799)
     /// - `output`: Buffer to write the half-framebuffer data into (must be 96000 bytes)
     pub fn extract_half(&self, slot: u8, output: &mut [u8]) {
-        const HALF_WIDTH_BYTES: usize = 200; // 400 pixels / 2 pixels per byte
-        const ROW_BYTES: usize = 400; // 800 pixels / 2 pixels per byte
+        let x = if slot == 0 { 0 } else { WIDTH as u16 / 2 };
+        self.extract_rect(&Rect::new(x, 0, WIDTH as u16 / 2, HEIGHT as u16), output);
+    }
 
-        debug_assert!(output.len() >= HALF_WIDTH_BYTES * HEIGHT as usize);
+    /// Extract a rectangular region for partial update.
+    ///
+    /// - `rect`: Region to copy (x and width are even, so rows are byte aligned)
+    /// - `output`: Buffer to write the region into (must be `rect.buffer_size()` bytes)
+    pub fn extract_rect(&self, rect: &Rect, output: &mut [u8]) {
+        debug_assert!(rect.is_valid());
+        debug_assert!(output.len() >= rect.buffer_size());
 
-        let x_byte_offset = if slot == 0 { 0 } else { HALF_WIDTH_BYTES };
+        let x_byte_offset = rect.x as usize / 2;
+        let width_bytes = rect.width as usize / 2;
 
-        for y in 0..HEIGHT as usize {
+        for (row, y) in (rect.y as usize..(rect.y + rect.height) as usize).enumerate() {
             let src_start = y * ROW_BYTES + x_byte_offset;
-            let dst_start = y * HALF_WIDTH_BYTES;
-            output[dst_start..dst_start + HALF_WIDTH_BYTES]
-                .copy_from_slice(&self.buffer[src_start..src_start + HALF_WIDTH_BYTES]);
+            let dst_start = row * width_bytes;
+            output[dst_start..dst_start + width_bytes]
+                .copy_from_slice(&self.buffer[src_start..src_start + width_bytes]);
         }
     }
+
+    /// Hash each tile of the framebuffer (FNV-1a) for change detection
+    pub fn tile_hashes(&self) -> TileHashes {
+        const TILE_BYTES: usize = TILE_SIZE as usize / 2;
+
+        let mut hashes = [0x811C_9DC5u32; TILE_COUNT];
+        for (y, row) in self.buffer.chunks_exact(ROW_BYTES).enumerate() {
+            let tile_row = y / TILE_SIZE as usize;
+            for (tile_col, bytes) in row.chunks_exact(TILE_BYTES).enumerate() {
+                let hash = &mut hashes[tile_row * TILE_COLS + tile_col];
+                for &byte in bytes {
+                    *hash = (*hash ^ byte as u32).wrapping_mul(0x0100_0193);
+                }
+            }
+        }
+        hashes
+    }
+}
+
+/// Bounding rectangle of all tiles that differ between two frames
+///
+/// Returns `None` when the frames are identical.
+pub fn changed_region(previous: &TileHashes, current: &TileHashes) -> Option<Rect> {
+    let mut bounds: Option<(usize, usize, usize, usize)> = None;
+
+    for (i, _) in previous
+        .iter()
+        .zip(current.iter())
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+    {
+        let (col, row) = (i % TILE_COLS, i / TILE_COLS);
+        bounds = Some(match bounds {
+            None => (col, row, col, row),
+            Some((x0, y0, x1, y1)) => (x0.min(col), y0.min(row), x1.max(col), y1.max(row)),
+        });
+    }
+
+    bounds.map(|(x0, y0, x1, y1)| {
+        let tile = TILE_SIZE as u16;
+        Rect::new(
+            x0 as u16 * tile,
+            y0 as u16 * tile,
+            (x1 - x0 + 1) as u16 * tile,
+            (y1 - y0 + 1) as u16 * tile,
+        )
+    })
 }
 
 impl Default for Framebuffer {
@@ -178,3 +253,51 @@ impl Default for Framebuffer {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_rect() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.fill_rect(10, 20, 4, 2, Color::Red);
+
+        let rect = Rect::new(10, 20, 4, 2);
+        let mut output = [0u8; 4];
+        framebuffer.extract_rect(&rect, &mut output);
+        assert_eq!(output, [Color::Red.to_dual_pixel(); 4]);
+
+        let mut half = alloc::vec![0u8; BUFFER_SIZE / 2];
+        framebuffer.extract_half(1, &mut half);
+        assert!(half.iter().all(|&b| b == Color::White.to_dual_pixel()));
+    }
+
+    #[test]
+    fn test_write_packed_rows() {
+        let mut framebuffer = Framebuffer::new();
+        let red = Color::Red.to_dual_pixel();
+        let half = alloc::vec![red; BUFFER_SIZE / 2];
+        framebuffer.write_packed_rows(400, 400, &half);
+
+        let mut output = alloc::vec![0u8; BUFFER_SIZE / 2];
+        framebuffer.extract_half(1, &mut output);
+        assert_eq!(output, half);
+        framebuffer.extract_half(0, &mut output);
+        assert!(output.iter().all(|&b| b == Color::White.to_dual_pixel()));
+    }
+
+    #[test]
+    fn test_changed_region() {
+        let mut framebuffer = Framebuffer::new();
+        let before = framebuffer.tile_hashes();
+        assert_eq!(changed_region(&before, &before), None);
+
+        // Two pixels in different tiles: region spans both tiles
+        framebuffer.set_pixel(730, 10, Color::Black);
+        framebuffer.set_pixel(650, 170, Color::Black);
+        let region = changed_region(&before, &framebuffer.tile_hashes()).unwrap();
+        assert_eq!(region, Rect::new(640, 0, 160, 240));
+        assert!(region.is_valid());
+    }
+}
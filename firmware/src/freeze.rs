@@ -0,0 +1,123 @@
+//! Freeze mode
+//!
+//! A double press of the button freezes the frame on what it's showing, for
+//! when guests ask about a show and it should stay up. Timer wakes then leave
+//! the panel alone, except once a week, when it's cleared and the same items
+//! redrawn so they don't ghost in. A single press still moves on to the next
+//! item (and the freeze with it); another double press ends the freeze and the
+//! rotation carries on.
+//!
+//! [`Freeze`] lives in `SleepState` across deep sleep. The frozen item's path
+//! is also kept in the settings store (SD card or flash), so a frame that lost
+//! power comes back frozen on it, found by path even if the items changed.
+
+use heapless::String;
+
+use crate::widget::MAX_PATH_LEN;
+
+/// Time between deghosting redraws while frozen (1 week)
+pub const DEGHOST_INTERVAL_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Shortest sleep before a deghosting redraw
+const MIN_FROZEN_SLEEP_SECS: u64 = 60;
+
+/// What a timer wake of a frozen frame should do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrozenWake {
+    /// Leave the panel alone and sleep this long
+    Sleep(u64),
+    /// Clear the panel and redraw the frozen items
+    Deghost,
+}
+
+/// A frame frozen on an item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Freeze {
+    /// Path of the first item on the panel
+    pub item: String<MAX_PATH_LEN>,
+    /// RTC time (us) the panel was last redrawn (0 if unknown)
+    pub redrawn_at: u64,
+}
+
+impl Freeze {
+    /// Freeze on `item`, redrawn at an unknown time
+    ///
+    /// `None` if the path is too long to keep.
+    pub fn new(item: &str) -> Option<Self> {
+        Some(Self {
+            item: String::try_from(item).ok()?,
+            redrawn_at: 0,
+        })
+    }
+
+    /// Decide what a timer wake at RTC time `now_us` does
+    ///
+    /// A freeze that was never redrawn (after a power cycle) redraws right
+    /// away, as does one whose RTC time is ahead of the clock.
+    pub fn wake(&self, now_us: u64) -> FrozenWake {
+        if self.redrawn_at == 0 || self.redrawn_at > now_us {
+            return FrozenWake::Deghost;
+        }
+        let age_secs = (now_us - self.redrawn_at) / 1_000_000;
+        match DEGHOST_INTERVAL_SECS.checked_sub(age_secs) {
+            Some(remaining) if remaining > 0 => {
+                FrozenWake::Sleep(remaining.max(MIN_FROZEN_SLEEP_SECS))
+            }
+            _ => FrozenWake::Deghost,
+        }
+    }
+}
+
+/// Parse a stored frozen item path (empty when not frozen)
+pub fn parse_frozen_item(content: &[u8]) -> Option<Freeze> {
+    let path = core::str::from_utf8(content).ok()?.trim();
+    if path.is_empty() {
+        return None;
+    }
+    Freeze::new(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECS: u64 = 1_000_000;
+
+    #[test]
+    fn test_wake() {
+        let mut freeze = Freeze::new("2024-06-15-band").unwrap();
+        assert_eq!(freeze.wake(5 * SECS), FrozenWake::Deghost);
+
+        freeze.redrawn_at = 10 * SECS;
+        assert_eq!(
+            freeze.wake(10 * SECS),
+            FrozenWake::Sleep(DEGHOST_INTERVAL_SECS)
+        );
+        assert_eq!(
+            freeze.wake((10 + 3600) * SECS),
+            FrozenWake::Sleep(DEGHOST_INTERVAL_SECS - 3600)
+        );
+        // Waking just short of the week doesn't sleep a few seconds
+        assert_eq!(
+            freeze.wake((10 + DEGHOST_INTERVAL_SECS - 5) * SECS),
+            FrozenWake::Sleep(MIN_FROZEN_SLEEP_SECS)
+        );
+        assert_eq!(
+            freeze.wake((10 + DEGHOST_INTERVAL_SECS) * SECS),
+            FrozenWake::Deghost
+        );
+        // RTC time went backwards
+        assert_eq!(freeze.wake(SECS), FrozenWake::Deghost);
+    }
+
+    #[test]
+    fn test_parse_frozen_item() {
+        let freeze = parse_frozen_item(b"2024-06-15-band\n").unwrap();
+        assert_eq!(freeze.item.as_str(), "2024-06-15-band");
+        assert_eq!(freeze.redrawn_at, 0);
+
+        assert_eq!(parse_frozen_item(b""), None);
+        assert_eq!(parse_frozen_item(&[0xff, 0xfe]), None);
+        assert_eq!(parse_frozen_item(&[b'a'; MAX_PATH_LEN + 1]), None);
+    }
+}
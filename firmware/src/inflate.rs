@@ -0,0 +1,629 @@
+//! Incremental inflater for zlib and gzip streams
+//!
+//! Takes compressed input in chunks of any size, so bodies can be decoded as
+//! they arrive from the network: PNG image data (zlib, see [`crate::png`]) and
+//! gzip-encoded HTTP responses. It needs a 32KB back-reference window and about
+//! 1.5KB of Huffman tables; Huffman codes are decoded a bit at a time, which is
+//! slower than table lookups but small.
+
+use alloc::boxed::Box;
+
+use crate::screenshot::{Adler32, Crc32};
+
+/// Deflate back-reference window (the maximum distance is 32768)
+const WINDOW_SIZE: usize = 32 * 1024;
+
+/// Longest Huffman code in a deflate stream
+const MAX_CODE_BITS: usize = 15;
+
+/// Base lengths and extra bits for length symbols 257..=285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Base distances and extra bits for distance symbols 0..=29
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Order in which code length code lengths are sent in a dynamic block header
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Least-significant-bit-first bit buffer
+///
+/// Steps of the inflater work on a copy and only store it back once they have
+/// all the bits they need, so a step cut short by the end of the input is
+/// simply retried when more arrives.
+#[derive(Clone, Copy, Default)]
+struct Bits {
+    buf: u64,
+    count: u32,
+}
+
+impl Bits {
+    /// Top up from `input`, consuming whole bytes
+    fn refill(&mut self, input: &mut &[u8]) {
+        while self.count <= 56 {
+            let Some((&byte, rest)) = input.split_first() else {
+                break;
+            };
+            self.buf |= (byte as u64) << self.count;
+            self.count += 8;
+            *input = rest;
+        }
+    }
+
+    fn take(&mut self, n: u32) -> Option<u32> {
+        if self.count < n {
+            return None;
+        }
+        let value = (self.buf & ((1u64 << n) - 1)) as u32;
+        self.buf >>= n;
+        self.count -= n;
+        Some(value)
+    }
+
+    /// Drop bits up to the next byte boundary
+    fn align(&mut self) {
+        let skip = self.count % 8;
+        self.buf >>= skip;
+        self.count -= skip;
+    }
+}
+
+/// Canonical Huffman code, decoded a bit at a time
+struct Huffman<const N: usize> {
+    /// Number of codes of each length
+    counts: [u16; MAX_CODE_BITS + 1],
+    /// Symbols ordered by code
+    symbols: [u16; N],
+}
+
+impl<const N: usize> Huffman<N> {
+    const fn empty() -> Self {
+        Self {
+            counts: [0; MAX_CODE_BITS + 1],
+            symbols: [0; N],
+        }
+    }
+
+    /// Build the code from per-symbol code lengths (0 for unused symbols)
+    fn build(&mut self, lengths: &[u8]) -> Result<(), &'static str> {
+        self.counts = [0; MAX_CODE_BITS + 1];
+        for &len in lengths {
+            self.counts[len as usize] += 1;
+        }
+
+        // Incomplete codes are allowed; unassigned codes fail when decoded
+        let mut left: i32 = 1;
+        for &count in &self.counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err("over-subscribed Huffman code");
+            }
+        }
+
+        let mut offsets = [0u16; MAX_CODE_BITS + 1];
+        for len in 1..MAX_CODE_BITS {
+            offsets[len + 1] = offsets[len] + self.counts[len];
+        }
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                self.symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode one symbol, or `None` if `bits` ran out first
+    fn decode(&self, bits: &mut Bits) -> Result<Option<u16>, &'static str> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            let Some(bit) = bits.take(1) else {
+                return Ok(None);
+            };
+            code |= bit as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(Some(self.symbols[(index + code - first) as usize]));
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code")
+    }
+}
+
+/// gzip header flags (RFC 1952)
+const GZIP_FHCRC: u8 = 0x02;
+const GZIP_FEXTRA: u8 = 0x04;
+const GZIP_FNAME: u8 = 0x08;
+const GZIP_FCOMMENT: u8 = 0x10;
+
+/// Container around the deflate data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// zlib (RFC 1950), as in PNG image data, checked with Adler-32
+    Zlib,
+    /// gzip (RFC 1952), as in `Content-Encoding: gzip`, checked with CRC-32
+    Gzip,
+}
+
+/// Position in the stream
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum InflateState {
+    ZlibHeader,
+    GzipHeader,
+    /// Fixed-size header fields being skipped
+    GzipSkip {
+        remaining: u16,
+    },
+    GzipExtraLength,
+    /// Zero-terminated file name or comment
+    GzipString,
+    BlockHeader,
+    StoredHeader,
+    Stored {
+        remaining: u16,
+    },
+    DynamicHeader,
+    CodeLengthCodes {
+        index: usize,
+    },
+    CodeLengths {
+        index: usize,
+    },
+    Compressed,
+    Checksum,
+    GzipSize,
+    Done,
+}
+
+/// Running checksum of the output
+enum Check {
+    Adler(Adler32),
+    Crc(Crc32),
+}
+
+/// Whether a step ran or is waiting on input
+enum Step {
+    Progress,
+    NeedInput,
+}
+
+/// Incremental zlib or gzip inflater
+pub struct Inflater {
+    state: InflateState,
+    /// gzip header fields still to skip
+    gzip_flags: u8,
+    bits: Bits,
+    last_block: bool,
+    /// Literal/length and distance code counts of the current dynamic block
+    lit_count: usize,
+    dist_count: usize,
+    code_length_count: usize,
+    lengths: [u8; 286 + 30],
+    code_lengths: Huffman<19>,
+    lit: Huffman<288>,
+    dist: Huffman<30>,
+    window: Box<[u8; WINDOW_SIZE]>,
+    /// Total bytes output so far (the window position is this modulo its size)
+    written: usize,
+    check: Check,
+}
+
+impl Inflater {
+    pub fn new(format: Format) -> Self {
+        let (state, check) = match format {
+            Format::Zlib => (InflateState::ZlibHeader, Check::Adler(Adler32::new())),
+            Format::Gzip => (InflateState::GzipHeader, Check::Crc(Crc32::new())),
+        };
+        Self {
+            state,
+            gzip_flags: 0,
+            bits: Bits::default(),
+            last_block: false,
+            lit_count: 0,
+            dist_count: 0,
+            code_length_count: 0,
+            lengths: [0; 286 + 30],
+            code_lengths: Huffman::empty(),
+            lit: Huffman::empty(),
+            dist: Huffman::empty(),
+            window: Box::new([0u8; WINDOW_SIZE]),
+            written: 0,
+            check,
+        }
+    }
+
+    /// Whether the whole stream, checksum included, has been decoded
+    pub fn is_done(&self) -> bool {
+        self.state == InflateState::Done
+    }
+
+    /// Inflate as much of `input` as possible, passing output to `out`
+    ///
+    /// Input after the end of the stream is ignored.
+    pub fn push(
+        &mut self,
+        mut input: &[u8],
+        out: &mut impl FnMut(&[u8]) -> Result<(), &'static str>,
+    ) -> Result<(), &'static str> {
+        loop {
+            self.bits.refill(&mut input);
+            match self.step(out)? {
+                Step::Progress => {}
+                Step::NeedInput if input.is_empty() => return Ok(()),
+                Step::NeedInput => {}
+            }
+            if self.is_done() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Record output in the window and checksum, and pass it on
+    fn emit(
+        &mut self,
+        data: &[u8],
+        out: &mut impl FnMut(&[u8]) -> Result<(), &'static str>,
+    ) -> Result<(), &'static str> {
+        for &byte in data {
+            self.window[self.written % WINDOW_SIZE] = byte;
+            self.written += 1;
+        }
+        match &mut self.check {
+            Check::Adler(adler) => adler.update(data),
+            Check::Crc(crc) => crc.update(data),
+        }
+        out(data)
+    }
+
+    fn step(
+        &mut self,
+        out: &mut impl FnMut(&[u8]) -> Result<(), &'static str>,
+    ) -> Result<Step, &'static str> {
+        let mut bits = self.bits;
+        let next = match self.state {
+            InflateState::ZlibHeader => {
+                let Some(header) = bits.take(16) else {
+                    return Ok(Step::NeedInput);
+                };
+                let (cmf, flg) = (header & 0xFF, header >> 8);
+                if cmf & 0x0F != 8 || cmf >> 4 > 7 || ((cmf << 8) | flg) % 31 != 0 {
+                    return Err("invalid zlib header");
+                }
+                if flg & 0x20 != 0 {
+                    return Err("zlib preset dictionary");
+                }
+                InflateState::BlockHeader
+            }
+            InflateState::GzipHeader => {
+                let Some(header) = bits.take(32) else {
+                    return Ok(Step::NeedInput);
+                };
+                let [id1, id2, method, flags] = header.to_le_bytes();
+                if id1 != 0x1F || id2 != 0x8B || method != 8 {
+                    return Err("invalid gzip header");
+                }
+                self.gzip_flags = flags;
+                // Modification time, extra flags and OS
+                InflateState::GzipSkip { remaining: 6 }
+            }
+            InflateState::GzipSkip { remaining: 0 } => self.next_gzip_field(),
+            InflateState::GzipSkip { remaining } => {
+                let n = (bits.count / 8).min(remaining as u32);
+                if n == 0 {
+                    return Ok(Step::NeedInput);
+                }
+                for _ in 0..n {
+                    bits.take(8);
+                }
+                InflateState::GzipSkip {
+                    remaining: remaining - n as u16,
+                }
+            }
+            InflateState::GzipExtraLength => {
+                let Some(len) = bits.take(16) else {
+                    return Ok(Step::NeedInput);
+                };
+                InflateState::GzipSkip {
+                    remaining: len as u16,
+                }
+            }
+            InflateState::GzipString => {
+                let Some(byte) = bits.take(8) else {
+                    return Ok(Step::NeedInput);
+                };
+                if byte == 0 {
+                    self.next_gzip_field()
+                } else {
+                    InflateState::GzipString
+                }
+            }
+            InflateState::BlockHeader => {
+                let Some(header) = bits.take(3) else {
+                    return Ok(Step::NeedInput);
+                };
+                self.last_block = header & 1 != 0;
+                match header >> 1 {
+                    0 => InflateState::StoredHeader,
+                    1 => {
+                        let mut lengths = [0u8; 288];
+                        lengths[..144].fill(8);
+                        lengths[144..256].fill(9);
+                        lengths[256..280].fill(7);
+                        lengths[280..].fill(8);
+                        self.lit.build(&lengths)?;
+                        self.dist.build(&[5; 30])?;
+                        InflateState::Compressed
+                    }
+                    2 => InflateState::DynamicHeader,
+                    _ => return Err("invalid deflate block type"),
+                }
+            }
+            InflateState::StoredHeader => {
+                bits.align();
+                let Some(len) = bits.take(16) else {
+                    return Ok(Step::NeedInput);
+                };
+                let Some(nlen) = bits.take(16) else {
+                    return Ok(Step::NeedInput);
+                };
+                if len != !nlen & 0xFFFF {
+                    return Err("stored block length mismatch");
+                }
+                InflateState::Stored {
+                    remaining: len as u16,
+                }
+            }
+            InflateState::Stored { remaining: 0 } => self.end_of_block(),
+            InflateState::Stored { remaining } => {
+                let mut chunk = [0u8; 8];
+                let n = (bits.count as usize / 8).min(remaining as usize);
+                if n == 0 {
+                    return Ok(Step::NeedInput);
+                }
+                for byte in &mut chunk[..n] {
+                    *byte = bits.take(8).unwrap_or_default() as u8;
+                }
+                self.bits = bits;
+                self.emit(&chunk[..n], out)?;
+                self.state = InflateState::Stored {
+                    remaining: remaining - n as u16,
+                };
+                return Ok(Step::Progress);
+            }
+            InflateState::DynamicHeader => {
+                let Some(header) = bits.take(14) else {
+                    return Ok(Step::NeedInput);
+                };
+                self.lit_count = (header & 0x1F) as usize + 257;
+                self.dist_count = ((header >> 5) & 0x1F) as usize + 1;
+                self.code_length_count = (header >> 10) as usize + 4;
+                if self.lit_count > 286 || self.dist_count > 30 {
+                    return Err("too many length or distance codes");
+                }
+                self.lengths[..19].fill(0);
+                InflateState::CodeLengthCodes { index: 0 }
+            }
+            InflateState::CodeLengthCodes { index } => {
+                let Some(len) = bits.take(3) else {
+                    return Ok(Step::NeedInput);
+                };
+                self.lengths[CODE_LENGTH_ORDER[index]] = len as u8;
+                if index + 1 < self.code_length_count {
+                    InflateState::CodeLengthCodes { index: index + 1 }
+                } else {
+                    self.code_lengths.build(&self.lengths[..19])?;
+                    InflateState::CodeLengths { index: 0 }
+                }
+            }
+            InflateState::CodeLengths { index } => {
+                let total = self.lit_count + self.dist_count;
+                let Some(symbol) = self.code_lengths.decode(&mut bits)? else {
+                    return Ok(Step::NeedInput);
+                };
+                let (value, repeat) = match symbol {
+                    0..=15 => (symbol as u8, 1),
+                    16 => {
+                        if index == 0 {
+                            return Err("repeat with no previous length");
+                        }
+                        let Some(extra) = bits.take(2) else {
+                            return Ok(Step::NeedInput);
+                        };
+                        (self.lengths[index - 1], 3 + extra as usize)
+                    }
+                    17 => {
+                        let Some(extra) = bits.take(3) else {
+                            return Ok(Step::NeedInput);
+                        };
+                        (0, 3 + extra as usize)
+                    }
+                    _ => {
+                        let Some(extra) = bits.take(7) else {
+                            return Ok(Step::NeedInput);
+                        };
+                        (0, 11 + extra as usize)
+                    }
+                };
+                if index + repeat > total {
+                    return Err("too many code lengths");
+                }
+                self.lengths[index..index + repeat].fill(value);
+                let index = index + repeat;
+                if index < total {
+                    InflateState::CodeLengths { index }
+                } else {
+                    if self.lengths[256] == 0 {
+                        return Err("missing end-of-block code");
+                    }
+                    let (lit, dist) = self.lengths[..total].split_at(self.lit_count);
+                    self.lit.build(lit)?;
+                    self.dist.build(dist)?;
+                    InflateState::Compressed
+                }
+            }
+            InflateState::Compressed => {
+                let Some(symbol) = self.lit.decode(&mut bits)? else {
+                    return Ok(Step::NeedInput);
+                };
+                match symbol {
+                    0..=255 => {
+                        self.bits = bits;
+                        self.emit(&[symbol as u8], out)?;
+                        return Ok(Step::Progress);
+                    }
+                    256 => self.end_of_block(),
+                    257..=285 => {
+                        let i = symbol as usize - 257;
+                        let Some(extra) = bits.take(LENGTH_EXTRA[i] as u32) else {
+                            return Ok(Step::NeedInput);
+                        };
+                        let len = LENGTH_BASE[i] as usize + extra as usize;
+                        let Some(symbol) = self.dist.decode(&mut bits)? else {
+                            return Ok(Step::NeedInput);
+                        };
+                        let i = symbol as usize;
+                        if i >= DIST_BASE.len() {
+                            return Err("invalid distance symbol");
+                        }
+                        let Some(extra) = bits.take(DIST_EXTRA[i] as u32) else {
+                            return Ok(Step::NeedInput);
+                        };
+                        let distance = DIST_BASE[i] as usize + extra as usize;
+                        if distance > self.written {
+                            return Err("distance too far back");
+                        }
+
+                        self.bits = bits;
+                        let mut copy = [0u8; 258];
+                        let start = self.written - distance;
+                        for k in 0..len {
+                            // Overlapping copies repeat what this copy just produced
+                            copy[k] = if k < distance {
+                                self.window[(start + k) % WINDOW_SIZE]
+                            } else {
+                                copy[k - distance]
+                            };
+                        }
+                        self.emit(&copy[..len], out)?;
+                        return Ok(Step::Progress);
+                    }
+                    _ => return Err("invalid length symbol"),
+                }
+            }
+            InflateState::Checksum => {
+                bits.align();
+                let Some(checksum) = bits.take(32) else {
+                    return Ok(Step::NeedInput);
+                };
+                match &self.check {
+                    // Adler-32 is stored big-endian, CRC-32 little-endian
+                    Check::Adler(adler) if checksum.swap_bytes() != adler.finish() => {
+                        return Err("zlib checksum mismatch");
+                    }
+                    Check::Adler(_) => InflateState::Done,
+                    Check::Crc(crc) if checksum != crc.finish() => {
+                        return Err("gzip checksum mismatch");
+                    }
+                    Check::Crc(_) => InflateState::GzipSize,
+                }
+            }
+            InflateState::GzipSize => {
+                let Some(size) = bits.take(32) else {
+                    return Ok(Step::NeedInput);
+                };
+                // Length modulo 2^32
+                if size != self.written as u32 {
+                    return Err("gzip length mismatch");
+                }
+                InflateState::Done
+            }
+            InflateState::Done => return Ok(Step::Progress),
+        };
+        self.bits = bits;
+        self.state = next;
+        Ok(Step::Progress)
+    }
+
+    /// Next optional gzip header field to skip, or the deflate data
+    fn next_gzip_field(&mut self) -> InflateState {
+        for (flag, state) in [
+            (GZIP_FEXTRA, InflateState::GzipExtraLength),
+            (GZIP_FNAME, InflateState::GzipString),
+            (GZIP_FCOMMENT, InflateState::GzipString),
+            (GZIP_FHCRC, InflateState::GzipSkip { remaining: 2 }),
+        ] {
+            if self.gzip_flags & flag != 0 {
+                self.gzip_flags &= !flag;
+                return state;
+            }
+        }
+        InflateState::BlockHeader
+    }
+
+    fn end_of_block(&self) -> InflateState {
+        if self.last_block {
+            InflateState::Checksum
+        } else {
+            InflateState::BlockHeader
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// `[{"path":"a"},{"path":"a"},{"path":"a"}]` gzipped with a file name
+    const GZIP_FIXTURE: [u8; 44] = [
+        0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0x77, 0x2e, 0x6a, 0x73, 0x6f,
+        0x6e, 0x00, 0x8b, 0xae, 0x56, 0x2a, 0x48, 0x2c, 0xc9, 0x50, 0xb2, 0x52, 0x4a, 0x54, 0xaa,
+        0xd5, 0xc1, 0xc9, 0x89, 0x05, 0x00, 0xaa, 0x9f, 0xac, 0x1c, 0x28, 0x00, 0x00, 0x00,
+    ];
+
+    fn inflate(input: &[u8], chunk_size: usize) -> Result<Vec<u8>, &'static str> {
+        let mut inflater = Inflater::new(Format::Gzip);
+        let mut output = Vec::new();
+        for chunk in input.chunks(chunk_size) {
+            inflater.push(chunk, &mut |data| {
+                output.extend_from_slice(data);
+                Ok(())
+            })?;
+        }
+        if !inflater.is_done() {
+            return Err("truncated");
+        }
+        Ok(output)
+    }
+
+    #[test]
+    fn test_gzip() {
+        for chunk_size in [1, 3, GZIP_FIXTURE.len()] {
+            let output = inflate(&GZIP_FIXTURE, chunk_size).unwrap();
+            assert_eq!(output, br#"[{"path":"a"},{"path":"a"},{"path":"a"}]"#);
+        }
+
+        assert_eq!(inflate(&GZIP_FIXTURE[..40], 8), Err("truncated"));
+        let mut corrupt = GZIP_FIXTURE;
+        corrupt[37] ^= 0x01;
+        assert_eq!(inflate(&corrupt, 8), Err("gzip checksum mismatch"));
+        assert_eq!(inflate(b"{\"plain\":1}", 8), Err("invalid gzip header"));
+    }
+}
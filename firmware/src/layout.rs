@@ -0,0 +1,78 @@
+//! Layouts offered by the installer preview
+//!
+//! Holding the button for two seconds at boot previews each layout with images
+//! already on the SD card, so whoever mounts the frame can pick one before
+//! leaving. A layout maps onto the orientation the frame renders in: the server
+//! only renders halves (two-up) and portrait images, and full-width items are
+//! chosen per item by the server rather than by the frame.
+
+use crate::widget::Orientation;
+
+/// A way of filling the panel, as chosen in the installer preview
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Landscape, two items side by side
+    TwoUp,
+    /// Portrait, one item filling the rotated panel
+    Vertical,
+}
+
+impl Layout {
+    /// Every layout, in preview order
+    pub const ALL: [Layout; 2] = [Layout::TwoUp, Layout::Vertical];
+
+    /// Layout the frame currently renders in the given orientation
+    pub fn from_orientation(orientation: Orientation) -> Self {
+        match orientation {
+            Orientation::Horizontal => Layout::TwoUp,
+            Orientation::Vertical => Layout::Vertical,
+        }
+    }
+
+    /// Orientation images are fetched, cached and drawn in for this layout
+    pub fn orientation(&self) -> Orientation {
+        match self {
+            Layout::TwoUp => Orientation::Horizontal,
+            Layout::Vertical => Orientation::Vertical,
+        }
+    }
+
+    /// Next layout in preview order, wrapping around
+    pub fn next(&self) -> Self {
+        let position = Self::ALL.iter().position(|l| l == self).unwrap_or(0);
+        Self::ALL[(position + 1) % Self::ALL.len()]
+    }
+
+    /// Number of items on the panel at once
+    pub fn items_shown(&self) -> usize {
+        match self {
+            Layout::TwoUp => 2,
+            Layout::Vertical => 1,
+        }
+    }
+
+    /// Label drawn over the preview
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layout::TwoUp => "Two-up",
+            Layout::Vertical => "Vertical",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_cycle() {
+        let start = Layout::from_orientation(Orientation::Horizontal);
+        assert_eq!(start, Layout::TwoUp);
+        assert_eq!(start.next(), Layout::Vertical);
+        assert_eq!(start.next().next(), start);
+
+        for layout in Layout::ALL {
+            assert_eq!(Layout::from_orientation(layout.orientation()), layout);
+        }
+    }
+}
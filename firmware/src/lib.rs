@@ -2,12 +2,60 @@
 
 extern crate alloc;
 
+pub mod axp2101;
 pub mod battery;
 pub mod cache;
+pub mod cache_layout;
+pub mod cache_policy;
+pub mod clock;
+pub mod config;
+#[cfg(feature = "demo")]
+pub mod demo;
 pub mod display;
+pub mod dns_cache;
 pub mod epd;
+pub mod error_screen;
 pub mod framebuffer;
+pub mod freeze;
+pub mod inflate;
+pub mod layout;
+pub mod log_ring;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod nvs;
+pub mod pin_state;
+pub mod png;
+pub mod power;
+pub mod provision;
+pub mod refresh_timing;
+pub mod render;
+pub mod resume;
+pub mod screenshot;
+pub mod shtc3;
+pub mod telemetry;
+pub mod text;
+pub mod thermal;
+pub mod tls;
+pub mod wake;
+pub mod watchdog;
 pub mod widget;
+pub mod x509;
+
+use core::cell::RefCell;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use critical_section::Mutex;
+use log_ring::{LogRing, LogShipping};
+
+/// Bytes of log kept in RAM for the SD card and the server
+pub const LOG_RING_SIZE: usize = 16 * 1024;
+
+/// Log lines since boot, while capturing (see `log_ring`)
+static LOG_RING: Mutex<RefCell<LogRing<LOG_RING_SIZE>>> = Mutex::new(RefCell::new(LogRing::new()));
+
+/// Whether log lines are copied into `LOG_RING`
+static LOG_CAPTURE: AtomicBool = AtomicBool::new(false);
 
 /// Timestamped logger for the `log` crate - adds timestamps to all log messages
 pub struct TimestampLogger;
@@ -20,6 +68,29 @@ impl TimestampLogger {
             log::set_max_level_racy(level);
         }
     }
+
+    /// Start or stop copying log lines into a RAM ring buffer, to be
+    /// [`read_captured`](Self::read_captured) into a file or
+    /// [`copy_captured`](Self::copy_captured) for the server
+    pub fn capture(enabled: bool) {
+        LOG_CAPTURE.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Move the oldest captured log text into `out`, returning its length (0
+    /// once there's none left)
+    pub fn read_captured(out: &mut [u8]) -> usize {
+        critical_section::with(|cs| LOG_RING.borrow_ref_mut(cs).read(out))
+    }
+
+    /// Copy the captured lines `shipping` selects into `out`, leaving them
+    /// captured, and return their length
+    pub fn copy_captured(out: &mut [u8], shipping: LogShipping) -> usize {
+        critical_section::with(|cs| {
+            LOG_RING
+                .borrow_ref(cs)
+                .copy_lines(out, |line| shipping.ships(line))
+        })
+    }
 }
 
 static LOGGER: TimestampLogger = TimestampLogger;
@@ -51,6 +122,22 @@ impl log::Log for TimestampLogger {
                 level,
                 record.args()
             );
+
+            if LOG_CAPTURE.load(Ordering::Relaxed) {
+                critical_section::with(|cs| {
+                    // A line logged while formatting this one is left out
+                    if let Ok(mut ring) = LOG_RING.borrow(cs).try_borrow_mut() {
+                        let _ = writeln!(
+                            ring,
+                            "[{:>4}.{:03}] {:>5} - {}",
+                            secs,
+                            millis,
+                            level,
+                            record.args()
+                        );
+                    }
+                });
+            }
         }
     }
 
@@ -0,0 +1,312 @@
+//! Log lines kept in RAM for the SD card
+//!
+//! Serial logging only helps with a cable attached. The logger can also copy
+//! each line into a [`LogRing`], which the firmware drains into `/LOG.TXT` on
+//! the SD card before deep sleep. The ring has a fixed size: once a wake logs
+//! more than fits, the oldest lines are dropped whole, and the first read after
+//! that starts with a note of how much was lost.
+//!
+//! The same lines can be shipped to the server after the refresh, all of them
+//! or only warnings and errors ([`LogShipping`]). Copying them out for that
+//! leaves the ring as it is, for the SD card.
+
+use core::fmt::{self, Write};
+
+/// Which captured lines are uploaded to the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogShipping {
+    All,
+    /// Only `WARN` and `ERROR` lines
+    Warnings,
+}
+
+impl LogShipping {
+    /// Parse the `REMOTE_LOG` build setting: `1` or `all` for every line,
+    /// `warn` for warnings and errors, anything else (or unset) for none
+    pub const fn parse(value: Option<&str>) -> Option<Self> {
+        match value {
+            Some(value) => match value.as_bytes() {
+                b"1" | b"all" => Some(Self::All),
+                b"warn" => Some(Self::Warnings),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    /// Whether a captured line is shipped
+    pub fn ships(self, line: &[u8]) -> bool {
+        match self {
+            Self::All => true,
+            Self::Warnings => is_warning(line),
+        }
+    }
+}
+
+/// Whether a line in the logger's `[secs.millis] LEVEL - message` format is a
+/// warning or an error
+fn is_warning(line: &[u8]) -> bool {
+    let Some(end) = line.iter().position(|&byte| byte == b']') else {
+        return false;
+    };
+    let level = line[end + 1..].trim_ascii_start();
+    level.starts_with(b"WARN ") || level.starts_with(b"ERROR ")
+}
+
+/// Fixed-size buffer of log text, overwriting the oldest lines when full
+pub struct LogRing<const N: usize> {
+    buf: [u8; N],
+    /// Position of the oldest byte
+    start: usize,
+    len: usize,
+    /// Bytes dropped since the last read
+    dropped: usize,
+}
+
+impl<const N: usize> LogRing<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            start: 0,
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Bytes held
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Append text, dropping the oldest lines to make room
+    pub fn push(&mut self, bytes: &[u8]) {
+        let mut overflowed = false;
+        for &byte in bytes {
+            if self.len == N {
+                self.pop_front();
+                self.dropped += 1;
+                overflowed = true;
+            }
+            self.buf[(self.start + self.len) % N] = byte;
+            self.len += 1;
+        }
+        // Don't leave the rest of a cut-off line at the front
+        if overflowed {
+            while let Some(byte) = self.pop_front() {
+                self.dropped += 1;
+                if byte == b'\n' {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Move the oldest text into `out`, returning the number of bytes written
+    ///
+    /// After lines were dropped, the first read starts with a line saying how
+    /// many bytes were lost (left out if `out` is too small for it).
+    pub fn read(&mut self, out: &mut [u8]) -> usize {
+        let mut written = 0;
+        let dropped = core::mem::take(&mut self.dropped);
+        if dropped > 0 {
+            let mut note = Cursor { buf: out, len: 0 };
+            if writeln!(note, "[{} bytes of log dropped]", dropped).is_ok() {
+                written = note.len;
+            }
+        }
+        while written < out.len() {
+            match self.pop_front() {
+                Some(byte) => {
+                    out[written] = byte;
+                    written += 1;
+                }
+                None => break,
+            }
+        }
+        written
+    }
+
+    /// Copy the held lines `keep` accepts into `out`, oldest first, returning
+    /// the number of bytes written
+    ///
+    /// Unlike [`read`](Self::read) this leaves the ring as it is. Copying stops
+    /// at the first line that doesn't fit.
+    pub fn copy_lines(&self, out: &mut [u8], keep: impl Fn(&[u8]) -> bool) -> usize {
+        let mut written = 0;
+        let mut line_start = 0;
+        for i in 0..self.len {
+            if written == out.len() {
+                return line_start;
+            }
+            let byte = self.buf[(self.start + i) % N];
+            out[written] = byte;
+            written += 1;
+            if byte == b'\n' {
+                if !keep(&out[line_start..written]) {
+                    written = line_start;
+                }
+                line_start = written;
+            }
+        }
+        // A line still being written is left out
+        line_start
+    }
+
+    /// Forget everything held
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+        self.dropped = 0;
+    }
+
+    fn pop_front(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.buf[self.start];
+        self.start = (self.start + 1) % N;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+impl<const N: usize> Default for LogRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Write for LogRing<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Writes into a byte buffer, failing once it's full
+struct Cursor<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for Cursor<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.buf.len() {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all<const N: usize>(ring: &mut LogRing<N>) -> std::string::String {
+        let mut text = std::vec::Vec::new();
+        let mut chunk = [0u8; 40];
+        loop {
+            let n = ring.read(&mut chunk);
+            if n == 0 {
+                break;
+            }
+            text.extend_from_slice(&chunk[..n]);
+        }
+        std::string::String::from_utf8(text).unwrap()
+    }
+
+    #[test]
+    fn test_read() {
+        let mut ring = LogRing::<64>::new();
+        assert!(ring.is_empty());
+        writeln!(ring, "[   1.000]  INFO - Boot!").unwrap();
+        ring.push(b"second line\n");
+        assert_eq!(ring.len(), 37);
+        assert_eq!(
+            read_all(&mut ring),
+            "[   1.000]  INFO - Boot!\nsecond line\n"
+        );
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut ring = LogRing::<32>::new();
+        ring.push(b"line one\n");
+        ring.push(b"line two\n");
+        ring.push(b"line three\n");
+        // Overwrites the start of "line one", and the rest of it to keep whole lines
+        ring.push(b"line four\n");
+        assert_eq!(ring.len(), 30);
+        assert_eq!(
+            read_all(&mut ring),
+            "[9 bytes of log dropped]\nline two\nline three\nline four\n"
+        );
+
+        // The note is only written once
+        ring.push(b"line five\n");
+        assert_eq!(read_all(&mut ring), "line five\n");
+    }
+
+    #[test]
+    fn test_copy_lines() {
+        let mut ring = LogRing::<128>::new();
+        ring.push(b"[   1.000]  INFO - Boot!\n");
+        ring.push(b"[   2.500]  WARN - Retrying\n");
+        ring.push(b"[  12.001] ERROR - TLS failed\n");
+        ring.push(b"[  12.002]  INFO - unfinished");
+
+        let mut out = [0u8; 128];
+        let len = ring.copy_lines(&mut out, |line| LogShipping::All.ships(line));
+        assert_eq!(
+            &out[..len],
+            b"[   1.000]  INFO - Boot!\n[   2.500]  WARN - Retrying\n[  12.001] ERROR - TLS failed\n"
+        );
+        let len = ring.copy_lines(&mut out, |line| LogShipping::Warnings.ships(line));
+        assert_eq!(
+            &out[..len],
+            b"[   2.500]  WARN - Retrying\n[  12.001] ERROR - TLS failed\n"
+        );
+        // Only whole lines, and the ring keeps them
+        let len = ring.copy_lines(&mut out[..40], |_| true);
+        assert_eq!(&out[..len], b"[   1.000]  INFO - Boot!\n");
+        assert_eq!(ring.len(), 112);
+
+        assert_eq!(LogShipping::parse(Some("1")), Some(LogShipping::All));
+        assert_eq!(
+            LogShipping::parse(Some("warn")),
+            Some(LogShipping::Warnings)
+        );
+        assert_eq!(LogShipping::parse(Some("0")), None);
+        assert_eq!(LogShipping::parse(None), None);
+    }
+
+    #[test]
+    fn test_wraps() {
+        let mut ring = LogRing::<16>::new();
+        for i in 0..10 {
+            writeln!(ring, "line {}", i).unwrap();
+        }
+        assert_eq!(
+            read_all(&mut ring),
+            "[56 bytes of log dropped]\nline 8\nline 9\n"
+        );
+
+        // Too small a buffer leaves the note out
+        ring.push(b"line one\nline two\nline three\n");
+        let mut chunk = [0u8; 8];
+        assert_eq!(ring.read(&mut chunk), 8);
+        assert_eq!(&chunk, b"line thr");
+
+        ring.push(b"partial");
+        ring.clear();
+        assert_eq!(read_all(&mut ring), "");
+    }
+}
@@ -0,0 +1,529 @@
+//! Home Assistant remote control over MQTT (feature `mqtt`)
+//!
+//! The frame spends nearly all its time in deep sleep, so it can't hold a
+//! broker connection open. Instead, after refreshing it connects to
+//! `MQTT_BROKER` (`host` or `host:port`, with optional `MQTT_USERNAME` and
+//! `MQTT_PASSWORD`, set at build time), publishes its state to [`STATE_TOPIC`]
+//! and picks up a command left on [`COMMAND_TOPIC`]. Both are retained, so a
+//! dashboard always sees the last state and a command published while the frame
+//! sleeps is delivered when it next wakes. A command is cleared once read, so it
+//! runs only once.
+//!
+//! Commands are `next`, `flip`, `refresh` (redraw the current items with a full
+//! refresh) and `sleep` (sleep until the button is pressed).
+//!
+//! Only the small part of MQTT 3.1.1 needed for this is implemented: QoS 0
+//! publish and subscribe, without keep-alive pings.
+
+use embassy_net::Stack;
+use embassy_net::dns::DnsQueryType;
+use embassy_net::tcp::TcpSocket;
+use embassy_time::{Duration, with_timeout};
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::widget::Orientation;
+
+/// Topic the frame's state is published to
+pub const STATE_TOPIC: &str = "sawthat/frame/state";
+
+/// Topic commands are read from
+pub const COMMAND_TOPIC: &str = "sawthat/frame/command";
+
+/// Broker port when `MQTT_BROKER` doesn't give one
+const DEFAULT_PORT: u16 = 1883;
+
+/// Keep-alive announced to the broker; the connection lasts well under it
+const KEEP_ALIVE_SECS: u16 = 30;
+
+/// How long to wait for the broker to connect, acknowledge or deliver
+const REPLY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long to wait for a retained command after subscribing
+const COMMAND_WAIT: Duration = Duration::from_secs(1);
+
+/// Packet identifier of the (only) subscription
+const SUBSCRIBE_PACKET_ID: u16 = 1;
+
+/// Largest packet sent or received
+const PACKET_SIZE: usize = 384;
+
+/// Remote command from Home Assistant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Show the next item, like a button tap
+    Next,
+    /// Toggle the orientation, like a button hold
+    Flip,
+    /// Redraw the current items with a full refresh
+    Refresh,
+    /// Sleep until the button is pressed
+    Sleep,
+}
+
+impl Command {
+    /// Parse a command payload, ignoring case and surrounding whitespace
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let command = core::str::from_utf8(payload).ok()?.trim();
+        [
+            ("next", Command::Next),
+            ("flip", Command::Flip),
+            ("refresh", Command::Refresh),
+            ("sleep", Command::Sleep),
+        ]
+        .into_iter()
+        .find(|(name, _)| command.eq_ignore_ascii_case(name))
+        .map(|(_, command)| command)
+    }
+}
+
+/// State published for dashboards, e.g.
+/// `{"battery_percent":80,"charging":false,"orientation":"horiz","item":"Phish","index":3,"total":42}`
+#[derive(Debug, Clone, Serialize)]
+pub struct FrameState<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub battery_percent: Option<u8>,
+    /// On USB power
+    pub charging: bool,
+    pub orientation: Orientation,
+    /// Path of the (first) item shown by the last refresh
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub item: Option<&'a str>,
+    /// Position in the rotation
+    pub index: usize,
+    /// Items in the rotation
+    pub total: usize,
+}
+
+/// Why an exchange with the broker failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MqttError {
+    /// `MQTT_BROKER` didn't resolve
+    Dns,
+    /// The TCP connection failed or dropped
+    Socket,
+    /// The broker didn't answer in time
+    Timeout,
+    /// The broker refused the connection, with its return code
+    Refused(u8),
+    /// A packet was malformed or too large
+    Protocol,
+}
+
+/// Broker host and port from `MQTT_BROKER`
+fn broker(value: &str) -> Option<(&str, u16)> {
+    match value.rsplit_once(':') {
+        Some((host, port)) => Some((host, port.parse().ok()?)),
+        None => Some((value, DEFAULT_PORT)),
+    }
+    .filter(|(host, _)| !host.is_empty())
+}
+
+/// Publish `state` and fetch any pending command, if a broker is configured
+///
+/// Returns the command left for the frame, if any.
+pub async fn exchange(
+    stack: Stack<'_>,
+    client_id: &str,
+    state: &FrameState<'_>,
+) -> Result<Option<Command>, MqttError> {
+    let Some(broker) = option_env!("MQTT_BROKER") else {
+        return Ok(None);
+    };
+    let (host, port) = broker(broker).ok_or(MqttError::Dns)?;
+    let address = *stack
+        .dns_query(host, DnsQueryType::A)
+        .await
+        .map_err(|_| MqttError::Dns)?
+        .first()
+        .ok_or(MqttError::Dns)?;
+
+    let mut rx_buf = [0u8; PACKET_SIZE];
+    let mut tx_buf = [0u8; PACKET_SIZE];
+    let mut socket = TcpSocket::new(stack, &mut rx_buf, &mut tx_buf);
+    socket.set_timeout(Some(REPLY_TIMEOUT));
+    with_timeout(REPLY_TIMEOUT, socket.connect((address, port)))
+        .await
+        .map_err(|_| MqttError::Timeout)?
+        .map_err(|_| MqttError::Socket)?;
+
+    let mut packet = [0u8; PACKET_SIZE];
+    let credentials = option_env!("MQTT_USERNAME")
+        .map(|username| (username, option_env!("MQTT_PASSWORD").unwrap_or_default()));
+    let len = encode_connect(&mut packet, client_id, credentials).ok_or(MqttError::Protocol)?;
+    write_all(&mut socket, &packet[..len]).await?;
+    match read_packet(&mut socket, &mut packet, REPLY_TIMEOUT).await? {
+        Packet::ConnAck { code: 0 } => {}
+        Packet::ConnAck { code } => return Err(MqttError::Refused(code)),
+        _ => return Err(MqttError::Protocol),
+    }
+
+    let mut payload = [0u8; PACKET_SIZE / 2];
+    let payload_len =
+        serde_json_core::to_slice(state, &mut payload).map_err(|_| MqttError::Protocol)?;
+    let len = encode_publish(&mut packet, STATE_TOPIC, &payload[..payload_len], true)
+        .ok_or(MqttError::Protocol)?;
+    write_all(&mut socket, &packet[..len]).await?;
+
+    let len = encode_subscribe(&mut packet, SUBSCRIBE_PACKET_ID, COMMAND_TOPIC)
+        .ok_or(MqttError::Protocol)?;
+    write_all(&mut socket, &packet[..len]).await?;
+
+    // A retained command arrives right after the subscription is acknowledged
+    let mut command = None;
+    loop {
+        match read_packet(&mut socket, &mut packet, COMMAND_WAIT).await {
+            Ok(Packet::Publish { topic, payload }) if topic == COMMAND_TOPIC => {
+                // An empty retained message is the cleared command
+                if !payload.is_empty() {
+                    command = Command::parse(payload);
+                    if command.is_none() {
+                        warn!("Ignoring MQTT command {:?}", core::str::from_utf8(payload));
+                    }
+                }
+                break;
+            }
+            Ok(_) => {}
+            Err(MqttError::Timeout) => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    if let Some(command) = command {
+        info!("MQTT command: {:?}", command);
+        let len =
+            encode_publish(&mut packet, COMMAND_TOPIC, &[], true).ok_or(MqttError::Protocol)?;
+        write_all(&mut socket, &packet[..len]).await?;
+    }
+
+    write_all(&mut socket, &DISCONNECT).await?;
+    let _ = socket.flush().await;
+    socket.close();
+    Ok(command)
+}
+
+/// Write a whole packet to the socket
+async fn write_all(socket: &mut TcpSocket<'_>, mut data: &[u8]) -> Result<(), MqttError> {
+    while !data.is_empty() {
+        let written = socket.write(data).await.map_err(|_| MqttError::Socket)?;
+        if written == 0 {
+            return Err(MqttError::Socket);
+        }
+        data = &data[written..];
+    }
+    Ok(())
+}
+
+/// Read the next whole packet into `buf`, waiting at most `timeout`
+async fn read_packet<'a>(
+    socket: &mut TcpSocket<'_>,
+    buf: &'a mut [u8],
+    timeout: Duration,
+) -> Result<Packet<'a>, MqttError> {
+    let mut len = 0;
+    loop {
+        if let Some(total) = packet_len(&buf[..len]) {
+            if total > buf.len() {
+                return Err(MqttError::Protocol);
+            }
+            if len >= total {
+                // Anything past this packet is dropped; the broker only sends
+                // more than one at a time when a command follows the SUBACK,
+                // and those are handled in separate reads
+                return decode_packet(&buf[..total]).ok_or(MqttError::Protocol);
+            }
+        }
+        if len == buf.len() {
+            return Err(MqttError::Protocol);
+        }
+        let read = with_timeout(timeout, socket.read(&mut buf[len..]))
+            .await
+            .map_err(|_| MqttError::Timeout)?
+            .map_err(|_| MqttError::Socket)?;
+        if read == 0 {
+            return Err(MqttError::Socket);
+        }
+        len += read;
+    }
+}
+
+/// DISCONNECT packet
+const DISCONNECT: [u8; 2] = [0xE0, 0x00];
+
+/// A packet received from the broker
+#[derive(Debug, PartialEq, Eq)]
+enum Packet<'a> {
+    /// Connection acknowledged, 0 if accepted
+    ConnAck { code: u8 },
+    /// Subscription acknowledged
+    SubAck,
+    /// A message on a subscribed topic
+    Publish { topic: &'a str, payload: &'a [u8] },
+    /// Anything else (e.g. PINGRESP)
+    Other,
+}
+
+/// Appends to a fixed buffer, tracking overflow
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    overflow: bool,
+}
+
+impl<'a> Writer<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    fn bytes(&mut self, data: &[u8]) {
+        match self.buf.get_mut(self.len..self.len + data.len()) {
+            Some(dest) => {
+                dest.copy_from_slice(data);
+                self.len += data.len();
+            }
+            None => self.overflow = true,
+        }
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    /// Length-prefixed UTF-8 string or binary data
+    fn string(&mut self, data: &[u8]) {
+        match u16::try_from(data.len()) {
+            Ok(len) => {
+                self.u16(len);
+                self.bytes(data);
+            }
+            Err(_) => self.overflow = true,
+        }
+    }
+
+    /// Remaining length, 7 bits per byte with the high bit set on all but the last
+    fn remaining_length(&mut self, mut len: usize) {
+        loop {
+            let mut byte = (len % 128) as u8;
+            len /= 128;
+            if len > 0 {
+                byte |= 0x80;
+            }
+            self.bytes(&[byte]);
+            if len == 0 {
+                break;
+            }
+        }
+    }
+
+    fn finish(self) -> Option<usize> {
+        (!self.overflow).then_some(self.len)
+    }
+}
+
+/// Write a packet of type `header` whose body is written by `body`
+fn encode(buf: &mut [u8], header: u8, body: impl Fn(&mut Writer)) -> Option<usize> {
+    // Measure the body first, the remaining length comes before it
+    let mut scratch = [0u8; PACKET_SIZE];
+    let mut measure = Writer::new(&mut scratch);
+    body(&mut measure);
+    let body_len = measure.finish()?;
+
+    let mut writer = Writer::new(buf);
+    writer.bytes(&[header]);
+    writer.remaining_length(body_len);
+    body(&mut writer);
+    writer.finish()
+}
+
+/// CONNECT with a clean session, optionally authenticating
+fn encode_connect(
+    buf: &mut [u8],
+    client_id: &str,
+    credentials: Option<(&str, &str)>,
+) -> Option<usize> {
+    encode(buf, 0x10, |w| {
+        w.string(b"MQTT");
+        // Protocol level 4 (3.1.1)
+        w.bytes(&[4]);
+        let flags = match credentials {
+            Some(_) => 0xC2,
+            None => 0x02,
+        };
+        w.bytes(&[flags]);
+        w.u16(KEEP_ALIVE_SECS);
+        w.string(client_id.as_bytes());
+        if let Some((username, password)) = credentials {
+            w.string(username.as_bytes());
+            w.string(password.as_bytes());
+        }
+    })
+}
+
+/// PUBLISH at QoS 0
+fn encode_publish(buf: &mut [u8], topic: &str, payload: &[u8], retain: bool) -> Option<usize> {
+    encode(buf, 0x30 | retain as u8, |w| {
+        w.string(topic.as_bytes());
+        w.bytes(payload);
+    })
+}
+
+/// SUBSCRIBE to one topic at QoS 0
+fn encode_subscribe(buf: &mut [u8], packet_id: u16, topic: &str) -> Option<usize> {
+    encode(buf, 0x82, |w| {
+        w.u16(packet_id);
+        w.string(topic.as_bytes());
+        w.bytes(&[0]);
+    })
+}
+
+/// Length of the packet starting `data`, once its header is complete
+fn packet_len(data: &[u8]) -> Option<usize> {
+    let (remaining, header_len) = remaining_length(data.get(1..)?)?;
+    Some(1 + header_len + remaining)
+}
+
+/// Decode a remaining length, returning it and the bytes it took
+fn remaining_length(data: &[u8]) -> Option<(usize, usize)> {
+    let mut len = 0;
+    for (i, byte) in data.iter().take(4).enumerate() {
+        len |= ((byte & 0x7F) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((len, i + 1));
+        }
+    }
+    None
+}
+
+/// Decode one whole packet
+fn decode_packet(data: &[u8]) -> Option<Packet<'_>> {
+    let header = *data.first()?;
+    let (remaining, header_len) = remaining_length(&data[1..])?;
+    let body = data.get(1 + header_len..1 + header_len + remaining)?;
+    let packet = match header >> 4 {
+        2 => Packet::ConnAck {
+            code: *body.get(1)?,
+        },
+        9 => Packet::SubAck,
+        3 => {
+            let topic_len = u16::from_be_bytes([*body.first()?, *body.get(1)?]) as usize;
+            let topic = core::str::from_utf8(body.get(2..2 + topic_len)?).ok()?;
+            // QoS 1 and 2 carry a packet identifier before the payload
+            let qos = (header >> 1) & 0x3;
+            let payload_start = 2 + topic_len + if qos > 0 { 2 } else { 0 };
+            Packet::Publish {
+                topic,
+                payload: body.get(payload_start..)?,
+            }
+        }
+        _ => Packet::Other,
+    };
+    Some(packet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let mut buf = [0u8; PACKET_SIZE];
+        let len = encode_connect(&mut buf, "frame-1", None).unwrap();
+        assert_eq!(
+            &buf[..len],
+            b"\x10\x13\x00\x04MQTT\x04\x02\x00\x1e\x00\x07frame-1"
+        );
+        let len = encode_connect(&mut buf, "f", Some(("u", "pw"))).unwrap();
+        assert_eq!(
+            &buf[..len],
+            b"\x10\x14\x00\x04MQTT\x04\xc2\x00\x1e\x00\x01f\x00\x01u\x00\x02pw"
+        );
+
+        let len = encode_publish(&mut buf, "a/b", b"next", true).unwrap();
+        assert_eq!(&buf[..len], b"\x31\x09\x00\x03a/bnext");
+        let len = encode_subscribe(&mut buf, 1, "a/b").unwrap();
+        assert_eq!(&buf[..len], b"\x82\x08\x00\x01\x00\x03a/b\x00");
+
+        // Remaining lengths over 127 take two bytes
+        let payload = [b'x'; 200];
+        let len = encode_publish(&mut buf, "t", &payload, false).unwrap();
+        assert_eq!(&buf[..3], b"\x30\xcb\x01");
+        assert_eq!(packet_len(&buf[..len]), Some(len));
+
+        // Too large for the buffer
+        assert_eq!(encode_publish(&mut buf[..16], "t", &payload, false), None);
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            decode_packet(b"\x20\x02\x00\x00"),
+            Some(Packet::ConnAck { code: 0 })
+        );
+        assert_eq!(
+            decode_packet(b"\x20\x02\x00\x05"),
+            Some(Packet::ConnAck { code: 5 })
+        );
+        assert_eq!(decode_packet(b"\x90\x03\x00\x01\x00"), Some(Packet::SubAck));
+        assert_eq!(
+            decode_packet(b"\x31\x09\x00\x03a/bflip"),
+            Some(Packet::Publish {
+                topic: "a/b",
+                payload: b"flip"
+            })
+        );
+        // QoS 1 delivery skips the packet identifier
+        assert_eq!(
+            decode_packet(b"\x32\x0b\x00\x03a/b\x00\x07flip"),
+            Some(Packet::Publish {
+                topic: "a/b",
+                payload: b"flip"
+            })
+        );
+        assert_eq!(decode_packet(b"\xd0\x00"), Some(Packet::Other));
+
+        // Incomplete packets
+        assert_eq!(packet_len(b"\x30"), None);
+        assert_eq!(packet_len(b"\x30\x80"), None);
+        assert_eq!(decode_packet(b"\x31\x09\x00\x03a/b"), None);
+    }
+
+    #[test]
+    fn test_command_and_broker() {
+        assert_eq!(Command::parse(b"next"), Some(Command::Next));
+        assert_eq!(Command::parse(b" Flip\n"), Some(Command::Flip));
+        assert_eq!(Command::parse(b"REFRESH"), Some(Command::Refresh));
+        assert_eq!(Command::parse(b"sleep"), Some(Command::Sleep));
+        assert_eq!(Command::parse(b"dance"), None);
+        assert_eq!(Command::parse(b"\xff"), None);
+
+        assert_eq!(
+            broker("homeassistant.local"),
+            Some(("homeassistant.local", 1883))
+        );
+        assert_eq!(broker("10.0.0.2:8883"), Some(("10.0.0.2", 8883)));
+        assert_eq!(broker("10.0.0.2:mqtt"), None);
+        assert_eq!(broker(":1883"), None);
+    }
+
+    #[test]
+    fn test_state_json() {
+        let state = FrameState {
+            battery_percent: Some(80),
+            charging: false,
+            orientation: Orientation::Horizontal,
+            item: Some("Phish"),
+            index: 3,
+            total: 42,
+        };
+        let mut buf = [0u8; 128];
+        let len = serde_json_core::to_slice(&state, &mut buf).unwrap();
+        assert_eq!(
+            core::str::from_utf8(&buf[..len]).unwrap(),
+            r#"{"battery_percent":80,"charging":false,"orientation":"horiz","item":"Phish","index":3,"total":42}"#
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Settings in the flash NVS partition, for builds without an SD card
+//!
+//! Keeps the small settings [`SdCache`](crate::cache::SdCache) would otherwise
+//! hold (orientation, device config, WiFi credentials and the frozen item) so a frame without a
+//! card still remembers them across power cycles. Images and widget data are
+//! not stored: without a card every wake fetches them from the server.
+//!
+//! The partition is the one ESP-IDF would use for NVS (24KB in the default
+//! partition table), but the layout is our own: one 4KB sector per setting,
+//! each holding a single record (magic, length, CRC-32, payload). A record cut
+//! short by power loss fails its CRC and reads as unset. Writes of an unchanged
+//! value are skipped to spare the flash.
+
+use embedded_storage::{ReadStorage, Storage};
+use log::info;
+
+use crate::cache::{CacheError, SettingsStore, parse_wifi_credentials};
+use crate::config::{self, CONFIG_JSON_SIZE, DeviceConfig};
+use crate::freeze::{self, Freeze};
+use crate::provision::WifiCredentials;
+use crate::screenshot::Crc32;
+use crate::widget::Orientation;
+
+/// Flash sector size (one record per sector)
+const SECTOR_SIZE: u32 = 4096;
+
+/// Magic at the start of every record
+const RECORD_MAGIC: [u8; 4] = *b"SFS1";
+
+/// Record header: magic, payload length (u16 LE), payload CRC-32 (u32 LE)
+const RECORD_HEADER_SIZE: usize = 10;
+
+/// Largest payload of any setting (the device config JSON)
+const MAX_PAYLOAD: usize = CONFIG_JSON_SIZE;
+
+/// Sector index of each setting within the partition
+#[derive(Debug, Clone, Copy)]
+enum Key {
+    Orientation = 0,
+    DeviceConfig = 1,
+    WifiCredentials = 2,
+    Freeze = 3,
+}
+
+/// Number of sectors the settings need
+const SECTORS_USED: u32 = 4;
+
+/// Settings store in a flash partition
+pub struct NvsStore<S> {
+    storage: S,
+    /// Partition offset in flash
+    offset: u32,
+}
+
+impl<S: ReadStorage + Storage> NvsStore<S> {
+    /// Use the partition at `offset` (sector aligned) of `size` bytes
+    pub fn new(storage: S, offset: u32, size: u32) -> Result<Self, CacheError> {
+        if !offset.is_multiple_of(SECTOR_SIZE) || size < SECTORS_USED * SECTOR_SIZE {
+            return Err(CacheError::TooLarge);
+        }
+        info!("NVS settings at {:#x} ({} bytes)", offset, size);
+        Ok(Self { storage, offset })
+    }
+
+    fn address(&self, key: Key) -> u32 {
+        self.offset + key as u32 * SECTOR_SIZE
+    }
+
+    /// Read a record's payload into `buf`, returning its length
+    fn read(&mut self, key: Key, buf: &mut [u8; MAX_PAYLOAD]) -> Option<usize> {
+        let address = self.address(key);
+        let mut header = [0u8; RECORD_HEADER_SIZE];
+        self.storage.read(address, &mut header).ok()?;
+        if header[..4] != RECORD_MAGIC {
+            return None;
+        }
+        let len = u16::from_le_bytes([header[4], header[5]]) as usize;
+        let crc = u32::from_le_bytes([header[6], header[7], header[8], header[9]]);
+        if len > MAX_PAYLOAD {
+            return None;
+        }
+
+        self.storage
+            .read(address + RECORD_HEADER_SIZE as u32, &mut buf[..len])
+            .ok()?;
+        let mut actual = Crc32::new();
+        actual.update(&buf[..len]);
+        if actual.finish() != crc {
+            info!("NVS record {:?} is corrupt", key);
+            return None;
+        }
+        Some(len)
+    }
+
+    /// Replace a record, unless it already holds `payload`
+    fn write(&mut self, key: Key, payload: &[u8]) -> Result<(), CacheError> {
+        if payload.len() > MAX_PAYLOAD {
+            return Err(CacheError::TooLarge);
+        }
+        let mut existing = [0u8; MAX_PAYLOAD];
+        if self
+            .read(key, &mut existing)
+            .is_some_and(|len| &existing[..len] == payload)
+        {
+            return Ok(());
+        }
+
+        let mut crc = Crc32::new();
+        crc.update(payload);
+        let mut record = [0u8; RECORD_HEADER_SIZE + MAX_PAYLOAD];
+        record[..4].copy_from_slice(&RECORD_MAGIC);
+        record[4..6].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+        record[6..10].copy_from_slice(&crc.finish().to_le_bytes());
+        record[RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + payload.len()].copy_from_slice(payload);
+
+        self.storage
+            .write(
+                self.address(key),
+                &record[..RECORD_HEADER_SIZE + payload.len()],
+            )
+            .map_err(|_| CacheError::Write)
+    }
+}
+
+impl<S: ReadStorage + Storage> SettingsStore for NvsStore<S> {
+    fn load_orientation(&mut self) -> Option<Orientation> {
+        let mut buf = [0u8; MAX_PAYLOAD];
+        let len = self.read(Key::Orientation, &mut buf)?;
+        let orientation = Orientation::from_u8(*buf[..len].first()?);
+        info!("Loaded orientation from NVS: {:?}", orientation);
+        Some(orientation)
+    }
+
+    fn store_orientation(&mut self, orientation: Orientation) -> Result<(), CacheError> {
+        self.write(Key::Orientation, &[orientation as u8])?;
+        info!("Stored orientation to NVS: {:?}", orientation);
+        Ok(())
+    }
+
+    fn load_device_config(&mut self) -> Option<DeviceConfig> {
+        let mut buf = [0u8; MAX_PAYLOAD];
+        let len = self.read(Key::DeviceConfig, &mut buf)?;
+        let json_str = core::str::from_utf8(&buf[..len]).ok()?;
+        let device_config = config::parse_device_config(json_str).ok()?;
+        info!(
+            "Loaded device config from NVS: refresh={}s",
+            device_config.refresh_interval_secs
+        );
+        Some(device_config)
+    }
+
+    fn store_device_config(&mut self, device_config: &DeviceConfig) -> Result<(), CacheError> {
+        let mut buf = [0u8; CONFIG_JSON_SIZE];
+        let len =
+            config::serialize_device_config(device_config, &mut buf).ok_or(CacheError::Write)?;
+        self.write(Key::DeviceConfig, &buf[..len])?;
+        info!("Stored device config to NVS");
+        Ok(())
+    }
+
+    fn load_wifi_credentials(&mut self) -> Option<WifiCredentials> {
+        let mut buf = [0u8; MAX_PAYLOAD];
+        let len = self.read(Key::WifiCredentials, &mut buf)?;
+        let credentials = parse_wifi_credentials(&buf[..len])?;
+        info!(
+            "Loaded WiFi credentials for '{}'",
+            credentials.ssid.as_str()
+        );
+        Some(credentials)
+    }
+
+    fn store_wifi_credentials(&mut self, credentials: &WifiCredentials) -> Result<(), CacheError> {
+        let mut buf = [0u8; MAX_PAYLOAD];
+        let ssid = credentials.ssid.as_bytes();
+        let password = credentials.password.as_bytes();
+        let len = ssid.len() + 1 + password.len();
+        buf[..ssid.len()].copy_from_slice(ssid);
+        buf[ssid.len()] = b'\n';
+        buf[ssid.len() + 1..len].copy_from_slice(password);
+        self.write(Key::WifiCredentials, &buf[..len])?;
+        info!(
+            "Stored WiFi credentials for '{}'",
+            credentials.ssid.as_str()
+        );
+        Ok(())
+    }
+
+    fn load_freeze(&mut self) -> Option<Freeze> {
+        let mut buf = [0u8; MAX_PAYLOAD];
+        let len = self.read(Key::Freeze, &mut buf)?;
+        let freeze = freeze::parse_frozen_item(&buf[..len])?;
+        info!("Loaded frozen item from NVS: {}", freeze.item);
+        Some(freeze)
+    }
+
+    fn store_freeze(&mut self, freeze: Option<&Freeze>) -> Result<(), CacheError> {
+        let item = freeze.map_or("", |f| f.item.as_str());
+        self.write(Key::Freeze, item.as_bytes())?;
+        info!(
+            "Stored frozen item to NVS: {:?}",
+            freeze.map(|f| f.item.as_str())
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    /// Flash stand-in that counts writes
+    struct RamStorage {
+        data: Vec<u8>,
+        writes: usize,
+    }
+
+    impl ReadStorage for RamStorage {
+        type Error = ();
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), ()> {
+            let start = offset as usize;
+            bytes.copy_from_slice(self.data.get(start..start + bytes.len()).ok_or(())?);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl Storage for RamStorage {
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), ()> {
+            let start = offset as usize;
+            self.data
+                .get_mut(start..start + bytes.len())
+                .ok_or(())?
+                .copy_from_slice(bytes);
+            self.writes += 1;
+            Ok(())
+        }
+    }
+
+    fn erased_store() -> NvsStore<RamStorage> {
+        let storage = RamStorage {
+            data: vec![0xFF; 0x9000 + 0x6000],
+            writes: 0,
+        };
+        NvsStore::new(storage, 0x9000, 0x6000).unwrap()
+    }
+
+    #[test]
+    fn test_settings_round_trip() {
+        let mut store = erased_store();
+        assert_eq!(store.load_orientation(), None);
+        assert!(store.load_wifi_credentials().is_none());
+
+        store.store_orientation(Orientation::Vertical).unwrap();
+        let credentials = WifiCredentials::new("frame-net", "hunter2").unwrap();
+        store.store_wifi_credentials(&credentials).unwrap();
+
+        assert_eq!(store.load_orientation(), Some(Orientation::Vertical));
+        let loaded = store.load_wifi_credentials().unwrap();
+        assert_eq!(loaded.ssid.as_str(), "frame-net");
+        assert_eq!(loaded.password.as_str(), "hunter2");
+
+        // Storing the same value again leaves the flash alone
+        let writes = store.storage.writes;
+        store.store_orientation(Orientation::Vertical).unwrap();
+        assert_eq!(store.storage.writes, writes);
+
+        let freeze = Freeze::new("2024-06-15-band").unwrap();
+        store.store_freeze(Some(&freeze)).unwrap();
+        assert_eq!(store.load_freeze(), Some(freeze));
+        store.store_freeze(None).unwrap();
+        assert_eq!(store.load_freeze(), None);
+    }
+
+    #[test]
+    fn test_corrupt_record_reads_as_unset() {
+        let mut store = erased_store();
+        store.store_orientation(Orientation::Vertical).unwrap();
+
+        // Flip a payload bit, as a write cut short might leave it
+        store.storage.data[0x9000 + RECORD_HEADER_SIZE] ^= 0x01;
+        assert_eq!(store.load_orientation(), None);
+
+        assert!(
+            NvsStore::new(
+                RamStorage {
+                    data: vec![],
+                    writes: 0
+                },
+                0x9000,
+                0x2000
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! Recovery from a device config SPKI pin the server doesn't match
+//!
+//! The config's `spki_sha256` is enforced on the sessions that deliver the
+//! config, so a mistyped hash, a rotated key or a stale cached config would
+//! lock the frame out for good. [`PinState`], kept in RTC memory, counts the
+//! handshakes in a row that fail on the config pin alone; after
+//! [`MAX_PIN_FAILURES`] the pin is set aside and the server is verified by the
+//! build's trust anchors, if it has any, until a config with another pin
+//! arrives. Nothing here touches the network, so it is also tested on the host
+//! (see `host-tests`).
+
+/// Handshakes in a row that may fail on the config pin before it's set aside
+pub const MAX_PIN_FAILURES: u8 = 3;
+/// Magic number to validate [`PinState`] in RTC memory
+const PIN_STATE_MAGIC: u32 = 0x5049_4E53;
+
+/// Config pin failures across wakes
+#[repr(C)]
+pub struct PinState {
+    magic: u32,
+    /// Handshakes in a row that failed on the config pin
+    failures: u8,
+    /// Whether `set_aside` holds a pin
+    has_set_aside: u8,
+    /// Config pin the server kept failing, not enforced again
+    set_aside: [u8; 32],
+}
+
+impl Default for PinState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PinState {
+    pub const fn new() -> Self {
+        Self {
+            magic: 0,
+            failures: 0,
+            has_set_aside: 0,
+            set_aside: [0; 32],
+        }
+    }
+
+    /// Forget state that wasn't recorded (e.g. garbage after power loss)
+    pub fn validate(&mut self) {
+        if self.magic != PIN_STATE_MAGIC {
+            *self = Self::new();
+        }
+    }
+
+    /// The config pin to enforce: `config_pin`, unless it's the one set aside
+    pub fn pin(&self, config_pin: Option<[u8; 32]>) -> Option<[u8; 32]> {
+        config_pin.filter(|pin| self.has_set_aside == 0 || *pin != self.set_aside)
+    }
+
+    /// Record a handshake that failed on `pin`, returning whether it's now set
+    /// aside
+    pub fn fail(&mut self, pin: [u8; 32]) -> bool {
+        self.magic = PIN_STATE_MAGIC;
+        self.failures = self.failures.saturating_add(1);
+        if self.failures < MAX_PIN_FAILURES {
+            return false;
+        }
+        self.failures = 0;
+        self.has_set_aside = 1;
+        self.set_aside = pin;
+        true
+    }
+
+    /// Record a handshake that passed the config pin
+    pub fn succeed(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Handshakes in a row that failed on the config pin
+    pub fn failures(&self) -> u8 {
+        self.failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BAD_PIN: [u8; 32] = [0xAB; 32];
+    const NEW_PIN: [u8; 32] = [0xCD; 32];
+
+    #[test]
+    fn test_pin_set_aside_then_replaced() {
+        let mut state = PinState::new();
+        assert_eq!(state.pin(None), None);
+        assert_eq!(state.pin(Some(BAD_PIN)), Some(BAD_PIN));
+
+        // A pinned session in between restarts the count
+        assert!(!state.fail(BAD_PIN));
+        state.succeed();
+        assert_eq!(state.failures(), 0);
+
+        // Only failures in a row set the pin aside
+        assert!(!state.fail(BAD_PIN));
+        assert!(!state.fail(BAD_PIN));
+        assert_eq!(state.pin(Some(BAD_PIN)), Some(BAD_PIN));
+        assert!(state.fail(BAD_PIN));
+        assert_eq!(state.failures(), 0);
+
+        // Surviving deep sleep, the cached config's pin stays set aside
+        state.validate();
+        assert_eq!(state.pin(Some(BAD_PIN)), None);
+
+        // A corrected pin from the server is enforced again
+        assert_eq!(state.pin(Some(NEW_PIN)), Some(NEW_PIN));
+        state.succeed();
+        assert_eq!(state.pin(Some(NEW_PIN)), Some(NEW_PIN));
+    }
+
+    #[test]
+    fn test_pin_state_validate() {
+        let mut state = PinState::new();
+        state.fail(BAD_PIN);
+        state.magic = 0;
+        state.validate();
+        assert_eq!(state.failures(), 0);
+        assert_eq!(state.pin(Some(BAD_PIN)), Some(BAD_PIN));
+    }
+}
@@ -0,0 +1,384 @@
+//! Streaming decoder for indexed PNGs
+//!
+//! The server sends palette-indexed PNGs: 8-bit, or 4-bit for bandwidth-reduced
+//! frames. Instead of buffering a whole file and inflating it into a full-size
+//! pixel buffer, [`PngDecoder`] takes the file in chunks of any size and hands
+//! each scanline to a callback as soon as it is unfiltered. Its only buffers
+//! are the 32KB deflate window (see [`crate::inflate`]) and two scanlines.
+//!
+//! Chunk CRCs are not checked: bodies are covered by TLS on the way in and by
+//! the SD cache footer at rest. The zlib stream's Adler-32 is checked.
+
+use crate::inflate::{Format, Inflater};
+
+/// Signature at the start of every PNG file
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Widest supported scanline in bytes (an 800 pixel row at 8 bits)
+pub const MAX_ROW_BYTES: usize = 800;
+
+/// Image properties from the IHDR chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PngHeader {
+    pub width: u32,
+    pub height: u32,
+    /// Bits per palette index (1, 2, 4 or 8)
+    pub bit_depth: u8,
+}
+
+impl PngHeader {
+    /// Bytes per scanline, excluding the filter byte
+    pub fn row_bytes(&self) -> usize {
+        (self.width as usize * self.bit_depth as usize).div_ceil(8)
+    }
+}
+
+/// Scanline reassembly and unfiltering
+struct Scanlines {
+    previous: [u8; MAX_ROW_BYTES],
+    /// Filter byte followed by the row
+    current: [u8; MAX_ROW_BYTES + 1],
+    filled: usize,
+    y: u32,
+}
+
+impl Scanlines {
+    fn accept(
+        &mut self,
+        mut data: &[u8],
+        header: &PngHeader,
+        on_row: &mut impl FnMut(&PngHeader, u32, &[u8]),
+    ) -> Result<(), &'static str> {
+        let row_bytes = header.row_bytes();
+        while !data.is_empty() {
+            if self.y >= header.height {
+                return Err("extra image data");
+            }
+            let n = (row_bytes + 1 - self.filled).min(data.len());
+            self.current[self.filled..self.filled + n].copy_from_slice(&data[..n]);
+            self.filled += n;
+            data = &data[n..];
+
+            if self.filled == row_bytes + 1 {
+                let (filter, row) = self.current[..=row_bytes].split_at_mut(1);
+                unfilter(filter[0], row, &self.previous[..row_bytes])?;
+                on_row(header, self.y, row);
+                self.previous[..row_bytes].copy_from_slice(row);
+                self.filled = 0;
+                self.y += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Undo a scanline filter, given the previous (already unfiltered) row
+///
+/// Indexed images have one byte per pixel at most, so "left" is the previous byte.
+fn unfilter(filter: u8, row: &mut [u8], previous: &[u8]) -> Result<(), &'static str> {
+    match filter {
+        0 => {}
+        1 => {
+            for i in 1..row.len() {
+                row[i] = row[i].wrapping_add(row[i - 1]);
+            }
+        }
+        2 => {
+            for (byte, &up) in row.iter_mut().zip(previous) {
+                *byte = byte.wrapping_add(up);
+            }
+        }
+        3 => {
+            for i in 0..row.len() {
+                let left = if i > 0 { row[i - 1] } else { 0 };
+                let average = (left as u16 + previous[i] as u16) / 2;
+                row[i] = row[i].wrapping_add(average as u8);
+            }
+        }
+        4 => {
+            for i in 0..row.len() {
+                let (left, up_left) = if i > 0 {
+                    (row[i - 1], previous[i - 1])
+                } else {
+                    (0, 0)
+                };
+                row[i] = row[i].wrapping_add(paeth(left, previous[i], up_left));
+            }
+        }
+        _ => return Err("invalid filter type"),
+    }
+    Ok(())
+}
+
+/// Paeth predictor: whichever neighbor is closest to `left + up - up_left`
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_up = (estimate - up as i16).abs();
+    let distance_up_left = (estimate - up_left as i16).abs();
+    if distance_left <= distance_up && distance_left <= distance_up_left {
+        left
+    } else if distance_up <= distance_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// Position in the PNG file
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Signature,
+    ChunkHeader,
+    ChunkData,
+    ChunkCrc,
+    End,
+}
+
+/// Push-based decoder for non-interlaced, palette-indexed PNGs
+pub struct PngDecoder {
+    stage: Stage,
+    /// Bytes collected of the signature, chunk header, IHDR body or CRC
+    buf: [u8; 13],
+    filled: usize,
+    chunk_type: [u8; 4],
+    /// Bytes of the current chunk's data still to come
+    chunk_remaining: u32,
+    header: Option<PngHeader>,
+    inflater: Inflater,
+    scanlines: Scanlines,
+}
+
+impl PngDecoder {
+    pub fn new() -> Self {
+        Self {
+            stage: Stage::Signature,
+            buf: [0; 13],
+            filled: 0,
+            chunk_type: [0; 4],
+            chunk_remaining: 0,
+            header: None,
+            inflater: Inflater::new(Format::Zlib),
+            scanlines: Scanlines {
+                previous: [0; MAX_ROW_BYTES],
+                current: [0; MAX_ROW_BYTES + 1],
+                filled: 0,
+                y: 0,
+            },
+        }
+    }
+
+    /// Image header, once the IHDR chunk has been read
+    pub fn header(&self) -> Option<PngHeader> {
+        self.header
+    }
+
+    /// Feed the next bytes of the file
+    ///
+    /// `on_row` is called with the header, row index and palette indices of
+    /// each scanline as it completes (sub-byte depths stay packed, leftmost
+    /// pixel in the high bits).
+    pub fn push(
+        &mut self,
+        mut data: &[u8],
+        on_row: &mut impl FnMut(&PngHeader, u32, &[u8]),
+    ) -> Result<(), &'static str> {
+        // Zero-length chunks (and the ends of chunks) are handled without more data
+        while !data.is_empty() || self.at_chunk_end() {
+            match self.stage {
+                Stage::Signature => {
+                    if !self.collect(&mut data, SIGNATURE.len()) {
+                        continue;
+                    }
+                    if self.buf[..8] != SIGNATURE {
+                        return Err("not a PNG");
+                    }
+                    self.stage = Stage::ChunkHeader;
+                }
+                Stage::ChunkHeader => {
+                    if !self.collect(&mut data, 8) {
+                        continue;
+                    }
+                    self.chunk_remaining =
+                        u32::from_be_bytes([self.buf[0], self.buf[1], self.buf[2], self.buf[3]]);
+                    self.chunk_type.copy_from_slice(&self.buf[4..8]);
+                    if self.header.is_none() && &self.chunk_type != b"IHDR" {
+                        return Err("missing IHDR");
+                    }
+                    if &self.chunk_type == b"IHDR" && self.chunk_remaining != 13 {
+                        return Err("invalid IHDR length");
+                    }
+                    self.stage = Stage::ChunkData;
+                }
+                Stage::ChunkData if self.chunk_remaining == 0 => {
+                    match &self.chunk_type {
+                        b"IHDR" => self.header = Some(parse_header(&self.buf)?),
+                        b"IEND" => {
+                            self.stage = Stage::End;
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    self.stage = Stage::ChunkCrc;
+                }
+                Stage::ChunkData => {
+                    let n = (self.chunk_remaining as usize).min(data.len());
+                    let (chunk, rest) = data.split_at(n);
+                    match &self.chunk_type {
+                        b"IHDR" => {
+                            self.buf[13 - self.chunk_remaining as usize..][..n]
+                                .copy_from_slice(chunk);
+                        }
+                        b"IDAT" => {
+                            let header = self.header.ok_or("missing IHDR")?;
+                            let scanlines = &mut self.scanlines;
+                            self.inflater.push(chunk, &mut |bytes| {
+                                scanlines.accept(bytes, &header, on_row)
+                            })?;
+                        }
+                        // Palette, transparency and metadata chunks don't matter here
+                        _ => {}
+                    }
+                    self.chunk_remaining -= n as u32;
+                    data = rest;
+                }
+                Stage::ChunkCrc => {
+                    if self.collect(&mut data, 4) {
+                        self.stage = Stage::ChunkHeader;
+                    }
+                }
+                Stage::End => return Ok(()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the whole image was decoded, returning its header
+    pub fn finish(&self) -> Result<PngHeader, &'static str> {
+        let header = self.header.ok_or("missing IHDR")?;
+        if self.scanlines.y < header.height || !self.inflater.is_done() {
+            return Err("truncated image data");
+        }
+        Ok(header)
+    }
+
+    fn at_chunk_end(&self) -> bool {
+        self.stage == Stage::ChunkData && self.chunk_remaining == 0
+    }
+
+    /// Move bytes from `data` into `buf` until it holds `len`, returning
+    /// whether it does (and resetting it for the next use)
+    fn collect(&mut self, data: &mut &[u8], len: usize) -> bool {
+        let n = (len - self.filled).min(data.len());
+        self.buf[self.filled..self.filled + n].copy_from_slice(&data[..n]);
+        self.filled += n;
+        *data = &data[n..];
+        if self.filled < len {
+            return false;
+        }
+        self.filled = 0;
+        true
+    }
+}
+
+impl Default for PngDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parse and validate an IHDR chunk body
+fn parse_header(ihdr: &[u8; 13]) -> Result<PngHeader, &'static str> {
+    let width = u32::from_be_bytes([ihdr[0], ihdr[1], ihdr[2], ihdr[3]]);
+    let height = u32::from_be_bytes([ihdr[4], ihdr[5], ihdr[6], ihdr[7]]);
+    let (bit_depth, color_type) = (ihdr[8], ihdr[9]);
+    let (compression, filter, interlace) = (ihdr[10], ihdr[11], ihdr[12]);
+
+    if color_type != 3 {
+        return Err("not a palette-indexed PNG");
+    }
+    if !matches!(bit_depth, 1 | 2 | 4 | 8) {
+        return Err("invalid bit depth");
+    }
+    if compression != 0 || filter != 0 {
+        return Err("unknown compression or filter method");
+    }
+    if interlace != 0 {
+        return Err("interlaced PNG");
+    }
+    let header = PngHeader {
+        width,
+        height,
+        bit_depth,
+    };
+    if width == 0 || height == 0 || header.row_bytes() > MAX_ROW_BYTES {
+        return Err("unsupported image size");
+    }
+    Ok(header)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// 6x4 8-bit indexed image, rows filtered with None, Sub, Up and Paeth
+    /// and compressed by zlib with fixed Huffman codes
+    pub(crate) const FIXTURE: [u8; 121] = [
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x04, 0x08, 0x03, 0x00, 0x00, 0x00, 0x9a,
+        0xda, 0xbe, 0x71, 0x00, 0x00, 0x00, 0x12, 0x50, 0x4c, 0x54, 0x45, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xe0,
+        0x2b, 0x19, 0xa2, 0x00, 0x00, 0x00, 0x22, 0x49, 0x44, 0x41, 0x54, 0x78, 0xda, 0x63, 0x60,
+        0x60, 0x64, 0x62, 0x66, 0x61, 0x65, 0x64, 0xfd, 0x0f, 0x02, 0x4c, 0xbf, 0xff, 0xfe, 0x67,
+        0x64, 0x66, 0x65, 0x61, 0x64, 0xf8, 0xff, 0x9f, 0x81, 0x01, 0x00, 0x81, 0xb2, 0x0a, 0x16,
+        0xb3, 0xfd, 0x5c, 0x49, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60,
+        0x82,
+    ];
+
+    fn decode(png: &[u8], chunk_size: usize) -> Result<(PngHeader, Vec<u8>), &'static str> {
+        let mut decoder = PngDecoder::new();
+        let mut pixels = Vec::new();
+        for chunk in png.chunks(chunk_size) {
+            decoder.push(chunk, &mut |_, _, row| pixels.extend_from_slice(row))?;
+        }
+        Ok((decoder.finish()?, pixels))
+    }
+
+    #[test]
+    fn test_decode_in_chunks() {
+        let expected = [
+            0, 1, 2, 3, 4, 5, //
+            5, 4, 3, 2, 1, 0, //
+            0, 1, 2, 3, 4, 5, //
+            1, 1, 1, 1, 1, 1,
+        ];
+        // Every split of the file decodes the same
+        for chunk_size in [1, 2, 5, FIXTURE.len()] {
+            let (header, pixels) = decode(&FIXTURE, chunk_size).unwrap();
+            assert_eq!(
+                header,
+                PngHeader {
+                    width: 6,
+                    height: 4,
+                    bit_depth: 8
+                }
+            );
+            assert_eq!(pixels, expected);
+        }
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(decode(&FIXTURE[..90], 16), Err("truncated image data"));
+        assert_eq!(decode(b"GIF89a\0\0", 8), Err("not a PNG"));
+
+        let mut corrupt = FIXTURE;
+        corrupt[100] ^= 0x01;
+        assert!(decode(&corrupt, 16).is_err());
+
+        let mut interlaced = FIXTURE;
+        interlaced[28] = 1;
+        assert_eq!(decode(&interlaced, 16), Err("interlaced PNG"));
+    }
+}
@@ -0,0 +1,89 @@
+//! Pre-sleep power-down
+//!
+//! Deep sleep only stops the ESP32-S3; the rest of the board keeps drawing
+//! whatever it was left at. Before sleeping the firmware releases the SD card
+//! with its chip select high, switches off the PMIC rails feeding the panel
+//! and floats the pins that drove it, then reads the rail enables back to log
+//! which sleep current to expect.
+
+use crate::axp2101::Ldos;
+
+/// What was powered down before deep sleep
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PowerDownReport {
+    /// SD card released with chip select high
+    pub sd_released: bool,
+    /// LDOs still enabled after the panel rails were switched off
+    pub ldo_rails: Option<Ldos>,
+    /// Number of pins floated
+    pub pins_floated: u8,
+}
+
+impl PowerDownReport {
+    /// Expected current class while asleep
+    pub fn sleep_current(&self) -> SleepCurrent {
+        match self.ldo_rails {
+            None => SleepCurrent::Unknown,
+            Some(rails) if rails.intersects(Ldos::PANEL) => SleepCurrent::PanelPowered,
+            Some(rails) if !rails.is_empty() => SleepCurrent::AuxRails,
+            Some(_) => SleepCurrent::Minimal,
+        }
+    }
+}
+
+/// Rough sleep current, from which rails were left on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SleepCurrent {
+    /// Only the always-on supply: chip deep sleep plus PMIC quiescent current
+    Minimal,
+    /// Panel off, but other LDOs still regulating (their quiescent current
+    /// and loads)
+    AuxRails,
+    /// Panel rails still on: the panel's idle draw dominates
+    PanelPowered,
+    /// The PMIC couldn't be read back
+    Unknown,
+}
+
+impl SleepCurrent {
+    /// Order of magnitude of the expected current
+    pub fn estimate(self) -> &'static str {
+        match self {
+            SleepCurrent::Minimal => "~10s of uA",
+            SleepCurrent::AuxRails => "~100s of uA",
+            SleepCurrent::PanelPowered => "~mA",
+            SleepCurrent::Unknown => "unknown",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sleep_current() {
+        let report = |ldo_rails| PowerDownReport {
+            sd_released: true,
+            ldo_rails,
+            pins_floated: 8,
+        };
+        assert_eq!(report(None).sleep_current(), SleepCurrent::Unknown);
+        assert_eq!(
+            report(Some(Ldos::ALDOS)).sleep_current(),
+            SleepCurrent::PanelPowered
+        );
+        assert_eq!(
+            report(Some(Ldos::ALDOS.without(Ldos::PANEL))).sleep_current(),
+            SleepCurrent::AuxRails
+        );
+        assert_eq!(
+            report(Some(Ldos::from_bits(0x30))).sleep_current(),
+            SleepCurrent::AuxRails
+        );
+        assert_eq!(
+            report(Some(Ldos::NONE)).sleep_current(),
+            SleepCurrent::Minimal
+        );
+    }
+}
@@ -0,0 +1,631 @@
+//! WiFi provisioning via a SoftAP captive portal
+//!
+//! When no credentials are available (or the user asks for setup at boot), the
+//! frame starts an open access point and serves:
+//! - DHCP: hands each client an address in 192.168.4.0/24
+//! - DNS: answers every A query with the frame's address (captive portal detection)
+//! - HTTP: a tiny form that accepts the SSID and password
+//!
+//! The portal returns once valid credentials are submitted; the caller persists
+//! them and reboots.
+
+use core::fmt::Write as FmtWrite;
+use core::net::Ipv4Addr;
+
+use embassy_futures::select::{Either3, select3};
+use embassy_net::tcp::TcpSocket;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::{IpAddress, IpEndpoint, Stack};
+use embassy_time::Duration;
+use embedded_io_async::Write;
+use heapless::String;
+use log::info;
+
+/// SSID of the provisioning access point
+pub const AP_SSID: &str = "SawThat-Frame-Setup";
+
+/// Address of the frame on the provisioning network
+pub const AP_ADDRESS: Ipv4Addr = Ipv4Addr::new(192, 168, 4, 1);
+
+/// Prefix length of the provisioning network
+pub const AP_PREFIX_LEN: u8 = 24;
+
+/// Maximum SSID length (802.11)
+pub const MAX_SSID_LEN: usize = 32;
+
+/// Maximum WPA passphrase length
+pub const MAX_PASSWORD_LEN: usize = 64;
+
+const HTTP_PORT: u16 = 80;
+
+/// Largest HTTP request the portal buffers (headers and body)
+const REQUEST_SIZE: usize = 1024;
+const DNS_PORT: u16 = 53;
+const DHCP_SERVER_PORT: u16 = 67;
+const DHCP_CLIENT_PORT: u16 = 68;
+
+/// DHCP lease time handed to clients (seconds)
+const DHCP_LEASE_SECS: u32 = 3600;
+
+/// DHCP magic cookie marking the start of the options field
+const DHCP_MAGIC: [u8; 4] = [99, 130, 83, 99];
+
+/// Offset of the options field in a DHCP packet
+const DHCP_OPTIONS_OFFSET: usize = 240;
+
+/// WiFi station credentials
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WifiCredentials {
+    pub ssid: String<MAX_SSID_LEN>,
+    pub password: String<MAX_PASSWORD_LEN>,
+}
+
+impl WifiCredentials {
+    /// Build credentials from string slices (None if either is too long or the SSID is empty)
+    pub fn new(ssid: &str, password: &str) -> Option<Self> {
+        if ssid.is_empty() {
+            return None;
+        }
+        let mut credentials = Self {
+            ssid: String::new(),
+            password: String::new(),
+        };
+        credentials.ssid.push_str(ssid).ok()?;
+        credentials.password.push_str(password).ok()?;
+        Some(credentials)
+    }
+}
+
+/// Form page served for every GET (also triggers OS captive portal popups)
+const FORM_PAGE: &str = "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+<meta name=\"viewport\" content=\"width=device-width,initial-scale=1\">\
+<title>SawThat Frame Setup</title></head><body style=\"font-family:sans-serif;max-width:24em;margin:2em auto\">\
+<h2>SawThat Frame WiFi Setup</h2>\
+<form method=\"POST\" action=\"/save\">\
+<p><label>Network name (SSID)<br><input name=\"ssid\" maxlength=\"32\" required></label></p>\
+<p><label>Password<br><input name=\"password\" type=\"password\" maxlength=\"63\"></label></p>\
+<p><button type=\"submit\">Save and restart</button></p>\
+</form></body></html>";
+
+/// Confirmation page served after credentials are accepted
+const SAVED_PAGE: &str = "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+<meta name=\"viewport\" content=\"width=device-width,initial-scale=1\">\
+<title>SawThat Frame Setup</title></head><body style=\"font-family:sans-serif;max-width:24em;margin:2em auto\">\
+<h2>Saved</h2><p>The frame will now restart and connect to your network.</p>\
+</body></html>";
+
+/// Parsed captive portal request
+#[derive(Debug, PartialEq, Eq)]
+pub enum PortalRequest {
+    /// Anything other than a valid form submission - serve the form
+    Page,
+    /// Form submitted with valid credentials
+    Submit(WifiCredentials),
+}
+
+/// Run the captive portal until credentials are submitted
+pub async fn run_portal(stack: Stack<'static>) -> WifiCredentials {
+    info!(
+        "Provisioning portal up: join '{}' and open http://{}/",
+        AP_SSID, AP_ADDRESS
+    );
+
+    match select3(serve_dhcp(stack), serve_dns(stack), serve_http(stack)).await {
+        Either3::Third(credentials) => credentials,
+        // DHCP and DNS services never complete
+        Either3::First(()) | Either3::Second(()) => unreachable!(),
+    }
+}
+
+/// Minimal DHCP server handing out one address per client MAC
+async fn serve_dhcp(stack: Stack<'static>) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buf = [0u8; 1024];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buf = [0u8; 1024];
+    let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buf, &mut tx_meta, &mut tx_buf);
+
+    if let Err(e) = socket.bind(DHCP_SERVER_PORT) {
+        info!("DHCP bind failed: {:?}", e);
+        return core::future::pending().await;
+    }
+
+    let mut packet = [0u8; 576];
+    let mut reply = [0u8; 576];
+    let broadcast = IpEndpoint::new(IpAddress::Ipv4(Ipv4Addr::BROADCAST), DHCP_CLIENT_PORT);
+
+    loop {
+        let Ok((len, _)) = socket.recv_from(&mut packet).await else {
+            continue;
+        };
+        if let Some(reply_len) = build_dhcp_reply(&packet[..len], &mut reply, AP_ADDRESS)
+            && let Err(e) = socket.send_to(&reply[..reply_len], broadcast).await
+        {
+            info!("DHCP send failed: {:?}", e);
+        }
+    }
+}
+
+/// DNS responder resolving every name to the portal address
+async fn serve_dns(stack: Stack<'static>) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buf = [0u8; 1024];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buf = [0u8; 1024];
+    let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buf, &mut tx_meta, &mut tx_buf);
+
+    if let Err(e) = socket.bind(DNS_PORT) {
+        info!("DNS bind failed: {:?}", e);
+        return core::future::pending().await;
+    }
+
+    let mut query = [0u8; 512];
+    let mut response = [0u8; 512];
+
+    loop {
+        let Ok((len, meta)) = socket.recv_from(&mut query).await else {
+            continue;
+        };
+        if let Some(response_len) = build_dns_response(&query[..len], &mut response, AP_ADDRESS)
+            && let Err(e) = socket.send_to(&response[..response_len], meta).await
+        {
+            info!("DNS send failed: {:?}", e);
+        }
+    }
+}
+
+/// HTTP server for the setup form, returns once credentials are submitted
+async fn serve_http(stack: Stack<'static>) -> WifiCredentials {
+    let mut rx_buf = [0u8; 1024];
+    let mut tx_buf = [0u8; 2048];
+    let mut request = [0u8; REQUEST_SIZE];
+
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buf, &mut tx_buf);
+        socket.set_timeout(Some(Duration::from_secs(10)));
+
+        if let Err(e) = socket.accept(HTTP_PORT).await {
+            info!("Portal accept failed: {:?}", e);
+            continue;
+        }
+
+        // Read until headers and body (per Content-Length or chunked framing) are complete
+        let mut len = 0;
+        while len < request.len() {
+            match socket.read(&mut request[len..]).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => len += n,
+            }
+            if request_complete(&request[..len]) {
+                break;
+            }
+        }
+
+        let parsed = parse_request(&request[..len]);
+        let page = match parsed {
+            PortalRequest::Submit(_) => SAVED_PAGE,
+            PortalRequest::Page => FORM_PAGE,
+        };
+
+        let mut headers: String<128> = String::new();
+        let _ = write!(
+            headers,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            page.len()
+        );
+        let written = async {
+            socket.write_all(headers.as_bytes()).await?;
+            socket.write_all(page.as_bytes()).await?;
+            socket.flush().await
+        }
+        .await;
+        if let Err(e) = written {
+            info!("Portal write failed: {:?}", e);
+        }
+        socket.close();
+        let _ = socket.flush().await;
+        socket.abort();
+
+        if let PortalRequest::Submit(credentials) = parsed {
+            info!("Received credentials for '{}'", credentials.ssid.as_str());
+            return credentials;
+        }
+    }
+}
+
+/// Check whether a buffered HTTP request has its headers and full body
+pub fn request_complete(request: &[u8]) -> bool {
+    let Some(header_end) = find(request, b"\r\n\r\n") else {
+        return false;
+    };
+    let head = &request[..header_end];
+    let body = &request[header_end + 4..];
+    if is_chunked(head) {
+        return dechunk(body).is_some();
+    }
+    content_length(head).is_none_or(|expected| body.len() >= expected)
+}
+
+/// Parse an HTTP request into a portal action
+pub fn parse_request(request: &[u8]) -> PortalRequest {
+    let Ok(text) = core::str::from_utf8(request) else {
+        return PortalRequest::Page;
+    };
+    let Some((head, body)) = text.split_once("\r\n\r\n") else {
+        return PortalRequest::Page;
+    };
+    let request_line = head.lines().next().unwrap_or("");
+    if !request_line.starts_with("POST /save ") {
+        return PortalRequest::Page;
+    }
+
+    let decoded;
+    let body = if is_chunked(head.as_bytes()) {
+        decoded = dechunk(body.as_bytes());
+        match decoded.as_deref().map(core::str::from_utf8) {
+            Some(Ok(body)) => body,
+            _ => return PortalRequest::Page,
+        }
+    } else {
+        // Trim the body to Content-Length if the client sent more
+        match content_length(head.as_bytes()) {
+            Some(len) if len <= body.len() => &body[..len],
+            _ => body,
+        }
+    };
+
+    match parse_form(body) {
+        Some(credentials) => PortalRequest::Submit(credentials),
+        None => PortalRequest::Page,
+    }
+}
+
+/// Parse an `application/x-www-form-urlencoded` body with `ssid` and `password` fields
+pub fn parse_form(body: &str) -> Option<WifiCredentials> {
+    let mut ssid: String<MAX_SSID_LEN> = String::new();
+    let mut password: String<MAX_PASSWORD_LEN> = String::new();
+    let mut has_ssid = false;
+
+    for pair in body.trim().split('&') {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        match key {
+            "ssid" => {
+                ssid = url_decode(value)?;
+                has_ssid = true;
+            }
+            "password" => password = url_decode(value)?,
+            _ => {}
+        }
+    }
+
+    if !has_ssid {
+        return None;
+    }
+    WifiCredentials::new(ssid.as_str(), password.as_str())
+}
+
+/// Decode a form-urlencoded value (`+` is a space, `%XX` a byte)
+fn url_decode<const N: usize>(value: &str) -> Option<String<N>> {
+    let mut bytes: heapless::Vec<u8, N> = heapless::Vec::new();
+    let mut iter = value.bytes();
+    while let Some(b) = iter.next() {
+        let decoded = match b {
+            b'+' => b' ',
+            b'%' => {
+                let hi = hex_value(iter.next()?)?;
+                let lo = hex_value(iter.next()?)?;
+                (hi << 4) | lo
+            }
+            _ => b,
+        };
+        bytes.push(decoded).ok()?;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Find the first occurrence of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Find a header's value in a request head
+fn header_value<'a>(head: &'a [u8], header: &str) -> Option<&'a str> {
+    let head = core::str::from_utf8(head).ok()?;
+    head.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case(header)
+            .then_some(value.trim())
+    })
+}
+
+/// Extract the Content-Length header value from a request head
+fn content_length(head: &[u8]) -> Option<usize> {
+    header_value(head, "content-length")?.parse().ok()
+}
+
+/// Whether the request body uses `Transfer-Encoding: chunked`
+fn is_chunked(head: &[u8]) -> bool {
+    header_value(head, "transfer-encoding").is_some_and(|value| {
+        value
+            .split(',')
+            .any(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+    })
+}
+
+/// Decode a chunked body
+///
+/// Returns None until the terminating zero-size chunk (and any trailers) has
+/// arrived, or if the framing is malformed.
+fn dechunk(mut body: &[u8]) -> Option<heapless::Vec<u8, REQUEST_SIZE>> {
+    let mut decoded = heapless::Vec::new();
+    loop {
+        let line_end = find(body, b"\r\n")?;
+        let size_line = core::str::from_utf8(&body[..line_end]).ok()?;
+        // Chunk extensions after ';' are ignored
+        let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_hex, 16).ok()?;
+        body = &body[line_end + 2..];
+
+        if size == 0 {
+            // Trailer fields, if any, end with an empty line
+            let done = body.starts_with(b"\r\n") || find(body, b"\r\n\r\n").is_some();
+            return done.then_some(decoded);
+        }
+        decoded.extend_from_slice(body.get(..size)?).ok()?;
+        if body.get(size..size + 2)? != b"\r\n" {
+            return None;
+        }
+        body = &body[size + 2..];
+    }
+}
+
+/// Build a DNS response answering an A query with `address`
+///
+/// Non-A queries get an empty answer so clients fall back to IPv4.
+/// Returns the response length, or None if the query is malformed.
+pub fn build_dns_response(query: &[u8], out: &mut [u8], address: Ipv4Addr) -> Option<usize> {
+    const HEADER_LEN: usize = 12;
+    if query.len() < HEADER_LEN {
+        return None;
+    }
+
+    // Only standard queries (QR=0, opcode=0) with a single question
+    let flags = u16::from_be_bytes([query[2], query[3]]);
+    let qdcount = u16::from_be_bytes([query[4], query[5]]);
+    if flags & 0xF800 != 0 || qdcount != 1 {
+        return None;
+    }
+
+    // Walk the question name labels
+    let mut pos = HEADER_LEN;
+    loop {
+        let label_len = *query.get(pos)? as usize;
+        pos += 1;
+        if label_len == 0 {
+            break;
+        }
+        if label_len & 0xC0 != 0 {
+            // Compression is not valid in a question
+            return None;
+        }
+        pos += label_len;
+    }
+    let question_end = pos + 4;
+    if question_end > query.len() {
+        return None;
+    }
+    let qtype = u16::from_be_bytes([query[pos], query[pos + 1]]);
+    let answer_a = qtype == 1;
+
+    let answer_len = if answer_a { 16 } else { 0 };
+    let total_len = question_end + answer_len;
+    if out.len() < total_len {
+        return None;
+    }
+
+    // Header: same ID, response + recursion desired/available, no error
+    out[..2].copy_from_slice(&query[..2]);
+    out[2..4].copy_from_slice(&(0x8180u16 | (flags & 0x0100)).to_be_bytes());
+    out[4..6].copy_from_slice(&1u16.to_be_bytes());
+    out[6..8].copy_from_slice(&(answer_a as u16).to_be_bytes());
+    out[8..12].fill(0);
+
+    // Echo the question
+    out[HEADER_LEN..question_end].copy_from_slice(&query[HEADER_LEN..question_end]);
+
+    if answer_a {
+        let answer = &mut out[question_end..total_len];
+        answer[0..2].copy_from_slice(&0xC00Cu16.to_be_bytes()); // pointer to question name
+        answer[2..4].copy_from_slice(&1u16.to_be_bytes()); // type A
+        answer[4..6].copy_from_slice(&1u16.to_be_bytes()); // class IN
+        answer[6..10].copy_from_slice(&60u32.to_be_bytes()); // TTL
+        answer[10..12].copy_from_slice(&4u16.to_be_bytes()); // RDLENGTH
+        answer[12..16].copy_from_slice(&address.octets());
+    }
+
+    Some(total_len)
+}
+
+/// Address handed to a DHCP client, derived from its MAC so it stays stable
+pub fn dhcp_client_address(server: Ipv4Addr, mac: &[u8]) -> Ipv4Addr {
+    let [a, b, c, _] = server.octets();
+    let host = 100 + mac.last().copied().unwrap_or(0) % 100;
+    Ipv4Addr::new(a, b, c, host)
+}
+
+/// Build a DHCP OFFER/ACK for a DISCOVER/REQUEST
+///
+/// Returns the reply length, or None if the packet should be ignored.
+pub fn build_dhcp_reply(request: &[u8], out: &mut [u8], server: Ipv4Addr) -> Option<usize> {
+    const DISCOVER: u8 = 1;
+    const OFFER: u8 = 2;
+    const REQUEST: u8 = 3;
+    const ACK: u8 = 5;
+
+    if request.len() < DHCP_OPTIONS_OFFSET || request[0] != 1 {
+        return None;
+    }
+    if request[236..240] != DHCP_MAGIC {
+        return None;
+    }
+
+    // Find the message type option
+    let mut message_type = None;
+    let mut pos = DHCP_OPTIONS_OFFSET;
+    while pos < request.len() {
+        match request[pos] {
+            0 => pos += 1,
+            255 => break,
+            code => {
+                let len = *request.get(pos + 1)? as usize;
+                if code == 53 && len == 1 {
+                    message_type = Some(*request.get(pos + 2)?);
+                }
+                pos += 2 + len;
+            }
+        }
+    }
+    let reply_type = match message_type? {
+        DISCOVER => OFFER,
+        REQUEST => ACK,
+        _ => return None,
+    };
+
+    let hlen = (request[2] as usize).min(16);
+    let client = dhcp_client_address(server, &request[28..28 + hlen]);
+
+    let options_len = 3 + 6 + 6 + 6 + 6 + 6 + 1;
+    let total_len = DHCP_OPTIONS_OFFSET + options_len;
+    if out.len() < total_len {
+        return None;
+    }
+    out[..total_len].fill(0);
+
+    // BOOTP header: reply, copy htype/hlen/xid/flags/giaddr/chaddr from request
+    out[0] = 2;
+    out[1] = request[1];
+    out[2] = request[2];
+    out[4..8].copy_from_slice(&request[4..8]);
+    out[10..12].copy_from_slice(&request[10..12]);
+    out[16..20].copy_from_slice(&client.octets());
+    out[20..24].copy_from_slice(&server.octets());
+    out[24..28].copy_from_slice(&request[24..28]);
+    out[28..44].copy_from_slice(&request[28..44]);
+    out[236..240].copy_from_slice(&DHCP_MAGIC);
+
+    let server = server.octets();
+    let mut pos = DHCP_OPTIONS_OFFSET;
+    let mut option = |code: u8, data: &[u8]| {
+        out[pos] = code;
+        out[pos + 1] = data.len() as u8;
+        out[pos + 2..pos + 2 + data.len()].copy_from_slice(data);
+        pos += 2 + data.len();
+    };
+    option(53, &[reply_type]);
+    option(54, &server); // server identifier
+    option(51, &DHCP_LEASE_SECS.to_be_bytes()); // lease time
+    option(1, &[255, 255, 255, 0]); // subnet mask
+    option(3, &server); // router
+    option(6, &server); // DNS server (captive portal)
+    out[total_len - 1] = 255;
+
+    Some(total_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_form() {
+        let creds = parse_form("ssid=My+Network%21&password=p%40ss+word").unwrap();
+        assert_eq!(creds.ssid.as_str(), "My Network!");
+        assert_eq!(creds.password.as_str(), "p@ss word");
+
+        // Open network (no password)
+        let creds = parse_form("ssid=cafe&password=").unwrap();
+        assert_eq!(creds.password.as_str(), "");
+
+        assert!(parse_form("password=secret").is_none());
+        assert!(parse_form("ssid=&password=secret").is_none());
+        assert!(parse_form("ssid=%zz").is_none());
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let post = b"POST /save HTTP/1.1\r\nHost: 192.168.4.1\r\nContent-Length: 21\r\n\r\nssid=home&password=pw";
+        assert!(request_complete(post));
+        assert_eq!(
+            parse_request(post),
+            PortalRequest::Submit(WifiCredentials::new("home", "pw").unwrap())
+        );
+
+        let partial = b"POST /save HTTP/1.1\r\nContent-Length: 21\r\n\r\nssid=ho";
+        assert!(!request_complete(partial));
+
+        let chunked = b"POST /save HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nssid=\r\na;ext=1\r\nhome&passw\r\n6\r\nord=pw\r\n0\r\n\r\n";
+        assert!(request_complete(chunked));
+        assert_eq!(
+            parse_request(chunked),
+            PortalRequest::Submit(WifiCredentials::new("home", "pw").unwrap())
+        );
+        // Without the terminating chunk the body isn't complete yet
+        assert!(!request_complete(&chunked[..chunked.len() - 5]));
+        assert_eq!(
+            parse_request(&chunked[..chunked.len() - 5]),
+            PortalRequest::Page
+        );
+
+        let get = b"GET /generate_204 HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        assert!(request_complete(get));
+        assert_eq!(parse_request(get), PortalRequest::Page);
+    }
+
+    #[test]
+    fn test_dns_response() {
+        // Query for "a.io" type A
+        let query = [
+            0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, b'a',
+            0x02, b'i', b'o', 0x00, 0x00, 0x01, 0x00, 0x01,
+        ];
+        let mut out = [0u8; 64];
+        let len = build_dns_response(&query, &mut out, AP_ADDRESS).unwrap();
+        assert_eq!(len, query.len() + 16);
+        assert_eq!(&out[..2], &[0x12, 0x34]);
+        assert_eq!(&out[6..8], &[0x00, 0x01]);
+        assert_eq!(&out[len - 4..len], &[192, 168, 4, 1]);
+    }
+
+    #[test]
+    fn test_dhcp_offer() {
+        let mut discover = [0u8; 300];
+        discover[0] = 1; // BOOTREQUEST
+        discover[1] = 1; // ethernet
+        discover[2] = 6; // MAC length
+        discover[4..8].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        discover[28..34].copy_from_slice(&[0x02, 0x00, 0x00, 0x00, 0x00, 0x07]);
+        discover[236..240].copy_from_slice(&DHCP_MAGIC);
+        discover[240..243].copy_from_slice(&[53, 1, 1]);
+        discover[243] = 255;
+
+        let mut out = [0u8; 576];
+        let len = build_dhcp_reply(&discover, &mut out, AP_ADDRESS).unwrap();
+        assert_eq!(out[0], 2);
+        assert_eq!(&out[4..8], &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(&out[16..20], &[192, 168, 4, 107]);
+        assert_eq!(&out[240..243], &[53, 1, 2]);
+        assert_eq!(out[len - 1], 255);
+    }
+}
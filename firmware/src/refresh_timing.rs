@@ -0,0 +1,239 @@
+//! Learned e-paper refresh durations
+//!
+//! The panel holds BUSY low for the whole refresh, which takes a few seconds for
+//! a warm partial update and well over ten for a cold full one. Rather than
+//! polling BUSY every 200ms through all of it, the frame keeps a rolling average
+//! of how long recent refreshes took, per kind of refresh and temperature band,
+//! sleeps through most of the expected time and only then polls quickly.
+//!
+//! The averages live in RTC memory: they survive deep sleep and are relearned
+//! after a power cycle. Until a band has a sample, BUSY is polled as before.
+
+use embassy_time::{Duration, Instant, Timer};
+use log::info;
+
+/// Poll interval while the expected duration is unknown
+pub const DEFAULT_POLL_MS: u32 = 200;
+
+/// Poll interval once most of the expected duration has passed
+pub const FAST_POLL_MS: u32 = 50;
+
+/// Magic number marking initialized timings
+const TIMINGS_MAGIC: u32 = 0x5246_5348;
+
+/// Refresh kinds tracked
+const KINDS: usize = 2;
+
+/// Temperature bands: below 10°C, 10-19°C, 20-29°C, 30°C and above
+const TEMPERATURE_BANDS: usize = 4;
+
+/// Share of the expected duration slept through before polling, in eighths
+const SLEEP_EIGHTHS: u32 = 7;
+
+/// A new sample moves the average by 1/AVERAGE_WEIGHT of the difference
+const AVERAGE_WEIGHT: i32 = 4;
+
+/// Longest plausible refresh; longer samples (e.g. a stuck BUSY line) are ignored
+const MAX_REFRESH_MS: u32 = 60_000;
+
+/// What the panel is refreshing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshKind {
+    /// Whole panel
+    Full = 0,
+    /// A partial window (a horizontal slot or a changed region)
+    Partial = 1,
+}
+
+/// How to wait for a refresh in progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaitPlan {
+    /// Sleep this long before the first poll
+    pub sleep_ms: u32,
+    /// Then poll BUSY this often
+    pub poll_ms: u32,
+}
+
+/// Rolling averages of refresh durations
+#[repr(C)]
+pub struct RefreshTimings {
+    magic: u32,
+    /// Average duration in ms per kind and temperature band, 0 if unknown
+    averages_ms: [[u16; TEMPERATURE_BANDS]; KINDS],
+}
+
+impl Default for RefreshTimings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RefreshTimings {
+    pub const fn new() -> Self {
+        Self {
+            magic: TIMINGS_MAGIC,
+            averages_ms: [[0; TEMPERATURE_BANDS]; KINDS],
+        }
+    }
+
+    /// Reset timings that weren't initialized (e.g. garbage after power loss)
+    pub fn validate(&mut self) {
+        if self.magic != TIMINGS_MAGIC {
+            *self = Self::new();
+        }
+    }
+
+    /// Average duration of this kind of refresh at `temperature`, if known
+    pub fn expected_ms(&self, kind: RefreshKind, temperature: Option<i8>) -> Option<u32> {
+        let average = self.averages_ms[kind as usize][temperature_band(temperature)] as u32;
+        (average > 0).then_some(average.min(MAX_REFRESH_MS))
+    }
+
+    /// Fold a measured refresh duration into the average
+    pub fn record(&mut self, kind: RefreshKind, temperature: Option<i8>, elapsed_ms: u32) {
+        if elapsed_ms == 0 || elapsed_ms > MAX_REFRESH_MS {
+            return;
+        }
+        let average = &mut self.averages_ms[kind as usize][temperature_band(temperature)];
+        *average = if *average == 0 {
+            elapsed_ms as u16
+        } else {
+            let delta = elapsed_ms as i32 - *average as i32;
+            (*average as i32 + delta / AVERAGE_WEIGHT) as u16
+        };
+    }
+
+    /// How to wait for a refresh that started `elapsed_ms` ago
+    pub fn wait_plan(
+        &self,
+        kind: RefreshKind,
+        temperature: Option<i8>,
+        elapsed_ms: u32,
+    ) -> WaitPlan {
+        match self.expected_ms(kind, temperature) {
+            Some(expected) => WaitPlan {
+                sleep_ms: (expected * SLEEP_EIGHTHS / 8).saturating_sub(elapsed_ms),
+                poll_ms: FAST_POLL_MS,
+            },
+            None => WaitPlan {
+                sleep_ms: 0,
+                poll_ms: DEFAULT_POLL_MS,
+            },
+        }
+    }
+
+    /// Wait for a refresh started at `started` to finish, then learn its duration
+    ///
+    /// `is_busy` polls the panel's BUSY line. Returns the measured duration in ms,
+    /// or None if the refresh had already finished (only a bound is known then,
+    /// so nothing is learned).
+    pub async fn wait_until_idle(
+        &mut self,
+        kind: RefreshKind,
+        temperature: Option<i8>,
+        started: Instant,
+        mut is_busy: impl FnMut() -> bool,
+    ) -> Option<u32> {
+        if !is_busy() {
+            return None;
+        }
+        let plan = self.wait_plan(kind, temperature, started.elapsed().as_millis() as u32);
+        if plan.sleep_ms > 0 {
+            Timer::after(Duration::from_millis(plan.sleep_ms as u64)).await;
+        }
+        while is_busy() {
+            Timer::after(Duration::from_millis(plan.poll_ms as u64)).await;
+        }
+
+        let elapsed_ms = started.elapsed().as_millis() as u32;
+        info!(
+            "{:?} refresh took {}ms at {:?}C (expected {:?}ms)",
+            kind,
+            elapsed_ms,
+            temperature,
+            self.expected_ms(kind, temperature)
+        );
+        self.record(kind, temperature, elapsed_ms);
+        Some(elapsed_ms)
+    }
+}
+
+/// Temperature band index, room temperature when unknown
+fn temperature_band(temperature: Option<i8>) -> usize {
+    match temperature.unwrap_or(20) {
+        t if t < 10 => 0,
+        t if t < 20 => 1,
+        t if t < 30 => 2,
+        _ => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolling_average() {
+        let mut timings = RefreshTimings::new();
+        assert_eq!(timings.expected_ms(RefreshKind::Full, Some(22)), None);
+
+        timings.record(RefreshKind::Full, Some(22), 16_000);
+        assert_eq!(
+            timings.expected_ms(RefreshKind::Full, Some(22)),
+            Some(16_000)
+        );
+        timings.record(RefreshKind::Full, Some(25), 12_000);
+        assert_eq!(
+            timings.expected_ms(RefreshKind::Full, Some(22)),
+            Some(15_000)
+        );
+
+        // Other kinds and bands are tracked separately, unknown temperature is room
+        assert_eq!(timings.expected_ms(RefreshKind::Partial, Some(22)), None);
+        assert_eq!(timings.expected_ms(RefreshKind::Full, Some(5)), None);
+        assert_eq!(timings.expected_ms(RefreshKind::Full, None), Some(15_000));
+
+        // Implausible samples are ignored
+        timings.record(RefreshKind::Full, Some(22), 0);
+        timings.record(RefreshKind::Full, Some(22), 120_000);
+        assert_eq!(
+            timings.expected_ms(RefreshKind::Full, Some(22)),
+            Some(15_000)
+        );
+    }
+
+    #[test]
+    fn test_wait_plan() {
+        let mut timings = RefreshTimings::new();
+        assert_eq!(
+            timings.wait_plan(RefreshKind::Partial, Some(-5), 0),
+            WaitPlan {
+                sleep_ms: 0,
+                poll_ms: DEFAULT_POLL_MS
+            }
+        );
+
+        timings.record(RefreshKind::Partial, Some(-5), 8_000);
+        assert_eq!(
+            timings.wait_plan(RefreshKind::Partial, Some(-5), 3_000),
+            WaitPlan {
+                sleep_ms: 4_000,
+                poll_ms: FAST_POLL_MS
+            }
+        );
+        // Work overlapping the refresh can use up the sleep
+        assert_eq!(
+            timings
+                .wait_plan(RefreshKind::Partial, Some(-5), 9_000)
+                .sleep_ms,
+            0
+        );
+
+        let mut garbage = RefreshTimings {
+            magic: 0,
+            averages_ms: [[u16::MAX; TEMPERATURE_BANDS]; KINDS],
+        };
+        garbage.validate();
+        assert_eq!(garbage.expected_ms(RefreshKind::Full, None), None);
+    }
+}
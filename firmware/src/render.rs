@@ -0,0 +1,371 @@
+//! Image bodies to framebuffer
+//!
+//! Renders the images the server sends, PNG or packed 4bpp, into a
+//! [`Framebuffer`] slot. Nothing here touches the network or the panel, so the
+//! host-side simulator draws frames with the same code as the device.
+
+use core::fmt::Write;
+
+use heapless::String;
+use log::info;
+
+use crate::epd::{Color, HEIGHT, WIDTH};
+use crate::framebuffer::Framebuffer;
+use crate::png::{PngDecoder, PngHeader};
+use crate::widget::Orientation;
+
+/// Query asking for vertical PNGs already rotated into the 800x480 panel geometry
+const ROTATED_QUERY: &str = "?rotate=ccw90";
+/// Signature at the start of every PNG file
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Why an image body couldn't be rendered
+#[derive(Debug)]
+pub enum RenderError {
+    /// PNG failed to decode, or doesn't have the panel's geometry
+    Png(&'static str),
+    /// Packed 4bpp body did not match the panel
+    Epd(&'static str),
+}
+
+/// Blank a slot whose image failed, so it doesn't show half-drawn rows
+pub(crate) fn fill_half(framebuffer: &mut Framebuffer, x_offset: u32) {
+    if x_offset == 0 {
+        framebuffer.fill_left_half(Color::White);
+    } else {
+        framebuffer.fill_right_half(Color::White);
+    }
+}
+
+/// Decode a PNG image into the framebuffer, row by row
+pub(crate) fn decode_png_to_framebuffer(
+    png_data: &[u8],
+    framebuffer: &mut Framebuffer,
+    x_offset: u32,
+    orientation: Orientation,
+) -> Result<(), RenderError> {
+    let mut decoder = PngDecoder::new();
+    PngRows::new(framebuffer, x_offset, orientation).push(&mut decoder, png_data)?;
+    let header = decoder.finish().map_err(RenderError::Png)?;
+
+    info!(
+        "PNG decode complete, {}x{} {}-bit processed",
+        header.width, header.height, header.bit_depth
+    );
+
+    Ok(())
+}
+
+/// Writes the rows a [`PngDecoder`] completes into a framebuffer slot
+///
+/// The image's geometry is checked (see [`check_png_header`]) as its first row
+/// comes out, before anything is written, so an image that doesn't fit leaves
+/// the framebuffer untouched.
+struct PngRows<'a> {
+    framebuffer: &'a mut Framebuffer,
+    x_offset: u32,
+    orientation: Orientation,
+    /// Whether the image fits, once its first row is decoded
+    fits: Option<bool>,
+}
+
+impl<'a> PngRows<'a> {
+    fn new(framebuffer: &'a mut Framebuffer, x_offset: u32, orientation: Orientation) -> Self {
+        Self {
+            framebuffer,
+            x_offset,
+            orientation,
+            fits: None,
+        }
+    }
+
+    /// Feed a chunk of the PNG file through `decoder`, writing the rows it
+    /// completes. Fails once the image turns out not to fit.
+    fn push(&mut self, decoder: &mut PngDecoder, data: &[u8]) -> Result<(), RenderError> {
+        let Self {
+            framebuffer,
+            x_offset,
+            orientation,
+            fits,
+        } = self;
+        decoder
+            .push(data, &mut |header, y, row| {
+                if *fits.get_or_insert_with(|| check_png_header(header, *orientation).is_ok()) {
+                    write_png_row(framebuffer, header, y, row, *x_offset, *orientation);
+                }
+            })
+            .map_err(RenderError::Png)?;
+        match (*fits, decoder.header()) {
+            (Some(false), Some(header)) => check_png_header(&header, *orientation),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Write one decoded PNG row (palette indices) to the framebuffer
+/// For horizontal: image is 400x480, written directly with flip
+/// For vertical: image is 800x480, already rotated by the server, written directly
+pub(crate) fn write_png_row(
+    framebuffer: &mut Framebuffer,
+    header: &PngHeader,
+    y: u32,
+    row: &[u8],
+    x_offset: u32,
+    orientation: Orientation,
+) {
+    let width = header.width as usize;
+    // Bandwidth-reduced images are 4-bit, packed two pixels per byte
+    let bits = header.bit_depth as usize;
+
+    match orientation {
+        Orientation::Horizontal => {
+            // Horizontal: 400x480 image, flip and write rows directly
+            // (full-width 800x480 images cover both slots)
+            let x_offset = if width > WIDTH as usize / 2 {
+                0
+            } else {
+                x_offset
+            };
+            let width = width.min(WIDTH as usize);
+            let mut row_buf = [0u8; WIDTH as usize];
+            for i in 0..width {
+                row_buf[width - 1 - i] = indexed_pixel(row, i, bits);
+            }
+            let flipped_y = header.height - 1 - y;
+            framebuffer.write_row(x_offset, flipped_y, &row_buf[..width]);
+        }
+        Orientation::Vertical => {
+            // Vertical: pre-rotated by the server, so rows are panel rows
+            let width = width.min(WIDTH as usize);
+            let mut row_buf = [0u8; WIDTH as usize];
+            for (x, px) in row_buf[..width].iter_mut().enumerate() {
+                *px = indexed_pixel(row, x, bits);
+            }
+            framebuffer.write_row(0, y, &row_buf[..width]);
+        }
+    }
+}
+
+/// Check that an image has the geometry `write_png_row` expects
+///
+/// Vertical images must come pre-rotated (see [`image_path`]); a 480x800 one
+/// is from a server or SD cache that predates rotation.
+pub(crate) fn check_png_header(
+    header: &PngHeader,
+    orientation: Orientation,
+) -> Result<(), RenderError> {
+    match orientation {
+        Orientation::Vertical if header.width != WIDTH => Err(RenderError::Png(
+            "vertical image not rotated to panel geometry",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Request path of an item's image, asking for vertical images pre-rotated
+///
+/// `None` if the path doesn't fit.
+pub fn image_path(
+    widget_name: &str,
+    item_path: &str,
+    orientation: Orientation,
+) -> Option<String<256>> {
+    let mut path: String<256> = String::new();
+    write!(
+        &mut path,
+        "/{}/{}/{}",
+        widget_name,
+        orientation.as_str(),
+        item_path
+    )
+    .ok()?;
+    if orientation == Orientation::Vertical {
+        path.push_str(ROTATED_QUERY).ok()?;
+    }
+    Some(path)
+}
+
+/// Whether an image body is a PNG (rather than packed 4bpp data)
+pub(crate) fn is_png(data: &[u8]) -> bool {
+    data.starts_with(PNG_SIGNATURE)
+}
+
+/// Whether a cached or fetched image body has the geometry the renderer takes
+///
+/// Only the PNG header (or the packed body length) is inspected, so this is
+/// cheap enough to run on every SD cache hit: vertical images cached before the
+/// server rotated them fail it and should be fetched again.
+pub fn fits_panel(data: &[u8], orientation: Orientation) -> bool {
+    if !is_png(data) {
+        return packed_width(data.len(), orientation).is_some();
+    }
+    // Signature, IHDR length and type, then the big-endian width
+    let Some(width) = data.get(16..20) else {
+        return false;
+    };
+    let width = u32::from_be_bytes([width[0], width[1], width[2], width[3]]);
+    orientation != Orientation::Vertical || width == WIDTH
+}
+
+/// Width in panel pixels of a packed 4bpp body, if it is a layout the panel takes:
+/// a half or the whole panel in horizontal mode, the whole panel in vertical mode
+fn packed_width(len: usize, orientation: Orientation) -> Option<u32> {
+    let row_bytes = len / HEIGHT as usize;
+    if row_bytes * HEIGHT as usize != len {
+        return None;
+    }
+    let width = row_bytes as u32 * 2;
+    match orientation {
+        Orientation::Horizontal if width == WIDTH / 2 || width == WIDTH => Some(width),
+        Orientation::Vertical if width == WIDTH => Some(width),
+        _ => None,
+    }
+}
+
+/// Copy a packed 4bpp body into the framebuffer
+///
+/// The server has already remapped and rotated it into panel layout, so rows are
+/// copied as they are: halves at `x_offset`, full-panel bodies over everything.
+pub(crate) fn copy_packed_to_framebuffer(
+    data: &[u8],
+    framebuffer: &mut Framebuffer,
+    x_offset: u32,
+    orientation: Orientation,
+) -> Result<(), RenderError> {
+    let width = packed_width(data.len(), orientation)
+        .ok_or(RenderError::Epd("packed image size mismatch"))?;
+    let x_offset = if width == WIDTH { 0 } else { x_offset };
+    framebuffer.write_packed_rows(x_offset, width, data);
+    info!("Packed image copied, {}x{}", width, HEIGHT);
+    Ok(())
+}
+
+/// Render a PNG or packed 4bpp image body to the framebuffer at the specified slot.
+///
+/// For horizontal mode: slot 0 = left (x_offset=0), slot 1 = right (x_offset=400),
+/// or the whole panel for full-width items (the slot is ignored)
+/// For vertical mode: full screen render
+pub fn render_image_to_framebuffer(
+    data: &[u8],
+    framebuffer: &mut Framebuffer,
+    slot: u8,
+    orientation: Orientation,
+) -> Result<(), RenderError> {
+    let x_offset = if orientation == Orientation::Vertical || slot == 0 {
+        0
+    } else {
+        400
+    };
+
+    if !is_png(data) {
+        return copy_packed_to_framebuffer(data, framebuffer, x_offset, orientation);
+    }
+
+    decode_png_to_framebuffer(data, framebuffer, x_offset, orientation)
+}
+
+/// Palette index of pixel `x` in a row of `bits`-per-pixel indexed data
+/// (sub-byte depths are packed with the leftmost pixel in the high bits)
+fn indexed_pixel(row: &[u8], x: usize, bits: usize) -> u8 {
+    if bits >= 8 {
+        return row[x];
+    }
+    let per_byte = 8 / bits;
+    let shift = 8 - bits * (x % per_byte + 1);
+    (row[x / per_byte] >> shift) & ((1 << bits) - 1) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epd::BUFFER_SIZE;
+
+    #[test]
+    fn test_indexed_pixel() {
+        let row = [0x12, 0x30];
+        assert_eq!(indexed_pixel(&row, 0, 4), 1);
+        assert_eq!(indexed_pixel(&row, 1, 4), 2);
+        assert_eq!(indexed_pixel(&row, 2, 4), 3);
+        assert_eq!(indexed_pixel(&row, 1, 8), 0x30);
+        assert_eq!(indexed_pixel(&[0b0100_0000], 1, 1), 1);
+    }
+
+    #[test]
+    fn test_packed_width() {
+        let half = (WIDTH / 2 * HEIGHT / 2) as usize;
+        assert_eq!(packed_width(half, Orientation::Horizontal), Some(400));
+        assert_eq!(
+            packed_width(BUFFER_SIZE, Orientation::Horizontal),
+            Some(800)
+        );
+        assert_eq!(packed_width(BUFFER_SIZE, Orientation::Vertical), Some(800));
+        assert_eq!(packed_width(half, Orientation::Vertical), None);
+        assert_eq!(packed_width(half + 1, Orientation::Horizontal), None);
+
+        assert!(is_png(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        // Packed color codes never reach 0x89
+        assert!(!is_png(&[0x11; 16]));
+    }
+
+    #[test]
+    fn test_fits_panel() {
+        let png = |width: u32| {
+            let mut data = [0u8; 24];
+            data[..8].copy_from_slice(PNG_SIGNATURE);
+            data[8..16].copy_from_slice(b"\0\0\0\rIHDR");
+            data[16..20].copy_from_slice(&width.to_be_bytes());
+            data[20..24].copy_from_slice(&HEIGHT.to_be_bytes());
+            data
+        };
+        assert!(fits_panel(&png(WIDTH), Orientation::Vertical));
+        assert!(fits_panel(&png(WIDTH / 2), Orientation::Horizontal));
+        // Cached before the server rotated vertical images
+        assert!(!fits_panel(&png(HEIGHT), Orientation::Vertical));
+        assert!(!fits_panel(&png(WIDTH)[..12], Orientation::Vertical));
+        assert!(fits_panel(&[0x11; BUFFER_SIZE], Orientation::Vertical));
+    }
+
+    #[test]
+    fn test_png_rows_check_geometry_first() {
+        // 6 pixels wide, so not rotated to the panel's geometry when vertical
+        let png = &crate::png::tests::FIXTURE;
+        let mut framebuffer = Framebuffer::new();
+        let blank = framebuffer.as_slice().to_vec();
+        let mut decoder = PngDecoder::new();
+        let mut rows = PngRows::new(&mut framebuffer, 0, Orientation::Vertical);
+        assert!(matches!(
+            rows.push(&mut decoder, png),
+            Err(RenderError::Png(
+                "vertical image not rotated to panel geometry"
+            ))
+        ));
+        assert!(framebuffer.as_slice() == &blank[..]);
+
+        let mut decoder = PngDecoder::new();
+        PngRows::new(&mut framebuffer, 0, Orientation::Horizontal)
+            .push(&mut decoder, png)
+            .unwrap();
+        assert!(framebuffer.as_slice() != &blank[..]);
+    }
+
+    #[test]
+    fn test_image_path() {
+        let path = |orientation| image_path("concerts", "2024-06-01-phish", orientation).unwrap();
+        assert_eq!(
+            path(Orientation::Horizontal).as_str(),
+            "/concerts/horiz/2024-06-01-phish"
+        );
+        assert_eq!(
+            path(Orientation::Vertical).as_str(),
+            "/concerts/vert/2024-06-01-phish?rotate=ccw90"
+        );
+        assert!(
+            image_path(
+                "concerts",
+                core::str::from_utf8(&[b'x'; 256]).unwrap(),
+                Orientation::Vertical
+            )
+            .is_none()
+        );
+    }
+}
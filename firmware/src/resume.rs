@@ -0,0 +1,263 @@
+//! Resuming wakes cut short by a reset
+//!
+//! The rotation is only saved to the sleep state once a wake is done, and that
+//! RTC memory is set up afresh by any reset other than a deep sleep wake. A
+//! panic or the watchdog firing mid-pass used to start the rotation over with a
+//! new shuffle, repeating items, and one landing between the refresh and the
+//! save could show the same item twice.
+//!
+//! So each pass marks what it's doing in a [`WakeProgress`] kept in RTC memory
+//! that survives resets: the rotation it belongs to, the item and slot it's
+//! drawing and whether the refresh has started. The mark is cleared when the
+//! wake saves its state. Finding one at boot means the last wake never got
+//! there, and [`Interrupted::resume_index`] picks where the rotation carries
+//! on, the same way every time.
+
+use crate::screenshot::Crc32;
+use crate::widget::Orientation;
+
+/// Magic number to validate the progress mark
+const WAKE_PROGRESS_MAGIC: u32 = 0x5052_4F47;
+
+/// No pass in progress
+const STAGE_IDLE: u8 = 0;
+const STAGE_RENDER: u8 = 1;
+const STAGE_REFRESH: u8 = 2;
+
+/// How far an interrupted pass got
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Fetching and drawing its images; the panel wasn't touched
+    Render,
+    /// The panel was refreshing, so it may be left half drawn
+    Refresh,
+}
+
+/// A pass of the display loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pass {
+    /// Shuffle seed of the rotation
+    pub shuffle_seed: u64,
+    /// Hash of the rotation's items
+    pub data_hash: u32,
+    pub total_items: usize,
+    pub orientation: Orientation,
+    /// Slot a partial refresh draws in horizontal mode (0 = left, 1 = right)
+    pub slot: u8,
+    /// Index of the (first) item drawn
+    pub item: usize,
+}
+
+/// A pass the last wake didn't finish
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupted {
+    pub pass: Pass,
+    pub stage: Stage,
+}
+
+impl Interrupted {
+    /// Whether the interrupted pass belongs to a rotation over these items
+    pub fn matches(&self, total_items: usize, data_hash: u32) -> bool {
+        self.pass.total_items == total_items && self.pass.data_hash == data_hash
+    }
+
+    /// Index the rotation carries on from
+    ///
+    /// A pass cut short while refreshing shows its item again, since the panel
+    /// may be half drawn. One cut short before then skips it: fetching or
+    /// decoding that item may be what crashed, and retrying it would crash
+    /// every wake.
+    pub fn resume_index(&self) -> usize {
+        match self.stage {
+            Stage::Refresh => self.pass.item,
+            Stage::Render => (self.pass.item + 1) % self.pass.total_items.max(1),
+        }
+    }
+}
+
+/// The pass in progress, in RTC memory that survives resets
+///
+/// Like the crash log this memory isn't initialized at boot, so
+/// [`validate`](Self::validate) clears whatever fails the magic and checksum.
+#[repr(C)]
+pub struct WakeProgress {
+    /// Magic number to validate state
+    magic: u32,
+    /// CRC32 over the fields below
+    crc: u32,
+    shuffle_seed: u64,
+    data_hash: u32,
+    total_items: u32,
+    item: u32,
+    orientation: u8,
+    slot: u8,
+    /// `STAGE_IDLE`, `STAGE_RENDER` or `STAGE_REFRESH`
+    stage: u8,
+}
+
+impl WakeProgress {
+    pub const fn new() -> Self {
+        Self {
+            magic: WAKE_PROGRESS_MAGIC,
+            crc: 0,
+            shuffle_seed: 0,
+            data_hash: 0,
+            total_items: 0,
+            item: 0,
+            orientation: 0,
+            slot: 0,
+            stage: STAGE_IDLE,
+        }
+    }
+
+    /// Reset the mark if it doesn't hold valid data
+    pub fn validate(&mut self) {
+        let valid = self.magic == WAKE_PROGRESS_MAGIC
+            && match self.stage {
+                STAGE_IDLE => true,
+                STAGE_RENDER | STAGE_REFRESH => self.crc == self.checksum(),
+                _ => false,
+            };
+        if !valid {
+            *self = Self::new();
+        }
+    }
+
+    /// Mark a pass as started
+    pub fn begin(&mut self, pass: &Pass) {
+        self.magic = WAKE_PROGRESS_MAGIC;
+        self.shuffle_seed = pass.shuffle_seed;
+        self.data_hash = pass.data_hash;
+        self.total_items = pass.total_items as u32;
+        self.item = pass.item as u32;
+        self.orientation = pass.orientation as u8;
+        self.slot = pass.slot;
+        self.stage = STAGE_RENDER;
+        self.crc = self.checksum();
+    }
+
+    /// Mark the current pass's refresh as started
+    pub fn refresh_started(&mut self) {
+        if self.stage == STAGE_RENDER {
+            self.stage = STAGE_REFRESH;
+            self.crc = self.checksum();
+        }
+    }
+
+    /// Clear the mark once the wake's state is saved
+    pub fn finish(&mut self) {
+        self.stage = STAGE_IDLE;
+    }
+
+    /// The pass the last wake was in when it was cut short, if any
+    pub fn interrupted(&self) -> Option<Interrupted> {
+        let stage = match self.stage {
+            STAGE_RENDER => Stage::Render,
+            STAGE_REFRESH => Stage::Refresh,
+            _ => return None,
+        };
+        Some(Interrupted {
+            pass: Pass {
+                shuffle_seed: self.shuffle_seed,
+                data_hash: self.data_hash,
+                total_items: self.total_items as usize,
+                orientation: Orientation::from_u8(self.orientation),
+                slot: self.slot,
+                item: self.item as usize,
+            },
+            stage,
+        })
+    }
+
+    /// CRC32 over the pass fields (excluding padding)
+    fn checksum(&self) -> u32 {
+        let mut crc = Crc32::new();
+        crc.update(&self.shuffle_seed.to_le_bytes());
+        crc.update(&self.data_hash.to_le_bytes());
+        crc.update(&self.total_items.to_le_bytes());
+        crc.update(&self.item.to_le_bytes());
+        crc.update(&[self.orientation, self.slot, self.stage]);
+        crc.finish()
+    }
+}
+
+impl Default for WakeProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pass(item: usize) -> Pass {
+        Pass {
+            shuffle_seed: 0x1234_5678_9abc_def0,
+            data_hash: 0xdead_beef,
+            total_items: 10,
+            orientation: Orientation::Vertical,
+            slot: 1,
+            item,
+        }
+    }
+
+    #[test]
+    fn test_interrupted() {
+        let mut progress = WakeProgress::new();
+        assert_eq!(progress.interrupted(), None);
+
+        progress.begin(&pass(4));
+        let interrupted = progress.interrupted().unwrap();
+        assert_eq!(interrupted.pass, pass(4));
+        assert_eq!(interrupted.stage, Stage::Render);
+        assert!(interrupted.matches(10, 0xdead_beef));
+        assert!(!interrupted.matches(11, 0xdead_beef));
+
+        progress.refresh_started();
+        assert_eq!(progress.interrupted().unwrap().stage, Stage::Refresh);
+
+        // A finished wake leaves nothing to resume
+        progress.finish();
+        progress.validate();
+        assert_eq!(progress.interrupted(), None);
+        progress.refresh_started();
+        assert_eq!(progress.interrupted(), None);
+    }
+
+    #[test]
+    fn test_resume_index() {
+        let mut progress = WakeProgress::new();
+
+        // Cut short while refreshing: the item is shown again
+        progress.begin(&pass(4));
+        progress.refresh_started();
+        assert_eq!(progress.interrupted().unwrap().resume_index(), 4);
+
+        // Cut short while rendering: the item is skipped, wrapping around
+        progress.begin(&pass(4));
+        assert_eq!(progress.interrupted().unwrap().resume_index(), 5);
+        progress.begin(&pass(9));
+        assert_eq!(progress.interrupted().unwrap().resume_index(), 0);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut progress = WakeProgress::new();
+        progress.begin(&pass(4));
+        progress.validate();
+        assert!(progress.interrupted().is_some());
+
+        // A corrupted mark is dropped
+        progress.item = 5;
+        progress.validate();
+        assert_eq!(progress.interrupted(), None);
+
+        // As is the memory's content at power-on
+        let mut progress = WakeProgress::new();
+        progress.magic = 0x1234_5678;
+        progress.stage = STAGE_REFRESH;
+        progress.validate();
+        assert_eq!(progress.interrupted(), None);
+    }
+}
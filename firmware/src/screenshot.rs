@@ -0,0 +1,244 @@
+//! Framebuffer screenshots as indexed PNG
+//!
+//! The framebuffer's 4bpp layout (high nibble = left pixel) is exactly PNG's
+//! 4-bit packed row format, so each row is emitted as-is behind a filter byte.
+//! Image data is wrapped in uncompressed (stored) deflate blocks, so no
+//! compressor is needed and the output is streamed without extra buffering.
+
+use crate::epd::{HEIGHT, WIDTH};
+use crate::framebuffer::Framebuffer;
+
+/// PNG file signature
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Palette indexed by EPD 4-bit color value (index 4 is unused by the panel)
+pub const EPD_PALETTE: [[u8; 3]; 7] = [
+    [2, 2, 2],       // 0: Black
+    [232, 232, 232], // 1: White
+    [205, 202, 0],   // 2: Yellow
+    [135, 19, 0],    // 3: Red
+    [232, 232, 232], // 4: Unused (white)
+    [5, 64, 158],    // 5: Blue
+    [39, 102, 60],   // 6: Green
+];
+
+/// Bytes per framebuffer row (two pixels per byte)
+const ROW_BYTES: usize = WIDTH as usize / 2;
+
+/// Bytes per PNG scanline (filter byte + row)
+const SCANLINE_BYTES: usize = ROW_BYTES + 1;
+
+/// Uncompressed image data size
+const RAW_SIZE: usize = SCANLINE_BYTES * HEIGHT as usize;
+
+/// Maximum payload of a stored deflate block
+const STORED_BLOCK_MAX: usize = 65535;
+
+/// Number of stored deflate blocks needed for the image data
+const STORED_BLOCKS: usize = RAW_SIZE.div_ceil(STORED_BLOCK_MAX);
+
+/// zlib stream size: header + per-block headers + data + Adler-32
+const ZLIB_SIZE: usize = 2 + STORED_BLOCKS * 5 + RAW_SIZE + 4;
+
+/// Total encoded PNG size (constant for the panel resolution)
+pub const PNG_SIZE: usize = PNG_SIGNATURE.len()
+    + (12 + 13) // IHDR
+    + (12 + EPD_PALETTE.len() * 3) // PLTE
+    + (12 + ZLIB_SIZE) // IDAT
+    + 12; // IEND
+
+/// Encode the framebuffer as a 4-bit indexed PNG, streaming bytes to `write`
+pub fn encode_png<E>(
+    framebuffer: &Framebuffer,
+    mut write: impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<(), E> {
+    write(&PNG_SIGNATURE)?;
+
+    // IHDR: width, height, bit depth 4, color type 3 (indexed), default methods
+    let mut ihdr = [0u8; 13];
+    ihdr[0..4].copy_from_slice(&WIDTH.to_be_bytes());
+    ihdr[4..8].copy_from_slice(&HEIGHT.to_be_bytes());
+    ihdr[8] = 4;
+    ihdr[9] = 3;
+    write_chunk(&mut write, b"IHDR", &ihdr)?;
+
+    let mut plte = [0u8; EPD_PALETTE.len() * 3];
+    for (i, rgb) in EPD_PALETTE.iter().enumerate() {
+        plte[i * 3..i * 3 + 3].copy_from_slice(rgb);
+    }
+    write_chunk(&mut write, b"PLTE", &plte)?;
+
+    // IDAT: zlib stream of stored deflate blocks, written scanline by scanline
+    let mut chunk = ChunkWriter::start(&mut write, b"IDAT", ZLIB_SIZE)?;
+    chunk.write(&[0x78, 0x01])?;
+
+    let rows = framebuffer.as_slice().chunks_exact(ROW_BYTES);
+    let mut adler = Adler32::new();
+    let mut block_remaining = 0usize;
+    let mut raw_remaining = RAW_SIZE;
+
+    for row in rows {
+        // Each scanline is a filter byte (0 = none) followed by the row
+        for part in [&[0u8][..], row] {
+            let mut part = part;
+            while !part.is_empty() {
+                if block_remaining == 0 {
+                    // Start a new stored block: BFINAL flag, LEN, NLEN
+                    let len = raw_remaining.min(STORED_BLOCK_MAX);
+                    let final_flag = (len == raw_remaining) as u8;
+                    let len16 = len as u16;
+                    let mut header = [final_flag, 0, 0, 0, 0];
+                    header[1..3].copy_from_slice(&len16.to_le_bytes());
+                    header[3..5].copy_from_slice(&(!len16).to_le_bytes());
+                    chunk.write(&header)?;
+                    block_remaining = len;
+                }
+                let n = part.len().min(block_remaining);
+                chunk.write(&part[..n])?;
+                adler.update(&part[..n]);
+                block_remaining -= n;
+                raw_remaining -= n;
+                part = &part[n..];
+            }
+        }
+    }
+
+    chunk.write(&adler.finish().to_be_bytes())?;
+    chunk.finish()?;
+
+    write_chunk(&mut write, b"IEND", &[])
+}
+
+/// Write a complete chunk (length, type, data, CRC)
+fn write_chunk<E>(
+    write: &mut impl FnMut(&[u8]) -> Result<(), E>,
+    kind: &[u8; 4],
+    data: &[u8],
+) -> Result<(), E> {
+    let mut chunk = ChunkWriter::start(write, kind, data.len())?;
+    chunk.write(data)?;
+    chunk.finish()
+}
+
+/// Streams a chunk of known length, accumulating its CRC
+struct ChunkWriter<'a, W> {
+    write: &'a mut W,
+    crc: Crc32,
+}
+
+impl<'a, E, W: FnMut(&[u8]) -> Result<(), E>> ChunkWriter<'a, W> {
+    fn start(write: &'a mut W, kind: &[u8; 4], len: usize) -> Result<Self, E> {
+        write(&(len as u32).to_be_bytes())?;
+        write(kind)?;
+        let mut crc = Crc32::new();
+        crc.update(kind);
+        Ok(Self { write, crc })
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), E> {
+        self.crc.update(data);
+        (self.write)(data)
+    }
+
+    fn finish(self) -> Result<(), E> {
+        (self.write)(&self.crc.finish().to_be_bytes())
+    }
+}
+
+/// CRC-32 (IEEE) as used by PNG chunks
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Self(0xFFFF_FFFF)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 ^= byte as u32;
+            for _ in 0..8 {
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Adler-32 checksum as used by zlib
+pub(crate) struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    const MOD: u32 = 65521;
+
+    pub(crate) fn new() -> Self {
+        Self { a: 1, b: 0 }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.a = (self.a + byte as u32) % Self::MOD;
+            self.b = (self.b + self.a) % Self::MOD;
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epd::Color;
+
+    #[test]
+    fn test_checksums() {
+        let mut crc = Crc32::new();
+        crc.update(b"IEND");
+        assert_eq!(crc.finish(), 0xAE42_6082);
+
+        let mut adler = Adler32::new();
+        adler.update(b"Wikipedia");
+        assert_eq!(adler.finish(), 0x11E6_0398);
+    }
+
+    #[test]
+    fn test_encode_png() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.fill_left_half(Color::Red);
+
+        let mut png = alloc::vec::Vec::new();
+        encode_png(&framebuffer, |data| {
+            png.extend_from_slice(data);
+            Ok::<(), ()>(())
+        })
+        .unwrap();
+
+        assert_eq!(png.len(), PNG_SIZE);
+
+        // Rows decode back to the framebuffer's packed color codes
+        let mut decoder = crate::png::PngDecoder::new();
+        let mut rows = framebuffer.as_slice().chunks_exact(ROW_BYTES);
+        decoder
+            .push(&png, &mut |_, _, row| assert_eq!(Some(row), rows.next()))
+            .unwrap();
+        let header = decoder.finish().unwrap();
+        assert_eq!(
+            (header.width, header.height, header.bit_depth),
+            (WIDTH, HEIGHT, 4)
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! SHTC3 temperature sensor driver
+//!
+//! The board's SHTC3 sits on the same I2C bus as the PMIC and reads the air
+//! inside the frame, unlike the PMIC's die sensor, which runs warmer than the
+//! room. The sensor sleeps between reads, drawing under 1µA, so each read wakes
+//! it, measures and puts it back to sleep.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+
+/// SHTC3 I2C address
+pub const SHTC3_ADDR: u8 = 0x70;
+
+/// Leave sleep mode
+const WAKEUP: u16 = 0x3517;
+/// Enter sleep mode
+const SLEEP: u16 = 0xB098;
+/// Measure, temperature first, in normal mode without clock stretching
+const MEASURE_T_FIRST: u16 = 0x7866;
+
+/// Longest time to leave sleep mode
+const WAKEUP_US: u32 = 240;
+/// Longest normal mode measurement
+const MEASURE_US: u32 = 12_100;
+
+/// A failed read
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    I2c(E),
+    /// The reading failed its checksum
+    Crc,
+}
+
+/// SHTC3 on an I2C bus
+pub struct Shtc3<I> {
+    i2c: I,
+}
+
+impl<I: I2c> Shtc3<I> {
+    pub fn new(i2c: I) -> Self {
+        Self { i2c }
+    }
+
+    /// Temperature in whole degrees Celsius, leaving the sensor asleep
+    pub fn temperature_c(&mut self, delay: &mut impl DelayNs) -> Result<i8, Error<I::Error>> {
+        self.command(WAKEUP)?;
+        delay.delay_us(WAKEUP_US);
+        self.command(MEASURE_T_FIRST)?;
+        delay.delay_us(MEASURE_US);
+        // The temperature word and its checksum (the humidity isn't used)
+        let mut reading = [0u8; 3];
+        self.i2c
+            .read(SHTC3_ADDR, &mut reading)
+            .map_err(Error::I2c)?;
+        self.command(SLEEP)?;
+
+        if crc8(&reading[..2]) != reading[2] {
+            return Err(Error::Crc);
+        }
+        Ok(temperature_from_raw(u16::from_be_bytes([
+            reading[0], reading[1],
+        ])))
+    }
+
+    fn command(&mut self, command: u16) -> Result<(), Error<I::Error>> {
+        self.i2c
+            .write(SHTC3_ADDR, &command.to_be_bytes())
+            .map_err(Error::I2c)
+    }
+}
+
+/// CRC-8 the sensor appends to each word (polynomial 0x31, initial 0xFF)
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0xFFu8;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Convert a temperature reading (-45°C at 0, 175°C over the full scale),
+/// rounded to the nearest degree
+fn temperature_from_raw(raw: u16) -> i8 {
+    let centi = -4500 + 17500 * raw as i32 / 65536;
+    let celsius = (centi + 50).div_euclid(100);
+    celsius.clamp(i8::MIN as i32, i8::MAX as i32) as i8
+}
+
+/// Reads checked against a mock I2C bus (run by the host-tests crate)
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use embedded_hal::i2c::ErrorKind;
+    use embedded_hal_mock::eh1::delay::NoopDelay;
+    use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction};
+
+    use super::*;
+
+    /// Wake, measure and read back `reading`
+    fn measurement(reading: [u8; 3]) -> [Transaction; 3] {
+        [
+            Transaction::write(SHTC3_ADDR, vec![0x35, 0x17]),
+            Transaction::write(SHTC3_ADDR, vec![0x78, 0x66]),
+            Transaction::read(SHTC3_ADDR, reading.to_vec()),
+        ]
+    }
+
+    #[test]
+    fn test_crc8() {
+        // Example from the datasheet
+        assert_eq!(crc8(&[0xBE, 0xEF]), 0x92);
+    }
+
+    #[test]
+    fn test_temperature() {
+        assert_eq!(temperature_from_raw(0x6666), 25);
+        assert_eq!(temperature_from_raw(0x3400), -9);
+        assert_eq!(temperature_from_raw(0x0BB4), -37);
+        assert_eq!(temperature_from_raw(0), -45);
+        assert_eq!(temperature_from_raw(u16::MAX), 127);
+    }
+
+    #[test]
+    fn test_read() {
+        let mut expected = vec![];
+        expected.extend(measurement([0x66, 0x66, 0x93]));
+        expected.push(Transaction::write(SHTC3_ADDR, vec![0xB0, 0x98]));
+        // A corrupted reading still puts the sensor back to sleep
+        expected.extend(measurement([0x66, 0x67, 0x93]));
+        expected.push(Transaction::write(SHTC3_ADDR, vec![0xB0, 0x98]));
+        // No sensor on the bus
+        expected
+            .push(Transaction::write(SHTC3_ADDR, vec![0x35, 0x17]).with_error(ErrorKind::Other));
+
+        let mut i2c = I2cMock::new(&expected);
+        let mut sensor = Shtc3::new(i2c.clone());
+        assert_eq!(sensor.temperature_c(&mut NoopDelay), Ok(25));
+        assert_eq!(sensor.temperature_c(&mut NoopDelay), Err(Error::Crc));
+        assert_eq!(
+            sensor.temperature_c(&mut NoopDelay),
+            Err(Error::I2c(ErrorKind::Other))
+        );
+        i2c.done();
+    }
+}
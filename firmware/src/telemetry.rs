@@ -0,0 +1,371 @@
+//! Wake telemetry posted to the edge service
+//!
+//! After each wake that reached the server, the firmware posts a small report to
+//! `/telemetry` over the same session, so battery drain can be followed without
+//! a serial console. JSON format:
+//! ```json
+//! {"battery_percent": 87, "boot_reason": "timer", "refresh_ms": 4200}
+//! ```
+//!
+//! A wake that showed an item the server asked for adds `"shown_item"`, which
+//! completes the request. After an unexpected reset (a panic, the watchdog or a
+//! brownout) the first report that gets through adds `"crash"`, from the
+//! [`CrashLog`] kept in RTC memory that survives the reset:
+//! ```json
+//! {"reset_reason": "panic", "message": "index out of bounds at src/bin/main.rs:1234", "count": 1}
+//! ```
+
+use core::fmt::{self, Write};
+
+use heapless::String;
+use serde::Serialize;
+
+use crate::widget::MAX_PATH_LEN;
+
+/// Maximum serialized report size
+pub const TELEMETRY_JSON_SIZE: usize = 448;
+
+/// Longest crash message kept (longer ones are cut off)
+pub const CRASH_MESSAGE_LEN: usize = 96;
+
+/// Magic number to validate the crash log
+const CRASH_LOG_MAGIC: u32 = 0x4352_5348;
+
+/// Why the device woke up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BootReason {
+    /// Refresh timer expired
+    Timer,
+    /// Button pressed
+    Button,
+    /// Power-on or reset
+    PowerOn,
+    /// Anything else
+    Other,
+}
+
+/// Why the chip reset unexpectedly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResetReason {
+    /// The firmware panicked
+    Panic,
+    /// A watchdog fired (see `watchdog`)
+    Watchdog,
+    /// The supply voltage dropped too low
+    Brownout,
+    /// Any other reset the firmware didn't ask for
+    Other,
+}
+
+impl ResetReason {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => ResetReason::Panic,
+            1 => ResetReason::Watchdog,
+            2 => ResetReason::Brownout,
+            _ => ResetReason::Other,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            ResetReason::Panic => 0,
+            ResetReason::Watchdog => 1,
+            ResetReason::Brownout => 2,
+            ResetReason::Other => 3,
+        }
+    }
+}
+
+/// Unexpected resets since the last report that got through
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CrashReport {
+    /// The latest reset's cause
+    pub reset_reason: ResetReason,
+    /// What the firmware recorded before it, e.g. the panic message and location
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String<CRASH_MESSAGE_LEN>>,
+    /// Unexpected resets since the last report
+    pub count: u16,
+}
+
+/// Crashes waiting to be reported, in RTC memory that survives resets
+///
+/// Unlike the other RTC state this memory isn't initialized at boot, so it
+/// holds whatever was there at power-on until [`validate`](Self::validate)
+/// finds no magic. The panic handler (or the watchdog task, on a stall)
+/// [`record`](Self::record)s what happened; the next boot classifies its reset
+/// with [`on_boot`](Self::on_boot), and the log is cleared once a report
+/// carrying it is posted.
+#[repr(C)]
+pub struct CrashLog {
+    /// Magic number to validate state
+    magic: u32,
+    /// Unexpected resets since the last report (0 if none)
+    count: u16,
+    /// `ResetReason` of the latest one
+    reason: u8,
+    /// A message was recorded since the last boot
+    recorded: u8,
+    /// Length of the message in bytes
+    message_len: u8,
+    message: [u8; CRASH_MESSAGE_LEN],
+}
+
+impl CrashLog {
+    pub const fn new() -> Self {
+        Self {
+            magic: CRASH_LOG_MAGIC,
+            count: 0,
+            reason: 0,
+            recorded: 0,
+            message_len: 0,
+            message: [0; CRASH_MESSAGE_LEN],
+        }
+    }
+
+    /// Reset the log if it doesn't hold valid data
+    pub fn validate(&mut self) {
+        if self.magic != CRASH_LOG_MAGIC || self.message_len as usize > CRASH_MESSAGE_LEN {
+            *self = Self::new();
+        }
+    }
+
+    /// Keep a message for the reset about to happen, cut off at
+    /// `CRASH_MESSAGE_LEN` bytes
+    pub fn record(&mut self, message: fmt::Arguments) {
+        let mut writer = Truncating {
+            buf: &mut self.message,
+            len: 0,
+        };
+        let _ = writer.write_fmt(message);
+        self.message_len = writer.len as u8;
+        self.recorded = 1;
+    }
+
+    /// Log the reset this boot followed, `reset` being the hardware's reason if
+    /// unexpected
+    ///
+    /// A reset the firmware asked for (a restart after provisioning, say)
+    /// counts as a panic if a message was recorded before it, and isn't logged
+    /// otherwise. Nothing changes for expected resets, so a crash stays logged
+    /// through later wakes until it's reported.
+    pub fn on_boot(&mut self, reset: Option<ResetReason>) {
+        let recorded = core::mem::take(&mut self.recorded) != 0;
+        let reason = match reset {
+            Some(reason) => reason,
+            None if recorded => ResetReason::Panic,
+            None => return,
+        };
+        self.count = self.count.saturating_add(1);
+        self.reason = reason.to_u8();
+        if !recorded {
+            self.message_len = 0;
+        }
+    }
+
+    /// The crashes to report, if any
+    pub fn report(&self) -> Option<CrashReport> {
+        if self.count == 0 {
+            return None;
+        }
+        let message = core::str::from_utf8(&self.message[..self.message_len as usize])
+            .ok()
+            .filter(|message| !message.is_empty())
+            .and_then(|message| String::try_from(message).ok());
+        Some(CrashReport {
+            reset_reason: ResetReason::from_u8(self.reason),
+            message,
+            count: self.count,
+        })
+    }
+
+    /// Forget the crashes once reported
+    pub fn clear(&mut self) {
+        self.count = 0;
+        self.message_len = 0;
+    }
+}
+
+impl Default for CrashLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Writes into a byte buffer, dropping whatever doesn't fit (at a character
+/// boundary)
+struct Truncating<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for Truncating<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let room = self.buf.len() - self.len;
+        let mut end = s.len().min(room);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.buf[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+        Ok(())
+    }
+}
+
+/// Report posted after each wake
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TelemetryReport {
+    /// Battery percentage, if the PMIC could be read
+    pub battery_percent: Option<u8>,
+    /// Why the device woke up
+    pub boot_reason: BootReason,
+    /// Milliseconds the last panel refresh took (0 without one)
+    pub refresh_ms: u32,
+    /// Requested item shown this wake
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shown_item: Option<String<MAX_PATH_LEN>>,
+    /// Unexpected resets not reported yet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crash: Option<CrashReport>,
+}
+
+/// Serialize a report to JSON, returning the number of bytes written
+pub fn serialize_report(report: &TelemetryReport, buf: &mut [u8]) -> Option<usize> {
+    serde_json_core::to_slice(report, buf).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_report() {
+        let report = TelemetryReport {
+            battery_percent: Some(87),
+            boot_reason: BootReason::PowerOn,
+            refresh_ms: u32::MAX,
+            shown_item: None,
+            crash: None,
+        };
+        let mut buf = [0u8; TELEMETRY_JSON_SIZE];
+        let len = serialize_report(&report, &mut buf).unwrap();
+        assert_eq!(
+            core::str::from_utf8(&buf[..len]).unwrap(),
+            r#"{"battery_percent":87,"boot_reason":"power_on","refresh_ms":4294967295}"#
+        );
+
+        // Room for the longest item path
+        let mut path = String::new();
+        while path.push('x').is_ok() {}
+        let report = TelemetryReport {
+            shown_item: Some(path),
+            ..report
+        };
+        assert!(serialize_report(&report, &mut buf).is_some());
+
+        // And a crash message that's all escapes
+        let mut message = String::new();
+        while message.push('"').is_ok() {}
+        let report = TelemetryReport {
+            crash: Some(CrashReport {
+                reset_reason: ResetReason::Panic,
+                message: Some(message),
+                count: u16::MAX,
+            }),
+            ..report
+        };
+        assert!(serialize_report(&report, &mut buf).is_some());
+    }
+
+    #[test]
+    fn test_serialize_crash() {
+        let report = TelemetryReport {
+            battery_percent: None,
+            boot_reason: BootReason::PowerOn,
+            refresh_ms: 4200,
+            shown_item: None,
+            crash: Some(CrashReport {
+                reset_reason: ResetReason::Watchdog,
+                message: None,
+                count: 2,
+            }),
+        };
+        let mut buf = [0u8; TELEMETRY_JSON_SIZE];
+        let len = serialize_report(&report, &mut buf).unwrap();
+        assert_eq!(
+            core::str::from_utf8(&buf[..len]).unwrap(),
+            r#"{"battery_percent":null,"boot_reason":"power_on","refresh_ms":4200,"crash":{"reset_reason":"watchdog","count":2}}"#
+        );
+    }
+
+    #[test]
+    fn test_crash_log() {
+        let mut log = CrashLog::new();
+        log.validate();
+        assert_eq!(log.report(), None);
+
+        // Deep sleep wakes and requested restarts aren't crashes
+        log.on_boot(None);
+        assert_eq!(log.report(), None);
+
+        // A panic restarts through a requested reset, after recording its message
+        log.record(format_args!("oops at {}:{}", "main.rs", 12));
+        log.on_boot(None);
+        let report = log.report().unwrap();
+        assert_eq!(report.reset_reason, ResetReason::Panic);
+        assert_eq!(report.message.as_deref(), Some("oops at main.rs:12"));
+        assert_eq!(report.count, 1);
+
+        // Kept through later wakes until reported
+        log.on_boot(None);
+        assert_eq!(log.report(), Some(report));
+
+        // The latest crash's cause wins, and a reset without a message drops the old one
+        log.on_boot(Some(ResetReason::Brownout));
+        let report = log.report().unwrap();
+        assert_eq!(report.reset_reason, ResetReason::Brownout);
+        assert_eq!(report.message, None);
+        assert_eq!(report.count, 2);
+
+        // A stall note stays with the watchdog reset that follows
+        log.record(format_args!("main loop stalled"));
+        log.on_boot(Some(ResetReason::Watchdog));
+        let report = log.report().unwrap();
+        assert_eq!(report.reset_reason, ResetReason::Watchdog);
+        assert_eq!(report.message.as_deref(), Some("main loop stalled"));
+        assert_eq!(report.count, 3);
+
+        log.clear();
+        assert_eq!(log.report(), None);
+    }
+
+    #[test]
+    fn test_crash_log_truncates() {
+        let mut log = CrashLog::new();
+        // Multi-byte characters aren't split at the cut
+        log.record(format_args!("{}", "é".repeat(CRASH_MESSAGE_LEN)));
+        log.on_boot(None);
+        let message = log.report().unwrap().message.unwrap();
+        assert_eq!(message.len(), CRASH_MESSAGE_LEN);
+        assert!(message.chars().all(|c| c == 'é'));
+    }
+
+    #[test]
+    fn test_crash_log_validate() {
+        // Whatever was in memory at power-on is thrown away
+        let mut log = CrashLog::new();
+        log.magic = 0xDEAD_BEEF;
+        log.count = 7;
+        log.validate();
+        assert_eq!(log.report(), None);
+
+        let mut log = CrashLog::new();
+        log.count = 1;
+        log.message_len = u8::MAX;
+        log.validate();
+        assert_eq!(log.report(), None);
+    }
+}
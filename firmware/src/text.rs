@@ -0,0 +1,354 @@
+//! On-device text rendering for status and error screens
+//!
+//! Uses embedded-graphics' 10x20 mono fonts, scaled up in whole-pixel blocks so
+//! messages are readable from across the room. Text follows the display
+//! orientation (vertical mode rotates 90° like the widget images).
+//!
+//! Network names and server metadata can be in any language, so each character
+//! is drawn in the first font of a fallback chain that has it: Western and
+//! Central European Latin, Cyrillic, Greek, then half-width katakana. All share
+//! the 10x20 cell, so lines stay monospaced. Characters none of them cover (e.g.
+//! CJK ideographs) are drawn as an empty box rather than dropped.
+
+use embedded_graphics::mono_font::mapping::{self, StrGlyphMapping};
+use embedded_graphics::mono_font::{
+    MonoFont, MonoTextStyle, iso_8859_1, iso_8859_2, iso_8859_5, iso_8859_7, jis_x0201,
+};
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+use embedded_graphics::text::{Baseline, Text};
+use heapless::Vec;
+
+use crate::epd::{Color, HEIGHT, WIDTH};
+use crate::framebuffer::Framebuffer;
+use crate::widget::Orientation;
+
+/// Scale factor for status screen titles (30x60 pixel glyphs)
+pub const TITLE_SCALE: u32 = 3;
+
+/// Scale factor for status screen details (20x40 pixel glyphs)
+pub const DETAIL_SCALE: u32 = 2;
+
+/// Maximum number of wrapped lines per string
+const MAX_LINES: usize = 8;
+
+/// Margin around the text block in panel pixels
+const MARGIN: u32 = 24;
+
+/// Fonts tried in order for each character, with the characters each has
+static FONTS: [(&MonoFont, &StrGlyphMapping); 5] = [
+    // Western European, including ASCII
+    (&iso_8859_1::FONT_10X20, &mapping::ISO_8859_1),
+    // Central European
+    (&iso_8859_2::FONT_10X20, &mapping::ISO_8859_2),
+    (&iso_8859_5::FONT_10X20, &mapping::ISO_8859_5),
+    (&iso_8859_7::FONT_10X20, &mapping::ISO_8859_7),
+    // Half-width katakana
+    (&jis_x0201::FONT_10X20, &mapping::JIS_X0201),
+];
+
+/// Glyph cell size shared by the fonts (unscaled)
+const CHAR_WIDTH: u32 = FONTS[0].0.character_size.width + FONTS[0].0.character_spacing;
+const CHAR_HEIGHT: u32 = FONTS[0].0.character_size.height;
+
+/// Framebuffer view in a display orientation, drawing each pixel as a `scale` x `scale` block
+struct Canvas<'a> {
+    framebuffer: &'a mut Framebuffer,
+    orientation: Orientation,
+    scale: u32,
+}
+
+impl Canvas<'_> {
+    /// Set a pixel in (unscaled) orientation coordinates
+    fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
+        match self.orientation {
+            Orientation::Horizontal => self.framebuffer.set_pixel(x, y, color),
+            // Rotate 90° CCW, matching how vertical images are rendered
+            Orientation::Vertical if x < HEIGHT => {
+                self.framebuffer.set_pixel(y, HEIGHT - 1 - x, color)
+            }
+            Orientation::Vertical => {}
+        }
+    }
+}
+
+impl OriginDimensions for Canvas<'_> {
+    fn size(&self) -> Size {
+        let (width, height) = screen_size(self.orientation);
+        Size::new(width / self.scale, height / self.scale)
+    }
+}
+
+impl DrawTarget for Canvas<'_> {
+    type Color = Color;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            let (Ok(x), Ok(y)) = (u32::try_from(point.x), u32::try_from(point.y)) else {
+                continue;
+            };
+            for dy in 0..self.scale {
+                for dx in 0..self.scale {
+                    self.set_pixel(x * self.scale + dx, y * self.scale + dy, color);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// First font in the fallback chain with a glyph for `c`
+fn font_for(c: char) -> Option<&'static MonoFont<'static>> {
+    FONTS
+        .iter()
+        .find(|(_, glyphs)| glyphs.contains(c))
+        .map(|&(font, _)| font)
+}
+
+/// Draw `c` with the top left of its cell at `position` (in canvas pixels)
+fn draw_char(canvas: &mut Canvas<'_>, c: char, position: Point, color: Color) {
+    match font_for(c) {
+        Some(font) => {
+            let mut utf8 = [0u8; 4];
+            let style = MonoTextStyle::new(font, color);
+            let _ = Text::with_baseline(c.encode_utf8(&mut utf8), position, style, Baseline::Top)
+                .draw(canvas);
+        }
+        // No font has it: an empty box, so the text visibly has something missing
+        None => {
+            let size = Size::new(CHAR_WIDTH - 2, CHAR_HEIGHT - 6);
+            let _ = Rectangle::new(position + Point::new(1, 3), size)
+                .into_styled(PrimitiveStyle::with_stroke(color, 1))
+                .draw(canvas);
+        }
+    }
+}
+
+/// Screen size (width, height) in the given orientation
+fn screen_size(orientation: Orientation) -> (u32, u32) {
+    match orientation {
+        Orientation::Horizontal => (WIDTH, HEIGHT),
+        Orientation::Vertical => (HEIGHT, WIDTH),
+    }
+}
+
+/// Number of characters that fit on one line at `scale`
+pub fn line_capacity(orientation: Orientation, scale: u32) -> usize {
+    let (width, _) = screen_size(orientation);
+    ((width - 2 * MARGIN) / (CHAR_WIDTH * scale)) as usize
+}
+
+/// Height in pixels of one line of text at `scale`
+pub fn line_height(scale: u32) -> u32 {
+    CHAR_HEIGHT * scale
+}
+
+/// Word-wrap `text` into lines of at most `max_chars` characters
+///
+/// Words longer than a line are split; lines beyond `MAX_LINES` are dropped.
+pub fn wrap(text: &str, max_chars: usize) -> Vec<&str, MAX_LINES> {
+    let mut lines = Vec::new();
+    let mut rest = text.trim();
+
+    while !rest.is_empty() && max_chars > 0 {
+        let line = match rest.char_indices().nth(max_chars) {
+            None => rest,
+            // Break at the last space that fits, or mid-word if there is none
+            Some((limit, _)) if rest[limit..].starts_with(' ') => &rest[..limit],
+            Some((limit, _)) => {
+                let end = rest[..limit].rfind(' ').filter(|&i| i > 0).unwrap_or(limit);
+                &rest[..end]
+            }
+        };
+        if lines.push(line.trim_end()).is_err() {
+            break;
+        }
+        rest = rest[line.len()..].trim_start();
+    }
+
+    lines
+}
+
+/// Draw a line of text centered horizontally, with its top at `y` (in screen pixels)
+pub fn draw_text_centered(
+    framebuffer: &mut Framebuffer,
+    orientation: Orientation,
+    text: &str,
+    y: u32,
+    scale: u32,
+    color: Color,
+) {
+    let (width, _) = screen_size(orientation);
+    let mut canvas = Canvas {
+        framebuffer,
+        orientation,
+        scale,
+    };
+    let line_width = text.chars().count() as i32 * CHAR_WIDTH as i32;
+    let mut position = Point::new(
+        (width / scale) as i32 / 2 - line_width / 2,
+        (y / scale) as i32,
+    );
+    for c in text.chars() {
+        draw_char(&mut canvas, c, position, color);
+        position.x += CHAR_WIDTH as i32;
+    }
+}
+
+/// Render a full-screen status message: a title and optional detail,
+/// word-wrapped and centered on a white background
+pub fn draw_message(
+    framebuffer: &mut Framebuffer,
+    orientation: Orientation,
+    title: &str,
+    detail: Option<&str>,
+) {
+    draw_lines(framebuffer, orientation, title, detail.as_slice());
+}
+
+/// Render a full-screen status message with several details, each starting
+/// on its own line
+pub fn draw_lines(
+    framebuffer: &mut Framebuffer,
+    orientation: Orientation,
+    title: &str,
+    details: &[&str],
+) {
+    framebuffer.clear(Color::White);
+
+    let title_lines = wrap(title, line_capacity(orientation, TITLE_SCALE));
+    let detail_capacity = line_capacity(orientation, DETAIL_SCALE);
+    let detail_count: usize = details
+        .iter()
+        .map(|detail| wrap(detail, detail_capacity).len())
+        .sum();
+
+    // Center the whole block vertically, with a blank detail line as the gap
+    let gap = if detail_count == 0 {
+        0
+    } else {
+        line_height(DETAIL_SCALE)
+    };
+    let block_height = title_lines.len() as u32 * line_height(TITLE_SCALE)
+        + gap
+        + detail_count as u32 * line_height(DETAIL_SCALE);
+    let (_, height) = screen_size(orientation);
+    let mut y = height.saturating_sub(block_height) / 2;
+
+    for line in title_lines {
+        draw_text_centered(framebuffer, orientation, line, y, TITLE_SCALE, Color::Black);
+        y += line_height(TITLE_SCALE);
+    }
+    y += gap;
+    for detail in details {
+        for line in wrap(detail, detail_capacity) {
+            draw_text_centered(framebuffer, orientation, line, y, DETAIL_SCALE, Color::Red);
+            y += line_height(DETAIL_SCALE);
+        }
+    }
+}
+
+/// Draw a line of text on a white band across the top of the screen,
+/// leaving the rest of the framebuffer as it is
+pub fn draw_banner(framebuffer: &mut Framebuffer, orientation: Orientation, text: &str) {
+    let band = line_height(DETAIL_SCALE) + MARGIN;
+    // The top of the vertical screen is the left edge of the panel
+    match orientation {
+        Orientation::Horizontal => framebuffer.fill_rect(0, 0, WIDTH, band, Color::White),
+        Orientation::Vertical => framebuffer.fill_rect(0, 0, band, HEIGHT, Color::White),
+    }
+    draw_text_centered(
+        framebuffer,
+        orientation,
+        text,
+        MARGIN / 2,
+        DETAIL_SCALE,
+        Color::Black,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(
+            wrap("WiFi connection failed", 10).as_slice(),
+            ["WiFi", "connection", "failed"]
+        );
+        assert_eq!(wrap("No SD card", 26).as_slice(), ["No SD card"]);
+        assert_eq!(wrap("abcdefghij", 4).as_slice(), ["abcd", "efgh", "ij"]);
+        assert!(wrap("   ", 10).is_empty());
+    }
+
+    #[test]
+    fn test_line_capacity() {
+        assert_eq!(line_capacity(Orientation::Horizontal, TITLE_SCALE), 25);
+        assert_eq!(line_capacity(Orientation::Vertical, TITLE_SCALE), 14);
+    }
+
+    #[test]
+    fn test_font_fallback() {
+        // Position in the fallback chain of the font drawing `c`
+        let chain = |c| {
+            let font = font_for(c)?;
+            FONTS.iter().position(|&(f, _)| core::ptr::eq(f, font))
+        };
+        assert_eq!(chain('A'), Some(0));
+        assert_eq!(chain('é'), Some(0));
+        assert_eq!(chain('ł'), Some(1));
+        assert_eq!(chain('Ж'), Some(2));
+        assert_eq!(chain('Ω'), Some(3));
+        assert_eq!(chain('ｱ'), Some(4));
+        assert_eq!(chain('東'), None);
+    }
+
+    #[test]
+    fn test_draw_message() {
+        for orientation in [Orientation::Horizontal, Orientation::Vertical] {
+            let mut framebuffer = Framebuffer::new();
+            draw_message(
+                &mut framebuffer,
+                orientation,
+                "No SD card",
+                Some("Insert a card"),
+            );
+
+            let white = Color::White.to_dual_pixel();
+            let drawn = framebuffer
+                .as_slice()
+                .iter()
+                .filter(|&&b| b != white)
+                .count();
+            assert!(drawn > 0);
+        }
+
+        // Fallback glyphs, and boxes for missing ones, are drawn too
+        let drawn = |text| {
+            let mut framebuffer = Framebuffer::new();
+            framebuffer.clear(Color::White);
+            draw_text_centered(
+                &mut framebuffer,
+                Orientation::Horizontal,
+                text,
+                0,
+                1,
+                Color::Black,
+            );
+            let white = Color::White.to_dual_pixel();
+            framebuffer
+                .as_slice()
+                .iter()
+                .filter(|&&b| b != white)
+                .count()
+        };
+        assert!(drawn("Ж") > 0);
+        assert!(drawn("東") > 0);
+        assert_eq!(drawn(" "), 0);
+    }
+}
@@ -0,0 +1,215 @@
+//! Thermal derating
+//!
+//! Spectra 6 panels are only rated to refresh between 0°C and 50°C. Driven
+//! colder the pigments barely move, leaving ghosting that can be permanent,
+//! which matters for frames in a garage or on a porch. Each wake reads the
+//! board's SHTC3 sensor (see `shtc3`) before drawing, falling back on the
+//! PMIC's die temperature if it can't, and one outside the range skips its
+//! refresh and sleeps longer, leaving the panel as it is. Once derating,
+//! refreshes only resume a few degrees inside the range so a frame hovering at
+//! the limit doesn't flip back and forth.
+//!
+//! [`ThermalState`] counts the skipped wakes in RTC memory, so the first wake
+//! back in range can put a one-time card on the panel saying refreshes were
+//! paused and why, before the rotation carries on.
+
+/// Coldest temperature at which the panel is refreshed
+pub const MIN_REFRESH_C: i8 = 0;
+
+/// Hottest temperature at which the panel is refreshed
+pub const MAX_REFRESH_C: i8 = 50;
+
+/// How far inside the range the temperature must be to end derating
+const RESUME_MARGIN_C: i8 = 3;
+
+/// Shortest sleep while derating (1 hour)
+const MIN_DERATED_SLEEP_SECS: u64 = 60 * 60;
+
+/// Magic number to validate the state
+const THERMAL_STATE_MAGIC: u32 = 0x5448_524d;
+
+/// Which side of the rated range the panel was on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    TooCold,
+    TooHot,
+}
+
+/// Refreshes skipped while out of range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pause {
+    pub limit: Limit,
+    /// Wakes that skipped their refresh
+    pub skipped: u16,
+    /// Coldest (or hottest) temperature read meanwhile
+    pub extreme_c: i8,
+}
+
+/// What this wake should do about the temperature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Thermal {
+    /// In range (or unknown): refresh as usual
+    Normal,
+    /// Out of range: skip the refresh and sleep longer
+    Derate(Limit),
+    /// Back in range after a pause: show the warning card once
+    Recovered(Pause),
+}
+
+/// Sleep between wakes while derating, for a refresh interval of `interval_secs`
+pub fn derated_sleep_secs(interval_secs: u64) -> u64 {
+    interval_secs.saturating_mul(2).max(MIN_DERATED_SLEEP_SECS)
+}
+
+/// Skipped refreshes, in RTC memory across deep sleep
+#[repr(C)]
+pub struct ThermalState {
+    magic: u32,
+    skipped: u16,
+    extreme_c: i8,
+    /// 0 while refreshing, 1 too cold, 2 too hot
+    limit: u8,
+}
+
+impl ThermalState {
+    pub const fn new() -> Self {
+        Self {
+            magic: THERMAL_STATE_MAGIC,
+            skipped: 0,
+            extreme_c: 0,
+            limit: 0,
+        }
+    }
+
+    /// Reset the state if it doesn't hold valid data
+    pub fn validate(&mut self) {
+        if self.magic != THERMAL_STATE_MAGIC || self.limit > 2 {
+            *self = Self::new();
+        }
+    }
+
+    /// Decide what this wake does at `temperature`, recording skipped wakes
+    ///
+    /// An unknown temperature refreshes as usual and leaves any pause for the
+    /// next reading to end.
+    pub fn check(&mut self, temperature: Option<i8>) -> Thermal {
+        let Some(celsius) = temperature else {
+            return Thermal::Normal;
+        };
+
+        let margin = if self.limit().is_some() {
+            RESUME_MARGIN_C
+        } else {
+            0
+        };
+        let limit = if celsius < MIN_REFRESH_C + margin {
+            Some(Limit::TooCold)
+        } else if celsius > MAX_REFRESH_C - margin {
+            Some(Limit::TooHot)
+        } else {
+            None
+        };
+
+        match (limit, self.limit()) {
+            (Some(limit), current) => {
+                if current != Some(limit) {
+                    self.skipped = 0;
+                    self.extreme_c = celsius;
+                }
+                self.limit = limit as u8 + 1;
+                self.skipped = self.skipped.saturating_add(1);
+                self.extreme_c = match limit {
+                    Limit::TooCold => self.extreme_c.min(celsius),
+                    Limit::TooHot => self.extreme_c.max(celsius),
+                };
+                Thermal::Derate(limit)
+            }
+            (None, Some(limit)) => {
+                let pause = Pause {
+                    limit,
+                    skipped: self.skipped,
+                    extreme_c: self.extreme_c,
+                };
+                *self = Self::new();
+                Thermal::Recovered(pause)
+            }
+            (None, None) => Thermal::Normal,
+        }
+    }
+
+    /// The limit refreshes are paused for, if any
+    fn limit(&self) -> Option<Limit> {
+        match self.limit {
+            1 => Some(Limit::TooCold),
+            2 => Some(Limit::TooHot),
+            _ => None,
+        }
+    }
+}
+
+impl Default for ThermalState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let mut state = ThermalState::new();
+        assert_eq!(state.check(Some(20)), Thermal::Normal);
+        assert_eq!(state.check(None), Thermal::Normal);
+        assert_eq!(state.check(Some(MIN_REFRESH_C)), Thermal::Normal);
+
+        // Cold nights skip refreshes
+        assert_eq!(state.check(Some(-1)), Thermal::Derate(Limit::TooCold));
+        assert_eq!(state.check(Some(-6)), Thermal::Derate(Limit::TooCold));
+        assert_eq!(state.check(None), Thermal::Normal);
+        // Back at the limit isn't enough to resume
+        assert_eq!(
+            state.check(Some(MIN_REFRESH_C)),
+            Thermal::Derate(Limit::TooCold)
+        );
+        assert_eq!(
+            state.check(Some(12)),
+            Thermal::Recovered(Pause {
+                limit: Limit::TooCold,
+                skipped: 3,
+                extreme_c: -6,
+            })
+        );
+        // The card is shown once
+        assert_eq!(state.check(Some(12)), Thermal::Normal);
+
+        assert_eq!(state.check(Some(55)), Thermal::Derate(Limit::TooHot));
+        assert_eq!(
+            state.check(Some(30)),
+            Thermal::Recovered(Pause {
+                limit: Limit::TooHot,
+                skipped: 1,
+                extreme_c: 55,
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut state = ThermalState::new();
+        state.check(Some(-2));
+        state.validate();
+        assert!(matches!(state.check(Some(20)), Thermal::Recovered(_)));
+
+        state.limit = 7;
+        state.validate();
+        assert_eq!(state.check(Some(20)), Thermal::Normal);
+    }
+
+    #[test]
+    fn test_derated_sleep() {
+        assert_eq!(derated_sleep_secs(15 * 60), 60 * 60);
+        assert_eq!(derated_sleep_secs(6 * 60 * 60), 12 * 60 * 60);
+    }
+}
@@ -0,0 +1,485 @@
+//! TLS to an https server, verifying it when the build asks to
+//!
+//! reqwless's embedded-tls integration has no way to plug in a certificate
+//! verifier, so the handshake is made here instead, over a plain TCP
+//! connection, and sessions send their requests through the
+//! [`ServerStream`] that [`ServerConnector::connect`] returns.
+//!
+//! The server is checked against what the build embeds:
+//! - `TLS_CA`: path to a root certificate (PEM or DER). The chain the server
+//!   sends must lead up to it and name the server's host
+//!   (see [`x509::verify_chain`]).
+//! - `TLS_SPKI_SHA256`: SHA-256 of the server key's SubjectPublicKeyInfo, in
+//!   hex. The leaf certificate must carry that key; nothing else about the
+//!   certificate is checked.
+//!
+//! The device config can pin the key too, with `spki_sha256` (the same hex
+//! hash), from the wake after it's first received. It narrows a build's trust
+//! anchors, or is the only one in builds without them, in which case the
+//! config that delivers it is trusted on first use. A pin the server keeps
+//! failing is set aside after a few handshakes, leaving the build's anchors
+//! (see [`crate::pin_state`]), so a bad one can't lock the frame out.
+//!
+//! With any of them, the handshake's CertificateVerify signature is checked
+//! with the leaf's key, so only the holder of that key can finish it, and a
+//! mismatch fails the session. Without any, or with the `insecure-tls` feature,
+//! any certificate is accepted.
+//!
+//! Validity periods are only checked once the clock is known, so the first
+//! session after a cold boot (before the SNTP sync) skips them.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell, UnsafeCell};
+use core::net::SocketAddr;
+
+use embedded_io_async::{Error, ErrorKind, ErrorType, Read, Write};
+use embedded_nal_async::TcpConnect;
+use embedded_tls::{
+    Aes128GcmSha256, CertificateEntryRef, CertificateRef, CryptoProvider, HandshakeVerifyRef,
+    SignatureScheme, TlsConfig, TlsConnection, TlsContext, TlsError, TlsVerifier,
+};
+use log::{info, warn};
+use p256::ecdsa::signature::hazmat::PrehashVerifier;
+use rand_chacha::ChaCha8Rng;
+use rand_core::{CryptoRngCore, RngCore, SeedableRng};
+use rsa::pkcs1::DecodeRsaPublicKey;
+use rsa::{Pkcs1v15Sign, Pss, RsaPublicKey};
+use sha2::{Digest, Sha256, Sha384};
+
+use crate::clock;
+use crate::x509::{self, Certificate, Hash, PublicKey, SignatureAlgorithm};
+
+/// TLS record buffer sizes
+pub const TLS_READ_BUF_SIZE: usize = 16640;
+pub const TLS_WRITE_BUF_SIZE: usize = 4096;
+
+/// Most certificates read from the server's chain
+const MAX_CHAIN_LEN: usize = 4;
+
+/// Root certificate from `TLS_CA` as DER, empty without one (written by build.rs)
+const TLS_CA: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/tls_ca.der"));
+
+/// SPKI pin from `TLS_SPKI_SHA256`
+const TLS_SPKI_PIN: Option<[u8; 32]> = match option_env!("TLS_SPKI_SHA256") {
+    Some(hex) => match x509::parse_spki_pin(hex) {
+        Some(pin) => Some(pin),
+        None => panic!("TLS_SPKI_SHA256 must be 64 hex digits"),
+    },
+    None => None,
+};
+
+/// Whether the build verifies https servers (see the module docs)
+pub const VERIFIES_SERVER: bool =
+    !cfg!(feature = "insecure-tls") && (!TLS_CA.is_empty() || TLS_SPKI_PIN.is_some());
+
+/// Start of the content the server's CertificateVerify signs (RFC 8446 4.4.3)
+const CERTIFICATE_VERIFY_CONTEXT: &[u8] = b"TLS 1.3, server CertificateVerify\0";
+
+/// What the server is verified against
+struct Trust {
+    /// Parsed from [`TLS_CA`]
+    root: Option<Certificate<'static>>,
+    /// Hash of the key the leaf must carry from the config, until set aside
+    config_pin: Cell<Option<[u8; 32]>>,
+    /// The last handshake failed on `config_pin` alone
+    config_pin_failed: Cell<bool>,
+}
+
+impl Trust {
+    /// The build's trust anchors and the config's `spki_pin`, or None if the
+    /// server isn't verified
+    fn new(spki_pin: Option<[u8; 32]>) -> Option<Self> {
+        if cfg!(feature = "insecure-tls") {
+            return None;
+        }
+        // Empty without `TLS_CA`, otherwise build.rs has already checked that it parses
+        let root = Certificate::parse(TLS_CA).ok();
+        (root.is_some() || TLS_SPKI_PIN.is_some() || spki_pin.is_some()).then_some(Self {
+            root,
+            config_pin: Cell::new(spki_pin),
+            config_pin_failed: Cell::new(false),
+        })
+    }
+
+    /// Whether anything still verifies the server
+    fn verifies(&self) -> bool {
+        self.root.is_some() || TLS_SPKI_PIN.is_some() || self.config_pin.get().is_some()
+    }
+}
+
+/// How a signature is checked
+#[derive(Clone, Copy)]
+enum Scheme {
+    Ecdsa(Hash),
+    RsaPkcs1(Hash),
+    RsaPss(Hash),
+}
+
+impl From<SignatureAlgorithm> for Scheme {
+    fn from(algorithm: SignatureAlgorithm) -> Self {
+        match algorithm {
+            SignatureAlgorithm::Ecdsa(hash) => Scheme::Ecdsa(hash),
+            SignatureAlgorithm::Rsa(hash) => Scheme::RsaPkcs1(hash),
+        }
+    }
+}
+
+fn digest(hash: Hash, message: &[u8]) -> Vec<u8> {
+    match hash {
+        Hash::Sha256 => Sha256::digest(message).to_vec(),
+        Hash::Sha384 => Sha384::digest(message).to_vec(),
+    }
+}
+
+/// Whether `signature` over `message` verifies with `key`
+fn verify(key: &PublicKey, scheme: Scheme, message: &[u8], signature: &[u8]) -> bool {
+    match (*key, scheme) {
+        (PublicKey::P256(point), Scheme::Ecdsa(hash)) => {
+            let (Ok(key), Ok(signature)) = (
+                p256::ecdsa::VerifyingKey::from_sec1_bytes(point),
+                p256::ecdsa::Signature::from_der(signature),
+            ) else {
+                return false;
+            };
+            key.verify_prehash(&digest(hash, message), &signature)
+                .is_ok()
+        }
+        (PublicKey::P384(point), Scheme::Ecdsa(hash)) => {
+            let (Ok(key), Ok(signature)) = (
+                p384::ecdsa::VerifyingKey::from_sec1_bytes(point),
+                p384::ecdsa::Signature::from_der(signature),
+            ) else {
+                return false;
+            };
+            key.verify_prehash(&digest(hash, message), &signature)
+                .is_ok()
+        }
+        (PublicKey::Rsa(der), Scheme::RsaPkcs1(hash) | Scheme::RsaPss(hash)) => {
+            let Ok(key) = RsaPublicKey::from_pkcs1_der(der) else {
+                return false;
+            };
+            let hashed = digest(hash, message);
+            let result = match (scheme, hash) {
+                (Scheme::RsaPss(_), Hash::Sha256) => {
+                    key.verify(Pss::new::<Sha256>(), &hashed, signature)
+                }
+                (Scheme::RsaPss(_), Hash::Sha384) => {
+                    key.verify(Pss::new::<Sha384>(), &hashed, signature)
+                }
+                (_, Hash::Sha256) => key.verify(Pkcs1v15Sign::new::<Sha256>(), &hashed, signature),
+                (_, Hash::Sha384) => key.verify(Pkcs1v15Sign::new::<Sha384>(), &hashed, signature),
+            };
+            result.is_ok()
+        }
+        _ => false,
+    }
+}
+
+/// Checks the server's certificate, then its CertificateVerify signature
+struct ServerVerifier<'a> {
+    trust: &'a Trust,
+    host: &'a str,
+    /// SubjectPublicKeyInfo of the leaf, for the CertificateVerify that follows it
+    leaf_spki: Option<Vec<u8>>,
+    /// Handshake transcript hash up to the certificate, which CertificateVerify signs
+    transcript: Option<[u8; 32]>,
+}
+
+impl TlsVerifier<Aes128GcmSha256> for ServerVerifier<'_> {
+    fn set_hostname_verification(&mut self, _hostname: &str) -> Result<(), TlsError> {
+        // Already known from the connection
+        Ok(())
+    }
+
+    fn verify_certificate(
+        &mut self,
+        transcript: &Sha256,
+        certificate: CertificateRef,
+    ) -> Result<(), TlsError> {
+        let mut entries = certificate.entries.iter();
+        let Some(CertificateEntryRef::X509(leaf)) = entries.next() else {
+            return Err(TlsError::InvalidCertificate);
+        };
+        let leaf = Certificate::parse(leaf).map_err(|e| {
+            warn!("Server certificate can't be read: {:?}", e);
+            TlsError::InvalidCertificate
+        })?;
+        let mut chain: heapless::Vec<Certificate, MAX_CHAIN_LEN> = heapless::Vec::new();
+        let _ = chain.push(leaf);
+        // Certificates past the leaf that can't be used are skipped
+        for entry in entries {
+            if let CertificateEntryRef::X509(der) = entry
+                && let Ok(cert) = Certificate::parse(der)
+            {
+                let _ = chain.push(cert);
+            }
+        }
+        let leaf = &chain[0];
+
+        let hash: [u8; 32] = Sha256::digest(leaf.spki).into();
+        if TLS_SPKI_PIN.is_some_and(|pin| pin != hash) {
+            warn!("Server key doesn't match the SPKI pin");
+            return Err(TlsError::InvalidCertificate);
+        }
+        if let Some(root) = &self.trust.root {
+            let now = clock::unix_time();
+            if now.is_none() {
+                info!("Clock not set, skipping certificate validity periods");
+            }
+            x509::verify_chain(&chain, root, self.host, now, |cert, key| {
+                verify(
+                    key,
+                    cert.signature_algorithm.into(),
+                    cert.tbs,
+                    cert.signature,
+                )
+            })
+            .map_err(|e| {
+                warn!("Server certificate rejected: {:?}", e);
+                TlsError::InvalidCertificate
+            })?;
+        }
+        // Last, so only a server the build accepts counts against the config pin
+        if self.trust.config_pin.get().is_some_and(|pin| pin != hash) {
+            warn!("Server key doesn't match the device config's SPKI pin");
+            self.trust.config_pin_failed.set(true);
+            return Err(TlsError::InvalidCertificate);
+        }
+
+        self.leaf_spki = Some(leaf.spki.to_vec());
+        self.transcript = Some(transcript.clone().finalize().into());
+        Ok(())
+    }
+
+    fn verify_signature(&mut self, verify_ref: HandshakeVerifyRef) -> Result<(), TlsError> {
+        let (Some(spki), Some(transcript)) = (self.leaf_spki.take(), self.transcript.take()) else {
+            return Err(TlsError::InvalidCertificate);
+        };
+        let key = PublicKey::from_spki(&spki).map_err(|_| TlsError::InvalidCertificate)?;
+        // TLS 1.3 ties ECDSA schemes to a curve, and only allows PSS for RSA
+        let scheme = match (verify_ref.signature_scheme, key) {
+            (SignatureScheme::EcdsaSecp256r1Sha256, PublicKey::P256(_)) => {
+                Scheme::Ecdsa(Hash::Sha256)
+            }
+            (SignatureScheme::EcdsaSecp384r1Sha384, PublicKey::P384(_)) => {
+                Scheme::Ecdsa(Hash::Sha384)
+            }
+            (SignatureScheme::RsaPssRsaeSha256, PublicKey::Rsa(_)) => Scheme::RsaPss(Hash::Sha256),
+            (SignatureScheme::RsaPssRsaeSha384, PublicKey::Rsa(_)) => Scheme::RsaPss(Hash::Sha384),
+            (scheme, _) => {
+                warn!("Server signed the handshake with unsupported {:?}", scheme);
+                return Err(TlsError::InvalidSignatureScheme);
+            }
+        };
+
+        let mut message = [b' '; 64 + CERTIFICATE_VERIFY_CONTEXT.len() + 32];
+        message[64..64 + CERTIFICATE_VERIFY_CONTEXT.len()]
+            .copy_from_slice(CERTIFICATE_VERIFY_CONTEXT);
+        message[64 + CERTIFICATE_VERIFY_CONTEXT.len()..].copy_from_slice(&transcript);
+        if verify(&key, scheme, &message, verify_ref.signature) {
+            Ok(())
+        } else {
+            warn!("Server's handshake signature doesn't match its certificate");
+            Err(TlsError::InvalidSignature)
+        }
+    }
+}
+
+/// Crypto for one handshake: its own RNG, and the verifier if the server is
+/// verified
+struct Provider<'a> {
+    rng: ChaCha8Rng,
+    verifier: Option<ServerVerifier<'a>>,
+}
+
+impl CryptoProvider for Provider<'_> {
+    type CipherSuite = Aes128GcmSha256;
+    type Signature = &'static [u8];
+
+    fn rng(&mut self) -> impl CryptoRngCore {
+        &mut self.rng
+    }
+
+    fn verifier(&mut self) -> Result<&mut impl TlsVerifier<Aes128GcmSha256>, TlsError> {
+        // No verifier: any certificate is accepted
+        self.verifier.as_mut().ok_or(TlsError::Unimplemented)
+    }
+}
+
+/// TLS record buffers, lent to one connection at a time
+pub struct TlsBuffers {
+    read: UnsafeCell<[u8; TLS_READ_BUF_SIZE]>,
+    write: UnsafeCell<[u8; TLS_WRITE_BUF_SIZE]>,
+    lent: Cell<bool>,
+}
+
+impl TlsBuffers {
+    pub const fn new() -> Self {
+        Self {
+            read: UnsafeCell::new([0; TLS_READ_BUF_SIZE]),
+            write: UnsafeCell::new([0; TLS_WRITE_BUF_SIZE]),
+            lent: Cell::new(false),
+        }
+    }
+}
+
+impl Default for TlsBuffers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Marks the [`TlsBuffers`] as lent until dropped
+pub struct BufferLease<'a>(&'a Cell<bool>);
+
+impl Drop for BufferLease<'_> {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+/// Why a connection to the server couldn't be opened
+#[derive(Debug)]
+pub enum ConnectError {
+    Tcp,
+    /// Another connection still holds the TLS buffers
+    Busy,
+    Tls(TlsError),
+}
+
+/// Connection to the server, over TLS for https
+pub enum ServerStream<'a, C: Read + Write> {
+    Plain(C),
+    /// Drops the connection before giving the buffers back
+    Tls(TlsConnection<'a, C, Aes128GcmSha256>, BufferLease<'a>),
+}
+
+impl<C: Read + Write> ErrorType for ServerStream<'_, C> {
+    type Error = ErrorKind;
+}
+
+impl<C: Read + Write> Read for ServerStream<'_, C> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind> {
+        match self {
+            ServerStream::Plain(conn) => conn.read(buf).await.map_err(|e| e.kind()),
+            ServerStream::Tls(tls, _) => tls.read(buf).await.map_err(|e| e.kind()),
+        }
+    }
+}
+
+impl<C: Read + Write> Write for ServerStream<'_, C> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+        match self {
+            ServerStream::Plain(conn) => conn.write(buf).await.map_err(|e| e.kind()),
+            ServerStream::Tls(tls, _) => tls.write(buf).await.map_err(|e| e.kind()),
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), ErrorKind> {
+        match self {
+            ServerStream::Plain(conn) => conn.flush().await.map_err(|e| e.kind()),
+            ServerStream::Tls(tls, _) => tls.flush().await.map_err(|e| e.kind()),
+        }
+    }
+}
+
+/// Opens connections to the server: TCP, then a TLS handshake for https
+pub struct ServerConnector<'d, T> {
+    tcp: &'d T,
+    buffers: &'d TlsBuffers,
+    /// None if the server isn't verified
+    trust: Option<Trust>,
+    /// Seeds each handshake's RNG
+    rng: RefCell<ChaCha8Rng>,
+}
+
+impl<'d, T: TcpConnect> ServerConnector<'d, T> {
+    /// `seed` must come from the hardware RNG, `spki_pin` is the device
+    /// config's `spki_sha256`
+    pub fn new(
+        tcp: &'d T,
+        buffers: &'d TlsBuffers,
+        seed: [u8; 32],
+        spki_pin: Option<[u8; 32]>,
+    ) -> Self {
+        Self {
+            tcp,
+            buffers,
+            trust: Trust::new(spki_pin),
+            rng: RefCell::new(ChaCha8Rng::from_seed(seed)),
+        }
+    }
+
+    /// Connect to `host` at `addr`, with a TLS handshake if `https`
+    ///
+    /// Only one TLS connection can be open at a time, as they share the
+    /// [`TlsBuffers`].
+    pub async fn connect(
+        &self,
+        host: &str,
+        addr: SocketAddr,
+        https: bool,
+    ) -> Result<ServerStream<'_, T::Connection<'_>>, ConnectError> {
+        let conn = self
+            .tcp
+            .connect(addr)
+            .await
+            .map_err(|_| ConnectError::Tcp)?;
+        if !https {
+            return Ok(ServerStream::Plain(conn));
+        }
+        if !self.trust.as_ref().is_some_and(Trust::verifies) {
+            warn!("TLS server is not verified (no TLS_CA, TLS_SPKI_SHA256 or spki_sha256)");
+        }
+
+        if self.buffers.lent.replace(true) {
+            return Err(ConnectError::Busy);
+        }
+        let lease = BufferLease(&self.buffers.lent);
+        // SAFETY: `lent` keeps the buffers to this connection until the lease,
+        // dropped after it, gives them back
+        let (read, write) = unsafe {
+            (
+                &mut *self.buffers.read.get(),
+                &mut *self.buffers.write.get(),
+            )
+        };
+
+        let mut seed = [0u8; 32];
+        self.rng.borrow_mut().fill_bytes(&mut seed);
+        let provider = Provider {
+            rng: ChaCha8Rng::from_seed(seed),
+            verifier: self.trust.as_ref().map(|trust| ServerVerifier {
+                trust,
+                host,
+                leaf_spki: None,
+                transcript: None,
+            }),
+        };
+        let config = TlsConfig::new()
+            .with_server_name(host)
+            .enable_rsa_signatures();
+        let mut tls = TlsConnection::new(conn, read, write);
+        tls.open(TlsContext::new(&config, provider))
+            .await
+            .map_err(ConnectError::Tls)?;
+        Ok(ServerStream::Tls(tls, lease))
+    }
+
+    /// Whether the last failed handshake failed on the config's `spki_pin`
+    /// alone, clearing it
+    pub fn take_config_pin_failure(&self) -> bool {
+        self.trust
+            .as_ref()
+            .is_some_and(|trust| trust.config_pin_failed.take())
+    }
+
+    /// Stop enforcing the config's `spki_pin` (see [`crate::pin_state`])
+    pub fn set_aside_config_pin(&self) {
+        if let Some(trust) = &self.trust {
+            trust.config_pin.set(None);
+        }
+    }
+}
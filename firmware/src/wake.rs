@@ -0,0 +1,240 @@
+//! Wake cycle state machine
+//!
+//! Every wake moves through the same phases:
+//!
+//! ```text
+//! Boot → LoadState → AcquireContent → Render → Refresh → Persist → Sleep
+//!                                       ↑__________________|
+//! ```
+//!
+//! `main` runs one handler per phase, each ending with the [`Event`] that
+//! picks the next, and a [`WakeCycle`] checks every event against the
+//! transitions below. A handler ending with one its phase doesn't allow is a
+//! bug: rather than carry on in the wrong phase, the wake is abandoned (see
+//! [`WakeCycle::abandon`]) and the frame goes to sleep.
+//!
+//! A wake that shouldn't refresh (quiet hours, a critical battery) skips from
+//! `LoadState` straight to `Sleep`, and one freezing the frame on what's
+//! already shown skips from `AcquireContent`. After each pass a button press
+//! or show request goes around again from `Render`; otherwise the wake
+//! persists its state and sleeps. A pass whose images couldn't be rendered
+//! never starts a refresh.
+
+use log::info;
+
+/// What the frame is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Heap, PSRAM and the async runtime coming up
+    Boot,
+    /// SD card, sleep state, settings, PMIC, panel, WiFi credentials and clock
+    LoadState,
+    /// The widget list (cached or fetched) and where the rotation left off
+    AcquireContent,
+    /// This pass's images fetched (SD or network) and drawn into the framebuffer
+    Render,
+    /// The panel refreshing, with prefetching and button monitoring alongside
+    Refresh,
+    /// Rotation state saved to RTC memory and telemetry reported
+    Persist,
+    /// Powered down until the timer or button (terminal)
+    Sleep,
+}
+
+/// What the current phase ended with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// Boot finished
+    Booted,
+    /// State loaded and this wake should refresh
+    Loaded,
+    /// This wake shouldn't refresh (quiet hours, critical battery, freezing)
+    Skip,
+    /// Items to show are known
+    ContentReady,
+    /// The panel started refreshing with the rendered frame
+    RefreshStarted,
+    /// The pass is over, with or without a refresh
+    PassEnded(NextPass),
+    /// State saved for the next wake
+    Persisted,
+}
+
+/// Whether another pass follows the one that just ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NextPass {
+    /// A button press or show request asks for another pass
+    Another,
+    /// Nothing more to show this wake
+    Done,
+}
+
+/// An event that isn't valid in the current phase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidTransition {
+    pub phase: Phase,
+    pub event: Event,
+}
+
+impl Phase {
+    /// Phase after `event`, if it's valid in this one
+    pub fn next(self, event: Event) -> Result<Phase, InvalidTransition> {
+        let next = match (self, event) {
+            (Phase::Boot, Event::Booted) => Phase::LoadState,
+            (Phase::LoadState, Event::Loaded) => Phase::AcquireContent,
+            (Phase::LoadState | Phase::AcquireContent, Event::Skip) => Phase::Sleep,
+            (Phase::AcquireContent, Event::ContentReady) => Phase::Render,
+            (Phase::Render, Event::RefreshStarted) => Phase::Refresh,
+            (Phase::Render | Phase::Refresh, Event::PassEnded(NextPass::Another)) => Phase::Render,
+            (Phase::Render | Phase::Refresh, Event::PassEnded(NextPass::Done)) => Phase::Persist,
+            (Phase::Persist, Event::Persisted) => Phase::Sleep,
+            (phase, event) => return Err(InvalidTransition { phase, event }),
+        };
+        Ok(next)
+    }
+}
+
+/// Phase of the current wake and the passes made so far
+#[derive(Debug)]
+pub struct WakeCycle {
+    phase: Phase,
+    /// Passes started, counting the first
+    passes: u8,
+}
+
+impl Default for WakeCycle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WakeCycle {
+    pub const fn new() -> Self {
+        Self {
+            phase: Phase::Boot,
+            passes: 0,
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Passes started this wake
+    pub fn passes(&self) -> u8 {
+        self.passes
+    }
+
+    /// Move to the phase after `event`, returning it
+    ///
+    /// An invalid event leaves the phase unchanged.
+    pub fn advance(&mut self, event: Event) -> Result<Phase, InvalidTransition> {
+        let next = self.phase.next(event)?;
+        if next == Phase::Render {
+            self.passes = self.passes.saturating_add(1);
+        }
+        info!("Wake: {:?} -> {:?} ({:?})", self.phase, next, event);
+        self.phase = next;
+        Ok(next)
+    }
+
+    /// Give up on the rest of the wake after an invalid transition, returning
+    /// the phase it ends with
+    ///
+    /// A pass in progress still persists its state before sleeping; before the
+    /// first pass there is nothing to save, so the wake sleeps straight away.
+    pub fn abandon(&mut self) -> Phase {
+        let next = match self.phase {
+            Phase::Render | Phase::Refresh => Phase::Persist,
+            _ => Phase::Sleep,
+        };
+        info!("Wake: {:?} -> {:?} (abandoned)", self.phase, next);
+        self.phase = next;
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wake_cycle() {
+        let mut wake = WakeCycle::new();
+        for (event, phase) in [
+            (Event::Booted, Phase::LoadState),
+            (Event::Loaded, Phase::AcquireContent),
+            (Event::ContentReady, Phase::Render),
+            (Event::RefreshStarted, Phase::Refresh),
+            // Button tap: around again
+            (Event::PassEnded(NextPass::Another), Phase::Render),
+            // The second pass failed to render
+            (Event::PassEnded(NextPass::Done), Phase::Persist),
+            (Event::Persisted, Phase::Sleep),
+        ] {
+            assert_eq!(wake.advance(event), Ok(phase));
+        }
+        assert_eq!(wake.passes(), 2);
+    }
+
+    #[test]
+    fn test_skipped_wake() {
+        let mut wake = WakeCycle::new();
+        wake.advance(Event::Booted).unwrap();
+        assert_eq!(wake.advance(Event::Skip), Ok(Phase::Sleep));
+        assert_eq!(wake.passes(), 0);
+
+        // Freezing on what's shown, once the items are known
+        let mut wake = WakeCycle::new();
+        wake.advance(Event::Booted).unwrap();
+        wake.advance(Event::Loaded).unwrap();
+        assert_eq!(wake.advance(Event::Skip), Ok(Phase::Sleep));
+    }
+
+    #[test]
+    fn test_invalid_transitions() {
+        assert_eq!(
+            Phase::Boot.next(Event::ContentReady),
+            Err(InvalidTransition {
+                phase: Phase::Boot,
+                event: Event::ContentReady
+            })
+        );
+        // Sleep is terminal, and skipping is decided before rendering
+        assert!(Phase::Sleep.next(Event::Booted).is_err());
+        assert!(Phase::Render.next(Event::Skip).is_err());
+        assert!(Phase::Refresh.next(Event::RefreshStarted).is_err());
+
+        // Invalid events are an error, and leave the phase unchanged
+        let mut wake = WakeCycle::new();
+        assert_eq!(
+            wake.advance(Event::Persisted),
+            Err(InvalidTransition {
+                phase: Phase::Boot,
+                event: Event::Persisted
+            })
+        );
+        assert_eq!(wake.phase(), Phase::Boot);
+        assert_eq!(wake.passes(), 0);
+    }
+
+    #[test]
+    fn test_abandoned_wake() {
+        // Mid-pass, the rotation is still saved
+        let mut wake = WakeCycle::new();
+        for event in [Event::Booted, Event::Loaded, Event::ContentReady] {
+            wake.advance(event).unwrap();
+        }
+        assert!(wake.advance(Event::Loaded).is_err());
+        assert_eq!(wake.abandon(), Phase::Persist);
+        assert_eq!(wake.advance(Event::Persisted), Ok(Phase::Sleep));
+
+        // Before the first pass, or once persisted, straight to sleep
+        let mut wake = WakeCycle::new();
+        wake.advance(Event::Booted).unwrap();
+        assert!(wake.advance(Event::RefreshStarted).is_err());
+        assert_eq!(wake.abandon(), Phase::Sleep);
+        assert_eq!(wake.abandon(), Phase::Sleep);
+        assert_eq!(wake.passes(), 0);
+    }
+}
@@ -0,0 +1,96 @@
+//! Stall watchdog
+//!
+//! The chip's hardware watchdog resets it unless fed every
+//! [`HARDWARE_TIMEOUT_SECS`]. A task feeds it once a second, but only while the
+//! main loop keeps beating its [`Heartbeat`] (each wake phase and each retry).
+//! A main loop stuck awaiting something that never comes, like the panel's
+//! busy line clearing, stops the feeding after [`STALL_SECS`]; a blocked
+//! executor stops the task itself. Either way the frame restarts instead of
+//! hanging on the wall until its battery runs out.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Seconds without a heartbeat after which the main loop counts as stalled
+///
+/// Long enough for the slowest pass: a cold full refresh, prefetching the next
+/// images and the button window after it.
+pub const STALL_SECS: u32 = 5 * 60;
+
+/// Seconds the hardware watchdog waits for a feed before resetting the chip
+///
+/// Outlasts the blocking waits for the panel (a cold full refresh), during
+/// which the feeding task can't run.
+pub const HARDWARE_TIMEOUT_SECS: u64 = 90;
+
+/// When the main loop last made progress
+pub struct Heartbeat {
+    /// Seconds since boot of the last beat
+    last_secs: AtomicU32,
+    /// No progress is expected, e.g. while the provisioning portal waits on the user
+    paused: AtomicBool,
+}
+
+impl Heartbeat {
+    pub const fn new() -> Self {
+        Self {
+            last_secs: AtomicU32::new(0),
+            paused: AtomicBool::new(false),
+        }
+    }
+
+    /// Record progress at `now_secs` since boot, resuming a paused heartbeat
+    pub fn beat(&self, now_secs: u32) {
+        self.last_secs.store(now_secs, Ordering::Relaxed);
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Stop expecting progress until the next beat
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the main loop has gone `STALL_SECS` without a beat
+    pub fn stalled(&self, now_secs: u32) -> bool {
+        !self.paused.load(Ordering::Relaxed)
+            && now_secs.saturating_sub(self.last_secs.load(Ordering::Relaxed)) >= STALL_SECS
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stall() {
+        let heartbeat = Heartbeat::new();
+        assert!(!heartbeat.stalled(0));
+        assert!(!heartbeat.stalled(STALL_SECS - 1));
+        assert!(heartbeat.stalled(STALL_SECS));
+
+        // Each beat restarts the count
+        heartbeat.beat(STALL_SECS);
+        assert!(!heartbeat.stalled(2 * STALL_SECS - 1));
+        assert!(heartbeat.stalled(2 * STALL_SECS));
+
+        // A beat from before the clock reading doesn't count as a stall
+        heartbeat.beat(100);
+        assert!(!heartbeat.stalled(50));
+    }
+
+    #[test]
+    fn test_pause() {
+        let heartbeat = Heartbeat::new();
+        heartbeat.pause();
+        assert!(!heartbeat.stalled(10 * STALL_SECS));
+
+        // The next beat resumes watching
+        heartbeat.beat(10 * STALL_SECS);
+        assert!(heartbeat.stalled(11 * STALL_SECS));
+    }
+}
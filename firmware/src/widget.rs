@@ -2,13 +2,20 @@
 //!
 //! JSON format from edge service:
 //! ```json
-//! ["2024-01-01-band-id", "2024-01-02-band-id"]
+//! [{"path": "2024-01-01-band-id", "width": 1, "cache_key": "2024-01-01-band-id",
+//!   "ttl": 86400, "title": "Band"}]
 //! ```
+//!
+//! Only `path` is required: `width` defaults to half, `cache_key` to the path,
+//! and items without a `ttl` never change under their cache key. Bare path
+//! strings (as listed by older servers) are accepted as half-width items.
 
 extern crate alloc;
 
 use alloc::boxed::Box;
+use core::fmt::{self, Write};
 use heapless::{String, Vec};
+use serde::{Deserialize, Serialize};
 
 /// Maximum number of widget items we support
 pub const MAX_ITEMS: usize = 128;
@@ -17,13 +24,15 @@ pub const MAX_ITEMS: usize = 128;
 pub const MAX_PATH_LEN: usize = 48;
 
 /// Display orientation
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum Orientation {
     /// Horizontal: 400x480 (half) or 800x480 (full)
     #[default]
+    #[serde(rename = "horiz")]
     Horizontal = 0,
     /// Vertical: 480x800
+    #[serde(rename = "vert")]
     Vertical = 1,
 }
 
@@ -53,8 +62,74 @@ impl Orientation {
     }
 }
 
-/// Widget data response (array of image paths)
-pub type WidgetData = Vec<String<MAX_PATH_LEN>, MAX_ITEMS>;
+/// Width of a widget item in horizontal mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum WidgetWidth {
+    /// One 400x480 slot
+    #[default]
+    Half = 1,
+    /// The whole 800x480 panel
+    Full = 2,
+}
+
+/// Maximum title length kept per item (longer titles are truncated)
+pub const MAX_TITLE_LEN: usize = 32;
+
+/// Largest widget data JSON accepted, from the server or the SD card
+pub const WIDGET_JSON_SIZE: usize = 32 * 1024;
+
+/// A widget item
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct WidgetItem {
+    /// Image path, as requested from the server
+    pub path: String<MAX_PATH_LEN>,
+    pub width: WidgetWidth,
+    /// Key the image is cached under on the SD card
+    pub cache_key: String<MAX_PATH_LEN>,
+    /// Seconds before the server may re-render the image under the same key
+    pub ttl: Option<u32>,
+    /// What the item shows, empty if untitled
+    pub title: String<MAX_TITLE_LEN>,
+}
+
+impl WidgetItem {
+    /// Image path of the item
+    pub fn as_str(&self) -> &str {
+        self.path.as_str()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.path.as_bytes()
+    }
+
+    /// Key the image is cached under on the SD card
+    pub fn cache_key(&self) -> &str {
+        self.cache_key.as_str()
+    }
+
+    /// Whether the item fills the whole panel in horizontal mode
+    pub fn is_full_width(&self) -> bool {
+        self.width == WidgetWidth::Full
+    }
+
+    /// Whether a cached copy of the image can go out of date
+    pub fn may_change(&self) -> bool {
+        self.ttl.is_some()
+    }
+
+    /// Title for logs, falling back to the path
+    pub fn label(&self) -> &str {
+        if self.title.is_empty() {
+            self.as_str()
+        } else {
+            self.title.as_str()
+        }
+    }
+}
+
+/// Widget data response (array of items)
+pub type WidgetData = Vec<WidgetItem, MAX_ITEMS>;
 
 /// Parse widget data JSON into a heap-allocated vector of items
 pub fn parse_widget_data(json: &str) -> Result<Box<WidgetData>, &'static str> {
@@ -67,46 +142,94 @@ pub fn parse_widget_data(json: &str) -> Result<Box<WidgetData>, &'static str> {
         return Err("expected JSON array");
     }
 
-    let inner = &json[1..json.len() - 1];
-    if inner.trim().is_empty() {
-        return Ok(data);
+    for item in TopLevel::new(&json[1..json.len() - 1]) {
+        if let Some(item) = parse_item(item) {
+            let _ = data.push(item);
+        }
     }
 
-    // Split by comma, handling quoted strings
-    let mut in_string = false;
-    let mut start = 0;
-    let bytes = inner.as_bytes();
+    Ok(data)
+}
 
-    for (i, &b) in bytes.iter().enumerate() {
-        match b {
-            b'"' => in_string = !in_string,
-            b',' if !in_string => {
-                if let Some(s) = parse_string_value(&inner[start..i]) {
-                    let mut item = String::new();
-                    if item.push_str(s).is_ok() {
-                        let _ = data.push(item);
-                    }
+/// Comma separated values of a JSON array or object body, skipping commas in
+/// strings and nested objects
+struct TopLevel<'a> {
+    rest: &'a str,
+}
+
+impl<'a> TopLevel<'a> {
+    fn new(body: &'a str) -> Self {
+        Self { rest: body }
+    }
+}
+
+impl<'a> Iterator for TopLevel<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.rest.trim().is_empty() {
+            return None;
+        }
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut depth = 0i32;
+        for (i, b) in self.rest.bytes().enumerate() {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' if in_string => escaped = true,
+                b'"' => in_string = !in_string,
+                b'{' if !in_string => depth += 1,
+                b'}' if !in_string => depth -= 1,
+                b',' if !in_string && depth == 0 => {
+                    let value = &self.rest[..i];
+                    self.rest = &self.rest[i + 1..];
+                    return Some(value);
                 }
-                start = i + 1;
+                _ => {}
             }
-            _ => {}
         }
+        let value = self.rest;
+        self.rest = "";
+        Some(value)
     }
+}
 
-    // Last item
-    if start < inner.len()
-        && let Some(s) = parse_string_value(&inner[start..])
-    {
-        let mut item = String::new();
-        if item.push_str(s).is_ok() {
-            let _ = data.push(item);
+/// Parse one item: an object with at least its path, or a bare path
+fn parse_item(s: &str) -> Option<WidgetItem> {
+    let s = s.trim();
+    let mut item = WidgetItem::default();
+    let mut cache_key = None;
+    match s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+        Some(fields) => {
+            let mut path = None;
+            for field in TopLevel::new(fields) {
+                let (key, value) = field.split_once(':')?;
+                let value = value.trim();
+                match parse_string_value(key)? {
+                    "path" => path = parse_string_value(value),
+                    "width" if value == "2" => item.width = WidgetWidth::Full,
+                    "cache_key" => cache_key = parse_string_value(value),
+                    "ttl" => item.ttl = value.parse().ok(),
+                    "title" => {
+                        if let Some(title) = parse_string_value(value) {
+                            unescape_into(title, &mut item.title);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            unescape_into(path?, &mut item.path).then_some(())?;
         }
+        None => unescape_into(parse_string_value(s)?, &mut item.path).then_some(())?,
     }
-
-    Ok(data)
+    match cache_key {
+        Some(key) => unescape_into(key, &mut item.cache_key).then_some(())?,
+        None => item.cache_key = item.path.clone(),
+    }
+    Some(item)
 }
 
-/// Parse a JSON string value, returning the unquoted content
+/// Parse a JSON string value, returning the quoted content (still escaped)
 fn parse_string_value(s: &str) -> Option<&str> {
     let s = s.trim();
     if s.starts_with('"') && s.ends_with('"') && s.len() >= 2 {
@@ -116,6 +239,74 @@ fn parse_string_value(s: &str) -> Option<&str> {
     }
 }
 
+/// Append escaped JSON string content to `out`, returning false if it didn't fit
+///
+/// Whatever fits is kept, so titles are truncated at a character boundary.
+/// Control characters become spaces and `\u` escapes outside the basic
+/// multilingual plane become `?`.
+fn unescape_into<const N: usize>(s: &str, out: &mut String<N>) -> bool {
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('u') => {
+                    let hex: String<16> = chars.by_ref().take(4).collect();
+                    u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .unwrap_or('?')
+                }
+                Some('"') => '"',
+                Some('/') => '/',
+                Some('\\') => '\\',
+                Some(_) => ' ',
+                None => break,
+            },
+            c => c,
+        };
+        let c = if c.is_control() { ' ' } else { c };
+        if out.push(c).is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Longest item written by `write_item_json`
+pub const ITEM_JSON_LEN: usize = 256;
+
+/// Write an item as a JSON object, in the format it was listed in
+pub fn write_item_json(item: &WidgetItem, out: &mut String<ITEM_JSON_LEN>) -> fmt::Result {
+    out.clear();
+    out.write_str("{\"path\":")?;
+    write_json_string(item.as_str(), out)?;
+    write!(out, ",\"width\":{},\"cache_key\":", item.width as u8)?;
+    write_json_string(item.cache_key(), out)?;
+    if let Some(ttl) = item.ttl {
+        write!(out, ",\"ttl\":{}", ttl)?;
+    }
+    if !item.title.is_empty() {
+        out.write_str(",\"title\":")?;
+        write_json_string(item.title.as_str(), out)?;
+    }
+    out.write_char('}')
+}
+
+/// Write `s` as a quoted JSON string
+fn write_json_string(s: &str, out: &mut impl fmt::Write) -> fmt::Result {
+    out.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                out.write_char('\\')?;
+                out.write_char(c)?;
+            }
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -130,6 +321,52 @@ mod tests {
         assert_eq!(items.len(), 2);
         assert_eq!(items[0].as_str(), "2024-01-01-band-id");
         assert_eq!(items[1].as_str(), "2024-01-02-band-id");
+        assert!(!items[0].is_full_width());
+    }
+
+    #[test]
+    fn test_parse_full_width_items() {
+        let json = r#"["2024-01-01-band-id", {"path": "2024-01-02-agenda", "width": 2}, {"width":1,"path":"2024-01-03-band-id"}]"#;
+
+        let items = parse_widget_data(json).unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[1].as_str(), "2024-01-02-agenda");
+        assert!(items[1].is_full_width());
+        assert_eq!(items[2].as_str(), "2024-01-03-band-id");
+        assert!(!items[2].is_full_width());
+    }
+
+    #[test]
+    fn test_parse_structured_items() {
+        let json = r#"[{"path":"2024-01-01-band-id","width":1,"cache_key":"1a2b3c4d","ttl":86400,
+            "title":"Crosby, Stills \"&\" Nash: Live \u00e9"},
+            {"path":"abc","title":"A really long title that won't fit on the device"}]"#;
+
+        let items = parse_widget_data(json).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_str(), "2024-01-01-band-id");
+        assert_eq!(items[0].cache_key(), "1a2b3c4d");
+        assert_eq!(items[0].ttl, Some(86400));
+        assert!(items[0].may_change());
+        assert_eq!(items[0].label(), "Crosby, Stills \"&\" Nash: Live é");
+
+        // Defaults, and titles truncated to fit
+        assert_eq!(items[1].cache_key(), "abc");
+        assert!(!items[1].may_change());
+        assert_eq!(items[1].title.as_str(), "A really long title that won't f");
+
+        // Written back in the same format
+        let mut json: alloc::string::String = "[".into();
+        let mut item_json = String::new();
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write_item_json(item, &mut item_json).unwrap();
+            json.push_str(&item_json);
+        }
+        json.push(']');
+        assert_eq!(*parse_widget_data(&json).unwrap(), *items);
     }
 
     #[test]
@@ -0,0 +1,628 @@
+//! X.509 certificate parsing and chain checks for verifying the server
+//!
+//! [`crate::tls`] verifies the server's certificate during the TLS handshake
+//! against what the build embeds. This module reads the parts of a DER
+//! certificate that takes: names, validity, basic constraints, the subject
+//! alternative names and the public key. It also walks the chain up to a root.
+//! Certificates are parsed in place, borrowing from the handshake message.
+//!
+//! Nothing here does any cryptography. [`verify_chain`] takes the signature
+//! check as a closure, so the chain rules can run on the host.
+
+use core::net::Ipv4Addr;
+
+/// Longest chain walked from the leaf to the root
+const MAX_CHAIN_DEPTH: usize = 4;
+
+/// DER tags
+const TAG_BOOLEAN: u8 = 0x01;
+const TAG_INTEGER: u8 = 0x02;
+const TAG_BIT_STRING: u8 = 0x03;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_UTC_TIME: u8 = 0x17;
+const TAG_GENERALIZED_TIME: u8 = 0x18;
+const TAG_SEQUENCE: u8 = 0x30;
+/// `[0] EXPLICIT` version of a TBSCertificate
+const TAG_VERSION: u8 = 0xa0;
+/// `[3] EXPLICIT` extensions of a TBSCertificate
+const TAG_EXTENSIONS: u8 = 0xa3;
+/// `dNSName` and `iPAddress` in GeneralNames
+const TAG_DNS_NAME: u8 = 0x82;
+const TAG_IP_ADDRESS: u8 = 0x87;
+
+/// Algorithm OIDs (DER contents)
+const OID_ECDSA_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_ECDSA_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+const OID_RSA_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const OID_RSA_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_P384: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+const OID_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+/// Extensions (`2.5.29.x`, encoded `55 1d x`)
+const EXT_SUBJECT_KEY_ID: u8 = 14;
+const EXT_KEY_USAGE: u8 = 15;
+const EXT_SUBJECT_ALT_NAME: u8 = 17;
+const EXT_BASIC_CONSTRAINTS: u8 = 19;
+const EXT_CERTIFICATE_POLICIES: u8 = 32;
+const EXT_AUTHORITY_KEY_ID: u8 = 35;
+const EXT_EXTENDED_KEY_USAGE: u8 = 37;
+
+/// Why a certificate or chain was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum X509Error {
+    /// Not valid DER, or not a certificate
+    Malformed,
+    /// Uses an algorithm, key type or critical extension that isn't supported
+    Unsupported,
+    /// The leaf isn't for the server's name
+    NameMismatch,
+    /// Outside its validity period
+    Expired,
+    /// No chain up to the root
+    UnknownIssuer,
+    /// A signature in the chain didn't verify
+    BadSignature,
+}
+
+/// Hash a signature is made over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hash {
+    Sha256,
+    Sha384,
+}
+
+/// Algorithm a certificate is signed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureAlgorithm {
+    Ecdsa(Hash),
+    /// RSASSA-PKCS1-v1_5
+    Rsa(Hash),
+}
+
+/// Public key of a certificate's subject
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublicKey<'a> {
+    /// SEC1 encoded point
+    P256(&'a [u8]),
+    /// SEC1 encoded point
+    P384(&'a [u8]),
+    /// PKCS#1 `RSAPublicKey`
+    Rsa(&'a [u8]),
+}
+
+impl<'a> PublicKey<'a> {
+    /// Key of a DER SubjectPublicKeyInfo
+    pub fn from_spki(spki: &'a [u8]) -> Result<Self, X509Error> {
+        let (spki, _) = expect(spki, TAG_SEQUENCE)?;
+        let (algorithm, rest) = expect(spki, TAG_SEQUENCE)?;
+        let (key, _) = expect(rest, TAG_BIT_STRING)?;
+        let key = bit_string(key)?;
+        let (oid, params) = expect(algorithm, TAG_OID)?;
+        match oid {
+            OID_EC_PUBLIC_KEY => match expect(params, TAG_OID)?.0 {
+                OID_P256 => Ok(Self::P256(key)),
+                OID_P384 => Ok(Self::P384(key)),
+                _ => Err(X509Error::Unsupported),
+            },
+            OID_RSA => Ok(Self::Rsa(key)),
+            _ => Err(X509Error::Unsupported),
+        }
+    }
+}
+
+/// The parts of a DER certificate the verifier uses
+#[derive(Debug, Clone)]
+pub struct Certificate<'a> {
+    /// DER of the TBSCertificate, which `signature` is made over
+    pub tbs: &'a [u8],
+    pub signature_algorithm: SignatureAlgorithm,
+    pub signature: &'a [u8],
+    /// DER of the issuer and subject names, compared byte for byte
+    pub issuer: &'a [u8],
+    pub subject: &'a [u8],
+    /// Validity period, in Unix seconds
+    pub not_before: u64,
+    pub not_after: u64,
+    /// DER of the SubjectPublicKeyInfo, which SPKI pins are a hash of
+    pub spki: &'a [u8],
+    pub public_key: PublicKey<'a>,
+    /// Basic constraints allow it to issue certificates
+    pub is_ca: bool,
+    /// Contents of the subject alternative names, if any
+    alt_names: Option<&'a [u8]>,
+}
+
+impl<'a> Certificate<'a> {
+    /// Parse a DER certificate
+    pub fn parse(der: &'a [u8]) -> Result<Self, X509Error> {
+        let (certificate, rest) = expect(der, TAG_SEQUENCE)?;
+        if !rest.is_empty() {
+            return Err(X509Error::Malformed);
+        }
+        let (tbs_value, after_tbs) = expect(certificate, TAG_SEQUENCE)?;
+        let tbs = &certificate[..certificate.len() - after_tbs.len()];
+        let (algorithm, rest) = expect(after_tbs, TAG_SEQUENCE)?;
+        let signature_algorithm = signature_algorithm(algorithm)?;
+        let (signature, _) = expect(rest, TAG_BIT_STRING)?;
+        let signature = bit_string(signature)?;
+
+        let mut tbs_rest = tbs_value;
+        if tbs_rest.first() == Some(&TAG_VERSION) {
+            tbs_rest = read(tbs_rest)?.2;
+        }
+        let (_serial, rest) = expect(tbs_rest, TAG_INTEGER)?;
+        let (_algorithm, rest) = expect(rest, TAG_SEQUENCE)?;
+        let (issuer, rest) = expect(rest, TAG_SEQUENCE)?;
+        let (validity, rest) = expect(rest, TAG_SEQUENCE)?;
+        let (not_before, validity) = time(validity)?;
+        let (not_after, _) = time(validity)?;
+        let (subject, rest) = expect(rest, TAG_SEQUENCE)?;
+        let (_, after_spki) = expect(rest, TAG_SEQUENCE)?;
+        let spki = &rest[..rest.len() - after_spki.len()];
+        let public_key = PublicKey::from_spki(spki)?;
+
+        let mut cert = Self {
+            tbs,
+            signature_algorithm,
+            signature,
+            issuer,
+            subject,
+            not_before,
+            not_after,
+            spki,
+            public_key,
+            is_ca: false,
+            alt_names: None,
+        };
+        // Skips the unique identifiers, which come before the extensions
+        let mut rest = after_spki;
+        while !rest.is_empty() {
+            let (tag, value, next) = read(rest)?;
+            if tag == TAG_EXTENSIONS {
+                cert.read_extensions(value)?;
+            }
+            rest = next;
+        }
+        Ok(cert)
+    }
+
+    fn read_extensions(&mut self, explicit: &'a [u8]) -> Result<(), X509Error> {
+        let (mut extensions, _) = expect(explicit, TAG_SEQUENCE)?;
+        while !extensions.is_empty() {
+            let (extension, next) = expect(extensions, TAG_SEQUENCE)?;
+            extensions = next;
+            let (oid, mut rest) = expect(extension, TAG_OID)?;
+            let mut critical = false;
+            if rest.first() == Some(&TAG_BOOLEAN) {
+                let (value, next) = expect(rest, TAG_BOOLEAN)?;
+                critical = value.first().is_some_and(|&b| b != 0);
+                rest = next;
+            }
+            let (value, _) = expect(rest, TAG_OCTET_STRING)?;
+
+            let id = match oid {
+                [0x55, 0x1d, id] => Some(*id),
+                _ => None,
+            };
+            match id {
+                Some(EXT_BASIC_CONSTRAINTS) => {
+                    let (constraints, _) = expect(value, TAG_SEQUENCE)?;
+                    if constraints.first() == Some(&TAG_BOOLEAN) {
+                        let (ca, _) = expect(constraints, TAG_BOOLEAN)?;
+                        self.is_ca = ca.first().is_some_and(|&b| b != 0);
+                    }
+                }
+                Some(EXT_SUBJECT_ALT_NAME) => {
+                    self.alt_names = Some(expect(value, TAG_SEQUENCE)?.0);
+                }
+                Some(
+                    EXT_SUBJECT_KEY_ID
+                    | EXT_KEY_USAGE
+                    | EXT_CERTIFICATE_POLICIES
+                    | EXT_AUTHORITY_KEY_ID
+                    | EXT_EXTENDED_KEY_USAGE,
+                ) => {}
+                // A critical extension that isn't understood (e.g. name
+                // constraints) must fail the certificate
+                _ if critical => return Err(X509Error::Unsupported),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the subject alternative names cover `host` (a DNS name or an
+    /// IPv4 address). The common name isn't looked at.
+    pub fn matches_host(&self, host: &str) -> bool {
+        let ip = host.parse::<Ipv4Addr>().ok();
+        let mut names = match self.alt_names {
+            Some(names) => names,
+            None => return false,
+        };
+        while let Ok((tag, value, next)) = read(names) {
+            names = next;
+            let matched = match (tag, ip) {
+                (TAG_DNS_NAME, None) => name_matches(value, host),
+                (TAG_IP_ADDRESS, Some(ip)) => value == ip.octets(),
+                _ => false,
+            };
+            if matched {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether `now` (Unix seconds) is in the validity period
+    pub fn valid_at(&self, now: u64) -> bool {
+        self.not_before <= now && now <= self.not_after
+    }
+}
+
+/// Verify that `chain` (leaf first, as the server sent it) is for `host` and
+/// leads up to `root`
+///
+/// Each certificate must be signed by the next one up, which must be a CA,
+/// until one is signed by the root. Extra certificates the server sends are
+/// skipped. Validity periods are checked when `now` is known (the root's
+/// isn't). `verify_signature` checks that a certificate's signature verifies
+/// with the given key.
+pub fn verify_chain(
+    chain: &[Certificate],
+    root: &Certificate,
+    host: &str,
+    now: Option<u64>,
+    verify_signature: impl Fn(&Certificate, &PublicKey) -> bool,
+) -> Result<(), X509Error> {
+    let leaf = chain.first().ok_or(X509Error::Malformed)?;
+    if !leaf.matches_host(host) {
+        return Err(X509Error::NameMismatch);
+    }
+    let mut cert = leaf;
+    for _ in 0..MAX_CHAIN_DEPTH {
+        if now.is_some_and(|now| !cert.valid_at(now)) {
+            return Err(X509Error::Expired);
+        }
+        if cert.issuer == root.subject {
+            return if verify_signature(cert, &root.public_key) {
+                Ok(())
+            } else {
+                Err(X509Error::BadSignature)
+            };
+        }
+        let issuer = chain[1..]
+            .iter()
+            .find(|issuer| issuer.is_ca && issuer.subject == cert.issuer)
+            .ok_or(X509Error::UnknownIssuer)?;
+        if !verify_signature(cert, &issuer.public_key) {
+            return Err(X509Error::BadSignature);
+        }
+        cert = issuer;
+    }
+    Err(X509Error::UnknownIssuer)
+}
+
+/// Whether a `dNSName` (which may start with a `*.` wildcard for exactly one
+/// label) matches `host`, ignoring case
+fn name_matches(pattern: &[u8], host: &str) -> bool {
+    let host = host.trim_end_matches('.').as_bytes();
+    match pattern.strip_prefix(b"*.") {
+        Some(suffix) => match host.iter().position(|&b| b == b'.') {
+            Some(dot) => dot > 0 && host[dot + 1..].eq_ignore_ascii_case(suffix),
+            None => false,
+        },
+        None => pattern.eq_ignore_ascii_case(host),
+    }
+}
+
+/// Parse a SHA-256 SPKI pin, 64 hex digits
+pub const fn parse_spki_pin(hex: &str) -> Option<[u8; 32]> {
+    let hex = hex.as_bytes();
+    if hex.len() != 64 {
+        return None;
+    }
+    let mut pin = [0u8; 32];
+    let mut i = 0;
+    while i < 32 {
+        let (Some(high), Some(low)) = (hex_digit(hex[2 * i]), hex_digit(hex[2 * i + 1])) else {
+            return None;
+        };
+        pin[i] = high << 4 | low;
+        i += 1;
+    }
+    Some(pin)
+}
+
+const fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Read one DER element: its tag, contents and what follows it
+fn read(input: &[u8]) -> Result<(u8, &[u8], &[u8]), X509Error> {
+    let (&tag, rest) = input.split_first().ok_or(X509Error::Malformed)?;
+    let (&first, rest) = rest.split_first().ok_or(X509Error::Malformed)?;
+    let (len, rest) = match first {
+        0..=0x7f => (first as usize, rest),
+        0x81..=0x83 => {
+            let bytes = (first & 0x7f) as usize;
+            if rest.len() < bytes {
+                return Err(X509Error::Malformed);
+            }
+            let len = rest[..bytes]
+                .iter()
+                .fold(0usize, |len, &b| len << 8 | b as usize);
+            (len, &rest[bytes..])
+        }
+        _ => return Err(X509Error::Malformed),
+    };
+    if rest.len() < len {
+        return Err(X509Error::Malformed);
+    }
+    Ok((tag, &rest[..len], &rest[len..]))
+}
+
+/// Read one DER element with the given tag
+fn expect(input: &[u8], tag: u8) -> Result<(&[u8], &[u8]), X509Error> {
+    match read(input)? {
+        (found, value, rest) if found == tag => Ok((value, rest)),
+        _ => Err(X509Error::Malformed),
+    }
+}
+
+/// Contents of a BIT STRING without unused bits
+fn bit_string(value: &[u8]) -> Result<&[u8], X509Error> {
+    match value.split_first() {
+        Some((0, bits)) => Ok(bits),
+        _ => Err(X509Error::Malformed),
+    }
+}
+
+fn signature_algorithm(algorithm: &[u8]) -> Result<SignatureAlgorithm, X509Error> {
+    let (oid, _) = expect(algorithm, TAG_OID)?;
+    match oid {
+        OID_ECDSA_SHA256 => Ok(SignatureAlgorithm::Ecdsa(Hash::Sha256)),
+        OID_ECDSA_SHA384 => Ok(SignatureAlgorithm::Ecdsa(Hash::Sha384)),
+        OID_RSA_SHA256 => Ok(SignatureAlgorithm::Rsa(Hash::Sha256)),
+        OID_RSA_SHA384 => Ok(SignatureAlgorithm::Rsa(Hash::Sha384)),
+        _ => Err(X509Error::Unsupported),
+    }
+}
+
+/// Read a UTCTime or GeneralizedTime (`Z` only, as RFC 5280 requires) as
+/// Unix seconds
+fn time(input: &[u8]) -> Result<(u64, &[u8]), X509Error> {
+    let (tag, value, rest) = read(input)?;
+    let (year, date) = match (tag, value.len()) {
+        (TAG_UTC_TIME, 13) => {
+            let year = digits(&value[..2])?;
+            (
+                if year < 50 { 2000 + year } else { 1900 + year },
+                &value[2..],
+            )
+        }
+        (TAG_GENERALIZED_TIME, 15) => (digits(&value[..4])?, &value[4..]),
+        _ => return Err(X509Error::Malformed),
+    };
+    if date[10] != b'Z' {
+        return Err(X509Error::Malformed);
+    }
+    let month = digits(&date[0..2])?;
+    let day = digits(&date[2..4])?;
+    let hours = digits(&date[4..6])?;
+    let minutes = digits(&date[6..8])?;
+    let seconds = digits(&date[8..10])?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || year < 1970 {
+        return Err(X509Error::Malformed);
+    }
+    let days = days_from_civil(year, month, day);
+    Ok((days * 86_400 + hours * 3600 + minutes * 60 + seconds, rest))
+}
+
+fn digits(ascii: &[u8]) -> Result<u64, X509Error> {
+    ascii.iter().try_fold(0, |n, &c| match c {
+        b'0'..=b'9' => Ok(n * 10 + u64::from(c - b'0')),
+        _ => Err(X509Error::Malformed),
+    })
+}
+
+/// Days since 1970-01-01 of a date from 1970 on (Howard Hinnant's
+/// days-from-civil, the inverse of [`crate::clock::CivilTime::from_unix`])
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: &[u8] = include_bytes!("../testdata/x509/root.der");
+    const INTERMEDIATE: &[u8] = include_bytes!("../testdata/x509/inter.der");
+    const LEAF: &[u8] = include_bytes!("../testdata/x509/leaf.der");
+    /// Self-signed RSA certificate
+    const RSA: &[u8] = include_bytes!("../testdata/x509/rsa.der");
+    /// For the leaf's name, but signed with the leaf's key
+    const FORGED: &[u8] = include_bytes!("../testdata/x509/evil.der");
+
+    /// 2026-02-01, inside the leaf's validity
+    const NOW: u64 = 1_769_904_000;
+
+    /// Stands in for the signature check: a certificate verifies with the key
+    /// of the certificate whose subject it names as issuer
+    fn signed_by_issuer<'a>(
+        certs: &'a [Certificate<'a>],
+    ) -> impl Fn(&Certificate, &PublicKey) -> bool + 'a {
+        |cert, key| {
+            certs
+                .iter()
+                .any(|issuer| issuer.subject == cert.issuer && issuer.public_key == *key)
+        }
+    }
+
+    #[test]
+    fn test_parse_leaf() {
+        let leaf = Certificate::parse(LEAF).unwrap();
+        assert_eq!(
+            leaf.signature_algorithm,
+            SignatureAlgorithm::Ecdsa(Hash::Sha256)
+        );
+        // 2026-01-01 to 2026-04-01
+        assert_eq!(leaf.not_before, 1_767_225_600);
+        assert_eq!(leaf.not_after, 1_775_001_600);
+        assert!(!leaf.is_ca);
+        assert!(matches!(leaf.public_key, PublicKey::P256(point) if point.len() == 65));
+        // The whole SubjectPublicKeyInfo, tag and length included
+        assert_eq!(leaf.spki.len(), 91);
+        assert_eq!(&leaf.spki[..2], &[0x30, 0x59]);
+        // The TBSCertificate follows the outer SEQUENCE header
+        assert_eq!(leaf.tbs, &LEAF[4..4 + 4 + 382]);
+
+        let intermediate = Certificate::parse(INTERMEDIATE).unwrap();
+        assert!(intermediate.is_ca);
+        assert_eq!(leaf.issuer, intermediate.subject);
+        assert_eq!(
+            intermediate.signature_algorithm,
+            SignatureAlgorithm::Ecdsa(Hash::Sha384)
+        );
+        let root = Certificate::parse(ROOT).unwrap();
+        assert!(matches!(root.public_key, PublicKey::P384(point) if point.len() == 97));
+    }
+
+    #[test]
+    fn test_parse_rsa() {
+        let cert = Certificate::parse(RSA).unwrap();
+        assert_eq!(
+            cert.signature_algorithm,
+            SignatureAlgorithm::Rsa(Hash::Sha256)
+        );
+        assert!(matches!(cert.public_key, PublicKey::Rsa(key) if key[0] == 0x30));
+    }
+
+    #[test]
+    fn test_parse_rejects_garbage() {
+        assert_eq!(Certificate::parse(&[]).unwrap_err(), X509Error::Malformed);
+        assert_eq!(
+            Certificate::parse(&LEAF[..LEAF.len() - 1]).unwrap_err(),
+            X509Error::Malformed
+        );
+        let mut trailing = LEAF.to_vec();
+        trailing.push(0);
+        assert_eq!(
+            Certificate::parse(&trailing).unwrap_err(),
+            X509Error::Malformed
+        );
+    }
+
+    #[test]
+    fn test_matches_host() {
+        let leaf = Certificate::parse(LEAF).unwrap();
+        assert!(leaf.matches_host("frame.example.com"));
+        assert!(leaf.matches_host("FRAME.Example.com."));
+        assert!(leaf.matches_host("kitchen.frames.example.net"));
+        assert!(leaf.matches_host("192.168.1.42"));
+        assert!(!leaf.matches_host("example.com"));
+        assert!(!leaf.matches_host("frames.example.net"));
+        assert!(!leaf.matches_host("a.kitchen.frames.example.net"));
+        assert!(!leaf.matches_host("192.168.1.43"));
+        // No subject alternative names at all
+        let root = Certificate::parse(ROOT).unwrap();
+        assert!(!root.matches_host("Test Root"));
+    }
+
+    #[test]
+    fn test_verify_chain() {
+        let root = Certificate::parse(ROOT).unwrap();
+        let chain = [
+            Certificate::parse(LEAF).unwrap(),
+            Certificate::parse(INTERMEDIATE).unwrap(),
+        ];
+        let mut all = chain.to_vec();
+        all.push(root.clone());
+        let verify = signed_by_issuer(&all);
+
+        assert_eq!(
+            verify_chain(&chain, &root, "frame.example.com", Some(NOW), &verify),
+            Ok(())
+        );
+        // Without a clock the validity periods are skipped
+        assert_eq!(
+            verify_chain(&chain, &root, "frame.example.com", None, &verify),
+            Ok(())
+        );
+        // A root sent along with the chain is fine too
+        assert_eq!(
+            verify_chain(&all, &root, "frame.example.com", Some(NOW), &verify),
+            Ok(())
+        );
+        assert_eq!(
+            verify_chain(&chain, &root, "other.example.com", Some(NOW), &verify),
+            Err(X509Error::NameMismatch)
+        );
+        assert_eq!(
+            verify_chain(
+                &chain,
+                &root,
+                "frame.example.com",
+                Some(NOW + 90 * 86_400),
+                &verify
+            ),
+            Err(X509Error::Expired)
+        );
+        assert_eq!(
+            verify_chain(&chain, &root, "frame.example.com", Some(NOW), |_, _| false),
+            Err(X509Error::BadSignature)
+        );
+        // The intermediate is missing
+        assert_eq!(
+            verify_chain(&chain[..1], &root, "frame.example.com", Some(NOW), &verify),
+            Err(X509Error::UnknownIssuer)
+        );
+    }
+
+    #[test]
+    fn test_verify_chain_needs_ca_issuers() {
+        // The leaf can't vouch for another certificate with its name
+        let root = Certificate::parse(ROOT).unwrap();
+        let chain = [
+            Certificate::parse(FORGED).unwrap(),
+            Certificate::parse(LEAF).unwrap(),
+            Certificate::parse(INTERMEDIATE).unwrap(),
+        ];
+        let verify = signed_by_issuer(&chain);
+        assert_eq!(
+            verify_chain(&chain, &root, "frame.example.com", Some(NOW), verify),
+            Err(X509Error::UnknownIssuer)
+        );
+    }
+
+    #[test]
+    fn test_parse_spki_pin() {
+        let hex = "ef4828e2a577251914ecaee0c9d10c7f2084c96ad2b5a2f2275f0560f7f96c03";
+        let pin = parse_spki_pin(hex).unwrap();
+        assert_eq!(pin[0], 0xef);
+        assert_eq!(pin[31], 0x03);
+        assert_eq!(parse_spki_pin(&hex.to_uppercase()), Some(pin));
+        assert_eq!(parse_spki_pin(&hex[2..]), None);
+        assert_eq!(parse_spki_pin(&hex.replace('e', "g")), None);
+    }
+
+    #[test]
+    fn test_days_from_civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(2026, 1, 1) * 86_400, 1_767_225_600);
+    }
+}
@@ -76,6 +76,106 @@
               description = "RUST_LOG filter string";
             };
 
+            refreshInterval = lib.mkOption {
+              type = lib.types.ints.between 60 86400;
+              default = 900;
+              description = "Seconds between device display refreshes";
+            };
+
+            defaultOrientation = lib.mkOption {
+              type = lib.types.enum [ "horiz" "vert" ];
+              default = "horiz";
+              description = "Orientation used until toggled on the device";
+            };
+
+            widgets = lib.mkOption {
+              type = lib.types.listOf (lib.types.enum [ "concerts" "spotify" "lastfm" "calendar" "photos" ]);
+              default = [ "concerts" ];
+              description = "Widgets the device rotates through";
+            };
+
+            heldWidget = lib.mkOption {
+              type = lib.types.nullOr (lib.types.enum [ "concerts" "spotify" "lastfm" "calendar" "photos" ]);
+              default = null;
+              description = "Widget held in the right half in horizontal mode, while the left half rotates";
+            };
+
+            heldWidgetTtl = lib.mkOption {
+              type = lib.types.ints.between 60 86400;
+              default = 3600;
+              description = "Seconds the held widget stays before it's refreshed";
+            };
+
+            concertsLimit = lib.mkOption {
+              type = lib.types.ints.between 1 128;
+              default = 128;
+              description = "Number of concerts the concerts widget rotates through";
+            };
+
+            concertsSort = lib.mkOption {
+              type = lib.types.enum [ "newest" "oldest" ];
+              default = "newest";
+              description = "Whether the concert rotation starts from the newest or oldest concert";
+            };
+
+            concertsSince = lib.mkOption {
+              type = lib.types.nullOr lib.types.str;
+              default = null;
+              example = "2015";
+              description = "Skip concerts before this year (YYYY) or date (YYYY-MM-DD)";
+            };
+
+            imageFit = lib.mkOption {
+              type = lib.types.enum [ "cover" "auto" "letterbox" ];
+              default = "cover";
+              description = "How cover art is fitted into cards";
+            };
+
+            imageDither = lib.mkOption {
+              type = lib.types.enum [ "fs" "atkinson" "jjn" "ordered" "none" ];
+              default = "fs";
+              description = "Dithering algorithm (Floyd-Steinberg, Atkinson, Jarvis-Judice-Ninke, ordered or none)";
+            };
+
+            imageDitherScan = lib.mkOption {
+              type = lib.types.enum [ "serpentine" "raster" ];
+              default = "serpentine";
+              description = "Error diffusion scan order (raster is kept for comparison)";
+            };
+
+            widgetDither = lib.mkOption {
+              type = lib.types.attrsOf (lib.types.enum [ "fs" "atkinson" "jjn" "ordered" "none" ]);
+              default = { };
+              example = { spotify = "ordered"; };
+              description = "Dithering algorithm per widget, overriding imageDither";
+            };
+
+            experiment = lib.mkOption {
+              type = lib.types.nullOr (lib.types.submodule {
+                options = {
+                  name = lib.mkOption {
+                    type = lib.types.str;
+                    default = "render";
+                    description = "Experiment name reported in X-Render-Variant";
+                  };
+                  variants = lib.mkOption {
+                    type = lib.types.listOf lib.types.str;
+                    example = [ "fs" "ordered:dither=ordered" ];
+                    description = "Variants as name[:key=value,...] with fit, saturation, dither and scan keys";
+                  };
+                };
+              });
+              default = null;
+              description = "Rendering A/B experiment across devices";
+            };
+
+            environmentFile = lib.mkOption {
+              type = lib.types.nullOr lib.types.path;
+              default = null;
+              example = "/run/secrets/sawthat-frame-server.env";
+              description = "File with secret environment variables, e.g. SPOTIFY_CLIENT_ID, SPOTIFY_CLIENT_SECRET, SPOTIFY_REFRESH_TOKEN, LASTFM_API_KEY, CALENDAR_URL or PHOTOS_URL and their credentials";
+            };
+
             package = lib.mkOption {
               type = lib.types.package;
               default = self.packages.${pkgs.system}.server;
@@ -99,13 +199,36 @@
               environment = {
                 PORT = toString cfg.port;
                 RUST_LOG = cfg.logLevel;
-              };
+                REFRESH_INTERVAL_SECS = toString cfg.refreshInterval;
+                DEFAULT_ORIENTATION = cfg.defaultOrientation;
+                WIDGETS = lib.concatStringsSep "," cfg.widgets;
+                CONCERTS_LIMIT = toString cfg.concertsLimit;
+                CONCERTS_SORT = cfg.concertsSort;
+                IMAGE_FIT = cfg.imageFit;
+                IMAGE_DITHER = cfg.imageDither;
+                IMAGE_DITHER_SCAN = cfg.imageDitherScan;
+                CACHE_DIR = "/var/cache/sawthat-frame-server";
+                STATE_DIR = "/var/lib/sawthat-frame-server";
+              } // lib.optionalAttrs (cfg.heldWidget != null) {
+                HELD_WIDGET = cfg.heldWidget;
+                HELD_WIDGET_TTL_SECS = toString cfg.heldWidgetTtl;
+              } // lib.optionalAttrs (cfg.concertsSince != null) {
+                CONCERTS_SINCE = cfg.concertsSince;
+              } // lib.optionalAttrs (cfg.experiment != null) {
+                EXPERIMENT_NAME = cfg.experiment.name;
+                EXPERIMENT_VARIANTS = lib.concatStringsSep ";" cfg.experiment.variants;
+              } // lib.mapAttrs' (
+                widget: dither: lib.nameValuePair "${lib.toUpper widget}_DITHER" dither
+              ) cfg.widgetDither;
 
               serviceConfig = {
                 Type = "simple";
                 ExecStart = "${cfg.package}/bin/sawthat-frame-server";
                 Restart = "on-failure";
                 RestartSec = 5;
+                CacheDirectory = "sawthat-frame-server";
+                StateDirectory = "sawthat-frame-server";
+                EnvironmentFile = lib.mkIf (cfg.environmentFile != null) cfg.environmentFile;
 
                 # Hardening
                 DynamicUser = true;
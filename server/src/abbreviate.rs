@@ -0,0 +1,236 @@
+//! Venue abbreviations
+//!
+//! Long venues are shortened before their font is shrunk: a trailing US state
+//! name becomes its postal code, then dictionary phrases ("Performing Arts
+//! Center" → "PAC") are replaced one at a time until the line fits. Extra
+//! phrases can be configured with `VENUE_ABBREVIATIONS`.
+
+use std::sync::OnceLock;
+
+/// Abbreviation dictionary, configured once at startup
+static ABBREVIATIONS: OnceLock<Abbreviations> = OnceLock::new();
+
+/// Built-in phrases, applied in order after any configured ones
+const DEFAULT_PHRASES: &[(&str, &str)] = &[
+    ("Performing Arts Center", "PAC"),
+    ("Performing Arts Centre", "PAC"),
+    ("Amphitheater", "Amph."),
+    ("Amphitheatre", "Amph."),
+    ("Auditorium", "Aud."),
+    ("Center", "Ctr."),
+    ("Centre", "Ctr."),
+];
+
+/// US state names and their postal codes
+const STATES: &[(&str, &str)] = &[
+    ("Alabama", "AL"),
+    ("Alaska", "AK"),
+    ("Arizona", "AZ"),
+    ("Arkansas", "AR"),
+    ("California", "CA"),
+    ("Colorado", "CO"),
+    ("Connecticut", "CT"),
+    ("Delaware", "DE"),
+    ("District of Columbia", "DC"),
+    ("Florida", "FL"),
+    ("Georgia", "GA"),
+    ("Hawaii", "HI"),
+    ("Idaho", "ID"),
+    ("Illinois", "IL"),
+    ("Indiana", "IN"),
+    ("Iowa", "IA"),
+    ("Kansas", "KS"),
+    ("Kentucky", "KY"),
+    ("Louisiana", "LA"),
+    ("Maine", "ME"),
+    ("Maryland", "MD"),
+    ("Massachusetts", "MA"),
+    ("Michigan", "MI"),
+    ("Minnesota", "MN"),
+    ("Mississippi", "MS"),
+    ("Missouri", "MO"),
+    ("Montana", "MT"),
+    ("Nebraska", "NE"),
+    ("Nevada", "NV"),
+    ("New Hampshire", "NH"),
+    ("New Jersey", "NJ"),
+    ("New Mexico", "NM"),
+    ("New York", "NY"),
+    ("North Carolina", "NC"),
+    ("North Dakota", "ND"),
+    ("Ohio", "OH"),
+    ("Oklahoma", "OK"),
+    ("Oregon", "OR"),
+    ("Pennsylvania", "PA"),
+    ("Rhode Island", "RI"),
+    ("South Carolina", "SC"),
+    ("South Dakota", "SD"),
+    ("Tennessee", "TN"),
+    ("Texas", "TX"),
+    ("Utah", "UT"),
+    ("Vermont", "VT"),
+    ("Virginia", "VA"),
+    ("Washington", "WA"),
+    ("West Virginia", "WV"),
+    ("Wisconsin", "WI"),
+    ("Wyoming", "WY"),
+];
+
+/// Ordered phrase dictionary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Abbreviations {
+    phrases: Vec<(String, String)>,
+}
+
+impl Default for Abbreviations {
+    fn default() -> Self {
+        Self::with_phrases(Vec::new())
+    }
+}
+
+impl Abbreviations {
+    /// Dictionary with extra phrases, tried before the built-in ones
+    pub fn with_phrases(mut phrases: Vec<(String, String)>) -> Self {
+        phrases.extend(
+            DEFAULT_PHRASES
+                .iter()
+                .map(|(phrase, short)| (phrase.to_string(), short.to_string())),
+        );
+        Self { phrases }
+    }
+
+    /// Successively shorter forms of `text`, starting with `text` itself
+    ///
+    /// Only steps that change the text are included.
+    pub fn variants(&self, text: &str) -> Vec<String> {
+        let mut variants = vec![text.to_string()];
+
+        if let Some(shorter) = abbreviate_state(text) {
+            variants.push(shorter);
+        }
+        for (phrase, short) in &self.phrases {
+            let current = variants.last().map(String::as_str).unwrap_or(text);
+            if let Some(shorter) = replace_words(current, phrase, short) {
+                variants.push(shorter);
+            }
+        }
+
+        variants
+    }
+}
+
+/// Set the configured phrases; the first call wins
+pub fn init(phrases: Vec<(String, String)>) {
+    if ABBREVIATIONS
+        .set(Abbreviations::with_phrases(phrases))
+        .is_err()
+    {
+        tracing::warn!("Venue abbreviations already initialized");
+    }
+}
+
+/// The configured dictionary (built-in phrases if `init` was never called)
+pub fn get() -> &'static Abbreviations {
+    ABBREVIATIONS.get_or_init(Abbreviations::default)
+}
+
+/// Parse `phrase=abbreviation` pairs separated by semicolons
+pub fn parse_phrases(value: &str) -> Option<Vec<(String, String)>> {
+    value
+        .split(';')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (phrase, short) = entry.split_once('=')?;
+            let (phrase, short) = (phrase.trim(), short.trim());
+            (!phrase.is_empty()).then(|| (phrase.to_string(), short.to_string()))
+        })
+        .collect()
+}
+
+/// Replace a trailing ", <State>" with its postal code
+fn abbreviate_state(text: &str) -> Option<String> {
+    let (head, last) = text.rsplit_once(',')?;
+    let (_, code) = STATES
+        .iter()
+        .find(|(name, _)| last.trim().eq_ignore_ascii_case(name))?;
+    Some(format!("{}, {}", head, code))
+}
+
+/// Replace whole-word, case-insensitive occurrences of `phrase`
+///
+/// Returns `None` if the phrase doesn't occur.
+fn replace_words(text: &str, phrase: &str, short: &str) -> Option<String> {
+    // ASCII lowercasing keeps byte offsets aligned with `text`
+    let haystack = text.to_ascii_lowercase();
+    let needle = phrase.to_ascii_lowercase();
+    let is_word_char = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut search = 0;
+    while let Some(found) = haystack[search..].find(&needle) {
+        let start = search + found;
+        let end = start + needle.len();
+        search = end;
+        if is_word_char(text[..start].chars().next_back())
+            || is_word_char(text[end..].chars().next())
+        {
+            continue;
+        }
+        result.push_str(&text[copied..start]);
+        result.push_str(short);
+        copied = end;
+    }
+
+    if copied == 0 {
+        return None;
+    }
+    result.push_str(&text[copied..]);
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variants() {
+        let variants = Abbreviations::default()
+            .variants("Saratoga Performing Arts Center, Saratoga Springs, New York");
+        assert_eq!(
+            variants,
+            vec![
+                "Saratoga Performing Arts Center, Saratoga Springs, New York",
+                "Saratoga Performing Arts Center, Saratoga Springs, NY",
+                "Saratoga PAC, Saratoga Springs, NY",
+            ]
+        );
+
+        // State names only count as the last component, phrases as whole words
+        let variants = Abbreviations::default().variants("Washington Centerstage, Seattle");
+        assert_eq!(variants, vec!["Washington Centerstage, Seattle"]);
+    }
+
+    #[test]
+    fn test_configured_phrases() {
+        let phrases = parse_phrases("Ballroom=Bllrm; theatre = Thtr.;").unwrap();
+        let abbreviations = Abbreviations::with_phrases(phrases);
+        assert_eq!(
+            abbreviations
+                .variants("Hammerstein Ballroom")
+                .last()
+                .unwrap(),
+            "Hammerstein Bllrm"
+        );
+        assert_eq!(
+            abbreviations
+                .variants("The Fillmore Theatre")
+                .last()
+                .unwrap(),
+            "The Fillmore Thtr."
+        );
+
+        assert_eq!(parse_phrases("Ballroom"), None);
+        assert_eq!(parse_phrases("=PAC"), None);
+    }
+}
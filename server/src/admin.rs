@@ -0,0 +1,516 @@
+//! Admin overview of the widget rotation
+//!
+//! Lists every item of every configured widget with its render status per
+//! orientation and links to act on it, as JSON or as an HTML page of
+//! thumbnails, so operators can see what the frames cycle through and which
+//! images haven't been rendered yet. Rendered concerts also show why their
+//! cover was chosen, with a form to pick another album, and concerts have a
+//! form to caption them with a note.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::ops::Range;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::datasource::DataSource;
+use crate::sawthat::CoverSelection;
+use crate::widget::{Orientation, WidgetItem, WidgetName};
+
+/// Longest side of item thumbnails, in pixels
+pub const THUMBNAIL_SIZE: u32 = 100;
+
+/// Items per page when not requested
+pub const DEFAULT_PER_PAGE: usize = 50;
+
+/// Most items served on one page
+pub const MAX_PER_PAGE: usize = 200;
+
+/// Orientations reported for each item
+const ORIENTATIONS: [Orientation; 2] = [Orientation::Horiz, Orientation::Vert];
+
+/// Output format of the item index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexFormat {
+    Json,
+    Html,
+}
+
+/// One page of the item index
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ItemIndex {
+    /// Page number, starting at 1
+    pub page: usize,
+    /// Items per page
+    pub per_page: usize,
+    /// Total items across all pages
+    pub total: usize,
+    /// Number of pages
+    pub pages: usize,
+    /// Items on this page
+    pub items: Vec<ItemSummary>,
+}
+
+/// A widget item and its renders
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ItemSummary {
+    pub widget: WidgetName,
+    /// Item path, as listed by the widget
+    pub path: String,
+    /// What the item shows, if the widget titles its items
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Description of the card for screen readers, if the widget provides one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt: Option<String>,
+    /// How the cover art was chosen, for rendered items of widgets that record it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover: Option<CoverSelection>,
+    /// Render status per orientation
+    pub renders: Vec<RenderStatus>,
+    /// Quick actions for the item
+    pub actions: Vec<ItemAction>,
+}
+
+/// Whether an item has been rendered in one orientation
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RenderStatus {
+    pub orientation: Orientation,
+    /// Rendered image is in the server cache
+    pub cached: bool,
+    /// Full-size image, rendered on request if not cached
+    pub image_url: String,
+    /// Thumbnail of the cached render
+    pub thumbnail_url: Option<String>,
+}
+
+/// A link an operator can follow for an item
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ItemAction {
+    /// Short description
+    pub label: String,
+    /// HTTP method
+    pub method: String,
+    pub href: String,
+    /// Form field sent with the request, if the action takes a value
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+}
+
+impl ItemAction {
+    fn new(label: impl Into<String>, method: &str, href: String) -> Self {
+        Self {
+            label: label.into(),
+            method: method.to_string(),
+            href,
+            field: None,
+        }
+    }
+
+    /// Send a value entered in a form field named `field`
+    fn with_field(mut self, field: &str) -> Self {
+        self.field = Some(field.to_string());
+        self
+    }
+}
+
+/// Build one page of the index over `sources`, reporting renders of the variant
+/// named by `variant` for each widget
+///
+/// Widgets whose item list can't be fetched are left out, with a warning.
+pub async fn build_index(
+    sources: &[(WidgetName, Arc<dyn DataSource>)],
+    variant: impl Fn(WidgetName) -> String,
+    page: usize,
+    per_page: usize,
+) -> ItemIndex {
+    let mut all = Vec::new();
+    for (widget, source) in sources {
+        match source.fetch_data().await {
+            Ok(items) => all.extend(items.into_iter().map(|item| (*widget, item))),
+            Err(e) => tracing::warn!("Skipping {} in item index: {}", widget, e),
+        }
+    }
+
+    let total = all.len();
+    let per_page = per_page.clamp(1, MAX_PER_PAGE);
+    let pages = total.div_ceil(per_page).max(1);
+    let page = page.clamp(1, pages);
+    let range = page_range(total, page, per_page);
+
+    let mut items = Vec::with_capacity(range.len());
+    for (widget, item) in all.drain(range) {
+        let source = sources
+            .iter()
+            .find(|(name, _)| *name == widget)
+            .map(|(_, source)| source)
+            .expect("item came from a listed source");
+        items.push(summarize(widget, source.as_ref(), item, &variant(widget)).await);
+    }
+
+    ItemIndex {
+        page,
+        per_page,
+        total,
+        pages,
+        items,
+    }
+}
+
+/// Indices of the items on a page, empty past the end
+pub(crate) fn page_range(total: usize, page: usize, per_page: usize) -> Range<usize> {
+    let start = page.saturating_sub(1).saturating_mul(per_page).min(total);
+    start..start.saturating_add(per_page).min(total)
+}
+
+async fn summarize(
+    widget: WidgetName,
+    source: &dyn DataSource,
+    item: WidgetItem,
+    variant: &str,
+) -> ItemSummary {
+    let WidgetItem {
+        path, title, alt, ..
+    } = item;
+    let mut renders = Vec::with_capacity(ORIENTATIONS.len());
+    let mut actions = Vec::new();
+    for orientation in ORIENTATIONS {
+        let cached = source
+            .cached_image(&path, orientation, variant)
+            .await
+            .is_some();
+        let image_url = item_url("", widget, orientation, &path);
+        let label = if cached { "View" } else { "Render" };
+        actions.push(ItemAction::new(
+            format!("{} {}", label, orientation),
+            "GET",
+            image_url.clone(),
+        ));
+        renders.push(RenderStatus {
+            orientation,
+            cached,
+            thumbnail_url: cached
+                .then(|| item_url("/admin/thumbnails", widget, orientation, &path)),
+            image_url,
+        });
+    }
+    if widget == WidgetName::Concerts {
+        actions.push(
+            ItemAction::new(
+                "Override album",
+                "POST",
+                format!("/concerts/{}/album", urlencoding::encode(&path)),
+            )
+            .with_field("title"),
+        );
+        actions.push(
+            ItemAction::new(
+                "Set note",
+                "POST",
+                format!("/concerts/{}/note", urlencoding::encode(&path)),
+            )
+            .with_field("note"),
+        );
+        actions.push(ItemAction::new(
+            "Pre-render all concerts",
+            "POST",
+            "/concerts/prerender".to_string(),
+        ));
+    }
+
+    // Only rendered items, the index shouldn't resolve every item
+    let cover = if renders.iter().any(|render| render.cached) {
+        source.cover_selection(&path).await.ok()
+    } else {
+        None
+    };
+
+    ItemSummary {
+        widget,
+        path,
+        title,
+        alt,
+        cover,
+        renders,
+        actions,
+    }
+}
+
+/// URL of an item image under `prefix`, with each path segment encoded
+pub(crate) fn item_url(
+    prefix: &str,
+    widget: WidgetName,
+    orientation: Orientation,
+    path: &str,
+) -> String {
+    let path = path
+        .split('/')
+        .map(|segment| urlencoding::encode(segment).into_owned())
+        .collect::<Vec<_>>()
+        .join("/");
+    format!("{}/{}/{}/{}", prefix, widget, orientation, path)
+}
+
+/// Render a page of the index as a standalone HTML document
+pub fn render_html(index: &ItemIndex, widget: Option<WidgetName>) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Widget items</title>\n<style>\n\
+         body { font-family: sans-serif; margin: 1em; }\n\
+         table { border-collapse: collapse; }\n\
+         td, th { border: 1px solid #ccc; padding: 4px 8px; text-align: left; vertical-align: middle; }\n\
+         .missing { display: inline-block; width: 60px; height: 100px; background: #eee; }\n\
+         form { display: inline; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+    let _ = writeln!(
+        html,
+        "<h1>Widget items</h1>\n<p>{} items, page {} of {}</p>",
+        index.total, index.page, index.pages
+    );
+
+    html.push_str("<table>\n<tr><th>Widget</th><th>Item</th>");
+    for orientation in ORIENTATIONS {
+        let _ = write!(html, "<th>{}</th>", orientation);
+    }
+    html.push_str("<th>Actions</th></tr>\n");
+
+    for item in &index.items {
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td><code>{}</code>",
+            item.widget,
+            escape(&item.path)
+        );
+        if let Some(title) = &item.title {
+            let _ = write!(html, "<br>{}", escape(title));
+        }
+        if let Some(cover) = &item.cover {
+            let _ = write!(html, "<br><small>Cover: {}", escape(&cover.reason));
+            if let Some(album) = &cover.album {
+                let _ = write!(html, " ({})", escape(album));
+            }
+            html.push_str("</small>");
+        }
+        html.push_str("</td>");
+        for render in &item.renders {
+            match &render.thumbnail_url {
+                Some(url) => {
+                    let _ =
+                        write!(
+                        html,
+                        "<td><a href=\"{}\"><img src=\"{}\" alt=\"{}\" loading=\"lazy\"></a></td>",
+                        escape(&render.image_url),
+                        escape(url),
+                        escape(item.alt.as_deref().unwrap_or(&render.orientation.to_string()))
+                    );
+                }
+                None => {
+                    html.push_str("<td><span class=\"missing\" title=\"Not rendered\"></span></td>")
+                }
+            }
+        }
+        html.push_str("<td>");
+        for action in &item.actions {
+            if action.method == "GET" {
+                let _ = write!(
+                    html,
+                    "<a href=\"{}\">{}</a> ",
+                    escape(&action.href),
+                    escape(&action.label)
+                );
+            } else {
+                let _ = write!(
+                    html,
+                    "<form method=\"{}\" action=\"{}\">",
+                    escape(&action.method.to_lowercase()),
+                    escape(&action.href)
+                );
+                if let Some(field) = &action.field {
+                    let _ = write!(html, "<input name=\"{}\" size=\"16\"> ", escape(field));
+                }
+                let _ = write!(html, "<button>{}</button></form> ", escape(&action.label));
+            }
+        }
+        html.push_str("</td></tr>\n");
+    }
+    html.push_str("</table>\n<p>");
+
+    let link = |page: usize| {
+        let mut href = format!("?format=html&page={}&per_page={}", page, index.per_page);
+        if let Some(widget) = widget {
+            let _ = write!(href, "&widget={}", widget);
+        }
+        href
+    };
+    if index.page > 1 {
+        let _ = write!(
+            html,
+            "<a href=\"{}\">Previous</a> ",
+            escape(&link(index.page - 1))
+        );
+    }
+    if index.page < index.pages {
+        let _ = write!(
+            html,
+            "<a href=\"{}\">Next</a>",
+            escape(&link(index.page + 1))
+        );
+    }
+    html.push_str("</p>\n</body>\n</html>\n");
+    html
+}
+
+/// Escape text for HTML content and attribute values
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AppError;
+    use crate::experiment::Variant;
+    use crate::sawthat::CoverSource;
+    use crate::widget::{CachePolicy, WidgetData};
+    use async_trait::async_trait;
+
+    /// Lists `count` items and has only the horizontal render of even ones cached
+    struct FakeSource {
+        count: usize,
+    }
+
+    #[async_trait]
+    impl DataSource for FakeSource {
+        fn data_cache_policy(&self) -> CachePolicy {
+            CachePolicy::Max
+        }
+
+        async fn fetch_data(&self) -> Result<WidgetData, AppError> {
+            Ok((0..self.count)
+                .map(|i| WidgetItem::new(format!("item {}", i)))
+                .collect())
+        }
+
+        async fn fetch_image(
+            &self,
+            _path: &str,
+            _orientation: Orientation,
+            _variant: &Variant,
+        ) -> Result<Vec<u8>, AppError> {
+            Err(AppError::NotFound("not rendered".to_string()))
+        }
+
+        async fn cached_image(
+            &self,
+            path: &str,
+            orientation: Orientation,
+            _variant: &str,
+        ) -> Option<Arc<Vec<u8>>> {
+            let index: usize = path.strip_prefix("item ")?.parse().ok()?;
+            (orientation == Orientation::Horiz && index.is_multiple_of(2))
+                .then(|| Arc::new(Vec::new()))
+        }
+
+        async fn cover_selection(&self, _path: &str) -> Result<CoverSelection, AppError> {
+            Ok(CoverSelection {
+                source: CoverSource::BandPicture,
+                reason: "album 'Live <3' is blocked".to_string(),
+                album: Some("Live <3".to_string()),
+                release_date: None,
+                days_before: None,
+                albums: Some(4),
+                url: None,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_item_index() {
+        let sources: Vec<(WidgetName, Arc<dyn DataSource>)> = vec![
+            (WidgetName::Concerts, Arc::new(FakeSource { count: 3 })),
+            (WidgetName::Photos, Arc::new(FakeSource { count: 2 })),
+        ];
+
+        let index = build_index(&sources, |_| "default".to_string(), 2, 2).await;
+        assert_eq!((index.page, index.total, index.pages), (2, 5, 3));
+        let paths: Vec<_> = index
+            .items
+            .iter()
+            .map(|item| (item.widget, item.path.as_str()))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                (WidgetName::Concerts, "item 2"),
+                (WidgetName::Photos, "item 0")
+            ]
+        );
+
+        let render = &index.items[0].renders[0];
+        assert!(render.cached);
+        assert_eq!(render.image_url, "/concerts/horiz/item%202");
+        assert_eq!(
+            render.thumbnail_url.as_deref(),
+            Some("/admin/thumbnails/concerts/horiz/item%202")
+        );
+        assert!(!index.items[0].renders[1].cached);
+        assert!(index.items[0]
+            .actions
+            .iter()
+            .any(|action| action.method == "POST"));
+        assert!(index.items[0]
+            .actions
+            .iter()
+            .any(|action| action.href == "/concerts/item%202/album"
+                && action.field.as_deref() == Some("title")));
+        assert!(index.items[0]
+            .actions
+            .iter()
+            .any(|action| action.href == "/concerts/item%202/note"
+                && action.field.as_deref() == Some("note")));
+        assert!(index.items[1]
+            .actions
+            .iter()
+            .all(|action| action.method == "GET"));
+
+        // Covers are only reported for rendered items
+        assert_eq!(
+            index.items[0].cover.as_ref().map(|cover| cover.source),
+            Some(CoverSource::BandPicture)
+        );
+        let first = build_index(&sources, |_| "default".to_string(), 1, 2).await;
+        assert!(first.items[1].cover.is_none());
+
+        // Out of range pages are clamped
+        assert_eq!(
+            build_index(&sources, |_| "default".to_string(), 9, 2)
+                .await
+                .page,
+            3
+        );
+
+        let html = render_html(&index, None);
+        assert!(html.contains("<img src=\"/admin/thumbnails/concerts/horiz/item%202\""));
+        assert!(html
+            .contains("<small>Cover: album &#39;Live &lt;3&#39; is blocked (Live &lt;3)</small>"));
+        assert!(html.contains(
+            "<form method=\"post\" action=\"/concerts/item%202/album\"><input name=\"title\""
+        ));
+        assert!(html.contains("href=\"?format=html&amp;page=1&amp;per_page=2\">Previous"));
+        assert!(html.contains("href=\"?format=html&amp;page=3&amp;per_page=2\">Next"));
+        assert_eq!(escape("<a href=\"x\">"), "&lt;a href=&quot;x&quot;&gt;");
+    }
+}
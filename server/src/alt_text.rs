@@ -0,0 +1,157 @@
+//! Alt text for concert cards
+//!
+//! A one-line description of what a card shows, for screen readers in companion
+//! apps and on share pages: the band, when and where, any caption detail such
+//! as the tour or personal note, and (once the art has been fetched) a rough description of the
+//! cover art's dominant color. Item listings carry the caption part; rendered
+//! PNGs embed the full text as a `Description` tEXt chunk.
+
+use crate::cache::PrimaryColor;
+use crate::text::ConcertInfo;
+
+/// tEXt keyword the description is embedded under (one of the PNG spec's predefined keywords)
+pub const PNG_KEYWORD: &str = "Description";
+
+/// Describe a concert card, optionally with its art's dominant color
+///
+/// Missing parts are left out, e.g. "Concert card for Phish at Madison Square
+/// Garden on July 17th, 2025. Cover art in dark blue tones."
+pub fn concert_alt_text(info: &ConcertInfo, art: Option<&PrimaryColor>) -> String {
+    let mut text = format!("Concert card for {}", info.band_name);
+    if !info.venue.is_empty() {
+        text.push_str(" at ");
+        text.push_str(&info.venue);
+    }
+    if !info.date.is_empty() {
+        text.push_str(" on ");
+        text.push_str(&info.date);
+    }
+    text.push('.');
+    if let Some(detail) = info.detail.as_deref().filter(|detail| !detail.is_empty()) {
+        text.push(' ');
+        text.push_str(detail);
+        text.push('.');
+    }
+    if let Some(note) = info.note.as_deref().filter(|note| !note.is_empty()) {
+        text.push_str(" Note: ");
+        text.push_str(note);
+        text.push('.');
+    }
+    if let Some(color) = art {
+        text.push_str(&format!(
+            " Cover art in {} tones.",
+            color_name(color.r, color.g, color.b)
+        ));
+    }
+    text
+}
+
+/// Plain-language name of an RGB color, e.g. "dark blue" or "light gray"
+fn color_name(r: u8, g: u8, b: u8) -> String {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let chroma = max - min;
+
+    // Near-neutral colors are named by lightness alone
+    if chroma < 0.12 {
+        return match lightness {
+            l if l < 0.15 => "black",
+            l if l < 0.4 => "dark gray",
+            l if l < 0.75 => "gray",
+            l if l < 0.92 => "light gray",
+            _ => "white",
+        }
+        .to_string();
+    }
+
+    let hue = if max == r {
+        60.0 * ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / chroma + 2.0)
+    } else {
+        60.0 * ((r - g) / chroma + 4.0)
+    };
+    let hue_name = match hue {
+        h if h < 15.0 => "red",
+        h if h < 45.0 => "orange",
+        h if h < 70.0 => "yellow",
+        h if h < 160.0 => "green",
+        h if h < 200.0 => "teal",
+        h if h < 255.0 => "blue",
+        h if h < 290.0 => "purple",
+        h if h < 340.0 => "pink",
+        _ => "red",
+    };
+    let shade = match lightness {
+        l if l < 0.3 => "dark ",
+        l if l > 0.75 => "light ",
+        _ => "",
+    };
+    format!("{}{}", shade, hue_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concert_alt_text() {
+        let info = ConcertInfo {
+            band_name: "Phish".to_string(),
+            date: "July 17th, 2025".to_string(),
+            venue: "Madison Square Garden".to_string(),
+            detail: None,
+            note: None,
+        };
+        assert_eq!(
+            concert_alt_text(&info, None),
+            "Concert card for Phish at Madison Square Garden on July 17th, 2025."
+        );
+
+        let navy = PrimaryColor {
+            r: 20,
+            g: 30,
+            b: 110,
+        };
+        let info = ConcertInfo {
+            venue: String::new(),
+            ..info
+        };
+        assert_eq!(
+            concert_alt_text(&info, Some(&navy)),
+            "Concert card for Phish on July 17th, 2025. Cover art in dark blue tones."
+        );
+
+        let info = ConcertInfo {
+            detail: Some("Opened with Tweezer".to_string()),
+            ..info
+        };
+        assert_eq!(
+            concert_alt_text(&info, None),
+            "Concert card for Phish on July 17th, 2025. Opened with Tweezer."
+        );
+
+        let info = ConcertInfo {
+            note: Some("Road trip with Sam".to_string()),
+            ..info
+        };
+        assert_eq!(
+            concert_alt_text(&info, None),
+            "Concert card for Phish on July 17th, 2025. Opened with Tweezer. Note: Road trip with Sam."
+        );
+    }
+
+    #[test]
+    fn test_color_name() {
+        assert_eq!(color_name(0, 0, 0), "black");
+        assert_eq!(color_name(128, 128, 130), "gray");
+        assert_eq!(color_name(250, 250, 250), "white");
+        assert_eq!(color_name(220, 30, 30), "red");
+        assert_eq!(color_name(240, 140, 20), "orange");
+        assert_eq!(color_name(30, 160, 60), "green");
+        assert_eq!(color_name(240, 150, 200), "light pink");
+        assert_eq!(color_name(90, 30, 120), "dark purple");
+    }
+}
@@ -0,0 +1,273 @@
+//! Artwork blocklist
+//!
+//! Keeps known-bad covers (say, an explicit one on a frame in the kitchen) off
+//! the display. An album is blocked by keywords in its title, a cover by its
+//! URL or by the perceptual hash of its image, so re-encoded or resized copies
+//! are caught too. A blocked Deezer cover falls back to the band photo (or the
+//! Last.fm image), and when every source is blocked a plain placeholder is
+//! rendered under the caption instead.
+//!
+//! Hashes are 64-bit difference hashes, written as 16 hex digits. The hash of
+//! every fetched source image is logged, so a cover seen on the frame can be
+//! blocked by copying its hash from the log into `BLOCKED_ART_HASHES`.
+
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+use reqwest::Client;
+use std::collections::HashSet;
+use std::io::Cursor;
+use std::sync::OnceLock;
+
+use crate::config::BlocklistConfig;
+use crate::error::AppError;
+
+/// Blocklist, configured once at startup
+static BLOCKLIST: OnceLock<Blocklist> = OnceLock::new();
+
+/// Most differing bits for an image to count as a blocked one
+const MAX_HASH_DISTANCE: u32 = 6;
+
+/// Side of the square placeholder image
+const PLACEHOLDER_SIZE: u32 = 600;
+
+/// Placeholder fill, a neutral gray the caption stays legible on
+const PLACEHOLDER_COLOR: Rgb<u8> = Rgb([96, 96, 96]);
+
+/// Blocked album titles, cover URLs and image hashes
+#[derive(Debug, Default)]
+pub struct Blocklist {
+    /// Keywords as lowercase words, matched as a run of whole words
+    keywords: Vec<Vec<String>>,
+    urls: HashSet<String>,
+    hashes: Vec<u64>,
+}
+
+impl Blocklist {
+    pub fn new(config: BlocklistConfig) -> Self {
+        Self {
+            keywords: config
+                .keywords
+                .iter()
+                .map(|keyword| words(keyword))
+                .filter(|words| !words.is_empty())
+                .collect(),
+            urls: config.urls.into_iter().collect(),
+            hashes: config.hashes,
+        }
+    }
+
+    /// Whether an album title contains a blocked keyword
+    ///
+    /// Case-insensitive and on whole words, so "Ass" doesn't block "Glass".
+    pub fn blocks_title(&self, title: &str) -> bool {
+        let title = words(title);
+        self.keywords.iter().any(|keyword| {
+            title
+                .windows(keyword.len())
+                .any(|window| window == keyword.as_slice())
+        })
+    }
+
+    /// Whether a cover URL is blocked
+    pub fn blocks_url(&self, url: &str) -> bool {
+        self.urls.contains(url)
+    }
+
+    /// Whether an image looks like a blocked one
+    pub fn blocks_hash(&self, hash: u64) -> bool {
+        self.hashes
+            .iter()
+            .any(|blocked| (blocked ^ hash).count_ones() <= MAX_HASH_DISTANCE)
+    }
+}
+
+/// Set the configured blocklist; the first call wins
+pub fn init(config: BlocklistConfig) {
+    if BLOCKLIST.set(Blocklist::new(config)).is_err() {
+        tracing::warn!("Artwork blocklist already initialized");
+    }
+}
+
+/// The configured blocklist (empty if `init` was never called)
+pub fn get() -> &'static Blocklist {
+    BLOCKLIST.get_or_init(Blocklist::default)
+}
+
+/// Lowercase alphanumeric words of a title
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Parse a hash written as 16 hex digits
+pub fn parse_hash(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if value.len() != 16 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u64::from_str_radix(value, 16).ok()
+}
+
+/// Difference hash of an image: whether each pixel of a 9x8 grayscale
+/// thumbnail is brighter than its right neighbour, row by row
+pub fn image_hash(img: &DynamicImage) -> u64 {
+    let thumbnail = img.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let bright = thumbnail.get_pixel(x, y)[0] > thumbnail.get_pixel(x + 1, y)[0];
+            hash = hash << 1 | bright as u64;
+        }
+    }
+    hash
+}
+
+/// A plain PNG rendered in place of blocked artwork
+pub fn placeholder() -> Vec<u8> {
+    let img = RgbImage::from_pixel(PLACEHOLDER_SIZE, PLACEHOLDER_SIZE, PLACEHOLDER_COLOR);
+    let mut png = Vec::new();
+    img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .expect("PNG encodes into memory");
+    png
+}
+
+/// Source art for a render, and where it came from
+pub struct Artwork {
+    pub image: Vec<u8>,
+    /// `None` for the placeholder
+    pub url: Option<String>,
+}
+
+/// Fetch the first of `urls` that isn't blocked, or the placeholder if they all are
+///
+/// Images that fail to decode are passed on as they are, for the caller's
+/// decoding to report.
+pub async fn fetch_artwork(client: &Client, urls: &[String]) -> Result<Artwork, AppError> {
+    let blocklist = get();
+    for url in urls {
+        if blocklist.blocks_url(url) {
+            tracing::info!("Skipping blocked image: {}", url);
+            continue;
+        }
+
+        tracing::info!("Fetching source image from: {}", url);
+        let response = client.get(url).header("Accept", "image/*").send().await?;
+        if !response.status().is_success() {
+            return Err(AppError::ExternalApi(format!(
+                "Failed to fetch image: {}",
+                response.status()
+            )));
+        }
+        let image = response.bytes().await?.to_vec();
+
+        if let Ok(img) = image::load_from_memory(&image) {
+            let hash = image_hash(&img);
+            tracing::info!("Source image hash: {:016x}", hash);
+            if blocklist.blocks_hash(hash) {
+                tracing::info!("Skipping image matching a blocked hash: {}", url);
+                continue;
+            }
+        }
+
+        return Ok(Artwork {
+            image,
+            url: Some(url.clone()),
+        });
+    }
+
+    tracing::info!("All artwork blocked, using the placeholder");
+    Ok(Artwork {
+        image: placeholder(),
+        url: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_processing;
+
+    fn blocklist(keywords: &[&str], urls: &[&str], hashes: &[u64]) -> Blocklist {
+        Blocklist::new(BlocklistConfig {
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+            urls: urls.iter().map(|u| u.to_string()).collect(),
+            hashes: hashes.to_vec(),
+        })
+    }
+
+    /// A horizontal gradient with a dark square, so thumbnails have structure
+    fn test_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            if x < width / 3 && y < height / 2 {
+                Rgb([20, 20, 20])
+            } else {
+                let v = (x * 255 / width) as u8;
+                Rgb([v, 255 - v, 128])
+            }
+        }))
+    }
+
+    #[test]
+    fn test_blocks_title() {
+        let blocklist = blocklist(&["Explicit Cover", "ass"], &[], &[]);
+        assert!(blocklist.blocks_title("The EXPLICIT cover (Deluxe)"));
+        assert!(blocklist.blocks_title("Kick Ass"));
+        assert!(!blocklist.blocks_title("Glass Houses"));
+        assert!(!blocklist.blocks_title("Cover, Explicit"));
+        assert!(!Blocklist::default().blocks_title("Anything"));
+
+        // Blank keywords block nothing
+        assert!(!self::blocklist(&[" - "], &[], &[]).blocks_title("A - B"));
+    }
+
+    #[test]
+    fn test_blocks_url() {
+        let blocklist = blocklist(&[], &["https://example.com/a.jpg"], &[]);
+        assert!(blocklist.blocks_url("https://example.com/a.jpg"));
+        assert!(!blocklist.blocks_url("https://example.com/b.jpg"));
+    }
+
+    #[test]
+    fn test_image_hash() {
+        let hash = image_hash(&test_image(400, 400));
+
+        // Resized and re-encoded copies stay within the distance
+        let resized = image_hash(&test_image(123, 97));
+        assert!((hash ^ resized).count_ones() <= MAX_HASH_DISTANCE);
+        let mut jpeg = Vec::new();
+        test_image(400, 400)
+            .write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+            .unwrap();
+        let reencoded = image_hash(&image::load_from_memory(&jpeg).unwrap());
+
+        let blocklist = blocklist(&[], &[], &[hash]);
+        assert!(blocklist.blocks_hash(resized));
+        assert!(blocklist.blocks_hash(reencoded));
+
+        // A different image doesn't match
+        let flipped = image_hash(&test_image(400, 400).fliph());
+        assert!(!blocklist.blocks_hash(flipped));
+    }
+
+    #[test]
+    fn test_parse_hash() {
+        assert_eq!(parse_hash("00ff00ff00ff00ff"), Some(0x00ff00ff00ff00ff));
+        assert_eq!(parse_hash(" FFFFFFFFFFFFFFFF "), Some(u64::MAX));
+        assert_eq!(parse_hash("ff"), None);
+        assert_eq!(parse_hash("zzzzzzzzzzzzzzzz"), None);
+        assert_eq!(parse_hash("+fffffffffffffff"), None);
+    }
+
+    #[test]
+    fn test_placeholder() {
+        let png = placeholder();
+        let img = image::load_from_memory(&png).unwrap();
+        assert_eq!(
+            (img.width(), img.height()),
+            (PLACEHOLDER_SIZE, PLACEHOLDER_SIZE)
+        );
+        assert!(image_processing::extract_primary_color(&png).is_ok());
+    }
+}
@@ -1,17 +1,36 @@
 //! In-memory cache with TTL expiration
 //!
-//! Provides concert data caching with 24-hour expiration.
+//! Provides concert data caching with 24-hour expiration. Concert entries
+//! (source art, metadata and rendered images) can also be persisted to a
+//! [`CacheStore`], so restarts and deploys don't re-fetch and re-dither
+//! everything.
+//! The bands list is memory-only, it's a single cheap request. It's refreshed
+//! in the background shortly before it expires, and once expired it's still
+//! served while a single background refresh replaces it
+//! (stale-while-revalidate).
 
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::fs;
 use tokio::sync::RwLock;
+use utoipa::ToSchema;
 
-use crate::sawthat::SawThatBand;
+use crate::cache_store::CacheStore;
+use crate::config::parse_name;
+use crate::palette;
+use crate::sawthat::{CoverSelection, SawThatBand};
+use crate::text::ConcertInfo;
 use crate::widget::Orientation;
 
-/// TTL for all cache entries (24 hours)
-const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// TTL for all cache entries in seconds (24 hours)
+pub const CACHE_TTL_SECS: u32 = 24 * 60 * 60;
+
+/// TTL for all cache entries
+const CACHE_TTL: Duration = Duration::from_secs(CACHE_TTL_SECS as u64);
 
 /// A cached entry with expiration time
 struct CacheEntry<V> {
@@ -21,15 +40,24 @@ struct CacheEntry<V> {
 
 impl<V> CacheEntry<V> {
     fn new(value: V) -> Self {
+        Self::expiring_in(value, CACHE_TTL)
+    }
+
+    fn expiring_in(value: V, ttl: Duration) -> Self {
         Self {
             value,
-            expires_at: Instant::now() + CACHE_TTL,
+            expires_at: Instant::now() + ttl,
         }
     }
 
     fn is_expired(&self) -> bool {
         Instant::now() > self.expires_at
     }
+
+    /// Time since the entry was stored (assuming the default TTL)
+    fn age(&self) -> Duration {
+        (Instant::now() + CACHE_TTL).saturating_duration_since(self.expires_at)
+    }
 }
 
 /// Cached data for a single concert
@@ -41,56 +69,96 @@ pub struct ConcertEntry {
     pub venue: String,
     /// Formatted date string (e.g., "July 17th, 2025")
     pub formatted_date: String,
+    /// Extra caption line, e.g. the tour name from Setlist.fm
+    pub detail: Option<String>,
+    /// Personal note captioned below the rest
+    pub note: Option<String>,
     /// Source image bytes (for rendering other orientations)
     pub source_image: Arc<Vec<u8>>,
+    /// Where the source image was fetched from (unknown for entries cached
+    /// before it was recorded)
+    pub source_url: Option<String>,
+    /// How the cover art was chosen, for concerts resolved since it was recorded
+    pub cover: Option<CoverSelection>,
     /// Primary color extracted from image
     pub primary_color: PrimaryColor,
-    /// Rendered horizontal image
-    pub image_horiz: Option<Arc<Vec<u8>>>,
-    /// Rendered vertical image
-    pub image_vert: Option<Arc<Vec<u8>>>,
+    /// Rendered images keyed by orientation and experiment variant
+    pub images: HashMap<(Orientation, String), Arc<Vec<u8>>>,
 }
 
 impl ConcertEntry {
-    /// Get rendered image for orientation if cached
-    pub fn get_image(&self, orientation: Orientation) -> Option<&Arc<Vec<u8>>> {
-        match orientation {
-            Orientation::Horiz => self.image_horiz.as_ref(),
-            Orientation::Vert => self.image_vert.as_ref(),
-        }
+    /// Get rendered image for orientation and variant if cached
+    pub fn get_image(&self, orientation: Orientation, variant: &str) -> Option<&Arc<Vec<u8>>> {
+        self.images.get(&(orientation, variant.to_string()))
+    }
+
+    /// Set rendered image for orientation and variant
+    pub fn set_image(&mut self, orientation: Orientation, variant: &str, image: Arc<Vec<u8>>) {
+        self.images
+            .insert((orientation, variant.to_string()), image);
     }
 
-    /// Set rendered image for orientation
-    pub fn set_image(&mut self, orientation: Orientation, image: Arc<Vec<u8>>) {
-        match orientation {
-            Orientation::Horiz => self.image_horiz = Some(image),
-            Orientation::Vert => self.image_vert = Some(image),
+    /// Caption rendered below the cover art
+    pub fn concert_info(&self) -> ConcertInfo {
+        ConcertInfo {
+            band_name: self.band_name.clone(),
+            date: self.formatted_date.clone(),
+            venue: self.venue.clone(),
+            detail: self.detail.clone(),
+            note: self.note.clone(),
         }
     }
 }
 
-/// Primary color with RGB values and lightness info
-#[derive(Clone, Copy)]
+/// Primary color of the cover art, filling the card's text area
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct PrimaryColor {
     pub r: u8,
     pub g: u8,
     pub b: u8,
-    pub is_light: bool,
+}
+
+impl PrimaryColor {
+    pub fn rgb(&self) -> palette::Rgb {
+        palette::Rgb::new(self.r, self.g, self.b)
+    }
+
+    /// Whether black text reads better than white on this color
+    pub fn is_light(&self) -> bool {
+        palette::prefers_dark_text(self.rgb())
+    }
 }
 
 /// Concert cache holding all cached data
 pub struct ConcertCache {
     /// Cached bands list from SawThat API
     bands: RwLock<Option<CacheEntry<Vec<SawThatBand>>>>,
+    /// Whether a background refresh of the bands list is underway
+    bands_revalidating: AtomicBool,
     /// Cached concert entries keyed by "{band_id}/{date}"
     concerts: RwLock<HashMap<String, CacheEntry<ConcertEntry>>>,
+    /// Persistent copy of concert entries
+    stored: Option<StoredEntries>,
 }
 
 impl ConcertCache {
     pub fn new() -> Self {
         Self {
             bands: RwLock::new(None),
+            bands_revalidating: AtomicBool::new(false),
             concerts: RwLock::new(HashMap::new()),
+            stored: None,
+        }
+    }
+
+    /// Create a cache that persists concert entries in `store`, under `namespace`
+    pub fn with_store(store: Arc<dyn CacheStore>, namespace: &str) -> Self {
+        Self {
+            stored: Some(StoredEntries {
+                store,
+                namespace: namespace.to_string(),
+            }),
+            ..Self::new()
         }
     }
 
@@ -106,22 +174,82 @@ impl ConcertCache {
         })
     }
 
+    /// Get the last fetched bands list even if expired, with how long ago it was fetched
+    ///
+    /// Served while the SawThat API is failing, stale data beats no data.
+    pub async fn get_stale_bands(&self) -> Option<(Vec<SawThatBand>, Duration)> {
+        let cache = self.bands.read().await;
+        cache
+            .as_ref()
+            .map(|entry| (entry.value.clone(), entry.age()))
+    }
+
+    /// How long ago the bands list was fetched, if it has expired
+    pub async fn expired_bands_age(&self) -> Option<Duration> {
+        let cache = self.bands.read().await;
+        cache
+            .as_ref()
+            .filter(|entry| entry.is_expired())
+            .map(|entry| entry.age())
+    }
+
+    /// Time left before the bands list expires (zero once it has), if one was fetched
+    pub async fn bands_expire_in(&self) -> Option<Duration> {
+        let cache = self.bands.read().await;
+        cache
+            .as_ref()
+            .map(|entry| entry.expires_at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Claim the background refresh of the bands list
+    ///
+    /// Returns false if another refresh already holds it, so concurrent requests
+    /// for an expired list don't each hit the API. Release it with
+    /// [`end_bands_revalidation`](Self::end_bands_revalidation).
+    pub fn start_bands_revalidation(&self) -> bool {
+        self.bands_revalidating
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// Release the background refresh claimed by
+    /// [`start_bands_revalidation`](Self::start_bands_revalidation)
+    pub fn end_bands_revalidation(&self) {
+        self.bands_revalidating.store(false, Ordering::Release);
+    }
+
+    /// Whether a background refresh of the bands list is underway
+    pub fn bands_revalidating(&self) -> bool {
+        self.bands_revalidating.load(Ordering::Acquire)
+    }
+
     /// Store bands list in cache
     pub async fn set_bands(&self, bands: Vec<SawThatBand>) {
         let mut cache = self.bands.write().await;
         *cache = Some(CacheEntry::new(bands));
     }
 
-    /// Get cached concert entry if not expired
+    /// Get cached concert entry if not expired, loading it from the store on a memory miss
     pub async fn get_concert(&self, key: &str) -> Option<ConcertEntry> {
-        let cache = self.concerts.read().await;
-        cache.get(key).and_then(|entry| {
-            if entry.is_expired() {
-                None
-            } else {
-                Some(entry.value.clone())
+        {
+            let cache = self.concerts.read().await;
+            if let Some(entry) = cache.get(key).filter(|entry| !entry.is_expired()) {
+                return Some(entry.value.clone());
             }
-        })
+        }
+
+        let (entry, ttl) = self.stored.as_ref()?.load(key).await?;
+        tracing::debug!("Loaded {} from cache store", key);
+        let mut cache = self.concerts.write().await;
+        let cached = cache
+            .entry(key.to_string())
+            .and_modify(|existing| {
+                if existing.is_expired() {
+                    *existing = CacheEntry::expiring_in(entry.clone(), ttl);
+                }
+            })
+            .or_insert_with(|| CacheEntry::expiring_in(entry.clone(), ttl));
+        Some(cached.value.clone())
     }
 
     /// Store a concert entry, only if no entry exists (or existing is expired)
@@ -136,25 +264,235 @@ impl ConcertCache {
             }
             _ => {
                 // No entry or expired - insert new one
+                if let Some(stored) = &self.stored {
+                    stored.store(&key, &entry).await;
+                }
                 cache.insert(key, CacheEntry::new(entry));
             }
         }
     }
 
-    /// Update a concert entry's rendered image for a specific orientation
+    /// Drop a concert entry from memory and the store, so it's resolved again
+    pub async fn remove_concert(&self, key: &str) {
+        self.concerts.write().await.remove(key);
+        if let Some(stored) = &self.stored {
+            if let Some(prefix) = stored.entry_prefix(key) {
+                stored.remove(&prefix).await;
+            }
+        }
+    }
+
+    /// Update a concert entry's rendered image for a specific orientation and variant
     pub async fn set_concert_image(
         &self,
         key: &str,
         orientation: Orientation,
+        variant: &str,
         image: Arc<Vec<u8>>,
     ) {
         let mut cache = self.concerts.write().await;
         if let Some(entry) = cache.get_mut(key) {
             if !entry.is_expired() {
-                entry.value.set_image(orientation, image);
+                if let Some(stored) = &self.stored {
+                    stored.store_image(key, orientation, variant, &image).await;
+                }
+                entry.value.set_image(orientation, variant, image);
             }
         }
     }
+
+    /// Sizes of the in-memory cache, for diagnostics
+    pub async fn stats(&self) -> CacheStats {
+        let cache = self.concerts.read().await;
+        let mut stats = CacheStats {
+            entries: cache.len(),
+            ..CacheStats::default()
+        };
+        for entry in cache.values() {
+            stats.expired += entry.is_expired() as usize;
+            stats.images += entry.value.images.len();
+            stats.image_bytes += entry.value.source_image.len();
+            stats.image_refs += Arc::strong_count(&entry.value.source_image);
+            for image in entry.value.images.values() {
+                stats.image_bytes += image.len();
+                stats.image_refs += Arc::strong_count(image);
+            }
+        }
+        stats
+    }
+}
+
+/// Sizes of a concert cache's in-memory entries
+///
+/// Expired entries are only replaced when requested again, so they count too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct CacheStats {
+    /// Concert entries held
+    pub entries: usize,
+    /// Entries past their TTL
+    pub expired: usize,
+    /// Rendered images across all entries
+    pub images: usize,
+    /// Bytes of source and rendered images
+    pub image_bytes: usize,
+    /// Strong references to source and rendered images, above one per image
+    /// while responses or renders still hold them
+    pub image_refs: usize,
+}
+
+/// Concert entry metadata stored alongside the source image
+#[derive(Serialize, Deserialize)]
+struct ConcertMeta {
+    band_name: String,
+    venue: String,
+    formatted_date: String,
+    #[serde(default)]
+    detail: Option<String>,
+    #[serde(default)]
+    note: Option<String>,
+    primary_color: PrimaryColor,
+    #[serde(default)]
+    source_url: Option<String>,
+    #[serde(default)]
+    cover: Option<CoverSelection>,
+    /// Unix timestamp (seconds) when the entry was created
+    cached_at: u64,
+}
+
+/// Concert entries persisted in a [`CacheStore`]
+///
+/// Each entry is stored under `{namespace}/{key}/`, as `meta.json`, the
+/// `source` image and rendered images as `{orientation}.{variant}.png`. With
+/// the disk store that's a directory per entry. Write errors are logged and
+/// otherwise ignored, the memory cache still works.
+struct StoredEntries {
+    store: Arc<dyn CacheStore>,
+    namespace: String,
+}
+
+impl StoredEntries {
+    /// Key prefix for an entry, or None if the key isn't safe to use as a file name
+    fn entry_prefix(&self, key: &str) -> Option<String> {
+        let safe = !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        safe.then(|| format!("{}/{}/", self.namespace, key))
+    }
+
+    /// Load an entry and its remaining TTL, removing it if expired or unreadable
+    async fn load(&self, key: &str) -> Option<(ConcertEntry, Duration)> {
+        let prefix = self.entry_prefix(key)?;
+        let meta = self
+            .store
+            .get(&format!("{}meta.json", prefix))
+            .await
+            .ok()??;
+
+        let loaded = async {
+            let meta: ConcertMeta = serde_json::from_slice(&meta).ok()?;
+            let age = Duration::from_secs(unix_now().saturating_sub(meta.cached_at));
+            let ttl = CACHE_TTL.checked_sub(age).filter(|ttl| !ttl.is_zero())?;
+            let source_image = self.store.get(&format!("{}source", prefix)).await.ok()??;
+
+            let mut entry = ConcertEntry {
+                band_name: meta.band_name,
+                venue: meta.venue,
+                formatted_date: meta.formatted_date,
+                detail: meta.detail,
+                note: meta.note,
+                source_image: Arc::new(source_image),
+                source_url: meta.source_url,
+                cover: meta.cover,
+                primary_color: meta.primary_color,
+                images: HashMap::new(),
+            };
+            for file in self.store.list(&prefix).await.ok()? {
+                let Some((orientation, variant)) = file
+                    .strip_prefix(&prefix)
+                    .and_then(|name| name.strip_suffix(".png"))
+                    .and_then(|stem| stem.split_once('.'))
+                    .and_then(|(o, v)| Some((parse_name::<Orientation>(o)?, v)))
+                else {
+                    continue;
+                };
+                if let Ok(Some(image)) = self.store.get(&file).await {
+                    entry.set_image(orientation, variant, Arc::new(image));
+                }
+            }
+            Some((entry, ttl))
+        }
+        .await;
+
+        if loaded.is_none() {
+            self.remove(&prefix).await;
+        }
+        loaded
+    }
+
+    /// Remove everything stored under an entry's prefix
+    async fn remove(&self, prefix: &str) {
+        for file in self.store.list(prefix).await.unwrap_or_default() {
+            let _ = self.store.delete(&file).await;
+        }
+    }
+
+    /// Store an entry's metadata and source image
+    async fn store(&self, key: &str, entry: &ConcertEntry) {
+        let Some(prefix) = self.entry_prefix(key) else {
+            return;
+        };
+        let meta = ConcertMeta {
+            band_name: entry.band_name.clone(),
+            venue: entry.venue.clone(),
+            formatted_date: entry.formatted_date.clone(),
+            detail: entry.detail.clone(),
+            note: entry.note.clone(),
+            primary_color: entry.primary_color,
+            source_url: entry.source_url.clone(),
+            cover: entry.cover.clone(),
+            cached_at: unix_now(),
+        };
+        let result = async {
+            // Source first: the entry only counts as present once meta.json exists
+            self.store
+                .put(&format!("{}source", prefix), &entry.source_image)
+                .await?;
+            self.store
+                .put(&format!("{}meta.json", prefix), &serde_json::to_vec(&meta)?)
+                .await
+        }
+        .await;
+        if let Err(e) = result {
+            tracing::warn!("Failed to write {} to cache store: {}", key, e);
+        }
+    }
+
+    /// Store a rendered image for an entry
+    async fn store_image(&self, key: &str, orientation: Orientation, variant: &str, image: &[u8]) {
+        let Some(prefix) = self.entry_prefix(key) else {
+            return;
+        };
+        let file = format!("{}{}.{}.png", prefix, orientation, variant);
+        if let Err(e) = self.store.put(&file, image).await {
+            tracing::warn!("Failed to write {} to cache store: {}", file, e);
+        }
+    }
+}
+
+/// Write a file via a temporary file and rename, so readers never see partial data
+pub(crate) async fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data).await?;
+    fs::rename(&tmp, path).await
+}
+
+/// Current Unix timestamp in seconds
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
 }
 
 impl Default for ConcertCache {
@@ -162,3 +500,183 @@ impl Default for ConcertCache {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_store::{DiskStore, MemoryStore};
+    use crate::sawthat::CoverSource;
+
+    fn entry() -> ConcertEntry {
+        ConcertEntry {
+            band_name: "Band".to_string(),
+            venue: "Venue".to_string(),
+            formatted_date: "July 17th, 2025".to_string(),
+            detail: Some("Summer Tour 2025".to_string()),
+            note: Some("Road trip with Sam".to_string()),
+            source_image: Arc::new(vec![1, 2, 3]),
+            source_url: Some("https://example.com/cover.jpg".to_string()),
+            cover: None,
+            primary_color: PrimaryColor {
+                r: 10,
+                g: 20,
+                b: 30,
+            },
+            images: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_store_survives_restart() {
+        let store: Arc<dyn CacheStore> = Arc::new(MemoryStore::default());
+        let key = "2025-07-17-abc";
+
+        let cover = CoverSelection {
+            source: CoverSource::DeezerAlbum,
+            reason: "closest release before the concert (12 days)".to_string(),
+            album: Some("Album".to_string()),
+            release_date: Some("2025-07-05".to_string()),
+            days_before: Some(12),
+            albums: Some(3),
+            url: Some("https://example.com/cover.jpg".to_string()),
+        };
+        let cache = ConcertCache::with_store(store.clone(), "concerts");
+        cache
+            .set_or_update_concert(
+                key.to_string(),
+                ConcertEntry {
+                    cover: Some(cover.clone()),
+                    ..entry()
+                },
+            )
+            .await;
+        cache
+            .set_concert_image(key, Orientation::Vert, "default", Arc::new(vec![9]))
+            .await;
+
+        let restarted = ConcertCache::with_store(store.clone(), "concerts");
+        let loaded = restarted.get_concert(key).await.unwrap();
+        assert_eq!(loaded.band_name, "Band");
+        assert_eq!(loaded.detail.as_deref(), Some("Summer Tour 2025"));
+        assert_eq!(loaded.note.as_deref(), Some("Road trip with Sam"));
+        assert_eq!(*loaded.source_image, vec![1, 2, 3]);
+        assert_eq!(
+            loaded.source_url.as_deref(),
+            Some("https://example.com/cover.jpg")
+        );
+        assert_eq!(loaded.primary_color.b, 30);
+        assert_eq!(loaded.cover, Some(cover));
+        assert_eq!(
+            loaded
+                .get_image(Orientation::Vert, "default")
+                .map(|i| i.to_vec()),
+            Some(vec![9])
+        );
+        assert!(loaded.get_image(Orientation::Horiz, "default").is_none());
+
+        // Removed entries are gone after a restart too
+        restarted.remove_concert(key).await;
+        assert!(restarted.get_concert(key).await.is_none());
+        assert!(store.list("concerts/").await.unwrap().is_empty());
+
+        // Namespaces keep data sources apart
+        let other = ConcertCache::with_store(store, "spotify");
+        assert!(other.get_concert(key).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_disk_store_layout() {
+        // Entries stay where earlier releases wrote them, one directory each
+        let dir = std::env::temp_dir().join(format!("sawthat-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = ConcertCache::with_store(Arc::new(DiskStore::new(dir.clone())), "concerts");
+        cache
+            .set_or_update_concert("abc".to_string(), entry())
+            .await;
+        cache
+            .set_concert_image("abc", Orientation::Horiz, "default", Arc::new(vec![9]))
+            .await;
+        for file in ["meta.json", "source", "horiz.default.png"] {
+            assert!(dir.join("concerts/abc").join(file).exists(), "{}", file);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_store_expiry_and_keys() {
+        let store = Arc::new(MemoryStore::default());
+        let stored = StoredEntries {
+            store: store.clone(),
+            namespace: "concerts".to_string(),
+        };
+        assert!(stored.entry_prefix("../escape").is_none());
+        assert!(stored.entry_prefix("a/b").is_none());
+        assert!(stored.entry_prefix("").is_none());
+
+        // An entry older than the TTL is discarded and removed
+        stored.store("old", &entry()).await;
+        stored
+            .store_image("old", Orientation::Vert, "default", &[9])
+            .await;
+        let meta_key = "concerts/old/meta.json";
+        let mut meta: ConcertMeta =
+            serde_json::from_slice(&store.get(meta_key).await.unwrap().unwrap()).unwrap();
+        meta.cached_at -= CACHE_TTL.as_secs() + 1;
+        store
+            .put(meta_key, &serde_json::to_vec(&meta).unwrap())
+            .await
+            .unwrap();
+
+        assert!(stored.load("old").await.is_none());
+        assert!(store.list("concerts/").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stats() {
+        let cache = ConcertCache::new();
+        assert_eq!(cache.stats().await, CacheStats::default());
+
+        cache.set_or_update_concert("a".to_string(), entry()).await;
+        cache.set_or_update_concert("b".to_string(), entry()).await;
+        let image = Arc::new(vec![9; 10]);
+        cache
+            .set_concert_image("a", Orientation::Vert, "default", image.clone())
+            .await;
+        let stats = cache.stats().await;
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.expired, 0);
+        assert_eq!(stats.images, 1);
+        assert_eq!(stats.image_bytes, 3 + 3 + 10);
+        // The image still held here counts on top of the cache's own references
+        assert_eq!(stats.image_refs, 4);
+        drop(image);
+        assert_eq!(cache.stats().await.image_refs, 3);
+    }
+    #[tokio::test]
+    async fn test_bands_revalidation() {
+        let cache = ConcertCache::new();
+        assert!(cache.get_stale_bands().await.is_none());
+        assert!(cache.bands_expire_in().await.is_none());
+
+        *cache.bands.write().await = Some(CacheEntry::expiring_in(Vec::new(), Duration::ZERO));
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert!(cache.get_bands().await.is_none());
+        assert!(cache.get_stale_bands().await.is_some());
+        assert!(cache.expired_bands_age().await.is_some());
+        assert_eq!(cache.bands_expire_in().await, Some(Duration::ZERO));
+
+        // Only one refresh at a time
+        assert!(cache.start_bands_revalidation());
+        assert!(cache.bands_revalidating());
+        assert!(!cache.start_bands_revalidation());
+
+        cache.set_bands(Vec::new()).await;
+        cache.end_bands_revalidation();
+        assert!(!cache.bands_revalidating());
+        assert!(cache.get_bands().await.is_some());
+        assert!(cache.expired_bands_age().await.is_none());
+        assert!(cache.bands_expire_in().await.unwrap() > CACHE_TTL - Duration::from_secs(60));
+        assert!(cache.start_bands_revalidation());
+    }
+}
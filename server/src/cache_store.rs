@@ -0,0 +1,365 @@
+//! Storage backends for the persisted side of the render cache
+//!
+//! [`ConcertCache`](crate::cache::ConcertCache) keeps entries in memory and
+//! writes them through to a [`CacheStore`], so they survive restarts and, with
+//! a shared backend, are rendered once for every instance behind a load
+//! balancer. Stores hold plain bytes under `/`-separated keys; entry layout and
+//! expiry are up to the cache.
+//!
+//! - [`DiskStore`]: files under a directory, one per key
+//! - [`RedisStore`]: a Redis server, with keys expiring after the cache TTL
+//!
+//! With the memory backend there is no store at all. [`MemoryStore`] is the
+//! reference implementation the cache is tested against.
+
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+
+use crate::cache::{write_atomic, CACHE_TTL_SECS};
+use crate::config::CacheBackend;
+
+/// Prefix of every key the server writes to Redis, so a shared server can hold other data
+const REDIS_KEY_PREFIX: &str = "sawthat:";
+
+/// Byte storage behind the render cache
+#[async_trait]
+pub trait CacheStore: Send + Sync {
+    /// Value stored under `key`, `None` if there is none
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// Store `value` under `key`, replacing any previous value
+    async fn put(&self, key: &str, value: &[u8]) -> io::Result<()>;
+
+    /// Remove `key`, if present
+    async fn delete(&self, key: &str) -> io::Result<()>;
+
+    /// Keys starting with `prefix`, in no particular order
+    async fn list(&self, prefix: &str) -> io::Result<Vec<String>>;
+}
+
+/// Open the store for a configured backend, `None` for memory
+///
+/// A Redis server that can't be reached is logged and the cache stays in
+/// memory, as with disk write errors.
+pub async fn open(backend: &CacheBackend) -> Option<Arc<dyn CacheStore>> {
+    match backend {
+        CacheBackend::Memory => None,
+        CacheBackend::Disk(dir) => Some(Arc::new(DiskStore::new(dir.clone()))),
+        CacheBackend::Redis(url) => match RedisStore::connect(url).await {
+            Ok(store) => Some(Arc::new(store)),
+            Err(e) => {
+                tracing::error!("Failed to connect to Redis, caching in memory: {}", e);
+                None
+            }
+        },
+    }
+}
+
+/// Whether a key only uses characters that are safe in file names and Redis
+/// patterns, with no empty, `.` or `..` segments
+fn is_safe_key(key: &str) -> bool {
+    key.split('/').all(|segment| {
+        !segment.is_empty()
+            && segment != "."
+            && segment != ".."
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    })
+}
+
+fn invalid_key(key: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid cache key: {}", key),
+    )
+}
+
+/// Keys as files under a directory, `/` separating subdirectories
+pub struct DiskStore {
+    dir: PathBuf,
+}
+
+impl DiskStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        if !is_safe_key(key) {
+            return Err(invalid_key(key));
+        }
+        Ok(self.dir.join(key))
+    }
+}
+
+#[async_trait]
+impl CacheStore for DiskStore {
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.path(key)?).await {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn put(&self, key: &str, value: &[u8]) -> io::Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        write_atomic(&path, value).await
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        let path = self.path(key)?;
+        match fs::remove_file(&path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        // Drop directories left empty, up to the store's own
+        let mut dir = path.parent();
+        while let Some(parent) = dir.filter(|d| *d != self.dir) {
+            if fs::remove_dir(parent).await.is_err() {
+                break;
+            }
+            dir = parent.parent();
+        }
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        // Only the subdirectory the prefix points into needs walking
+        let start = match prefix.rsplit_once('/') {
+            Some((dir, _)) if is_safe_key(dir) => self.dir.join(dir),
+            Some(_) => return Ok(Vec::new()),
+            None => self.dir.clone(),
+        };
+        let mut keys = Vec::new();
+        let mut dirs = vec![start];
+        while let Some(dir) = dirs.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if entry.file_type().await?.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                // Skip writes in progress (see `write_atomic`)
+                if path.extension().is_some_and(|ext| ext == "tmp") {
+                    continue;
+                }
+                if let Some(key) = relative_key(&self.dir, &path) {
+                    if key.starts_with(prefix) {
+                        keys.push(key);
+                    }
+                }
+            }
+        }
+        Ok(keys)
+    }
+}
+
+/// Key of a file under the store's directory
+fn relative_key(root: &Path, path: &Path) -> Option<String> {
+    let parts: Option<Vec<&str>> = path
+        .strip_prefix(root)
+        .ok()?
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect();
+    Some(parts?.join("/"))
+}
+
+/// Keys in a Redis server, expiring after the cache TTL
+///
+/// Entries also carry their own creation time, so an entry's files expiring a
+/// little apart is harmless: whatever outlives the entry is cleared by Redis.
+pub struct RedisStore {
+    connection: ConnectionManager,
+}
+
+impl RedisStore {
+    /// Connect to the server at `url` (`redis://host:port/db`); dropped
+    /// connections are re-established on the next command
+    pub async fn connect(url: &str) -> redis::RedisResult<Self> {
+        let client = redis::Client::open(url)?;
+        Ok(Self {
+            connection: ConnectionManager::new(client).await?,
+        })
+    }
+
+    fn key(key: &str) -> io::Result<String> {
+        if !is_safe_key(key) {
+            return Err(invalid_key(key));
+        }
+        Ok(format!("{}{}", REDIS_KEY_PREFIX, key))
+    }
+}
+
+#[async_trait]
+impl CacheStore for RedisStore {
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let key = Self::key(key)?;
+        let mut connection = self.connection.clone();
+        connection.get(key).await.map_err(io::Error::other)
+    }
+
+    async fn put(&self, key: &str, value: &[u8]) -> io::Result<()> {
+        let key = Self::key(key)?;
+        let mut connection = self.connection.clone();
+        connection
+            .set_ex(key, value, CACHE_TTL_SECS as u64)
+            .await
+            .map_err(io::Error::other)
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        let key = Self::key(key)?;
+        let mut connection = self.connection.clone();
+        connection.del(key).await.map_err(io::Error::other)
+    }
+
+    async fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        // Safe keys have no glob metacharacters to escape
+        if !prefix
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'))
+        {
+            return Ok(Vec::new());
+        }
+        let pattern = format!("{}{}*", REDIS_KEY_PREFIX, prefix);
+        let mut connection = self.connection.clone();
+        let mut iter = connection
+            .scan_match::<_, String>(pattern)
+            .await
+            .map_err(io::Error::other)?;
+        let mut keys = Vec::new();
+        while let Some(key) = iter.next_item().await {
+            if let Some(key) = key.strip_prefix(REDIS_KEY_PREFIX) {
+                keys.push(key.to_string());
+            }
+        }
+        Ok(keys)
+    }
+}
+
+/// Keys in a map, for tests of the cache's use of a store
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStore {
+    values: tokio::sync::RwLock<std::collections::HashMap<String, Vec<u8>>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl CacheStore for MemoryStore {
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.values.read().await.get(key).cloned())
+    }
+
+    async fn put(&self, key: &str, value: &[u8]) -> io::Result<()> {
+        if !is_safe_key(key) {
+            return Err(invalid_key(key));
+        }
+        self.values
+            .write()
+            .await
+            .insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        self.values.write().await.remove(key);
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let values = self.values.read().await;
+        Ok(values
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Behaviour every store shares
+    async fn check_store(store: &dyn CacheStore) {
+        assert_eq!(store.get("concerts/a/meta.json").await.unwrap(), None);
+        store.put("concerts/a/meta.json", b"{}").await.unwrap();
+        store.put("concerts/a/source", &[1, 2, 3]).await.unwrap();
+        store.put("concerts/ab/source", &[4]).await.unwrap();
+        store.put("spotify/a/source", &[5]).await.unwrap();
+        assert_eq!(
+            store.get("concerts/a/source").await.unwrap(),
+            Some(vec![1, 2, 3])
+        );
+
+        store.put("concerts/a/source", &[6]).await.unwrap();
+        assert_eq!(store.get("concerts/a/source").await.unwrap(), Some(vec![6]));
+
+        let mut keys = store.list("concerts/a/").await.unwrap();
+        keys.sort();
+        assert_eq!(keys, ["concerts/a/meta.json", "concerts/a/source"]);
+        assert_eq!(store.list("concerts/").await.unwrap().len(), 3);
+        assert!(store.list("photos/").await.unwrap().is_empty());
+
+        store.delete("concerts/a/meta.json").await.unwrap();
+        store.delete("concerts/a/missing").await.unwrap();
+        assert_eq!(store.get("concerts/a/meta.json").await.unwrap(), None);
+        assert_eq!(store.list("concerts/a/").await.unwrap().len(), 1);
+
+        // Keys can't escape the store
+        assert!(store.put("../escape", b"x").await.is_err());
+        assert!(store.put("concerts//a", b"x").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        check_store(&MemoryStore::default()).await;
+    }
+
+    #[tokio::test]
+    async fn test_disk_store() {
+        let dir = std::env::temp_dir().join(format!("sawthat-store-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = DiskStore::new(dir.clone());
+        check_store(&store).await;
+
+        // Emptied directories are removed, and leftover temp files aren't listed
+        store.delete("concerts/a/source").await.unwrap();
+        assert!(!dir.join("concerts/a").exists());
+        assert!(dir.join("concerts").exists());
+        std::fs::write(dir.join("concerts/ab/source.tmp"), b"partial").unwrap();
+        assert_eq!(
+            store.list("concerts/").await.unwrap(),
+            ["concerts/ab/source"]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_safe_keys() {
+        assert!(is_safe_key("concerts/2025-07-17-abc/vert.default.png"));
+        assert!(!is_safe_key("concerts/../secrets"));
+        assert!(!is_safe_key("/etc/passwd"));
+        assert!(!is_safe_key("concerts/a b"));
+        assert!(!is_safe_key("concerts/*"));
+    }
+}
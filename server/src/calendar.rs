@@ -0,0 +1,590 @@
+//! Calendar agenda widget
+//!
+//! Fetches an ICS feed (a public calendar link, or a CalDAV collection's ICS
+//! export with basic auth) and renders the next few events as a text-only
+//! panel. Recurring events support the common RRULE subset: FREQ
+//! (DAILY/WEEKLY/MONTHLY/YEARLY) with INTERVAL, COUNT and UNTIL. BYDAY-style
+//! rules and EXDATE are ignored, and TZID times are treated as server-local.
+
+use chrono::{Datelike, Days, Local, Months, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use image::Rgb;
+use reqwest::Client;
+
+use crate::config::CalendarConfig;
+use crate::error::AppError;
+use crate::image_processing::RenderParams;
+use crate::layout::{Align, Block, Canvas};
+use crate::palette::{PaletteIndex, PALETTE};
+use crate::widget::{Orientation, WidgetWidth};
+
+/// Padding around the agenda
+const PADDING: u32 = 24;
+
+/// Height of the colored header band (title and date)
+const HEADER_HEIGHT: u32 = 112;
+
+/// Space between the date and the first event, clearing the band's gradient
+const HEADER_GAP: u32 = 32;
+
+/// Font sizes, largest first
+const TITLE_SIZES: &[f32] = &[44.0, 36.0, 28.0];
+const DATE_SIZES: &[f32] = &[24.0, 20.0];
+const WHEN_SIZES: &[f32] = &[20.0, 18.0];
+const SUMMARY_SIZES: &[f32] = &[28.0, 24.0, 20.0];
+const LOCATION_SIZES: &[f32] = &[20.0, 18.0];
+
+/// Most recurrences stepped through when looking for the next occurrence
+const MAX_RECURRENCE_STEPS: u32 = 1000;
+
+/// A single calendar event (or the next occurrence of a recurring one)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CalendarEvent {
+    pub summary: String,
+    pub location: Option<String>,
+    /// Start in server-local time (midnight for all-day events)
+    pub start: NaiveDateTime,
+    /// End in server-local time, exclusive
+    pub end: Option<NaiveDateTime>,
+    pub all_day: bool,
+}
+
+impl CalendarEvent {
+    /// Whether the event is still on at `now`
+    fn is_upcoming(&self, now: NaiveDateTime) -> bool {
+        match self.end {
+            Some(end) => end > now,
+            None if self.all_day => self.start.date() >= now.date(),
+            None => self.start >= now,
+        }
+    }
+}
+
+/// Recurrence frequency from an RRULE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// The supported subset of an RRULE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Recurrence {
+    frequency: Frequency,
+    interval: u32,
+    count: Option<u32>,
+    until: Option<NaiveDateTime>,
+}
+
+impl Recurrence {
+    fn parse(value: &str) -> Option<Self> {
+        let mut frequency = None;
+        let mut interval = 1;
+        let mut count = None;
+        let mut until = None;
+
+        for part in value.split(';') {
+            let (key, value) = part.split_once('=')?;
+            match key {
+                "FREQ" => {
+                    frequency = match value {
+                        "DAILY" => Some(Frequency::Daily),
+                        "WEEKLY" => Some(Frequency::Weekly),
+                        "MONTHLY" => Some(Frequency::Monthly),
+                        "YEARLY" => Some(Frequency::Yearly),
+                        _ => None,
+                    }
+                }
+                "INTERVAL" => interval = value.parse().ok().filter(|&n| n > 0)?,
+                "COUNT" => count = value.parse().ok(),
+                "UNTIL" => until = parse_date_time(value, false).map(|(time, _)| time),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            frequency: frequency?,
+            interval,
+            count,
+            until,
+        })
+    }
+
+    /// The `n`th occurrence of a series starting at `start`
+    fn nth(&self, start: NaiveDateTime, n: u32) -> Option<NaiveDateTime> {
+        let steps = n.checked_mul(self.interval)?;
+        match self.frequency {
+            Frequency::Daily => start.checked_add_days(Days::new(steps.into())),
+            Frequency::Weekly => start.checked_add_days(Days::new(u64::from(steps) * 7)),
+            Frequency::Monthly => start.checked_add_months(Months::new(steps)),
+            Frequency::Yearly => start.checked_add_months(Months::new(steps.checked_mul(12)?)),
+        }
+    }
+
+    /// Longest possible gap between occurrences, in days
+    fn max_period_days(&self) -> i64 {
+        let days = match self.frequency {
+            Frequency::Daily => 1,
+            Frequency::Weekly => 7,
+            Frequency::Monthly => 31,
+            Frequency::Yearly => 366,
+        };
+        days * i64::from(self.interval)
+    }
+
+    /// Move a recurring event to its first occurrence still on at `now`
+    fn next_occurrence(&self, event: &CalendarEvent, now: NaiveDateTime) -> Option<CalendarEvent> {
+        let duration = event.end.map(|end| end - event.start);
+
+        // Skip ahead by a lower bound on the elapsed occurrences, then step
+        let elapsed_days = (now - event.start).num_days() - 1;
+        let first = u32::try_from(elapsed_days / self.max_period_days()).unwrap_or(0);
+
+        for n in first..first.saturating_add(MAX_RECURRENCE_STEPS) {
+            if self.count.is_some_and(|count| n >= count) {
+                return None;
+            }
+            let start = self.nth(event.start, n)?;
+            if self.until.is_some_and(|until| start > until) {
+                return None;
+            }
+            let occurrence = CalendarEvent {
+                start,
+                end: duration.map(|duration| start + duration),
+                ..event.clone()
+            };
+            if occurrence.is_upcoming(now) {
+                return Some(occurrence);
+            }
+        }
+        None
+    }
+}
+
+/// Fetch and parse the configured calendar
+pub async fn fetch_events(
+    client: &Client,
+    config: &CalendarConfig,
+    now: NaiveDateTime,
+) -> Result<Vec<CalendarEvent>, AppError> {
+    let url = match config.url.strip_prefix("webcal://") {
+        Some(rest) => format!("https://{}", rest),
+        None => config.url.clone(),
+    };
+
+    tracing::info!("Fetching calendar from: {}", url);
+
+    let mut request = client.get(&url).header("Accept", "text/calendar");
+    if let Some(username) = &config.username {
+        request = request.basic_auth(username, config.password.as_ref());
+    }
+    let response = request.send().await?;
+
+    if !response.status().is_success() {
+        return Err(AppError::ExternalApi(format!(
+            "Calendar returned status: {}",
+            response.status()
+        )));
+    }
+
+    let events = parse_ics(&response.text().await?, now);
+
+    tracing::info!("Parsed {} calendar events", events.len());
+
+    Ok(events)
+}
+
+/// Parse the events of an ICS calendar
+///
+/// Recurring events are replaced by their next occurrence still on at `now`;
+/// series that have ended are dropped.
+pub fn parse_ics(text: &str, now: NaiveDateTime) -> Vec<CalendarEvent> {
+    let mut events = Vec::new();
+    // Properties of the VEVENT being read, and how deep we are in its subcomponents
+    let mut current: Option<EventProperties> = None;
+    let mut depth = 0;
+
+    for line in unfold(text) {
+        let Some((name, params, value)) = split_property(&line) else {
+            continue;
+        };
+
+        let Some(properties) = current.as_mut() else {
+            if name == "BEGIN" && value == "VEVENT" {
+                current = Some(EventProperties::default());
+            }
+            continue;
+        };
+
+        match name.as_str() {
+            "BEGIN" => depth += 1,
+            "END" if depth > 0 => depth -= 1,
+            "END" => {
+                events.extend(current.take().and_then(|properties| properties.build(now)));
+            }
+            // Skip properties of nested components (e.g. VALARM)
+            _ if depth > 0 => {}
+            "SUMMARY" => properties.summary = unescape(value),
+            "LOCATION" => {
+                properties.location = Some(unescape(value)).filter(|location| !location.is_empty())
+            }
+            "DTSTART" => properties.start = parse_date_time(value, is_date(&params)),
+            "DTEND" => {
+                properties.end = parse_date_time(value, is_date(&params)).map(|(time, _)| time)
+            }
+            "RRULE" => properties.recurrence = Recurrence::parse(value),
+            _ => {}
+        }
+    }
+
+    events
+}
+
+/// Properties collected from a VEVENT
+#[derive(Debug, Default)]
+struct EventProperties {
+    summary: String,
+    location: Option<String>,
+    start: Option<(NaiveDateTime, bool)>,
+    end: Option<NaiveDateTime>,
+    recurrence: Option<Recurrence>,
+}
+
+impl EventProperties {
+    /// The event, or its next occurrence still on at `now` if it recurs
+    fn build(self, now: NaiveDateTime) -> Option<CalendarEvent> {
+        let (start, all_day) = self.start?;
+        let event = CalendarEvent {
+            summary: self.summary,
+            location: self.location,
+            start,
+            end: self.end,
+            all_day,
+        };
+        match self.recurrence {
+            Some(recurrence) => recurrence.next_occurrence(&event, now),
+            None => Some(event),
+        }
+    }
+}
+
+/// The next `limit` events still on at `now`, soonest first
+pub fn upcoming(events: &[CalendarEvent], now: NaiveDateTime, limit: usize) -> Vec<CalendarEvent> {
+    let mut upcoming: Vec<_> = events
+        .iter()
+        .filter(|event| event.is_upcoming(now))
+        .cloned()
+        .collect();
+    upcoming.sort_by(|a, b| (a.start, &a.summary).cmp(&(b.start, &b.summary)));
+    upcoming.truncate(limit);
+    upcoming
+}
+
+/// Widget item path for an agenda: date and a checksum of its events
+///
+/// Path format: YYYY-MM-DD-xxxxxxxx (FAT-safe, changes whenever the agenda does)
+pub fn agenda_path(today: NaiveDate, events: &[CalendarEvent]) -> String {
+    let mut hasher = crc32fast::Hasher::new();
+    for event in events {
+        hasher.update(event.summary.as_bytes());
+        hasher.update(event.location.as_deref().unwrap_or_default().as_bytes());
+        hasher.update(event.start.to_string().as_bytes());
+        hasher.update(&[event.all_day as u8]);
+    }
+    format!("{}-{:08x}", today.format("%Y-%m-%d"), hasher.finalize())
+}
+
+/// Render an agenda as an indexed PNG
+pub fn render_agenda(
+    events: &[CalendarEvent],
+    title: &str,
+    today: NaiveDate,
+    orientation: Orientation,
+    item_width: WidgetWidth,
+    params: &RenderParams,
+) -> Result<Vec<u8>, AppError> {
+    let (width, height) = orientation.dimensions(item_width);
+    let background = palette_rgb(PaletteIndex::White);
+    let band = palette_rgb(PaletteIndex::Blue);
+
+    let mut canvas = Canvas::new(width, height, PADDING, background).band(band, HEADER_HEIGHT);
+    canvas.push(
+        Block::new()
+            .text(title, TITLE_SIZES, Align::Left, PaletteIndex::White)
+            .text(
+                &today.format("%A, %-d %B").to_string(),
+                DATE_SIZES,
+                Align::Left,
+                PaletteIndex::White,
+            )
+            .gap(HEADER_GAP),
+    );
+
+    if events.is_empty() {
+        canvas.push(Block::new().text(
+            "No upcoming events",
+            SUMMARY_SIZES,
+            Align::Center,
+            PaletteIndex::Black,
+        ));
+    }
+
+    for (i, event) in events.iter().enumerate() {
+        let mut block = Block::new();
+        if i > 0 {
+            block = block.gap(8).rule(PaletteIndex::Black).gap(8);
+        }
+        block = block
+            .text(
+                &when(event, today),
+                WHEN_SIZES,
+                Align::Left,
+                PaletteIndex::Red,
+            )
+            .text(
+                &event.summary,
+                SUMMARY_SIZES,
+                Align::Left,
+                PaletteIndex::Black,
+            );
+        if let Some(location) = &event.location {
+            block = block.text(location, LOCATION_SIZES, Align::Left, PaletteIndex::Black);
+        }
+        canvas.push(block);
+    }
+
+    canvas.render(params)
+}
+
+/// Day and time line for an event, e.g. "Tomorrow · 19:30"
+fn when(event: &CalendarEvent, today: NaiveDate) -> String {
+    let date = event.start.date();
+    let day = if date <= today {
+        "Today".to_string()
+    } else if today.succ_opt() == Some(date) {
+        "Tomorrow".to_string()
+    } else if date.year() == today.year() {
+        date.format("%a %-d %b").to_string()
+    } else {
+        date.format("%a %-d %b %Y").to_string()
+    };
+
+    if event.all_day {
+        format!("{} · All day", day)
+    } else {
+        format!("{} · {}", day, event.start.format("%H:%M"))
+    }
+}
+
+/// Palette color as an RGB pixel
+fn palette_rgb(index: PaletteIndex) -> Rgb<u8> {
+    let color = PALETTE[index as usize];
+    Rgb([color.r, color.g, color.b])
+}
+
+/// Join folded content lines (continuations start with a space or tab)
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Split a content line into its uppercase name, parameters and value
+fn split_property(line: &str) -> Option<(String, String, &str)> {
+    // The value starts at the first colon outside a quoted parameter value
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(i),
+        _ => None,
+    })?;
+
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let (name, params) = head.split_once(';').unwrap_or((head, ""));
+    Some((
+        name.to_ascii_uppercase(),
+        params.to_ascii_uppercase(),
+        value,
+    ))
+}
+
+/// Whether property parameters mark a date-only value
+fn is_date(params: &str) -> bool {
+    params.split(';').any(|param| param == "VALUE=DATE")
+}
+
+/// Parse a DATE or DATE-TIME value into server-local time
+///
+/// Returns the time and whether it was a date. UTC times are converted;
+/// floating and TZID times are taken as local.
+fn parse_date_time(value: &str, date_only: bool) -> Option<(NaiveDateTime, bool)> {
+    let value = value.trim();
+    if date_only || value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        return Some((date.and_hms_opt(0, 0, 0)?, true));
+    }
+
+    match value.strip_suffix('Z') {
+        Some(utc) => {
+            let time = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+            Some((
+                Utc.from_utc_datetime(&time)
+                    .with_timezone(&Local)
+                    .naive_local(),
+                false,
+            ))
+        }
+        None => Some((
+            NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?,
+            false,
+        )),
+    }
+}
+
+/// Unescape a TEXT value
+fn unescape(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => text.push(' '),
+            Some(escaped) => text.push(escaped),
+            None => {}
+        }
+    }
+    text.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ICS: &str = "BEGIN:VCALENDAR\r\n\
+VERSION:2.0\r\n\
+BEGIN:VEVENT\r\n\
+SUMMARY:Dentist\r\n\
+DTSTART;TZID=\"America/New_York\":20251020T093000\r\n\
+DTEND;TZID=\"America/New_York\":20251020T103000\r\n\
+LOCATION:Main St\\, Suite 4\r\n\
+BEGIN:VALARM\r\n\
+SUMMARY:Reminder\r\n\
+END:VALARM\r\n\
+END:VEVENT\r\n\
+BEGIN:VEVENT\r\n\
+SUMMARY:A very long event title that is folded\r\n\
+\x20 across two lines\r\n\
+DTSTART;VALUE=DATE:20251018\r\n\
+DTEND;VALUE=DATE:20251019\r\n\
+END:VEVENT\r\n\
+BEGIN:VEVENT\r\n\
+SUMMARY:Past\r\n\
+DTSTART:20250101T120000\r\n\
+END:VEVENT\r\n\
+BEGIN:VEVENT\r\n\
+SUMMARY:Band practice\r\n\
+DTSTART:20250107T190000\r\n\
+DTEND:20250107T210000\r\n\
+RRULE:FREQ=WEEKLY;INTERVAL=2\r\n\
+END:VEVENT\r\n\
+BEGIN:VEVENT\r\n\
+SUMMARY:Ended series\r\n\
+DTSTART:20250101T080000\r\n\
+RRULE:FREQ=DAILY;COUNT=3\r\n\
+END:VEVENT\r\n\
+END:VCALENDAR\r\n";
+
+    fn at(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_parse_ics() {
+        let events = parse_ics(ICS, at("2025-10-18 12:00"));
+        let summaries: Vec<_> = events.iter().map(|e| e.summary.as_str()).collect();
+        assert_eq!(
+            summaries,
+            vec![
+                "Dentist",
+                "A very long event title that is folded across two lines",
+                "Past",
+                "Band practice",
+            ]
+        );
+
+        assert_eq!(events[0].location.as_deref(), Some("Main St, Suite 4"));
+        assert_eq!(events[0].start, at("2025-10-20 09:30"));
+        assert_eq!(events[0].end, Some(at("2025-10-20 10:30")));
+        assert!(events[1].all_day);
+
+        // Every other Tuesday from 7 January: 28 October is the next one
+        assert_eq!(events[3].start, at("2025-10-28 19:00"));
+        assert_eq!(events[3].end, Some(at("2025-10-28 21:00")));
+    }
+
+    #[test]
+    fn test_recurrence() {
+        let event = CalendarEvent {
+            summary: "Rent".to_string(),
+            location: None,
+            start: at("2024-01-31 09:00"),
+            end: None,
+            all_day: false,
+        };
+        let next = |rule: &str, now: &str| {
+            Recurrence::parse(rule)
+                .unwrap()
+                .next_occurrence(&event, at(now))
+                .map(|event| event.start)
+        };
+
+        assert_eq!(
+            next("FREQ=MONTHLY", "2025-02-10 00:00"),
+            Some(at("2025-02-28 09:00"))
+        );
+        assert_eq!(
+            next("FREQ=YEARLY", "2025-02-10 00:00"),
+            Some(at("2026-01-31 09:00"))
+        );
+        assert_eq!(
+            next("FREQ=DAILY;UNTIL=20240205T000000Z", "2025-02-10 00:00"),
+            None
+        );
+        assert_eq!(Recurrence::parse("FREQ=HOURLY"), None);
+    }
+
+    #[test]
+    fn test_upcoming() {
+        let now = at("2025-10-18 12:00");
+        let events = upcoming(&parse_ics(ICS, now), now, 2);
+        let summaries: Vec<_> = events.iter().map(|e| e.summary.as_str()).collect();
+        assert_eq!(
+            summaries,
+            vec![
+                "A very long event title that is folded across two lines",
+                "Dentist"
+            ]
+        );
+
+        let today = now.date();
+        assert_eq!(when(&events[0], today), "Today · All day");
+        assert_eq!(when(&events[1], today), "Mon 20 Oct · 09:30");
+        assert!(agenda_path(today, &events).starts_with("2025-10-18-"));
+        assert_ne!(
+            agenda_path(today, &events),
+            agenda_path(today, &events[..1])
+        );
+    }
+}
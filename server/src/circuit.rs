@@ -0,0 +1,115 @@
+//! Circuit breaker for upstream APIs
+//!
+//! After a run of consecutive failures the circuit opens and requests skip the
+//! upstream entirely for a cooldown, instead of each one waiting out a timeout.
+//! Once the cooldown passes a single trial request is let through: success
+//! closes the circuit, failure opens it for another cooldown.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Consecutive failures before the circuit opens
+const FAILURE_THRESHOLD: u32 = 3;
+
+/// How long an open circuit skips the upstream
+const COOLDOWN: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+struct BreakerState {
+    /// Consecutive failures since the last success
+    failures: u32,
+    /// When the open circuit lets a trial request through
+    open_until: Option<Instant>,
+}
+
+/// Tracks upstream failures and decides whether to try it
+#[derive(Default)]
+pub struct CircuitBreaker {
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a request should be sent to the upstream
+    ///
+    /// While open this is false until the cooldown passes, then true for one
+    /// trial request (the cooldown restarts so concurrent callers keep skipping).
+    pub fn allow(&self) -> bool {
+        self.allow_at(Instant::now())
+    }
+
+    /// Record a successful request, closing the circuit
+    pub fn record_success(&self) {
+        *self.state.lock().unwrap() = BreakerState::default();
+    }
+
+    /// Record a failed request, opening the circuit once failures pile up
+    pub fn record_failure(&self) {
+        self.record_failure_at(Instant::now());
+    }
+
+    fn allow_at(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.open_until {
+            None => true,
+            Some(until) if now >= until => {
+                state.open_until = Some(now + COOLDOWN);
+                true
+            }
+            Some(_) => false,
+        }
+    }
+
+    fn record_failure_at(&self, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        state.failures = state.failures.saturating_add(1);
+        if state.failures >= FAILURE_THRESHOLD {
+            if state.open_until.is_none() {
+                tracing::warn!(
+                    "Circuit opened after {} consecutive failures",
+                    state.failures
+                );
+            }
+            state.open_until = Some(now + COOLDOWN);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new();
+        let now = Instant::now();
+        assert!(breaker.allow_at(now));
+
+        // Stays closed below the threshold
+        for _ in 1..FAILURE_THRESHOLD {
+            breaker.record_failure_at(now);
+        }
+        assert!(breaker.allow_at(now));
+
+        // Opens, skipping the upstream until the cooldown passes
+        breaker.record_failure_at(now);
+        assert!(!breaker.allow_at(now));
+        assert!(!breaker.allow_at(now + COOLDOWN / 2));
+
+        // One trial request after the cooldown, then skipping again
+        let later = now + COOLDOWN;
+        assert!(breaker.allow_at(later));
+        assert!(!breaker.allow_at(later));
+
+        // A failed trial reopens it, a successful one closes it
+        breaker.record_failure_at(later);
+        assert!(!breaker.allow_at(later + COOLDOWN / 2));
+        assert!(breaker.allow_at(later + COOLDOWN));
+        breaker.record_success();
+        assert!(breaker.allow_at(later + COOLDOWN));
+        assert!(breaker.allow_at(later + COOLDOWN));
+    }
+}
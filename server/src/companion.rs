@@ -0,0 +1,295 @@
+//! Concert listing for companion apps
+//!
+//! The widget data the firmware fetches is kept to a bare array of paths. Apps
+//! browsing the same concerts on a phone or desktop get a JSON:API document
+//! instead: each concert's band, date and venue, links to its card in several
+//! sizes (the dithered panel images, and full-color renders from the print
+//! endpoint), and pagination links.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::admin;
+use crate::alt_text;
+use crate::image_processing::PRINT_BASE_DPI;
+use crate::sawthat::ConcertListing;
+use crate::widget::{Orientation, WidgetName, WidgetWidth};
+
+/// JSON:API media type
+pub const JSONAPI_CONTENT_TYPE: &str = "application/vnd.api+json";
+
+/// Path of the listing
+pub const CONCERTS_PATH: &str = "/api/v1/concerts";
+
+/// Concerts per page when not requested
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+
+/// Most concerts served on one page
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Resolutions of the full-color renders linked, by size name
+const PRINT_SIZES: [(&str, u32); 3] = [("small", 50), ("medium", 150), ("large", 300)];
+
+/// Pagination query, in JSON:API's `page[number]` and `page[size]` style
+#[derive(Debug, Default, Deserialize)]
+pub struct PageQuery {
+    #[serde(rename = "page[number]")]
+    pub number: Option<usize>,
+    #[serde(rename = "page[size]")]
+    pub size: Option<usize>,
+}
+
+/// A page of concerts
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ConcertsDocument {
+    pub data: Vec<ConcertResource>,
+    pub links: PageLinks,
+    pub meta: PageMeta,
+}
+
+/// A concert as a JSON:API resource object
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ConcertResource {
+    /// Always `concerts`
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Item path, as listed in the widget data
+    pub id: String,
+    pub attributes: ConcertAttributes,
+    pub links: ConcertLinks,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ConcertAttributes {
+    pub band: String,
+    pub band_id: String,
+    /// Concert date (YYYY-MM-DD)
+    pub date: String,
+    /// Date as captioned on the card, e.g. "June 15th, 2024"
+    pub formatted_date: String,
+    /// Venue and location
+    pub venue: String,
+    /// Description of the card for screen readers
+    pub alt: String,
+    /// The band's photo from Spotify
+    pub band_picture: String,
+    /// The card in every size served, smallest first within each kind
+    pub images: Vec<ImageLink>,
+}
+
+/// A rendering of a concert's card
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct ImageLink {
+    /// `panel_horiz` and `panel_vert` as a frame shows them, dithered to its
+    /// six colors; `small`, `medium` and `large` in full color
+    pub name: String,
+    pub href: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ConcertLinks {
+    /// How the card's cover art was chosen
+    pub cover: String,
+}
+
+/// Links between pages, `prev` and `next` left out at either end
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct PageLinks {
+    #[serde(rename = "self")]
+    pub this: String,
+    pub first: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+    pub last: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct PageMeta {
+    /// Page number, starting at 1
+    pub page: usize,
+    /// Concerts per page
+    pub size: usize,
+    /// Total concerts across all pages
+    pub total: usize,
+    /// Number of pages
+    pub pages: usize,
+}
+
+/// Build the page of `concerts` that `query` asks for
+pub fn build_document(concerts: Vec<ConcertListing>, query: &PageQuery) -> ConcertsDocument {
+    let total = concerts.len();
+    let size = query
+        .size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let pages = total.div_ceil(size).max(1);
+    let page = query.number.unwrap_or(1).clamp(1, pages);
+    let range = admin::page_range(total, page, size);
+
+    let page_link = |number: usize| {
+        format!(
+            "{}?page%5Bnumber%5D={}&page%5Bsize%5D={}",
+            CONCERTS_PATH, number, size
+        )
+    };
+    ConcertsDocument {
+        data: concerts
+            .into_iter()
+            .skip(range.start)
+            .take(range.len())
+            .map(resource)
+            .collect(),
+        links: PageLinks {
+            this: page_link(page),
+            first: page_link(1),
+            prev: (page > 1).then(|| page_link(page - 1)),
+            next: (page < pages).then(|| page_link(page + 1)),
+            last: page_link(pages),
+        },
+        meta: PageMeta {
+            page,
+            size,
+            total,
+            pages,
+        },
+    }
+}
+
+fn resource(concert: ConcertListing) -> ConcertResource {
+    let path = urlencoding::encode(&concert.path).into_owned();
+    let alt = alt_text::concert_alt_text(&concert.info(), None);
+    ConcertResource {
+        kind: "concerts".to_string(),
+        links: ConcertLinks {
+            cover: format!("/concerts/{}/cover", path),
+        },
+        attributes: ConcertAttributes {
+            images: images(&concert.path),
+            band: concert.band,
+            band_id: concert.band_id,
+            date: concert.date,
+            formatted_date: concert.formatted_date,
+            venue: concert.venue,
+            alt,
+            band_picture: concert.band_picture,
+        },
+        id: concert.path,
+    }
+}
+
+/// The card's panel images and full-color renders
+fn images(path: &str) -> Vec<ImageLink> {
+    let panel = [Orientation::Horiz, Orientation::Vert]
+        .into_iter()
+        .map(|orientation| {
+            let (width, height) = orientation.dimensions(WidgetWidth::Half);
+            ImageLink {
+                name: format!("panel_{}", orientation),
+                href: admin::item_url("", WidgetName::Concerts, orientation, path),
+                width,
+                height,
+            }
+        });
+    // Printed cards are the horizontal card scaled up from PRINT_BASE_DPI
+    let (card_width, card_height) = Orientation::Horiz.dimensions(WidgetWidth::Half);
+    let print = PRINT_SIZES.iter().map(|&(name, dpi)| ImageLink {
+        name: name.to_string(),
+        href: format!("/concerts/{}/print?dpi={}", urlencoding::encode(path), dpi),
+        width: card_width * dpi / PRINT_BASE_DPI,
+        height: card_height * dpi / PRINT_BASE_DPI,
+    });
+    panel.chain(print).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(index: usize) -> ConcertListing {
+        ConcertListing {
+            path: format!("2024-06-{:02}-band-{}", index + 1, index),
+            band: format!("Band {}", index),
+            band_id: format!("band-{}", index),
+            band_picture: "https://example.com/band.jpg".to_string(),
+            date: format!("2024-06-{:02}", index + 1),
+            formatted_date: format!("June {}th, 2024", index + 1),
+            venue: "The Venue, Somewhere".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_build_document() {
+        let concerts: Vec<_> = (0..5).map(listing).collect();
+        let query = PageQuery {
+            number: Some(2),
+            size: Some(2),
+        };
+        let document = build_document(concerts.clone(), &query);
+        let ids: Vec<_> = document.data.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["2024-06-03-band-2", "2024-06-04-band-3"]);
+        assert_eq!(
+            document.meta,
+            PageMeta {
+                page: 2,
+                size: 2,
+                total: 5,
+                pages: 3,
+            }
+        );
+        assert_eq!(
+            document.links.prev.as_deref(),
+            Some("/api/v1/concerts?page%5Bnumber%5D=1&page%5Bsize%5D=2")
+        );
+        assert_eq!(
+            document.links.next.as_deref(),
+            Some("/api/v1/concerts?page%5Bnumber%5D=3&page%5Bsize%5D=2")
+        );
+
+        // Out of range pages and sizes are clamped
+        let document = build_document(
+            concerts,
+            &PageQuery {
+                number: Some(9),
+                size: Some(1000),
+            },
+        );
+        assert_eq!(document.data.len(), 5);
+        assert_eq!(document.meta.pages, 1);
+        assert_eq!(document.links.prev, None);
+        assert_eq!(document.links.next, None);
+
+        // Bracketed keys parse whether or not they're percent-encoded
+        let uri: axum::http::Uri = "/api/v1/concerts?page%5Bnumber%5D=3&page[size]=7"
+            .parse()
+            .unwrap();
+        let query = axum::extract::Query::<PageQuery>::try_from_uri(&uri).unwrap();
+        assert_eq!((query.number, query.size), (Some(3), Some(7)));
+    }
+
+    #[test]
+    fn test_resource() {
+        let resource = resource(listing(0));
+        let json = serde_json::to_value(&resource).unwrap();
+        assert_eq!(json["type"], "concerts");
+        assert_eq!(json["attributes"]["band"], "Band 0");
+        assert_eq!(json["links"]["cover"], "/concerts/2024-06-01-band-0/cover");
+
+        let images = &resource.attributes.images;
+        assert_eq!(
+            images[0],
+            ImageLink {
+                name: "panel_horiz".to_string(),
+                href: "/concerts/horiz/2024-06-01-band-0".to_string(),
+                width: 400,
+                height: 480,
+            }
+        );
+        let large = images.iter().find(|image| image.name == "large").unwrap();
+        assert_eq!(large.href, "/concerts/2024-06-01-band-0/print?dpi=300");
+        assert_eq!((large.width, large.height), (2400, 2880));
+    }
+}
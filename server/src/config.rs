@@ -0,0 +1,1338 @@
+//! Server configuration, including the device settings served to the firmware
+//!
+//! Settings are read from environment variables at startup:
+//! - `REFRESH_INTERVAL_SECS`: seconds between display refreshes (default 900)
+//! - `DEFAULT_ORIENTATION`: `horiz` or `vert` (default `horiz`)
+//! - `WIDGETS`: comma-separated widget rotation (default `concerts`)
+//! - `HELD_WIDGET`: widget held in the right slot of horizontal mode, refreshed every
+//!   `HELD_WIDGET_TTL_SECS` (default 3600) while the left slot rotates every wake
+//! - `QUIET_HOURS`: local time window the frame sleeps through, e.g. `23:00-07:00`,
+//!   with `QUIET_HOURS_UTC_OFFSET_MINS` giving local time's offset from UTC (default 0)
+//! - `CLEAR_EVERY_REFRESHES`: refreshes between full clears of the panel to reduce
+//!   ghosting, `0` to never clear (default: the device's own, 24)
+//! - `REFRESH_MODE`: panel waveform for routine refreshes, `fast` (default) or
+//!   `standard` (slower, cleaner); clears always use `standard`
+//! - `SERVER_IP`: IPv4 address frames connect to without resolving the server's name
+//! - `SPKI_SHA256`: SHA-256 of the https server key's SubjectPublicKeyInfo, in hex,
+//!   which frames pin the server to
+//! - `IMAGE_FIT`: `cover`, `auto` or `letterbox` (default `cover`)
+//! - `IMAGE_SATURATION`: saturation multiplier (default 2.0)
+//! - `IMAGE_DITHER`: `fs`, `atkinson`, `jjn`, `ordered` or `none` (default `fs`)
+//! - `IMAGE_DITHER_SCAN`: error diffusion order, `serpentine` or `raster` (default `serpentine`)
+//! - `IMAGE_TEXT_COLOR`: concert caption color, `mono` (black or white, default) or
+//!   `accent` (the most contrasting panel color, when legible)
+//! - `CONCERTS_DITHER`, `SPOTIFY_DITHER`, `LASTFM_DITHER`, `CALENDAR_DITHER`,
+//!   `PHOTOS_DITHER`: dithering for one widget's images, overriding `IMAGE_DITHER`
+//! - `CONCERTS_LIMIT`: concerts in the rotation (default and maximum 128)
+//! - `CONCERTS_SORT`: `newest` (default) or `oldest` first
+//! - `CONCERTS_SINCE`: only concerts on or after this date (`YYYY` or `YYYY-MM-DD`)
+//! - `SAWTHAT_API_URL`, `DEEZER_API_URL`: upstream APIs for the concerts widget (defaults
+//!   are the public ones; overridden to point at mocks in tests)
+//! - `VENUE_ABBREVIATIONS`: extra `phrase=abbreviation` pairs, `;`-separated (see `abbreviate`)
+//! - `EXPERIMENT_NAME`, `EXPERIMENT_VARIANTS`: rendering A/B experiment (see `experiment`)
+//! - `SPOTIFY_CLIENT_ID`, `SPOTIFY_CLIENT_SECRET`, `SPOTIFY_REFRESH_TOKEN`: enable the
+//!   Spotify recently played widget
+//! - `LASTFM_API_KEY`, `LASTFM_USER`: enable the Last.fm top albums widget, over
+//!   `LASTFM_PERIOD` (`overall`, `7day`, `1month` (default), `3month`, `6month` or `12month`)
+//! - `CALENDAR_URL`: ICS feed (or CalDAV export) for the calendar widget, with optional
+//!   `CALENDAR_USERNAME`/`CALENDAR_PASSWORD`, `CALENDAR_EVENTS` (default 5),
+//!   `CALENDAR_TITLE` (default `Upcoming`) and `CALENDAR_WIDTH` (`half` (default) or
+//!   `full`, spanning both halves in horizontal mode)
+//! - `PHOTOS_DIR` or `PHOTOS_URL`: photo album for the photos widget, either a local folder,
+//!   a WebDAV collection (`https://...`, optional `PHOTOS_USERNAME`/`PHOTOS_PASSWORD`) or
+//!   a public S3 bucket (`s3+https://bucket.host/prefix/`)
+//! - `CACHE_BACKEND`: where cached art and renders persist, `memory`, `disk` (under
+//!   `CACHE_DIR`, the default when it is set) or `redis` (at `REDIS_URL`)
+//! - `BLOCKED_ART_KEYWORDS`, `BLOCKED_ART_URLS`, `BLOCKED_ART_HASHES`: comma-separated
+//!   album title keywords, cover URLs and perceptual hashes of artwork never shown
+//!   (see `blocklist`)
+
+use chrono::NaiveDate;
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::de::IntoDeserializer;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use utoipa::ToSchema;
+
+use crate::abbreviate;
+use crate::blocklist;
+use crate::deezer::DEEZER_BASE;
+use crate::experiment::Experiment;
+use crate::image_processing::{DitherMode, RenderParams};
+use crate::lastfm::Period;
+use crate::musicbrainz::{COVERART_API_URL, MUSICBRAINZ_API_URL};
+use crate::sawthat::{ConcertSort, SAWTHAT_API_URL};
+use crate::setlistfm::{SetlistCaption, SETLISTFM_API_URL};
+use crate::widget::{Orientation, WidgetName, WidgetWidth};
+
+/// Default refresh interval (15 minutes)
+const DEFAULT_REFRESH_INTERVAL_SECS: u32 = 15 * 60;
+
+/// Shortest refresh interval the device will accept (1 minute)
+pub(crate) const MIN_REFRESH_INTERVAL_SECS: u32 = 60;
+
+/// Longest refresh interval the device will accept (24 hours)
+pub(crate) const MAX_REFRESH_INTERVAL_SECS: u32 = 24 * 60 * 60;
+
+/// Default time a held widget stays in its slot (1 hour)
+const DEFAULT_HELD_TTL_SECS: u32 = 60 * 60;
+
+/// Largest UTC offset in minutes (UTC+14:00)
+const MAX_UTC_OFFSET_MINS: i16 = 14 * 60;
+
+/// Device settings fetched by the frame on each wake
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct DeviceConfig {
+    /// Seconds between display refreshes (deep sleep duration)
+    pub refresh_interval_secs: u32,
+    /// Orientation used until the user toggles it on the device
+    pub default_orientation: Orientation,
+    /// Widgets to rotate through, in order
+    pub widgets: Vec<WidgetName>,
+    /// Item the device was asked to show next, until it reports showing it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub show_next: Option<String>,
+    /// Commands queued for the device, oldest first, delivered once
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<DeviceCommand>,
+    /// Widget held in the right slot of horizontal mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub held_slot: Option<HeldSlot>,
+    /// Hours the device sleeps through instead of refreshing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quiet_hours: Option<QuietHours>,
+    /// Refreshes between full clears of the panel, 0 to never clear (the
+    /// device's default when unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clear_every: Option<u16>,
+    /// Panel waveform for routine refreshes (the device's default, fast, when unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_mode: Option<RefreshMode>,
+    /// Address the device connects to from its next wake on, without resolving
+    /// the server's name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, example = "192.168.1.20")]
+    pub server_ip: Option<Ipv4Addr>,
+    /// SHA-256 of the server key's SubjectPublicKeyInfo (64 hex digits), which
+    /// the device requires the server's certificate to carry from its next wake on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spki_sha256: Option<String>,
+}
+
+/// Panel waveform the device refreshes with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RefreshMode {
+    /// Fast refresh (~5-8s), slightly more ghosting
+    Fast,
+    /// Standard refresh (~15-20s), best image quality
+    Standard,
+}
+
+/// A remote-control command for a device, run after its next refresh
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceCommand {
+    /// Show the next item, like a button tap
+    Next,
+    /// Toggle the orientation, like a button hold
+    Flip,
+    /// Redraw the current items with a full refresh
+    Refresh,
+}
+
+/// A widget held in the right slot, refreshed on its own cadence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct HeldSlot {
+    /// Widget whose first item is shown
+    pub widget: WidgetName,
+    /// Seconds the slot is kept before the device refreshes it (60-86400)
+    pub ttl_secs: u32,
+}
+
+/// A daily window the device sleeps through, e.g.
+/// `{"start": "23:00", "end": "07:00", "utc_offset_mins": -300}`
+///
+/// The device only knows UTC, so local time is a fixed offset (daylight saving
+/// time changes need the offset updated).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct QuietHours {
+    /// Local time the window starts, `HH:MM`
+    pub start: String,
+    /// Local time the window ends, `HH:MM` (earlier than `start` if it spans midnight)
+    pub end: String,
+    /// Local time's offset from UTC in minutes (-840 to 840)
+    #[serde(default)]
+    pub utc_offset_mins: i16,
+}
+
+impl QuietHours {
+    /// Parse a `HH:MM-HH:MM` window
+    fn parse(window: &str, utc_offset_mins: i16) -> Option<Self> {
+        let (start, end) = window.split_once('-')?;
+        let quiet_hours = Self {
+            start: start.trim().to_string(),
+            end: end.trim().to_string(),
+            utc_offset_mins,
+        };
+        quiet_hours.validate().ok()?;
+        Some(quiet_hours)
+    }
+
+    /// Check the times are `HH:MM` and the offset a real one
+    pub fn validate(&self) -> Result<(), String> {
+        for time in [&self.start, &self.end] {
+            if minute_of_day(time).is_none() {
+                return Err(format!("quiet hours time must be HH:MM, got {:?}", time));
+            }
+        }
+        if self.utc_offset_mins.abs() > MAX_UTC_OFFSET_MINS {
+            return Err(format!(
+                "utc_offset_mins must be between -{} and {}",
+                MAX_UTC_OFFSET_MINS, MAX_UTC_OFFSET_MINS
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Minutes since midnight of a `HH:MM` time
+fn minute_of_day(time: &str) -> Option<u16> {
+    let (hours, minutes) = time.split_once(':')?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return None;
+    }
+    let hours: u16 = hours.parse().ok()?;
+    let minutes: u16 = minutes.parse().ok()?;
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// An SPKI hash as the device takes it, 64 lowercase hex digits
+pub(crate) fn parse_spki_sha256(value: &str) -> Option<String> {
+    let value = value.trim();
+    (value.len() == 64 && value.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| value.to_ascii_lowercase())
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self {
+            refresh_interval_secs: DEFAULT_REFRESH_INTERVAL_SECS,
+            default_orientation: Orientation::Horiz,
+            widgets: vec![WidgetName::Concerts],
+            show_next: None,
+            commands: Vec::new(),
+            held_slot: None,
+            quiet_hours: None,
+            clear_every: None,
+            refresh_mode: None,
+            server_ip: None,
+            spki_sha256: None,
+        }
+    }
+}
+
+impl DeviceConfig {
+    /// Load configuration from environment variables
+    pub fn from_env() -> Self {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    /// Load configuration from a variable lookup, falling back to defaults for invalid values
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let mut config = Self::default();
+
+        if let Some(value) = var("REFRESH_INTERVAL_SECS") {
+            match value.trim().parse::<u32>() {
+                Ok(secs) => {
+                    config.refresh_interval_secs =
+                        secs.clamp(MIN_REFRESH_INTERVAL_SECS, MAX_REFRESH_INTERVAL_SECS)
+                }
+                Err(_) => tracing::warn!("Invalid REFRESH_INTERVAL_SECS: {}", value),
+            }
+        }
+
+        if let Some(value) = var("DEFAULT_ORIENTATION") {
+            match parse_name(&value) {
+                Some(orientation) => config.default_orientation = orientation,
+                None => tracing::warn!("Invalid DEFAULT_ORIENTATION: {}", value),
+            }
+        }
+
+        if let Some(value) = var("WIDGETS") {
+            let widgets: Vec<WidgetName> = value
+                .split(',')
+                .filter(|name| !name.trim().is_empty())
+                .filter_map(|name| {
+                    let widget = parse_name(name);
+                    if widget.is_none() {
+                        tracing::warn!("Unknown widget in WIDGETS: {}", name.trim());
+                    }
+                    widget
+                })
+                .collect();
+            if !widgets.is_empty() {
+                config.widgets = widgets;
+            }
+        }
+
+        if let Some(value) = var("HELD_WIDGET") {
+            match parse_name(&value) {
+                Some(widget) => {
+                    let ttl_secs = match var("HELD_WIDGET_TTL_SECS") {
+                        Some(ttl) => ttl.trim().parse::<u32>().unwrap_or_else(|_| {
+                            tracing::warn!("Invalid HELD_WIDGET_TTL_SECS: {}", ttl);
+                            DEFAULT_HELD_TTL_SECS
+                        }),
+                        None => DEFAULT_HELD_TTL_SECS,
+                    };
+                    config.held_slot = Some(HeldSlot {
+                        widget,
+                        ttl_secs: ttl_secs
+                            .clamp(MIN_REFRESH_INTERVAL_SECS, MAX_REFRESH_INTERVAL_SECS),
+                    });
+                }
+                None => tracing::warn!("Unknown widget in HELD_WIDGET: {}", value.trim()),
+            }
+        }
+
+        if let Some(value) = var("QUIET_HOURS") {
+            let utc_offset_mins = match var("QUIET_HOURS_UTC_OFFSET_MINS") {
+                Some(offset) => offset.trim().parse::<i16>().unwrap_or_else(|_| {
+                    tracing::warn!("Invalid QUIET_HOURS_UTC_OFFSET_MINS: {}", offset);
+                    0
+                }),
+                None => 0,
+            };
+            match QuietHours::parse(&value, utc_offset_mins) {
+                Some(quiet_hours) => config.quiet_hours = Some(quiet_hours),
+                None => tracing::warn!("Invalid QUIET_HOURS: {}", value),
+            }
+        }
+
+        if let Some(value) = var("CLEAR_EVERY_REFRESHES") {
+            match value.trim().parse::<u16>() {
+                Ok(refreshes) => config.clear_every = Some(refreshes),
+                Err(_) => tracing::warn!("Invalid CLEAR_EVERY_REFRESHES: {}", value),
+            }
+        }
+
+        if let Some(value) = var("REFRESH_MODE") {
+            match parse_name(&value) {
+                Some(mode) => config.refresh_mode = Some(mode),
+                None => tracing::warn!("Invalid REFRESH_MODE: {}", value),
+            }
+        }
+
+        if let Some(value) = var("SERVER_IP") {
+            match value.trim().parse() {
+                Ok(ip) => config.server_ip = Some(ip),
+                Err(_) => tracing::warn!("Invalid SERVER_IP: {}", value),
+            }
+        }
+
+        if let Some(value) = var("SPKI_SHA256") {
+            match parse_spki_sha256(&value) {
+                Some(hash) => config.spki_sha256 = Some(hash),
+                None => tracing::warn!("Invalid SPKI_SHA256: {}", value),
+            }
+        }
+
+        config
+    }
+}
+
+/// Valid range for the saturation multiplier
+const SATURATION_RANGE: std::ops::RangeInclusive<f32> = 0.0..=4.0;
+
+/// Server-side rendering settings
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenderConfig {
+    /// Rendering parameters used outside of experiments
+    pub params: RenderParams,
+    /// Active A/B experiment, if configured
+    pub experiment: Option<Experiment>,
+    /// Per-widget dithering, overriding `params` and experiment variants
+    pub widget_dither: HashMap<WidgetName, DitherMode>,
+    /// Venue abbreviations tried before the built-in ones
+    pub venue_abbreviations: Vec<(String, String)>,
+}
+
+impl RenderConfig {
+    /// Load configuration from environment variables
+    pub fn from_env() -> Self {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    /// Load configuration from a variable lookup, falling back to defaults for invalid values
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let mut config = Self::default();
+
+        if let Some(value) = var("IMAGE_FIT") {
+            match parse_name(&value) {
+                Some(fit) => config.params.fit = fit,
+                None => tracing::warn!("Invalid IMAGE_FIT: {}", value),
+            }
+        }
+
+        if let Some(value) = var("IMAGE_SATURATION") {
+            match parse_saturation(&value) {
+                Some(saturation) => config.params.saturation = saturation,
+                None => tracing::warn!("Invalid IMAGE_SATURATION: {}", value),
+            }
+        }
+
+        if let Some(value) = var("IMAGE_DITHER") {
+            match parse_name(&value) {
+                Some(dither) => config.params.dither = dither,
+                None => tracing::warn!("Invalid IMAGE_DITHER: {}", value),
+            }
+        }
+
+        if let Some(value) = var("IMAGE_DITHER_SCAN") {
+            match parse_name(&value) {
+                Some(scan) => config.params.scan = scan,
+                None => tracing::warn!("Invalid IMAGE_DITHER_SCAN: {}", value),
+            }
+        }
+
+        if let Some(value) = var("IMAGE_TEXT_COLOR") {
+            match parse_name(&value) {
+                Some(text_color) => config.params.text_color = text_color,
+                None => tracing::warn!("Invalid IMAGE_TEXT_COLOR: {}", value),
+            }
+        }
+
+        for widget in WidgetName::ALL {
+            let key = format!("{}_DITHER", widget.to_string().to_uppercase());
+            if let Some(value) = var(&key) {
+                match parse_name(&value) {
+                    Some(dither) => {
+                        config.widget_dither.insert(widget, dither);
+                    }
+                    None => tracing::warn!("Invalid {}: {}", key, value),
+                }
+            }
+        }
+
+        if let Some(value) = var("VENUE_ABBREVIATIONS") {
+            match abbreviate::parse_phrases(&value) {
+                Some(phrases) => config.venue_abbreviations = phrases,
+                None => tracing::warn!("Invalid VENUE_ABBREVIATIONS: {}", value),
+            }
+        }
+
+        if let Some(variants) = var("EXPERIMENT_VARIANTS") {
+            let name = var("EXPERIMENT_NAME").unwrap_or_else(|| "render".to_string());
+            config.experiment = Experiment::parse(&name, &variants, config.params);
+            if config.experiment.is_none() {
+                tracing::warn!("Invalid EXPERIMENT_VARIANTS: {}", variants);
+            }
+        }
+
+        config
+    }
+}
+
+/// Most concerts in the rotation, as many as the frame can hold
+const MAX_CONCERTS: usize = 128;
+
+/// Which concerts the concerts widget rotates through, and where they come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConcertsConfig {
+    /// Number of concerts listed
+    pub limit: usize,
+    /// Order of the rotation
+    pub sort: ConcertSort,
+    /// Earliest concert date included
+    pub since: Option<NaiveDate>,
+    /// SawThat bands API
+    pub api_url: String,
+    /// Deezer API, for album art matching each concert
+    pub deezer_url: String,
+    /// MusicBrainz lookups for album art Deezer doesn't have, unless disabled
+    pub musicbrainz: Option<MusicBrainzConfig>,
+    /// Directory album overrides are persisted to
+    pub state_dir: Option<PathBuf>,
+    /// Setlist.fm lookups for caption details, if an API key is set
+    pub setlistfm: Option<SetlistFmConfig>,
+}
+
+impl Default for ConcertsConfig {
+    fn default() -> Self {
+        Self {
+            limit: MAX_CONCERTS,
+            sort: ConcertSort::default(),
+            since: None,
+            api_url: SAWTHAT_API_URL.to_string(),
+            deezer_url: DEEZER_BASE.to_string(),
+            musicbrainz: Some(MusicBrainzConfig::default()),
+            state_dir: None,
+            setlistfm: None,
+        }
+    }
+}
+
+impl ConcertsConfig {
+    /// Load configuration from environment variables
+    pub fn from_env() -> Self {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    /// Load configuration from a variable lookup, falling back to defaults for invalid values
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let mut config = Self::default();
+
+        if let Some(value) = var("CONCERTS_LIMIT") {
+            match value.trim().parse::<usize>() {
+                Ok(limit) => config.limit = limit.clamp(1, MAX_CONCERTS),
+                Err(_) => tracing::warn!("Invalid CONCERTS_LIMIT: {}", value),
+            }
+        }
+
+        if let Some(value) = var("CONCERTS_SORT") {
+            match parse_name(&value) {
+                Some(sort) => config.sort = sort,
+                None => tracing::warn!("Invalid CONCERTS_SORT: {}", value),
+            }
+        }
+
+        if let Some(value) = var("CONCERTS_SINCE") {
+            match parse_since(&value) {
+                Some(since) => config.since = Some(since),
+                None => tracing::warn!("Invalid CONCERTS_SINCE: {}", value),
+            }
+        }
+
+        // Upstream APIs can be pointed elsewhere, e.g. at mocks in tests
+        if let Some(url) = var("SAWTHAT_API_URL") {
+            config.api_url = url.trim().trim_end_matches('/').to_string();
+        }
+        if let Some(url) = var("DEEZER_API_URL") {
+            config.deezer_url = url.trim().trim_end_matches('/').to_string();
+        }
+        config.musicbrainz = MusicBrainzConfig::from_vars(&var);
+
+        // Shared with the device store
+        config.state_dir = var("STATE_DIR").map(PathBuf::from);
+
+        config.setlistfm = SetlistFmConfig::from_vars(&var);
+
+        config
+    }
+}
+
+/// MusicBrainz and Cover Art Archive endpoints, for album art fallbacks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MusicBrainzConfig {
+    /// MusicBrainz API
+    pub api_url: String,
+    /// Cover Art Archive
+    pub coverart_url: String,
+}
+
+impl Default for MusicBrainzConfig {
+    fn default() -> Self {
+        Self {
+            api_url: MUSICBRAINZ_API_URL.to_string(),
+            coverart_url: COVERART_API_URL.to_string(),
+        }
+    }
+}
+
+impl MusicBrainzConfig {
+    /// Load settings from a variable lookup, `None` if `MUSICBRAINZ_API_URL`
+    /// is set empty to turn the lookups off
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        let url = |key, default: &str| match var(key) {
+            Some(url) => url.trim().trim_end_matches('/').to_string(),
+            None => default.to_string(),
+        };
+        let api_url = url("MUSICBRAINZ_API_URL", MUSICBRAINZ_API_URL);
+        if api_url.is_empty() {
+            return None;
+        }
+        Some(Self {
+            api_url,
+            coverart_url: url("COVERART_API_URL", COVERART_API_URL),
+        })
+    }
+}
+
+/// Setlist.fm account for concert caption details
+#[derive(Clone, PartialEq, Eq)]
+pub struct SetlistFmConfig {
+    pub api_key: String,
+    /// What the setlist adds to the caption
+    pub caption: SetlistCaption,
+    /// Setlist.fm API
+    pub api_url: String,
+}
+
+impl SetlistFmConfig {
+    /// Load settings from a variable lookup, `None` unless the API key is set
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        let api_key = var("SETLISTFM_API_KEY")
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty())?;
+
+        let caption = match var("SETLISTFM_CAPTION") {
+            Some(value) => parse_name(&value).unwrap_or_else(|| {
+                tracing::warn!("Invalid SETLISTFM_CAPTION: {}", value);
+                SetlistCaption::default()
+            }),
+            None => SetlistCaption::default(),
+        };
+
+        Some(Self {
+            api_key,
+            caption,
+            api_url: var("SETLISTFM_API_URL")
+                .map(|url| url.trim().trim_end_matches('/').to_string())
+                .unwrap_or_else(|| SETLISTFM_API_URL.to_string()),
+        })
+    }
+}
+
+// The API key stays out of the logged config
+impl std::fmt::Debug for SetlistFmConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SetlistFmConfig")
+            .field("caption", &self.caption)
+            .field("api_url", &self.api_url)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Parse a minimum date, either `YYYY` (January 1st) or `YYYY-MM-DD`
+fn parse_since(value: &str) -> Option<NaiveDate> {
+    let value = value.trim();
+    match value.parse::<i32>() {
+        Ok(year) => NaiveDate::from_ymd_opt(year, 1, 1),
+        Err(_) => NaiveDate::parse_from_str(value, "%Y-%m-%d").ok(),
+    }
+}
+
+/// Spotify Web API credentials for the recently played widget
+///
+/// The refresh token comes from a one-time authorization code flow with the
+/// `user-read-recently-played` scope; the server exchanges it for access tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpotifyConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    pub refresh_token: String,
+}
+
+impl SpotifyConfig {
+    /// Load credentials from environment variables, `None` unless all are set
+    pub fn from_env() -> Option<Self> {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        let value = |key| {
+            var(key)
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        Some(Self {
+            client_id: value("SPOTIFY_CLIENT_ID")?,
+            client_secret: value("SPOTIFY_CLIENT_SECRET")?,
+            refresh_token: value("SPOTIFY_REFRESH_TOKEN")?,
+        })
+    }
+}
+
+/// Last.fm account for the top albums widget
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastFmConfig {
+    pub api_key: String,
+    /// Username whose scrobbles are ranked
+    pub user: String,
+    /// Time range for the ranking
+    pub period: Period,
+}
+
+impl LastFmConfig {
+    /// Load settings from environment variables, `None` unless the key and user are set
+    pub fn from_env() -> Option<Self> {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        let value = |key| {
+            var(key)
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+
+        let period = match value("LASTFM_PERIOD") {
+            Some(period) => parse_name(&period).unwrap_or_else(|| {
+                tracing::warn!("Invalid LASTFM_PERIOD: {}", period);
+                Period::default()
+            }),
+            None => Period::default(),
+        };
+
+        Some(Self {
+            api_key: value("LASTFM_API_KEY")?,
+            user: value("LASTFM_USER")?,
+            period,
+        })
+    }
+}
+
+/// Default number of events on the calendar agenda
+const DEFAULT_CALENDAR_EVENTS: usize = 5;
+
+/// Most events the calendar agenda will list
+const MAX_CALENDAR_EVENTS: usize = 20;
+
+/// Calendar feed settings for the agenda widget
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalendarConfig {
+    /// ICS URL (`webcal://` is fetched over HTTPS)
+    pub url: String,
+    /// HTTP basic auth credentials, for private CalDAV collections
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Number of upcoming events to list
+    pub events: usize,
+    /// Heading shown above the events
+    pub title: String,
+    /// Width of the agenda in horizontal mode
+    pub width: WidgetWidth,
+}
+
+impl CalendarConfig {
+    /// Load settings from environment variables, `None` unless `CALENDAR_URL` is set
+    pub fn from_env() -> Option<Self> {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        let value = |key| {
+            var(key)
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+
+        let events = match value("CALENDAR_EVENTS") {
+            Some(events) => match events.parse::<usize>() {
+                Ok(n) => n.clamp(1, MAX_CALENDAR_EVENTS),
+                Err(_) => {
+                    tracing::warn!("Invalid CALENDAR_EVENTS: {}", events);
+                    DEFAULT_CALENDAR_EVENTS
+                }
+            },
+            None => DEFAULT_CALENDAR_EVENTS,
+        };
+
+        let width = match value("CALENDAR_WIDTH").as_deref() {
+            None | Some("half") => WidgetWidth::Half,
+            Some("full") => WidgetWidth::Full,
+            Some(width) => {
+                tracing::warn!("Invalid CALENDAR_WIDTH: {}", width);
+                WidgetWidth::Half
+            }
+        };
+
+        Some(Self {
+            url: value("CALENDAR_URL")?,
+            username: value("CALENDAR_USERNAME"),
+            password: value("CALENDAR_PASSWORD"),
+            events,
+            title: value("CALENDAR_TITLE").unwrap_or_else(|| "Upcoming".to_string()),
+            width,
+        })
+    }
+}
+
+/// Where the photos widget lists photos from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PhotoSource {
+    /// Local folder, scanned recursively
+    Dir(PathBuf),
+    /// WebDAV collection URL
+    WebDav(String),
+    /// Public S3 bucket URL (virtual-hosted), with an optional key prefix path
+    S3(String),
+}
+
+/// Photos widget settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhotosConfig {
+    pub source: PhotoSource,
+    /// HTTP basic auth credentials, for private WebDAV collections
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl PhotosConfig {
+    /// Load settings from environment variables, `None` unless `PHOTOS_DIR` or
+    /// `PHOTOS_URL` is set
+    pub fn from_env() -> Option<Self> {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        let value = |key| {
+            var(key)
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+
+        let source = match (value("PHOTOS_DIR"), value("PHOTOS_URL")) {
+            (Some(dir), _) => PhotoSource::Dir(PathBuf::from(dir)),
+            (None, Some(url)) => match url.strip_prefix("s3+") {
+                Some(bucket) => PhotoSource::S3(bucket.to_string()),
+                None if url.starts_with("http://") || url.starts_with("https://") => {
+                    PhotoSource::WebDav(url)
+                }
+                None => {
+                    tracing::warn!("Unsupported PHOTOS_URL: {}", url);
+                    return None;
+                }
+            },
+            (None, None) => return None,
+        };
+
+        Some(Self {
+            source,
+            username: value("PHOTOS_USERNAME"),
+            password: value("PHOTOS_PASSWORD"),
+        })
+    }
+}
+
+/// Where cached source art and rendered images are kept beyond process memory
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum CacheBackend {
+    /// Process memory only, lost on restart
+    #[default]
+    Memory,
+    /// Files under a directory
+    Disk(PathBuf),
+    /// A Redis server, shared by every instance behind a load balancer
+    Redis(String),
+}
+
+impl CacheBackend {
+    /// Load the backend from environment variables
+    pub fn from_env() -> Self {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    /// `CACHE_BACKEND` picks the backend, defaulting to disk when `CACHE_DIR` is
+    /// set; a backend missing its setting falls back to memory
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let value = |key| {
+            var(key)
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+
+        let dir = value("CACHE_DIR").map(PathBuf::from);
+        let backend = value("CACHE_BACKEND");
+        match (backend.as_deref(), dir) {
+            (None, Some(dir)) | (Some("disk"), Some(dir)) => CacheBackend::Disk(dir),
+            (None, None) | (Some("memory"), _) => CacheBackend::Memory,
+            (Some("redis"), _) => match value("REDIS_URL") {
+                Some(url) => CacheBackend::Redis(url),
+                None => {
+                    tracing::warn!("CACHE_BACKEND=redis needs REDIS_URL, caching in memory");
+                    CacheBackend::Memory
+                }
+            },
+            (Some("disk"), None) => {
+                tracing::warn!("CACHE_BACKEND=disk needs CACHE_DIR, caching in memory");
+                CacheBackend::Memory
+            }
+            (Some(backend), _) => {
+                tracing::warn!("Invalid CACHE_BACKEND: {}", backend);
+                CacheBackend::Memory
+            }
+        }
+    }
+}
+
+/// Artwork kept off the frame, replaced by the next source or a placeholder
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlocklistConfig {
+    /// Words or phrases that block an album whose title contains them
+    pub keywords: Vec<String>,
+    /// Cover URLs blocked outright
+    pub urls: Vec<String>,
+    /// Perceptual hashes of blocked images (see `blocklist::image_hash`)
+    pub hashes: Vec<u64>,
+}
+
+impl BlocklistConfig {
+    /// Load the blocklist from environment variables
+    pub fn from_env() -> Self {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    /// Comma-separated lists; invalid hashes are logged and skipped
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let list = |key| -> Vec<String> {
+            var(key)
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|entry| !entry.is_empty())
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let hashes = list("BLOCKED_ART_HASHES")
+            .into_iter()
+            .filter_map(|hash| match blocklist::parse_hash(&hash) {
+                Some(hash) => Some(hash),
+                None => {
+                    tracing::warn!("Invalid hash in BLOCKED_ART_HASHES: {}", hash);
+                    None
+                }
+            })
+            .collect();
+
+        Self {
+            keywords: list("BLOCKED_ART_KEYWORDS"),
+            urls: list("BLOCKED_ART_URLS"),
+            hashes,
+        }
+    }
+}
+
+/// Parse a saturation multiplier, rejecting values outside `SATURATION_RANGE`
+pub(crate) fn parse_saturation(value: &str) -> Option<f32> {
+    value
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|s| SATURATION_RANGE.contains(s))
+}
+
+/// Parse a lowercase enum name using its serde representation
+pub(crate) fn parse_name<'de, T: Deserialize<'de>>(name: &'de str) -> Option<T> {
+    let deserializer: StrDeserializer<'de, ValueError> = name.trim().into_deserializer();
+    T::deserialize(deserializer).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_processing::{DitherMode, FitMode, ScanOrder, TextColor};
+    use std::collections::HashMap;
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    fn config_from(vars: &[(&str, &str)]) -> DeviceConfig {
+        DeviceConfig::from_vars(lookup(vars))
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(config_from(&[]), DeviceConfig::default());
+    }
+
+    #[test]
+    fn test_from_vars() {
+        let config = config_from(&[
+            ("REFRESH_INTERVAL_SECS", "1800"),
+            ("DEFAULT_ORIENTATION", "vert"),
+            ("WIDGETS", "concerts, concerts"),
+        ]);
+        assert_eq!(config.refresh_interval_secs, 1800);
+        assert_eq!(config.default_orientation, Orientation::Vert);
+        assert_eq!(
+            config.widgets,
+            vec![WidgetName::Concerts, WidgetName::Concerts]
+        );
+        assert_eq!(config.held_slot, None);
+
+        let config = config_from(&[
+            ("HELD_WIDGET", "calendar"),
+            ("HELD_WIDGET_TTL_SECS", "7200"),
+        ]);
+        assert_eq!(
+            config.held_slot,
+            Some(HeldSlot {
+                widget: WidgetName::Calendar,
+                ttl_secs: 7200
+            })
+        );
+        let held = config_from(&[("HELD_WIDGET", "calendar")]).held_slot;
+        assert_eq!(held.map(|held| held.ttl_secs), Some(DEFAULT_HELD_TTL_SECS));
+
+        let config = config_from(&[
+            ("QUIET_HOURS", "23:00 - 07:30"),
+            ("QUIET_HOURS_UTC_OFFSET_MINS", "-300"),
+        ]);
+        assert_eq!(
+            config.quiet_hours,
+            Some(QuietHours {
+                start: "23:00".to_string(),
+                end: "07:30".to_string(),
+                utc_offset_mins: -300,
+            })
+        );
+        assert_eq!(
+            serde_json::to_string(&config.quiet_hours).unwrap(),
+            r#"{"start":"23:00","end":"07:30","utc_offset_mins":-300}"#
+        );
+
+        let config = config_from(&[("CLEAR_EVERY_REFRESHES", "0")]);
+        assert_eq!(config.clear_every, Some(0));
+
+        let config = config_from(&[("REFRESH_MODE", "standard")]);
+        assert_eq!(config.refresh_mode, Some(RefreshMode::Standard));
+        assert_eq!(
+            serde_json::to_string(&config.refresh_mode).unwrap(),
+            r#""standard""#
+        );
+
+        let config = config_from(&[("SERVER_IP", "192.168.1.20")]);
+        assert_eq!(config.server_ip, Some(Ipv4Addr::new(192, 168, 1, 20)));
+        assert_eq!(
+            serde_json::to_string(&config.server_ip).unwrap(),
+            r#""192.168.1.20""#
+        );
+
+        let hash = "EF4828E2A577251914ECAEE0C9D10C7F2084C96AD2B5A2F2275F0560F7F96C03";
+        let config = config_from(&[("SPKI_SHA256", hash)]);
+        assert_eq!(config.spki_sha256, Some(hash.to_ascii_lowercase()));
+    }
+
+    #[test]
+    fn test_invalid_values_fall_back() {
+        let config = config_from(&[
+            ("REFRESH_INTERVAL_SECS", "soon"),
+            ("DEFAULT_ORIENTATION", "diagonal"),
+            ("WIDGETS", "weather"),
+            ("HELD_WIDGET", "weather"),
+            ("QUIET_HOURS", "11pm-7am"),
+            ("CLEAR_EVERY_REFRESHES", "-1"),
+            ("REFRESH_MODE", "slow"),
+            ("SERVER_IP", "frame.example.com"),
+            ("SPKI_SHA256", "ef4828e2"),
+        ]);
+        assert_eq!(config, DeviceConfig::default());
+        assert_eq!(
+            config_from(&[("QUIET_HOURS", "24:00-07:00")]).quiet_hours,
+            None
+        );
+        assert_eq!(
+            config_from(&[
+                ("QUIET_HOURS", "23:00-07:00"),
+                ("QUIET_HOURS_UTC_OFFSET_MINS", "900")
+            ])
+            .quiet_hours,
+            None
+        );
+
+        // Out of range intervals are clamped
+        let config = config_from(&[("REFRESH_INTERVAL_SECS", "5")]);
+        assert_eq!(config.refresh_interval_secs, MIN_REFRESH_INTERVAL_SECS);
+    }
+
+    #[test]
+    fn test_render_config() {
+        let render = |vars: &[(&str, &str)]| RenderConfig::from_vars(lookup(vars));
+        let fit = |value| render(&[("IMAGE_FIT", value)]).params.fit;
+        assert_eq!(render(&[]), RenderConfig::default());
+        assert_eq!(fit("letterbox"), FitMode::Letterbox);
+        assert_eq!(fit("auto"), FitMode::Auto);
+        assert_eq!(fit("stretch"), FitMode::Cover);
+
+        let config = render(&[("IMAGE_SATURATION", "1.6"), ("IMAGE_DITHER", "ordered")]);
+        assert_eq!(config.params.saturation, 1.6);
+        assert_eq!(config.params.dither, DitherMode::Ordered);
+        let config = render(&[("IMAGE_SATURATION", "-1"), ("IMAGE_DITHER", "random")]);
+        assert_eq!(config.params, RenderParams::default());
+
+        let config = render(&[
+            ("IMAGE_DITHER", "none"),
+            ("SPOTIFY_DITHER", "jarvis"),
+            ("PHOTOS_DITHER", "atkinson"),
+            ("CALENDAR_DITHER", "sierra"),
+        ]);
+        assert_eq!(config.params.dither, DitherMode::None);
+        assert_eq!(config.params.scan, ScanOrder::Serpentine);
+        let scan = |value| render(&[("IMAGE_DITHER_SCAN", value)]).params.scan;
+        assert_eq!(scan("raster"), ScanOrder::Raster);
+        assert_eq!(scan("zigzag"), ScanOrder::Serpentine);
+        let text_color = |value| render(&[("IMAGE_TEXT_COLOR", value)]).params.text_color;
+        assert_eq!(text_color("accent"), TextColor::Accent);
+        assert_eq!(text_color("rainbow"), TextColor::Mono);
+        assert_eq!(
+            config.widget_dither,
+            HashMap::from([
+                (WidgetName::Spotify, DitherMode::JarvisJudiceNinke),
+                (WidgetName::Photos, DitherMode::Atkinson),
+            ])
+        );
+
+        let config = render(&[("VENUE_ABBREVIATIONS", "Music Hall=MH")]);
+        assert_eq!(
+            config.venue_abbreviations,
+            vec![("Music Hall".to_string(), "MH".to_string())]
+        );
+        assert!(render(&[("VENUE_ABBREVIATIONS", "Music Hall")])
+            .venue_abbreviations
+            .is_empty());
+    }
+
+    #[test]
+    fn test_experiment_config() {
+        let config = RenderConfig::from_vars(lookup(&[
+            ("IMAGE_FIT", "auto"),
+            ("EXPERIMENT_NAME", "dither"),
+            ("EXPERIMENT_VARIANTS", "fs; ordered:dither=ordered"),
+        ]));
+        let experiment = config.experiment.unwrap();
+        assert_eq!(experiment.name, "dither");
+        assert_eq!(experiment.variants.len(), 2);
+        // Variants inherit the base parameters
+        assert_eq!(experiment.variants[1].params.fit, FitMode::Auto);
+
+        let config = RenderConfig::from_vars(lookup(&[("EXPERIMENT_VARIANTS", "a:size=2")]));
+        assert_eq!(config.experiment, None);
+    }
+
+    #[test]
+    fn test_spotify_config() {
+        let spotify = |vars: &[(&str, &str)]| SpotifyConfig::from_vars(lookup(vars));
+        assert_eq!(spotify(&[]), None);
+        assert_eq!(
+            spotify(&[
+                ("SPOTIFY_CLIENT_ID", "id"),
+                ("SPOTIFY_CLIENT_SECRET", "secret"),
+                ("SPOTIFY_REFRESH_TOKEN", " "),
+            ]),
+            None
+        );
+
+        let config = spotify(&[
+            ("SPOTIFY_CLIENT_ID", "id"),
+            ("SPOTIFY_CLIENT_SECRET", "secret"),
+            ("SPOTIFY_REFRESH_TOKEN", "token"),
+        ])
+        .unwrap();
+        assert_eq!(config.refresh_token, "token");
+    }
+
+    #[test]
+    fn test_lastfm_config() {
+        let lastfm = |vars: &[(&str, &str)]| LastFmConfig::from_vars(lookup(vars));
+        assert_eq!(lastfm(&[("LASTFM_API_KEY", "key")]), None);
+
+        let config = lastfm(&[("LASTFM_API_KEY", "key"), ("LASTFM_USER", "someone")]).unwrap();
+        assert_eq!(config.period, Period::Month);
+
+        let period = |value| {
+            lastfm(&[
+                ("LASTFM_API_KEY", "key"),
+                ("LASTFM_USER", "someone"),
+                ("LASTFM_PERIOD", value),
+            ])
+            .unwrap()
+            .period
+        };
+        assert_eq!(period("7day"), Period::Week);
+        assert_eq!(period("overall"), Period::Overall);
+        assert_eq!(period("fortnight"), Period::Month);
+    }
+
+    #[test]
+    fn test_calendar_config() {
+        let calendar = |vars: &[(&str, &str)]| CalendarConfig::from_vars(lookup(vars));
+        assert_eq!(calendar(&[("CALENDAR_EVENTS", "3")]), None);
+
+        let config = calendar(&[("CALENDAR_URL", "webcal://example.com/cal.ics")]).unwrap();
+        assert_eq!(config.events, DEFAULT_CALENDAR_EVENTS);
+        assert_eq!(config.title, "Upcoming");
+        assert_eq!(config.username, None);
+        assert_eq!(config.width, WidgetWidth::Half);
+
+        let config = calendar(&[
+            ("CALENDAR_URL", "https://dav.example.com/cal/?export"),
+            ("CALENDAR_USERNAME", "me"),
+            ("CALENDAR_PASSWORD", "secret"),
+            ("CALENDAR_EVENTS", "100"),
+            ("CALENDAR_TITLE", "This week"),
+            ("CALENDAR_WIDTH", "full"),
+        ])
+        .unwrap();
+        assert_eq!(config.events, MAX_CALENDAR_EVENTS);
+        assert_eq!(config.width, WidgetWidth::Full);
+        assert_eq!(config.title, "This week");
+        assert_eq!(config.password.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn test_concerts_config() {
+        let concerts = |vars: &[(&str, &str)]| ConcertsConfig::from_vars(lookup(vars));
+        assert_eq!(concerts(&[]), ConcertsConfig::default());
+
+        let config = concerts(&[
+            ("CONCERTS_LIMIT", "50"),
+            ("CONCERTS_SORT", "oldest"),
+            ("CONCERTS_SINCE", "2015"),
+        ]);
+        assert_eq!(config.limit, 50);
+        assert_eq!(config.sort, ConcertSort::Oldest);
+        assert_eq!(config.since, NaiveDate::from_ymd_opt(2015, 1, 1));
+
+        let config = concerts(&[
+            ("CONCERTS_LIMIT", "1000"),
+            ("CONCERTS_SORT", "random"),
+            ("CONCERTS_SINCE", "2019-06-30"),
+        ]);
+        assert_eq!(config.limit, MAX_CONCERTS);
+        assert_eq!(config.sort, ConcertSort::Newest);
+        assert_eq!(config.since, NaiveDate::from_ymd_opt(2019, 6, 30));
+
+        assert_eq!(concerts(&[("CONCERTS_SINCE", "last year")]).since, None);
+        assert_eq!(
+            concerts(&[("STATE_DIR", "/var/lib/sawthat")]).state_dir,
+            Some(PathBuf::from("/var/lib/sawthat"))
+        );
+
+        let setlistfm = concerts(&[
+            ("SETLISTFM_API_KEY", " secret "),
+            ("SETLISTFM_CAPTION", "closer"),
+        ])
+        .setlistfm
+        .unwrap();
+        assert_eq!(setlistfm.api_key, "secret");
+        assert_eq!(setlistfm.caption, SetlistCaption::Closer);
+        assert_eq!(setlistfm.api_url, SETLISTFM_API_URL);
+        assert!(!format!("{:?}", setlistfm).contains("secret"));
+        let setlistfm = concerts(&[
+            ("SETLISTFM_API_KEY", "key"),
+            ("SETLISTFM_CAPTION", "encore"),
+        ])
+        .setlistfm
+        .unwrap();
+        assert_eq!(setlistfm.caption, SetlistCaption::Tour);
+        assert_eq!(concerts(&[("SETLISTFM_API_KEY", "")]).setlistfm, None);
+
+        let config = concerts(&[
+            ("SAWTHAT_API_URL", "http://127.0.0.1:8080/api/bands"),
+            ("DEEZER_API_URL", "http://127.0.0.1:8080/deezer/"),
+        ]);
+        assert_eq!(config.api_url, "http://127.0.0.1:8080/api/bands");
+        assert_eq!(config.deezer_url, "http://127.0.0.1:8080/deezer");
+
+        let musicbrainz = concerts(&[("COVERART_API_URL", "http://127.0.0.1:8080/caa/")])
+            .musicbrainz
+            .unwrap();
+        assert_eq!(musicbrainz.api_url, MUSICBRAINZ_API_URL);
+        assert_eq!(musicbrainz.coverart_url, "http://127.0.0.1:8080/caa");
+        assert_eq!(concerts(&[("MUSICBRAINZ_API_URL", " ")]).musicbrainz, None);
+    }
+
+    #[test]
+    fn test_photos_config() {
+        let photos = |vars: &[(&str, &str)]| PhotosConfig::from_vars(lookup(vars));
+        assert_eq!(photos(&[("PHOTOS_USERNAME", "me")]), None);
+        assert_eq!(photos(&[("PHOTOS_URL", "ftp://example.com/photos")]), None);
+
+        let config = photos(&[
+            ("PHOTOS_DIR", "/srv/photos"),
+            ("PHOTOS_URL", "https://dav.example.com/photos/"),
+        ])
+        .unwrap();
+        assert_eq!(config.source, PhotoSource::Dir("/srv/photos".into()));
+
+        let config = photos(&[
+            ("PHOTOS_URL", "https://dav.example.com/photos/"),
+            ("PHOTOS_USERNAME", "me"),
+            ("PHOTOS_PASSWORD", "secret"),
+        ])
+        .unwrap();
+        assert_eq!(
+            config.source,
+            PhotoSource::WebDav("https://dav.example.com/photos/".to_string())
+        );
+        assert_eq!(config.password.as_deref(), Some("secret"));
+
+        let config = photos(&[("PHOTOS_URL", "s3+https://frame.s3.amazonaws.com/album/")]).unwrap();
+        assert_eq!(
+            config.source,
+            PhotoSource::S3("https://frame.s3.amazonaws.com/album/".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cache_backend() {
+        let backend = |vars: &[(&str, &str)]| CacheBackend::from_vars(lookup(vars));
+        assert_eq!(backend(&[]), CacheBackend::Memory);
+        assert_eq!(
+            backend(&[("CACHE_DIR", "/var/cache/frame")]),
+            CacheBackend::Disk("/var/cache/frame".into())
+        );
+        assert_eq!(
+            backend(&[
+                ("CACHE_BACKEND", "redis"),
+                ("CACHE_DIR", "/var/cache/frame"),
+                ("REDIS_URL", "redis://cache:6379/"),
+            ]),
+            CacheBackend::Redis("redis://cache:6379/".to_string())
+        );
+        assert_eq!(
+            backend(&[
+                ("CACHE_BACKEND", "memory"),
+                ("CACHE_DIR", "/var/cache/frame")
+            ]),
+            CacheBackend::Memory
+        );
+
+        // Missing settings and unknown backends cache in memory
+        assert_eq!(backend(&[("CACHE_BACKEND", "redis")]), CacheBackend::Memory);
+        assert_eq!(backend(&[("CACHE_BACKEND", "disk")]), CacheBackend::Memory);
+        assert_eq!(
+            backend(&[("CACHE_BACKEND", "s3"), ("CACHE_DIR", "/var/cache/frame")]),
+            CacheBackend::Memory
+        );
+    }
+
+    #[test]
+    fn test_blocklist_config() {
+        assert_eq!(
+            BlocklistConfig::from_vars(lookup(&[])),
+            BlocklistConfig::default()
+        );
+
+        let config = BlocklistConfig::from_vars(lookup(&[
+            ("BLOCKED_ART_KEYWORDS", "Explicit Cover, ,Uncensored"),
+            ("BLOCKED_ART_URLS", "https://example.com/a.jpg"),
+            ("BLOCKED_ART_HASHES", "00ff00ff00ff00ff, nothex, 1"),
+        ]));
+        assert_eq!(config.keywords, ["Explicit Cover", "Uncensored"]);
+        assert_eq!(config.urls, ["https://example.com/a.jpg"]);
+        assert_eq!(config.hashes, [0x00ff00ff00ff00ff]);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let json = serde_json::to_string(&DeviceConfig::default()).unwrap();
+        assert_eq!(
+            json,
+            r#"{"refresh_interval_secs":900,"default_orientation":"horiz","widgets":["concerts"]}"#
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! Device status dashboard
+//!
+//! One HTML page for checking on headless frames: for each device, its latest
+//! screenshot and the last image it fetched, its battery level, when it last
+//! checked in (flagged once it has missed two refreshes), its last reported
+//! crash and anything queued for it, followed by the server's image cache sizes.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::admin::{escape, item_url};
+use crate::cache::CacheStats;
+use crate::config::{DeviceCommand, DeviceConfig};
+use crate::device::{CrashReport, DeviceStore, ImageRef};
+
+/// Missed refreshes after which a device is flagged as overdue
+const OVERDUE_REFRESHES: u64 = 2;
+
+/// What the dashboard shows for one device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceStatus {
+    pub id: String,
+    /// Unix timestamp (seconds) of the latest request or telemetry report
+    pub last_seen_at: Option<u64>,
+    /// Seconds the device sleeps between refreshes
+    pub refresh_interval_secs: u32,
+    pub battery_percent: Option<u8>,
+    pub firmware_version: Option<String>,
+    pub last_image: Option<ImageRef>,
+    /// Unix timestamp (seconds) of the latest screenshot
+    pub screenshot_at: Option<u64>,
+    /// Latest reported crash, with the Unix timestamp (seconds) of its report
+    pub last_crash: Option<(u64, CrashReport)>,
+    pub show_next: Option<String>,
+    pub commands: Vec<DeviceCommand>,
+}
+
+impl DeviceStatus {
+    /// Whether the device has missed [`OVERDUE_REFRESHES`] refreshes in a row
+    fn overdue(&self, now: u64) -> bool {
+        self.last_seen_at.is_none_or(|seen| {
+            now.saturating_sub(seen) > OVERDUE_REFRESHES * self.refresh_interval_secs as u64
+        })
+    }
+}
+
+/// Status of every known device
+///
+/// Request telemetry is only kept in memory, so after a restart the last
+/// check-in and battery level fall back to the persisted telemetry reports.
+pub async fn device_statuses(devices: &DeviceStore, base: &DeviceConfig) -> Vec<DeviceStatus> {
+    let mut statuses = Vec::new();
+    for summary in devices.list().await {
+        let Some(details) = devices.details(&summary.id, base).await else {
+            continue;
+        };
+        let history = devices.history(&summary.id).await.unwrap_or_default();
+        let last_crash = history.iter().rev().find_map(|report| {
+            report
+                .crash
+                .clone()
+                .map(|crash| (report.received_at, crash))
+        });
+        let last_report = history.last().cloned();
+        let telemetry = details.telemetry;
+        statuses.push(DeviceStatus {
+            last_seen_at: telemetry
+                .as_ref()
+                .map(|t| t.last_seen_at)
+                .or(last_report.as_ref().map(|report| report.received_at)),
+            refresh_interval_secs: details.config.refresh_interval_secs,
+            battery_percent: telemetry
+                .as_ref()
+                .and_then(|t| t.battery_percent)
+                .or(last_report.and_then(|report| report.battery_percent)),
+            firmware_version: telemetry.as_ref().and_then(|t| t.firmware_version.clone()),
+            last_image: telemetry.and_then(|t| t.last_image),
+            screenshot_at: details.screenshot_at,
+            last_crash,
+            show_next: details.config.show_next,
+            commands: details.config.commands,
+            id: summary.id,
+        });
+    }
+    statuses
+}
+
+/// Render the dashboard as a standalone HTML document
+pub fn render_html(
+    devices: &[DeviceStatus],
+    caches: &BTreeMap<String, CacheStats>,
+    now: u64,
+) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Frames</title>\n<style>\n\
+         body { font-family: sans-serif; margin: 1em; }\n\
+         table { border-collapse: collapse; margin-bottom: 1em; }\n\
+         td, th { border: 1px solid #ccc; padding: 4px 8px; text-align: left; vertical-align: middle; }\n\
+         .overdue { color: #b00; font-weight: bold; }\n\
+         .screenshot { width: 200px; }\n\
+         </style>\n</head>\n<body>\n<h1>Frames</h1>\n",
+    );
+
+    if devices.is_empty() {
+        html.push_str("<p>No device has checked in yet.</p>\n");
+    } else {
+        html.push_str(
+            "<table>\n<tr><th>Device</th><th>Screenshot</th><th>Last image</th>\
+             <th>Battery</th><th>Last check-in</th><th>Queued</th></tr>\n",
+        );
+    }
+    for device in devices {
+        let id = escape(&device.id);
+        let _ = write!(
+            html,
+            "<tr><td><a href=\"/devices/{}\"><code>{}</code></a>",
+            id, id
+        );
+        if let Some(version) = &device.firmware_version {
+            let _ = write!(html, "<br>firmware {}", escape(version));
+        }
+        if let Some((at, crash)) = &device.last_crash {
+            let reason = format!("{:?}", crash.reset_reason).to_lowercase();
+            let _ = write!(
+                html,
+                "<br><span class=\"overdue\" title=\"{} unexpected resets\">{} {}</span>",
+                crash.count,
+                reason,
+                format_age(now.saturating_sub(*at))
+            );
+            if let Some(message) = &crash.message {
+                let _ = write!(html, "<br><code>{}</code>", escape(message));
+            }
+        }
+        html.push_str("</td><td>");
+        if let Some(at) = device.screenshot_at {
+            let _ = write!(
+                html,
+                "<a href=\"/devices/{id}/screenshot\"><img class=\"screenshot\" \
+                 src=\"/devices/{id}/screenshot\" alt=\"Screenshot of {id}\"></a><br>{}",
+                format_age(now.saturating_sub(at))
+            );
+        }
+        html.push_str("</td><td>");
+        if let Some(image) = &device.last_image {
+            let image_url = item_url("", image.widget, image.orientation, &image.path);
+            let thumbnail_url = item_url(
+                "/admin/thumbnails",
+                image.widget,
+                image.orientation,
+                &image.path,
+            );
+            let _ = write!(
+                html,
+                "<a href=\"{}\"><img src=\"{}\" alt=\"{}\" loading=\"lazy\"></a><br><code>{}</code>",
+                escape(&image_url),
+                escape(&thumbnail_url),
+                escape(&image.path),
+                escape(&image.path)
+            );
+        }
+        html.push_str("</td><td>");
+        match device.battery_percent {
+            Some(percent) => {
+                let _ = write!(html, "{}%", percent);
+            }
+            None => html.push_str("unknown"),
+        }
+        html.push_str("</td><td>");
+        let age = match device.last_seen_at {
+            Some(seen) => format_age(now.saturating_sub(seen)),
+            None => "never".to_string(),
+        };
+        if device.overdue(now) {
+            let _ = write!(
+                html,
+                "<span class=\"overdue\" title=\"Missed {} refreshes\">{}</span>",
+                OVERDUE_REFRESHES, age
+            );
+        } else {
+            html.push_str(&age);
+        }
+        html.push_str("</td><td>");
+        if let Some(path) = &device.show_next {
+            let _ = write!(html, "show <code>{}</code><br>", escape(path));
+        }
+        if !device.commands.is_empty() {
+            let commands: Vec<_> = device
+                .commands
+                .iter()
+                .map(|command| format!("{:?}", command).to_lowercase())
+                .collect();
+            html.push_str(&commands.join(", "));
+        }
+        html.push_str("</td></tr>\n");
+    }
+    if !devices.is_empty() {
+        html.push_str("</table>\n");
+    }
+
+    html.push_str(
+        "<h2>Image caches</h2>\n<table>\n<tr><th>Widget</th><th>Entries</th>\
+         <th>Expired</th><th>Images</th><th>Image size</th></tr>\n",
+    );
+    for (widget, stats) in caches {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1} MB</td></tr>",
+            escape(widget),
+            stats.entries,
+            stats.expired,
+            stats.images,
+            stats.image_bytes as f64 / 1_000_000.0
+        );
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+/// Rough age of something `secs` old, e.g. "5 min ago"
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", secs / 60),
+        3600..86_400 => format!("{} h ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86_400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::ResetReason;
+    use crate::widget::{Orientation, WidgetName};
+
+    fn status(last_seen_at: Option<u64>) -> DeviceStatus {
+        DeviceStatus {
+            id: "frame-240ac400beef".to_string(),
+            last_seen_at,
+            refresh_interval_secs: 900,
+            battery_percent: Some(73),
+            firmware_version: Some("0.1.0".to_string()),
+            last_image: Some(ImageRef {
+                widget: WidgetName::Concerts,
+                orientation: Orientation::Vert,
+                path: "2025-07-25-phish & friends".to_string(),
+            }),
+            screenshot_at: None,
+            last_crash: None,
+            show_next: None,
+            commands: vec![DeviceCommand::Flip, DeviceCommand::Next],
+        }
+    }
+
+    #[test]
+    fn test_render_html() {
+        let now = 1_750_000_000;
+        let caches = BTreeMap::from([(
+            "concerts".to_string(),
+            CacheStats {
+                entries: 3,
+                images: 6,
+                image_bytes: 1_500_000,
+                ..CacheStats::default()
+            },
+        )]);
+        let html = render_html(&[status(Some(now - 300))], &caches, now);
+        assert!(html.contains("<code>frame-240ac400beef</code>"));
+        assert!(html.contains("73%"));
+        assert!(html.contains("<td>5 min ago</td>"));
+        assert!(html
+            .contains("src=\"/admin/thumbnails/concerts/vert/2025-07-25-phish%20%26%20friends\""));
+        assert!(html.contains("<code>2025-07-25-phish &amp; friends</code>"));
+        assert!(html.contains("flip, next"));
+        assert!(html.contains("<td>concerts</td><td>3</td><td>0</td><td>6</td><td>1.5 MB</td>"));
+        assert!(!html.contains("overdue\""));
+
+        // Two missed refreshes
+        let html = render_html(&[status(Some(now - 3 * 900))], &caches, now);
+        assert!(
+            html.contains("<span class=\"overdue\" title=\"Missed 2 refreshes\">45 min ago</span>")
+        );
+        let html = render_html(&[status(None)], &caches, now);
+        assert!(html.contains("title=\"Missed 2 refreshes\">never</span>"));
+
+        // A reported crash
+        let crashed = DeviceStatus {
+            last_crash: Some((
+                now - 7200,
+                CrashReport {
+                    reset_reason: ResetReason::Panic,
+                    message: Some("unwrap on <None>".to_string()),
+                    count: 3,
+                },
+            )),
+            ..status(Some(now))
+        };
+        let html = render_html(&[crashed], &caches, now);
+        assert!(html.contains("title=\"3 unexpected resets\">panic 2 h ago</span>"));
+        assert!(html.contains("<code>unwrap on &lt;None&gt;</code>"));
+
+        assert!(render_html(&[], &BTreeMap::new(), now).contains("No device has checked in yet"));
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(0), "just now");
+        assert_eq!(format_age(59), "just now");
+        assert_eq!(format_age(60), "1 min ago");
+        assert_eq!(format_age(7200), "2 h ago");
+        assert_eq!(format_age(3 * 86_400 + 5), "3 days ago");
+    }
+}
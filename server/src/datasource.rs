@@ -2,18 +2,41 @@
 //!
 //! Data sources fetch and transform data from external APIs into widget items.
 
-use crate::cache::ConcertCache;
+use crate::cache::{CacheStats, ConcertCache, ConcertEntry};
+use crate::cache_store::CacheStore;
+use crate::calendar::{self, CalendarEvent};
+use crate::circuit::CircuitBreaker;
+use crate::config::{CalendarConfig, ConcertsConfig, LastFmConfig, PhotosConfig, SpotifyConfig};
 use crate::error::AppError;
-use crate::sawthat::{self, SawThatBand};
-use crate::widget::{CachePolicy, Orientation, WidgetData, WidgetName};
+use crate::experiment::Variant;
+use crate::image_processing;
+use crate::lastfm::{self, LastFmAlbum};
+use crate::musicbrainz::MusicBrainz;
+use crate::photos::{self, Photo};
+use crate::sawthat::{
+    self, AlbumOverrides, ConcertListing, ConcertNotes, CoverSelection, SawThatBand,
+};
+use crate::setlistfm::SetlistFm;
+use crate::spotify::{self, SpotifyClient};
+use crate::widget::{CachePolicy, Orientation, WidgetData, WidgetItem, WidgetName, WidgetWidth};
 use async_trait::async_trait;
 use reqwest::Client;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, RwLock, Semaphore};
 
 /// SawThat user ID - configured via environment or hardcoded
 /// TODO: Make this configurable via environment variable
 const SAWTHAT_USER_ID: &str = "a320940a-b493-4515-9f25-d393ebb540e6";
 
+/// How long before the bands list expires it's refreshed in the background
+const BANDS_REFRESH_LEAD: Duration = Duration::from_secs(10 * 60);
+
+/// Wait before retrying a failed background refresh of the bands list
+const BANDS_REFRESH_RETRY: Duration = Duration::from_secs(5 * 60);
+
 /// A data source that provides widget items
 #[async_trait]
 pub trait DataSource: Send + Sync {
@@ -23,26 +46,250 @@ pub trait DataSource: Send + Sync {
     /// Fetch widget data from the source
     async fn fetch_data(&self) -> Result<WidgetData, AppError>;
 
-    /// Fetch and process an image for a widget item
-    async fn fetch_image(&self, path: &str, orientation: Orientation) -> Result<Vec<u8>, AppError>;
+    /// Fetch and process an image for a widget item, rendered with the variant's parameters
+    async fn fetch_image(
+        &self,
+        path: &str,
+        orientation: Orientation,
+        variant: &Variant,
+    ) -> Result<Vec<u8>, AppError>;
+
+    /// Age of the data last served from an expired copy, while it's refreshed
+    /// in the background or because the upstream failed
+    async fn stale_age(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Whether an expired copy is being refreshed in the background
+    fn revalidating(&self) -> bool {
+        false
+    }
+
+    /// Already rendered image for a widget item, without fetching or rendering
+    ///
+    /// Sources that render on demand have nothing cached.
+    async fn cached_image(
+        &self,
+        _path: &str,
+        _orientation: Orientation,
+        _variant: &str,
+    ) -> Option<Arc<Vec<u8>>> {
+        None
+    }
+
+    /// Continuous-tone print export of a widget item's card at `dpi`
+    ///
+    /// Only sources whose cards are worth printing have one.
+    async fn print_image(
+        &self,
+        path: &str,
+        _variant: &Variant,
+        _dpi: u32,
+    ) -> Result<Vec<u8>, AppError> {
+        Err(AppError::NotFound(format!("no print export for {}", path)))
+    }
+
+    /// Sizes of the source's image cache, for diagnostics
+    ///
+    /// Sources that render on demand have none.
+    async fn cache_stats(&self) -> Option<CacheStats> {
+        None
+    }
+
+    /// How a widget item's cover art was chosen, resolving the item if needed
+    ///
+    /// Only sources that pick art among candidates record it.
+    async fn cover_selection(&self, path: &str) -> Result<CoverSelection, AppError> {
+        Err(AppError::NotFound(format!(
+            "no cover selection for {}",
+            path
+        )))
+    }
+
+    /// Pick a widget item's album by title instead, or go back to the
+    /// automatic choice with `None`
+    async fn override_album(&self, path: &str, _title: Option<&str>) -> Result<(), AppError> {
+        Err(AppError::NotFound(format!(
+            "no album override for {}",
+            path
+        )))
+    }
+
+    /// Caption a widget item with a personal note, or remove it with `None`
+    async fn set_note(&self, path: &str, _note: Option<&str>) -> Result<(), AppError> {
+        Err(AppError::NotFound(format!("no notes for {}", path)))
+    }
+}
+
+/// Outcome of a render job, once it's done
+type RenderOutcome = Option<Result<Vec<u8>, AppError>>;
+
+/// Renders in progress, so concurrent requests for an image share one render
+///
+/// The first request for a key runs the job and the others wait for its
+/// outcome instead of repeating the lookups and dithering. If that request is
+/// dropped before the job finishes, the next waiting one takes the job over.
+/// At most `workers` jobs run at once; the rest queue for a slot.
+struct RenderJobs {
+    in_flight: Mutex<HashMap<String, watch::Receiver<RenderOutcome>>>,
+    workers: Semaphore,
+}
+
+/// Ends a job on drop, whether it finished or its request went away
+struct RenderJob<'a> {
+    jobs: &'a RenderJobs,
+    key: &'a str,
+}
+
+impl Drop for RenderJob<'_> {
+    fn drop(&mut self) {
+        self.jobs.in_flight.lock().unwrap().remove(self.key);
+    }
+}
+
+impl RenderJobs {
+    fn new(workers: usize) -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+            workers: Semaphore::new(workers),
+        }
+    }
+
+    /// Run `render` as the job for `key`, or wait for the one already running
+    async fn run<F, Fut>(&self, key: &str, render: F) -> Result<Vec<u8>, AppError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Vec<u8>, AppError>>,
+    {
+        let mut render = Some(render);
+        loop {
+            let running = {
+                let mut in_flight = self.in_flight.lock().unwrap();
+                match in_flight.get(key) {
+                    Some(outcome) => Err(outcome.clone()),
+                    None => {
+                        let (sender, receiver) = watch::channel(None);
+                        in_flight.insert(key.to_string(), receiver);
+                        Ok(sender)
+                    }
+                }
+            };
+
+            match running {
+                Ok(sender) => {
+                    let _job = RenderJob { jobs: self, key };
+                    let _worker = self.workers.acquire().await.expect("never closed");
+                    let render = render.take().expect("only run once");
+                    let result = render().await;
+                    let shared = match &result {
+                        Ok(image) => Ok(image.clone()),
+                        Err(e) => Err(e.to_shared()),
+                    };
+                    sender.send_replace(Some(shared));
+                    return result;
+                }
+                Err(mut outcome) => {
+                    tracing::debug!("Waiting for the render of {} in progress", key);
+                    // A closed channel without an outcome: the job was dropped
+                    if let Ok(outcome) = outcome.wait_for(Option::is_some).await {
+                        return match outcome.as_ref().expect("waited for an outcome") {
+                            Ok(image) => Ok(image.clone()),
+                            Err(e) => Err(e.to_shared()),
+                        };
+                    }
+                }
+            }
+        }
+    }
 }
 
 /// Concert data source - fetches concert history from SawThat.band
 pub struct ConcertDataSource {
     client: Client,
-    /// In-memory cache with 24-hour TTL
+    /// Which concerts are listed, and in what order
+    config: ConcertsConfig,
+    /// Cache with 24-hour TTL, optionally persisted to disk
     cache: Arc<ConcertCache>,
+    /// Skips the SawThat API while it's failing, shared with background refreshes
+    breaker: Arc<CircuitBreaker>,
+    /// Albums picked by hand for concerts
+    album_overrides: AlbumOverrides,
+    /// Personal notes captioned under concerts
+    notes: ConcertNotes,
+    /// MusicBrainz lookups for album art Deezer doesn't have, unless disabled
+    musicbrainz: Option<MusicBrainz>,
+    /// Setlist.fm lookups for caption details, if configured
+    setlists: Option<SetlistFm>,
+    /// Image renders in progress
+    renders: RenderJobs,
 }
 
 impl ConcertDataSource {
-    pub fn new(client: Client) -> Self {
+    pub fn new(client: Client, config: ConcertsConfig, store: Option<Arc<dyn CacheStore>>) -> Self {
+        let cache = match store {
+            Some(store) => ConcertCache::with_store(store, "concerts"),
+            None => ConcertCache::new(),
+        };
         Self {
+            musicbrainz: config
+                .musicbrainz
+                .clone()
+                .map(|musicbrainz| MusicBrainz::new(client.clone(), musicbrainz)),
+            setlists: config
+                .setlistfm
+                .clone()
+                .map(|setlistfm| SetlistFm::new(client.clone(), setlistfm)),
             client,
-            cache: Arc::new(ConcertCache::new()),
+            cache: Arc::new(cache),
+            breaker: Arc::new(CircuitBreaker::new()),
+            album_overrides: AlbumOverrides::new(config.state_dir.clone()),
+            notes: ConcertNotes::new(config.state_dir.clone()),
+            renders: RenderJobs::new(
+                std::thread::available_parallelism().map_or(1, |cores| cores.get()),
+            ),
+            config,
         }
     }
 
+    /// The concerts in the rotation, with the details companion apps show
+    pub async fn listings(&self) -> Result<Vec<ConcertListing>, AppError> {
+        let bands = self.get_bands().await?;
+        Ok(sawthat::list_concerts(&bands, &self.config))
+    }
+
+    /// Source art and caption of a concert, resolved with any album override
+    /// and note
+    async fn concert_entry(&self, path: &str) -> Result<ConcertEntry, AppError> {
+        let (band_id, date) = sawthat::parse_item_path(path)
+            .ok_or_else(|| AppError::InvalidPath(format!("invalid path format: {}", path)))?;
+
+        let bands = self.get_bands().await?;
+        let album_override = self.album_overrides.get(path).await;
+        let note = self.notes.get(path).await;
+        sawthat::fetch_concert_entry(
+            &self.client,
+            &self.config.deezer_url,
+            &bands,
+            &band_id,
+            Some(&date),
+            path,
+            &self.cache,
+            album_override.as_deref(),
+            note.as_deref(),
+            self.musicbrainz.as_ref(),
+            self.setlists.as_ref(),
+        )
+        .await
+    }
+
     /// Get bands, fetching from API if not cached
+    ///
+    /// The list is normally kept fresh by
+    /// [`start_bands_refresh`](Self::start_bands_refresh). An expired list is
+    /// served straight away while a background request refreshes it
+    /// (stale-while-revalidate), so only a request before the first fetch
+    /// waits on the API. If the refresh fails (or the circuit is open) the
+    /// expired list keeps being served.
     async fn get_bands(&self) -> Result<Vec<SawThatBand>, AppError> {
         // Check cache first
         if let Some(bands) = self.cache.get_bands().await {
@@ -50,15 +297,132 @@ impl ConcertDataSource {
             return Ok(bands);
         }
 
+        if let Some((bands, age)) = self.cache.get_stale_bands().await {
+            tracing::debug!("Serving bands fetched {}s ago", age.as_secs());
+            self.revalidate_bands();
+            return Ok(bands);
+        }
+
+        if !self.breaker.allow() {
+            return Err(AppError::ExternalApi(
+                "SawThat API circuit open after repeated failures".to_string(),
+            ));
+        }
+
         // Fetch from API
         tracing::info!("Fetching bands from API (cache miss)");
-        let bands = sawthat::fetch_bands(&self.client, SAWTHAT_USER_ID).await?;
+        let bands =
+            match sawthat::fetch_bands(&self.client, &self.config.api_url, SAWTHAT_USER_ID).await {
+                Ok(bands) => bands,
+                Err(e) => {
+                    self.breaker.record_failure();
+                    return Err(e);
+                }
+            };
+        self.breaker.record_success();
 
         // Cache for subsequent requests
         self.cache.set_bands(bands.clone()).await;
 
         Ok(bands)
     }
+
+    /// Refresh the expired bands list in the background, unless already underway
+    fn revalidate_bands(&self) {
+        if !self.cache.start_bands_revalidation() {
+            return;
+        }
+        if !self.breaker.allow() {
+            self.cache.end_bands_revalidation();
+            return;
+        }
+
+        let client = self.client.clone();
+        let api_url = self.config.api_url.clone();
+        let cache = self.cache.clone();
+        let breaker = self.breaker.clone();
+        tokio::spawn(async move {
+            tracing::info!("Revalidating expired bands list");
+            if let Err(e) = refresh_bands(&client, &api_url, &cache, &breaker).await {
+                tracing::warn!("Keeping expired bands list: {}", e);
+            }
+            cache.end_bands_revalidation();
+        });
+    }
+
+    /// Keep the bands list fresh in the background, starting now
+    ///
+    /// The list is fetched right away, then refreshed [`BANDS_REFRESH_LEAD`]
+    /// before each expiry, so devices neither wait on the SawThat API nor get
+    /// an expired list while it's up. Failed refreshes are retried every
+    /// [`BANDS_REFRESH_RETRY`].
+    pub fn start_bands_refresh(self: &Arc<Self>) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let wait = this
+                    .cache
+                    .bands_expire_in()
+                    .await
+                    .map_or(Duration::ZERO, |left| {
+                        left.saturating_sub(BANDS_REFRESH_LEAD)
+                    });
+                tokio::time::sleep(wait).await;
+                if !this.cache.start_bands_revalidation() {
+                    // A request is already revalidating the expired list
+                    tokio::time::sleep(BANDS_REFRESH_RETRY).await;
+                    continue;
+                }
+                let result = this.refresh_bands_ahead().await;
+                this.cache.end_bands_revalidation();
+                if let Err(e) = result {
+                    tracing::warn!(
+                        "Background refresh of the bands list failed, retrying in {}s: {}",
+                        BANDS_REFRESH_RETRY.as_secs(),
+                        e
+                    );
+                    tokio::time::sleep(BANDS_REFRESH_RETRY).await;
+                }
+            }
+        });
+    }
+
+    /// Refresh the bands list ahead of its expiry, unless the circuit is open
+    async fn refresh_bands_ahead(&self) -> Result<(), AppError> {
+        if !self.breaker.allow() {
+            return Err(AppError::ExternalApi(
+                "SawThat API circuit open after repeated failures".to_string(),
+            ));
+        }
+        tracing::info!("Refreshing bands list before it expires");
+        refresh_bands(
+            &self.client,
+            &self.config.api_url,
+            &self.cache,
+            &self.breaker,
+        )
+        .await
+    }
+}
+
+/// Fetch the bands list into `cache`, recording the outcome in `breaker`
+async fn refresh_bands(
+    client: &Client,
+    api_url: &str,
+    cache: &ConcertCache,
+    breaker: &CircuitBreaker,
+) -> Result<(), AppError> {
+    match sawthat::fetch_bands(client, api_url, SAWTHAT_USER_ID).await {
+        Ok(bands) => {
+            breaker.record_success();
+            cache.set_bands(bands).await;
+            Ok(())
+        }
+        Err(e) => {
+            breaker.record_failure();
+            Err(e)
+        }
+    }
 }
 
 #[async_trait]
@@ -71,8 +435,7 @@ impl DataSource for ConcertDataSource {
     async fn fetch_data(&self) -> Result<WidgetData, AppError> {
         let bands = self.get_bands().await?;
 
-        // Convert to widget items (most recent concerts first)
-        let items = sawthat::bands_to_widget_items(&bands, 128);
+        let items = sawthat::bands_to_widget_items(&bands, &self.config);
 
         if items.is_empty() {
             tracing::warn!("No concerts found in SawThat data");
@@ -83,15 +446,34 @@ impl DataSource for ConcertDataSource {
         Ok(items)
     }
 
-    async fn fetch_image(&self, path: &str, orientation: Orientation) -> Result<Vec<u8>, AppError> {
+    async fn stale_age(&self) -> Option<Duration> {
+        // A fresh list replaces the expired one, so one still cached was served stale
+        self.cache.expired_bands_age().await
+    }
+
+    fn revalidating(&self) -> bool {
+        self.cache.bands_revalidating()
+    }
+
+    async fn fetch_image(
+        &self,
+        path: &str,
+        orientation: Orientation,
+        variant: &Variant,
+    ) -> Result<Vec<u8>, AppError> {
         // Path format: YYYY-MM-DD-band-id
         let (band_id, date) = sawthat::parse_item_path(path)
             .ok_or_else(|| AppError::InvalidPath(format!("invalid path format: {}", path)))?;
 
         // Check concert cache for existing rendered image
         if let Some(entry) = self.cache.get_concert(path).await {
-            if let Some(cached_image) = entry.get_image(orientation) {
-                tracing::debug!("Using cached image for {} ({:?})", path, orientation);
+            if let Some(cached_image) = entry.get_image(orientation, &variant.name) {
+                tracing::debug!(
+                    "Using cached image for {} ({:?}, {})",
+                    path,
+                    orientation,
+                    variant.name
+                );
                 return Ok((**cached_image).clone());
             }
         }
@@ -102,37 +484,602 @@ impl DataSource for ConcertDataSource {
             date
         );
 
-        let bands = self.get_bands().await?;
-        let image = sawthat::fetch_band_image(
+        // Concurrent requests for the same image await one render
+        let key = format!("{}/{:?}/{}", path, orientation, variant.name);
+        self.renders
+            .run(&key, || async {
+                let bands = self.get_bands().await?;
+                let album_override = self.album_overrides.get(path).await;
+                let note = self.notes.get(path).await;
+                sawthat::fetch_band_image(
+                    &self.client,
+                    &self.config.deezer_url,
+                    &bands,
+                    &band_id,
+                    Some(&date),
+                    orientation,
+                    path,
+                    &self.cache,
+                    variant,
+                    album_override.as_deref(),
+                    note.as_deref(),
+                    self.musicbrainz.as_ref(),
+                    self.setlists.as_ref(),
+                )
+                .await
+            })
+            .await
+    }
+
+    async fn cached_image(
+        &self,
+        path: &str,
+        orientation: Orientation,
+        variant: &str,
+    ) -> Option<Arc<Vec<u8>>> {
+        let entry = self.cache.get_concert(path).await?;
+        entry.get_image(orientation, variant).cloned()
+    }
+
+    async fn cache_stats(&self) -> Option<CacheStats> {
+        Some(self.cache.stats().await)
+    }
+
+    async fn print_image(
+        &self,
+        path: &str,
+        variant: &Variant,
+        dpi: u32,
+    ) -> Result<Vec<u8>, AppError> {
+        let entry = self.concert_entry(path).await?;
+
+        // Printed at the size of the horizontal card at PRINT_BASE_DPI
+        let (card_width, card_height) = Orientation::Horiz.dimensions(WidgetWidth::Half);
+        let scale = dpi as f32 / image_processing::PRINT_BASE_DPI as f32;
+        let fit = variant.params.fit;
+        tokio::task::spawn_blocking(move || {
+            image_processing::render_print(
+                &entry.source_image,
+                card_width,
+                card_height,
+                &entry.concert_info(),
+                &entry.primary_color,
+                fit,
+                scale,
+                dpi,
+                entry.source_url.as_deref(),
+            )
+        })
+        .await
+        .map_err(|e| AppError::ImageProcessing(format!("Print rendering failed: {}", e)))?
+    }
+
+    async fn cover_selection(&self, path: &str) -> Result<CoverSelection, AppError> {
+        self.concert_entry(path)
+            .await?
+            .cover
+            .ok_or_else(|| AppError::NotFound(format!("cover selection not recorded for {}", path)))
+    }
+
+    async fn override_album(&self, path: &str, title: Option<&str>) -> Result<(), AppError> {
+        sawthat::parse_item_path(path)
+            .ok_or_else(|| AppError::InvalidPath(format!("invalid path format: {}", path)))?;
+        self.album_overrides.set(path, title).await?;
+        // Resolved again with the new album on the next request
+        self.cache.remove_concert(path).await;
+        tracing::info!("Album override for {} set to {:?}", path, title);
+        Ok(())
+    }
+
+    async fn set_note(&self, path: &str, note: Option<&str>) -> Result<(), AppError> {
+        sawthat::parse_item_path(path)
+            .ok_or_else(|| AppError::InvalidPath(format!("invalid path format: {}", path)))?;
+        self.notes.set(path, note).await?;
+        // Captioned with the new note on the next request
+        self.cache.remove_concert(path).await;
+        tracing::info!("Note for {} set to {:?}", path, note);
+        Ok(())
+    }
+}
+
+/// Number of albums shown by the recently played widget
+const SPOTIFY_ITEM_LIMIT: usize = 20;
+
+/// Spotify data source - recently played album covers
+pub struct SpotifyDataSource {
+    client: Client,
+    spotify: SpotifyClient,
+    /// Cover art and rendered images keyed by track ID, optionally persisted to disk
+    cache: Arc<ConcertCache>,
+}
+
+impl SpotifyDataSource {
+    pub fn new(client: Client, config: SpotifyConfig, store: Option<Arc<dyn CacheStore>>) -> Self {
+        let cache = match store {
+            Some(store) => ConcertCache::with_store(store, "spotify"),
+            None => ConcertCache::new(),
+        };
+        Self {
+            spotify: SpotifyClient::new(client.clone(), config),
+            client,
+            cache: Arc::new(cache),
+        }
+    }
+}
+
+#[async_trait]
+impl DataSource for SpotifyDataSource {
+    fn data_cache_policy(&self) -> CachePolicy {
+        // Listening history changes quickly, refresh every 15 minutes
+        CachePolicy::Ttl(900)
+    }
+
+    async fn fetch_data(&self) -> Result<WidgetData, AppError> {
+        let plays = self.spotify.recently_played().await?;
+        let items = spotify::plays_to_widget_items(&plays, SPOTIFY_ITEM_LIMIT);
+
+        if items.is_empty() {
+            tracing::warn!("No recently played tracks found on Spotify");
+        } else {
+            tracing::info!("Generated {} Spotify widget items", items.len());
+        }
+
+        Ok(items)
+    }
+
+    async fn fetch_image(
+        &self,
+        path: &str,
+        orientation: Orientation,
+        variant: &Variant,
+    ) -> Result<Vec<u8>, AppError> {
+        // Path format: Spotify track ID
+        spotify::validate_track_id(path)?;
+
+        spotify::fetch_track_image(
             &self.client,
-            &bands,
-            &band_id,
-            Some(&date),
+            &self.spotify,
+            path,
             orientation,
+            &self.cache,
+            variant,
+        )
+        .await
+    }
+
+    async fn cached_image(
+        &self,
+        path: &str,
+        orientation: Orientation,
+        variant: &str,
+    ) -> Option<Arc<Vec<u8>>> {
+        let entry = self.cache.get_concert(path).await?;
+        entry.get_image(orientation, variant).cloned()
+    }
+
+    async fn cache_stats(&self) -> Option<CacheStats> {
+        Some(self.cache.stats().await)
+    }
+}
+
+/// Number of albums shown by the top albums widget
+const LASTFM_ITEM_LIMIT: usize = 20;
+
+/// How long the fetched top albums are reused
+const LASTFM_ALBUMS_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Last.fm data source - a user's most played albums over a period
+pub struct LastFmDataSource {
+    client: Client,
+    config: LastFmConfig,
+    /// Last fetched top albums and when they were fetched
+    albums: RwLock<Option<(Instant, Vec<LastFmAlbum>)>>,
+    /// Cover art and rendered images keyed by item path, optionally persisted to disk
+    cache: Arc<ConcertCache>,
+}
+
+impl LastFmDataSource {
+    pub fn new(client: Client, config: LastFmConfig, store: Option<Arc<dyn CacheStore>>) -> Self {
+        let cache = match store {
+            Some(store) => ConcertCache::with_store(store, "lastfm"),
+            None => ConcertCache::new(),
+        };
+        Self {
+            client,
+            config,
+            albums: RwLock::new(None),
+            cache: Arc::new(cache),
+        }
+    }
+
+    /// Get the top albums, fetching from the API if the cached list is stale
+    async fn get_albums(&self) -> Result<Vec<LastFmAlbum>, AppError> {
+        if let Some((fetched_at, albums)) = self.albums.read().await.as_ref() {
+            if fetched_at.elapsed() < LASTFM_ALBUMS_TTL {
+                tracing::debug!("Using cached Last.fm albums");
+                return Ok(albums.clone());
+            }
+        }
+
+        let albums =
+            lastfm::fetch_top_albums(&self.client, &self.config, LASTFM_ITEM_LIMIT).await?;
+        *self.albums.write().await = Some((Instant::now(), albums.clone()));
+
+        Ok(albums)
+    }
+}
+
+#[async_trait]
+impl DataSource for LastFmDataSource {
+    fn data_cache_policy(&self) -> CachePolicy {
+        // Play counts move slowly, refresh hourly
+        CachePolicy::Ttl(3600)
+    }
+
+    async fn fetch_data(&self) -> Result<WidgetData, AppError> {
+        let albums = self.get_albums().await?;
+        let items = lastfm::albums_to_widget_items(&albums, LASTFM_ITEM_LIMIT);
+
+        if items.is_empty() {
+            tracing::warn!("No top albums found on Last.fm");
+        } else {
+            tracing::info!("Generated {} Last.fm widget items", items.len());
+        }
+
+        Ok(items)
+    }
+
+    async fn fetch_image(
+        &self,
+        path: &str,
+        orientation: Orientation,
+        variant: &Variant,
+    ) -> Result<Vec<u8>, AppError> {
+        // Path format: album key (hex)-play count
+        let albums = self.get_albums().await?;
+        let album = lastfm::find_album(&albums, path)?;
+
+        lastfm::fetch_album_image(
+            &self.client,
+            album,
+            self.config.period,
             path,
+            orientation,
             &self.cache,
+            variant,
+        )
+        .await
+    }
+
+    async fn cached_image(
+        &self,
+        path: &str,
+        orientation: Orientation,
+        variant: &str,
+    ) -> Option<Arc<Vec<u8>>> {
+        let entry = self.cache.get_concert(path).await?;
+        entry.get_image(orientation, variant).cloned()
+    }
+
+    async fn cache_stats(&self) -> Option<CacheStats> {
+        Some(self.cache.stats().await)
+    }
+}
+
+/// How long fetched calendar events are reused
+const CALENDAR_EVENTS_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Calendar data source - agenda of upcoming events from an ICS feed
+///
+/// Lists a single item per agenda; its path changes whenever the agenda does,
+/// so the frame's caches never serve a stale one.
+pub struct CalendarDataSource {
+    client: Client,
+    config: CalendarConfig,
+    /// Last fetched events and when they were fetched
+    events: RwLock<Option<(Instant, Vec<CalendarEvent>)>>,
+}
+
+impl CalendarDataSource {
+    pub fn new(client: Client, config: CalendarConfig) -> Self {
+        Self {
+            client,
+            config,
+            events: RwLock::new(None),
+        }
+    }
+
+    /// Get the upcoming events, fetching the calendar if the cached copy is stale
+    async fn get_upcoming(&self) -> Result<Vec<CalendarEvent>, AppError> {
+        let now = chrono::Local::now().naive_local();
+
+        if let Some((fetched_at, events)) = self.events.read().await.as_ref() {
+            if fetched_at.elapsed() < CALENDAR_EVENTS_TTL {
+                tracing::debug!("Using cached calendar events");
+                return Ok(calendar::upcoming(events, now, self.config.events));
+            }
+        }
+
+        let events = calendar::fetch_events(&self.client, &self.config, now).await?;
+        let upcoming = calendar::upcoming(&events, now, self.config.events);
+        *self.events.write().await = Some((Instant::now(), events));
+
+        Ok(upcoming)
+    }
+}
+
+#[async_trait]
+impl DataSource for CalendarDataSource {
+    fn data_cache_policy(&self) -> CachePolicy {
+        CachePolicy::Ttl(900)
+    }
+
+    async fn fetch_data(&self) -> Result<WidgetData, AppError> {
+        let events = self.get_upcoming().await?;
+        let today = chrono::Local::now().date_naive();
+
+        tracing::info!("Generated calendar agenda with {} events", events.len());
+
+        Ok(vec![WidgetItem::new(calendar::agenda_path(today, &events))
+            .with_width(self.config.width)
+            .with_title(&self.config.title)])
+    }
+
+    async fn fetch_image(
+        &self,
+        path: &str,
+        orientation: Orientation,
+        variant: &Variant,
+    ) -> Result<Vec<u8>, AppError> {
+        // Path format: YYYY-MM-DD-xxxxxxxx, only the current agenda is served
+        let events = self.get_upcoming().await?;
+        let today = chrono::Local::now().date_naive();
+        if path != calendar::agenda_path(today, &events) {
+            return Err(AppError::NotFound(format!(
+                "agenda {} is out of date",
+                path
+            )));
+        }
+
+        calendar::render_agenda(
+            &events,
+            &self.config.title,
+            today,
+            orientation,
+            self.config.width,
+            &variant.params,
         )
-        .await?;
+    }
+}
+
+/// Number of photos shown by the photos widget
+const PHOTOS_ITEM_LIMIT: usize = 128;
+
+/// How long the photo listing is reused
+const PHOTOS_LIST_TTL: Duration = Duration::from_secs(60 * 60);
 
-        Ok(image)
+/// Photos data source - the user's own photos, without a caption
+///
+/// Rendered photos aren't cached here; the server's image ETags and the
+/// frame's SD card cache keep them from being re-rendered on every wake.
+pub struct PhotosDataSource {
+    client: Client,
+    config: PhotosConfig,
+    /// Last listed photos and when they were listed
+    photos: RwLock<Option<(Instant, Vec<Photo>)>>,
+}
+
+impl PhotosDataSource {
+    pub fn new(client: Client, config: PhotosConfig) -> Self {
+        Self {
+            client,
+            config,
+            photos: RwLock::new(None),
+        }
+    }
+
+    /// Get the photo listing, listing the source again if the cached copy is stale
+    async fn get_photos(&self) -> Result<Vec<Photo>, AppError> {
+        if let Some((listed_at, photos)) = self.photos.read().await.as_ref() {
+            if listed_at.elapsed() < PHOTOS_LIST_TTL {
+                tracing::debug!("Using cached photo listing");
+                return Ok(photos.clone());
+            }
+        }
+
+        let photos = photos::list_photos(&self.client, &self.config).await?;
+        *self.photos.write().await = Some((Instant::now(), photos.clone()));
+
+        Ok(photos)
+    }
+}
+
+#[async_trait]
+impl DataSource for PhotosDataSource {
+    fn data_cache_policy(&self) -> CachePolicy {
+        CachePolicy::Ttl(3600)
+    }
+
+    async fn fetch_data(&self) -> Result<WidgetData, AppError> {
+        let photos = self.get_photos().await?;
+        let items = photos::photos_to_widget_items(&photos, PHOTOS_ITEM_LIMIT);
+
+        if items.is_empty() {
+            tracing::warn!("No photos found");
+        } else {
+            tracing::info!("Generated {} photo widget items", items.len());
+        }
+
+        Ok(items)
+    }
+
+    async fn fetch_image(
+        &self,
+        path: &str,
+        orientation: Orientation,
+        variant: &Variant,
+    ) -> Result<Vec<u8>, AppError> {
+        // Path format: CRC-32 of the photo location (hex)
+        let photos = self.get_photos().await?;
+        let photo = photos::find_photo(&photos, path)?;
+        let data = photos::fetch_photo(&self.client, &self.config, photo).await?;
+
+        let (target_width, target_height) = orientation.dimensions(WidgetWidth::Half);
+        let params = variant.params;
+        let location = photo.location().into_owned();
+        tokio::task::spawn_blocking(move || {
+            image_processing::process_photo(&data, target_width, target_height, &params, &location)
+        })
+        .await
+        .map_err(|e| AppError::ImageProcessing(format!("Photo processing failed: {}", e)))?
     }
 }
 
 /// Registry of available data sources
 pub struct DataSourceRegistry {
     concerts: Arc<ConcertDataSource>,
+    /// Only available when Spotify credentials are configured
+    spotify: Option<Arc<SpotifyDataSource>>,
+    /// Only available when a Last.fm account is configured
+    lastfm: Option<Arc<LastFmDataSource>>,
+    /// Only available when a calendar URL is configured
+    calendar: Option<Arc<CalendarDataSource>>,
+    /// Only available when a photo album is configured
+    photos: Option<Arc<PhotosDataSource>>,
 }
 
 impl DataSourceRegistry {
-    pub fn new(client: Client) -> Self {
+    pub fn new(
+        client: Client,
+        store: Option<Arc<dyn CacheStore>>,
+        concerts: ConcertsConfig,
+        spotify: Option<SpotifyConfig>,
+        lastfm: Option<LastFmConfig>,
+        calendar: Option<CalendarConfig>,
+        photos: Option<PhotosConfig>,
+    ) -> Self {
         Self {
-            concerts: Arc::new(ConcertDataSource::new(client)),
+            photos: photos.map(|config| Arc::new(PhotosDataSource::new(client.clone(), config))),
+            lastfm: lastfm.map(|config| {
+                Arc::new(LastFmDataSource::new(client.clone(), config, store.clone()))
+            }),
+            calendar: calendar
+                .map(|config| Arc::new(CalendarDataSource::new(client.clone(), config))),
+            spotify: spotify.map(|config| {
+                Arc::new(SpotifyDataSource::new(
+                    client.clone(),
+                    config,
+                    store.clone(),
+                ))
+            }),
+            concerts: Arc::new(ConcertDataSource::new(client, concerts, store)),
         }
     }
 
-    pub fn get(&self, name: WidgetName) -> Arc<dyn DataSource> {
+    /// Get the data source for a widget, if it is configured
+    pub fn get(&self, name: WidgetName) -> Result<Arc<dyn DataSource>, AppError> {
         match name {
-            WidgetName::Concerts => self.concerts.clone(),
+            WidgetName::Concerts => Ok(self.concerts.clone()),
+            WidgetName::Spotify => self
+                .spotify
+                .clone()
+                .map(|source| source as Arc<dyn DataSource>)
+                .ok_or_else(|| AppError::NotFound("Spotify widget not configured".to_string())),
+            WidgetName::Lastfm => self
+                .lastfm
+                .clone()
+                .map(|source| source as Arc<dyn DataSource>)
+                .ok_or_else(|| AppError::NotFound("Last.fm widget not configured".to_string())),
+            WidgetName::Calendar => self
+                .calendar
+                .clone()
+                .map(|source| source as Arc<dyn DataSource>)
+                .ok_or_else(|| AppError::NotFound("Calendar widget not configured".to_string())),
+            WidgetName::Photos => self
+                .photos
+                .clone()
+                .map(|source| source as Arc<dyn DataSource>)
+                .ok_or_else(|| AppError::NotFound("Photos widget not configured".to_string())),
+        }
+    }
+
+    /// The concerts data source, for its richer listing
+    pub fn concerts(&self) -> Arc<ConcertDataSource> {
+        self.concerts.clone()
+    }
+
+    /// Data sources of every configured widget
+    pub fn configured(&self) -> Vec<(WidgetName, Arc<dyn DataSource>)> {
+        WidgetName::ALL
+            .into_iter()
+            .filter_map(|name| self.get(name).ok().map(|source| (name, source)))
+            .collect()
+    }
+
+    /// Image cache sizes of every configured widget that caches
+    pub async fn cache_stats(&self) -> BTreeMap<String, CacheStats> {
+        let mut caches = BTreeMap::new();
+        for (name, source) in self.configured() {
+            if let Some(stats) = source.cache_stats().await {
+                caches.insert(name.to_string(), stats);
+            }
         }
+        caches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_render_jobs() {
+        let jobs = RenderJobs::new(4);
+        let renders = AtomicUsize::new(0);
+        let render = |image: &'static [u8]| {
+            let renders = &renders;
+            move || async move {
+                renders.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok(image.to_vec())
+            }
+        };
+
+        // Concurrent requests for one key share a render
+        let (first, second, other) = tokio::join!(
+            jobs.run("a/Horiz/control", render(b"a")),
+            jobs.run("a/Horiz/control", render(b"b")),
+            jobs.run("a/Vert/control", render(b"c")),
+        );
+        assert_eq!(first.unwrap(), b"a");
+        assert_eq!(second.unwrap(), b"a");
+        assert_eq!(other.unwrap(), b"c");
+        assert_eq!(renders.load(Ordering::SeqCst), 2);
+
+        // Errors are shared too
+        let failing = || async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Err(AppError::BandNotFound("a".to_string()))
+        };
+        let (first, second) = tokio::join!(
+            jobs.run("b/Horiz/control", failing),
+            jobs.run("b/Horiz/control", render(b"b")),
+        );
+        assert!(matches!(first, Err(AppError::BandNotFound(_))));
+        assert!(matches!(second, Err(AppError::BandNotFound(_))));
+
+        // A waiting request takes over a job whose request went away
+        let abandoned = tokio::time::timeout(
+            Duration::from_millis(10),
+            jobs.run("c/Horiz/control", render(b"a")),
+        );
+        let (abandoned, waiting) = tokio::join!(abandoned, async {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            jobs.run("c/Horiz/control", render(b"b")).await
+        });
+        assert!(abandoned.is_err());
+        assert_eq!(waiting.unwrap(), b"b");
+        assert!(jobs.in_flight.lock().unwrap().is_empty());
     }
 }
@@ -1,13 +1,16 @@
 //! Deezer API integration
 //!
-//! Fetches artist and album data to find album art matching concert dates.
+//! Fetches artist and album data to find album art matching concert dates or
+//! album titles.
 
+use chrono::NaiveDate;
 use reqwest::Client;
 use serde::Deserialize;
 
 use crate::error::AppError;
 
-const DEEZER_BASE: &str = "https://api.deezer.com";
+/// Deezer API base URL
+pub const DEEZER_BASE: &str = "https://api.deezer.com";
 
 /// Deezer artist search response
 #[derive(Debug, Deserialize)]
@@ -44,10 +47,14 @@ impl DeezerAlbum {
 }
 
 /// Search for an artist on Deezer and return their ID
-pub async fn search_artist(client: &Client, name: &str) -> Result<Option<u64>, AppError> {
+pub async fn search_artist(
+    client: &Client,
+    base: &str,
+    name: &str,
+) -> Result<Option<u64>, AppError> {
     let url = format!(
         "{}/search/artist?q={}&limit=1",
-        DEEZER_BASE,
+        base,
         urlencoding::encode(name)
     );
 
@@ -57,8 +64,12 @@ pub async fn search_artist(client: &Client, name: &str) -> Result<Option<u64>, A
 }
 
 /// Fetch all albums for an artist
-pub async fn fetch_albums(client: &Client, artist_id: u64) -> Result<Vec<DeezerAlbum>, AppError> {
-    let url = format!("{}/artist/{}/albums?limit=100", DEEZER_BASE, artist_id);
+pub async fn fetch_albums(
+    client: &Client,
+    base: &str,
+    artist_id: u64,
+) -> Result<Vec<DeezerAlbum>, AppError> {
+    let url = format!("{}/artist/{}/albums?limit=100", base, artist_id);
 
     let response: AlbumsResponse = client.get(&url).send().await?.json().await?;
 
@@ -117,26 +128,52 @@ pub fn find_closest_album<'a>(
     best_match
 }
 
-/// Fetch the best album art URL for a band at a specific concert date
+/// Find an artist on Deezer and fetch their albums
 ///
-/// Returns the cover art URL for the album closest to the concert date,
-/// or None if no suitable album is found.
-pub async fn fetch_album_art_for_concert(
+/// Returns None if the artist is not found.
+async fn fetch_artist_albums(
     client: &Client,
-    band_name: &str,
-    concert_date: &str,
-) -> Result<Option<String>, AppError> {
-    // Search for the artist
-    let artist_id = match search_artist(client, band_name).await? {
-        Some(id) => id,
+    base: &str,
+    artist_name: &str,
+) -> Result<Option<Vec<DeezerAlbum>>, AppError> {
+    match search_artist(client, base, artist_name).await? {
+        Some(id) => Ok(Some(fetch_albums(client, base, id).await?)),
         None => {
-            tracing::debug!("Artist not found on Deezer: {}", band_name);
-            return Ok(None);
+            tracing::debug!("Artist not found on Deezer: {}", artist_name);
+            Ok(None)
         }
-    };
+    }
+}
 
-    // Fetch their albums
-    let albums = fetch_albums(client, artist_id).await?;
+/// What looking up the album a band was touring at a concert found
+#[derive(Debug, Clone)]
+pub enum AlbumLookup {
+    /// The artist isn't on Deezer
+    ArtistNotFound,
+    /// None of the artist's `albums` was released before the concert
+    NoneBefore { albums: usize },
+    /// The album released closest to (but before) the concert, out of `albums`
+    Found {
+        album: DeezerAlbum,
+        /// Days from its release to the concert
+        days_before: Option<i64>,
+        albums: usize,
+    },
+}
+
+/// Fetch the album a band was touring at a specific concert date
+///
+/// Finds the album released closest to (but before) the concert date, which
+/// may have no cover art.
+pub async fn fetch_album_for_concert(
+    client: &Client,
+    base: &str,
+    band_name: &str,
+    concert_date: &str,
+) -> Result<AlbumLookup, AppError> {
+    let Some(albums) = fetch_artist_albums(client, base, band_name).await? else {
+        return Ok(AlbumLookup::ArtistNotFound);
+    };
 
     // Find the closest album
     let album = match find_closest_album(&albums, concert_date) {
@@ -147,7 +184,9 @@ pub async fn fetch_album_art_for_concert(
                 band_name,
                 concert_date
             );
-            return Ok(None);
+            return Ok(AlbumLookup::NoneBefore {
+                albums: albums.len(),
+            });
         }
     };
 
@@ -158,7 +197,80 @@ pub async fn fetch_album_art_for_concert(
         concert_date
     );
 
-    Ok(album.cover_url().map(String::from))
+    Ok(AlbumLookup::Found {
+        album: album.clone(),
+        days_before: album
+            .release_date
+            .as_deref()
+            .and_then(|release| days_between(release, concert_date)),
+        albums: albums.len(),
+    })
+}
+
+/// Days from a YYYY-MM-DD release date to a DD-MM-YYYY concert date
+pub fn days_between(release_date: &str, concert_date: &str) -> Option<i64> {
+    let release = NaiveDate::parse_from_str(release_date, "%Y-%m-%d").ok()?;
+    let concert = NaiveDate::parse_from_str(concert_date, "%d-%m-%Y").ok()?;
+    Some((concert - release).num_days())
+}
+
+/// Fetch an artist's album by title
+///
+/// Returns None if the artist or album is not found, or it has no cover art.
+pub async fn fetch_album_by_title(
+    client: &Client,
+    base: &str,
+    artist_name: &str,
+    album_title: &str,
+) -> Result<Option<DeezerAlbum>, AppError> {
+    let Some(albums) = fetch_artist_albums(client, base, artist_name).await? else {
+        return Ok(None);
+    };
+
+    let Some(album) = find_album_by_title(&albums, album_title) else {
+        tracing::debug!(
+            "Album not found on Deezer: {} - {}",
+            artist_name,
+            album_title
+        );
+        return Ok(None);
+    };
+
+    Ok(album.cover_url().is_some().then(|| album.clone()))
+}
+
+/// Normalize an album title for comparison (lowercase alphanumerics only)
+fn normalize_title(title: &str) -> String {
+    title
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Find an album by title, ignoring case and punctuation
+///
+/// Falls back to the shortest title starting with the one given, so editions
+/// like "Album (Deluxe)" match "Album" and vice versa.
+pub fn find_album_by_title<'a>(albums: &'a [DeezerAlbum], title: &str) -> Option<&'a DeezerAlbum> {
+    let target = normalize_title(title);
+    if target.is_empty() {
+        return None;
+    }
+
+    albums
+        .iter()
+        .find(|album| normalize_title(&album.title) == target)
+        .or_else(|| {
+            albums
+                .iter()
+                .filter(|album| {
+                    let candidate = normalize_title(&album.title);
+                    !candidate.is_empty()
+                        && (candidate.starts_with(&target) || target.starts_with(&candidate))
+                })
+                .min_by_key(|album| album.title.len())
+        })
 }
 
 #[cfg(test)]
@@ -179,6 +291,13 @@ mod tests {
         assert_eq!(parse_release_date("invalid"), None);
     }
 
+    #[test]
+    fn test_days_between() {
+        assert_eq!(days_between("2020-06-15", "01-03-2021"), Some(259));
+        assert_eq!(days_between("2024-06-15", "15-06-2024"), Some(0));
+        assert_eq!(days_between("2024", "15-06-2024"), None);
+    }
+
     #[test]
     fn test_find_closest_album() {
         let albums = vec![
@@ -218,4 +337,27 @@ mod tests {
         let result = find_closest_album(&albums, "01-01-2017");
         assert!(result.is_none());
     }
+
+    #[test]
+    fn test_find_album_by_title() {
+        let album = |title: &str| DeezerAlbum {
+            title: title.to_string(),
+            release_date: None,
+            cover_xl: None,
+            cover_big: None,
+        };
+        let albums = vec![
+            album("In Rainbows (Disk 2)"),
+            album("OK Computer OKNOTOK 1997 2017"),
+            album("OK Computer"),
+            album("Kid A"),
+        ];
+
+        let find = |title| find_album_by_title(&albums, title).map(|a| a.title.as_str());
+        assert_eq!(find("ok computer"), Some("OK Computer"));
+        assert_eq!(find("In Rainbows"), Some("In Rainbows (Disk 2)"));
+        assert_eq!(find("Kid A (Remastered)"), Some("Kid A"));
+        assert_eq!(find("Amnesiac"), None);
+        assert_eq!(find("..."), None);
+    }
 }
@@ -0,0 +1,1309 @@
+//! Per-device state
+//!
+//! Frames identify themselves with an `X-Device-Id` header. For each device the
+//! server keeps settings overriding the global device config, last-seen
+//! telemetry from its requests, a rolling history of the reports it posts after
+//! each wake, the latest framebuffer screenshot it uploaded so support can
+//! see what a frame is currently showing, the log lines it uploads, an item it
+//! has been asked to show next, and remote-control commands queued for it.
+//! Settings and history are persisted to `$STATE_DIR` when configured; logs
+//! are kept in memory, show requests until the device reports showing the
+//! item, and commands until its next config fetch.
+
+use axum::http::HeaderMap;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::io::Cursor;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use utoipa::ToSchema;
+
+use crate::cache::{unix_now, write_atomic};
+use crate::config::{
+    parse_spki_sha256, DeviceCommand, DeviceConfig, HeldSlot, QuietHours, RefreshMode,
+    MAX_REFRESH_INTERVAL_SECS, MIN_REFRESH_INTERVAL_SECS,
+};
+use crate::error::AppError;
+use crate::image_processing::PanelType;
+use crate::widget::{Orientation, WidgetName};
+
+/// Header identifying the requesting device
+pub const DEVICE_ID_HEADER: &str = "x-device-id";
+
+/// Header reporting the device's battery percentage
+const BATTERY_HEADER: &str = "x-battery-percent";
+
+/// Header reporting the device's firmware version
+const FIRMWARE_HEADER: &str = "x-firmware-version";
+
+/// Panel resolution of uploaded screenshots
+const SCREENSHOT_WIDTH: u32 = 800;
+const SCREENSHOT_HEIGHT: u32 = 480;
+
+/// Settings file in the state directory
+const SETTINGS_FILE: &str = "devices.json";
+
+/// Telemetry history file in the state directory
+const HISTORY_FILE: &str = "telemetry.json";
+
+/// Maximum device ID length
+const MAX_DEVICE_ID_LEN: usize = 64;
+
+/// Maximum reported firmware version length
+const MAX_FIRMWARE_VERSION_LEN: usize = 32;
+
+/// Telemetry reports kept per device (about 10 days at the default interval)
+pub const MAX_TELEMETRY_REPORTS: usize = 1000;
+
+/// Longest plausible panel refresh, to reject garbage durations (10 minutes)
+const MAX_REFRESH_MS: u32 = 10 * 60 * 1000;
+
+/// Longest crash message the firmware keeps
+///
+/// Must match `CRASH_MESSAGE_LEN` in the firmware telemetry.
+const MAX_CRASH_MESSAGE_LEN: usize = 96;
+
+/// Largest log upload, the size of the firmware's log buffer
+///
+/// Must match `LOG_RING_SIZE` in the firmware.
+const MAX_LOG_UPLOAD_BYTES: usize = 16 * 1024;
+
+/// Log text kept per device, dropping the oldest uploads beyond it
+pub const MAX_DEVICE_LOG_BYTES: usize = 256 * 1024;
+
+/// Longest item path the firmware can hold
+pub const MAX_ITEM_PATH_LEN: usize = 48;
+
+/// Commands queued per device, the most the firmware takes in one config
+///
+/// Must match `MAX_COMMANDS` in the firmware config.
+pub const MAX_QUEUED_COMMANDS: usize = 4;
+
+/// A screenshot uploaded by a device
+#[derive(Clone)]
+pub struct Screenshot {
+    /// PNG image data
+    pub png: Arc<Vec<u8>>,
+    /// Unix timestamp (seconds) when the screenshot was received
+    pub received_at: u64,
+}
+
+/// Log lines uploaded by a device after a wake
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogUpload {
+    /// Unix timestamp (seconds) when the upload was received
+    pub received_at: u64,
+    pub text: String,
+}
+
+/// Items sent per widget to devices on the minimal bandwidth profile
+pub const MINIMAL_MAX_ITEMS: usize = 16;
+
+/// How much data a device is sent, for frames on metered connections
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Bandwidth {
+    /// 8-bit PNGs and every widget item
+    #[default]
+    Full,
+    /// 4-bit PNGs at maximum compression
+    Reduced,
+    /// Reduced images and at most 16 items per widget
+    Minimal,
+}
+
+impl Bandwidth {
+    /// Whether PNGs are re-encoded at 4 bits per pixel
+    pub fn compact_images(self) -> bool {
+        self != Bandwidth::Full
+    }
+
+    /// Cap on widget items per response
+    pub fn max_items(self) -> Option<usize> {
+        match self {
+            Bandwidth::Minimal => Some(MINIMAL_MAX_ITEMS),
+            _ => None,
+        }
+    }
+}
+
+/// Per-device overrides of the global device config
+///
+/// Unset fields follow the server-wide settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceSettings {
+    /// Seconds between display refreshes (60-86400)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_interval_secs: Option<u32>,
+    /// Orientation used until the user toggles it on the device
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_orientation: Option<Orientation>,
+    /// Widgets to rotate through, in order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub widgets: Option<Vec<WidgetName>>,
+    /// Payload size profile (server-side only, defaults to full)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<Bandwidth>,
+    /// Display panel images are rendered for (server-side only, defaults to spectra6)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub panel: Option<PanelType>,
+    /// Widget held in the right slot of horizontal mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub held_slot: Option<HeldSlot>,
+    /// Hours the device sleeps through instead of refreshing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quiet_hours: Option<QuietHours>,
+    /// Refreshes between full clears of the panel, 0 to never clear
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clear_every: Option<u16>,
+    /// Panel waveform for routine refreshes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_mode: Option<RefreshMode>,
+    /// Address the device connects to without resolving the server's name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, example = "192.168.1.20")]
+    pub server_ip: Option<Ipv4Addr>,
+    /// SHA-256 of the server key's SubjectPublicKeyInfo (64 hex digits) the
+    /// device pins the server to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spki_sha256: Option<String>,
+}
+
+impl DeviceSettings {
+    /// Whether nothing is overridden
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The global config with these overrides applied
+    pub fn apply(&self, base: &DeviceConfig) -> DeviceConfig {
+        DeviceConfig {
+            refresh_interval_secs: self
+                .refresh_interval_secs
+                .unwrap_or(base.refresh_interval_secs),
+            default_orientation: self.default_orientation.unwrap_or(base.default_orientation),
+            widgets: self.widgets.clone().unwrap_or_else(|| base.widgets.clone()),
+            show_next: None,
+            commands: Vec::new(),
+            held_slot: self.held_slot.or(base.held_slot),
+            quiet_hours: self
+                .quiet_hours
+                .clone()
+                .or_else(|| base.quiet_hours.clone()),
+            clear_every: self.clear_every.or(base.clear_every),
+            refresh_mode: self.refresh_mode.or(base.refresh_mode),
+            server_ip: self.server_ip.or(base.server_ip),
+            spki_sha256: self
+                .spki_sha256
+                .as_deref()
+                .and_then(parse_spki_sha256)
+                .or_else(|| base.spki_sha256.clone()),
+        }
+    }
+
+    fn validate(&self) -> Result<(), AppError> {
+        if let Some(secs) = self.refresh_interval_secs {
+            if !(MIN_REFRESH_INTERVAL_SECS..=MAX_REFRESH_INTERVAL_SECS).contains(&secs) {
+                return Err(AppError::InvalidUpload(format!(
+                    "refresh_interval_secs must be between {} and {}",
+                    MIN_REFRESH_INTERVAL_SECS, MAX_REFRESH_INTERVAL_SECS
+                )));
+            }
+        }
+        if let Some(held) = self.held_slot {
+            if !(MIN_REFRESH_INTERVAL_SECS..=MAX_REFRESH_INTERVAL_SECS).contains(&held.ttl_secs) {
+                return Err(AppError::InvalidUpload(format!(
+                    "held_slot.ttl_secs must be between {} and {}",
+                    MIN_REFRESH_INTERVAL_SECS, MAX_REFRESH_INTERVAL_SECS
+                )));
+            }
+        }
+        if let Some(quiet_hours) = &self.quiet_hours {
+            quiet_hours.validate().map_err(AppError::InvalidUpload)?;
+        }
+        if self
+            .spki_sha256
+            .as_deref()
+            .is_some_and(|hash| parse_spki_sha256(hash).is_none())
+        {
+            return Err(AppError::InvalidUpload(
+                "spki_sha256 must be 64 hex digits".to_string(),
+            ));
+        }
+        if self.widgets.as_ref().is_some_and(Vec::is_empty) {
+            return Err(AppError::InvalidUpload(
+                "widgets must not be empty".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Telemetry from a device's latest request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Telemetry {
+    /// Unix timestamp (seconds) of the latest request
+    pub last_seen_at: u64,
+    /// Requests since the server started
+    pub requests: u64,
+    /// Latest reported battery percentage
+    pub battery_percent: Option<u8>,
+    /// Latest reported firmware version
+    pub firmware_version: Option<String>,
+    /// Latest widget image requested, which may have been prefetched for the
+    /// next refresh rather than shown
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_image: Option<ImageRef>,
+}
+
+/// A widget image as requested by a device
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct ImageRef {
+    pub widget: WidgetName,
+    pub orientation: Orientation,
+    /// Item path, as listed by the widget
+    pub path: String,
+}
+
+/// Why the device woke up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BootReason {
+    /// Refresh timer expired
+    Timer,
+    /// Button pressed
+    Button,
+    /// Power-on or reset
+    PowerOn,
+    /// Anything else
+    Other,
+}
+
+/// Why a device reset unexpectedly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ResetReason {
+    /// The firmware panicked
+    Panic,
+    /// The watchdog restarted a stalled wake
+    Watchdog,
+    /// The supply voltage dropped too low
+    Brownout,
+    /// Any other reset the firmware didn't ask for
+    Other,
+}
+
+/// Unexpected resets a device reports with its next telemetry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CrashReport {
+    /// The latest reset's cause
+    pub reset_reason: ResetReason,
+    /// What the firmware recorded before it, e.g. the panic message and location
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Unexpected resets since the device's last report
+    pub count: u16,
+}
+
+/// Report a device posts after each wake
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TelemetryReport {
+    /// Battery percentage, if the PMIC could be read
+    #[serde(default)]
+    pub battery_percent: Option<u8>,
+    /// Why the device woke up
+    pub boot_reason: BootReason,
+    /// Milliseconds the device's last panel refresh took
+    pub refresh_ms: u32,
+    /// Requested item the device showed this wake
+    #[serde(default)]
+    pub shown_item: Option<String>,
+    /// Unexpected resets since the last report, if any
+    #[serde(default)]
+    pub crash: Option<CrashReport>,
+}
+
+impl TelemetryReport {
+    fn validate(&self) -> Result<(), AppError> {
+        if let Some(path) = &self.shown_item {
+            validate_item_path(path)?;
+        }
+        if self.battery_percent.is_some_and(|percent| percent > 100) {
+            return Err(AppError::InvalidUpload(
+                "battery_percent must be at most 100".to_string(),
+            ));
+        }
+        if self.refresh_ms > MAX_REFRESH_MS {
+            return Err(AppError::InvalidUpload(format!(
+                "refresh_ms must be at most {}",
+                MAX_REFRESH_MS
+            )));
+        }
+        if let Some(crash) = &self.crash {
+            if crash
+                .message
+                .as_ref()
+                .is_some_and(|message| message.len() > MAX_CRASH_MESSAGE_LEN)
+            {
+                return Err(AppError::InvalidUpload(format!(
+                    "crash message must be at most {} bytes",
+                    MAX_CRASH_MESSAGE_LEN
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A stored telemetry report
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TelemetryEntry {
+    /// Unix timestamp (seconds) when the report was received
+    pub received_at: u64,
+    /// Battery percentage, if the PMIC could be read
+    pub battery_percent: Option<u8>,
+    /// Why the device woke up
+    pub boot_reason: BootReason,
+    /// Milliseconds the device's last panel refresh took
+    pub refresh_ms: u32,
+    /// Requested item the device showed this wake
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shown_item: Option<String>,
+    /// Unexpected resets reported with this report
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crash: Option<CrashReport>,
+}
+
+/// Item a device is asked to show on its next refresh
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ShowItem {
+    /// Item path, as listed by one of the device's widgets
+    pub path: String,
+}
+
+/// Command queued for a device
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct QueueCommand {
+    pub command: DeviceCommand,
+}
+
+/// Summary of a device's reported state
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct DeviceSummary {
+    /// Device identifier
+    pub id: String,
+    /// Unix timestamp (seconds) of the latest request
+    pub last_seen_at: Option<u64>,
+    /// Whether the device has its own settings
+    pub has_settings: bool,
+    /// Unix timestamp (seconds) of the latest screenshot
+    pub screenshot_at: Option<u64>,
+    /// Size of the latest screenshot in bytes
+    pub screenshot_bytes: Option<usize>,
+}
+
+/// Everything known about a device
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct DeviceDetails {
+    /// Device identifier
+    pub id: String,
+    /// Config served to the device: the global config with its settings applied
+    pub config: DeviceConfig,
+    /// The device's own settings
+    pub settings: DeviceSettings,
+    /// Telemetry from its latest request, if it has made one since the server started
+    pub telemetry: Option<Telemetry>,
+    /// Number of telemetry reports in its history
+    pub reports: usize,
+    /// Unix timestamp (seconds) of the latest screenshot
+    pub screenshot_at: Option<u64>,
+}
+
+/// State of every known device
+pub struct DeviceStore {
+    screenshots: RwLock<HashMap<String, Screenshot>>,
+    settings: RwLock<HashMap<String, DeviceSettings>>,
+    telemetry: RwLock<HashMap<String, Telemetry>>,
+    history: RwLock<HashMap<String, VecDeque<TelemetryEntry>>>,
+    /// Uploaded log lines, oldest first
+    logs: RwLock<HashMap<String, VecDeque<LogUpload>>>,
+    /// Item each device should show next, until it reports showing it
+    show_next: RwLock<HashMap<String, String>>,
+    /// Commands for each device, until it next fetches its config
+    commands: RwLock<HashMap<String, VecDeque<DeviceCommand>>>,
+    /// Directory settings and history are persisted to
+    state_dir: Option<PathBuf>,
+}
+
+impl DeviceStore {
+    /// In-memory store
+    pub fn new() -> Self {
+        Self {
+            screenshots: RwLock::new(HashMap::new()),
+            settings: RwLock::new(HashMap::new()),
+            telemetry: RwLock::new(HashMap::new()),
+            history: RwLock::new(HashMap::new()),
+            logs: RwLock::new(HashMap::new()),
+            show_next: RwLock::new(HashMap::new()),
+            commands: RwLock::new(HashMap::new()),
+            state_dir: None,
+        }
+    }
+
+    /// Store persisted to `dir`, loading any state saved there
+    pub async fn with_state_dir(dir: PathBuf) -> Self {
+        let settings: HashMap<String, DeviceSettings> = load_json(&dir.join(SETTINGS_FILE)).await;
+        let history: HashMap<String, VecDeque<TelemetryEntry>> =
+            load_json(&dir.join(HISTORY_FILE)).await;
+        tracing::info!(
+            "Loaded settings for {} devices and telemetry for {} from {}",
+            settings.len(),
+            history.len(),
+            dir.display()
+        );
+
+        Self {
+            settings: RwLock::new(settings),
+            history: RwLock::new(history),
+            state_dir: Some(dir),
+            ..Self::new()
+        }
+    }
+
+    /// Record a request from the device identified in `headers`
+    ///
+    /// Returns the device ID, or `None` if the request carried no valid one.
+    pub async fn record_request<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        let id = header(DEVICE_ID_HEADER).filter(|id| validate_device_id(id).is_ok())?;
+        let battery_percent = header(BATTERY_HEADER)
+            .and_then(|value| value.trim().parse::<u8>().ok())
+            .filter(|percent| *percent <= 100);
+        let firmware_version = header(FIRMWARE_HEADER)
+            .map(str::trim)
+            .filter(|version| !version.is_empty() && version.len() <= MAX_FIRMWARE_VERSION_LEN)
+            .map(String::from);
+
+        let mut telemetry = self.telemetry.write().await;
+        let entry = telemetry.entry(id.to_string()).or_insert(Telemetry {
+            last_seen_at: 0,
+            requests: 0,
+            battery_percent: None,
+            firmware_version: None,
+            last_image: None,
+        });
+        entry.last_seen_at = unix_now();
+        entry.requests += 1;
+        if battery_percent.is_some() {
+            entry.battery_percent = battery_percent;
+        }
+        if firmware_version.is_some() {
+            entry.firmware_version = firmware_version;
+        }
+
+        Some(id)
+    }
+
+    /// Record the widget image a device requested, after [`Self::record_request`]
+    pub async fn record_image(&self, id: &str, image: ImageRef) {
+        if let Some(entry) = self.telemetry.write().await.get_mut(id) {
+            entry.last_image = Some(image);
+        }
+    }
+
+    /// Config for a device (the global config if it has no settings), with any
+    /// item it's been asked to show next and the commands queued for it
+    pub async fn config(&self, id: Option<&str>, base: &DeviceConfig) -> DeviceConfig {
+        let Some(id) = id else {
+            return base.clone();
+        };
+        let mut config = match self.settings.read().await.get(id) {
+            Some(settings) => settings.apply(base),
+            None => base.clone(),
+        };
+        config.show_next = self.show_next.read().await.get(id).cloned();
+        config.commands = self.pending_commands(id).await;
+        config
+    }
+
+    /// Config to deliver to a device, taking its queued commands so each runs once
+    pub async fn deliver_config(&self, id: Option<&str>, base: &DeviceConfig) -> DeviceConfig {
+        let mut config = self.config(id, base).await;
+        if let Some(id) = id {
+            config.commands = self
+                .commands
+                .write()
+                .await
+                .remove(id)
+                .map(Vec::from)
+                .unwrap_or_default();
+            if !config.commands.is_empty() {
+                tracing::info!("Delivered {:?} to device {}", config.commands, id);
+            }
+        }
+        config
+    }
+
+    /// Ask a device to show an item on its next refresh, replacing any earlier request
+    pub async fn set_show_next(&self, id: &str, path: String) -> Result<(), AppError> {
+        validate_device_id(id)?;
+        validate_item_path(&path)?;
+        self.show_next.write().await.insert(id.to_string(), path);
+        Ok(())
+    }
+
+    /// Withdraw a device's show request, returning whether it had one
+    pub async fn clear_show_next(&self, id: &str) -> bool {
+        self.show_next.write().await.remove(id).is_some()
+    }
+
+    /// Queue a command for a device's next wake, dropping its oldest once it has
+    /// [`MAX_QUEUED_COMMANDS`]
+    pub async fn queue_command(&self, id: &str, command: DeviceCommand) -> Result<(), AppError> {
+        validate_device_id(id)?;
+        let mut commands = self.commands.write().await;
+        let queue = commands.entry(id.to_string()).or_default();
+        if queue.len() >= MAX_QUEUED_COMMANDS {
+            queue.pop_front();
+        }
+        queue.push_back(command);
+        Ok(())
+    }
+
+    /// Withdraw a device's queued commands, returning whether it had any
+    pub async fn clear_commands(&self, id: &str) -> bool {
+        self.commands.write().await.remove(id).is_some()
+    }
+
+    /// Commands queued for a device, oldest first
+    async fn pending_commands(&self, id: &str) -> Vec<DeviceCommand> {
+        self.commands
+            .read()
+            .await
+            .get(id)
+            .map(|queue| queue.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Bandwidth profile for a device (full if it has none)
+    pub async fn bandwidth(&self, id: Option<&str>) -> Bandwidth {
+        let settings = self.settings.read().await;
+        id.and_then(|id| settings.get(id))
+            .and_then(|settings| settings.bandwidth)
+            .unwrap_or_default()
+    }
+
+    /// Panel type of a device (Spectra 6 if it has none)
+    pub async fn panel(&self, id: Option<&str>) -> PanelType {
+        let settings = self.settings.read().await;
+        id.and_then(|id| settings.get(id))
+            .and_then(|settings| settings.panel)
+            .unwrap_or_default()
+    }
+
+    /// Validate and store a device's settings, replacing any previous ones
+    ///
+    /// Empty settings remove the device's overrides.
+    pub async fn set_settings(&self, id: &str, settings: DeviceSettings) -> Result<(), AppError> {
+        validate_device_id(id)?;
+        settings.validate()?;
+
+        let mut all = self.settings.write().await;
+        if settings.is_empty() {
+            all.remove(id);
+        } else {
+            all.insert(id.to_string(), settings);
+        }
+
+        if let Some(dir) = &self.state_dir {
+            save_json(&dir.join(SETTINGS_FILE), &*all).await;
+        }
+        Ok(())
+    }
+
+    /// Validate and append a telemetry report, dropping the device's oldest
+    /// once it has [`MAX_TELEMETRY_REPORTS`]
+    pub async fn add_report(&self, id: &str, report: TelemetryReport) -> Result<(), AppError> {
+        validate_device_id(id)?;
+        report.validate()?;
+
+        if let Some(shown) = &report.shown_item {
+            let mut show_next = self.show_next.write().await;
+            if show_next.get(id) == Some(shown) {
+                tracing::info!("Device {} showed requested item {}", id, shown);
+                show_next.remove(id);
+            }
+        }
+
+        if let Some(crash) = &report.crash {
+            tracing::warn!(
+                "Device {} reset unexpectedly ({} since its last report): {:?}{}",
+                id,
+                crash.count,
+                crash.reset_reason,
+                crash
+                    .message
+                    .as_deref()
+                    .map(|message| format!(", {}", message))
+                    .unwrap_or_default()
+            );
+        }
+
+        let mut history = self.history.write().await;
+        let entries = history.entry(id.to_string()).or_default();
+        if entries.len() >= MAX_TELEMETRY_REPORTS {
+            entries.pop_front();
+        }
+        entries.push_back(TelemetryEntry {
+            received_at: unix_now(),
+            battery_percent: report.battery_percent,
+            boot_reason: report.boot_reason,
+            refresh_ms: report.refresh_ms,
+            shown_item: report.shown_item,
+            crash: report.crash,
+        });
+
+        if let Some(dir) = &self.state_dir {
+            save_json(&dir.join(HISTORY_FILE), &*history).await;
+        }
+        Ok(())
+    }
+
+    /// A device's telemetry reports, oldest first, `None` if it has posted none
+    pub async fn history(&self, id: &str) -> Option<Vec<TelemetryEntry>> {
+        self.history
+            .read()
+            .await
+            .get(id)
+            .map(|entries| entries.iter().cloned().collect())
+    }
+
+    /// Everything known about a device, `None` if it's unknown
+    pub async fn details(&self, id: &str, base: &DeviceConfig) -> Option<DeviceDetails> {
+        let settings = self.settings.read().await.get(id).cloned();
+        let telemetry = self.telemetry.read().await.get(id).cloned();
+        let screenshot_at = self
+            .screenshots
+            .read()
+            .await
+            .get(id)
+            .map(|shot| shot.received_at);
+        let reports = self.history.read().await.get(id).map_or(0, VecDeque::len);
+        if settings.is_none() && telemetry.is_none() && screenshot_at.is_none() && reports == 0 {
+            return None;
+        }
+
+        let settings = settings.unwrap_or_default();
+        let mut config = settings.apply(base);
+        config.show_next = self.show_next.read().await.get(id).cloned();
+        config.commands = self.pending_commands(id).await;
+        Some(DeviceDetails {
+            id: id.to_string(),
+            config,
+            settings,
+            telemetry,
+            reports,
+            screenshot_at,
+        })
+    }
+
+    /// Validate and store a screenshot, replacing the device's previous one
+    pub async fn set_screenshot(&self, id: &str, png: Vec<u8>) -> Result<(), AppError> {
+        validate_device_id(id)?;
+        validate_screenshot(&png)?;
+
+        let mut screenshots = self.screenshots.write().await;
+        screenshots.insert(
+            id.to_string(),
+            Screenshot {
+                png: Arc::new(png),
+                received_at: unix_now(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Get the latest screenshot for a device
+    pub async fn get_screenshot(&self, id: &str) -> Option<Screenshot> {
+        self.screenshots.read().await.get(id).cloned()
+    }
+
+    /// Store uploaded log lines, dropping the device's oldest uploads once it
+    /// has more than [`MAX_DEVICE_LOG_BYTES`]
+    pub async fn add_logs(&self, id: &str, body: &[u8]) -> Result<(), AppError> {
+        validate_device_id(id)?;
+        if body.is_empty() || body.len() > MAX_LOG_UPLOAD_BYTES {
+            return Err(AppError::InvalidUpload(format!(
+                "Expected 1 to {} bytes of log, got {}",
+                MAX_LOG_UPLOAD_BYTES,
+                body.len()
+            )));
+        }
+
+        let mut logs = self.logs.write().await;
+        let uploads = logs.entry(id.to_string()).or_default();
+        uploads.push_back(LogUpload {
+            received_at: unix_now(),
+            text: String::from_utf8_lossy(body).into_owned(),
+        });
+        let mut total: usize = uploads.iter().map(|upload| upload.text.len()).sum();
+        while total > MAX_DEVICE_LOG_BYTES {
+            let Some(oldest) = uploads.pop_front() else {
+                break;
+            };
+            total -= oldest.text.len();
+        }
+        Ok(())
+    }
+
+    /// A device's uploaded log lines, oldest first, `None` if it has uploaded none
+    pub async fn logs(&self, id: &str) -> Option<Vec<LogUpload>> {
+        self.logs
+            .read()
+            .await
+            .get(id)
+            .map(|uploads| uploads.iter().cloned().collect())
+    }
+
+    /// List all known devices, sorted by ID
+    pub async fn list(&self) -> Vec<DeviceSummary> {
+        let screenshots = self.screenshots.read().await;
+        let settings = self.settings.read().await;
+        let telemetry = self.telemetry.read().await;
+        let history = self.history.read().await;
+
+        let ids: BTreeSet<&String> = screenshots
+            .keys()
+            .chain(settings.keys())
+            .chain(telemetry.keys())
+            .chain(history.keys())
+            .collect();
+        ids.into_iter()
+            .map(|id| {
+                let shot = screenshots.get(id);
+                DeviceSummary {
+                    id: id.clone(),
+                    last_seen_at: telemetry.get(id).map(|t| t.last_seen_at),
+                    has_settings: settings.contains_key(id),
+                    screenshot_at: shot.map(|shot| shot.received_at),
+                    screenshot_bytes: shot.map(|shot| shot.png.len()),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for DeviceStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Read a JSON state file, starting empty if it's missing or invalid
+pub(crate) async fn load_json<T: DeserializeOwned + Default>(path: &Path) -> T {
+    match tokio::fs::read(path).await {
+        Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring invalid {}: {}", path.display(), e);
+            T::default()
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => T::default(),
+        Err(e) => {
+            tracing::warn!("Failed to read {}: {}", path.display(), e);
+            T::default()
+        }
+    }
+}
+
+/// Write a JSON state file, logging failures (the in-memory state stays current)
+pub(crate) async fn save_json<T: Serialize>(path: &Path, value: &T) {
+    let data = serde_json::to_vec_pretty(value).expect("state serializes to JSON");
+    if let Err(e) = write_atomic(path, &data).await {
+        tracing::warn!("Failed to write {}: {}", path.display(), e);
+    }
+}
+
+/// Device IDs are short alphanumeric strings (dashes and underscores allowed)
+pub(crate) fn validate_device_id(id: &str) -> Result<(), AppError> {
+    let valid = !id.is_empty()
+        && id.len() <= MAX_DEVICE_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::InvalidPath(format!("Invalid device ID: {}", id)))
+    }
+}
+
+/// Item paths are short printable ASCII, without quotes or escapes the
+/// firmware's JSON parser would have to handle
+fn validate_item_path(path: &str) -> Result<(), AppError> {
+    let valid = !path.is_empty()
+        && path.len() <= MAX_ITEM_PATH_LEN
+        && path
+            .chars()
+            .all(|c| c.is_ascii_graphic() && c != '"' && c != '\\');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::InvalidPath(format!(
+            "Invalid item path: {}",
+            path
+        )))
+    }
+}
+
+/// Check that the upload is a PNG matching the panel resolution
+fn validate_screenshot(png: &[u8]) -> Result<(), AppError> {
+    let decoder = png::Decoder::new(Cursor::new(png));
+    let reader = decoder
+        .read_info()
+        .map_err(|e| AppError::InvalidUpload(format!("Not a PNG: {}", e)))?;
+
+    let info = reader.info();
+    if (info.width, info.height) != (SCREENSHOT_WIDTH, SCREENSHOT_HEIGHT) {
+        return Err(AppError::InvalidUpload(format!(
+            "Expected {}x{} screenshot, got {}x{}",
+            SCREENSHOT_WIDTH, SCREENSHOT_HEIGHT, info.width, info.height
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_png(width: u32, height: u32) -> Vec<u8> {
+        let mut output = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(Cursor::new(&mut output), width, height);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer
+                .write_image_data(&vec![0u8; (width * height) as usize])
+                .unwrap();
+        }
+        output
+    }
+
+    #[tokio::test]
+    async fn test_store_screenshot() {
+        let store = DeviceStore::new();
+        let png = encode_png(SCREENSHOT_WIDTH, SCREENSHOT_HEIGHT);
+
+        store.set_screenshot("frame-1", png.clone()).await.unwrap();
+
+        let shot = store.get_screenshot("frame-1").await.unwrap();
+        assert_eq!(*shot.png, png);
+        assert!(store.get_screenshot("frame-2").await.is_none());
+
+        let devices = store.list().await;
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].id, "frame-1");
+        assert_eq!(devices[0].screenshot_bytes, Some(png.len()));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_uploads() {
+        let store = DeviceStore::new();
+        let png = encode_png(SCREENSHOT_WIDTH, SCREENSHOT_HEIGHT);
+
+        assert!(store.set_screenshot("../etc", png).await.is_err());
+        assert!(store
+            .set_screenshot("frame-1", b"not a png".to_vec())
+            .await
+            .is_err());
+        assert!(store
+            .set_screenshot("frame-1", encode_png(400, 480))
+            .await
+            .is_err());
+        assert!(store.list().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_record_request() {
+        let store = DeviceStore::new();
+        let mut headers = HeaderMap::new();
+        assert_eq!(store.record_request(&headers).await, None);
+
+        headers.insert(DEVICE_ID_HEADER, "frame-1".parse().unwrap());
+        headers.insert(BATTERY_HEADER, "87".parse().unwrap());
+        headers.insert(FIRMWARE_HEADER, "0.1.0".parse().unwrap());
+        assert_eq!(store.record_request(&headers).await, Some("frame-1"));
+
+        // A bad battery reading keeps the last good one
+        headers.insert(BATTERY_HEADER, "250".parse().unwrap());
+        store.record_request(&headers).await;
+
+        let details = store
+            .details("frame-1", &DeviceConfig::default())
+            .await
+            .unwrap();
+        let telemetry = details.telemetry.unwrap();
+        assert_eq!(telemetry.requests, 2);
+        assert_eq!(telemetry.battery_percent, Some(87));
+        assert_eq!(telemetry.firmware_version.as_deref(), Some("0.1.0"));
+        assert_eq!(details.config, DeviceConfig::default());
+
+        headers.insert(DEVICE_ID_HEADER, "../etc".parse().unwrap());
+        assert_eq!(store.record_request(&headers).await, None);
+        assert!(store
+            .details("frame-2", &DeviceConfig::default())
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_settings() {
+        let dir = std::env::temp_dir().join(format!("devices-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let base = DeviceConfig::default();
+
+        let store = DeviceStore::with_state_dir(dir.clone()).await;
+        let settings = DeviceSettings {
+            default_orientation: Some(Orientation::Vert),
+            widgets: Some(vec![WidgetName::Calendar, WidgetName::Concerts]),
+            held_slot: Some(HeldSlot {
+                widget: WidgetName::Calendar,
+                ttl_secs: 3600,
+            }),
+            clear_every: Some(12),
+            refresh_mode: Some(RefreshMode::Standard),
+            server_ip: Some(Ipv4Addr::new(192, 168, 1, 20)),
+            spki_sha256: Some(
+                "EF4828E2A577251914ECAEE0C9D10C7F2084C96AD2B5A2F2275F0560F7F96C03".to_string(),
+            ),
+            ..DeviceSettings::default()
+        };
+        store
+            .set_settings(
+                "frame-2",
+                DeviceSettings {
+                    bandwidth: Some(Bandwidth::Minimal),
+                    panel: Some(PanelType::Bw),
+                    ..DeviceSettings::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(store.bandwidth(Some("frame-2")).await, Bandwidth::Minimal);
+        assert_eq!(store.panel(Some("frame-2")).await, PanelType::Bw);
+        assert_eq!(store.panel(None).await, PanelType::Spectra6);
+        store
+            .set_settings("frame-1", settings.clone())
+            .await
+            .unwrap();
+
+        let config = store.config(Some("frame-1"), &base).await;
+        assert_eq!(config.default_orientation, Orientation::Vert);
+        assert_eq!(
+            config.widgets,
+            vec![WidgetName::Calendar, WidgetName::Concerts]
+        );
+        assert_eq!(config.refresh_interval_secs, base.refresh_interval_secs);
+        assert_eq!(config.held_slot, settings.held_slot);
+        assert_eq!(config.clear_every, Some(12));
+        assert_eq!(config.refresh_mode, Some(RefreshMode::Standard));
+        assert_eq!(config.server_ip, Some(Ipv4Addr::new(192, 168, 1, 20)));
+        assert_eq!(
+            config.spki_sha256.as_deref(),
+            Some("ef4828e2a577251914ecaee0c9d10c7f2084c96ad2b5a2f2275f0560f7f96c03")
+        );
+        assert_eq!(store.config(Some("frame-2"), &base).await, base);
+        assert_eq!(store.config(None, &base).await, base);
+        assert_eq!(store.bandwidth(Some("frame-1")).await, Bandwidth::Full);
+
+        // Settings survive a restart
+        let reloaded = DeviceStore::with_state_dir(dir.clone()).await;
+        let details = reloaded.details("frame-1", &base).await.unwrap();
+        assert_eq!(details.settings, settings);
+        assert!(reloaded.list().await[0].has_settings);
+
+        // Empty settings reset the device to the global config
+        reloaded
+            .set_settings("frame-1", DeviceSettings::default())
+            .await
+            .unwrap();
+        assert!(reloaded.details("frame-1", &base).await.is_none());
+
+        let too_fast = DeviceSettings {
+            refresh_interval_secs: Some(5),
+            ..DeviceSettings::default()
+        };
+        assert!(store.set_settings("frame-1", too_fast).await.is_err());
+        let no_widgets = DeviceSettings {
+            widgets: Some(Vec::new()),
+            ..DeviceSettings::default()
+        };
+        assert!(store.set_settings("frame-1", no_widgets).await.is_err());
+        let held_briefly = DeviceSettings {
+            held_slot: Some(HeldSlot {
+                widget: WidgetName::Calendar,
+                ttl_secs: 5,
+            }),
+            ..DeviceSettings::default()
+        };
+        assert!(store.set_settings("frame-1", held_briefly).await.is_err());
+        let unpadded = DeviceSettings {
+            quiet_hours: Some(QuietHours {
+                start: "7:00".to_string(),
+                end: "23:00".to_string(),
+                utc_offset_mins: 0,
+            }),
+            ..DeviceSettings::default()
+        };
+        assert!(store.set_settings("frame-1", unpadded).await.is_err());
+        let short_pin = DeviceSettings {
+            spki_sha256: Some("ef4828e2".to_string()),
+            ..DeviceSettings::default()
+        };
+        assert!(store.set_settings("frame-1", short_pin).await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_telemetry_history() {
+        let dir = std::env::temp_dir().join(format!("telemetry-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let report = |battery_percent| TelemetryReport {
+            battery_percent,
+            boot_reason: BootReason::Timer,
+            refresh_ms: 4200,
+            shown_item: None,
+            crash: None,
+        };
+
+        let store = DeviceStore::new();
+        assert!(store.history("frame-1").await.is_none());
+        for percent in (0..=100).rev().cycle().take(MAX_TELEMETRY_REPORTS + 5) {
+            store
+                .add_report("frame-1", report(Some(percent)))
+                .await
+                .unwrap();
+        }
+        assert!(store
+            .add_report("frame-1", report(Some(101)))
+            .await
+            .is_err());
+        assert!(store.add_report("../etc", report(None)).await.is_err());
+
+        // The oldest reports roll off
+        let history = store.history("frame-1").await.unwrap();
+        assert_eq!(history.len(), MAX_TELEMETRY_REPORTS);
+        assert_eq!(history[0].battery_percent, Some(95));
+        assert_eq!(history[0].boot_reason, BootReason::Timer);
+
+        // History survives a restart
+        let store = DeviceStore::with_state_dir(dir.clone()).await;
+        store.add_report("frame-1", report(Some(80))).await.unwrap();
+        store.add_report("frame-1", report(None)).await.unwrap();
+        let reloaded = DeviceStore::with_state_dir(dir.clone()).await;
+        let history = reloaded.history("frame-1").await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].battery_percent, None);
+        assert_eq!(
+            reloaded
+                .details("frame-1", &DeviceConfig::default())
+                .await
+                .unwrap()
+                .reports,
+            2
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_crash_report() {
+        let report = |message: &str| TelemetryReport {
+            battery_percent: None,
+            boot_reason: BootReason::Timer,
+            refresh_ms: 4200,
+            shown_item: None,
+            crash: Some(CrashReport {
+                reset_reason: ResetReason::Panic,
+                message: Some(message.to_string()),
+                count: 2,
+            }),
+        };
+
+        let store = DeviceStore::new();
+        assert!(store
+            .add_report("frame-1", report(&"x".repeat(MAX_CRASH_MESSAGE_LEN + 1)))
+            .await
+            .is_err());
+        store
+            .add_report("frame-1", report("index out of bounds at src/lib.rs:1"))
+            .await
+            .unwrap();
+        let history = store.history("frame-1").await.unwrap();
+        let crash = history[0].crash.as_ref().unwrap();
+        assert_eq!(crash.reset_reason, ResetReason::Panic);
+        assert_eq!(crash.count, 2);
+
+        // Reports from firmware without crash reporting still parse
+        let json = r#"{"battery_percent":80,"boot_reason":"timer","refresh_ms":4200}"#;
+        let parsed: TelemetryReport = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed.crash, None);
+        let json = r#"{"boot_reason":"timer","refresh_ms":1,"crash":{"reset_reason":"watchdog","count":1}}"#;
+        let parsed: TelemetryReport = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed.crash.unwrap().reset_reason, ResetReason::Watchdog);
+    }
+
+    #[tokio::test]
+    async fn test_show_next() {
+        let store = DeviceStore::new();
+        let base = DeviceConfig::default();
+        let path = "2024-01-01-band-id".to_string();
+
+        assert!(store
+            .set_show_next("frame-1", "a\"b".to_string())
+            .await
+            .is_err());
+        assert!(store
+            .set_show_next("frame-1", "x".repeat(49))
+            .await
+            .is_err());
+        store.set_show_next("frame-1", path.clone()).await.unwrap();
+        assert_eq!(
+            store.config(Some("frame-1"), &base).await.show_next,
+            Some(path.clone())
+        );
+        assert_eq!(store.config(Some("frame-2"), &base).await, base);
+
+        // Another item shown doesn't complete the request, the requested one does
+        let report = |shown_item: &str| TelemetryReport {
+            battery_percent: None,
+            boot_reason: BootReason::Timer,
+            refresh_ms: 4200,
+            shown_item: Some(shown_item.to_string()),
+            crash: None,
+        };
+        store
+            .add_report("frame-1", report("2024-02-02-other"))
+            .await
+            .unwrap();
+        assert!(store
+            .config(Some("frame-1"), &base)
+            .await
+            .show_next
+            .is_some());
+        store.add_report("frame-1", report(&path)).await.unwrap();
+        assert_eq!(store.config(Some("frame-1"), &base).await, base);
+        assert_eq!(
+            store.history("frame-1").await.unwrap()[1].shown_item,
+            Some(path.clone())
+        );
+
+        store.set_show_next("frame-1", path).await.unwrap();
+        assert!(store.clear_show_next("frame-1").await);
+        assert!(!store.clear_show_next("frame-1").await);
+    }
+
+    #[tokio::test]
+    async fn test_commands() {
+        let store = DeviceStore::new();
+        let base = DeviceConfig::default();
+
+        assert!(store
+            .queue_command("bad id", DeviceCommand::Next)
+            .await
+            .is_err());
+        for command in [
+            DeviceCommand::Flip,
+            DeviceCommand::Next,
+            DeviceCommand::Next,
+            DeviceCommand::Refresh,
+            DeviceCommand::Next,
+        ] {
+            store.queue_command("frame-1", command).await.unwrap();
+        }
+        // The oldest is dropped once the queue is full
+        let queued = vec![
+            DeviceCommand::Next,
+            DeviceCommand::Next,
+            DeviceCommand::Refresh,
+            DeviceCommand::Next,
+        ];
+        assert_eq!(store.config(Some("frame-1"), &base).await.commands, queued);
+        let report = TelemetryReport {
+            battery_percent: None,
+            boot_reason: BootReason::Timer,
+            refresh_ms: 4200,
+            shown_item: None,
+            crash: None,
+        };
+        store.add_report("frame-1", report).await.unwrap();
+        assert_eq!(
+            store
+                .details("frame-1", &base)
+                .await
+                .unwrap()
+                .config
+                .commands,
+            queued
+        );
+        let json = serde_json::to_value(store.config(Some("frame-1"), &base).await).unwrap();
+        assert_eq!(json["commands"][2], "refresh");
+
+        // Delivered once
+        assert_eq!(
+            store.deliver_config(Some("frame-1"), &base).await.commands,
+            queued
+        );
+        assert_eq!(store.deliver_config(Some("frame-1"), &base).await, base);
+        assert!(!store.clear_commands("frame-1").await);
+
+        store
+            .queue_command("frame-1", DeviceCommand::Flip)
+            .await
+            .unwrap();
+        assert!(store.clear_commands("frame-1").await);
+        assert_eq!(store.config(Some("frame-1"), &base).await, base);
+    }
+    #[tokio::test]
+    async fn test_logs() {
+        let store = DeviceStore::new();
+        assert!(store.logs("frame-1").await.is_none());
+        assert!(store.add_logs("../etc", b"line\n").await.is_err());
+        assert!(store.add_logs("frame-1", b"").await.is_err());
+        assert!(store
+            .add_logs("frame-1", &vec![b'x'; MAX_LOG_UPLOAD_BYTES + 1])
+            .await
+            .is_err());
+
+        store
+            .add_logs("frame-1", b"[   2.500]  WARN - Retrying\n")
+            .await
+            .unwrap();
+        let logs = store.logs("frame-1").await.unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].text, "[   2.500]  WARN - Retrying\n");
+
+        // The oldest uploads go once there's too much
+        let upload = vec![b'x'; MAX_LOG_UPLOAD_BYTES];
+        for _ in 0..MAX_DEVICE_LOG_BYTES / MAX_LOG_UPLOAD_BYTES {
+            store.add_logs("frame-1", &upload).await.unwrap();
+        }
+        let logs = store.logs("frame-1").await.unwrap();
+        assert_eq!(logs.len(), MAX_DEVICE_LOG_BYTES / MAX_LOG_UPLOAD_BYTES);
+        assert!(logs
+            .iter()
+            .all(|log| log.text.len() == MAX_LOG_UPLOAD_BYTES));
+    }
+}
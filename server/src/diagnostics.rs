@@ -0,0 +1,262 @@
+//! Soak diagnostics
+//!
+//! A background task samples the process's resident memory, the size of every
+//! image cache and the reference counts of shared state once an hour, keeping a
+//! day of samples for `GET /metrics`. On a small VPS a leak (say, cache entries
+//! that are never evicted) shows up as steady growth long before it runs out of
+//! memory, so a metric that grew at each of the last few samples is logged as a
+//! warning.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use utoipa::ToSchema;
+
+use crate::cache::CacheStats;
+use crate::datasource::DataSourceRegistry;
+use crate::device::DeviceStore;
+
+/// Time between samples
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Samples kept, a day at the sample interval
+const HISTORY_LEN: usize = 24;
+
+/// Consecutive samples a metric must grow across to be flagged
+const GROWTH_SAMPLES: usize = 6;
+
+/// One sample of the server's memory use
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct DiagnosticsSample {
+    /// Unix timestamp (seconds) the sample was taken at
+    pub at: u64,
+    /// Resident set size in bytes, omitted where `/proc` isn't available
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rss_bytes: Option<u64>,
+    /// Image cache sizes by widget
+    pub caches: BTreeMap<String, CacheStats>,
+    /// Strong reference counts of shared state, including the sampler's own
+    pub arc_refs: BTreeMap<String, usize>,
+}
+
+impl DiagnosticsSample {
+    /// Every metric of the sample by name, e.g. `caches.concerts.entries`
+    fn metrics(&self) -> BTreeMap<String, u64> {
+        let mut metrics = BTreeMap::new();
+        if let Some(rss) = self.rss_bytes {
+            metrics.insert("rss_bytes".to_string(), rss);
+        }
+        for (widget, stats) in &self.caches {
+            for (name, value) in [
+                ("entries", stats.entries),
+                ("expired", stats.expired),
+                ("images", stats.images),
+                ("image_bytes", stats.image_bytes),
+                ("image_refs", stats.image_refs),
+            ] {
+                metrics.insert(format!("caches.{}.{}", widget, name), value as u64);
+            }
+        }
+        for (name, refs) in &self.arc_refs {
+            metrics.insert(format!("arc_refs.{}", name), *refs as u64);
+        }
+        metrics
+    }
+}
+
+/// Recent samples and the metrics growing across them
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DiagnosticsReport {
+    /// Seconds between samples
+    pub interval_secs: u64,
+    /// Samples from the last day, oldest first
+    pub samples: Vec<DiagnosticsSample>,
+    /// Metrics that grew at each of the last samples, a sign of a leak
+    pub growing: Vec<String>,
+}
+
+/// Periodic samples of memory use
+pub struct Diagnostics {
+    samples: RwLock<VecDeque<DiagnosticsSample>>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self {
+            samples: RwLock::new(VecDeque::with_capacity(HISTORY_LEN)),
+        }
+    }
+
+    /// Sample `registry` and `devices` every [`SAMPLE_INTERVAL`] in the background,
+    /// starting now
+    pub fn start(self: &Arc<Self>, registry: Arc<DataSourceRegistry>, devices: Arc<DeviceStore>) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let sample = sample(&registry, &devices).await;
+                this.record(sample).await;
+            }
+        });
+    }
+
+    /// Add a sample, warning about metrics that keep growing
+    pub async fn record(&self, sample: DiagnosticsSample) {
+        tracing::debug!("Diagnostics sample: {:?}", sample);
+        let mut samples = self.samples.write().await;
+        if samples.len() == HISTORY_LEN {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+        for metric in growing(&samples) {
+            tracing::warn!(
+                "{} grew at each of the last {} hourly samples, possible leak",
+                metric,
+                GROWTH_SAMPLES
+            );
+        }
+    }
+
+    /// Recent samples and growing metrics
+    pub async fn report(&self) -> DiagnosticsReport {
+        let samples = self.samples.read().await;
+        DiagnosticsReport {
+            interval_secs: SAMPLE_INTERVAL.as_secs(),
+            growing: growing(&samples),
+            samples: samples.iter().cloned().collect(),
+        }
+    }
+}
+
+impl Default for Diagnostics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Take a sample of the process and its shared state
+async fn sample(
+    registry: &Arc<DataSourceRegistry>,
+    devices: &Arc<DeviceStore>,
+) -> DiagnosticsSample {
+    let caches = registry.cache_stats().await;
+    let arc_refs = BTreeMap::from([
+        ("registry".to_string(), Arc::strong_count(registry)),
+        ("devices".to_string(), Arc::strong_count(devices)),
+    ]);
+
+    DiagnosticsSample {
+        at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        rss_bytes: rss_bytes(),
+        caches,
+        arc_refs,
+    }
+}
+
+/// Resident set size of this process, from `/proc/self/status`
+fn rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    parse_vm_rss(&status)
+}
+
+/// The `VmRSS` line of a `/proc/<pid>/status` file in bytes, e.g. `VmRSS:  5120 kB`
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    let line = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?;
+    let kb = line.trim().strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+    Some(kb * 1024)
+}
+
+/// Metrics of the latest sample that grew at each of the last [`GROWTH_SAMPLES`]
+fn growing(samples: &VecDeque<DiagnosticsSample>) -> Vec<String> {
+    if samples.len() < GROWTH_SAMPLES {
+        return Vec::new();
+    }
+    let recent: Vec<_> = samples
+        .iter()
+        .skip(samples.len() - GROWTH_SAMPLES)
+        .map(DiagnosticsSample::metrics)
+        .collect();
+    recent[GROWTH_SAMPLES - 1]
+        .keys()
+        .filter(|name| {
+            recent
+                .windows(2)
+                .all(|pair| match (pair[0].get(*name), pair[1].get(*name)) {
+                    (Some(before), Some(after)) => after > before,
+                    _ => false,
+                })
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(rss: u64, entries: usize, refs: usize) -> DiagnosticsSample {
+        DiagnosticsSample {
+            rss_bytes: Some(rss),
+            caches: BTreeMap::from([(
+                "concerts".to_string(),
+                CacheStats {
+                    entries,
+                    ..CacheStats::default()
+                },
+            )]),
+            arc_refs: BTreeMap::from([("registry".to_string(), refs)]),
+            ..DiagnosticsSample::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_vm_rss() {
+        let status = "Name:\tserver\nVmPeak:\t  20480 kB\nVmRSS:\t   5120 kB\nThreads:\t4\n";
+        assert_eq!(parse_vm_rss(status), Some(5120 * 1024));
+        assert_eq!(parse_vm_rss("Name:\tserver\n"), None);
+        assert_eq!(parse_vm_rss("VmRSS:\t lots\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_growth_detection() {
+        let diagnostics = Diagnostics::new();
+        // Memory and entries grow every hour, references hold steady
+        for hour in 0..GROWTH_SAMPLES {
+            let report = diagnostics.report().await;
+            assert!(report.growing.is_empty());
+            diagnostics
+                .record(sample(1000 + hour as u64, 10 + hour, 3))
+                .await;
+        }
+        let report = diagnostics.report().await;
+        assert_eq!(
+            report.growing,
+            vec![
+                "caches.concerts.entries".to_string(),
+                "rss_bytes".to_string()
+            ]
+        );
+
+        // A plateau ends the streak
+        diagnostics
+            .record(sample(2000, 10 + GROWTH_SAMPLES - 1, 3))
+            .await;
+        assert_eq!(diagnostics.report().await.growing, vec!["rss_bytes"]);
+
+        // Only a day of samples is kept
+        for _ in 0..HISTORY_LEN {
+            diagnostics.record(sample(2000, 20, 3)).await;
+        }
+        let report = diagnostics.report().await;
+        assert_eq!(report.samples.len(), HISTORY_LEN);
+        assert!(report.growing.is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+//! EPD-native image output
+//!
+//! Converts the 6-color indexed PNGs into the panel's own framebuffer format:
+//! two pixels per byte (left pixel in the high nibble) in EPD color codes, so the
+//! firmware can stream the body straight to the display without decoding,
+//! remapping or rotating it.
+
+use std::io::Cursor;
+
+use crate::error::AppError;
+use crate::widget::Orientation;
+
+/// EPD color code for each palette index (black, white, red, yellow, blue, green)
+///
+/// Must match `COLOR_REMAP` in the firmware framebuffer.
+const COLOR_REMAP: [u8; 6] = [0x00, 0x01, 0x03, 0x02, 0x05, 0x06];
+
+/// Color code used for out-of-palette indices (white)
+const DEFAULT_CODE: u8 = 0x01;
+
+/// A packed EPD image with its dimensions in panel orientation
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpdImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+/// Convert an indexed PNG to packed EPD color codes
+///
+/// Laid out the way the firmware writes decoded PNGs: horizontal images are
+/// rotated 180° (the panel is mounted upside down in landscape), and vertical
+/// (480x800) images are rotated 90° counter-clockwise onto the 800x480 panel.
+pub fn png_to_epd(png: &[u8], orientation: Orientation) -> Result<EpdImage, AppError> {
+    let mut decoder = png::Decoder::new(Cursor::new(png));
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| AppError::ImageProcessing(format!("Failed to read PNG: {}", e)))?;
+
+    let info = reader.info();
+    if info.color_type != png::ColorType::Indexed || info.bit_depth != png::BitDepth::Eight {
+        return Err(AppError::ImageProcessing(
+            "Expected an 8-bit indexed PNG".to_string(),
+        ));
+    }
+    let (src_width, src_height) = (info.width, info.height);
+
+    let mut indices = vec![0; reader.output_buffer_size()];
+    let frame = reader
+        .next_frame(&mut indices)
+        .map_err(|e| AppError::ImageProcessing(format!("Failed to decode PNG: {}", e)))?;
+    let stride = frame.line_size;
+
+    let (width, height) = match orientation {
+        Orientation::Horiz => (src_width, src_height),
+        Orientation::Vert => (src_height, src_width),
+    };
+    if width % 2 != 0 {
+        return Err(AppError::ImageProcessing(format!(
+            "Odd image width {} cannot be packed",
+            width
+        )));
+    }
+
+    // Source pixel for panel pixel (x, y)
+    let index_at = |x: u32, y: u32| -> u8 {
+        let (sx, sy) = match orientation {
+            Orientation::Horiz => (src_width - 1 - x, src_height - 1 - y),
+            Orientation::Vert => (src_width - 1 - y, x),
+        };
+        indices[sy as usize * stride + sx as usize]
+    };
+
+    let mut data = Vec::with_capacity((width / 2 * height) as usize);
+    for y in 0..height {
+        for x in (0..width).step_by(2) {
+            data.push(remap(index_at(x, y)) << 4 | remap(index_at(x + 1, y)));
+        }
+    }
+
+    Ok(EpdImage {
+        width,
+        height,
+        data,
+    })
+}
+
+/// Map a palette index to its EPD color code
+fn remap(index: u8) -> u8 {
+    COLOR_REMAP
+        .get(index as usize)
+        .copied()
+        .unwrap_or(DEFAULT_CODE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_indexed(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(Cursor::new(&mut output), width, height);
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_palette(vec![0; 18]);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(pixels).unwrap();
+        }
+        output
+    }
+
+    #[test]
+    fn test_horizontal_packing() {
+        // Row 0: black, white, yellow, red; row 1: blue, green, invalid, black
+        let png = encode_indexed(4, 2, &[0, 1, 2, 3, 4, 5, 9, 0]);
+        let epd = png_to_epd(&png, Orientation::Horiz).unwrap();
+
+        // Rotated 180°: the last source row, reversed, becomes the top row
+        assert_eq!((epd.width, epd.height), (4, 2));
+        assert_eq!(epd.data, vec![0x01, 0x65, 0x23, 0x10]);
+    }
+
+    #[test]
+    fn test_vertical_rotation() {
+        // 2x4 portrait image, each pixel a distinct color
+        #[rustfmt::skip]
+        let pixels = [
+            0, 1,
+            2, 3,
+            4, 5,
+            1, 0,
+        ];
+        let png = encode_indexed(2, 4, &pixels);
+        let epd = png_to_epd(&png, Orientation::Vert).unwrap();
+
+        // Rotated counter-clockwise: the right column becomes the top row
+        assert_eq!((epd.width, epd.height), (4, 2));
+        assert_eq!(epd.data, vec![0x12, 0x60, 0x03, 0x51]);
+    }
+
+    #[test]
+    fn test_rejects_non_indexed() {
+        let mut output = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(Cursor::new(&mut output), 2, 2);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[0; 4]).unwrap();
+        }
+        assert!(png_to_epd(&output, Orientation::Horiz).is_err());
+    }
+}
@@ -15,6 +15,12 @@ pub enum AppError {
     #[error("Image processing error: {0}")]
     ImageProcessing(String),
 
+    #[error("Invalid upload: {0}")]
+    InvalidUpload(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
     #[error("External API error: {0}")]
     ExternalApi(String),
 
@@ -22,11 +28,31 @@ pub enum AppError {
     HttpClient(#[from] reqwest::Error),
 }
 
+impl AppError {
+    /// A copy for another request sharing this outcome, with an HTTP client
+    /// error kept as its message
+    pub fn to_shared(&self) -> Self {
+        match self {
+            AppError::InvalidPath(message) => AppError::InvalidPath(message.clone()),
+            AppError::BandNotFound(message) => AppError::BandNotFound(message.clone()),
+            AppError::ImageProcessing(message) => AppError::ImageProcessing(message.clone()),
+            AppError::InvalidUpload(message) => AppError::InvalidUpload(message.clone()),
+            AppError::NotFound(message) => AppError::NotFound(message.clone()),
+            AppError::ExternalApi(message) => AppError::ExternalApi(message.clone()),
+            AppError::HttpClient(e) => AppError::ExternalApi(e.to_string()),
+        }
+    }
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let (status, message) = match &self {
-            AppError::BandNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            AppError::InvalidPath(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            AppError::BandNotFound(_) | AppError::NotFound(_) => {
+                (StatusCode::NOT_FOUND, self.to_string())
+            }
+            AppError::InvalidPath(_) | AppError::InvalidUpload(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string())
+            }
             AppError::ImageProcessing(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
             AppError::ExternalApi(_) | AppError::HttpClient(_) => {
                 (StatusCode::BAD_GATEWAY, self.to_string())
@@ -0,0 +1,392 @@
+//! A/B experiments for rendering parameters
+//!
+//! Devices are split into variant buckets by a stable hash of their device ID,
+//! and each bucket renders images with its own parameters (saturation, dither
+//! mode and scan order, fit, caption color). Experiments are configured with environment variables:
+//! - `EXPERIMENT_NAME`: experiment name, reported in headers (default `render`)
+//! - `EXPERIMENT_VARIANTS`: `;`-separated variants as `name[:key=value,...]`,
+//!   with keys `fit`, `saturation`, `dither`, `scan` and `text`, e.g.
+//!   `fs; ordered:dither=ordered; vivid:saturation=2.4`
+//!
+//! Whether users keep a variant is inferred from request timing: an image
+//! requested again well before the refresh interval means the user pressed the
+//! button to skip it, otherwise it was kept on the display until the next wake.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use utoipa::ToSchema;
+
+use crate::config::{parse_name, parse_saturation};
+use crate::image_processing::{DitherMode, PanelType, RenderParams, ScanOrder};
+
+/// Name of the variant served to devices outside an experiment
+pub const DEFAULT_VARIANT: &str = "default";
+
+/// A named set of rendering parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub name: String,
+    pub params: RenderParams,
+}
+
+impl Variant {
+    /// This variant rendered with another dither, cached under its own name
+    pub fn with_dither(&self, dither: DitherMode) -> Variant {
+        if dither == self.params.dither {
+            return self.clone();
+        }
+        Variant {
+            name: format!("{}+{}", self.name, dither.name()),
+            params: RenderParams {
+                dither,
+                ..self.params
+            },
+        }
+    }
+
+    /// This variant rendered for another panel, cached under its own name
+    pub fn with_panel(&self, panel: PanelType) -> Variant {
+        if panel == self.params.panel {
+            return self.clone();
+        }
+        Variant {
+            name: format!("{}+{}", self.name, panel.name()),
+            params: RenderParams {
+                panel,
+                ..self.params
+            },
+        }
+    }
+}
+
+/// An experiment splitting devices across variants
+#[derive(Debug, Clone, PartialEq)]
+pub struct Experiment {
+    pub name: String,
+    pub variants: Vec<Variant>,
+}
+
+impl Experiment {
+    /// Parse a variant list, with unspecified parameters taken from `base`
+    ///
+    /// Returns `None` if any variant is invalid or fewer than two are given.
+    pub fn parse(name: &str, spec: &str, base: RenderParams) -> Option<Self> {
+        let variants = spec
+            .split(';')
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| parse_variant(v, base))
+            .collect::<Option<Vec<_>>>()?;
+
+        let mut names: Vec<&str> = variants.iter().map(|v| v.name.as_str()).collect();
+        names.sort_unstable();
+        names.dedup();
+        if variants.len() < 2 || names.len() != variants.len() {
+            return None;
+        }
+
+        Some(Self {
+            name: name.trim().to_string(),
+            variants,
+        })
+    }
+
+    /// Pick the variant for a device (stable across requests and restarts)
+    pub fn assign(&self, device_id: &str) -> &Variant {
+        let hash = fnv1a(format!("{}/{}", self.name, device_id).as_bytes());
+        &self.variants[hash as usize % self.variants.len()]
+    }
+}
+
+/// Parse `name[:key=value,...]`
+fn parse_variant(spec: &str, base: RenderParams) -> Option<Variant> {
+    let (name, settings) = spec.split_once(':').unwrap_or((spec, ""));
+    let name = name.trim();
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return None;
+    }
+
+    let mut params = base;
+    for setting in settings.split(',').filter(|s| !s.trim().is_empty()) {
+        let (key, value) = setting.split_once('=')?;
+        match key.trim() {
+            "fit" => params.fit = parse_name(value)?,
+            "saturation" => params.saturation = parse_saturation(value)?,
+            "dither" => params.dither = parse_name(value)?,
+            "scan" => params.scan = parse_name(value)?,
+            "text" => params.text_color = parse_name(value)?,
+            _ => return None,
+        }
+    }
+
+    Some(Variant {
+        name: name.to_string(),
+        params,
+    })
+}
+
+/// 32-bit FNV-1a hash
+fn fnv1a(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Aggregated results for one variant
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+pub struct VariantReport {
+    /// Variant name
+    pub name: String,
+    /// Saturation multiplier
+    pub saturation: f32,
+    /// Dithering algorithm
+    #[schema(value_type = String)]
+    pub dither: DitherMode,
+    /// Error diffusion scan order (`serpentine` or `raster`)
+    #[schema(value_type = String)]
+    pub scan: ScanOrder,
+    /// Devices that have requested an image in this variant
+    pub devices: usize,
+    /// Images served
+    pub served: u64,
+    /// Images left on the display until the next scheduled refresh
+    pub kept: u64,
+    /// Images skipped with the button shortly after being shown
+    pub skipped: u64,
+}
+
+/// Current experiment results
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ExperimentReport {
+    /// Experiment name
+    pub name: String,
+    /// Per-variant results, in configuration order
+    pub variants: Vec<VariantReport>,
+}
+
+/// Most recent image served to a device
+struct LastView {
+    variant: String,
+    at: Instant,
+}
+
+/// Variant assignment and result tracking
+pub struct Experiments {
+    experiment: Option<Experiment>,
+    default: Variant,
+    /// Requests sooner than this after the previous one count as a skip
+    skip_window: Duration,
+    views: RwLock<HashMap<String, LastView>>,
+    stats: RwLock<HashMap<String, VariantReport>>,
+}
+
+impl Experiments {
+    pub fn new(experiment: Option<Experiment>, base: RenderParams, skip_window: Duration) -> Self {
+        Self {
+            experiment,
+            default: Variant {
+                name: DEFAULT_VARIANT.to_string(),
+                params: base,
+            },
+            skip_window,
+            views: RwLock::new(HashMap::new()),
+            stats: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Name of the active experiment
+    pub fn name(&self) -> Option<&str> {
+        self.experiment.as_ref().map(|e| e.name.as_str())
+    }
+
+    /// Every variant that may be served
+    pub fn variants(&self) -> Vec<Variant> {
+        match &self.experiment {
+            Some(experiment) => experiment.variants.clone(),
+            None => vec![self.default.clone()],
+        }
+    }
+
+    /// Variant to render for a device, the default outside an experiment
+    pub fn assign(&self, device_id: Option<&str>) -> &Variant {
+        match (&self.experiment, device_id) {
+            (Some(experiment), Some(id)) => experiment.assign(id),
+            _ => &self.default,
+        }
+    }
+
+    /// Record an image served to a device, scoring its previous image as kept or skipped
+    pub async fn record_view(&self, device_id: &str, variant: &Variant) {
+        if self.experiment.is_none() {
+            return;
+        }
+
+        let now = Instant::now();
+        let previous = self.views.write().await.insert(
+            device_id.to_string(),
+            LastView {
+                variant: variant.name.clone(),
+                at: now,
+            },
+        );
+
+        let mut stats = self.stats.write().await;
+        if let Some(previous) = &previous {
+            if let Some(report) = stats.get_mut(&previous.variant) {
+                if now.duration_since(previous.at) < self.skip_window {
+                    report.skipped += 1;
+                } else {
+                    report.kept += 1;
+                }
+            }
+        }
+
+        let report = stats
+            .entry(variant.name.clone())
+            .or_insert_with(|| VariantReport {
+                name: variant.name.clone(),
+                ..Default::default()
+            });
+        report.served += 1;
+        if previous.is_none_or(|p| p.variant != variant.name) {
+            report.devices += 1;
+        }
+    }
+
+    /// Results of the active experiment
+    pub async fn report(&self) -> Option<ExperimentReport> {
+        let experiment = self.experiment.as_ref()?;
+        let stats = self.stats.read().await;
+
+        let variants = experiment
+            .variants
+            .iter()
+            .map(|variant| {
+                let counts = stats.get(&variant.name).cloned().unwrap_or_default();
+                VariantReport {
+                    name: variant.name.clone(),
+                    saturation: variant.params.saturation,
+                    dither: variant.params.dither,
+                    scan: variant.params.scan,
+                    ..counts
+                }
+            })
+            .collect();
+
+        Some(ExperimentReport {
+            name: experiment.name.clone(),
+            variants,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_processing::{FitMode, TextColor};
+
+    fn experiment() -> Experiment {
+        Experiment::parse(
+            "dither",
+            "fs; ordered:dither=ordered, saturation=1.6",
+            RenderParams::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let experiment = experiment();
+        assert_eq!(experiment.variants[0].name, "fs");
+        assert_eq!(experiment.variants[0].params, RenderParams::default());
+        assert_eq!(experiment.variants[1].params.dither, DitherMode::Ordered);
+        assert_eq!(experiment.variants[1].params.saturation, 1.6);
+        assert_eq!(experiment.variants[1].params.fit, FitMode::Cover);
+        assert_eq!(experiment.variants[1].params.scan, ScanOrder::Serpentine);
+        let raster = Experiment::parse(
+            "scan",
+            "serpentine; raster:scan=raster",
+            RenderParams::default(),
+        )
+        .unwrap();
+        assert_eq!(raster.variants[1].params.scan, ScanOrder::Raster);
+        let accent =
+            Experiment::parse("text", "mono; accent:text=accent", RenderParams::default()).unwrap();
+        assert_eq!(accent.variants[1].params.text_color, TextColor::Accent);
+
+        let base = RenderParams::default();
+        assert_eq!(Experiment::parse("x", "only", base), None);
+        assert_eq!(Experiment::parse("x", "a; a", base), None);
+        assert_eq!(Experiment::parse("x", "a; b:saturation=9", base), None);
+        assert_eq!(Experiment::parse("x", "a; b:dither", base), None);
+        assert_eq!(Experiment::parse("x", "a; b c", base), None);
+    }
+
+    #[test]
+    fn test_with_dither() {
+        let variant = experiment().variants[1].clone();
+        assert_eq!(variant.with_dither(DitherMode::Ordered), variant);
+
+        let atkinson = variant.with_dither(DitherMode::Atkinson);
+        assert_eq!(atkinson.name, "ordered+atkinson");
+        assert_eq!(atkinson.params.dither, DitherMode::Atkinson);
+        assert_eq!(atkinson.params.saturation, 1.6);
+
+        assert_eq!(variant.with_panel(PanelType::Spectra6), variant);
+        let gray = atkinson.with_panel(PanelType::Gray4);
+        assert_eq!(gray.name, "ordered+atkinson+gray4");
+        assert_eq!(gray.params.dither, DitherMode::Atkinson);
+    }
+
+    #[test]
+    fn test_assign_is_stable_and_spread() {
+        let experiment = experiment();
+        let assigned: Vec<&str> = (0..100)
+            .map(|i| experiment.assign(&format!("frame-{}", i)).name.as_str())
+            .collect();
+
+        assert_eq!(experiment.assign("frame-7").name, assigned[7]);
+        let ordered = assigned.iter().filter(|&&n| n == "ordered").count();
+        assert!((20..80).contains(&ordered));
+    }
+
+    #[tokio::test]
+    async fn test_record_views() {
+        let experiments = Experiments::new(
+            Some(experiment()),
+            RenderParams::default(),
+            Duration::from_secs(3600),
+        );
+        assert_eq!(experiments.assign(None).name, DEFAULT_VARIANT);
+
+        let variant = experiments.assign(Some("frame-1")).clone();
+        // Three requests in quick succession: two skips
+        for _ in 0..3 {
+            experiments.record_view("frame-1", &variant).await;
+        }
+
+        let report = experiments.report().await.unwrap();
+        let stats = report
+            .variants
+            .iter()
+            .find(|v| v.name == variant.name)
+            .unwrap();
+        assert_eq!(stats.devices, 1);
+        assert_eq!(stats.served, 3);
+        assert_eq!(stats.skipped, 2);
+        assert_eq!(stats.kept, 0);
+    }
+
+    #[tokio::test]
+    async fn test_no_experiment() {
+        let experiments = Experiments::new(None, RenderParams::default(), Duration::ZERO);
+        assert_eq!(experiments.assign(Some("frame-1")).name, DEFAULT_VARIANT);
+        assert_eq!(experiments.variants().len(), 1);
+        assert_eq!(experiments.report().await, None);
+    }
+}
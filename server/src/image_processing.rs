@@ -1,21 +1,46 @@
 //! Image processing for 6-color E Ink display
 //!
 //! Pipeline:
-//! 1. Resize to target dimensions
+//! 1. Resize to target dimensions (center crop, or letterbox over a blurred fill)
 //! 2. Apply exposure/saturation/s-curve adjustments
 //! 3. Extract dominant color from image edges
 //! 4. Compose canvas: image + gradient + solid color text area
-//! 5. Floyd-Steinberg dithering to 6-color palette (OKLab color space)
-//! 6. Render concert info text (black or white based on background)
+//! 5. Floyd-Steinberg or ordered dithering to 6-color palette (OKLab color space)
+//! 6. Render concert info text (black or white, whichever contrasts more with
+//!    the text area, or an accent color with `TextColor::Accent`)
 //! 7. Encode as indexed PNG
+//!
+//! Photos (`process_photo`) fill the whole card: no gradient or text area, so
+//! steps 3, 4 and 6 are skipped.
+//!
+//! Print exports (`render_print`) lay out the same card at a multiple of the
+//! panel size and stop short of the panel: no adjustments or dithering, text is
+//! drawn anti-aliased and the card is encoded as a continuous-tone RGB PNG.
+//!
+//! For black-and-white panels (`PanelType::Bw` and `PanelType::Gray4`), step 5
+//! instead reduces the canvas to lightness and dithers it to 2 or 4 gray levels.
+//!
+//! Per-pixel stages (adjustments, composing, color conversion and the
+//! dithers that don't diffuse error) are spread over rayon's thread pool, and
+//! the letterbox fill and foreground are resized side by side. Error diffusion
+//! itself is sequential, but its palette matching goes through a lookup cube
+//! (see [`crate::palette`]). None of this changes the output.
 
+use crate::alt_text;
 use crate::cache::PrimaryColor;
 use crate::error::AppError;
-use crate::palette::{extract_dominant_color, Oklab, OklabPalette, PNG_PALETTE};
+use crate::palette::{
+    self, extract_dominant_color, Oklab, OklabPalette, PaletteIndex, GRAY_PALETTE, PALETTE,
+    PNG_GRAY_PALETTE, PNG_PALETTE,
+};
 use crate::text::{self, ConcertInfo};
-use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
-use png::{BitDepth, ColorType, Encoder};
+use image::{DynamicImage, GenericImageView, ImageDecoder, Rgb, RgbImage};
+use png::{BitDepth, ColorType, Compression, Encoder};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::io::Cursor;
+use std::sync::OnceLock;
+use utoipa::ToSchema;
 
 /// Height reserved for text info at bottom
 const TEXT_AREA_HEIGHT: u32 = 120;
@@ -23,9 +48,262 @@ const TEXT_AREA_HEIGHT: u32 = 120;
 /// Height of the gradient transition zone
 const GRADIENT_HEIGHT: u32 = 80;
 
+/// Resolution at which a print is the size of the panel card (one panel pixel
+/// per print dot): at 300 dpi, the 400x480 card is rendered at 2400x2880
+pub const PRINT_BASE_DPI: u32 = 50;
+
+/// Version of the rendering pipeline recorded in every rendered PNG; bump it
+/// whenever a change alters how the same source renders
+pub const PIPELINE_VERSION: u32 = 1;
+
+/// PNG text keywords of the provenance chunks (`Creation Time` is a registered
+/// PNG keyword, the others are specific to this server)
+const PIPELINE_KEYWORD: &str = "Pipeline Version";
+const SOURCE_KEYWORD: &str = "Source Hash";
+const COLOR_KEYWORD: &str = "Dominant Color";
+const DITHER_KEYWORD: &str = "Dither";
+const CREATION_TIME_KEYWORD: &str = "Creation Time";
+
+/// In auto fit mode, letterbox when center crop would keep less than this fraction of the source
+const AUTO_LETTERBOX_MIN_COVERAGE: f32 = 0.75;
+
+/// Downscale factor used to blur the letterbox background
+const LETTERBOX_BLUR_FACTOR: u32 = 24;
+
+/// How strongly the letterbox background is tinted toward the dominant color
+const LETTERBOX_TINT: f32 = 0.45;
+
+/// Strength of the ordered dither threshold, in OKLab lightness units
+const ORDERED_DITHER_SPREAD: f32 = 0.5;
+
+/// 8x8 Bayer threshold matrix for ordered dithering
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// How the source image is fitted into the image area
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FitMode {
+    /// Scale to fill the area and center crop
+    #[default]
+    Cover,
+    /// Letterbox only when center crop would discard too much of the source
+    Auto,
+    /// Always letterbox over a blurred, tinted enlargement of the source
+    Letterbox,
+}
+
+impl FitMode {
+    /// Whether to letterbox a source of the given size into the target area
+    fn letterbox(
+        &self,
+        src_width: u32,
+        src_height: u32,
+        target_width: u32,
+        target_height: u32,
+    ) -> bool {
+        match self {
+            FitMode::Cover => false,
+            FitMode::Letterbox => true,
+            FitMode::Auto => {
+                let scale_x = target_width as f32 / src_width as f32;
+                let scale_y = target_height as f32 / src_height as f32;
+                // Fraction of the source that survives a center crop
+                let coverage = scale_x.min(scale_y) / scale_x.max(scale_y);
+                coverage < AUTO_LETTERBOX_MIN_COVERAGE
+            }
+        }
+    }
+}
+
+/// Dithering algorithm used to map the canvas to the palette
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DitherMode {
+    /// Floyd-Steinberg error diffusion
+    #[default]
+    #[serde(rename = "fs")]
+    FloydSteinberg,
+    /// Atkinson error diffusion (diffuses 3/4 of the error, keeping more contrast)
+    Atkinson,
+    /// Jarvis-Judice-Ninke error diffusion (wider kernel, smoother gradients)
+    #[serde(rename = "jjn", alias = "jarvis")]
+    JarvisJudiceNinke,
+    /// 8x8 Bayer ordered dithering (regular pattern, no error bleeding)
+    Ordered,
+    /// Nearest palette color, no dithering
+    None,
+}
+
+impl DitherMode {
+    /// Name as used in configuration and query parameters
+    pub fn name(self) -> &'static str {
+        match self {
+            DitherMode::FloydSteinberg => "fs",
+            DitherMode::Atkinson => "atkinson",
+            DitherMode::JarvisJudiceNinke => "jjn",
+            DitherMode::Ordered => "ordered",
+            DitherMode::None => "none",
+        }
+    }
+}
+
+/// Order pixels are visited in by error diffusion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanOrder {
+    /// Alternate direction every row, so error doesn't always flow the same way
+    /// (avoids "worm" artifacts in flat gradients)
+    #[default]
+    Serpentine,
+    /// Left to right on every row
+    Raster,
+}
+
+/// Color of concert card captions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextColor {
+    /// Black or white, whichever contrasts more with the text area
+    #[default]
+    Mono,
+    /// The panel's most contrasting accent color (red, yellow, blue or green)
+    /// when it's legible, otherwise black or white
+    Accent,
+}
+
+/// Display panel an image is rendered for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PanelType {
+    /// 6-color Spectra 6 panel
+    #[default]
+    Spectra6,
+    /// Black-and-white panel
+    Bw,
+    /// 4-level grayscale panel
+    Gray4,
+}
+
+impl PanelType {
+    /// Name as used in configuration and variant names
+    pub fn name(self) -> &'static str {
+        match self {
+            PanelType::Spectra6 => "spectra6",
+            PanelType::Bw => "bw",
+            PanelType::Gray4 => "gray4",
+        }
+    }
+
+    /// Palette indices of the panel's gray levels, or `None` for color panels
+    fn gray_levels(self) -> Option<&'static [u8]> {
+        match self {
+            PanelType::Spectra6 => None,
+            PanelType::Bw => Some(&[0, 1]),
+            PanelType::Gray4 => Some(&[0, 2, 3, 1]),
+        }
+    }
+
+    /// PNG palette of images for the panel
+    ///
+    /// Black-and-white images only use the first two colors, so they share the
+    /// 6-color palette and decode the same on color firmware.
+    fn png_palette(self) -> &'static [u8] {
+        match self {
+            PanelType::Gray4 => &PNG_GRAY_PALETTE,
+            _ => &PNG_PALETTE,
+        }
+    }
+}
+
+/// Error diffusion kernel: (dx, dy, weight) offsets from the current pixel,
+/// for a left-to-right scan (mirrored on right-to-left rows)
+type DiffusionKernel = [(i32, u32, f32)];
+
+/// Floyd-Steinberg kernel:
+///       *  7/16
+/// 3/16 5/16 1/16
+const FLOYD_STEINBERG: [(i32, u32, f32); 4] = [
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+
+/// Atkinson kernel, 1/8 each (the remaining 2/8 of the error is dropped):
+///      *  1  1
+///   1  1  1
+///      1
+const ATKINSON: [(i32, u32, f32); 6] = [
+    (1, 0, 1.0 / 8.0),
+    (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0),
+    (0, 1, 1.0 / 8.0),
+    (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+
+/// Jarvis-Judice-Ninke kernel, in 48ths:
+///         *  7  5
+///   3  5  7  5  3
+///   1  3  5  3  1
+const JARVIS_JUDICE_NINKE: [(i32, u32, f32); 12] = [
+    (1, 0, 7.0 / 48.0),
+    (2, 0, 5.0 / 48.0),
+    (-2, 1, 3.0 / 48.0),
+    (-1, 1, 5.0 / 48.0),
+    (0, 1, 7.0 / 48.0),
+    (1, 1, 5.0 / 48.0),
+    (2, 1, 3.0 / 48.0),
+    (-2, 2, 1.0 / 48.0),
+    (-1, 2, 3.0 / 48.0),
+    (0, 2, 5.0 / 48.0),
+    (1, 2, 3.0 / 48.0),
+    (2, 2, 1.0 / 48.0),
+];
+
+/// Tunable rendering parameters
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RenderParams {
+    /// How cover art is fitted into the card
+    pub fit: FitMode,
+    /// Saturation multiplier applied before dithering
+    pub saturation: f32,
+    /// Dithering algorithm
+    pub dither: DitherMode,
+    /// Error diffusion scan order
+    pub scan: ScanOrder,
+    /// Panel the image is rendered for
+    pub panel: PanelType,
+    /// Caption color
+    pub text_color: TextColor,
+}
+
+impl Default for RenderParams {
+    fn default() -> Self {
+        Self {
+            fit: FitMode::default(),
+            saturation: SATURATION,
+            dither: DitherMode::default(),
+            scan: ScanOrder::default(),
+            panel: PanelType::default(),
+            text_color: TextColor::default(),
+        }
+    }
+}
+
 // Image adjustment parameters (aitjcize/esp32-photoframe style)
 const EXPOSURE: f32 = 0.8;
-const SATURATION: f32 = 2.0;
+/// Default saturation multiplier
+pub const SATURATION: f32 = 2.0;
 const SCURVE_STRENGTH: f32 = 1.0;
 const SCURVE_SHADOW_BOOST: f32 = 0.0;
 const SCURVE_HIGHLIGHT_COMPRESS: f32 = 2.0;
@@ -54,7 +332,7 @@ fn apply_scurve(normalized: f32) -> f32 {
 }
 
 /// Apply saturation adjustment using HSL color space
-fn apply_saturation(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+fn apply_saturation(r: u8, g: u8, b: u8, saturation: f32) -> (u8, u8, u8) {
     // Convert RGB to HSL
     let r_norm = r as f32 / 255.0;
     let g_norm = g as f32 / 255.0;
@@ -89,7 +367,7 @@ fn apply_saturation(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
     };
 
     // Apply saturation multiplier
-    let new_s = (s * SATURATION).clamp(0.0, 1.0);
+    let new_s = (s * saturation).clamp(0.0, 1.0);
 
     // Convert HSL back to RGB
     let c = (1.0 - (2.0 * l - 1.0).abs()) * new_s;
@@ -117,26 +395,31 @@ fn apply_saturation(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
     )
 }
 
+/// S-curve tone mapping of every channel value
+fn scurve_table() -> &'static [u8; 256] {
+    static SCURVE: OnceLock<[u8; 256]> = OnceLock::new();
+    SCURVE.get_or_init(|| {
+        std::array::from_fn(|v| (apply_scurve(v as f32 / 255.0) * 255.0).clamp(0.0, 255.0) as u8)
+    })
+}
+
 /// Apply all image adjustments (exposure, saturation, s-curve) to an RGB image
-fn apply_adjustments(img: &mut RgbImage) {
-    for pixel in img.pixels_mut() {
+fn apply_adjustments(img: &mut RgbImage, saturation: f32) {
+    let scurve = scurve_table();
+    img.par_chunks_exact_mut(3).for_each(|pixel| {
         // 1. Exposure adjustment
         let r = apply_exposure(pixel[0]);
         let g = apply_exposure(pixel[1]);
         let b = apply_exposure(pixel[2]);
 
         // 2. Saturation adjustment (HSL-based)
-        let (r, g, b) = apply_saturation(r, g, b);
+        let (r, g, b) = apply_saturation(r, g, b, saturation);
 
         // 3. S-curve tone mapping (per channel)
-        let r = (apply_scurve(r as f32 / 255.0) * 255.0).clamp(0.0, 255.0) as u8;
-        let g = (apply_scurve(g as f32 / 255.0) * 255.0).clamp(0.0, 255.0) as u8;
-        let b = (apply_scurve(b as f32 / 255.0) * 255.0).clamp(0.0, 255.0) as u8;
-
-        pixel[0] = r;
-        pixel[1] = g;
-        pixel[2] = b;
-    }
+        pixel[0] = scurve[r as usize];
+        pixel[1] = scurve[g as usize];
+        pixel[2] = scurve[b as usize];
+    });
 }
 
 /// Process a source image for the e-paper display
@@ -153,7 +436,7 @@ pub fn extract_primary_color(image_data: &[u8]) -> Result<PrimaryColor, AppError
 
     // Apply filters first so color extraction matches the final processed image
     let mut rgb_img = img.to_rgb8();
-    apply_adjustments(&mut rgb_img);
+    apply_adjustments(&mut rgb_img, SATURATION);
 
     let dominant = extract_dominant_color(&rgb_img);
 
@@ -161,19 +444,21 @@ pub fn extract_primary_color(image_data: &[u8]) -> Result<PrimaryColor, AppError
         r: dominant.r,
         g: dominant.g,
         b: dominant.b,
-        is_light: dominant.is_light,
     })
 }
 
 /// Process image with pre-extracted primary color
 ///
-/// Use this when the color has already been extracted and cached.
+/// Use this when the color has already been extracted and cached. `source_url`
+/// is where `image_data` came from, if known, recorded in the PNG's provenance.
 pub fn process_image_with_color(
     image_data: &[u8],
     target_width: u32,
     target_height: u32,
     concert_info: Option<&ConcertInfo>,
     color: &PrimaryColor,
+    params: &RenderParams,
+    source_url: Option<&str>,
 ) -> Result<Vec<u8>, AppError> {
     // Decode source image
     let img = image::load_from_memory(image_data)
@@ -184,31 +469,22 @@ pub fn process_image_with_color(
         color.r,
         color.g,
         color.b,
-        color.is_light
+        color.is_light()
     );
 
-    // Calculate image area (leave room for text)
-    let image_area_height = target_height - TEXT_AREA_HEIGHT;
-
-    // 2. Resize to cover image area (fill width, center crop height)
-    let mut resized = resize_cover(&img, target_width, image_area_height);
-
-    // 3. Apply image adjustments (exposure, saturation, s-curve)
-    apply_adjustments(&mut resized);
-
-    // 4. Compose full RGB canvas with gradient
-    let canvas = compose_canvas_with_gradient(
-        &resized,
+    // 2-4. Resize, adjust and compose the full RGB canvas with gradient
+    let (canvas, image_area_height) = compose_card(
+        &img,
         target_width,
         target_height,
-        image_area_height,
-        color.r,
-        color.g,
-        color.b,
+        color,
+        params.fit,
+        Some(params.saturation),
+        1.0,
     );
 
-    // 5. Apply Floyd-Steinberg dithering to entire canvas
-    let mut indexed = floyd_steinberg_dither(&canvas);
+    // 5. Dither the entire canvas to the panel's palette
+    let mut indexed = dither_for_panel(&canvas, params);
 
     // 6. Render concert info text
     if let Some(info) = concert_info {
@@ -217,12 +493,204 @@ pub fn process_image_with_color(
             target_width,
             info,
             image_area_height,
-            color.is_light,
+            caption_index(color, params),
         );
     }
 
-    // 7. Encode as indexed PNG
-    encode_indexed_png(&indexed, target_width, target_height)
+    // 7. Encode as indexed PNG, described for screen readers
+    let description = concert_info.map(|info| alt_text::concert_alt_text(info, Some(color)));
+    let metadata = PngMetadata {
+        description: description.as_deref(),
+        source_url,
+        dominant_color: Some(*color),
+        dither: Some(params.dither),
+    };
+    encode_panel_png(
+        &indexed,
+        target_width,
+        target_height,
+        params.panel,
+        &metadata,
+    )
+}
+
+/// Render a concert card for printing, `scale` times the size of the panel card
+///
+/// Same layout and typography as [`process_image_with_color`], but continuous
+/// tone: the cover art keeps its own colors (no e-paper adjustments), nothing is
+/// dithered and the caption is anti-aliased. The PNG records `dpi` so it prints
+/// at the intended size.
+#[allow(clippy::too_many_arguments)]
+pub fn render_print(
+    image_data: &[u8],
+    card_width: u32,
+    card_height: u32,
+    concert_info: &ConcertInfo,
+    color: &PrimaryColor,
+    fit: FitMode,
+    scale: f32,
+    dpi: u32,
+    source_url: Option<&str>,
+) -> Result<Vec<u8>, AppError> {
+    let img = image::load_from_memory(image_data)
+        .map_err(|e| AppError::ImageProcessing(format!("Failed to decode image: {}", e)))?;
+
+    let width = (card_width as f32 * scale).round() as u32;
+    let height = (card_height as f32 * scale).round() as u32;
+    tracing::info!("Rendering {}x{} print at {} dpi", width, height, dpi);
+
+    let (mut canvas, image_area_height) =
+        compose_card(&img, width, height, color, fit, None, scale);
+    text::render_concert_info_rgb(
+        &mut canvas,
+        concert_info,
+        image_area_height,
+        color.is_light(),
+        scale,
+    );
+
+    let metadata = PngMetadata {
+        source_url,
+        dominant_color: Some(*color),
+        ..PngMetadata::default()
+    };
+    write_rgb_png(&canvas, dpi, &metadata.text_chunks())
+}
+
+/// Palette index to caption a card whose text area is `color` with
+///
+/// Gray panels have no accents, so they're always captioned in black or white.
+fn caption_index(color: &PrimaryColor, params: &RenderParams) -> u8 {
+    let index = match (params.text_color, params.panel.gray_levels()) {
+        (TextColor::Accent, None) => palette::accent_text_index(color.rgb(), &PALETTE),
+        _ if color.is_light() => PaletteIndex::Black as usize,
+        _ => PaletteIndex::White as usize,
+    };
+    index as u8
+}
+
+/// Resize the cover art into the card's image area and compose it with the
+/// gradient and text area, at `scale` times the panel layout
+///
+/// With `saturation`, the e-paper adjustments (exposure, saturation, s-curve)
+/// are applied to the art. Returns the canvas and the top of the text area.
+fn compose_card(
+    img: &DynamicImage,
+    target_width: u32,
+    target_height: u32,
+    color: &PrimaryColor,
+    fit: FitMode,
+    saturation: Option<f32>,
+    scale: f32,
+) -> (RgbImage, u32) {
+    // Calculate image area (leave room for text)
+    let text_area_height = (TEXT_AREA_HEIGHT as f32 * scale).round() as u32;
+    let image_area_height = target_height - text_area_height;
+
+    // Resize to the image area (center crop, or letterbox for extreme aspect ratios)
+    let (src_width, src_height) = img.dimensions();
+    let mut resized = if fit.letterbox(src_width, src_height, target_width, image_area_height) {
+        tracing::debug!("Letterboxing {}x{} source", src_width, src_height);
+        resize_letterbox(
+            img,
+            target_width,
+            image_area_height,
+            Rgb([color.r, color.g, color.b]),
+        )
+    } else {
+        resize_cover(img, target_width, image_area_height)
+    };
+
+    // Apply image adjustments (exposure, saturation, s-curve)
+    if let Some(saturation) = saturation {
+        apply_adjustments(&mut resized, saturation);
+    }
+
+    // Compose full RGB canvas with gradient
+    let canvas = compose_canvas_with_gradient(
+        &resized,
+        target_width,
+        target_height,
+        image_area_height,
+        (GRADIENT_HEIGHT as f32 * scale).round() as u32,
+        Rgb([color.r, color.g, color.b]),
+    );
+
+    (canvas, image_area_height)
+}
+
+/// Process a photo into a full-card indexed PNG, without a caption
+///
+/// The EXIF orientation is applied first, so phone photos come out upright.
+/// `source` is the photo's location, recorded in the PNG's provenance.
+pub fn process_photo(
+    image_data: &[u8],
+    target_width: u32,
+    target_height: u32,
+    params: &RenderParams,
+    source: &str,
+) -> Result<Vec<u8>, AppError> {
+    let decode_error =
+        |e: image::ImageError| AppError::ImageProcessing(format!("Failed to decode image: {}", e));
+    let mut decoder = image::ImageReader::new(Cursor::new(image_data))
+        .with_guessed_format()
+        .map_err(|e| AppError::ImageProcessing(format!("Failed to read image: {}", e)))?
+        .into_decoder()
+        .map_err(decode_error)?;
+    let orientation = decoder.orientation().map_err(decode_error)?;
+    let mut img = DynamicImage::from_decoder(decoder).map_err(decode_error)?;
+    img.apply_orientation(orientation);
+
+    let (src_width, src_height) = img.dimensions();
+    let mut resized = if params
+        .fit
+        .letterbox(src_width, src_height, target_width, target_height)
+    {
+        let dominant = extract_dominant_color(&img.thumbnail(64, 64).to_rgb8());
+        resize_letterbox(
+            &img,
+            target_width,
+            target_height,
+            Rgb([dominant.r, dominant.g, dominant.b]),
+        )
+    } else {
+        resize_cover(&img, target_width, target_height)
+    };
+
+    apply_adjustments(&mut resized, params.saturation);
+    let indexed = dither_for_panel(&resized, params);
+
+    let metadata = PngMetadata {
+        source_url: Some(source),
+        dither: Some(params.dither),
+        ..PngMetadata::default()
+    };
+    encode_panel_png(
+        &indexed,
+        target_width,
+        target_height,
+        params.panel,
+        &metadata,
+    )
+}
+
+/// Dither an RGB canvas to palette indices
+pub fn dither(canvas: &RgbImage, mode: DitherMode, scan: ScanOrder) -> Vec<u8> {
+    match mode {
+        DitherMode::FloydSteinberg => error_diffusion_dither(canvas, &FLOYD_STEINBERG, scan),
+        DitherMode::Atkinson => error_diffusion_dither(canvas, &ATKINSON, scan),
+        DitherMode::JarvisJudiceNinke => error_diffusion_dither(canvas, &JARVIS_JUDICE_NINKE, scan),
+        DitherMode::Ordered => ordered_dither(canvas),
+        DitherMode::None => nearest_color(canvas),
+    }
+}
+
+/// Dither an RGB canvas to palette indices for the panel `params` renders for
+pub fn dither_for_panel(canvas: &RgbImage, params: &RenderParams) -> Vec<u8> {
+    match params.panel.gray_levels() {
+        Some(levels) => gray_dither(canvas, levels, params.dither, params.scan),
+        None => dither(canvas, params.dither, params.scan),
+    }
 }
 
 /// Compose the full canvas with image, gradient transition, and solid background
@@ -231,45 +699,50 @@ fn compose_canvas_with_gradient(
     target_width: u32,
     target_height: u32,
     image_area_height: u32,
-    bg_r: u8,
-    bg_g: u8,
-    bg_b: u8,
+    gradient_height: u32,
+    background: Rgb<u8>,
 ) -> RgbImage {
     let mut canvas = RgbImage::new(target_width, target_height);
+    let Rgb([bg_r, bg_g, bg_b]) = background;
 
     // Gradient starts this many pixels above the image/text boundary
-    let gradient_start = image_area_height.saturating_sub(GRADIENT_HEIGHT);
-
-    for y in 0..target_height {
-        for x in 0..target_width {
-            let pixel = if y < gradient_start {
-                // Pure image region
-                *img.get_pixel(x, y)
-            } else if y < image_area_height {
-                // Gradient transition zone (blend image into background color)
-                let img_pixel = img.get_pixel(x, y);
-                let t = (y - gradient_start) as f32 / GRADIENT_HEIGHT as f32;
-                // Smooth easing (ease-in-out)
-                let t = t * t * (3.0 - 2.0 * t);
-                Rgb([
-                    lerp_u8(img_pixel[0], bg_r, t),
-                    lerp_u8(img_pixel[1], bg_g, t),
-                    lerp_u8(img_pixel[2], bg_b, t),
-                ])
-            } else {
-                // Solid background for text area
-                Rgb([bg_r, bg_g, bg_b])
-            };
-            canvas.put_pixel(x, y, pixel);
-        }
-    }
+    let gradient_start = image_area_height.saturating_sub(gradient_height);
+
+    let row_len = target_width as usize * 3;
+    canvas
+        .par_chunks_exact_mut(row_len)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let y = y as u32;
+            for (x, out) in (0..target_width).zip(row.chunks_exact_mut(3)) {
+                let pixel = if y < gradient_start {
+                    // Pure image region
+                    *img.get_pixel(x, y)
+                } else if y < image_area_height {
+                    // Gradient transition zone (blend image into background color)
+                    let img_pixel = img.get_pixel(x, y);
+                    let t = (y - gradient_start) as f32 / gradient_height as f32;
+                    // Smooth easing (ease-in-out)
+                    let t = t * t * (3.0 - 2.0 * t);
+                    Rgb([
+                        lerp_u8(img_pixel[0], bg_r, t),
+                        lerp_u8(img_pixel[1], bg_g, t),
+                        lerp_u8(img_pixel[2], bg_b, t),
+                    ])
+                } else {
+                    // Solid background for text area
+                    Rgb([bg_r, bg_g, bg_b])
+                };
+                out.copy_from_slice(&pixel.0);
+            }
+        });
 
     canvas
 }
 
 /// Linear interpolation between two u8 values
 #[inline]
-fn lerp_u8(a: u8, b: u8, t: f32) -> u8 {
+pub(crate) fn lerp_u8(a: u8, b: u8, t: f32) -> u8 {
     let a = a as f32;
     let b = b as f32;
     (a + (b - a) * t).clamp(0.0, 255.0) as u8
@@ -315,9 +788,60 @@ fn resize_cover(img: &DynamicImage, target_width: u32, target_height: u32) -> Rg
     output
 }
 
-/// Apply Floyd-Steinberg dithering to convert RGB image to 6-color indexed
+/// Resize image to fit inside the target area, centered over a blurred fill
+///
+/// The fill is a cover-scaled copy of the image, blurred by a downscale/upscale
+/// round trip and tinted toward `tint`, so the letterbox bars carry the cover's colors.
+fn resize_letterbox(
+    img: &DynamicImage,
+    target_width: u32,
+    target_height: u32,
+    tint: Rgb<u8>,
+) -> RgbImage {
+    let (src_width, src_height) = img.dimensions();
+
+    // Foreground: scale to fit (smaller of the two scales) and center
+    let scale_x = target_width as f32 / src_width as f32;
+    let scale_y = target_height as f32 / src_height as f32;
+    let scale = scale_x.min(scale_y);
+    let new_width = ((src_width as f32 * scale).round() as u32).clamp(1, target_width);
+    let new_height = ((src_height as f32 * scale).round() as u32).clamp(1, target_height);
+
+    // Both are resized from the source, so side by side
+    let (mut output, foreground) = rayon::join(
+        || {
+            // Blurred background: shrink the cover fill, then scale it back up
+            let small = img.resize_exact(
+                (target_width / LETTERBOX_BLUR_FACTOR).max(1),
+                (target_height / LETTERBOX_BLUR_FACTOR).max(1),
+                image::imageops::FilterType::Triangle,
+            );
+            resize_cover(&small, target_width, target_height)
+        },
+        || {
+            img.resize_exact(new_width, new_height, image::imageops::FilterType::Triangle)
+                .to_rgb8()
+        },
+    );
+    output.par_chunks_exact_mut(3).for_each(|pixel| {
+        for c in 0..3 {
+            pixel[c] = lerp_u8(pixel[c], tint[c], LETTERBOX_TINT);
+        }
+    });
+
+    image::imageops::replace(
+        &mut output,
+        &foreground,
+        ((target_width - new_width) / 2) as i64,
+        ((target_height - new_height) / 2) as i64,
+    );
+
+    output
+}
+
+/// Apply error diffusion dithering to convert RGB image to 6-color indexed
 /// All operations performed in OKLab color space for perceptual uniformity
-fn floyd_steinberg_dither(img: &RgbImage) -> Vec<u8> {
+fn error_diffusion_dither(img: &RgbImage, kernel: &DiffusionKernel, scan: ScanOrder) -> Vec<u8> {
     let (width, height) = img.dimensions();
     let mut indexed = vec![0u8; (width * height) as usize];
 
@@ -325,13 +849,15 @@ fn floyd_steinberg_dither(img: &RgbImage) -> Vec<u8> {
     let oklab_palette = OklabPalette::new();
 
     // Working buffer in OKLab space for error accumulation
-    let mut buffer: Vec<Oklab> = img
-        .pixels()
-        .map(|p| Oklab::from_rgb(p[0], p[1], p[2]))
-        .collect();
+    let mut buffer = oklab_pixels(img);
 
     for y in 0..height {
-        for x in 0..width {
+        // Serpentine scans run odd rows right to left, with the kernel mirrored
+        let reverse = scan == ScanOrder::Serpentine && y % 2 == 1;
+        let direction = if reverse { -1 } else { 1 };
+
+        for step in 0..width {
+            let x = if reverse { width - 1 - step } else { step };
             let idx = (y * width + x) as usize;
 
             // Get current pixel in OKLab space
@@ -349,40 +875,118 @@ fn floyd_steinberg_dither(img: &RgbImage) -> Vec<u8> {
             let err_a = current.a - target.a;
             let err_b = current.b - target.b;
 
-            // Floyd-Steinberg error diffusion pattern:
-            //       *  7/16
-            // 3/16 5/16 1/16
-
-            // Right: 7/16
-            if x + 1 < width {
-                let right_idx = idx + 1;
-                buffer[right_idx].l += err_l * (7.0 / 16.0);
-                buffer[right_idx].a += err_a * (7.0 / 16.0);
-                buffer[right_idx].b += err_b * (7.0 / 16.0);
+            // Spread it over the neighbours the kernel reaches, within the image
+            for &(dx, dy, weight) in kernel {
+                let nx = x as i32 + dx * direction;
+                let ny = y + dy;
+                if nx < 0 || nx >= width as i32 || ny >= height {
+                    continue;
+                }
+                let neighbour = &mut buffer[(ny * width + nx as u32) as usize];
+                neighbour.l += err_l * weight;
+                neighbour.a += err_a * weight;
+                neighbour.b += err_b * weight;
             }
+        }
+    }
 
-            if y + 1 < height {
-                // Bottom-left: 3/16
-                if x > 0 {
-                    let bl_idx = idx + width as usize - 1;
-                    buffer[bl_idx].l += err_l * (3.0 / 16.0);
-                    buffer[bl_idx].a += err_a * (3.0 / 16.0);
-                    buffer[bl_idx].b += err_b * (3.0 / 16.0);
-                }
+    indexed
+}
+
+/// Apply 8x8 Bayer ordered dithering to convert RGB image to 6-color indexed
+///
+/// Each pixel's lightness is offset by its threshold before the nearest OKLab
+/// palette match, giving a stable regular pattern instead of diffused error.
+fn ordered_dither(img: &RgbImage) -> Vec<u8> {
+    let oklab_palette = OklabPalette::new();
+    let width = img.width() as usize;
+
+    img.par_chunks_exact(3)
+        .enumerate()
+        .map(|(i, p)| {
+            let (x, y) = (i % width, i / width);
+            let threshold = BAYER_8X8[y % 8][x % 8] as f32;
+            let offset = ((threshold + 0.5) / 64.0 - 0.5) * ORDERED_DITHER_SPREAD;
+            let mut color = Oklab::from_rgb(p[0], p[1], p[2]);
+            color.l += offset;
+            oklab_palette.nearest(&color).as_u8()
+        })
+        .collect()
+}
+
+/// Every pixel of an RGB image in OKLab space
+fn oklab_pixels(img: &RgbImage) -> Vec<Oklab> {
+    img.par_chunks_exact(3)
+        .map(|p| Oklab::from_rgb(p[0], p[1], p[2]))
+        .collect()
+}
+
+/// Dither an RGB canvas to the gray levels at palette indices `levels`
+///
+/// Pixels are reduced to OKLab lightness and quantized against the lightness
+/// of each level, with the same algorithms as color dithering. Ordered
+/// dithering spreads its threshold over one step between levels.
+fn gray_dither(img: &RgbImage, levels: &[u8], mode: DitherMode, scan: ScanOrder) -> Vec<u8> {
+    let (width, height) = img.dimensions();
+    let lightness: Vec<f32> = levels
+        .iter()
+        .map(|&index| GRAY_PALETTE[index as usize].to_oklab().l)
+        .collect();
+    let nearest = |l: f32| -> usize {
+        (0..lightness.len())
+            .min_by(|&a, &b| {
+                (l - lightness[a])
+                    .abs()
+                    .total_cmp(&(l - lightness[b]).abs())
+            })
+            .unwrap_or(0)
+    };
+
+    let mut buffer: Vec<f32> = img
+        .par_chunks_exact(3)
+        .map(|p| Oklab::from_rgb(p[0], p[1], p[2]).l)
+        .collect();
 
-                // Bottom: 5/16
-                let bottom_idx = idx + width as usize;
-                buffer[bottom_idx].l += err_l * (5.0 / 16.0);
-                buffer[bottom_idx].a += err_a * (5.0 / 16.0);
-                buffer[bottom_idx].b += err_b * (5.0 / 16.0);
-
-                // Bottom-right: 1/16
-                if x + 1 < width {
-                    let br_idx = idx + width as usize + 1;
-                    buffer[br_idx].l += err_l * (1.0 / 16.0);
-                    buffer[br_idx].a += err_a * (1.0 / 16.0);
-                    buffer[br_idx].b += err_b * (1.0 / 16.0);
+    let kernel: &DiffusionKernel = match mode {
+        DitherMode::FloydSteinberg => &FLOYD_STEINBERG,
+        DitherMode::Atkinson => &ATKINSON,
+        DitherMode::JarvisJudiceNinke => &JARVIS_JUDICE_NINKE,
+        DitherMode::Ordered => {
+            let step = (lightness[lightness.len() - 1] - lightness[0]) / (levels.len() - 1) as f32;
+            return buffer
+                .into_par_iter()
+                .enumerate()
+                .map(|(i, l)| {
+                    let (x, y) = (i % width as usize, i / width as usize);
+                    let threshold = BAYER_8X8[y % 8][x % 8] as f32;
+                    let offset = ((threshold + 0.5) / 64.0 - 0.5) * step;
+                    levels[nearest(l + offset)]
+                })
+                .collect();
+        }
+        DitherMode::None => return buffer.into_par_iter().map(|l| levels[nearest(l)]).collect(),
+    };
+
+    let mut indexed = vec![0u8; (width * height) as usize];
+    for y in 0..height {
+        let reverse = scan == ScanOrder::Serpentine && y % 2 == 1;
+        let direction = if reverse { -1 } else { 1 };
+
+        for step in 0..width {
+            let x = if reverse { width - 1 - step } else { step };
+            let idx = (y * width + x) as usize;
+
+            let level = nearest(buffer[idx]);
+            indexed[idx] = levels[level];
+            let err = buffer[idx] - lightness[level];
+
+            for &(dx, dy, weight) in kernel {
+                let nx = x as i32 + dx * direction;
+                let ny = y + dy;
+                if nx < 0 || nx >= width as i32 || ny >= height {
+                    continue;
                 }
+                buffer[(ny * width + nx as u32) as usize] += err * weight;
             }
         }
     }
@@ -390,33 +994,816 @@ fn floyd_steinberg_dither(img: &RgbImage) -> Vec<u8> {
     indexed
 }
 
-/// Encode indexed pixel data as PNG with 6-color palette
-fn encode_indexed_png(indexed: &[u8], width: u32, height: u32) -> Result<Vec<u8>, AppError> {
+/// Map each pixel to its nearest palette color, without dithering
+fn nearest_color(img: &RgbImage) -> Vec<u8> {
+    let oklab_palette = OklabPalette::new();
+
+    img.par_chunks_exact(3)
+        .map(|p| {
+            oklab_palette
+                .nearest(&Oklab::from_rgb(p[0], p[1], p[2]))
+                .as_u8()
+        })
+        .collect()
+}
+
+/// Text chunks recording what an image shows and how it was rendered
+///
+/// Every rendered PNG carries the pipeline version and render time, so a cached
+/// or archived copy can be traced back to how it was produced.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct PngMetadata<'a> {
+    /// Alt text (see [`alt_text`])
+    pub description: Option<&'a str>,
+    /// Where the source image came from, recorded as a CRC-32
+    pub source_url: Option<&'a str>,
+    /// Dominant color of the source image
+    pub dominant_color: Option<PrimaryColor>,
+    /// Dithering algorithm, for dithered images
+    pub dither: Option<DitherMode>,
+}
+
+impl PngMetadata<'_> {
+    /// Keyword and text of each chunk, stamped with the current time
+    fn text_chunks(&self) -> Vec<(String, String)> {
+        let mut chunks = vec![(PIPELINE_KEYWORD.to_string(), PIPELINE_VERSION.to_string())];
+        if let Some(description) = self.description {
+            chunks.push((alt_text::PNG_KEYWORD.to_string(), description.to_string()));
+        }
+        if let Some(url) = self.source_url {
+            chunks.push((
+                SOURCE_KEYWORD.to_string(),
+                format!("{:08x}", crc32fast::hash(url.as_bytes())),
+            ));
+        }
+        if let Some(color) = self.dominant_color {
+            chunks.push((
+                COLOR_KEYWORD.to_string(),
+                format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b),
+            ));
+        }
+        if let Some(dither) = self.dither {
+            chunks.push((DITHER_KEYWORD.to_string(), dither.name().to_string()));
+        }
+        chunks.push((
+            CREATION_TIME_KEYWORD.to_string(),
+            chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        ));
+        chunks
+    }
+}
+
+/// Encode indexed pixel data as PNG for a panel
+///
+/// On black-and-white panels, colors drawn after dithering (accent rules and
+/// text) are replaced by the gray level closest in lightness. `metadata` is
+/// embedded as text chunks, including any alt text (see [`alt_text`]).
+pub(crate) fn encode_panel_png(
+    indexed: &[u8],
+    width: u32,
+    height: u32,
+    panel: PanelType,
+    metadata: &PngMetadata,
+) -> Result<Vec<u8>, AppError> {
+    let mapped;
+    let indexed = match panel.gray_levels() {
+        Some(levels) => {
+            mapped = indexed
+                .iter()
+                .map(|&index| nearest_gray(index, levels))
+                .collect::<Vec<_>>();
+            &mapped
+        }
+        None => indexed,
+    };
+    write_indexed_png(
+        indexed,
+        width,
+        height,
+        BitDepth::Eight,
+        Compression::Default,
+        panel.png_palette(),
+        &metadata.text_chunks(),
+    )
+}
+
+/// The gray level at palette indices `levels` closest in lightness to a color index
+fn nearest_gray(index: u8, levels: &[u8]) -> u8 {
+    if levels.contains(&index) {
+        return index;
+    }
+    let l = PALETTE
+        .get(index as usize)
+        .map_or(1.0, |color| color.to_oklab().l);
+    levels
+        .iter()
+        .copied()
+        .min_by(|&a, &b| {
+            let distance = |level: u8| (GRAY_PALETTE[level as usize].to_oklab().l - l).abs();
+            distance(a).total_cmp(&distance(b))
+        })
+        .unwrap_or(index)
+}
+
+/// A decoded 8-bit indexed PNG
+struct IndexedPng {
+    width: u32,
+    height: u32,
+    palette: Vec<u8>,
+    /// Keyword and text of each text chunk
+    text: Vec<(String, String)>,
+    /// Palette indices, `width` per row
+    indices: Vec<u8>,
+}
+
+/// Decode an 8-bit indexed PNG as rendered by [`encode_panel_png`]
+fn read_indexed_png(png_data: &[u8]) -> Result<IndexedPng, AppError> {
+    let mut decoder = png::Decoder::new(Cursor::new(png_data));
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| AppError::ImageProcessing(format!("Failed to read PNG: {}", e)))?;
+
+    let info = reader.info();
+    if info.color_type != ColorType::Indexed || info.bit_depth != BitDepth::Eight {
+        return Err(AppError::ImageProcessing(
+            "Expected an 8-bit indexed PNG".to_string(),
+        ));
+    }
+    let (width, height) = (info.width, info.height);
+    let palette = info
+        .palette
+        .as_ref()
+        .map_or_else(|| PNG_PALETTE.to_vec(), |palette| palette.to_vec());
+    let text = png_info_text(info);
+
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader
+        .next_frame(&mut buf)
+        .map_err(|e| AppError::ImageProcessing(format!("Failed to decode PNG: {}", e)))?;
+    let indices = buf
+        .chunks(frame.line_size)
+        .take(height as usize)
+        .flat_map(|row| &row[..width as usize])
+        .copied()
+        .collect();
+
+    Ok(IndexedPng {
+        width,
+        height,
+        palette,
+        text,
+        indices,
+    })
+}
+
+/// Re-encode an 8-bit indexed PNG at 4 bits per pixel with maximum compression
+///
+/// Lossless, since the palette only has six colors (four on grayscale panels):
+/// the payload shrinks at the cost of encoding time, for devices on metered
+/// connections. Embedded text (alt text and provenance) is kept.
+pub(crate) fn compact_png(png_data: &[u8]) -> Result<Vec<u8>, AppError> {
+    let image = read_indexed_png(png_data)?;
+
+    // Two pixels per byte, left pixel in the high nibble, rows padded to a byte
+    let packed: Vec<u8> = image
+        .indices
+        .chunks(image.width as usize)
+        .flat_map(|row| {
+            row.chunks(2)
+                .map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0))
+        })
+        .collect();
+
+    write_indexed_png(
+        &packed,
+        image.width,
+        image.height,
+        BitDepth::Four,
+        Compression::Best,
+        &image.palette,
+        &image.text,
+    )
+}
+
+/// Rotate an 8-bit indexed PNG 90° counter-clockwise
+///
+/// A vertical (480x800) image comes out in the panel's native 800x480 geometry,
+/// laid out the way the firmware would otherwise rotate it pixel by pixel, so
+/// the device can write its rows straight to the framebuffer. The palette and
+/// embedded text are kept.
+pub(crate) fn rotate_png_ccw90(png_data: &[u8]) -> Result<Vec<u8>, AppError> {
+    let image = read_indexed_png(png_data)?;
+    let (width, height) = (image.height, image.width);
+
+    // Output pixel (x, y) comes from source pixel (source width - 1 - y, x)
+    let mut rotated = Vec::with_capacity(image.indices.len());
+    for y in 0..height {
+        let source_x = (image.width - 1 - y) as usize;
+        for x in 0..width {
+            rotated.push(image.indices[x as usize * image.width as usize + source_x]);
+        }
+    }
+
+    write_indexed_png(
+        &rotated,
+        width,
+        height,
+        BitDepth::Eight,
+        Compression::Default,
+        &image.palette,
+        &image.text,
+    )
+}
+
+/// Text chunks embedded by [`encode_panel_png`], in file order per chunk type
+fn png_info_text(info: &png::Info) -> Vec<(String, String)> {
+    let latin1 = info
+        .uncompressed_latin1_text
+        .iter()
+        .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()));
+    let utf8 = info.utf8_text.iter().filter_map(|chunk| {
+        let text = chunk.get_text().ok()?;
+        Some((chunk.keyword.clone(), text))
+    });
+    latin1.chain(utf8).collect()
+}
+
+/// Downscale a rendered PNG to fit within `max_size` pixels on its longest side
+///
+/// The thumbnail is a plain RGB PNG; it's for browsers, not the panel.
+pub(crate) fn thumbnail_png(png_data: &[u8], max_size: u32) -> Result<Vec<u8>, AppError> {
+    let img = image::load_from_memory_with_format(png_data, image::ImageFormat::Png)
+        .map_err(|e| AppError::ImageProcessing(format!("Failed to decode PNG: {}", e)))?;
+    let thumbnail = DynamicImage::ImageRgb8(img.thumbnail(max_size, max_size).to_rgb8());
+
+    let mut output = Vec::new();
+    thumbnail
+        .write_to(&mut Cursor::new(&mut output), image::ImageFormat::Png)
+        .map_err(|e| AppError::ImageProcessing(format!("PNG write error: {}", e)))?;
+    Ok(output)
+}
+
+/// Largest brightness shift of the simulated e-paper grain
+const PREVIEW_GRAIN: i16 = 12;
+
+/// Render a panel PNG the way the panel shows it, for browsers
+///
+/// Each pixel is drawn in its palette color (the measured panel colors, so it
+/// reads as on the wall rather than as the saturated sRGB primaries) and
+/// enlarged `scale` times without smoothing. With `texture`, a fixed grain of
+/// brightness noise imitates the mottled look of the pigment particles; the
+/// same image always gets the same grain.
+pub(crate) fn preview_png(png_data: &[u8], scale: u32, texture: bool) -> Result<Vec<u8>, AppError> {
+    let image = read_indexed_png(png_data)?;
+    let color = |index: u8| {
+        let i = index as usize * 3;
+        image
+            .palette
+            .get(i..i + 3)
+            .map_or([255, 255, 255], |rgb| [rgb[0], rgb[1], rgb[2]])
+    };
+
+    let preview = RgbImage::from_fn(image.width * scale, image.height * scale, |x, y| {
+        let index = image.indices[(y / scale * image.width + x / scale) as usize];
+        let rgb = color(index);
+        if !texture {
+            return Rgb(rgb);
+        }
+        let grain = preview_grain(x, y);
+        Rgb(rgb.map(|c| (c as i16 + grain).clamp(0, 255) as u8))
+    });
+
+    let mut output = Vec::new();
+    DynamicImage::ImageRgb8(preview)
+        .write_to(&mut Cursor::new(&mut output), image::ImageFormat::Png)
+        .map_err(|e| AppError::ImageProcessing(format!("PNG write error: {}", e)))?;
+    Ok(output)
+}
+
+/// Deterministic brightness noise in `-PREVIEW_GRAIN..=PREVIEW_GRAIN` for a pixel
+fn preview_grain(x: u32, y: u32) -> i16 {
+    // Integer hash (a round of xorshift-multiply) of the coordinates
+    let mut h = x.wrapping_mul(0x9E37_79B1) ^ y.wrapping_mul(0x85EB_CA77);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2C1B_3C6D);
+    h ^= h >> 12;
+    (h % (2 * PREVIEW_GRAIN as u32 + 1)) as i16 - PREVIEW_GRAIN
+}
+
+/// Encode a continuous-tone RGB image as a PNG, tagged with its print resolution
+fn write_rgb_png(
+    image: &RgbImage,
+    dpi: u32,
+    text: &[(String, String)],
+) -> Result<Vec<u8>, AppError> {
+    let mut output = Vec::new();
+
+    {
+        let mut encoder = Encoder::new(Cursor::new(&mut output), image.width(), image.height());
+        encoder.set_color(ColorType::Rgb);
+        encoder.set_depth(BitDepth::Eight);
+        // pHYs is in pixels per meter
+        let pixels_per_meter = (dpi as f32 / 0.0254).round() as u32;
+        encoder.set_pixel_dims(Some(png::PixelDimensions {
+            xppu: pixels_per_meter,
+            yppu: pixels_per_meter,
+            unit: png::Unit::Meter,
+        }));
+        add_text_chunks(&mut encoder, text)?;
+
+        let mut writer = encoder
+            .write_header()
+            .map_err(|e| AppError::ImageProcessing(format!("PNG header error: {}", e)))?;
+
+        writer
+            .write_image_data(image.as_raw())
+            .map_err(|e| AppError::ImageProcessing(format!("PNG write error: {}", e)))?;
+    }
+
+    Ok(output)
+}
+
+fn write_indexed_png(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    depth: BitDepth,
+    compression: Compression,
+    palette: &[u8],
+    text: &[(String, String)],
+) -> Result<Vec<u8>, AppError> {
     let mut output = Vec::new();
 
     {
         let mut encoder = Encoder::new(Cursor::new(&mut output), width, height);
         encoder.set_color(ColorType::Indexed);
-        encoder.set_depth(BitDepth::Eight);
-        encoder.set_palette(PNG_PALETTE.to_vec());
+        encoder.set_depth(depth);
+        encoder.set_compression(compression);
+        encoder.set_palette(palette.to_vec());
+        add_text_chunks(&mut encoder, text)?;
 
         let mut writer = encoder
             .write_header()
             .map_err(|e| AppError::ImageProcessing(format!("PNG header error: {}", e)))?;
 
         writer
-            .write_image_data(indexed)
+            .write_image_data(data)
             .map_err(|e| AppError::ImageProcessing(format!("PNG write error: {}", e)))?;
     }
 
     Ok(output)
 }
 
+/// Add text chunks to a PNG about to be written
+fn add_text_chunks<W: std::io::Write>(
+    encoder: &mut Encoder<W>,
+    text: &[(String, String)],
+) -> Result<(), AppError> {
+    for (keyword, value) in text {
+        // tEXt is Latin-1 only; anything else goes in a UTF-8 iTXt chunk
+        let added = if value.chars().all(|c| (c as u32) < 0x100) {
+            encoder.add_text_chunk(keyword.clone(), value.clone())
+        } else {
+            encoder.add_itxt_chunk(keyword.clone(), value.clone())
+        };
+        added.map_err(|e| AppError::ImageProcessing(format!("PNG text error: {}", e)))?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::palette::PaletteIndex;
 
+    #[test]
+    fn test_thumbnail_png() {
+        let indexed = vec![PaletteIndex::Red.as_u8(); 480 * 800];
+        let png = encode_panel_png(
+            &indexed,
+            480,
+            800,
+            PanelType::Spectra6,
+            &PngMetadata::default(),
+        )
+        .unwrap();
+
+        let thumbnail = image::load_from_memory(&thumbnail_png(&png, 100).unwrap()).unwrap();
+        assert_eq!(thumbnail.dimensions(), (60, 100));
+        let red = PaletteIndex::Red.as_u8() as usize * 3;
+        assert_eq!(
+            thumbnail.to_rgb8().get_pixel(30, 50).0,
+            PNG_PALETTE[red..red + 3]
+        );
+    }
+
+    #[test]
+    fn test_preview_png() {
+        let indexed = [
+            PaletteIndex::Red.as_u8(),
+            PaletteIndex::White.as_u8(),
+            PaletteIndex::Blue.as_u8(),
+            PaletteIndex::Black.as_u8(),
+        ];
+        let png =
+            encode_panel_png(&indexed, 2, 2, PanelType::Spectra6, &PngMetadata::default()).unwrap();
+        let measured = |index: PaletteIndex| {
+            let i = index as usize * 3;
+            [PNG_PALETTE[i], PNG_PALETTE[i + 1], PNG_PALETTE[i + 2]]
+        };
+
+        let preview = image::load_from_memory(&preview_png(&png, 3, false).unwrap())
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(preview.dimensions(), (6, 6));
+        // Each pixel becomes a 3x3 block in its measured color
+        assert_eq!(preview.get_pixel(2, 2).0, measured(PaletteIndex::Red));
+        assert_eq!(preview.get_pixel(3, 0).0, measured(PaletteIndex::White));
+        assert_eq!(preview.get_pixel(0, 5).0, measured(PaletteIndex::Blue));
+
+        let textured = preview_png(&png, 3, true).unwrap();
+        assert_eq!(textured, preview_png(&png, 3, true).unwrap());
+        let textured = image::load_from_memory(&textured).unwrap().to_rgb8();
+        let white = measured(PaletteIndex::White);
+        let grain = textured.get_pixel(4, 1).0[0] as i16 - white[0] as i16;
+        assert!(grain.abs() <= PREVIEW_GRAIN);
+        assert_ne!(textured, preview);
+        assert!(preview_png(b"not a png", 2, false).is_err());
+    }
+
+    #[test]
+    fn test_fit_mode_auto() {
+        // Square cover: cropped for horizontal cards, letterboxed for vertical ones
+        assert!(!FitMode::Auto.letterbox(1000, 1000, 400, 360));
+        assert!(FitMode::Auto.letterbox(1000, 1000, 480, 680));
+        assert!(!FitMode::Cover.letterbox(1000, 1000, 480, 680));
+        assert!(FitMode::Letterbox.letterbox(1000, 1000, 400, 360));
+    }
+
+    #[test]
+    fn test_error_diffusion_dither() {
+        let total = |kernel: &DiffusionKernel| kernel.iter().map(|&(_, _, w)| w).sum::<f32>();
+        assert!((total(&FLOYD_STEINBERG) - 1.0).abs() < 1e-6);
+        assert!((total(&JARVIS_JUDICE_NINKE) - 1.0).abs() < 1e-6);
+        assert!((total(&ATKINSON) - 0.75).abs() < 1e-6);
+
+        // Mid gray diffuses to a mix of colors, without dithering it's one flat color
+        let gray = RgbImage::from_pixel(16, 16, Rgb([128, 128, 128]));
+        for mode in [
+            DitherMode::FloydSteinberg,
+            DitherMode::Atkinson,
+            DitherMode::JarvisJudiceNinke,
+        ] {
+            let indexed = dither(&gray, mode, ScanOrder::Serpentine);
+            assert!(indexed.iter().any(|&i| i != indexed[0]), "{:?}", mode);
+        }
+        let flat = dither(&gray, DitherMode::None, ScanOrder::Serpentine);
+        assert!(flat.iter().all(|&i| i == flat[0]));
+    }
+
+    #[test]
+    fn test_serpentine_scan() {
+        // A single row has nothing to alternate, so both scans agree
+        let row = RgbImage::from_fn(32, 1, |x, _| Rgb([(x * 8) as u8, 90, 160]));
+        assert_eq!(
+            dither(&row, DitherMode::FloydSteinberg, ScanOrder::Serpentine),
+            dither(&row, DitherMode::FloydSteinberg, ScanOrder::Raster)
+        );
+
+        // The first row is scanned the same way, later ones diffuse differently
+        let gradient = RgbImage::from_fn(32, 8, |x, y| Rgb([(x * 8) as u8, (y * 32) as u8, 128]));
+        let serpentine = dither(&gradient, DitherMode::FloydSteinberg, ScanOrder::Serpentine);
+        let raster = dither(&gradient, DitherMode::FloydSteinberg, ScanOrder::Raster);
+        assert_eq!(serpentine[..32], raster[..32]);
+        assert_ne!(serpentine, raster);
+    }
+
+    #[test]
+    fn test_gray_dither() {
+        // Saturated colors reduce to grays, only ever the panel's levels
+        let colors = RgbImage::from_fn(32, 8, |x, y| Rgb([(x * 8) as u8, 200, (y * 32) as u8]));
+        for mode in [
+            DitherMode::FloydSteinberg,
+            DitherMode::Atkinson,
+            DitherMode::Ordered,
+            DitherMode::None,
+        ] {
+            let params = |panel| RenderParams {
+                dither: mode,
+                panel,
+                ..RenderParams::default()
+            };
+            assert!(dither_for_panel(&colors, &params(PanelType::Bw))
+                .iter()
+                .all(|&i| i <= 1));
+            assert!(dither_for_panel(&colors, &params(PanelType::Gray4))
+                .iter()
+                .all(|&i| i <= 3));
+        }
+
+        // Mid gray lands on one of the intermediate levels when undithered
+        let gray = RgbImage::from_pixel(4, 4, Rgb([100, 100, 100]));
+        let params = RenderParams {
+            dither: DitherMode::None,
+            panel: PanelType::Gray4,
+            ..RenderParams::default()
+        };
+        assert!(dither_for_panel(&gray, &params).iter().all(|&i| i == 2));
+
+        // Diffusion mixes black and white for mid gray on a 2-level panel
+        let params = RenderParams {
+            panel: PanelType::Bw,
+            ..RenderParams::default()
+        };
+        let indexed =
+            dither_for_panel(&RgbImage::from_pixel(16, 16, Rgb([128, 128, 128])), &params);
+        assert!(indexed.contains(&PaletteIndex::Black.as_u8()));
+        assert!(indexed.contains(&PaletteIndex::White.as_u8()));
+    }
+
+    #[test]
+    fn test_caption_index() {
+        let color = |r, g, b| PrimaryColor { r, g, b };
+        let navy = color(20, 30, 110);
+        let red = color(230, 30, 40);
+        let mono = RenderParams::default();
+        assert_eq!(caption_index(&navy, &mono), PaletteIndex::White.as_u8());
+        assert_eq!(caption_index(&red, &mono), PaletteIndex::Black.as_u8());
+
+        let accent = RenderParams {
+            text_color: TextColor::Accent,
+            ..mono
+        };
+        assert_eq!(caption_index(&navy, &accent), PaletteIndex::Yellow.as_u8());
+        assert_eq!(caption_index(&red, &accent), PaletteIndex::Black.as_u8());
+        let gray = RenderParams {
+            panel: PanelType::Gray4,
+            ..accent
+        };
+        assert_eq!(caption_index(&navy, &gray), PaletteIndex::White.as_u8());
+    }
+
+    #[test]
+    fn test_encode_panel_png() {
+        // Accents drawn after dithering become the nearest gray
+        assert_eq!(nearest_gray(PaletteIndex::Yellow.as_u8(), &[0, 1]), 1);
+        assert_eq!(nearest_gray(PaletteIndex::Blue.as_u8(), &[0, 1]), 0);
+        assert_eq!(nearest_gray(PaletteIndex::White.as_u8(), &[0, 2, 3, 1]), 1);
+
+        let indexed = [0, 1, 2, 3];
+        let png =
+            encode_panel_png(&indexed, 4, 1, PanelType::Gray4, &PngMetadata::default()).unwrap();
+        let decoder = png::Decoder::new(Cursor::new(&png));
+        let reader = decoder.read_info().unwrap();
+        assert_eq!(
+            reader.info().palette.as_deref(),
+            Some(&PNG_GRAY_PALETTE[..])
+        );
+
+        // The compact re-encoding keeps the gray palette
+        let compact = compact_png(&png).unwrap();
+        let reader = png::Decoder::new(Cursor::new(&compact))
+            .read_info()
+            .unwrap();
+        assert_eq!(
+            reader.info().palette.as_deref(),
+            Some(&PNG_GRAY_PALETTE[..])
+        );
+    }
+
+    #[test]
+    fn test_ordered_dither() {
+        // Mid gray dithers to a repeating pattern, white stays solid
+        let gray = RgbImage::from_pixel(16, 16, Rgb([128, 128, 128]));
+        let indexed = ordered_dither(&gray);
+        assert!(indexed.contains(&PaletteIndex::White.as_u8()));
+        assert!(indexed.iter().any(|&i| i != PaletteIndex::White.as_u8()));
+        assert_eq!(indexed[..8], indexed[8..16]);
+
+        let white = RgbImage::from_pixel(8, 8, Rgb([255, 255, 255]));
+        assert!(ordered_dither(&white)
+            .iter()
+            .all(|&i| i == PaletteIndex::White.as_u8()));
+    }
+
+    #[test]
+    fn test_resize_letterbox() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(100, 100, Rgb([200, 0, 0])));
+        let output = resize_letterbox(&img, 480, 680, Rgb([0, 0, 200]));
+        assert_eq!(output.dimensions(), (480, 680));
+
+        // Foreground is centered and untouched
+        assert_eq!(*output.get_pixel(240, 340), Rgb([200, 0, 0]));
+
+        // Fill above the foreground is tinted toward the dominant color
+        let fill = output.get_pixel(240, 10);
+        assert!(fill[2] > 0 && fill[0] < 200);
+    }
+
+    #[test]
+    fn test_process_photo() {
+        // Portrait photo fills a horizontal card, no caption band
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(60, 90, Rgb([0, 0, 0])));
+        let mut source = Vec::new();
+        img.write_to(&mut Cursor::new(&mut source), image::ImageFormat::Png)
+            .unwrap();
+
+        let png = process_photo(&source, 400, 480, &RenderParams::default(), "photo.jpg").unwrap();
+        let output = image::load_from_memory(&png).unwrap();
+        assert_eq!(output.dimensions(), (400, 480));
+        let output = output.to_rgb8();
+        assert_eq!(output.get_pixel(200, 470), output.get_pixel(200, 10));
+    }
+
+    #[test]
+    fn test_render_print() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(60, 60, |x, y| {
+            Rgb([x as u8 * 4, y as u8 * 4, 128])
+        }));
+        let mut source = Vec::new();
+        img.write_to(&mut Cursor::new(&mut source), image::ImageFormat::Png)
+            .unwrap();
+        let info = ConcertInfo {
+            band_name: "Print Band".to_string(),
+            date: "June 1st, 2024".to_string(),
+            venue: "Palace Theatre".to_string(),
+            detail: None,
+            note: None,
+        };
+        let color = PrimaryColor {
+            r: 20,
+            g: 20,
+            b: 60,
+        };
+
+        // Twice the panel card, at 100 dpi
+        let png = render_print(
+            &source,
+            400,
+            480,
+            &info,
+            &color,
+            FitMode::Cover,
+            2.0,
+            100,
+            None,
+        )
+        .unwrap();
+        let reader = png::Decoder::new(Cursor::new(&png)).read_info().unwrap();
+        let png_info = reader.info();
+        assert_eq!((png_info.width, png_info.height), (800, 960));
+        assert_eq!(png_info.color_type, ColorType::Rgb);
+        assert_eq!(png_info.pixel_dims.unwrap().xppu, 3937);
+
+        // Continuous tone: far more colors than the panel palette, and the
+        // caption is drawn (white on the dark text area)
+        let output = image::load_from_memory(&png).unwrap().to_rgb8();
+        let colors: std::collections::HashSet<_> = output.pixels().collect();
+        assert!(colors.len() > 64);
+        let caption = output
+            .rows()
+            .skip(960 - 240)
+            .flatten()
+            .filter(|pixel| pixel[0] > 200)
+            .count();
+        assert!(caption > 0);
+    }
+
+    fn png_text(png_data: &[u8], keyword: &str) -> Option<String> {
+        let reader = png::Decoder::new(Cursor::new(png_data)).read_info().ok()?;
+        png_info_text(reader.info())
+            .into_iter()
+            .find(|(k, _)| k == keyword)
+            .map(|(_, text)| text)
+    }
+
+    fn png_description(png_data: &[u8]) -> Option<String> {
+        png_text(png_data, alt_text::PNG_KEYWORD)
+    }
+
+    #[test]
+    fn test_png_provenance() {
+        let color = PrimaryColor {
+            r: 10,
+            g: 20,
+            b: 255,
+        };
+        let url = "https://e-cdns-images.dzcdn.net/images/cover/abc/1000x1000.jpg";
+        let metadata = PngMetadata {
+            description: Some("Concert card"),
+            source_url: Some(url),
+            dominant_color: Some(color),
+            dither: Some(DitherMode::Atkinson),
+        };
+        let png = encode_panel_png(&[0, 1], 2, 1, PanelType::Spectra6, &metadata).unwrap();
+
+        assert_eq!(png_text(&png, PIPELINE_KEYWORD), Some("1".to_string()));
+        assert_eq!(
+            png_text(&png, SOURCE_KEYWORD),
+            Some(format!("{:08x}", crc32fast::hash(url.as_bytes())))
+        );
+        assert_eq!(png_text(&png, COLOR_KEYWORD).as_deref(), Some("#0a14ff"));
+        assert_eq!(png_text(&png, DITHER_KEYWORD).as_deref(), Some("atkinson"));
+        let created = png_text(&png, CREATION_TIME_KEYWORD).unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(&created).is_ok());
+
+        // Provenance survives re-encoding for the device
+        let compact = compact_png(&rotate_png_ccw90(&png).unwrap()).unwrap();
+        assert_eq!(
+            png_text(&compact, DITHER_KEYWORD).as_deref(),
+            Some("atkinson")
+        );
+        assert_eq!(png_text(&compact, CREATION_TIME_KEYWORD), Some(created));
+
+        // Untraced images still carry the pipeline version and render time
+        let png = encode_panel_png(&[0], 1, 1, PanelType::Bw, &PngMetadata::default()).unwrap();
+        assert!(png_text(&png, PIPELINE_KEYWORD).is_some());
+        assert!(png_text(&png, CREATION_TIME_KEYWORD).is_some());
+        assert_eq!(png_text(&png, SOURCE_KEYWORD), None);
+    }
+
+    #[test]
+    fn test_compact_png() {
+        // Odd width exercises the padded last nibble
+        let (width, height) = (7, 5);
+        let indexed: Vec<u8> = (0..width * height).map(|i| (i % 6) as u8).collect();
+        let description = "Concert card for Sigur Rós at Harpa";
+        let png = encode_panel_png(
+            &indexed,
+            width,
+            height,
+            PanelType::Spectra6,
+            &PngMetadata {
+                description: Some(description),
+                ..PngMetadata::default()
+            },
+        )
+        .unwrap();
+
+        let compact = compact_png(&png).unwrap();
+        assert_eq!(png_description(&compact).as_deref(), Some(description));
+        let original = image::load_from_memory(&png).unwrap().to_rgb8();
+        let output = image::load_from_memory(&compact).unwrap().to_rgb8();
+        assert_eq!(output, original);
+
+        let info = png::Decoder::new(Cursor::new(&compact))
+            .read_info()
+            .unwrap();
+        assert_eq!(info.info().bit_depth, BitDepth::Four);
+        assert!(compact_png(&compact).is_err());
+
+        // Text outside Latin-1 survives too
+        let description = "Concert card for 坂本龍一";
+        let png = encode_panel_png(
+            &indexed,
+            width,
+            height,
+            PanelType::Spectra6,
+            &PngMetadata {
+                description: Some(description),
+                ..PngMetadata::default()
+            },
+        )
+        .unwrap();
+        let compact = compact_png(&png).unwrap();
+        assert_eq!(png_description(&compact).as_deref(), Some(description));
+        assert_eq!(png_description(b"not a png"), None);
+    }
+
+    #[test]
+    fn test_rotate_png_ccw90() {
+        // Row 0: 0 1 2; row 1: 3 4 5
+        let description = "Concert card for Phish";
+        let png = encode_panel_png(
+            &[0, 1, 2, 3, 4, 5],
+            3,
+            2,
+            PanelType::Spectra6,
+            &PngMetadata {
+                description: Some(description),
+                ..PngMetadata::default()
+            },
+        )
+        .unwrap();
+
+        let rotated = rotate_png_ccw90(&png).unwrap();
+        assert_eq!(png_description(&rotated).as_deref(), Some(description));
+        let image = read_indexed_png(&rotated).unwrap();
+        assert_eq!((image.width, image.height), (2, 3));
+        // The right column becomes the top row
+        assert_eq!(image.indices, vec![2, 5, 1, 4, 0, 3]);
+
+        // Four turns come back around
+        let turned = (0..3).try_fold(rotated, |png, _| rotate_png_ccw90(&png));
+        let original = read_indexed_png(&png).unwrap();
+        assert_eq!(
+            read_indexed_png(&turned.unwrap()).unwrap().indices,
+            original.indices
+        );
+        assert!(rotate_png_ccw90(&compact_png(&png).unwrap()).is_err());
+    }
+
     #[test]
     fn test_nearest_color() {
         let palette = OklabPalette::new();
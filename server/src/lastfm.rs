@@ -0,0 +1,438 @@
+//! Last.fm API integration
+//!
+//! Builds a "top albums" widget from a user's scrobbles over a configurable
+//! period. Covers are resolved through Deezer for high-resolution art, falling
+//! back to Last.fm's own (300px) images.
+
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::blocklist;
+use crate::cache::{ConcertCache, ConcertEntry, CACHE_TTL_SECS};
+use crate::config::LastFmConfig;
+use crate::deezer;
+use crate::error::AppError;
+use crate::experiment::Variant;
+use crate::image_processing;
+use crate::text::ConcertInfo;
+use crate::widget::{Orientation, WidgetData, WidgetItem, WidgetWidth};
+
+/// Last.fm API endpoint
+const LASTFM_API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+
+/// Time range for top albums
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum Period {
+    #[serde(rename = "overall")]
+    Overall,
+    #[serde(rename = "7day")]
+    Week,
+    #[default]
+    #[serde(rename = "1month")]
+    Month,
+    #[serde(rename = "3month")]
+    Quarter,
+    #[serde(rename = "6month")]
+    HalfYear,
+    #[serde(rename = "12month")]
+    Year,
+}
+
+impl Period {
+    /// API parameter value
+    fn as_str(self) -> &'static str {
+        match self {
+            Period::Overall => "overall",
+            Period::Week => "7day",
+            Period::Month => "1month",
+            Period::Quarter => "3month",
+            Period::HalfYear => "6month",
+            Period::Year => "12month",
+        }
+    }
+
+    /// Caption suffix, e.g. "42 plays this month"
+    fn caption(self) -> &'static str {
+        match self {
+            Period::Overall => "all time",
+            Period::Week => "this week",
+            Period::Month => "this month",
+            Period::Quarter => "in 3 months",
+            Period::HalfYear => "in 6 months",
+            Period::Year => "this year",
+        }
+    }
+}
+
+/// An album from the top albums endpoint
+#[derive(Debug, Clone, Deserialize)]
+pub struct LastFmAlbum {
+    pub name: String,
+    pub artist: LastFmArtist,
+    /// Play count (the API returns it as a string)
+    #[serde(deserialize_with = "deserialize_count")]
+    pub playcount: u64,
+    #[serde(default)]
+    pub image: Vec<LastFmImage>,
+}
+
+impl LastFmAlbum {
+    /// Widget item path
+    ///
+    /// Path format: CRC-32 of artist and album (hex), then the play count, so
+    /// the path (and cached caption) changes as plays accrue.
+    pub fn item_path(&self) -> String {
+        format!("{:08x}-{}", self.key(), self.playcount)
+    }
+
+    /// Stable identifier of the album
+    fn key(&self) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(self.artist.name.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(self.name.as_bytes());
+        hasher.finalize()
+    }
+
+    /// URL of the largest Last.fm cover image
+    fn cover_url(&self) -> Option<&str> {
+        self.image
+            .iter()
+            .rev()
+            .map(|image| image.url.as_str())
+            .find(|url| !url.is_empty())
+    }
+}
+
+/// An artist from the Last.fm API
+#[derive(Debug, Clone, Deserialize)]
+pub struct LastFmArtist {
+    pub name: String,
+}
+
+/// A cover image from the Last.fm API (ordered smallest to largest)
+#[derive(Debug, Clone, Deserialize)]
+pub struct LastFmImage {
+    #[serde(rename = "#text")]
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TopAlbumsResponse {
+    topalbums: TopAlbums,
+}
+
+#[derive(Debug, Deserialize)]
+struct TopAlbums {
+    album: Vec<LastFmAlbum>,
+}
+
+fn deserialize_count<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+/// Fetch a user's top albums for the configured period
+pub async fn fetch_top_albums(
+    client: &Client,
+    config: &LastFmConfig,
+    limit: usize,
+) -> Result<Vec<LastFmAlbum>, AppError> {
+    tracing::info!(
+        "Fetching Last.fm top albums for {} ({})",
+        config.user,
+        config.period.as_str()
+    );
+
+    let response = client
+        .get(LASTFM_API_URL)
+        .query(&[
+            ("method", "user.gettopalbums"),
+            ("user", config.user.as_str()),
+            ("period", config.period.as_str()),
+            ("api_key", config.api_key.as_str()),
+            ("format", "json"),
+            ("limit", &limit.to_string()),
+        ])
+        .header("Accept", "application/json")
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(AppError::ExternalApi(format!(
+            "Last.fm API returned status: {}",
+            response.status()
+        )));
+    }
+
+    let response: TopAlbumsResponse = response.json().await?;
+
+    tracing::info!(
+        "Fetched {} top albums from Last.fm",
+        response.topalbums.album.len()
+    );
+
+    Ok(response.topalbums.album)
+}
+
+/// Convert top albums to widget items, most played first
+///
+/// Titled "album - artist". Covers are looked up again once the cache expires,
+/// so renders may change after its TTL.
+pub fn albums_to_widget_items(albums: &[LastFmAlbum], limit: usize) -> WidgetData {
+    albums
+        .iter()
+        .take(limit)
+        .map(|album| {
+            WidgetItem::new(album.item_path())
+                .with_ttl(CACHE_TTL_SECS)
+                .with_title(format!("{} - {}", album.name, album.artist.name))
+        })
+        .collect()
+}
+
+/// Find the album for an item path
+///
+/// Matches on the album key only, so paths with an outdated play count still
+/// resolve.
+pub fn find_album<'a>(albums: &'a [LastFmAlbum], path: &str) -> Result<&'a LastFmAlbum, AppError> {
+    let key = path
+        .split_once('-')
+        .and_then(|(key, count)| {
+            count.parse::<u64>().ok()?;
+            u32::from_str_radix(key, 16).ok()
+        })
+        .ok_or_else(|| AppError::InvalidPath(format!("invalid path format: {}", path)))?;
+
+    albums
+        .iter()
+        .find(|album| album.key() == key)
+        .ok_or_else(|| AppError::NotFound(format!("album {} is not in the top albums", path)))
+}
+
+/// Resolve the cover URLs to try for an album, best first
+///
+/// Deezer album art first, then the Last.fm image. An album whose title is
+/// blocked has none.
+async fn resolve_image_urls(client: &Client, album: &LastFmAlbum) -> Vec<String> {
+    if blocklist::get().blocks_title(&album.name) {
+        tracing::info!(
+            "Album {} - {} is blocked, using the placeholder",
+            album.artist.name,
+            album.name
+        );
+        return Vec::new();
+    }
+
+    let mut urls = Vec::new();
+    match deezer::fetch_album_by_title(client, deezer::DEEZER_BASE, &album.artist.name, &album.name)
+        .await
+    {
+        Ok(Some(deezer_album)) if blocklist::get().blocks_title(&deezer_album.title) => {
+            tracing::info!(
+                "Deezer album '{}' is blocked, using Last.fm image",
+                deezer_album.title
+            );
+        }
+        Ok(Some(deezer_album)) => {
+            let url = deezer_album.cover_url().unwrap_or_default().to_string();
+            tracing::info!(
+                "Using Deezer album art for {} - {}: {}",
+                album.artist.name,
+                album.name,
+                url
+            );
+            urls.push(url);
+        }
+        Ok(None) => {
+            tracing::info!(
+                "No Deezer album found for {} - {}, using Last.fm image",
+                album.artist.name,
+                album.name
+            );
+        }
+        Err(e) => {
+            tracing::warn!(
+                "Deezer API error for {} - {}: {}, using Last.fm image",
+                album.artist.name,
+                album.name,
+                e
+            );
+        }
+    }
+
+    urls.extend(album.cover_url().map(String::from));
+    urls
+}
+
+/// Fetch and process the cover for a top album
+///
+/// The caption shows the album, artist and play count for the period. Source
+/// art, metadata and rendered images are cached like concert entries, keyed by
+/// item path.
+pub async fn fetch_album_image(
+    client: &Client,
+    album: &LastFmAlbum,
+    period: Period,
+    path: &str,
+    orientation: Orientation,
+    cache: &ConcertCache,
+    variant: &Variant,
+) -> Result<Vec<u8>, AppError> {
+    let (target_width, target_height) = orientation.dimensions(WidgetWidth::Half);
+
+    // Album title takes the headline, artist and plays the smaller lines
+    let info = ConcertInfo {
+        band_name: album.name.clone(),
+        date: album.artist.name.clone(),
+        venue: format!(
+            "{} {} {}",
+            album.playcount,
+            if album.playcount == 1 {
+                "play"
+            } else {
+                "plays"
+            },
+            period.caption()
+        ),
+        detail: None,
+        note: None,
+    };
+
+    // Render from cached source art if we have it
+    if let Some(entry) = cache.get_concert(path).await {
+        if let Some(cached_image) = entry.get_image(orientation, &variant.name) {
+            return Ok((**cached_image).clone());
+        }
+
+        tracing::info!(
+            "Rendering {:?} ({}) for album {} using cached data",
+            orientation,
+            variant.name,
+            path
+        );
+        let rendered = image_processing::process_image_with_color(
+            &entry.source_image,
+            target_width,
+            target_height,
+            Some(&info),
+            &entry.primary_color,
+            &variant.params,
+            entry.source_url.as_deref(),
+        )?;
+        cache
+            .set_concert_image(path, orientation, &variant.name, Arc::new(rendered.clone()))
+            .await;
+
+        return Ok(rendered);
+    }
+
+    // No cached entry - fetch the cover, skipping blocked artwork
+    let image_urls = resolve_image_urls(client, album).await;
+    if image_urls.is_empty() && !blocklist::get().blocks_title(&album.name) {
+        return Err(AppError::NotFound(format!(
+            "no cover art for album {}",
+            path
+        )));
+    }
+    let artwork = blocklist::fetch_artwork(client, &image_urls).await?;
+    let source_image = Arc::new(artwork.image);
+    let primary_color = image_processing::extract_primary_color(&source_image)?;
+
+    cache
+        .set_or_update_concert(
+            path.to_string(),
+            ConcertEntry {
+                band_name: info.band_name.clone(),
+                venue: info.venue.clone(),
+                formatted_date: info.date.clone(),
+                detail: None,
+                note: None,
+                source_image: source_image.clone(),
+                source_url: artwork.url.clone(),
+                cover: None,
+                primary_color,
+                images: HashMap::new(),
+            },
+        )
+        .await;
+
+    let rendered = image_processing::process_image_with_color(
+        &source_image,
+        target_width,
+        target_height,
+        Some(&info),
+        &primary_color,
+        &variant.params,
+        artwork.url.as_deref(),
+    )?;
+    cache
+        .set_concert_image(path, orientation, &variant.name, Arc::new(rendered.clone()))
+        .await;
+
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOP_ALBUMS: &str = r##"{
+        "topalbums": {
+            "album": [
+                {"name": "In Rainbows", "playcount": "42", "artist": {"name": "Radiohead"},
+                    "image": [
+                        {"#text": "https://lastfm.freetls.fastly.net/i/u/34s/a.png", "size": "small"},
+                        {"#text": "https://lastfm.freetls.fastly.net/i/u/300x300/a.png", "size": "extralarge"}
+                    ], "@attr": {"rank": "1"}},
+                {"name": "Blue", "playcount": "1", "artist": {"name": "Joni Mitchell"},
+                    "image": [{"#text": "", "size": "extralarge"}], "@attr": {"rank": "2"}}
+            ],
+            "@attr": {"user": "someone", "page": "1", "total": "2"}
+        }
+    }"##;
+
+    fn albums() -> Vec<LastFmAlbum> {
+        serde_json::from_str::<TopAlbumsResponse>(TOP_ALBUMS)
+            .unwrap()
+            .topalbums
+            .album
+    }
+
+    #[test]
+    fn test_parse_top_albums() {
+        let albums = albums();
+        assert_eq!(albums[0].playcount, 42);
+        assert_eq!(
+            albums[0].cover_url(),
+            Some("https://lastfm.freetls.fastly.net/i/u/300x300/a.png")
+        );
+        assert_eq!(albums[1].cover_url(), None);
+    }
+
+    #[test]
+    fn test_item_paths() {
+        let albums = albums();
+        let items = albums_to_widget_items(&albums, 10);
+        assert_eq!(items.len(), 2);
+        assert!(items[0].path.ends_with("-42"));
+        assert_eq!(items[1].title.as_deref(), Some("Blue - Joni Mitchell"));
+
+        // Lookups ignore the play count
+        let (key, _) = items[1].path.split_once('-').unwrap();
+        let found = find_album(&albums, &format!("{}-7", key)).unwrap();
+        assert_eq!(found.name, "Blue");
+
+        assert!(matches!(
+            find_album(&albums, "00000000-1"),
+            Err(AppError::NotFound(_))
+        ));
+        assert!(matches!(
+            find_album(&albums, "../etc"),
+            Err(AppError::InvalidPath(_))
+        ));
+    }
+}
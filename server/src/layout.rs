@@ -0,0 +1,291 @@
+//! Text-only canvas layout
+//!
+//! Composes typographic panels without a source photo (e.g. the calendar
+//! agenda). The background, a colored header band blending into the body
+//! color, is dithered to the palette like cover art; rules and text are then
+//! drawn on top in solid palette colors so they stay crisp.
+
+use image::{Rgb, RgbImage};
+
+use crate::error::AppError;
+use crate::image_processing::{
+    dither_for_panel, encode_panel_png, lerp_u8, PngMetadata, RenderParams,
+};
+use crate::palette::PaletteIndex;
+use crate::text;
+
+/// Height of the transition from the header band into the body color
+const BAND_GRADIENT_HEIGHT: u32 = 24;
+
+/// Line height as a multiple of the font size
+const LINE_HEIGHT: f32 = 1.25;
+
+/// Thickness of horizontal rules
+const RULE_THICKNESS: u32 = 2;
+
+/// Horizontal alignment of a line of text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+}
+
+/// A layout element, stacked top to bottom
+#[derive(Debug, Clone)]
+enum Element {
+    /// A single line of text at the largest size that fits
+    Text {
+        text: String,
+        sizes: &'static [f32],
+        align: Align,
+        color: PaletteIndex,
+    },
+    /// Vertical space in pixels
+    Gap(u32),
+    /// Full-width horizontal rule
+    Rule(PaletteIndex),
+}
+
+impl Element {
+    /// Height taken by the element (text at its largest size)
+    fn height(&self) -> u32 {
+        match self {
+            Element::Text { sizes, .. } => line_height(sizes.first().copied().unwrap_or(20.0)),
+            Element::Gap(height) => *height,
+            Element::Rule(_) => RULE_THICKNESS,
+        }
+    }
+}
+
+/// A group of elements kept together: placed whole or not at all
+#[derive(Debug, Clone, Default)]
+pub struct Block {
+    elements: Vec<Element>,
+}
+
+impl Block {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a line of text, shrunk through `sizes` (largest first) to fit
+    pub fn text(
+        mut self,
+        text: &str,
+        sizes: &'static [f32],
+        align: Align,
+        color: PaletteIndex,
+    ) -> Self {
+        self.elements.push(Element::Text {
+            text: text.to_string(),
+            sizes,
+            align,
+            color,
+        });
+        self
+    }
+
+    /// Add vertical space
+    pub fn gap(mut self, height: u32) -> Self {
+        self.elements.push(Element::Gap(height));
+        self
+    }
+
+    /// Add a horizontal rule
+    pub fn rule(mut self, color: PaletteIndex) -> Self {
+        self.elements.push(Element::Rule(color));
+        self
+    }
+
+    fn height(&self) -> u32 {
+        self.elements.iter().map(Element::height).sum()
+    }
+}
+
+/// A text-only canvas
+#[derive(Debug, Clone)]
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    padding: u32,
+    background: Rgb<u8>,
+    /// Header band color and height
+    band: Option<(Rgb<u8>, u32)>,
+    blocks: Vec<Block>,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32, padding: u32, background: Rgb<u8>) -> Self {
+        Self {
+            width,
+            height,
+            padding,
+            background,
+            band: None,
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Color the top of the canvas, blending into the background below `height`
+    pub fn band(mut self, color: Rgb<u8>, height: u32) -> Self {
+        self.band = Some((color, height));
+        self
+    }
+
+    /// Add a block below the previous ones
+    pub fn push(&mut self, block: Block) {
+        self.blocks.push(block);
+    }
+
+    /// Number of leading blocks that fit on the canvas
+    pub fn fitting_blocks(&self) -> usize {
+        let available = self.height.saturating_sub(2 * self.padding);
+        let mut used = 0;
+        self.blocks
+            .iter()
+            .take_while(|block| {
+                used += block.height();
+                used <= available
+            })
+            .count()
+    }
+
+    /// Render to an indexed PNG, dropping blocks that don't fit
+    pub fn render(&self, params: &RenderParams) -> Result<Vec<u8>, AppError> {
+        let mut indexed = dither_for_panel(&self.background_image(), params);
+
+        let max_width = self.width.saturating_sub(2 * self.padding) as f32;
+        let mut y = self.padding;
+        for block in self.blocks.iter().take(self.fitting_blocks()) {
+            for element in &block.elements {
+                match element {
+                    Element::Text {
+                        text,
+                        sizes,
+                        align,
+                        color,
+                    } => {
+                        let (scale, line) = text::fit_line(text, max_width, sizes);
+                        let x = match align {
+                            Align::Left => self.padding,
+                            Align::Center => {
+                                let width = text::line_width(&line, scale);
+                                ((self.width as f32 - width) / 2.0).max(0.0) as u32
+                            }
+                        };
+                        // Shrunk lines keep their slot, vertically centered
+                        let slot = element.height();
+                        let offset = slot.saturating_sub(line_height(scale.y)) / 2;
+                        text::draw_line(
+                            &mut indexed,
+                            self.width,
+                            &line,
+                            scale,
+                            x,
+                            y + offset,
+                            color.as_u8(),
+                        );
+                    }
+                    Element::Gap(_) => {}
+                    Element::Rule(color) => {
+                        for row in y..(y + RULE_THICKNESS).min(self.height) {
+                            let start = (row * self.width + self.padding) as usize;
+                            let end = (row * self.width + self.width - self.padding) as usize;
+                            indexed[start..end].fill(color.as_u8());
+                        }
+                    }
+                }
+                y += element.height();
+            }
+        }
+
+        let metadata = PngMetadata {
+            dither: Some(params.dither),
+            ..PngMetadata::default()
+        };
+        encode_panel_png(&indexed, self.width, self.height, params.panel, &metadata)
+    }
+
+    /// RGB background: header band easing into the body color
+    fn background_image(&self) -> RgbImage {
+        let mut image = RgbImage::from_pixel(self.width, self.height, self.background);
+        if let Some((color, band_height)) = self.band {
+            for y in 0..self.height.min(band_height + BAND_GRADIENT_HEIGHT) {
+                let t = y.saturating_sub(band_height) as f32 / BAND_GRADIENT_HEIGHT as f32;
+                // Smooth easing (ease-in-out), as for cover art gradients
+                let t = t * t * (3.0 - 2.0 * t);
+                let pixel = Rgb([
+                    lerp_u8(color[0], self.background[0], t),
+                    lerp_u8(color[1], self.background[1], t),
+                    lerp_u8(color[2], self.background[2], t),
+                ]);
+                for x in 0..self.width {
+                    image.put_pixel(x, y, pixel);
+                }
+            }
+        }
+        image
+    }
+}
+
+/// Pixel height of a line of text at a font size
+fn line_height(size: f32) -> u32 {
+    (size * LINE_HEIGHT).ceil() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZES: &[f32] = &[32.0, 20.0];
+
+    fn block(lines: usize) -> Block {
+        (0..lines).fold(Block::new(), |block, i| {
+            block.text(
+                &format!("Line {}", i),
+                SIZES,
+                Align::Left,
+                PaletteIndex::Black,
+            )
+        })
+    }
+
+    #[test]
+    fn test_fitting_blocks() {
+        // 100px of room: each 32px line takes 40px
+        let mut canvas = Canvas::new(200, 120, 10, Rgb([255, 255, 255]));
+        canvas.push(block(1));
+        canvas.push(block(1).gap(8));
+        canvas.push(block(1));
+        assert_eq!(canvas.fitting_blocks(), 2);
+
+        // A block is placed whole or not at all
+        let mut canvas = Canvas::new(200, 120, 10, Rgb([255, 255, 255]));
+        canvas.push(block(3));
+        assert_eq!(canvas.fitting_blocks(), 0);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut canvas = Canvas::new(120, 80, 8, Rgb([232, 232, 232])).band(Rgb([5, 64, 158]), 20);
+        canvas.push(Block::new().rule(PaletteIndex::Red).text(
+            "A very long line that gets cut short",
+            SIZES,
+            Align::Center,
+            PaletteIndex::Black,
+        ));
+        let png = canvas.render(&RenderParams::default()).unwrap();
+
+        let decoder = png::Decoder::new(std::io::Cursor::new(png));
+        let mut reader = decoder.read_info().unwrap();
+        let mut indexed = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut indexed).unwrap();
+
+        // Blue band at the top, rule across the padded width, white body below
+        assert_eq!(indexed[0], PaletteIndex::Blue.as_u8());
+        assert_eq!(indexed[8 * 120 + 8], PaletteIndex::Red.as_u8());
+        assert_eq!(indexed[8 * 120 + 7], PaletteIndex::Blue.as_u8());
+        assert_eq!(indexed[79 * 120], PaletteIndex::White.as_u8());
+        assert!(indexed.contains(&PaletteIndex::Black.as_u8()));
+    }
+}
@@ -1,35 +1,211 @@
+mod abbreviate;
+mod admin;
+mod alt_text;
+mod blocklist;
 mod cache;
+mod cache_store;
+mod calendar;
+mod circuit;
+mod companion;
+mod config;
+mod dashboard;
 mod datasource;
 mod deezer;
+mod device;
+mod diagnostics;
+mod epd;
 mod error;
+mod experiment;
 mod image_processing;
+mod lastfm;
+mod layout;
+mod musicbrainz;
 mod palette;
+mod photos;
+mod prerender;
 mod sawthat;
+mod setlistfm;
+mod spotify;
 mod text;
 mod widget;
 
 use axum::{
-    extract::{Path, State},
-    http::{header, StatusCode},
-    response::{IntoResponse, Response},
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
     routing::get,
-    Json, Router,
+    Form, Json, Router,
 };
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Write;
 use std::sync::Arc;
+use std::time::Duration;
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
-use utoipa::OpenApi;
+use utoipa::{OpenApi, ToSchema};
 use utoipa_scalar::{Scalar, Servable};
 
+use crate::admin::{
+    IndexFormat, ItemAction, ItemIndex, ItemSummary, RenderStatus, DEFAULT_PER_PAGE, THUMBNAIL_SIZE,
+};
+use crate::cache::{unix_now, CacheStats};
+use crate::companion::{
+    ConcertAttributes, ConcertLinks, ConcertResource, ConcertsDocument, ImageLink, PageLinks,
+    PageMeta, PageQuery, JSONAPI_CONTENT_TYPE,
+};
+use crate::config::{
+    BlocklistConfig, CacheBackend, CalendarConfig, ConcertsConfig, DeviceCommand, DeviceConfig,
+    HeldSlot, LastFmConfig, PhotosConfig, QuietHours, RefreshMode, RenderConfig, SpotifyConfig,
+};
 use crate::datasource::DataSourceRegistry;
+use crate::device::{
+    Bandwidth, BootReason, CrashReport, DeviceDetails, DeviceSettings, DeviceStore, DeviceSummary,
+    ImageRef, QueueCommand, ResetReason, ShowItem, Telemetry, TelemetryEntry, TelemetryReport,
+    DEVICE_ID_HEADER,
+};
+use crate::diagnostics::{Diagnostics, DiagnosticsReport, DiagnosticsSample};
 use crate::error::AppError;
-use crate::widget::{Orientation, WidgetName};
+use crate::experiment::{ExperimentReport, Experiments, Variant, VariantReport};
+use crate::image_processing::{DitherMode, PanelType};
+use crate::prerender::{PrerenderReport, PrerenderStatus, Prerenderer};
+use crate::sawthat::{CoverSelection, CoverSource};
+use crate::widget::{CachePolicyHeader, Orientation, WidgetItem, WidgetName};
 
 /// Application state shared across handlers
 #[derive(Clone)]
 struct AppState {
     registry: Arc<DataSourceRegistry>,
+    config: Arc<DeviceConfig>,
+    devices: Arc<DeviceStore>,
+    prerender: Arc<Prerenderer>,
+    experiments: Arc<Experiments>,
+    diagnostics: Arc<Diagnostics>,
+    /// Per-widget dithering, overriding the variant's
+    widget_dither: Arc<HashMap<WidgetName, DitherMode>>,
+}
+
+impl AppState {
+    /// `variant` with the widget's dithering applied, if it has its own
+    fn widget_variant(&self, widget: WidgetName, variant: &Variant) -> Variant {
+        match self.widget_dither.get(&widget) {
+            Some(&dither) => variant.with_dither(dither),
+            None => variant.clone(),
+        }
+    }
+}
+
+/// Header reporting the experiment variant an image was rendered with
+const RENDER_VARIANT_HEADER: &str = "x-render-variant";
+
+/// Header reporting the age in seconds of widget data served from an expired
+/// copy, while it's refreshed (stale-while-revalidate) or because the upstream
+/// API failed (stale-if-error)
+const STALE_AGE_HEADER: &str = "x-stale-age";
+
+/// Header flagging widget data served from an expired copy, so devices keep
+/// their cached images and retry sooner
+const DATA_STALE_HEADER: &str = "x-data-stale";
+
+/// Header flagging stale widget data whose background refresh is underway
+const DATA_REVALIDATING_HEADER: &str = "x-data-revalidating";
+
+/// Seconds a device is asked to sleep after being served stale widget data,
+/// so it picks up the fresh list soon after the upstream recovers
+const STALE_RETRY_SECS: u32 = 15 * 60;
+
+/// Upstream connect timeout, so an unreachable API fails fast
+const UPSTREAM_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Upstream read timeout, between bytes rather than for the whole response
+const UPSTREAM_READ_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Header reporting the width of an EPD-native image in panel pixels
+const EPD_WIDTH_HEADER: &str = "x-epd-width";
+
+/// Header reporting the height of an EPD-native image in panel pixels
+const EPD_HEIGHT_HEADER: &str = "x-epd-height";
+
+/// Media type of EPD-native images, which devices can also ask for with `Accept`
+const EPD_CONTENT_TYPE: &str = "application/x-epd-4bpp";
+
+/// Output format for processed images
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum ImageFormat {
+    /// 8-bit indexed PNG
+    #[default]
+    Png,
+    /// Packed EPD color codes, two pixels per byte, in panel orientation
+    Epd,
+}
+
+/// Rotation applied to vertical PNGs before they are sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum ImageRotation {
+    /// 90° counter-clockwise, into the panel's native 800x480 geometry
+    Ccw90,
+}
+
+/// Query parameters for image requests
+#[derive(Debug, Deserialize)]
+struct ImageQuery {
+    format: Option<ImageFormat>,
+    dither: Option<DitherMode>,
+    rotate: Option<ImageRotation>,
+}
+
+/// Print resolution used when none is requested (a 2400x2880 card)
+const DEFAULT_PRINT_DPI: u32 = 300;
+
+/// Highest print resolution, bounding the size of the canvas rendered
+const MAX_PRINT_DPI: u32 = 600;
+
+/// Query parameters for print exports
+#[derive(Debug, Deserialize)]
+struct PrintQuery {
+    dpi: Option<u32>,
+}
+
+/// Album picked for a concert, from the admin page's form
+#[derive(Debug, Deserialize, ToSchema)]
+struct AlbumOverride {
+    /// Deezer album title, empty to go back to the automatic choice
+    title: String,
+}
+
+/// Note attached to a concert, from the admin page's form
+#[derive(Debug, Deserialize, ToSchema)]
+struct ConcertNote {
+    /// Note captioned under the concert, empty to remove it
+    note: String,
+}
+
+/// Enlargement used when none is requested
+const DEFAULT_PREVIEW_SCALE: u32 = 2;
+
+/// Largest preview enlargement
+const MAX_PREVIEW_SCALE: u32 = 4;
+
+/// Query parameters for simulated panel previews
+#[derive(Debug, Deserialize)]
+struct PreviewQuery {
+    scale: Option<u32>,
+    texture: Option<bool>,
+}
+
+/// Query parameters for the admin item index
+#[derive(Debug, Deserialize)]
+struct ItemIndexQuery {
+    widget: Option<WidgetName>,
+    page: Option<usize>,
+    per_page: Option<usize>,
+    format: Option<IndexFormat>,
 }
 
 /// OpenAPI documentation
@@ -41,10 +217,94 @@ struct AppState {
         version = "0.1.0"
     ),
     tags(
-        (name = "Concerts", description = "Concert history widget endpoints")
+        (name = "Device", description = "Device configuration and support endpoints"),
+        (name = "Widgets", description = "Widget data and image endpoints"),
+        (name = "Concerts", description = "Concert history widget endpoints"),
+        (name = "Experiments", description = "Rendering A/B experiment results"),
+        (name = "Admin", description = "Operator overview of widget items")
+    ),
+    paths(
+        health,
+        get_config,
+        list_devices,
+        get_device,
+        set_device_config,
+        show_item_next,
+        cancel_show_item,
+        queue_command,
+        clear_commands,
+        post_telemetry,
+        get_device_telemetry,
+        upload_device_logs,
+        get_device_logs,
+        upload_screenshot,
+        get_screenshot,
+        get_experiments,
+        get_widget_data,
+        get_prerender_status,
+        prerender_concerts,
+        get_widget_image,
+        get_concert_print,
+        get_concert_cover,
+        override_concert_album,
+        set_concert_note,
+        get_concert_preview,
+        list_concerts,
+        list_items,
+        get_item_thumbnail,
+        get_metrics,
+        get_dashboard
     ),
-    paths(health, get_concerts_data, get_concerts_image),
-    components(schemas(Orientation))
+    components(schemas(
+        ConcertsDocument,
+        ConcertResource,
+        ConcertAttributes,
+        ImageLink,
+        ConcertLinks,
+        PageLinks,
+        PageMeta,
+        Orientation,
+        ImageFormat,
+        ImageRotation,
+        DitherMode,
+        PanelType,
+        WidgetName,
+        WidgetItem,
+        DeviceConfig,
+        HeldSlot,
+        QuietHours,
+        RefreshMode,
+        DeviceSummary,
+        DeviceDetails,
+        DeviceSettings,
+        ShowItem,
+        DeviceCommand,
+        QueueCommand,
+        Telemetry,
+        ImageRef,
+        TelemetryReport,
+        TelemetryEntry,
+        BootReason,
+        CrashReport,
+        ResetReason,
+        Bandwidth,
+        PrerenderReport,
+        PrerenderStatus,
+        ExperimentReport,
+        VariantReport,
+        IndexFormat,
+        ItemIndex,
+        ItemSummary,
+        RenderStatus,
+        ItemAction,
+        CoverSelection,
+        CoverSource,
+        AlbumOverride,
+        ConcertNote,
+        CacheStats,
+        DiagnosticsSample,
+        DiagnosticsReport
+    ))
 )]
 struct ApiDoc;
 
@@ -58,21 +318,173 @@ async fn main() {
         .init();
 
     // Create HTTP client
-    let client = Client::new();
+    let client = Client::builder()
+        .connect_timeout(UPSTREAM_CONNECT_TIMEOUT)
+        .read_timeout(UPSTREAM_READ_TIMEOUT)
+        .build()
+        .expect("HTTP client builds");
+
+    // Load rendering configuration
+    let render_config = RenderConfig::from_env();
+    tracing::info!("Render config: {:?}", render_config);
+    abbreviate::init(render_config.venue_abbreviations.clone());
+
+    // Load the font and its metrics up front, rather than on the first render
+    text::init();
+
+    // Keep blocked artwork off the frame
+    let blocklist_config = BlocklistConfig::from_env();
+    tracing::info!("Artwork blocklist: {:?}", blocklist_config);
+    blocklist::init(blocklist_config);
+
+    // Persist rendered images across restarts if a cache backend is configured
+    let cache_backend = CacheBackend::from_env();
+    match &cache_backend {
+        CacheBackend::Memory => {
+            tracing::info!("Cache in memory only (set CACHE_DIR or CACHE_BACKEND=redis to persist)")
+        }
+        CacheBackend::Disk(dir) => tracing::info!("Disk cache: {}", dir.display()),
+        CacheBackend::Redis(_) => tracing::info!("Redis cache enabled"),
+    }
+    let cache_store = cache_store::open(&cache_backend).await;
+
+    // Scope the concert rotation
+    let concerts_config = ConcertsConfig::from_env();
+    tracing::info!("Concerts config: {:?}", concerts_config);
+
+    // Enable the Spotify widget if credentials are configured
+    let spotify_config = SpotifyConfig::from_env();
+    if spotify_config.is_none() {
+        tracing::info!("Spotify widget disabled (set SPOTIFY_* credentials to enable)");
+    }
+
+    // Enable the Last.fm widget if an account is configured
+    let lastfm_config = LastFmConfig::from_env();
+    if lastfm_config.is_none() {
+        tracing::info!("Last.fm widget disabled (set LASTFM_API_KEY and LASTFM_USER to enable)");
+    }
+
+    // Enable the calendar widget if a feed is configured
+    let calendar_config = CalendarConfig::from_env();
+    if calendar_config.is_none() {
+        tracing::info!("Calendar widget disabled (set CALENDAR_URL to enable)");
+    }
+
+    // Enable the photos widget if an album is configured
+    let photos_config = PhotosConfig::from_env();
+    if photos_config.is_none() {
+        tracing::info!("Photos widget disabled (set PHOTOS_DIR or PHOTOS_URL to enable)");
+    }
 
     // Create data source registry
-    let registry = Arc::new(DataSourceRegistry::new(client));
+    let registry = Arc::new(DataSourceRegistry::new(
+        client,
+        cache_store,
+        concerts_config,
+        spotify_config,
+        lastfm_config,
+        calendar_config,
+        photos_config,
+    ));
+
+    // Refresh the bands list before it expires, so devices never wait on SawThat
+    registry.concerts().start_bands_refresh();
+
+    // Load device configuration
+    let config = Arc::new(DeviceConfig::from_env());
+    tracing::info!("Device config: {:?}", config);
+
+    // Per-device settings and telemetry, persisted if a state directory is configured
+    let devices = Arc::new(match std::env::var_os("STATE_DIR") {
+        Some(dir) => DeviceStore::with_state_dir(dir.into()).await,
+        None => {
+            tracing::info!("Device state kept in memory (set STATE_DIR to persist)");
+            DeviceStore::new()
+        }
+    });
+
+    // Images requested again within half a refresh interval were skipped on the device
+    let skip_window = Duration::from_secs(config.refresh_interval_secs as u64 / 2);
+    let experiments = Arc::new(Experiments::new(
+        render_config.experiment,
+        render_config.params,
+        skip_window,
+    ));
+
+    // Sample memory and cache growth hourly, to catch leaks on long-running servers
+    let diagnostics = Arc::new(Diagnostics::new());
+    diagnostics.start(registry.clone(), devices.clone());
 
     // Create app state
-    let state = AppState { registry };
+    let state = AppState {
+        registry,
+        config,
+        devices,
+        prerender: Arc::new(Prerenderer::new()),
+        experiments,
+        diagnostics,
+        widget_dither: Arc::new(render_config.widget_dither),
+    };
 
     // Build router
     let app = Router::new()
         .route("/health", get(health))
-        .route("/concerts", get(get_concerts_data))
+        .route("/config", get(get_config))
+        .route("/telemetry", axum::routing::post(post_telemetry))
+        .route("/devices", get(list_devices))
+        .route("/devices/{id}", get(get_device))
         .route(
-            "/concerts/{orientation}/{*image_path}",
-            get(get_concerts_image),
+            "/devices/{id}/config",
+            axum::routing::put(set_device_config),
+        )
+        .route(
+            "/devices/{id}/show",
+            axum::routing::put(show_item_next).delete(cancel_show_item),
+        )
+        .route(
+            "/devices/{id}/commands",
+            axum::routing::post(queue_command).delete(clear_commands),
+        )
+        .route("/devices/{id}/telemetry", get(get_device_telemetry))
+        .route(
+            "/devices/{id}/logs",
+            get(get_device_logs).post(upload_device_logs),
+        )
+        .route(
+            "/devices/{id}/screenshot",
+            get(get_screenshot).post(upload_screenshot),
+        )
+        .route("/experiments", get(get_experiments))
+        .route("/admin/items", get(list_items))
+        .route(
+            "/admin/thumbnails/{widget}/{orientation}/{*image_path}",
+            get(get_item_thumbnail),
+        )
+        .route("/metrics", get(get_metrics))
+        .route("/dashboard", get(get_dashboard))
+        .route("/{widget}", get(get_widget_data))
+        .route(companion::CONCERTS_PATH, get(list_concerts))
+        .route(
+            "/concerts/prerender",
+            get(get_prerender_status).post(prerender_concerts),
+        )
+        .route("/concerts/{image_path}/print", get(get_concert_print))
+        .route("/concerts/{image_path}/cover", get(get_concert_cover))
+        .route(
+            "/concerts/{image_path}/album",
+            axum::routing::post(override_concert_album),
+        )
+        .route(
+            "/concerts/{image_path}/note",
+            axum::routing::post(set_concert_note),
+        )
+        .route(
+            "/concerts/{orientation}/{image_path}/preview",
+            get(get_concert_preview),
+        )
+        .route(
+            "/{widget}/{orientation}/{*image_path}",
+            get(get_widget_image),
         )
         .merge(Scalar::with_url("/docs", ApiDoc::openapi()))
         .route("/openapi.json", get(openapi_json))
@@ -105,89 +517,1316 @@ async fn health() -> &'static str {
     "ok"
 }
 
+/// Get device configuration
+///
+/// Returns settings the frame applies on each wake. Devices sending `X-Device-Id`
+/// get the global settings with their own overrides applied.
+#[utoipa::path(
+    get,
+    path = "/config",
+    tag = "Device",
+    params(
+        ("X-Device-Id" = Option<String>, Header, description = "Device identifier")
+    ),
+    responses(
+        (status = 200, description = "Device configuration", body = DeviceConfig)
+    )
+)]
+async fn get_config(State(state): State<AppState>, headers: HeaderMap) -> Json<DeviceConfig> {
+    let device_id = state.devices.record_request(&headers).await;
+    Json(state.devices.deliver_config(device_id, &state.config).await)
+}
+
+/// List devices
+///
+/// Returns every device that has reported to the server.
+#[utoipa::path(
+    get,
+    path = "/devices",
+    tag = "Device",
+    responses(
+        (status = 200, description = "Reported devices", body = Vec<DeviceSummary>)
+    )
+)]
+async fn list_devices(State(state): State<AppState>) -> Json<Vec<DeviceSummary>> {
+    Json(state.devices.list().await)
+}
+
+/// Get a device
+///
+/// Returns the device's effective configuration, its own settings, telemetry from
+/// its latest request and when it last uploaded a screenshot.
+#[utoipa::path(
+    get,
+    path = "/devices/{id}",
+    tag = "Device",
+    params(
+        ("id" = String, Path, description = "Device identifier")
+    ),
+    responses(
+        (status = 200, description = "Device state", body = DeviceDetails),
+        (status = 404, description = "Unknown device")
+    )
+)]
+async fn get_device(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<DeviceDetails>, AppError> {
+    state
+        .devices
+        .details(&id, &state.config)
+        .await
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Unknown device {}", id)))
+}
+
+/// Set device settings
+///
+/// Replaces the device's overrides of the global configuration; unset fields
+/// follow the global settings and an empty object removes all overrides. Returns
+/// the configuration the device will get on its next wake.
+#[utoipa::path(
+    put,
+    path = "/devices/{id}/config",
+    tag = "Device",
+    params(
+        ("id" = String, Path, description = "Device identifier")
+    ),
+    request_body = DeviceSettings,
+    responses(
+        (status = 200, description = "Effective device configuration", body = DeviceConfig),
+        (status = 400, description = "Invalid device ID or settings")
+    )
+)]
+async fn set_device_config(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(settings): Json<DeviceSettings>,
+) -> Result<Json<DeviceConfig>, AppError> {
+    tracing::info!("Device settings: device={}, {:?}", id, settings);
+    state.devices.set_settings(&id, settings).await?;
+    Ok(Json(state.devices.config(Some(&id), &state.config).await))
+}
+
+/// Show an item next
+///
+/// Asks the device to show an item from its rotation on its next refresh, or
+/// straight away if it's awake on USB power. The request is delivered as
+/// `show_next` in the device's config until a telemetry report says the item
+/// was shown. Replaces any earlier request.
+#[utoipa::path(
+    put,
+    path = "/devices/{id}/show",
+    tag = "Device",
+    params(
+        ("id" = String, Path, description = "Device identifier")
+    ),
+    request_body = ShowItem,
+    responses(
+        (status = 202, description = "Request queued, with the config the device will get", body = DeviceConfig),
+        (status = 400, description = "Invalid device ID or item path"),
+        (status = 404, description = "Item not in any of the device's widgets")
+    )
+)]
+async fn show_item_next(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(item): Json<ShowItem>,
+) -> Result<impl IntoResponse, AppError> {
+    let config = state.devices.config(Some(&id), &state.config).await;
+    let mut listed = false;
+    for widget in &config.widgets {
+        let Ok(source) = state.registry.get(*widget) else {
+            continue;
+        };
+        if source
+            .fetch_data()
+            .await?
+            .iter()
+            .any(|listed| listed.path == item.path)
+        {
+            listed = true;
+            break;
+        }
+    }
+    if !listed {
+        return Err(AppError::NotFound(format!(
+            "{} is not in the rotation of {}",
+            item.path, id
+        )));
+    }
+
+    tracing::info!("Show next: device={}, item={}", id, item.path);
+    state.devices.set_show_next(&id, item.path).await?;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(state.devices.config(Some(&id), &state.config).await),
+    ))
+}
+
+/// Cancel a show request
+///
+/// Withdraws the item the device was asked to show next, if it hasn't shown it yet.
+#[utoipa::path(
+    delete,
+    path = "/devices/{id}/show",
+    tag = "Device",
+    params(
+        ("id" = String, Path, description = "Device identifier")
+    ),
+    responses(
+        (status = 204, description = "Request withdrawn"),
+        (status = 404, description = "No pending request")
+    )
+)]
+async fn cancel_show_item(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    if state.devices.clear_show_next(&id).await {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound(format!("No show request for {}", id)))
+    }
+}
+
+/// Queue a command
+///
+/// Remote control without a button press: `next` shows the next item, `flip`
+/// toggles the orientation and `refresh` redraws the current items with a full
+/// refresh. Commands are delivered in `commands` of the device's next config
+/// fetch, once, and run in order after that wake's refresh. Up to four are kept;
+/// more replace the oldest.
+#[utoipa::path(
+    post,
+    path = "/devices/{id}/commands",
+    tag = "Device",
+    params(
+        ("id" = String, Path, description = "Device identifier")
+    ),
+    request_body = QueueCommand,
+    responses(
+        (status = 202, description = "Command queued, with the config the device will get", body = DeviceConfig),
+        (status = 400, description = "Invalid device ID or command")
+    )
+)]
+async fn queue_command(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<QueueCommand>,
+) -> Result<impl IntoResponse, AppError> {
+    tracing::info!("Command: device={}, {:?}", id, request.command);
+    state.devices.queue_command(&id, request.command).await?;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(state.devices.config(Some(&id), &state.config).await),
+    ))
+}
+
+/// Clear queued commands
+///
+/// Withdraws the commands the device hasn't picked up yet.
+#[utoipa::path(
+    delete,
+    path = "/devices/{id}/commands",
+    tag = "Device",
+    params(
+        ("id" = String, Path, description = "Device identifier")
+    ),
+    responses(
+        (status = 204, description = "Commands withdrawn"),
+        (status = 404, description = "No queued commands")
+    )
+)]
+async fn clear_commands(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    if state.devices.clear_commands(&id).await {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound(format!("No queued commands for {}", id)))
+    }
+}
+
+/// Post a telemetry report
+///
+/// Appends the report to the history of the device named in `X-Device-Id`. Frames
+/// post one after each wake, over the connection they fetched content with.
+#[utoipa::path(
+    post,
+    path = "/telemetry",
+    tag = "Device",
+    params(
+        ("X-Device-Id" = String, Header, description = "Device identifier")
+    ),
+    request_body = TelemetryReport,
+    responses(
+        (status = 204, description = "Report stored"),
+        (status = 400, description = "Missing device ID or invalid report")
+    )
+)]
+async fn post_telemetry(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(report): Json<TelemetryReport>,
+) -> Result<StatusCode, AppError> {
+    let id = state
+        .devices
+        .record_request(&headers)
+        .await
+        .ok_or_else(|| AppError::InvalidUpload("Missing or invalid X-Device-Id".to_string()))?;
+    tracing::info!("Telemetry: device={}, {:?}", id, report);
+    state.devices.add_report(id, report).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get device telemetry history
+///
+/// Returns the device's telemetry reports, oldest first, up to the most recent
+/// 1000.
+#[utoipa::path(
+    get,
+    path = "/devices/{id}/telemetry",
+    tag = "Device",
+    params(
+        ("id" = String, Path, description = "Device identifier")
+    ),
+    responses(
+        (status = 200, description = "Telemetry history", body = Vec<TelemetryEntry>),
+        (status = 404, description = "No telemetry for this device")
+    )
+)]
+async fn get_device_telemetry(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<TelemetryEntry>>, AppError> {
+    state
+        .devices
+        .history(&id)
+        .await
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("No telemetry for device {}", id)))
+}
+
+/// Upload device log lines
+///
+/// Stores the log lines a frame captured during a wake (all of them, or only
+/// warnings and errors, depending on its build), uploaded after its refresh.
+/// The server keeps the latest 256 KB per device, in memory.
+#[utoipa::path(
+    post,
+    path = "/devices/{id}/logs",
+    tag = "Device",
+    params(
+        ("id" = String, Path, description = "Device identifier")
+    ),
+    request_body(content = String, content_type = "text/plain"),
+    responses(
+        (status = 204, description = "Log lines stored"),
+        (status = 400, description = "Invalid device ID, or an empty or oversized upload")
+    )
+)]
+async fn upload_device_logs(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<StatusCode, AppError> {
+    tracing::info!("Log upload: device={}, {} bytes", id, body.len());
+    state.devices.add_logs(&id, &body).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get a device's uploaded log lines
+///
+/// Returns the kept uploads as plain text, oldest first, each after a line
+/// with the Unix time it was received.
+#[utoipa::path(
+    get,
+    path = "/devices/{id}/logs",
+    tag = "Device",
+    params(
+        ("id" = String, Path, description = "Device identifier")
+    ),
+    responses(
+        (status = 200, description = "Uploaded log lines", body = String, content_type = "text/plain"),
+        (status = 404, description = "No logs for this device")
+    )
+)]
+async fn get_device_logs(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let uploads = state
+        .devices
+        .logs(&id)
+        .await
+        .ok_or_else(|| AppError::NotFound(format!("No logs for device {}", id)))?;
+
+    let mut text = String::new();
+    for upload in uploads {
+        text.push_str(&format!("--- received at {} ---\n", upload.received_at));
+        text.push_str(&upload.text);
+        if !upload.text.ends_with('\n') {
+            text.push('\n');
+        }
+    }
+    Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], text).into_response())
+}
+
+/// Upload a device screenshot
+///
+/// Stores the framebuffer screenshot (800x480 PNG) captured by a frame, replacing its previous one.
+#[utoipa::path(
+    post,
+    path = "/devices/{id}/screenshot",
+    tag = "Device",
+    params(
+        ("id" = String, Path, description = "Device identifier")
+    ),
+    request_body(content = Vec<u8>, content_type = "image/png"),
+    responses(
+        (status = 204, description = "Screenshot stored"),
+        (status = 400, description = "Invalid device ID or image")
+    )
+)]
+async fn upload_screenshot(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<StatusCode, AppError> {
+    tracing::info!("Screenshot upload: device={}, {} bytes", id, body.len());
+    state.devices.set_screenshot(&id, body.to_vec()).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get the latest device screenshot
+///
+/// Returns the most recent screenshot uploaded by a frame.
+#[utoipa::path(
+    get,
+    path = "/devices/{id}/screenshot",
+    tag = "Device",
+    params(
+        ("id" = String, Path, description = "Device identifier")
+    ),
+    responses(
+        (status = 200, description = "Latest screenshot", content_type = "image/png"),
+        (status = 404, description = "No screenshot for this device")
+    )
+)]
+async fn get_screenshot(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let screenshot = state
+        .devices
+        .get_screenshot(&id)
+        .await
+        .ok_or_else(|| AppError::NotFound(format!("No screenshot for device {}", id)))?;
+
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "image/png".to_string()),
+            (header::CACHE_CONTROL, "no-store".to_string()),
+            (
+                header::HeaderName::from_static("x-captured-at"),
+                screenshot.received_at.to_string(),
+            ),
+        ],
+        screenshot.png.to_vec(),
+    )
+        .into_response())
+}
+
+/// Get experiment results
+///
+/// Returns per-variant counts of devices, images served, kept and skipped for the
+/// active rendering experiment.
+#[utoipa::path(
+    get,
+    path = "/experiments",
+    tag = "Experiments",
+    responses(
+        (status = 200, description = "Experiment results", body = ExperimentReport),
+        (status = 404, description = "No experiment configured")
+    )
+)]
+async fn get_experiments(
+    State(state): State<AppState>,
+) -> Result<Json<ExperimentReport>, AppError> {
+    state
+        .experiments
+        .report()
+        .await
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("No experiment configured".to_string()))
+}
+
+/// List widget items
+///
+/// Returns a page of every item across the configured widgets (or just `widget`),
+/// with whether each orientation is rendered in the server cache, thumbnail links
+/// for the cached renders and quick actions. Served as an HTML page with
+/// `format=html`, or when the request prefers `text/html`.
+#[utoipa::path(
+    get,
+    path = "/admin/items",
+    tag = "Admin",
+    params(
+        ("widget" = Option<WidgetName>, Query, description = "Only list this widget's items"),
+        ("page" = Option<usize>, Query, description = "Page number, starting at 1"),
+        ("per_page" = Option<usize>, Query, description = "Items per page (default 50, at most 200)"),
+        ("format" = Option<IndexFormat>, Query, description = "Output format: json or html")
+    ),
+    responses(
+        (status = 200, description = "Page of widget items", content((ItemIndex = "application/json"), (String = "text/html"))),
+        (status = 404, description = "Widget not configured")
+    )
+)]
+async fn list_items(
+    State(state): State<AppState>,
+    Query(query): Query<ItemIndexQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let sources = match query.widget {
+        Some(widget) => vec![(widget, state.registry.get(widget)?)],
+        None => state.registry.configured(),
+    };
+    let default = state.experiments.assign(None);
+    let index = admin::build_index(
+        &sources,
+        |widget| state.widget_variant(widget, default).name,
+        query.page.unwrap_or(1),
+        query.per_page.unwrap_or(DEFAULT_PER_PAGE),
+    )
+    .await;
+
+    let format = query.format.unwrap_or_else(|| {
+        let wants_html = headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("text/html"));
+        if wants_html {
+            IndexFormat::Html
+        } else {
+            IndexFormat::Json
+        }
+    });
+    Ok(match format {
+        IndexFormat::Json => Json(index).into_response(),
+        IndexFormat::Html => Html(admin::render_html(&index, query.widget)).into_response(),
+    })
+}
+
+/// Get item thumbnail
+///
+/// Returns a 100px thumbnail of a widget item's cached render. Items that haven't
+/// been rendered yet have none; requesting the full image renders it.
+#[utoipa::path(
+    get,
+    path = "/admin/thumbnails/{widget}/{orientation}/{image_path}",
+    tag = "Admin",
+    params(
+        ("widget" = WidgetName, Path, description = "Widget name"),
+        ("orientation" = Orientation, Path, description = "Display orientation"),
+        ("image_path" = String, Path, description = "Path to the image resource")
+    ),
+    responses(
+        (status = 200, description = "Thumbnail", content_type = "image/png"),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 404, description = "Not rendered, or widget not configured")
+    )
+)]
+async fn get_item_thumbnail(
+    State(state): State<AppState>,
+    Path((widget, orientation, image_path)): Path<(WidgetName, Orientation, String)>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let source = state.registry.get(widget)?;
+    let variant = state.widget_variant(widget, state.experiments.assign(None));
+    let png_data = source
+        .cached_image(&image_path, orientation, &variant.name)
+        .await
+        .ok_or_else(|| AppError::NotFound(format!("{} is not rendered", image_path)))?;
+    let thumbnail = image_processing::thumbnail_png(&png_data, THUMBNAIL_SIZE)?;
+
+    Ok((
+        [(header::CACHE_CONTROL, "no-cache")],
+        conditional_response(&headers, "image/png", thumbnail),
+    )
+        .into_response())
+}
+
+/// Get a concert card for printing
+///
+/// Returns the concert's card as a continuous-tone RGB PNG: the same layout and
+/// typography as on the frame, rendered at `dpi` without dithering or e-paper
+/// color adjustments. The card prints 8 x 9.6 inches (2400x2880 at 300 dpi).
+#[utoipa::path(
+    get,
+    path = "/concerts/{image_path}/print",
+    tag = "Widgets",
+    params(
+        ("image_path" = String, Path, description = "Concert item path (YYYY-MM-DD-band-id)"),
+        ("dpi" = Option<u32>, Query, description = "Print resolution, 50 to 600 (default 300)")
+    ),
+    responses(
+        (status = 200, description = "Print-resolution card", content_type = "image/png"),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 400, description = "Invalid path or resolution"),
+        (status = 404, description = "Band not found")
+    )
+)]
+async fn get_concert_print(
+    State(state): State<AppState>,
+    Path(image_path): Path<String>,
+    Query(query): Query<PrintQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let dpi = query.dpi.unwrap_or(DEFAULT_PRINT_DPI);
+    if !(image_processing::PRINT_BASE_DPI..=MAX_PRINT_DPI).contains(&dpi) {
+        return Err(AppError::InvalidPath(format!(
+            "dpi must be between {} and {}",
+            image_processing::PRINT_BASE_DPI,
+            MAX_PRINT_DPI
+        )));
+    }
+
+    let widget = WidgetName::Concerts;
+    let source = state.registry.get(widget)?;
+    let variant = state.widget_variant(widget, state.experiments.assign(None));
+    let png_data = source.print_image(&image_path, &variant, dpi).await?;
+
+    // Offer a descriptive name when saved
+    let filename: String = image_path
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    Ok((
+        [
+            (header::CACHE_CONTROL, "public, max-age=86400".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"{}-{}dpi.png\"", filename, dpi),
+            ),
+        ],
+        conditional_response(&headers, "image/png", png_data),
+    )
+        .into_response())
+}
+
+/// Get how a concert's cover was chosen
+///
+/// Returns the cover's source (the Deezer album closest before the concert, an
+/// album override, the band picture or the placeholder) and why: the album
+/// picked and how long before the concert it was released, or why it was
+/// passed over. Resolves the concert if it isn't cached yet.
+#[utoipa::path(
+    get,
+    path = "/concerts/{image_path}/cover",
+    tag = "Concerts",
+    params(
+        ("image_path" = String, Path, description = "Concert item path (YYYY-MM-DD-band-id)")
+    ),
+    responses(
+        (status = 200, description = "Cover selection", body = CoverSelection),
+        (status = 400, description = "Invalid path"),
+        (status = 404, description = "Band not found, or resolved before selections were recorded")
+    )
+)]
+async fn get_concert_cover(
+    State(state): State<AppState>,
+    Path(image_path): Path<String>,
+) -> Result<Json<CoverSelection>, AppError> {
+    let source = state.registry.get(WidgetName::Concerts)?;
+    Ok(Json(source.cover_selection(&image_path).await?))
+}
+
+/// Override a concert's album
+///
+/// Uses the artist's Deezer album with this title for the concert's cover
+/// instead of the one released closest before it, ignoring the title
+/// blocklist; an empty title goes back to the automatic choice. The concert is
+/// resolved again on its next request, and the response redirects to its
+/// cover selection. Takes a form body, so the admin page can post it directly.
+#[utoipa::path(
+    post,
+    path = "/concerts/{image_path}/album",
+    tag = "Concerts",
+    params(
+        ("image_path" = String, Path, description = "Concert item path (YYYY-MM-DD-band-id)")
+    ),
+    request_body(content = AlbumOverride, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Override saved, redirecting to the cover selection"),
+        (status = 400, description = "Invalid path or title")
+    )
+)]
+async fn override_concert_album(
+    State(state): State<AppState>,
+    Path(image_path): Path<String>,
+    Form(form): Form<AlbumOverride>,
+) -> Result<Redirect, AppError> {
+    let source = state.registry.get(WidgetName::Concerts)?;
+    source
+        .override_album(&image_path, Some(&form.title))
+        .await?;
+    Ok(Redirect::to(&format!(
+        "/concerts/{}/cover",
+        urlencoding::encode(&image_path)
+    )))
+}
+
+/// Attach a note to a concert
+///
+/// Captions the concert's card with a short personal note (e.g. "road trip
+/// with Sam"), in oblique below the venue; an empty note removes it. The
+/// concert is rendered again on its next request, and the response redirects
+/// to a preview of its horizontal card. Takes a form body, so the admin page
+/// can post it directly.
+#[utoipa::path(
+    post,
+    path = "/concerts/{image_path}/note",
+    tag = "Concerts",
+    params(
+        ("image_path" = String, Path, description = "Concert item path (YYYY-MM-DD-band-id)")
+    ),
+    request_body(content = ConcertNote, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Note saved, redirecting to the card preview"),
+        (status = 400, description = "Invalid path or note")
+    )
+)]
+async fn set_concert_note(
+    State(state): State<AppState>,
+    Path(image_path): Path<String>,
+    Form(form): Form<ConcertNote>,
+) -> Result<Redirect, AppError> {
+    let source = state.registry.get(WidgetName::Concerts)?;
+    source.set_note(&image_path, Some(&form.note)).await?;
+    Ok(Redirect::to(&format!(
+        "/concerts/{}/{}/preview",
+        Orientation::Horiz,
+        urlencoding::encode(&image_path)
+    )))
+}
+
+/// List concerts for companion apps
+///
+/// Returns a page of the concert rotation as a JSON:API document: each concert's
+/// band, date and venue, links to its card as the panel shows it and in full
+/// color at several sizes, and links to the neighbouring pages. The firmware
+/// keeps using the bare path list from `/concerts`.
+#[utoipa::path(
+    get,
+    path = "/api/v1/concerts",
+    tag = "Concerts",
+    params(
+        ("page[number]" = Option<usize>, Query, description = "Page number, starting at 1"),
+        ("page[size]" = Option<usize>, Query, description = "Concerts per page (default 20, at most 100)")
+    ),
+    responses(
+        (status = 200, description = "Page of concerts", body = ConcertsDocument, content_type = "application/vnd.api+json"),
+        (status = 502, description = "SawThat API unavailable")
+    )
+)]
+async fn list_concerts(
+    State(state): State<AppState>,
+    Query(query): Query<PageQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let concerts = state.registry.concerts().listings().await?;
+    let document = companion::build_document(concerts, &query);
+    let body = serde_json::to_vec(&document).expect("concerts document serializes to JSON");
+    Ok(compressed_response(&headers, JSONAPI_CONTENT_TYPE, body).into_response())
+}
+
+/// Preview a concert card as the panel shows it
+///
+/// Returns the dithered image a frame would get, drawn in the measured panel
+/// colors and enlarged `scale` times so the dithering stays visible. With
+/// `texture=true` a simulated e-paper grain is laid over it. For looking at in
+/// a browser; frames should fetch `/concerts/{orientation}/{image_path}`.
+#[utoipa::path(
+    get,
+    path = "/concerts/{orientation}/{image_path}/preview",
+    tag = "Widgets",
+    params(
+        ("orientation" = Orientation, Path, description = "Display orientation"),
+        ("image_path" = String, Path, description = "Concert item path (YYYY-MM-DD-band-id)"),
+        ("scale" = Option<u32>, Query, description = "Enlargement, 1 to 4 (default 2)"),
+        ("texture" = Option<bool>, Query, description = "Simulate e-paper grain (default false)")
+    ),
+    responses(
+        (status = 200, description = "Simulated panel image", content_type = "image/png"),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 400, description = "Invalid orientation, path or scale"),
+        (status = 404, description = "Band not found")
+    )
+)]
+async fn get_concert_preview(
+    State(state): State<AppState>,
+    Path((orientation, image_path)): Path<(Orientation, String)>,
+    Query(query): Query<PreviewQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let scale = query.scale.unwrap_or(DEFAULT_PREVIEW_SCALE);
+    if !(1..=MAX_PREVIEW_SCALE).contains(&scale) {
+        return Err(AppError::InvalidPath(format!(
+            "scale must be between 1 and {}",
+            MAX_PREVIEW_SCALE
+        )));
+    }
+
+    let widget = WidgetName::Concerts;
+    let source = state.registry.get(widget)?;
+    let variant = state.widget_variant(widget, state.experiments.assign(None));
+    let png_data = source
+        .fetch_image(&image_path, orientation, &variant)
+        .await?;
+    let preview = image_processing::preview_png(&png_data, scale, query.texture.unwrap_or(false))?;
+
+    Ok((
+        [(header::CACHE_CONTROL, "public, max-age=86400")],
+        conditional_response(&headers, "image/png", preview),
+    )
+        .into_response())
+}
+
 /// Get OpenAPI JSON specification
 async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
     Json(ApiDoc::openapi())
 }
 
-/// Get concerts data
+/// Get widget data
+///
+/// Returns the items to display for a widget: each item's image path, its width
+/// (2 for full-width items, 800x480 in horizontal mode filling the whole panel),
+/// the key devices cache its image under, how long that image stays current, a
+/// title and alt text for screen readers. Devices on the `minimal` bandwidth
+/// profile get at most the first 16. Requests from frames (with `X-Device-Id`)
+/// leave out the alt text, which a frame never shows, so long lists still fit
+/// its buffer.
 ///
-/// Returns a list of concert items to display.
+/// Cache keys differ per rendering variant, so a device whose experiment variant,
+/// panel or dithering changes doesn't keep its old renders.
+///
+/// An expired list is served straight away while it's refreshed in the
+/// background, and kept being served if the upstream API is failing. It's
+/// flagged with `X-Data-Stale: true` and its age in seconds in `X-Stale-Age`,
+/// plus `X-Data-Revalidating: true` while the refresh is underway.
 #[utoipa::path(
     get,
-    path = "/concerts",
-    tag = "Concerts",
+    path = "/{widget}",
+    tag = "Widgets",
+    params(
+        ("widget" = WidgetName, Path, description = "Widget name")
+    ),
     responses(
-        (status = 200, description = "Concert data", body = Vec<String>)
+        (status = 200, description = "Widget items", body = Vec<WidgetItem>),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 404, description = "Widget not configured")
     )
 )]
-async fn get_concerts_data(State(state): State<AppState>) -> impl IntoResponse {
-    let source = state.registry.get(WidgetName::Concerts);
+async fn get_widget_data(
+    State(state): State<AppState>,
+    Path(widget): Path<WidgetName>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    let device_id = state.devices.record_request(&headers).await;
+    let bandwidth = state.devices.bandwidth(device_id).await;
+    let variant = state
+        .widget_variant(widget, state.experiments.assign(device_id))
+        .with_panel(state.devices.panel(device_id).await);
+    let source = state.registry.get(widget)?;
     let items = source.fetch_data().await;
     let cache_policy = source.data_cache_policy();
+    let stale_age = source.stale_age().await;
+    let revalidating = source.revalidating();
 
     match items {
-        Ok(items) => Ok((
-            [(
-                header::HeaderName::from_static("x-cache-policy"),
-                cache_policy.to_string(),
-            )],
-            Json(items),
-        )),
+        Ok(mut items) => {
+            if let Some(max_items) = bandwidth.max_items() {
+                items.truncate(max_items);
+            }
+            let items: Vec<WidgetItem> = items
+                .into_iter()
+                .map(|item| item.for_variant(&variant.name))
+                .map(|item| match device_id {
+                    Some(_) => WidgetItem { alt: None, ..item },
+                    None => item,
+                })
+                .collect();
+            let body = serde_json::to_vec(&items).expect("widget items serialize to JSON");
+            let mut cache_policy = CachePolicyHeader::new(&cache_policy);
+            if stale_age.is_some() {
+                cache_policy = cache_policy.with_suggested_sleep(STALE_RETRY_SECS);
+            }
+            let mut response = (
+                [(
+                    header::HeaderName::from_static(CachePolicyHeader::NAME),
+                    cache_policy.to_string(),
+                )],
+                compressed_response(&headers, "application/json", body),
+            )
+                .into_response();
+            if let Some(age) = stale_age {
+                let response_headers = response.headers_mut();
+                response_headers
+                    .insert(DATA_STALE_HEADER, header::HeaderValue::from_static("true"));
+                response_headers.insert(STALE_AGE_HEADER, header::HeaderValue::from(age.as_secs()));
+                if revalidating {
+                    response_headers.insert(
+                        DATA_REVALIDATING_HEADER,
+                        header::HeaderValue::from_static("true"),
+                    );
+                }
+            }
+            Ok(response)
+        }
         Err(e) => Err(e),
     }
 }
 
-/// Get processed concert image
+/// Whether the request's `Accept` header asks for EPD-native images
+fn accepts_epd(request_headers: &HeaderMap) -> bool {
+    header_accepts(request_headers, header::ACCEPT, EPD_CONTENT_TYPE)
+}
+
+/// Whether the request's `Accept-Encoding` header allows a gzipped body
+fn accepts_gzip(request_headers: &HeaderMap) -> bool {
+    header_accepts(request_headers, header::ACCEPT_ENCODING, "gzip")
+}
+
+/// Whether a comma-separated `Accept`-style header lists `token` with a nonzero q
+fn header_accepts(request_headers: &HeaderMap, name: header::HeaderName, token: &str) -> bool {
+    request_headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|range| {
+            let mut params = range.split(';');
+            let media_type = params.next().unwrap_or_default().trim();
+            // An explicit q=0 rules the type out
+            media_type.eq_ignore_ascii_case(token)
+                && !params.any(|param| {
+                    param
+                        .trim()
+                        .strip_prefix("q=")
+                        .and_then(|q| q.parse::<f32>().ok())
+                        .is_some_and(|q| q <= 0.0)
+                })
+        })
+}
+
+/// Strong ETag for a response body
+///
+/// A CRC-32 of the bytes, the same checksum the firmware stores in its SD cache
+/// footers, so a cached image's ETag is known without extra bookkeeping.
+fn body_etag(body: &[u8]) -> String {
+    format!("\"{:08x}\"", crc32fast::hash(body))
+}
+
+/// Respond with `body` and its ETag, or a bodyless 304 if `If-None-Match` matches
+fn conditional_response(
+    request_headers: &HeaderMap,
+    content_type: &'static str,
+    body: Vec<u8>,
+) -> Response {
+    let etag = body_etag(&body);
+    tagged_response(request_headers, content_type, body, etag)
+}
+
+/// Like [`conditional_response`], but gzips the body when the client allows it
+///
+/// The ETag stays that of the uncompressed body, since that is what the
+/// firmware caches and checksums after inflating it.
+fn compressed_response(
+    request_headers: &HeaderMap,
+    content_type: &'static str,
+    body: Vec<u8>,
+) -> Response {
+    let etag = body_etag(&body);
+    let mut response = if accepts_gzip(request_headers) {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&body)
+            .expect("writing to a Vec cannot fail");
+        let compressed = encoder.finish().expect("writing to a Vec cannot fail");
+        let mut response = tagged_response(request_headers, content_type, compressed, etag);
+        if response.status() == StatusCode::OK {
+            response.headers_mut().insert(
+                header::CONTENT_ENCODING,
+                header::HeaderValue::from_static("gzip"),
+            );
+        }
+        response
+    } else {
+        tagged_response(request_headers, content_type, body, etag)
+    };
+    response.headers_mut().insert(
+        header::VARY,
+        header::HeaderValue::from_static("accept-encoding"),
+    );
+    response
+}
+
+/// Respond with `body` under a precomputed `etag`, honouring `If-None-Match`
+fn tagged_response(
+    request_headers: &HeaderMap,
+    content_type: &'static str,
+    body: Vec<u8>,
+    etag: String,
+) -> Response {
+    let not_modified = request_headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        });
+
+    if not_modified {
+        (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response()
+    } else {
+        (
+            [
+                (header::ETAG, etag),
+                (header::CONTENT_TYPE, content_type.to_string()),
+            ],
+            body,
+        )
+            .into_response()
+    }
+}
+
+/// Pre-render all concert images
+///
+/// Starts a background job that renders every concert item in both orientations
+/// (and every experiment variant) into the cache, so devices don't wait on image
+/// lookup and processing.
+#[utoipa::path(
+    post,
+    path = "/concerts/prerender",
+    tag = "Concerts",
+    responses(
+        (status = 202, description = "Pre-render started", body = PrerenderStatus),
+        (status = 409, description = "Pre-render already running", body = PrerenderStatus)
+    )
+)]
+async fn prerender_concerts(State(state): State<AppState>) -> Result<impl IntoResponse, AppError> {
+    let source = state.registry.get(WidgetName::Concerts)?;
+    let variants = state
+        .experiments
+        .variants()
+        .iter()
+        .map(|variant| state.widget_variant(WidgetName::Concerts, variant))
+        .collect();
+    let status = if state.prerender.start(source, variants) {
+        StatusCode::ACCEPTED
+    } else {
+        StatusCode::CONFLICT
+    };
+    Ok((status, Json(state.prerender.status().await)))
+}
+
+/// Get memory diagnostics
+///
+/// Returns the last day of hourly samples of resident memory, image cache sizes
+/// and shared state reference counts, and the metrics that grew at each of the
+/// last few samples.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "Admin",
+    responses(
+        (status = 200, description = "Diagnostics samples", body = DiagnosticsReport)
+    )
+)]
+async fn get_metrics(State(state): State<AppState>) -> Json<DiagnosticsReport> {
+    Json(state.diagnostics.report().await)
+}
+
+/// Device status dashboard
+///
+/// An HTML page showing each device's latest screenshot, the last image it
+/// fetched, its battery level, last check-in and queued requests, with devices
+/// that have missed two refreshes flagged, followed by the image cache sizes.
+#[utoipa::path(
+    get,
+    path = "/dashboard",
+    tag = "Admin",
+    responses(
+        (status = 200, description = "Dashboard page", content((String = "text/html")))
+    )
+)]
+async fn get_dashboard(State(state): State<AppState>) -> Html<String> {
+    let devices = dashboard::device_statuses(&state.devices, &state.config).await;
+    let caches = state.registry.cache_stats().await;
+    Html(dashboard::render_html(&devices, &caches, unix_now()))
+}
+
+/// Get pre-render status
 ///
-/// Returns a processed PNG image for a concert item.
+/// Returns whether a pre-render job is running and the result of the last one.
 #[utoipa::path(
     get,
-    path = "/concerts/{orientation}/{image_path}",
+    path = "/concerts/prerender",
     tag = "Concerts",
+    responses(
+        (status = 200, description = "Pre-render status", body = PrerenderStatus)
+    )
+)]
+async fn get_prerender_status(State(state): State<AppState>) -> Json<PrerenderStatus> {
+    Json(state.prerender.status().await)
+}
+
+/// Get processed widget image
+///
+/// Returns a processed PNG image for a widget item. Devices sending `X-Device-Id`
+/// are assigned to a variant of the active experiment, reported in `X-Render-Variant`.
+/// Devices on a `reduced` or `minimal` bandwidth profile get the PNG re-encoded at
+/// 4 bits per pixel with maximum compression.
+///
+/// `dither` picks the dithering algorithm (`fs`, `atkinson`, `jjn`, `ordered` or
+/// `none`), overriding the widget's configured one and the experiment variant's.
+///
+/// Devices whose settings name a `bw` or `gray4` panel get images dithered to 2
+/// or 4 gray levels instead of the 6-color palette.
+///
+/// With `format=epd` the body is instead the panel's native framebuffer format:
+/// EPD color codes packed two pixels per byte (left pixel in the high nibble),
+/// already rotated the way the firmware lays them out on the panel. The dimensions
+/// are reported in `X-Epd-Width` and `X-Epd-Height`.
+///
+/// Without `format`, devices sending `Accept: application/x-epd-4bpp` get the same
+/// format, unless their panel is `gray4` or their bandwidth profile asks for
+/// compact images, which fall back to PNG.
+///
+/// `rotate=ccw90` delivers vertical PNGs rotated into the panel's 800x480
+/// geometry, so devices can write the rows as they come instead of rotating each
+/// pixel. Horizontal and EPD-native images are already in panel geometry and
+/// ignore it.
+#[utoipa::path(
+    get,
+    path = "/{widget}/{orientation}/{image_path}",
+    tag = "Widgets",
     params(
+        ("widget" = WidgetName, Path, description = "Widget name"),
         ("orientation" = Orientation, Path, description = "Display orientation: horiz (400x480 or 800x480) or vert (480x800)"),
-        ("image_path" = String, Path, description = "Path to the image resource")
+        ("image_path" = String, Path, description = "Path to the image resource"),
+        ("format" = Option<ImageFormat>, Query, description = "Output format: png or epd (default: negotiated from Accept, else png)"),
+        ("dither" = Option<DitherMode>, Query, description = "Dithering algorithm, overriding the widget's and the experiment variant's"),
+        ("rotate" = Option<ImageRotation>, Query, description = "Rotate vertical PNGs into panel geometry (ccw90)"),
+        ("X-Device-Id" = Option<String>, Header, description = "Device identifier for experiment assignment"),
+        ("Accept" = Option<String>, Header, description = "application/x-epd-4bpp for EPD-native images when format is not given")
     ),
     responses(
-        (status = 200, description = "Processed image", content_type = "image/png"),
-        (status = 400, description = "Invalid orientation or path"),
-        (status = 404, description = "Image not found")
+        (status = 200, description = "Processed image", content(("image/png"), ("application/x-epd-4bpp"))),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 400, description = "Invalid orientation or path, or EPD format for a gray4 panel"),
+        (status = 404, description = "Image not found or widget not configured")
     )
 )]
-async fn get_concerts_image(
+async fn get_widget_image(
     State(state): State<AppState>,
-    Path((orientation, image_path)): Path<(Orientation, String)>,
+    Path((widget, orientation, image_path)): Path<(WidgetName, Orientation, String)>,
+    Query(query): Query<ImageQuery>,
+    headers: HeaderMap,
 ) -> Result<Response, AppError> {
     tracing::info!(
-        "Image request: concerts, orientation={:?}, path={}",
+        "Image request: {:?}, orientation={:?}, path={}, format={:?}",
+        widget,
         orientation,
-        image_path
+        image_path,
+        query.format
     );
 
-    let source = state.registry.get(WidgetName::Concerts);
-    let png_data = source.fetch_image(&image_path, orientation).await?;
+    let source = state.registry.get(widget)?;
+    let device_id = state.devices.record_request(&headers).await;
+    if let Some(id) = device_id {
+        let image = ImageRef {
+            widget,
+            orientation,
+            path: image_path.clone(),
+        };
+        state.devices.record_image(id, image).await;
+    }
+    let variant = state.experiments.assign(device_id);
+    let panel = state.devices.panel(device_id).await;
+    if panel == PanelType::Gray4 && query.format == Some(ImageFormat::Epd) {
+        return Err(AppError::InvalidPath(
+            "EPD format is only available for color and black-and-white panels".to_string(),
+        ));
+    }
+    let render_variant = match query.dither {
+        Some(dither) => variant.with_dither(dither),
+        None => state.widget_variant(widget, variant),
+    }
+    .with_panel(panel);
+    let bandwidth = state.devices.bandwidth(device_id).await;
+    let format = match query.format {
+        Some(format) => format,
+        None if accepts_epd(&headers)
+            && panel != PanelType::Gray4
+            && !bandwidth.compact_images() =>
+        {
+            ImageFormat::Epd
+        }
+        None => ImageFormat::Png,
+    };
+    let mut png_data = source
+        .fetch_image(&image_path, orientation, &render_variant)
+        .await?;
+    if query.rotate == Some(ImageRotation::Ccw90)
+        && orientation == Orientation::Vert
+        && format == ImageFormat::Png
+    {
+        png_data = image_processing::rotate_png_ccw90(&png_data)?;
+    }
 
-    Ok((
-        StatusCode::OK,
-        [
-            (header::CONTENT_TYPE, "image/png"),
-            (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
-        ],
-        png_data,
-    )
-        .into_response())
+    let mut response = match format {
+        ImageFormat::Png if bandwidth.compact_images() => (
+            [(
+                header::CACHE_CONTROL,
+                "private, max-age=31536000, immutable",
+            )],
+            conditional_response(
+                &headers,
+                "image/png",
+                image_processing::compact_png(&png_data)?,
+            ),
+        )
+            .into_response(),
+        ImageFormat::Png => (
+            [(header::CACHE_CONTROL, "public, max-age=31536000, immutable")],
+            conditional_response(&headers, "image/png", png_data),
+        )
+            .into_response(),
+        ImageFormat::Epd => {
+            let image = epd::png_to_epd(&png_data, orientation)?;
+            (
+                [
+                    (
+                        header::CACHE_CONTROL,
+                        "public, max-age=31536000, immutable".to_string(),
+                    ),
+                    (
+                        header::HeaderName::from_static(EPD_WIDTH_HEADER),
+                        image.width.to_string(),
+                    ),
+                    (
+                        header::HeaderName::from_static(EPD_HEIGHT_HEADER),
+                        image.height.to_string(),
+                    ),
+                ],
+                conditional_response(&headers, EPD_CONTENT_TYPE, image.data),
+            )
+                .into_response()
+        }
+    };
+
+    if query.format.is_none() {
+        // The format was picked from the Accept header
+        response
+            .headers_mut()
+            .append(header::VARY, header::HeaderValue::from_static("accept"));
+    }
+    if let Some(experiment) = state.experiments.name() {
+        if let Some(id) = device_id {
+            state.experiments.record_view(id, variant).await;
+        }
+        let headers = response.headers_mut();
+        // The same URL renders differently per device while an experiment runs
+        headers.append(
+            header::VARY,
+            header::HeaderValue::from_static(DEVICE_ID_HEADER),
+        );
+        if let Ok(value) =
+            header::HeaderValue::from_str(&format!("{}/{}", experiment, variant.name))
+        {
+            headers.insert(RENDER_VARIANT_HEADER, value);
+        }
+    }
+
+    Ok(response)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::image_processing::{extract_primary_color, process_image_with_color};
+    use crate::image_processing::{extract_primary_color, process_image_with_color, RenderParams};
     use crate::text::ConcertInfo;
     use crate::widget::WidgetWidth;
     use std::fs;
     use std::path::Path;
 
+    #[test]
+    fn test_conditional_response() {
+        // Same CRC-32 as the firmware's cache footer
+        assert_eq!(body_etag(b"IEND"), "\"ae426082\"");
+
+        let mut headers = HeaderMap::new();
+        let response = conditional_response(&headers, "image/png", b"IEND".to_vec());
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ETAG], "\"ae426082\"");
+
+        headers.insert(
+            header::IF_NONE_MATCH,
+            "\"00000000\", \"ae426082\"".parse().unwrap(),
+        );
+        let response = conditional_response(&headers, "image/png", b"IEND".to_vec());
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        headers.insert(header::IF_NONE_MATCH, "\"00000000\"".parse().unwrap());
+        let response = conditional_response(&headers, "image/png", b"IEND".to_vec());
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_compressed_response() {
+        let body = br#"[{"id":"a"},{"id":"a"},{"id":"a"}]"#.to_vec();
+        let etag = body_etag(&body);
+
+        let response = compressed_response(&HeaderMap::new(), "application/json", body.clone());
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(response.headers()[header::VARY], "accept-encoding");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_ENCODING, "gzip, deflate".parse().unwrap());
+        let response = compressed_response(&headers, "application/json", body.clone());
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(response.headers()[header::ETAG], etag.as_str());
+
+        // The ETag of the uncompressed body still matches a compressed request
+        headers.insert(header::IF_NONE_MATCH, etag.parse().unwrap());
+        let response = compressed_response(&headers, "application/json", body);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+
+        headers.insert(header::ACCEPT_ENCODING, "gzip;q=0".parse().unwrap());
+        assert!(!accepts_gzip(&headers));
+    }
+
+    #[test]
+    fn test_accepts_epd() {
+        let accept = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, value.parse().unwrap());
+            accepts_epd(&headers)
+        };
+        assert!(!accepts_epd(&HeaderMap::new()));
+        assert!(accept("application/x-epd-4bpp"));
+        assert!(accept("application/x-epd-4bpp, image/png;q=0.5"));
+        assert!(accept("image/png, Application/X-EPD-4bpp;q=0.9"));
+        assert!(!accept("application/x-epd-4bpp;q=0"));
+        assert!(!accept("image/png, */*"));
+    }
+
     /// Concert data: (filename, band_name, date, venue, image_url)
     /// Uses Deezer album art URLs for period-appropriate artwork
     const EXAMPLE_CONCERTS: &[(&str, &str, &str, &str, &str)] = &[
@@ -294,13 +1933,18 @@ mod tests {
                 extract_primary_color(&image_data).expect("Failed to extract color");
             println!(
                 "  Primary color: RGB({}, {}, {}), light: {}",
-                primary_color.r, primary_color.g, primary_color.b, primary_color.is_light
+                primary_color.r,
+                primary_color.g,
+                primary_color.b,
+                primary_color.is_light()
             );
 
             let concert_info = ConcertInfo {
                 band_name: band_name.to_string(),
                 date: date.to_string(),
                 venue: venue.to_string(),
+                detail: None,
+                note: None,
             };
 
             // Generate horizontal image (400x480)
@@ -311,6 +1955,8 @@ mod tests {
                 horiz_height,
                 Some(&concert_info),
                 &primary_color,
+                &RenderParams::default(),
+                Some(*image_url),
             )
             .expect("Failed to process horizontal image");
 
@@ -326,6 +1972,8 @@ mod tests {
                 vert_height,
                 Some(&concert_info),
                 &primary_color,
+                &RenderParams::default(),
+                Some(*image_url),
             )
             .expect("Failed to process vertical image");
 
@@ -0,0 +1,376 @@
+//! MusicBrainz and Cover Art Archive integration
+//!
+//! Second source of album art, for concerts Deezer has nothing usable for: the
+//! artist's albums come from MusicBrainz and their covers from the Cover Art
+//! Archive. MusicBrainz asks clients to send at most one request per second,
+//! so requests to it are spaced out, and both lookups are cached for a week
+//! (misses included) since the same bands come up for every concert.
+
+use chrono::NaiveDate;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+
+use crate::config::MusicBrainzConfig;
+use crate::error::AppError;
+
+/// MusicBrainz API base URL
+pub const MUSICBRAINZ_API_URL: &str = "https://musicbrainz.org/ws/2";
+
+/// Cover Art Archive base URL
+pub const COVERART_API_URL: &str = "https://coverartarchive.org";
+
+/// MusicBrainz rejects requests without an identifying user agent
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// How long a lookup is reused, found or not
+const LOOKUP_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Shortest gap between MusicBrainz requests
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Lowest search score accepted for an artist match
+const MIN_ARTIST_SCORE: u8 = 90;
+
+/// Albums checked for cover art, closest to the concert first
+const MAX_COVER_LOOKUPS: usize = 3;
+
+/// Artist search response
+#[derive(Debug, Deserialize)]
+struct ArtistSearchResponse {
+    #[serde(default)]
+    artists: Vec<Artist>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Artist {
+    id: String,
+    name: String,
+    #[serde(default)]
+    score: u8,
+}
+
+/// Release group browse response
+#[derive(Debug, Deserialize)]
+struct ReleaseGroupsResponse {
+    #[serde(rename = "release-groups", default)]
+    release_groups: Vec<ReleaseGroup>,
+}
+
+/// An album on MusicBrainz, across all its releases
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReleaseGroup {
+    pub id: String,
+    pub title: String,
+    /// YYYY, YYYY-MM or YYYY-MM-DD
+    #[serde(rename = "first-release-date", default)]
+    pub first_release_date: Option<String>,
+}
+
+impl ReleaseGroup {
+    /// First release date, on the first of the month or year if that's all
+    /// MusicBrainz knows
+    pub fn release_date(&self) -> Option<NaiveDate> {
+        let date = self.first_release_date.as_deref()?;
+        let mut parts = date.splitn(3, '-').map(str::parse::<u32>);
+        let year = parts.next()?.ok()? as i32;
+        let month = parts.next().transpose().ok()?.unwrap_or(1);
+        let day = parts.next().transpose().ok()?.unwrap_or(1);
+        NaiveDate::from_ymd_opt(year, month, day)
+    }
+}
+
+/// Cover Art Archive listing for a release group
+#[derive(Debug, Deserialize)]
+struct CoverArtResponse {
+    #[serde(default)]
+    images: Vec<CoverArtImage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CoverArtImage {
+    #[serde(default)]
+    front: bool,
+    image: String,
+    #[serde(default)]
+    thumbnails: HashMap<String, String>,
+}
+
+impl CoverArtImage {
+    /// A panel-sized thumbnail, or the original upload
+    fn url(&self) -> &str {
+        ["1200", "500", "large"]
+            .iter()
+            .find_map(|size| self.thumbnails.get(*size))
+            .unwrap_or(&self.image)
+    }
+}
+
+/// An album picked for a concert, with its cover
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MusicBrainzAlbum {
+    pub title: String,
+    /// YYYY-MM-DD
+    pub release_date: String,
+    /// Days from the album's release to the concert
+    pub days_before: i64,
+    pub cover_url: String,
+}
+
+/// Albums released on or before `concert`, closest first
+pub fn albums_before(
+    groups: &[ReleaseGroup],
+    concert: NaiveDate,
+) -> Vec<(&ReleaseGroup, NaiveDate)> {
+    let mut albums: Vec<_> = groups
+        .iter()
+        .filter_map(|group| Some((group, group.release_date()?)))
+        .filter(|(_, release)| *release <= concert)
+        .collect();
+    albums.sort_by_key(|(_, release)| std::cmp::Reverse(*release));
+    albums
+}
+
+/// Lookup results by key, with when they were fetched
+type LookupCache<T> = RwLock<HashMap<String, (Instant, T)>>;
+
+/// MusicBrainz client with lookup caches
+pub struct MusicBrainz {
+    client: Client,
+    config: MusicBrainzConfig,
+    /// Albums by lowercased artist name, `None` if the artist wasn't found
+    albums: LookupCache<Option<Vec<ReleaseGroup>>>,
+    /// Front cover by release group ID
+    covers: LookupCache<Option<String>>,
+    /// When the last MusicBrainz request was sent
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl MusicBrainz {
+    pub fn new(client: Client, config: MusicBrainzConfig) -> Self {
+        Self {
+            client,
+            config,
+            albums: RwLock::new(HashMap::new()),
+            covers: RwLock::new(HashMap::new()),
+            last_request: Mutex::new(None),
+        }
+    }
+
+    /// The album with cover art released closest before a DD-MM-YYYY concert
+    /// date, skipping titles `blocked` rejects
+    ///
+    /// `None` if the artist isn't on MusicBrainz or none of the few closest
+    /// albums has cover art.
+    pub async fn album_for_concert(
+        &self,
+        artist: &str,
+        concert_date: &str,
+        blocked: impl Fn(&str) -> bool,
+    ) -> Result<Option<MusicBrainzAlbum>, AppError> {
+        let Ok(concert) = NaiveDate::parse_from_str(concert_date, "%d-%m-%Y") else {
+            return Ok(None);
+        };
+        let Some(groups) = self.artist_albums(artist).await? else {
+            tracing::debug!("Artist not found on MusicBrainz: {}", artist);
+            return Ok(None);
+        };
+
+        let candidates = albums_before(&groups, concert)
+            .into_iter()
+            .filter(|(group, _)| !blocked(&group.title))
+            .take(MAX_COVER_LOOKUPS);
+        for (group, release) in candidates {
+            if let Some(cover_url) = self.front_cover(&group.id).await? {
+                return Ok(Some(MusicBrainzAlbum {
+                    title: group.title.clone(),
+                    release_date: release.format("%Y-%m-%d").to_string(),
+                    days_before: (concert - release).num_days(),
+                    cover_url,
+                }));
+            }
+            tracing::debug!("No cover art for '{}' by {}", group.title, artist);
+        }
+        Ok(None)
+    }
+
+    /// An artist's albums, from the cache or the API
+    async fn artist_albums(&self, artist: &str) -> Result<Option<Vec<ReleaseGroup>>, AppError> {
+        let key = artist.to_lowercase();
+        if let Some((fetched_at, albums)) = self.albums.read().await.get(&key) {
+            if fetched_at.elapsed() < LOOKUP_TTL {
+                return Ok(albums.clone());
+            }
+        }
+
+        let albums = match self.search_artist(artist).await? {
+            Some(id) => Some(self.browse_albums(&id).await?),
+            None => None,
+        };
+        self.albums
+            .write()
+            .await
+            .insert(key, (Instant::now(), albums.clone()));
+        Ok(albums)
+    }
+
+    /// The MusicBrainz ID of the artist named `artist`
+    async fn search_artist(&self, artist: &str) -> Result<Option<String>, AppError> {
+        let query = format!("artist:\"{}\"", artist.replace('"', ""));
+        let response: ArtistSearchResponse = self
+            .get(
+                &format!("{}/artist", self.config.api_url),
+                &[("query", query.as_str()), ("limit", "5")],
+            )
+            .await?;
+        // Searches are fuzzy: only take a confident match with the same name
+        Ok(response
+            .artists
+            .into_iter()
+            .find(|a| a.score >= MIN_ARTIST_SCORE && a.name.eq_ignore_ascii_case(artist))
+            .map(|a| a.id))
+    }
+
+    /// An artist's albums (singles and EPs left out)
+    async fn browse_albums(&self, artist_id: &str) -> Result<Vec<ReleaseGroup>, AppError> {
+        let response: ReleaseGroupsResponse = self
+            .get(
+                &format!("{}/release-group", self.config.api_url),
+                &[("artist", artist_id), ("type", "album"), ("limit", "100")],
+            )
+            .await?;
+        Ok(response.release_groups)
+    }
+
+    /// GET a MusicBrainz API URL, waiting for the request's turn
+    async fn get<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, AppError> {
+        // Hold the slot until the request is sent, so concurrent lookups queue up
+        let mut last_request = self.last_request.lock().await;
+        if let Some(wait) = last_request.map(|at| REQUEST_INTERVAL.saturating_sub(at.elapsed())) {
+            tokio::time::sleep(wait).await;
+        }
+        *last_request = Some(Instant::now());
+
+        let response = self
+            .client
+            .get(url)
+            .query(query)
+            .query(&[("fmt", "json")])
+            .header("User-Agent", USER_AGENT)
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+        drop(last_request);
+
+        if !response.status().is_success() {
+            return Err(AppError::ExternalApi(format!(
+                "MusicBrainz API returned status: {}",
+                response.status()
+            )));
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Front cover of a release group, from the cache or the Cover Art Archive
+    async fn front_cover(&self, release_group_id: &str) -> Result<Option<String>, AppError> {
+        if let Some((fetched_at, url)) = self.covers.read().await.get(release_group_id) {
+            if fetched_at.elapsed() < LOOKUP_TTL {
+                return Ok(url.clone());
+            }
+        }
+
+        let response = self
+            .client
+            .get(format!(
+                "{}/release-group/{}",
+                self.config.coverart_url, release_group_id
+            ))
+            .header("User-Agent", USER_AGENT)
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+        // A release group without art is reported as not found
+        let url = if response.status() == StatusCode::NOT_FOUND {
+            None
+        } else if response.status().is_success() {
+            let response: CoverArtResponse = response.json().await?;
+            response
+                .images
+                .iter()
+                .find(|image| image.front)
+                .map(|image| image.url().to_string())
+        } else {
+            return Err(AppError::ExternalApi(format!(
+                "Cover Art Archive returned status: {}",
+                response.status()
+            )));
+        };
+
+        self.covers
+            .write()
+            .await
+            .insert(release_group_id.to_string(), (Instant::now(), url.clone()));
+        Ok(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(title: &str, date: Option<&str>) -> ReleaseGroup {
+        ReleaseGroup {
+            id: title.to_lowercase(),
+            title: title.to_string(),
+            first_release_date: date.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_release_date() {
+        let date = |value| group("Album", value).release_date();
+        assert_eq!(
+            date(Some("2019-05-17")),
+            NaiveDate::from_ymd_opt(2019, 5, 17)
+        );
+        assert_eq!(date(Some("2019-05")), NaiveDate::from_ymd_opt(2019, 5, 1));
+        assert_eq!(date(Some("2019")), NaiveDate::from_ymd_opt(2019, 1, 1));
+        assert_eq!(date(Some("")), None);
+        assert_eq!(date(None), None);
+    }
+
+    #[test]
+    fn test_albums_before() {
+        let groups = vec![
+            group("First", Some("2010-03-01")),
+            group("Undated", None),
+            group("Third", Some("2018")),
+            group("Next", Some("2024-09-20")),
+        ];
+        let concert = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let titles: Vec<_> = albums_before(&groups, concert)
+            .iter()
+            .map(|(group, _)| group.title.as_str())
+            .collect();
+        assert_eq!(titles, ["Third", "First"]);
+    }
+
+    #[test]
+    fn test_cover_url() {
+        let json = r#"{"images": [
+            {"front": false, "image": "http://example.com/back.jpg", "thumbnails": {}},
+            {"front": true, "image": "http://example.com/front.jpg",
+             "thumbnails": {"250": "http://example.com/front-250.jpg", "500": "http://example.com/front-500.jpg"}}
+        ]}"#;
+        let response: CoverArtResponse = serde_json::from_str(json).unwrap();
+        let front = response.images.iter().find(|image| image.front).unwrap();
+        assert_eq!(front.url(), "http://example.com/front-500.jpg");
+        assert_eq!(response.images[0].url(), "http://example.com/back.jpg");
+    }
+}
@@ -2,6 +2,13 @@
 //!
 //! Uses OKLab color space for perceptually uniform color matching.
 //! Palette values from aitjcize/esp32-photoframe (measured e-paper colors).
+//!
+//! Dithering matches every pixel of a card against the palette, so matching is
+//! sped up with a lookup cube over OKLab space: each cell lists the palette
+//! colors that can be nearest anywhere inside it (usually just one), and only
+//! those are compared. The result is exactly what comparing all of them gives.
+
+use std::sync::OnceLock;
 
 /// RGB color representation
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -39,12 +46,17 @@ impl Oklab {
     /// Convert sRGB byte to linear
     #[inline]
     fn srgb_to_linear(c: u8) -> f32 {
-        let c = c as f32 / 255.0;
-        if c <= 0.04045 {
-            c / 12.92
-        } else {
-            ((c + 0.055) / 1.055).powf(2.4)
-        }
+        static LINEAR: OnceLock<[f32; 256]> = OnceLock::new();
+        LINEAR.get_or_init(|| {
+            std::array::from_fn(|c| {
+                let c = c as f32 / 255.0;
+                if c <= 0.04045 {
+                    c / 12.92
+                } else {
+                    ((c + 0.055) / 1.055).powf(2.4)
+                }
+            })
+        })[c as usize]
     }
 
     /// Convert linear to sRGB byte
@@ -149,41 +161,86 @@ pub const PNG_PALETTE: [u8; 18] = [
     39, 102, 60, // Green
 ];
 
+/// Gray levels of black-and-white panels, indexed like `PALETTE`: black and
+/// white keep indices 0 and 1 so text drawn on either renders unchanged, and the
+/// two intermediate grays of 4-level panels follow
+pub const GRAY_PALETTE: [Rgb; 4] = [
+    Rgb::new(2, 2, 2),       // Black
+    Rgb::new(232, 232, 232), // White
+    Rgb::new(79, 79, 79),    // Dark gray
+    Rgb::new(155, 155, 155), // Light gray
+];
+
+/// PNG palette bytes (RGB triplets) for 4-level grayscale images
+pub const PNG_GRAY_PALETTE: [u8; 12] = [
+    2, 2, 2, // Black
+    232, 232, 232, // White
+    79, 79, 79, // Dark gray
+    155, 155, 155, // Light gray
+];
+
+/// Cells along each axis of the lookup cube
+const CUBE_CELLS: usize = 32;
+
+/// OKLab (L, a, b) corner of the lookup cube
+///
+/// Diffused error pushes colors past the sRGB gamut, so the cube reaches
+/// beyond it; colors outside it are compared against the whole palette.
+const CUBE_MIN: [f32; 3] = [-0.25, -0.5, -0.5];
+
+/// Opposite corner of the lookup cube
+const CUBE_MAX: [f32; 3] = [1.25, 0.5, 0.5];
+
+/// Every palette color, as a candidate mask
+const ALL_CANDIDATES: u8 = (1 << PALETTE.len()) - 1;
+
 /// Palette matcher using OKLab perceptual distance
 pub struct OklabPalette {
     /// Precomputed OKLab values for each palette color
     palette_oklab: [Oklab; 6],
+    /// Palette colors that can be nearest in each cell of the lookup cube, as
+    /// bit masks (indexed L-major)
+    cube: &'static [u8],
 }
 
 impl OklabPalette {
     pub fn new() -> Self {
+        let palette_oklab = PALETTE.map(Rgb::to_oklab);
+        static CUBE: OnceLock<Vec<u8>> = OnceLock::new();
+        let cube = CUBE.get_or_init(|| build_cube(&palette_oklab));
         Self {
-            palette_oklab: [
-                PALETTE[0].to_oklab(),
-                PALETTE[1].to_oklab(),
-                PALETTE[2].to_oklab(),
-                PALETTE[3].to_oklab(),
-                PALETTE[4].to_oklab(),
-                PALETTE[5].to_oklab(),
-            ],
+            palette_oklab,
+            cube,
         }
     }
 
     /// Find nearest palette color using OKLab perceptual distance
     #[inline]
     pub fn nearest(&self, color: &Oklab) -> PaletteIndex {
+        let candidates = cube_cell(color).map_or(ALL_CANDIDATES, |cell| self.cube[cell]);
+        // A single candidate needs no comparing
+        if candidates.is_power_of_two() {
+            return Self::index(candidates.trailing_zeros() as usize);
+        }
+
         let mut best_index = 0;
         let mut best_dist = f32::MAX;
-
-        for (i, p) in self.palette_oklab.iter().enumerate() {
-            let dist = color.distance_squared(p);
+        let mut remaining = candidates;
+        while remaining != 0 {
+            let i = remaining.trailing_zeros() as usize;
+            remaining &= remaining - 1;
+            let dist = color.distance_squared(&self.palette_oklab[i]);
             if dist < best_dist {
                 best_dist = dist;
                 best_index = i;
             }
         }
 
-        match best_index {
+        Self::index(best_index)
+    }
+
+    fn index(i: usize) -> PaletteIndex {
+        match i {
             0 => PaletteIndex::Black,
             1 => PaletteIndex::White,
             2 => PaletteIndex::Red,
@@ -206,12 +263,128 @@ impl Default for OklabPalette {
     }
 }
 
-/// Extracted dominant color with RGB values and lightness info
+/// Index of the lookup cube cell holding `color`, if it's inside the cube
+#[inline]
+fn cube_cell(color: &Oklab) -> Option<usize> {
+    let mut cell = 0;
+    for (axis, value) in [color.l, color.a, color.b].into_iter().enumerate() {
+        let position =
+            (value - CUBE_MIN[axis]) / (CUBE_MAX[axis] - CUBE_MIN[axis]) * CUBE_CELLS as f32;
+        // Also false for NaN
+        if !(0.0..CUBE_CELLS as f32).contains(&position) {
+            return None;
+        }
+        cell = cell * CUBE_CELLS + position as usize;
+    }
+    Some(cell)
+}
+
+/// Candidate masks of every lookup cube cell
+///
+/// A color can only be nearest somewhere in a cell if its distance to the
+/// closest point of the cell is no more than some color's distance to the
+/// farthest point: that color is at least that close everywhere in the cell.
+fn build_cube(palette: &[Oklab; 6]) -> Vec<u8> {
+    let size = |axis: usize| (CUBE_MAX[axis] - CUBE_MIN[axis]) / CUBE_CELLS as f32;
+    let mut cube = Vec::with_capacity(CUBE_CELLS.pow(3));
+    for l in 0..CUBE_CELLS {
+        for a in 0..CUBE_CELLS {
+            for b in 0..CUBE_CELLS {
+                let bounds = [(0, l), (1, a), (2, b)].map(|(axis, i)| {
+                    let min = CUBE_MIN[axis] + i as f32 * size(axis);
+                    (min, min + size(axis))
+                });
+                let distances = palette.map(|color| {
+                    let mut closest = 0.0;
+                    let mut farthest = 0.0;
+                    for (value, (min, max)) in [color.l, color.a, color.b].into_iter().zip(bounds) {
+                        let near = (min - value).max(value - max).max(0.0);
+                        let far = (value - min).abs().max((max - value).abs());
+                        closest += near * near;
+                        farthest += far * far;
+                    }
+                    (closest, farthest)
+                });
+                let bound = distances
+                    .iter()
+                    .map(|&(_, farthest)| farthest)
+                    .fold(f32::MAX, f32::min);
+                // With some slack for rounding, so no candidate is left out
+                let mask = distances
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &(closest, _))| closest <= bound * 1.001 + 1e-6)
+                    .fold(0u8, |mask, (i, _)| mask | 1 << i);
+                cube.push(mask);
+            }
+        }
+    }
+    cube
+}
+
+/// Extracted dominant color
 pub struct DominantColor {
     pub r: u8,
     pub g: u8,
     pub b: u8,
-    pub is_light: bool,
+}
+
+/// Smallest contrast ratio of a colored caption (WCAG AA for normal text)
+const MIN_ACCENT_CONTRAST: f32 = 4.5;
+
+/// Least OKLab chroma of a palette color counted as an accent rather than a neutral
+const MIN_ACCENT_CHROMA: f32 = 0.05;
+
+/// WCAG relative luminance of an sRGB color, 0 (black) to 1 (white)
+pub fn relative_luminance(color: Rgb) -> f32 {
+    0.2126 * Oklab::srgb_to_linear(color.r)
+        + 0.7152 * Oklab::srgb_to_linear(color.g)
+        + 0.0722 * Oklab::srgb_to_linear(color.b)
+}
+
+/// WCAG contrast ratio between two colors, 1 (identical) to 21 (black on white)
+pub fn contrast_ratio(a: Rgb, b: Rgb) -> f32 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Index of the palette color contrasting most with `background`
+pub fn max_contrast_index(background: Rgb, palette: &[Rgb]) -> usize {
+    palette
+        .iter()
+        .map(|&color| contrast_ratio(background, color))
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(0, |(index, _)| index)
+}
+
+/// Whether black text contrasts more than white with `background`, as the
+/// panel actually shows them (its black is not quite black, nor its white white)
+pub fn prefers_dark_text(background: Rgb) -> bool {
+    max_contrast_index(background, &PALETTE[..2]) == 0
+}
+
+/// Index of the palette color to caption `background` with, picking a
+/// colored one when it's legible
+///
+/// Black or white always contrast most, so the accent (non-neutral) color with
+/// the highest contrast is preferred as long as it meets WCAG AA; otherwise
+/// it's whichever of black and white contrasts more.
+pub fn accent_text_index(background: Rgb, palette: &[Rgb]) -> usize {
+    palette
+        .iter()
+        .enumerate()
+        .filter(|(_, color)| {
+            let oklab = color.to_oklab();
+            oklab.a.hypot(oklab.b) >= MIN_ACCENT_CHROMA
+        })
+        .map(|(index, &color)| (index, contrast_ratio(background, color)))
+        .filter(|&(_, contrast)| contrast >= MIN_ACCENT_CONTRAST)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or_else(
+            || max_contrast_index(background, &palette[..2]),
+            |(index, _)| index,
+        )
 }
 
 /// Extract dominant color from the bottom 10% of an image.
@@ -243,7 +416,7 @@ pub fn extract_dominant_color(img: &image::RgbImage) -> DominantColor {
 
     // Get top 3 colors by count
     let mut colors: Vec<_> = color_counts.into_values().collect();
-    colors.sort_by(|a, b| b.1.cmp(&a.1));
+    colors.sort_by_key(|c| std::cmp::Reverse(c.1));
     let top3: Vec<_> = colors.into_iter().take(3).collect();
 
     // Average top 3 in OKLab space (weighted by count)
@@ -267,13 +440,100 @@ pub fn extract_dominant_color(img: &image::RgbImage) -> DominantColor {
     let oklab = Oklab::new(avg_l, avg_a, avg_b);
     let rgb = oklab.to_rgb();
 
-    // Lightness threshold for text contrast (L > 0.6 in OKLab)
-    let is_light = avg_l > 0.6;
-
     DominantColor {
         r: rgb.r,
         g: rgb.g,
         b: rgb.b,
-        is_light,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nearest_matches_exhaustive() {
+        let palette = OklabPalette::new();
+        let exhaustive = |color: &Oklab| {
+            (0..PALETTE.len())
+                .min_by(|&a, &b| {
+                    color
+                        .distance_squared(&palette.palette_oklab[a])
+                        .total_cmp(&color.distance_squared(&palette.palette_oklab[b]))
+                })
+                .unwrap()
+        };
+
+        // A grid finer than the cube's, reaching past it on every side
+        let steps = 97;
+        let (mut inside, mut narrowed) = (0, 0);
+        for l in 0..=steps {
+            for a in 0..=steps {
+                for b in 0..=steps {
+                    let at = |i: usize, axis: usize| {
+                        let span = CUBE_MAX[axis] - CUBE_MIN[axis];
+                        CUBE_MIN[axis] - 0.1 * span + 1.2 * span * i as f32 / steps as f32
+                    };
+                    let color = Oklab::new(at(l, 0), at(a, 1), at(b, 2));
+                    assert_eq!(
+                        palette.nearest(&color) as usize,
+                        exhaustive(&color),
+                        "{:?}",
+                        color
+                    );
+                    if let Some(cell) = cube_cell(&color) {
+                        inside += 1;
+                        if palette.cube[cell].count_ones() == 1 {
+                            narrowed += 1;
+                        }
+                    }
+                }
+            }
+        }
+        // Most of the cube needs no comparing at all
+        assert!(narrowed > inside / 2, "{} of {}", narrowed, inside);
+
+        let nan = Oklab::new(f32::NAN, 0.0, 0.0);
+        assert_eq!(cube_cell(&nan), None);
+        palette.nearest(&nan);
+    }
+
+    #[test]
+    fn test_contrast() {
+        let black = Rgb::new(0, 0, 0);
+        let white = Rgb::new(255, 255, 255);
+        assert!((contrast_ratio(black, white) - 21.0).abs() < 0.01);
+        assert!((contrast_ratio(white, white) - 1.0).abs() < 0.01);
+
+        // Saturated mid-tones that OKLab lightness alone got wrong
+        assert!(prefers_dark_text(Rgb::new(230, 30, 40)));
+        assert!(!prefers_dark_text(Rgb::new(40, 90, 230)));
+        assert!(prefers_dark_text(Rgb::new(250, 200, 20)));
+        assert!(prefers_dark_text(Rgb::new(200, 200, 200)));
+        assert!(!prefers_dark_text(Rgb::new(20, 30, 110)));
+    }
+
+    #[test]
+    fn test_accent_text_index() {
+        // Yellow on navy, red on pale backgrounds
+        let navy = Rgb::new(20, 30, 110);
+        assert_eq!(
+            accent_text_index(navy, &PALETTE),
+            PaletteIndex::Yellow as usize
+        );
+        let cream = Rgb::new(240, 235, 220);
+        assert_eq!(
+            accent_text_index(cream, &PALETTE),
+            PaletteIndex::Red as usize
+        );
+        // No legible accent on a mid gray: back to black or white
+        let gray = Rgb::new(128, 128, 128);
+        assert_eq!(
+            accent_text_index(gray, &PALETTE),
+            max_contrast_index(gray, &PALETTE[..2])
+        );
+        // Gray panels have no accents
+        assert_eq!(accent_text_index(navy, &GRAY_PALETTE), 1);
+        assert_eq!(max_contrast_index(navy, &PALETTE), 1);
     }
 }
@@ -0,0 +1,364 @@
+//! User photo album
+//!
+//! Lists photos from a local folder, a WebDAV collection or a public S3 bucket
+//! and serves them as full-card images without a caption. Items are CRC-32
+//! hashes of each photo's location, so paths never expose (or accept)
+//! filesystem paths or URLs.
+
+use reqwest::{Client, Method};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+use crate::cache::CACHE_TTL_SECS;
+use crate::config::{PhotoSource, PhotosConfig};
+use crate::error::AppError;
+use crate::widget::{WidgetData, WidgetItem};
+
+/// Photo file extensions (compared case-insensitively)
+const PHOTO_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+
+/// Deepest folder nesting scanned below the photos directory
+const MAX_SCAN_DEPTH: usize = 8;
+
+/// WebDAV PROPFIND body requesting only what we need
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/></d:prop></d:propfind>"#;
+
+/// A photo and where to fetch it from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Photo {
+    File(PathBuf),
+    Url(String),
+}
+
+impl Photo {
+    /// File path or URL of the photo
+    pub fn location(&self) -> Cow<'_, str> {
+        match self {
+            Photo::File(path) => path.to_string_lossy(),
+            Photo::Url(url) => url.into(),
+        }
+    }
+
+    /// Widget item path: CRC-32 of the location (hex)
+    pub fn item_path(&self) -> String {
+        format!("{:08x}", crc32fast::hash(self.location().as_bytes()))
+    }
+
+    /// Sort key: the file name, so photos show in name order across folders
+    fn name(&self) -> &str {
+        match self {
+            Photo::File(path) => path.file_name().and_then(|n| n.to_str()).unwrap_or(""),
+            Photo::Url(url) => url.rsplit('/').next().unwrap_or(url),
+        }
+    }
+}
+
+/// List the photos in the configured source, in name order
+pub async fn list_photos(client: &Client, config: &PhotosConfig) -> Result<Vec<Photo>, AppError> {
+    let mut photos = match &config.source {
+        PhotoSource::Dir(dir) => {
+            let dir = dir.clone();
+            tokio::task::spawn_blocking(move || scan_dir(&dir))
+                .await
+                .map_err(|e| AppError::ExternalApi(format!("Photo scan failed: {}", e)))??
+        }
+        PhotoSource::WebDav(url) => list_webdav(client, config, url).await?,
+        PhotoSource::S3(url) => list_s3(client, url).await?,
+    };
+    photos.sort_by(|a, b| {
+        a.name()
+            .cmp(b.name())
+            .then_with(|| a.item_path().cmp(&b.item_path()))
+    });
+
+    tracing::info!("Found {} photos", photos.len());
+
+    Ok(photos)
+}
+
+/// Convert photos to widget items, titled with the file name
+///
+/// A photo can be replaced under the same name, which shows once the cache expires.
+pub fn photos_to_widget_items(photos: &[Photo], limit: usize) -> WidgetData {
+    photos
+        .iter()
+        .take(limit)
+        .map(|photo| {
+            WidgetItem::new(photo.item_path())
+                .with_ttl(CACHE_TTL_SECS)
+                .with_title(photo.name())
+        })
+        .collect()
+}
+
+/// Find the photo for an item path
+pub fn find_photo<'a>(photos: &'a [Photo], path: &str) -> Result<&'a Photo, AppError> {
+    if path.len() != 8 || !path.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(AppError::InvalidPath(format!("invalid photo ID: {}", path)));
+    }
+    photos
+        .iter()
+        .find(|photo| photo.item_path() == path)
+        .ok_or_else(|| AppError::NotFound(format!("photo {} not found", path)))
+}
+
+/// Read a photo's bytes
+pub async fn fetch_photo(
+    client: &Client,
+    config: &PhotosConfig,
+    photo: &Photo,
+) -> Result<Vec<u8>, AppError> {
+    match photo {
+        Photo::File(path) => tokio::fs::read(path)
+            .await
+            .map_err(|e| AppError::NotFound(format!("Failed to read {}: {}", path.display(), e))),
+        Photo::Url(url) => {
+            tracing::info!("Fetching photo from: {}", url);
+            let response = authorized(client.get(url), config).send().await?;
+            if !response.status().is_success() {
+                return Err(AppError::ExternalApi(format!(
+                    "Failed to fetch photo: {}",
+                    response.status()
+                )));
+            }
+            Ok(response.bytes().await?.to_vec())
+        }
+    }
+}
+
+/// Add the configured basic auth credentials to a request
+fn authorized(request: reqwest::RequestBuilder, config: &PhotosConfig) -> reqwest::RequestBuilder {
+    match &config.username {
+        Some(username) => request.basic_auth(username, config.password.as_ref()),
+        None => request,
+    }
+}
+
+/// Whether a file name has a photo extension
+fn is_photo(name: &str) -> bool {
+    name.rsplit_once('.').is_some_and(|(_, ext)| {
+        PHOTO_EXTENSIONS
+            .iter()
+            .any(|photo_ext| ext.eq_ignore_ascii_case(photo_ext))
+    })
+}
+
+/// Recursively list photos in a folder, skipping hidden files and folders
+fn scan_dir(dir: &Path) -> Result<Vec<Photo>, AppError> {
+    let mut photos = Vec::new();
+    let mut pending = vec![(dir.to_path_buf(), 0)];
+    while let Some((dir, depth)) = pending.pop() {
+        let entries = std::fs::read_dir(&dir)
+            .map_err(|e| AppError::NotFound(format!("Failed to read {}: {}", dir.display(), e)))?;
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if name.starts_with('.') {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() && depth < MAX_SCAN_DEPTH {
+                pending.push((entry.path(), depth + 1));
+            } else if file_type.is_file() && is_photo(name) {
+                photos.push(Photo::File(entry.path()));
+            }
+        }
+    }
+    Ok(photos)
+}
+
+/// List photos in a WebDAV collection (one level deep)
+async fn list_webdav(
+    client: &Client,
+    config: &PhotosConfig,
+    url: &str,
+) -> Result<Vec<Photo>, AppError> {
+    tracing::info!("Listing WebDAV photos from: {}", url);
+
+    let method = Method::from_bytes(b"PROPFIND").expect("valid method");
+    let response = authorized(client.request(method, url), config)
+        .header("Depth", "1")
+        .header("Content-Type", "application/xml")
+        .body(PROPFIND_BODY)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(AppError::ExternalApi(format!(
+            "WebDAV listing returned status: {}",
+            response.status()
+        )));
+    }
+
+    let base = reqwest::Url::parse(url)
+        .map_err(|e| AppError::ExternalApi(format!("Invalid photos URL: {}", e)))?;
+    let body = response.text().await?;
+
+    Ok(xml_elements(&body, "href")
+        .into_iter()
+        .filter(|href| is_photo(href))
+        .filter_map(|href| base.join(&href).ok())
+        .map(|url| Photo::Url(url.into()))
+        .collect())
+}
+
+/// List photos in a public S3 bucket (ListObjectsV2, anonymous)
+///
+/// `url` is the virtual-hosted bucket URL, optionally with a key prefix path,
+/// e.g. `https://bucket.s3.amazonaws.com/albums/frame/`.
+async fn list_s3(client: &Client, url: &str) -> Result<Vec<Photo>, AppError> {
+    let url = reqwest::Url::parse(url)
+        .map_err(|e| AppError::ExternalApi(format!("Invalid photos URL: {}", e)))?;
+    let prefix = url.path().trim_start_matches('/').to_string();
+    let mut bucket = url.clone();
+    bucket.set_path("/");
+    bucket.set_query(None);
+
+    tracing::info!("Listing S3 photos from: {} (prefix {:?})", bucket, prefix);
+
+    let mut photos = Vec::new();
+    let mut continuation: Option<String> = None;
+    loop {
+        let mut query = vec![("list-type", "2".to_string()), ("prefix", prefix.clone())];
+        if let Some(token) = &continuation {
+            query.push(("continuation-token", token.clone()));
+        }
+        let response = client.get(bucket.clone()).query(&query).send().await?;
+        if !response.status().is_success() {
+            return Err(AppError::ExternalApi(format!(
+                "S3 listing returned status: {}",
+                response.status()
+            )));
+        }
+        let body = response.text().await?;
+
+        photos.extend(
+            xml_elements(&body, "Key")
+                .into_iter()
+                .filter(|key| is_photo(key))
+                .filter_map(|key| {
+                    bucket
+                        .join(&urlencoding::encode(&key).replace("%2F", "/"))
+                        .ok()
+                })
+                .map(|url| Photo::Url(url.into())),
+        );
+
+        continuation = xml_elements(&body, "NextContinuationToken")
+            .into_iter()
+            .next();
+        if continuation.is_none() {
+            break;
+        }
+    }
+
+    Ok(photos)
+}
+
+/// Text of every element with the given local name, ignoring namespace prefixes
+///
+/// A minimal scanner for the flat listings returned by WebDAV and S3; it does
+/// not handle CDATA or nested elements of the same name.
+fn xml_elements(xml: &str, name: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let Some(tag_end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..tag_end];
+        let tag_name = tag.split_whitespace().next().unwrap_or(tag);
+        let local = tag_name.rsplit(':').next().unwrap_or(tag_name);
+        if tag.starts_with('/') || tag.ends_with('/') || local != name {
+            continue;
+        }
+
+        let content = &rest[tag_end + 1..];
+        let Some(end) = content.find("</") else {
+            break;
+        };
+        values.push(unescape_xml(content[..end].trim()));
+        rest = &content[end..];
+    }
+    values
+}
+
+/// Replace the predefined XML entities
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xml_elements() {
+        let webdav = r#"<?xml version="1.0"?>
+            <d:multistatus xmlns:d="DAV:">
+                <d:response><d:href>/dav/photos/</d:href></d:response>
+                <d:response><d:href>/dav/photos/Beach%20Day.JPG</d:href></d:response>
+                <d:response><D:href xmlns:D="DAV:">/dav/photos/notes.txt</D:href></d:response>
+            </d:multistatus>"#;
+        assert_eq!(
+            xml_elements(webdav, "href"),
+            vec![
+                "/dav/photos/",
+                "/dav/photos/Beach%20Day.JPG",
+                "/dav/photos/notes.txt"
+            ]
+        );
+
+        let s3 = "<ListBucketResult><Contents><Key>frame/a &amp; b.png</Key></Contents>\
+            <IsTruncated>false</IsTruncated></ListBucketResult>";
+        assert_eq!(xml_elements(s3, "Key"), vec!["frame/a & b.png"]);
+        assert!(xml_elements(s3, "NextContinuationToken").is_empty());
+    }
+
+    #[test]
+    fn test_scan_dir() {
+        let dir = std::env::temp_dir().join(format!("photos-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("trip")).unwrap();
+        std::fs::create_dir_all(dir.join(".thumbnails")).unwrap();
+        for name in [
+            "b.jpg",
+            "trip/a.PNG",
+            "notes.txt",
+            ".hidden.jpg",
+            ".thumbnails/c.jpg",
+        ] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let mut photos = scan_dir(&dir).unwrap();
+        photos.sort_by(|a, b| a.name().cmp(b.name()));
+        assert_eq!(
+            photos,
+            vec![
+                Photo::File(dir.join("trip/a.PNG")),
+                Photo::File(dir.join("b.jpg"))
+            ]
+        );
+
+        let items = photos_to_widget_items(&photos, 1);
+        assert_eq!(items.len(), 1);
+        assert_eq!(find_photo(&photos, &items[0].path).unwrap(), &photos[0]);
+        assert_eq!(items[0].title.as_deref(), Some("a.PNG"));
+        assert!(matches!(
+            find_photo(&photos, "../../etc/passwd"),
+            Err(AppError::InvalidPath(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
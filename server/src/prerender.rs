@@ -0,0 +1,215 @@
+//! Batch pre-rendering of widget images
+//!
+//! Walks a data source's full item list and renders every orientation ahead of
+//! time (for every experiment variant), so device requests are served from the
+//! in-memory cache instead of paying the image lookup, download and dithering
+//! latency on first access.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::RwLock;
+use utoipa::ToSchema;
+
+use crate::datasource::DataSource;
+use crate::experiment::Variant;
+use crate::widget::Orientation;
+
+/// Orientations rendered for each item
+const ORIENTATIONS: [Orientation; 2] = [Orientation::Horiz, Orientation::Vert];
+
+/// Outcome of a completed pre-render run
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct PrerenderReport {
+    /// Number of widget items walked
+    pub items: usize,
+    /// Images rendered (or already cached)
+    pub rendered: usize,
+    /// Images that failed to render
+    pub failed: usize,
+    /// Wall-clock duration of the run in seconds
+    pub duration_secs: f32,
+    /// Error that aborted the run before any images were rendered
+    pub error: Option<String>,
+}
+
+/// Current pre-render state
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct PrerenderStatus {
+    /// Whether a run is in progress
+    pub running: bool,
+    /// Report from the most recent completed run
+    pub last: Option<PrerenderReport>,
+}
+
+/// Runs pre-render jobs in the background, one at a time
+pub struct Prerenderer {
+    running: AtomicBool,
+    last: RwLock<Option<PrerenderReport>>,
+}
+
+impl Prerenderer {
+    pub fn new() -> Self {
+        Self {
+            running: AtomicBool::new(false),
+            last: RwLock::new(None),
+        }
+    }
+
+    /// Start a background run over `source`, returns false if one is already running
+    pub fn start(self: &Arc<Self>, source: Arc<dyn DataSource>, variants: Vec<Variant>) -> bool {
+        if self.running.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+
+        let this = self.clone();
+        tokio::spawn(async move {
+            let report = prerender(source.as_ref(), &variants).await;
+            *this.last.write().await = Some(report);
+            this.running.store(false, Ordering::Release);
+        });
+        true
+    }
+
+    /// Current state and last report
+    pub async fn status(&self) -> PrerenderStatus {
+        PrerenderStatus {
+            running: self.running.load(Ordering::Acquire),
+            last: self.last.read().await.clone(),
+        }
+    }
+}
+
+impl Default for Prerenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Render every item of `source` in all orientations and variants
+///
+/// Items are rendered sequentially to stay within upstream API rate limits.
+pub async fn prerender(source: &dyn DataSource, variants: &[Variant]) -> PrerenderReport {
+    let started = Instant::now();
+
+    let (items, error) = match source.fetch_data().await {
+        Ok(items) => (items, None),
+        Err(e) => {
+            tracing::warn!("Pre-render aborted, failed to fetch items: {}", e);
+            (Vec::new(), Some(e.to_string()))
+        }
+    };
+    tracing::info!("Pre-rendering {} items", items.len());
+
+    let mut rendered = 0;
+    let mut failed = 0;
+    for path in items.iter().map(|item| item.path.as_str()) {
+        for orientation in ORIENTATIONS {
+            for variant in variants {
+                match source.fetch_image(path, orientation, variant).await {
+                    Ok(_) => rendered += 1,
+                    Err(e) => {
+                        tracing::warn!(
+                            "Pre-render failed for {} ({}, {}): {}",
+                            path,
+                            orientation,
+                            variant.name,
+                            e
+                        );
+                        failed += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    let report = PrerenderReport {
+        items: items.len(),
+        rendered,
+        failed,
+        duration_secs: started.elapsed().as_secs_f32(),
+        error,
+    };
+    tracing::info!("Pre-render complete: {:?}", report);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AppError;
+    use crate::image_processing::RenderParams;
+    use crate::widget::{CachePolicy, WidgetData, WidgetItem};
+    use async_trait::async_trait;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Data source with three items, one of which fails to render
+    struct MockSource {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl DataSource for MockSource {
+        fn data_cache_policy(&self) -> CachePolicy {
+            CachePolicy::Max
+        }
+
+        async fn fetch_data(&self) -> Result<WidgetData, AppError> {
+            Ok(["a", "b", "broken"]
+                .into_iter()
+                .map(|path| WidgetItem::new(path.to_string()))
+                .collect())
+        }
+
+        async fn fetch_image(
+            &self,
+            path: &str,
+            _orientation: Orientation,
+            _variant: &Variant,
+        ) -> Result<Vec<u8>, AppError> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            if path == "broken" {
+                Err(AppError::BandNotFound(path.to_string()))
+            } else {
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    fn variants() -> Vec<Variant> {
+        ["a", "b"]
+            .into_iter()
+            .map(|name| Variant {
+                name: name.to_string(),
+                params: RenderParams::default(),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_prerender() {
+        let source = MockSource {
+            calls: AtomicUsize::new(0),
+        };
+        let report = prerender(&source, &variants()).await;
+
+        assert_eq!(source.calls.load(Ordering::Relaxed), 12);
+        assert_eq!(report.items, 3);
+        assert_eq!(report.rendered, 8);
+        assert_eq!(report.failed, 4);
+        assert_eq!(report.error, None);
+    }
+
+    #[tokio::test]
+    async fn test_single_run_at_a_time() {
+        let prerenderer = Arc::new(Prerenderer::new());
+        let source: Arc<dyn DataSource> = Arc::new(MockSource {
+            calls: AtomicUsize::new(0),
+        });
+
+        assert!(prerenderer.start(source.clone(), variants()));
+        assert!(!prerenderer.start(source, variants()));
+        assert!(prerenderer.status().await.running);
+    }
+}
@@ -1,21 +1,249 @@
 //! SawThat.band API integration
 //!
 //! Fetches concert history from sawthat.band API and generates widget items.
-//! Uses Deezer API to find album art matching each concert date.
+//! Uses Deezer API to find album art matching each concert date, falling back
+//! to MusicBrainz, and records why each cover was chosen ([`CoverSelection`]). Operators can pick another album
+//! for a concert with an [`AlbumOverrides`] entry, persisted to `$STATE_DIR`,
+//! and attach a personal note to its caption with [`ConcertNotes`].
 
 use reqwest::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
-
-use crate::cache::{ConcertCache, ConcertEntry};
-use crate::deezer;
+use tokio::sync::{OnceCell, RwLock};
+use utoipa::ToSchema;
+
+use crate::alt_text;
+use crate::blocklist;
+use crate::cache::{ConcertCache, ConcertEntry, CACHE_TTL_SECS};
+use crate::config::ConcertsConfig;
+use crate::deezer::{self, AlbumLookup};
+use crate::device::{load_json, save_json};
 use crate::error::AppError;
+use crate::experiment::Variant;
 use crate::image_processing;
+use crate::musicbrainz::MusicBrainz;
+use crate::setlistfm::SetlistFm;
 use crate::text::ConcertInfo;
-use crate::widget::{Orientation, WidgetData, WidgetWidth};
+use crate::widget::{Orientation, WidgetData, WidgetItem, WidgetWidth};
 
 /// SawThat API base URL
-const SAWTHAT_API_URL: &str = "https://server.sawthat.band/api/bands";
+pub const SAWTHAT_API_URL: &str = "https://server.sawthat.band/api/bands";
+
+/// Order of the concert rotation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConcertSort {
+    /// Most recent concerts first
+    #[default]
+    Newest,
+    /// Earliest concerts first
+    Oldest,
+}
+
+/// Where a concert's cover art came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CoverSource {
+    /// The Deezer album released closest before the concert
+    DeezerAlbum,
+    /// The Deezer album picked for the concert by an override
+    AlbumOverride,
+    /// The MusicBrainz album released closest before the concert, when Deezer
+    /// had none to use
+    MusicbrainzAlbum,
+    /// The band's Spotify picture
+    BandPicture,
+    /// Every candidate was blocked
+    Placeholder,
+}
+
+/// How a concert's cover art was chosen
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct CoverSelection {
+    pub source: CoverSource,
+    /// Why, in a few words
+    pub reason: String,
+    /// Album picked, or passed over if it was blocked or had no art
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    /// The album's release date (YYYY-MM-DD)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_date: Option<String>,
+    /// Days from the album's release to the concert
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub days_before: Option<i64>,
+    /// Albums the artist has on Deezer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub albums: Option<usize>,
+    /// Cover image fetched, `None` for the placeholder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl CoverSelection {
+    /// The band picture, for `reason`
+    fn band_picture(reason: impl Into<String>) -> Self {
+        Self {
+            source: CoverSource::BandPicture,
+            reason: reason.into(),
+            album: None,
+            release_date: None,
+            days_before: None,
+            albums: None,
+            url: None,
+        }
+    }
+
+    /// Account for the cover actually fetched, if blocked images were skipped
+    fn fetched(mut self, url: Option<&str>) -> Self {
+        if self.url.as_deref() == url {
+            return self;
+        }
+        match url {
+            Some(_) => {
+                self.source = CoverSource::BandPicture;
+                self.reason = "album cover is blocked".to_string();
+            }
+            None => {
+                self.source = CoverSource::Placeholder;
+                self.reason = "every candidate image is blocked".to_string();
+            }
+        }
+        self.url = url.map(String::from);
+        self
+    }
+}
+
+/// Album overrides file in the state directory
+const ALBUM_OVERRIDES_FILE: &str = "album-overrides.json";
+
+/// Longest album title accepted as an override
+const MAX_ALBUM_TITLE_LEN: usize = 200;
+
+/// Deezer albums picked by hand for concerts, keyed by item path
+///
+/// Loaded from the state directory on first use and saved on every change.
+pub struct AlbumOverrides {
+    titles: OnceCell<RwLock<HashMap<String, String>>>,
+    file: Option<PathBuf>,
+}
+
+impl AlbumOverrides {
+    /// Overrides persisted in `state_dir`, or kept in memory without one
+    pub fn new(state_dir: Option<PathBuf>) -> Self {
+        Self {
+            titles: OnceCell::new(),
+            file: state_dir.map(|dir| dir.join(ALBUM_OVERRIDES_FILE)),
+        }
+    }
+
+    async fn titles(&self) -> &RwLock<HashMap<String, String>> {
+        self.titles
+            .get_or_init(|| async {
+                let titles = match &self.file {
+                    Some(file) => load_json(file).await,
+                    None => HashMap::new(),
+                };
+                RwLock::new(titles)
+            })
+            .await
+    }
+
+    /// Album title picked for a concert
+    pub async fn get(&self, path: &str) -> Option<String> {
+        self.titles().await.read().await.get(path).cloned()
+    }
+
+    /// Pick the album for a concert by title, or clear the override with `None`
+    pub async fn set(&self, path: &str, title: Option<&str>) -> Result<(), AppError> {
+        let title = title.map(str::trim).filter(|title| !title.is_empty());
+        if title.is_some_and(|title| title.len() > MAX_ALBUM_TITLE_LEN) {
+            return Err(AppError::InvalidUpload(format!(
+                "Album title longer than {} bytes",
+                MAX_ALBUM_TITLE_LEN
+            )));
+        }
+
+        let mut titles = self.titles().await.write().await;
+        match title {
+            Some(title) => titles.insert(path.to_string(), title.to_string()),
+            None => titles.remove(path),
+        };
+        if let Some(file) = &self.file {
+            save_json(file, &*titles).await;
+        }
+        Ok(())
+    }
+}
+
+/// Concert notes file in the state directory
+const CONCERT_NOTES_FILE: &str = "concert-notes.json";
+
+/// Longest concert note accepted
+const MAX_NOTE_LEN: usize = 100;
+
+/// Personal notes captioned under concerts, keyed by item path
+///
+/// Loaded from the state directory on first use and saved on every change.
+pub struct ConcertNotes {
+    notes: OnceCell<RwLock<HashMap<String, String>>>,
+    file: Option<PathBuf>,
+}
+
+impl ConcertNotes {
+    /// Notes persisted in `state_dir`, or kept in memory without one
+    pub fn new(state_dir: Option<PathBuf>) -> Self {
+        Self {
+            notes: OnceCell::new(),
+            file: state_dir.map(|dir| dir.join(CONCERT_NOTES_FILE)),
+        }
+    }
+
+    async fn notes(&self) -> &RwLock<HashMap<String, String>> {
+        self.notes
+            .get_or_init(|| async {
+                let notes = match &self.file {
+                    Some(file) => load_json(file).await,
+                    None => HashMap::new(),
+                };
+                RwLock::new(notes)
+            })
+            .await
+    }
+
+    /// Note attached to a concert
+    pub async fn get(&self, path: &str) -> Option<String> {
+        self.notes().await.read().await.get(path).cloned()
+    }
+
+    /// Attach a note to a concert, or remove it with `None`
+    ///
+    /// Runs of whitespace, line breaks included, become single spaces: the
+    /// note is captioned on one line.
+    pub async fn set(&self, path: &str, note: Option<&str>) -> Result<(), AppError> {
+        let note = note
+            .map(|note| note.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|note| !note.is_empty());
+        if note.as_ref().is_some_and(|note| note.len() > MAX_NOTE_LEN) {
+            return Err(AppError::InvalidUpload(format!(
+                "Note longer than {} bytes",
+                MAX_NOTE_LEN
+            )));
+        }
+
+        let mut notes = self.notes().await.write().await;
+        match note {
+            Some(note) => notes.insert(path.to_string(), note),
+            None => notes.remove(path),
+        };
+        if let Some(file) = &self.file {
+            save_json(file, &*notes).await;
+        }
+        Ok(())
+    }
+}
 
 /// A band from the SawThat API
 #[derive(Debug, Clone, Deserialize)]
@@ -41,8 +269,12 @@ pub struct SawThatConcert {
 }
 
 /// Fetch bands from SawThat API
-pub async fn fetch_bands(client: &Client, user_id: &str) -> Result<Vec<SawThatBand>, AppError> {
-    let url = format!("{}?id={}", SAWTHAT_API_URL, user_id);
+pub async fn fetch_bands(
+    client: &Client,
+    api_url: &str,
+    user_id: &str,
+) -> Result<Vec<SawThatBand>, AppError> {
+    let url = format!("{}?id={}", api_url, user_id);
 
     tracing::info!("Fetching SawThat bands from: {}", url);
 
@@ -66,11 +298,42 @@ pub async fn fetch_bands(client: &Client, user_id: &str) -> Result<Vec<SawThatBa
     Ok(bands)
 }
 
-/// Convert SawThat bands to widget items
+/// A concert in the rotation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConcertListing {
+    /// Item path, YYYY-MM-DD-band-id
+    pub path: String,
+    pub band: String,
+    pub band_id: String,
+    /// The band's Spotify picture
+    pub band_picture: String,
+    /// YYYY-MM-DD
+    pub date: String,
+    /// Date as captioned, e.g. "June 15th, 2024"
+    pub formatted_date: String,
+    pub venue: String,
+}
+
+impl ConcertListing {
+    /// Caption of the concert's card, without a setlist detail or note
+    pub fn info(&self) -> ConcertInfo {
+        ConcertInfo {
+            band_name: self.band.clone(),
+            date: self.formatted_date.clone(),
+            venue: self.venue.clone(),
+            detail: None,
+            note: None,
+        }
+    }
+}
+
+/// List the concerts in the rotation
 ///
-/// Returns all concerts sorted by date (most recent first).
-/// Path format: YYYY-MM-DD-band-id (FAT-safe, sortable)
-pub fn bands_to_widget_items(bands: &[SawThatBand], limit: usize) -> WidgetData {
+/// Returns concerts on or after the configured minimum date, in the configured
+/// order, up to the configured limit.
+pub fn list_concerts(bands: &[SawThatBand], config: &ConcertsConfig) -> Vec<ConcertListing> {
+    let since = config.since.map(|date| date.format("%Y-%m-%d").to_string());
+
     // Flatten all concerts from all bands
     let mut all_concerts: Vec<_> = bands
         .iter()
@@ -91,17 +354,46 @@ pub fn bands_to_widget_items(bands: &[SawThatBand], limit: usize) -> WidgetData
                 }
             })
         })
+        .filter(|(_, _, iso_date)| since.as_ref().is_none_or(|since| iso_date >= since))
         .collect();
 
-    // Sort by date descending (most recent first)
-    all_concerts.sort_by(|a, b| b.2.cmp(&a.2));
+    // ISO dates sort chronologically
+    match config.sort {
+        ConcertSort::Newest => all_concerts.sort_by(|a, b| b.2.cmp(&a.2)),
+        ConcertSort::Oldest => all_concerts.sort_by(|a, b| a.2.cmp(&b.2)),
+    }
 
-    // Take the most recent concerts
     // Path format: YYYY-MM-DD-band-id
     all_concerts
         .into_iter()
-        .take(limit)
-        .map(|(band, _concert, iso_date)| format!("{}-{}", iso_date, band.id))
+        .take(config.limit)
+        .map(|(band, concert, iso_date)| ConcertListing {
+            path: format!("{}-{}", iso_date, band.id),
+            band: band.band.clone(),
+            band_id: band.id.clone(),
+            band_picture: band.picture.clone(),
+            date: iso_date,
+            formatted_date: format_date(&concert.date),
+            venue: concert.location.clone(),
+        })
+        .collect()
+}
+
+/// Convert SawThat bands to widget items
+///
+/// Lists the concerts as [`list_concerts`] does, titled with the band name and
+/// described for screen readers (see [`alt_text`]). Art is looked up again once
+/// the cache expires, so renders may change after its TTL.
+/// Path format: YYYY-MM-DD-band-id (FAT-safe, sortable)
+pub fn bands_to_widget_items(bands: &[SawThatBand], config: &ConcertsConfig) -> WidgetData {
+    list_concerts(bands, config)
+        .into_iter()
+        .map(|concert| {
+            WidgetItem::new(concert.path.clone())
+                .with_ttl(CACHE_TTL_SECS)
+                .with_title(&concert.band)
+                .with_alt(alt_text::concert_alt_text(&concert.info(), None))
+        })
         .collect()
 }
 
@@ -126,56 +418,116 @@ pub fn parse_item_path(path: &str) -> Option<(String, String)> {
 /// Fetch and process an image for a band
 ///
 /// Uses cached data when available. Caches:
-/// - Resolved image URL (Deezer or Spotify fallback)
+/// - Resolved image URL (Deezer, MusicBrainz or Spotify fallback)
 /// - Source image bytes
 /// - Primary color
-/// - Rendered images per orientation
+/// - Rendered images per orientation and experiment variant
+#[allow(clippy::too_many_arguments)]
 pub async fn fetch_band_image(
     client: &Client,
+    deezer_url: &str,
     bands: &[SawThatBand],
     band_id: &str,
     date: Option<&str>,
     orientation: Orientation,
     cache_key: &str,
     cache: &ConcertCache,
+    variant: &Variant,
+    album_override: Option<&str>,
+    note: Option<&str>,
+    musicbrainz: Option<&MusicBrainz>,
+    setlists: Option<&SetlistFm>,
 ) -> Result<Vec<u8>, AppError> {
-    // Check if we have a cached entry
-    if let Some(entry) = cache.get_concert(cache_key).await {
-        // Check if we have this orientation's image
-        if let Some(cached_image) = entry.get_image(orientation) {
-            tracing::debug!(
-                "Using fully cached image for {} ({:?})",
-                cache_key,
-                orientation
-            );
-            return Ok((**cached_image).clone());
-        }
-
-        // We have cached data but need to render this orientation
-        tracing::info!(
-            "Rendering {:?} for {} using cached data",
+    // Check if we have this orientation's image
+    if let Some(cached_image) = cache
+        .get_concert(cache_key)
+        .await
+        .and_then(|entry| entry.get_image(orientation, &variant.name).cloned())
+    {
+        tracing::debug!(
+            "Using fully cached image for {} ({:?}, {})",
+            cache_key,
             orientation,
-            cache_key
+            variant.name
         );
-        let (target_width, target_height) = orientation.dimensions(WidgetWidth::Half);
-        let rendered = image_processing::process_image_with_color(
+        return Ok((*cached_image).clone());
+    }
+
+    let entry = fetch_concert_entry(
+        client,
+        deezer_url,
+        bands,
+        band_id,
+        date,
+        cache_key,
+        cache,
+        album_override,
+        note,
+        musicbrainz,
+        setlists,
+    )
+    .await?;
+
+    // Render the image
+    tracing::info!(
+        "Rendering {:?} ({}) for {}",
+        orientation,
+        variant.name,
+        cache_key
+    );
+    let (target_width, target_height) = orientation.dimensions(WidgetWidth::Half);
+    let params = variant.params;
+    // Dithering takes a while, so it runs off the async workers
+    let rendered = tokio::task::spawn_blocking(move || {
+        image_processing::process_image_with_color(
             &entry.source_image,
             target_width,
             target_height,
-            Some(&ConcertInfo {
-                band_name: entry.band_name.clone(),
-                date: entry.formatted_date.clone(),
-                venue: entry.venue.clone(),
-            }),
+            Some(&entry.concert_info()),
             &entry.primary_color,
-        )?;
+            &params,
+            entry.source_url.as_deref(),
+        )
+    })
+    .await
+    .map_err(|e| AppError::ImageProcessing(format!("Rendering failed: {}", e)))??;
 
-        // Cache this orientation
-        cache
-            .set_concert_image(cache_key, orientation, Arc::new(rendered.clone()))
-            .await;
+    // Cache this orientation and variant
+    cache
+        .set_concert_image(
+            cache_key,
+            orientation,
+            &variant.name,
+            Arc::new(rendered.clone()),
+        )
+        .await;
+
+    Ok(rendered)
+}
 
-        return Ok(rendered);
+/// Source art and caption of a concert, from the cache or fetched and cached
+///
+/// `album_override` picks the Deezer album by title instead of by date, `note`
+/// is captioned below the rest, `musicbrainz` looks for album art Deezer
+/// doesn't have, and `setlists` adds a detail line from the concert's setlist
+/// to the caption.
+#[allow(clippy::too_many_arguments)]
+pub async fn fetch_concert_entry(
+    client: &Client,
+    deezer_url: &str,
+    bands: &[SawThatBand],
+    band_id: &str,
+    date: Option<&str>,
+    cache_key: &str,
+    cache: &ConcertCache,
+    album_override: Option<&str>,
+    note: Option<&str>,
+    musicbrainz: Option<&MusicBrainz>,
+    setlists: Option<&SetlistFm>,
+) -> Result<ConcertEntry, AppError> {
+    if let Some(entry) = cache.get_concert(cache_key).await {
+        tracing::debug!("Using cached data for {}", cache_key);
+        return Ok(entry);
     }
 
     // No cached entry - fetch everything from scratch
@@ -184,23 +536,12 @@ pub async fn fetch_band_image(
         .find(|b| b.id == band_id)
         .ok_or_else(|| AppError::BandNotFound(band_id.to_string()))?;
 
-    // Resolve image URL (Deezer or fallback)
-    let image_url = resolve_image_url(client, band, date).await;
-
-    // Fetch the source image
-    tracing::info!("Fetching source image from: {}", image_url);
-    let response = client
-        .get(&image_url)
-        .header("Accept", "image/*")
-        .send()
-        .await?;
-    if !response.status().is_success() {
-        return Err(AppError::ExternalApi(format!(
-            "Failed to fetch image: {}",
-            response.status()
-        )));
-    }
-    let source_image = Arc::new(response.bytes().await?.to_vec());
+    // Fetch the source image, Deezer or fallback, skipping blocked artwork
+    let (image_urls, cover) =
+        resolve_image_urls(client, deezer_url, band, date, album_override, musicbrainz).await;
+    let artwork = blocklist::fetch_artwork(client, &image_urls).await?;
+    let cover = cover.fetched(artwork.url.as_deref());
+    let source_image = Arc::new(artwork.image);
 
     // Extract primary color
     let primary_color = image_processing::extract_primary_color(&source_image)?;
@@ -214,81 +555,209 @@ pub async fn fetch_band_image(
                 .map(|c| (format_date(&c.date), c.location.clone()))
         })
         .unwrap_or_else(|| ("".to_string(), "".to_string()));
+    let detail = match (setlists, date) {
+        (Some(setlists), Some(date)) => setlists.caption(&band.band, date, &venue).await,
+        _ => None,
+    };
 
     // Create and cache the entry data
+    let entry = ConcertEntry {
+        band_name: band.band.clone(),
+        venue,
+        formatted_date,
+        detail,
+        note: note.map(String::from),
+        source_image,
+        source_url: artwork.url,
+        cover: Some(cover),
+        primary_color,
+        images: HashMap::new(),
+    };
     cache
-        .set_or_update_concert(
-            cache_key.to_string(),
-            ConcertEntry {
-                band_name: band.band.clone(),
-                venue: venue.clone(),
-                formatted_date: formatted_date.clone(),
-                source_image: source_image.clone(),
-                primary_color,
-                image_horiz: None,
-                image_vert: None,
-            },
-        )
+        .set_or_update_concert(cache_key.to_string(), entry.clone())
         .await;
 
-    // Render the image
-    let (target_width, target_height) = orientation.dimensions(WidgetWidth::Half);
-    let rendered = image_processing::process_image_with_color(
-        &source_image,
-        target_width,
-        target_height,
-        Some(&ConcertInfo {
-            band_name: band.band.clone(),
-            date: formatted_date.clone(),
-            venue: venue.clone(),
-        }),
-        &primary_color,
-    )?;
-
-    // Add the rendered image
-    cache
-        .set_concert_image(cache_key, orientation, Arc::new(rendered.clone()))
+    Ok(entry)
+}
+
+/// Resolve the image URLs to try for a band/concert, best first, and why
+///
+/// The Deezer album picked by `album_override`, or else the one released
+/// closest before the concert unless its title is blocked, or failing that the
+/// closest one on MusicBrainz, then the Spotify picture.
+async fn resolve_image_urls(
+    client: &Client,
+    deezer_url: &str,
+    band: &SawThatBand,
+    date: Option<&str>,
+    album_override: Option<&str>,
+    musicbrainz: Option<&MusicBrainz>,
+) -> (Vec<String>, CoverSelection) {
+    let overridden = match album_override {
+        Some(title) => override_album(client, deezer_url, band, date, title).await,
+        None => None,
+    };
+    let mut selection = match (overridden, date) {
+        (Some(selection), _) => selection,
+        (None, Some(concert_date)) => {
+            let lookup =
+                deezer::fetch_album_for_concert(client, deezer_url, &band.band, concert_date).await;
+            let mut selection = select_album(lookup, |title| blocklist::get().blocks_title(title));
+            if let (None, Some(musicbrainz)) = (&selection.url, musicbrainz) {
+                selection = musicbrainz_album(musicbrainz, band, concert_date, selection).await;
+            }
+            tracing::info!(
+                "Cover for {} at {}: {:?}, {}",
+                band.band,
+                concert_date,
+                selection.source,
+                selection.reason
+            );
+            selection
+        }
+        (None, None) => {
+            tracing::info!("No date provided for {}, using Spotify picture", band.band);
+            CoverSelection::band_picture("no concert date")
+        }
+    };
+
+    let mut urls = Vec::new();
+    if let Some(url) = selection.url.take() {
+        urls.push(url);
+    }
+    urls.push(band.picture.clone());
+    selection.url = urls.first().cloned();
+    (urls, selection)
+}
+
+/// The closest album before the concert on MusicBrainz, or `deezer` (the band
+/// picture Deezer's lookup fell back to) if there's none with cover art
+async fn musicbrainz_album(
+    musicbrainz: &MusicBrainz,
+    band: &SawThatBand,
+    concert_date: &str,
+    deezer: CoverSelection,
+) -> CoverSelection {
+    let lookup = musicbrainz
+        .album_for_concert(&band.band, concert_date, |title| {
+            blocklist::get().blocks_title(title)
+        })
         .await;
+    match lookup {
+        Ok(Some(album)) => CoverSelection {
+            source: CoverSource::MusicbrainzAlbum,
+            reason: format!(
+                "{}; closest MusicBrainz release before the concert ({} days)",
+                deezer.reason, album.days_before
+            ),
+            album: Some(album.title),
+            release_date: Some(album.release_date),
+            days_before: Some(album.days_before),
+            albums: deezer.albums,
+            url: Some(album.cover_url),
+        },
+        Ok(None) => deezer,
+        Err(e) => {
+            tracing::warn!("MusicBrainz lookup failed for {}: {}", band.band, e);
+            deezer
+        }
+    }
+}
 
-    Ok(rendered)
+/// The album an override picks for a concert, `None` if it can't be found
+async fn override_album(
+    client: &Client,
+    deezer_url: &str,
+    band: &SawThatBand,
+    date: Option<&str>,
+    title: &str,
+) -> Option<CoverSelection> {
+    match deezer::fetch_album_by_title(client, deezer_url, &band.band, title).await {
+        Ok(Some(album)) => {
+            tracing::info!("Using album override '{}' for {}", album.title, band.band);
+            Some(CoverSelection {
+                source: CoverSource::AlbumOverride,
+                reason: format!("overridden to '{}'", title),
+                days_before: album
+                    .release_date
+                    .as_deref()
+                    .zip(date)
+                    .and_then(|(release, concert)| deezer::days_between(release, concert)),
+                url: album.cover_url().map(String::from),
+                album: Some(album.title),
+                release_date: album.release_date,
+                albums: None,
+            })
+        }
+        Ok(None) => {
+            tracing::warn!(
+                "Album override '{}' for {} not found on Deezer",
+                title,
+                band.band
+            );
+            None
+        }
+        Err(e) => {
+            tracing::warn!(
+                "Deezer API error looking up override '{}' for {}: {}",
+                title,
+                band.band,
+                e
+            );
+            None
+        }
+    }
 }
 
-/// Resolve the image URL for a band/concert
+/// Pick the cover for a concert from the Deezer album lookup
 ///
-/// Tries Deezer album art first, falls back to Spotify picture.
-async fn resolve_image_url(client: &Client, band: &SawThatBand, date: Option<&str>) -> String {
-    if let Some(concert_date) = date {
-        match deezer::fetch_album_art_for_concert(client, &band.band, concert_date).await {
-            Ok(Some(url)) => {
-                tracing::info!(
-                    "Using Deezer album art for {} at {}: {}",
-                    band.band,
-                    concert_date,
-                    url
-                );
-                return url;
-            }
-            Ok(None) => {
-                tracing::info!(
-                    "No Deezer album found for {} at {}, using Spotify picture",
-                    band.band,
-                    concert_date
-                );
-            }
-            Err(e) => {
-                tracing::warn!(
-                    "Deezer API error for {} at {}: {}, using Spotify picture",
-                    band.band,
-                    concert_date,
-                    e
-                );
-            }
+/// The closest album's cover unless `blocked` rejects its title or it has none,
+/// otherwise the band picture (with no URL set).
+fn select_album(
+    lookup: Result<AlbumLookup, AppError>,
+    blocked: impl Fn(&str) -> bool,
+) -> CoverSelection {
+    let (album, days_before, albums) = match lookup {
+        Ok(AlbumLookup::Found {
+            album,
+            days_before,
+            albums,
+        }) => (album, days_before, albums),
+        Ok(AlbumLookup::NoneBefore { albums }) => {
+            return CoverSelection {
+                albums: Some(albums),
+                ..CoverSelection::band_picture(format!(
+                    "none of {} albums released before the concert",
+                    albums
+                ))
+            };
         }
+        Ok(AlbumLookup::ArtistNotFound) => {
+            return CoverSelection::band_picture("artist not found on Deezer");
+        }
+        Err(e) => return CoverSelection::band_picture(format!("Deezer API error: {}", e)),
+    };
+
+    let mut selection = CoverSelection {
+        album: Some(album.title.clone()),
+        release_date: album.release_date.clone(),
+        days_before,
+        albums: Some(albums),
+        ..CoverSelection::band_picture("")
+    };
+    if blocked(&album.title) {
+        selection.reason = format!("album '{}' is blocked", album.title);
+    } else if let Some(url) = album.cover_url() {
+        selection.source = CoverSource::DeezerAlbum;
+        selection.reason = match days_before {
+            Some(days) => format!("closest release before the concert ({} days)", days),
+            None => "closest release before the concert".to_string(),
+        };
+        selection.url = Some(url.to_string());
     } else {
-        tracing::info!("No date provided for {}, using Spotify picture", band.band);
+        selection.reason = format!("album '{}' has no cover art", album.title);
     }
-
-    band.picture.clone()
+    selection
 }
 
 /// Format date from DD-MM-YYYY to "Month DDth, YYYY" (e.g., "July 17th, 2025")
@@ -340,10 +809,56 @@ mod tests {
             id: "test-id".to_string(),
         }];
 
-        let items = bands_to_widget_items(&bands, 10);
+        let items = bands_to_widget_items(&bands, &ConcertsConfig::default());
         assert_eq!(items.len(), 1);
         // New format: YYYY-MM-DD-band-id
-        assert_eq!(items[0], "2024-06-15-test-id");
+        assert_eq!(items[0].path, "2024-06-15-test-id");
+        assert_eq!(items[0].cache_key, "2024-06-15-test-id");
+        assert_eq!(items[0].title.as_deref(), Some("Test Band"));
+        assert_eq!(
+            items[0].alt.as_deref(),
+            Some("Concert card for Test Band at Test Venue on June 15th, 2024.")
+        );
+    }
+
+    #[test]
+    fn test_bands_to_widget_items_filtered() {
+        let concert = |date: &str| SawThatConcert {
+            date: date.to_string(),
+            location: "Test Venue".to_string(),
+        };
+        let bands = vec![SawThatBand {
+            band: "Test Band".to_string(),
+            picture: "https://example.com/image.jpg".to_string(),
+            concerts: vec![
+                concert("01-03-2014"),
+                concert("15-06-2024"),
+                concert("20-01-2015"),
+                concert("31-12-2019"),
+            ],
+            id: "test-id".to_string(),
+        }];
+
+        let config = ConcertsConfig {
+            limit: 2,
+            sort: ConcertSort::Oldest,
+            since: chrono::NaiveDate::from_ymd_opt(2015, 1, 1),
+            ..Default::default()
+        };
+        let paths = |items: WidgetData| items.into_iter().map(|item| item.path).collect::<Vec<_>>();
+        assert_eq!(
+            paths(bands_to_widget_items(&bands, &config)),
+            vec!["2015-01-20-test-id", "2019-12-31-test-id"]
+        );
+
+        let config = ConcertsConfig {
+            sort: ConcertSort::Newest,
+            ..config
+        };
+        assert_eq!(
+            paths(bands_to_widget_items(&bands, &config)),
+            vec!["2024-06-15-test-id", "2019-12-31-test-id"]
+        );
     }
 
     #[test]
@@ -365,4 +880,139 @@ mod tests {
         assert_eq!(band_id, "my-cool-band-name");
         assert_eq!(date, "20-01-2024");
     }
+
+    fn album(title: &str, cover: Option<&str>) -> deezer::DeezerAlbum {
+        deezer::DeezerAlbum {
+            title: title.to_string(),
+            release_date: Some("2024-05-01".to_string()),
+            cover_xl: cover.map(String::from),
+            cover_big: None,
+        }
+    }
+
+    #[test]
+    fn test_select_album() {
+        let found = |album| {
+            Ok(AlbumLookup::Found {
+                album,
+                days_before: Some(45),
+                albums: 7,
+            })
+        };
+        let cover = "https://example.com/cover.jpg";
+
+        let selection = select_album(found(album("Tour", Some(cover))), |_| false);
+        assert_eq!(selection.source, CoverSource::DeezerAlbum);
+        assert_eq!(
+            selection.reason,
+            "closest release before the concert (45 days)"
+        );
+        assert_eq!(selection.url.as_deref(), Some(cover));
+        assert_eq!(selection.release_date.as_deref(), Some("2024-05-01"));
+        assert_eq!(selection.albums, Some(7));
+
+        let selection = select_album(found(album("Live", Some(cover))), |title| title == "Live");
+        assert_eq!(selection.source, CoverSource::BandPicture);
+        assert_eq!(selection.reason, "album 'Live' is blocked");
+        assert_eq!(selection.album.as_deref(), Some("Live"));
+        assert_eq!(selection.url, None);
+
+        let selection = select_album(found(album("Tour", None)), |_| false);
+        assert_eq!(selection.reason, "album 'Tour' has no cover art");
+
+        let selection = select_album(Ok(AlbumLookup::NoneBefore { albums: 3 }), |_| false);
+        assert_eq!(
+            selection.reason,
+            "none of 3 albums released before the concert"
+        );
+        let selection = select_album(Ok(AlbumLookup::ArtistNotFound), |_| false);
+        assert_eq!(selection.reason, "artist not found on Deezer");
+        let selection = select_album(Err(AppError::ExternalApi("timed out".to_string())), |_| {
+            false
+        });
+        assert!(selection.reason.starts_with("Deezer API error"));
+    }
+
+    #[test]
+    fn test_cover_fetched() {
+        let selection = CoverSelection {
+            source: CoverSource::DeezerAlbum,
+            url: Some("https://example.com/cover.jpg".to_string()),
+            ..CoverSelection::band_picture("closest release before the concert")
+        };
+        // The first candidate was fetched
+        let fetched = selection
+            .clone()
+            .fetched(Some("https://example.com/cover.jpg"));
+        assert_eq!(fetched, selection);
+
+        // The album cover was blocked by its hash, the band picture was used
+        let fetched = selection
+            .clone()
+            .fetched(Some("https://example.com/band.jpg"));
+        assert_eq!(fetched.source, CoverSource::BandPicture);
+        assert_eq!(fetched.reason, "album cover is blocked");
+        assert_eq!(fetched.url.as_deref(), Some("https://example.com/band.jpg"));
+
+        let fetched = selection.fetched(None);
+        assert_eq!(fetched.source, CoverSource::Placeholder);
+        assert_eq!(fetched.url, None);
+    }
+
+    #[tokio::test]
+    async fn test_album_overrides() {
+        let dir = std::env::temp_dir().join(format!("overrides-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = "2024-06-15-band-id";
+
+        let overrides = AlbumOverrides::new(Some(dir.clone()));
+        assert_eq!(overrides.get(path).await, None);
+        overrides
+            .set(path, Some("  Live at the Fillmore "))
+            .await
+            .unwrap();
+        assert_eq!(
+            overrides.get(path).await.as_deref(),
+            Some("Live at the Fillmore")
+        );
+        assert!(overrides.set(path, Some(&"x".repeat(201))).await.is_err());
+
+        // Saved across restarts, and cleared by an empty title
+        let reloaded = AlbumOverrides::new(Some(dir.clone()));
+        assert_eq!(
+            reloaded.get(path).await.as_deref(),
+            Some("Live at the Fillmore")
+        );
+        reloaded.set(path, Some("")).await.unwrap();
+        assert_eq!(AlbumOverrides::new(Some(dir.clone())).get(path).await, None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_concert_notes() {
+        let dir = std::env::temp_dir().join(format!("notes-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = "2024-06-15-band-id";
+
+        let notes = ConcertNotes::new(Some(dir.clone()));
+        assert_eq!(notes.get(path).await, None);
+        notes
+            .set(path, Some(" Road trip\n with  Sam "))
+            .await
+            .unwrap();
+        assert_eq!(notes.get(path).await.as_deref(), Some("Road trip with Sam"));
+        assert!(notes.set(path, Some(&"x".repeat(101))).await.is_err());
+
+        // Saved across restarts, and removed by an empty note
+        let reloaded = ConcertNotes::new(Some(dir.clone()));
+        assert_eq!(
+            reloaded.get(path).await.as_deref(),
+            Some("Road trip with Sam")
+        );
+        reloaded.set(path, Some(" ")).await.unwrap();
+        assert_eq!(ConcertNotes::new(Some(dir.clone())).get(path).await, None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,328 @@
+//! Setlist.fm API integration
+//!
+//! Looks up what a band played at a concert, to add the tour name or the
+//! opening or closing song to the caption under the card's image. Lookups,
+//! misses included, are cached for a week: setlists of past shows rarely
+//! change, and concert entries are resolved again every day. Requests are
+//! spaced out to stay under the API's rate limit.
+
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+
+use crate::config::SetlistFmConfig;
+use crate::error::AppError;
+
+/// Setlist.fm API base URL
+pub const SETLISTFM_API_URL: &str = "https://api.setlist.fm/rest/1.0";
+
+/// How long a lookup is reused, found or not
+const SETLIST_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Shortest gap between requests (the API allows 2 per second)
+const REQUEST_INTERVAL: Duration = Duration::from_millis(600);
+
+/// What a setlist adds to a concert's caption
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SetlistCaption {
+    /// The tour the show was part of
+    #[default]
+    Tour,
+    /// The first song played
+    Opener,
+    /// The last song played, encores included
+    Closer,
+}
+
+/// Setlist search response
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    #[serde(default)]
+    setlist: Vec<Setlist>,
+}
+
+/// A setlist from the Setlist.fm API
+#[derive(Debug, Clone, Deserialize)]
+pub struct Setlist {
+    #[serde(default)]
+    pub venue: Option<Venue>,
+    #[serde(default)]
+    pub tour: Option<Tour>,
+    #[serde(default)]
+    pub sets: Sets,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Venue {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tour {
+    pub name: String,
+}
+
+/// The sets of a show, main set first and encores after
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Sets {
+    #[serde(default)]
+    pub set: Vec<Set>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Set {
+    #[serde(default)]
+    pub song: Vec<Song>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Song {
+    pub name: String,
+    /// Played from a recording, like an intro
+    #[serde(default)]
+    pub tape: bool,
+}
+
+impl Setlist {
+    /// Songs played live, in order
+    fn songs(&self) -> impl Iterator<Item = &str> {
+        self.sets
+            .set
+            .iter()
+            .flat_map(|set| &set.song)
+            .filter(|song| !song.tape)
+            .map(|song| song.name.trim())
+            .filter(|name| !name.is_empty())
+    }
+
+    pub fn opener(&self) -> Option<&str> {
+        self.songs().next()
+    }
+
+    pub fn closer(&self) -> Option<&str> {
+        self.songs().last()
+    }
+
+    pub fn tour(&self) -> Option<&str> {
+        self.tour
+            .as_ref()
+            .map(|tour| tour.name.trim())
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Caption line for the show, preferring `caption` and falling back to
+    /// the tour or the opener
+    pub fn caption(&self, caption: SetlistCaption) -> Option<String> {
+        let tour = || self.tour().map(String::from);
+        let opener = || self.opener().map(|song| format!("Opened with {}", song));
+        match caption {
+            SetlistCaption::Tour => tour().or_else(opener),
+            SetlistCaption::Opener => opener().or_else(tour),
+            SetlistCaption::Closer => self
+                .closer()
+                .map(|song| format!("Closed with {}", song))
+                .or_else(tour),
+        }
+    }
+
+    /// Whether the show was at a SawThat location ("Venue, City")
+    fn played_at(&self, location: &str) -> bool {
+        let location = location.to_lowercase();
+        self.venue.as_ref().is_some_and(|venue| {
+            let name = venue.name.trim().to_lowercase();
+            !name.is_empty() && location.contains(&name)
+        })
+    }
+}
+
+/// The setlist of the show at `location`, or the first one that has anything
+/// to show if none matches (the venue names of the two sites often differ)
+pub fn pick_setlist<'a>(setlists: &'a [Setlist], location: &str) -> Option<&'a Setlist> {
+    setlists
+        .iter()
+        .find(|setlist| setlist.played_at(location))
+        .or_else(|| {
+            setlists
+                .iter()
+                .find(|setlist| setlist.tour().is_some() || setlist.opener().is_some())
+        })
+}
+
+/// Setlist.fm client with a lookup cache
+pub struct SetlistFm {
+    client: Client,
+    config: SetlistFmConfig,
+    /// Setlists by "{date} {artist}", with when they were fetched
+    cache: RwLock<HashMap<String, (Instant, Vec<Setlist>)>>,
+    /// When the last request was sent
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl SetlistFm {
+    pub fn new(client: Client, config: SetlistFmConfig) -> Self {
+        Self {
+            client,
+            config,
+            cache: RwLock::new(HashMap::new()),
+            last_request: Mutex::new(None),
+        }
+    }
+
+    /// Caption line for a band's show on a DD-MM-YYYY date at `location`
+    ///
+    /// `None` if Setlist.fm has nothing for it; API errors are logged and
+    /// leave the caption as it is.
+    pub async fn caption(&self, artist: &str, date: &str, location: &str) -> Option<String> {
+        let setlists = match self.setlists(artist, date).await {
+            Ok(setlists) => setlists,
+            Err(e) => {
+                tracing::warn!("Setlist.fm lookup failed for {} on {}: {}", artist, date, e);
+                return None;
+            }
+        };
+        let caption = pick_setlist(&setlists, location)?.caption(self.config.caption);
+        tracing::info!(
+            "Setlist.fm caption for {} on {}: {:?}",
+            artist,
+            date,
+            caption
+        );
+        caption
+    }
+
+    /// Setlists of a band's shows on a date, from the cache or the API
+    async fn setlists(&self, artist: &str, date: &str) -> Result<Vec<Setlist>, AppError> {
+        let key = format!("{} {}", date, artist);
+        if let Some((fetched_at, setlists)) = self.cache.read().await.get(&key) {
+            if fetched_at.elapsed() < SETLIST_TTL {
+                return Ok(setlists.clone());
+            }
+        }
+
+        let setlists = self.search(artist, date).await?;
+        self.cache
+            .write()
+            .await
+            .insert(key, (Instant::now(), setlists.clone()));
+        Ok(setlists)
+    }
+
+    /// Search the API for a band's setlists on a date
+    async fn search(&self, artist: &str, date: &str) -> Result<Vec<Setlist>, AppError> {
+        // Hold the slot until the request is sent, so concurrent lookups queue up
+        let mut last_request = self.last_request.lock().await;
+        if let Some(wait) = last_request.map(|at| REQUEST_INTERVAL.saturating_sub(at.elapsed())) {
+            tokio::time::sleep(wait).await;
+        }
+        *last_request = Some(Instant::now());
+
+        let response = self
+            .client
+            .get(format!("{}/search/setlists", self.config.api_url))
+            .query(&[("artistName", artist), ("date", date)])
+            .header("x-api-key", &self.config.api_key)
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+        drop(last_request);
+
+        // No matching setlists is reported as not found
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        if !response.status().is_success() {
+            return Err(AppError::ExternalApi(format!(
+                "Setlist.fm API returned status: {}",
+                response.status()
+            )));
+        }
+
+        let response: SearchResponse = response.json().await?;
+        Ok(response.setlist)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setlists() -> Vec<Setlist> {
+        let json = r#"{
+            "setlist": [
+                {
+                    "venue": {"name": "The Fillmore", "city": {"name": "San Francisco"}},
+                    "sets": {"set": []}
+                },
+                {
+                    "venue": {"name": "Red Rocks Amphitheatre", "city": {"name": "Morrison"}},
+                    "tour": {"name": "Summer Tour 2024"},
+                    "sets": {"set": [
+                        {"song": [
+                            {"name": "Intro", "tape": true},
+                            {"name": "Tweezer"},
+                            {"name": "Ghost"}
+                        ]},
+                        {"encore": 1, "song": [{"name": "Tweezer Reprise"}]}
+                    ]}
+                }
+            ]
+        }"#;
+        serde_json::from_str::<SearchResponse>(json)
+            .unwrap()
+            .setlist
+    }
+
+    #[test]
+    fn test_caption() {
+        let setlists = setlists();
+        let setlist = &setlists[1];
+        assert_eq!(setlist.opener(), Some("Tweezer"));
+        assert_eq!(setlist.closer(), Some("Tweezer Reprise"));
+        assert_eq!(
+            setlist.caption(SetlistCaption::Tour).as_deref(),
+            Some("Summer Tour 2024")
+        );
+        assert_eq!(
+            setlist.caption(SetlistCaption::Opener).as_deref(),
+            Some("Opened with Tweezer")
+        );
+        assert_eq!(
+            setlist.caption(SetlistCaption::Closer).as_deref(),
+            Some("Closed with Tweezer Reprise")
+        );
+
+        // Falls back to what the setlist has
+        let mut untoured = setlist.clone();
+        untoured.tour = None;
+        assert_eq!(
+            untoured.caption(SetlistCaption::Tour).as_deref(),
+            Some("Opened with Tweezer")
+        );
+        assert_eq!(setlists[0].caption(SetlistCaption::Closer), None);
+    }
+
+    #[test]
+    fn test_pick_setlist() {
+        let setlists = setlists();
+        fn venue(setlist: Option<&Setlist>) -> Option<&str> {
+            setlist
+                .and_then(|s| s.venue.as_ref())
+                .map(|v| v.name.as_str())
+        }
+
+        assert_eq!(
+            venue(pick_setlist(&setlists, "The Fillmore, San Francisco")),
+            Some("The Fillmore")
+        );
+        // No venue match: the first one with something to show
+        assert_eq!(
+            venue(pick_setlist(&setlists, "Somewhere Else")),
+            Some("Red Rocks Amphitheatre")
+        );
+        assert!(pick_setlist(&setlists[..1], "Somewhere Else").is_none());
+        assert!(pick_setlist(&[], "The Fillmore").is_none());
+    }
+}
@@ -0,0 +1,407 @@
+//! Spotify Web API integration
+//!
+//! Builds a "recently played" widget from the user's listening history. Access
+//! tokens are obtained from the configured refresh token and reused until they
+//! expire. Each item is a track ID; items are limited to one per album so the
+//! rotation shows distinct covers.
+
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+use crate::cache::{ConcertCache, ConcertEntry};
+use crate::config::SpotifyConfig;
+use crate::error::AppError;
+use crate::experiment::Variant;
+use crate::image_processing;
+use crate::text::ConcertInfo;
+use crate::widget::{Orientation, WidgetData, WidgetItem, WidgetWidth};
+
+/// Spotify accounts service token endpoint
+const SPOTIFY_TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+
+/// Spotify Web API base URL
+const SPOTIFY_API_URL: &str = "https://api.spotify.com/v1";
+
+/// Most plays returned by the recently played endpoint
+const RECENTLY_PLAYED_LIMIT: u32 = 50;
+
+/// Refresh access tokens this long before they expire
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// A track from the Spotify API
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpotifyTrack {
+    /// Track ID (base62)
+    pub id: String,
+    /// Track title
+    pub name: String,
+    /// Credited artists
+    pub artists: Vec<SpotifyArtist>,
+    /// Album the track was played from
+    pub album: SpotifyAlbum,
+}
+
+impl SpotifyTrack {
+    /// Artist names joined for display
+    pub fn artist_names(&self) -> String {
+        self.artists
+            .iter()
+            .map(|artist| artist.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// An artist from the Spotify API
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpotifyArtist {
+    pub name: String,
+}
+
+/// An album from the Spotify API
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpotifyAlbum {
+    /// Album ID (base62)
+    pub id: String,
+    /// Album title
+    pub name: String,
+    /// Cover art in several sizes
+    pub images: Vec<SpotifyImage>,
+}
+
+impl SpotifyAlbum {
+    /// URL of the largest cover image
+    pub fn cover_url(&self) -> Option<&str> {
+        self.images
+            .iter()
+            .max_by_key(|image| image.width.unwrap_or(0))
+            .map(|image| image.url.as_str())
+    }
+}
+
+/// A cover image from the Spotify API
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpotifyImage {
+    pub url: String,
+    pub width: Option<u32>,
+}
+
+/// A play from the recently played endpoint
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlayHistory {
+    pub track: SpotifyTrack,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecentlyPlayedResponse {
+    items: Vec<PlayHistory>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// A cached access token
+struct AccessToken {
+    token: String,
+    expires_at: Instant,
+}
+
+/// Spotify Web API client authorized with a refresh token
+pub struct SpotifyClient {
+    client: Client,
+    config: SpotifyConfig,
+    token: RwLock<Option<AccessToken>>,
+}
+
+impl SpotifyClient {
+    pub fn new(client: Client, config: SpotifyConfig) -> Self {
+        Self {
+            client,
+            config,
+            token: RwLock::new(None),
+        }
+    }
+
+    /// Get a valid access token, refreshing it if needed
+    async fn access_token(&self) -> Result<String, AppError> {
+        if let Some(token) = self.token.read().await.as_ref() {
+            if Instant::now() + TOKEN_EXPIRY_MARGIN < token.expires_at {
+                return Ok(token.token.clone());
+            }
+        }
+
+        tracing::info!("Refreshing Spotify access token");
+        let response = self
+            .client
+            .post(SPOTIFY_TOKEN_URL)
+            .basic_auth(&self.config.client_id, Some(&self.config.client_secret))
+            .form(&[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", self.config.refresh_token.as_str()),
+            ])
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(AppError::ExternalApi(format!(
+                "Spotify token refresh returned status: {}",
+                response.status()
+            )));
+        }
+
+        let token: TokenResponse = response.json().await?;
+        *self.token.write().await = Some(AccessToken {
+            token: token.access_token.clone(),
+            expires_at: Instant::now() + Duration::from_secs(token.expires_in),
+        });
+
+        Ok(token.access_token)
+    }
+
+    /// GET an API endpoint and parse the JSON response
+    async fn get<T: for<'de> Deserialize<'de>>(&self, path: &str) -> Result<T, AppError> {
+        let token = self.access_token().await?;
+        let url = format!("{}{}", SPOTIFY_API_URL, path);
+
+        let response = self
+            .client
+            .get(&url)
+            .bearer_auth(token)
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(AppError::ExternalApi(format!(
+                "Spotify API returned status: {}",
+                response.status()
+            )));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Fetch the user's recently played tracks (most recent first)
+    pub async fn recently_played(&self) -> Result<Vec<PlayHistory>, AppError> {
+        let response: RecentlyPlayedResponse = self
+            .get(&format!(
+                "/me/player/recently-played?limit={}",
+                RECENTLY_PLAYED_LIMIT
+            ))
+            .await?;
+
+        tracing::info!("Fetched {} recently played tracks", response.items.len());
+
+        Ok(response.items)
+    }
+
+    /// Fetch a single track
+    pub async fn track(&self, id: &str) -> Result<SpotifyTrack, AppError> {
+        self.get(&format!("/tracks/{}", id)).await
+    }
+}
+
+/// Convert recently played tracks to widget items
+///
+/// Keeps the most recent play of each album, in play order, titled with the
+/// album name.
+/// Path format: Spotify track ID
+pub fn plays_to_widget_items(plays: &[PlayHistory], limit: usize) -> WidgetData {
+    let mut albums = HashSet::new();
+    plays
+        .iter()
+        .filter(|play| albums.insert(play.track.album.id.as_str()))
+        .take(limit)
+        .map(|play| WidgetItem::new(play.track.id.clone()).with_title(&play.track.album.name))
+        .collect()
+}
+
+/// Check that a path is a Spotify ID (base62)
+pub fn validate_track_id(path: &str) -> Result<(), AppError> {
+    if !path.is_empty() && path.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Ok(())
+    } else {
+        Err(AppError::InvalidPath(format!("invalid track ID: {}", path)))
+    }
+}
+
+/// Fetch and process the album cover for a track
+///
+/// The caption shows the track title, artists and album. Source art, metadata
+/// and rendered images are cached like concert entries, keyed by track ID.
+pub async fn fetch_track_image(
+    client: &Client,
+    spotify: &SpotifyClient,
+    track_id: &str,
+    orientation: Orientation,
+    cache: &ConcertCache,
+    variant: &Variant,
+) -> Result<Vec<u8>, AppError> {
+    let (target_width, target_height) = orientation.dimensions(WidgetWidth::Half);
+
+    // Render from cached source art if we have it
+    if let Some(entry) = cache.get_concert(track_id).await {
+        if let Some(cached_image) = entry.get_image(orientation, &variant.name) {
+            return Ok((**cached_image).clone());
+        }
+
+        tracing::info!(
+            "Rendering {:?} ({}) for track {} using cached data",
+            orientation,
+            variant.name,
+            track_id
+        );
+        let rendered = image_processing::process_image_with_color(
+            &entry.source_image,
+            target_width,
+            target_height,
+            Some(&ConcertInfo {
+                band_name: entry.band_name.clone(),
+                date: entry.formatted_date.clone(),
+                venue: entry.venue.clone(),
+                detail: None,
+                note: None,
+            }),
+            &entry.primary_color,
+            &variant.params,
+            entry.source_url.as_deref(),
+        )?;
+        cache
+            .set_concert_image(
+                track_id,
+                orientation,
+                &variant.name,
+                Arc::new(rendered.clone()),
+            )
+            .await;
+
+        return Ok(rendered);
+    }
+
+    // No cached entry - look up the track and fetch its cover
+    let track = spotify.track(track_id).await?;
+    let image_url = track
+        .album
+        .cover_url()
+        .ok_or_else(|| AppError::NotFound(format!("no cover art for track {}", track_id)))?;
+
+    tracing::info!("Fetching source image from: {}", image_url);
+    let response = client
+        .get(image_url)
+        .header("Accept", "image/*")
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(AppError::ExternalApi(format!(
+            "Failed to fetch image: {}",
+            response.status()
+        )));
+    }
+    let source_image = Arc::new(response.bytes().await?.to_vec());
+    let primary_color = image_processing::extract_primary_color(&source_image)?;
+
+    // Track title takes the headline, artists and album the smaller lines
+    let info = ConcertInfo {
+        band_name: track.name.clone(),
+        date: track.artist_names(),
+        venue: track.album.name.clone(),
+        detail: None,
+        note: None,
+    };
+
+    cache
+        .set_or_update_concert(
+            track_id.to_string(),
+            ConcertEntry {
+                band_name: info.band_name.clone(),
+                venue: info.venue.clone(),
+                formatted_date: info.date.clone(),
+                detail: None,
+                note: None,
+                source_image: source_image.clone(),
+                source_url: Some(image_url.to_string()),
+                cover: None,
+                primary_color,
+                images: HashMap::new(),
+            },
+        )
+        .await;
+
+    let rendered = image_processing::process_image_with_color(
+        &source_image,
+        target_width,
+        target_height,
+        Some(&info),
+        &primary_color,
+        &variant.params,
+        Some(image_url),
+    )?;
+    cache
+        .set_concert_image(
+            track_id,
+            orientation,
+            &variant.name,
+            Arc::new(rendered.clone()),
+        )
+        .await;
+
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECENTLY_PLAYED: &str = r#"{
+        "items": [
+            {"track": {"id": "t1", "name": "Song A", "artists": [{"name": "Band"}],
+                "album": {"id": "a1", "name": "Album 1", "images": [
+                    {"url": "https://i.scdn.co/small", "width": 64, "height": 64},
+                    {"url": "https://i.scdn.co/large", "width": 640, "height": 640}
+                ]}}, "played_at": "2025-01-02T10:00:00Z"},
+            {"track": {"id": "t2", "name": "Song B", "artists": [{"name": "Band"}, {"name": "Guest"}],
+                "album": {"id": "a1", "name": "Album 1", "images": []}}, "played_at": "2025-01-02T09:55:00Z"},
+            {"track": {"id": "t3", "name": "Song C", "artists": [{"name": "Other"}],
+                "album": {"id": "a2", "name": "Album 2", "images": []}}, "played_at": "2025-01-02T09:50:00Z"}
+        ]
+    }"#;
+
+    fn plays() -> Vec<PlayHistory> {
+        serde_json::from_str::<RecentlyPlayedResponse>(RECENTLY_PLAYED)
+            .unwrap()
+            .items
+    }
+
+    #[test]
+    fn test_plays_to_widget_items() {
+        let plays = plays();
+        let paths = |items: WidgetData| items.into_iter().map(|item| item.path).collect::<Vec<_>>();
+        assert_eq!(paths(plays_to_widget_items(&plays, 10)), vec!["t1", "t3"]);
+        assert_eq!(paths(plays_to_widget_items(&plays, 1)), vec!["t1"]);
+    }
+
+    #[test]
+    fn test_track_details() {
+        let plays = plays();
+        assert_eq!(
+            plays[0].track.album.cover_url(),
+            Some("https://i.scdn.co/large")
+        );
+        assert_eq!(plays[1].track.album.cover_url(), None);
+        assert_eq!(plays[1].track.artist_names(), "Band, Guest");
+    }
+
+    #[test]
+    fn test_validate_track_id() {
+        assert!(validate_track_id("4uLU6hMCjMI75M1A2tKUQC").is_ok());
+        assert!(validate_track_id("").is_err());
+        assert!(validate_track_id("../etc").is_err());
+    }
+}
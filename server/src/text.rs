@@ -1,14 +1,30 @@
 //! Text rendering for e-paper display
 //!
 //! Renders text onto indexed images using fonts discovered at runtime via fontconfig.
+//! Concert captions can also be drawn anti-aliased onto RGB canvases, at a
+//! multiple of the panel layout, for print exports. A caption with a detail
+//! line (the tour or a song, from Setlist.fm) or a personal note is set tighter
+//! to fit four lines, and tighter still to fit both. Notes are slanted into a
+//! synthetic oblique, as there's only the one bold face.
+//!
+//! Lines are measured many times over while fitting them (at each size, and for
+//! every abbreviation or truncation tried), so the font is loaded once with
+//! advance tables for the caption sizes, and measured lines are remembered.
 
 use ab_glyph::{Font, FontVec, PxScale, ScaleFont};
+use image::{Rgb, RgbImage};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::process::Command;
-use std::sync::OnceLock;
+use std::sync::{Mutex, OnceLock};
+
+use crate::abbreviate;
 
 /// Cached font loaded at runtime
-static FONT: OnceLock<FontVec> = OnceLock::new();
+static FONT: OnceLock<Typeface> = OnceLock::new();
+
+/// Lines remembered per size before they're forgotten
+const LINE_CACHE_CAPACITY: usize = 1024;
 
 /// Font patterns to try in order of preference
 const FONT_PATTERNS: &[&str] = &[
@@ -21,12 +37,89 @@ const FONT_PATTERNS: &[&str] = &[
 ];
 
 /// Load and cache the font, or return the cached version
-fn get_font() -> &'static FontVec {
+fn typeface() -> &'static Typeface {
     FONT.get_or_init(|| {
-        load_font().expect("Failed to load font. Install Berkeley Mono or a fallback (IBM Plex, DejaVu Sans, Liberation Sans)")
+        Typeface::new(load_font().expect("Failed to load font. Install Berkeley Mono or a fallback (IBM Plex, DejaVu Sans, Liberation Sans)"))
     })
 }
 
+/// Load the font and precompute its metrics now rather than on the first render
+pub fn init() {
+    typeface();
+}
+
+/// The font, with its metrics precomputed
+struct Typeface {
+    font: FontVec,
+    /// Advances of the first 256 code points at each caption size, keyed by
+    /// the size's bits
+    advances: HashMap<u32, [f32; 256]>,
+    /// Widths of lines already measured, keyed by the size's bits and the text
+    line_widths: Mutex<HashMap<u32, HashMap<String, f32>>>,
+}
+
+impl Typeface {
+    fn new(font: FontVec) -> Self {
+        let advances = [
+            BAND_SIZES,
+            VENUE_SIZES,
+            COMPACT_BAND_SIZES,
+            COMPACT_LINE_SIZES,
+        ]
+        .concat()
+        .into_iter()
+        .map(|size| {
+            let scaled_font = font.as_scaled(PxScale::from(size));
+            let table =
+                std::array::from_fn(|c| scaled_font.h_advance(font.glyph_id(char::from(c as u8))));
+            (size.to_bits(), table)
+        })
+        .collect();
+        Self {
+            font,
+            advances,
+            line_widths: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Width of a line of text at a given scale
+    ///
+    /// The same as measuring it with the font, only faster.
+    fn line_width(&self, text: &str, scale: PxScale) -> f32 {
+        let key = scale.x.to_bits();
+        if let Some(&width) = self
+            .line_widths
+            .lock()
+            .unwrap()
+            .get(&key)
+            .and_then(|lines| lines.get(text))
+        {
+            return width;
+        }
+
+        let width = match self.advances.get(&key).filter(|_| scale.x == scale.y) {
+            Some(table) => {
+                let scaled_font = self.font.as_scaled(scale);
+                text.chars()
+                    .map(|c| match table.get(c as usize) {
+                        Some(&advance) => advance,
+                        None => scaled_font.h_advance(self.font.glyph_id(c)),
+                    })
+                    .sum()
+            }
+            None => measure_text_width(&self.font, text, scale),
+        };
+
+        let mut line_widths = self.line_widths.lock().unwrap();
+        let lines = line_widths.entry(key).or_default();
+        if lines.len() >= LINE_CACHE_CAPACITY {
+            lines.clear();
+        }
+        lines.insert(text.to_string(), width);
+        width
+    }
+}
+
 /// Find and load a font using fontconfig's fc-match
 fn load_font() -> Option<FontVec> {
     for pattern in FONT_PATTERNS {
@@ -77,85 +170,270 @@ fn find_font(pattern: &str) -> Option<PathBuf> {
     }
 }
 
-/// Palette indices
-const BLACK_INDEX: u8 = 0;
-const WHITE_INDEX: u8 = 1;
-
 /// Font size steps for band name (largest to smallest)
 const BAND_SIZES: &[f32] = &[48.0, 40.0, 32.0, 24.0, 20.0];
 
 /// Font size steps for venue (largest to smallest)
 const VENUE_SIZES: &[f32] = &[24.0, 20.0, 16.0];
 
+/// Font size steps for band name above a detail line
+const COMPACT_BAND_SIZES: &[f32] = &[36.0, 32.0, 24.0, 20.0];
+
+/// Font size steps for venue and detail lines in the compact layout
+const COMPACT_LINE_SIZES: &[f32] = &[20.0, 16.0];
+
+/// Font size steps for venue, detail and note lines when there are both
+const DENSE_LINE_SIZES: &[f32] = &[16.0];
+
+/// Horizontal shift of oblique text per pixel above the baseline
+const OBLIQUE_SLANT: f32 = 0.2;
+
 /// Concert info to render
 pub struct ConcertInfo {
     pub band_name: String,
     pub date: String,
     pub venue: String,
+    /// Extra line below the venue, e.g. the tour name
+    pub detail: Option<String>,
+    /// Personal note, set in oblique below everything else
+    pub note: Option<String>,
 }
 
 /// Render concert info text onto an indexed buffer (post-dithering)
-/// Places text in the bottom area (below the image)
-/// Uses black text on light backgrounds, white text on dark backgrounds
+/// Places text in the bottom area (below the image), in palette color `text_index`
 pub fn render_concert_info_indexed(
     indexed: &mut [u8],
     width: u32,
     info: &ConcertInfo,
     text_area_top: u32,
+    text_index: u8,
+) {
+    let height = indexed.len() as u32 / width;
+
+    layout_concert_info(width, info, text_area_top, 1.0, &mut |x, y, coverage| {
+        // Hard edge threshold (0.5 for clean edges with bold fonts)
+        if x < width && y < height && coverage > 0.5 {
+            indexed[(y * width + x) as usize] = text_index;
+        }
+    });
+}
+
+/// Render concert info text onto an RGB canvas, anti-aliased
+///
+/// Same layout as [`render_concert_info_indexed`], with every size and offset
+/// multiplied by `scale` (the canvas is `scale` times the panel card).
+pub fn render_concert_info_rgb(
+    canvas: &mut RgbImage,
+    info: &ConcertInfo,
+    text_area_top: u32,
     is_light_bg: bool,
+    scale: f32,
 ) {
-    let font = get_font();
-    let text_color = if is_light_bg {
-        BLACK_INDEX
-    } else {
-        WHITE_INDEX
-    };
+    let text_color: u8 = if is_light_bg { 0 } else { 255 };
+    let (width, height) = canvas.dimensions();
+
+    layout_concert_info(width, info, text_area_top, scale, &mut |x, y, coverage| {
+        if x < width && y < height {
+            let Rgb(pixel) = canvas.get_pixel_mut(x, y);
+            let t = coverage.clamp(0.0, 1.0);
+            for channel in pixel.iter_mut() {
+                *channel = (*channel as f32 + (text_color as f32 - *channel as f32) * t) as u8;
+            }
+        }
+    });
+}
+
+/// Lay out the band name, date and venue below `text_area_top`, at `scale`
+/// times the panel sizes, passing each glyph pixel and its coverage to `plot`
+fn layout_concert_info(
+    width: u32,
+    info: &ConcertInfo,
+    text_area_top: u32,
+    scale: f32,
+    plot: &mut impl FnMut(u32, u32, f32),
+) {
+    let detail = info.detail.as_deref().filter(|detail| !detail.is_empty());
+    let note = info.note.as_deref().filter(|note| !note.is_empty());
+    if detail.is_some() || note.is_some() {
+        layout_compact(width, info, detail, note, text_area_top, scale, plot);
+        return;
+    }
+
+    let face = typeface();
+    let px = |value: f32| (value * scale) as u32;
 
     // Leave some horizontal padding (8px each side)
-    let max_width = width.saturating_sub(16) as f32;
+    let max_width = width.saturating_sub(px(16.0)) as f32;
 
     // Band name - find largest font size that fits
-    let (band_scale, band_y_offset) = fit_text_size(&font, &info.band_name, max_width, BAND_SIZES);
+    let (band_scale, band_y_offset) =
+        fit_text_size(face, &info.band_name, max_width, BAND_SIZES, scale);
     let band_y = text_area_top + band_y_offset;
-    draw_text_indexed_centered(
-        indexed,
-        width,
-        &font,
-        &info.band_name,
-        band_scale,
-        band_y,
-        text_color,
-    );
+    draw_text_centered(width, face, &info.band_name, band_scale, band_y, plot);
 
     // Calculate remaining space and position date/venue accordingly
     let band_height = (band_scale.y * 1.1) as u32;
 
     // Date - fixed size (24px)
-    let date_scale = PxScale::from(24.0);
+    let date_scale = PxScale::from(24.0 * scale);
     let date_y = band_y + band_height;
-    draw_text_indexed_centered(
-        indexed, width, &font, &info.date, date_scale, date_y, text_color,
-    );
+    draw_text_centered(width, face, &info.date, date_scale, date_y, plot);
+
+    // Venue - abbreviate, then scale to fit if needed
+    let (venue, venue_scale) = fit_abbreviated(face, &info.venue, max_width, VENUE_SIZES, scale);
+    let venue_y = date_y + px(28.0);
+    draw_text_centered(width, face, &venue, venue_scale, venue_y, plot);
+}
+
+/// Lay out the band name, date, venue and a detail line, a note or both in the
+/// same space, with smaller type
+fn layout_compact(
+    width: u32,
+    info: &ConcertInfo,
+    detail: Option<&str>,
+    note: Option<&str>,
+    text_area_top: u32,
+    scale: f32,
+    plot: &mut impl FnMut(u32, u32, f32),
+) {
+    let face = typeface();
+    let px = |value: f32| (value * scale) as u32;
+    let max_width = width.saturating_sub(px(16.0)) as f32;
 
-    // Venue - scale to fit if needed
-    let (venue_scale, _) = fit_text_size(&font, &info.venue, max_width, VENUE_SIZES);
-    let venue_y = date_y + 28;
-    draw_text_indexed_centered(
-        indexed,
+    // Smaller band names move down to stay centered in the 40px line
+    let (band_name, band_scale) =
+        fit_truncated(face, &info.band_name, max_width, COMPACT_BAND_SIZES, scale);
+    let band_y = text_area_top + px((COMPACT_BAND_SIZES[0] - band_scale.y / scale) / 2.0);
+    draw_text_centered(width, face, &band_name, band_scale, band_y, plot);
+
+    // Five lines only fit 18px apart
+    let (date_size, line_gap, line_sizes) = if detail.is_some() && note.is_some() {
+        (18.0, 18.0, DENSE_LINE_SIZES)
+    } else {
+        (20.0, 22.0, COMPACT_LINE_SIZES)
+    };
+
+    let date_y = text_area_top + px(40.0);
+    draw_text_centered(
         width,
-        &font,
-        &info.venue,
-        venue_scale,
-        venue_y,
-        text_color,
+        face,
+        &info.date,
+        PxScale::from(date_size * scale),
+        date_y,
+        plot,
     );
+
+    let (venue, venue_scale) = fit_abbreviated(face, &info.venue, max_width, line_sizes, scale);
+    let mut line_y = date_y + px(line_gap);
+    draw_text_centered(width, face, &venue, venue_scale, line_y, plot);
+
+    if let Some(detail) = detail {
+        let (detail, detail_scale) = fit_truncated(face, detail, max_width, line_sizes, scale);
+        line_y += px(line_gap);
+        draw_text_centered(width, face, &detail, detail_scale, line_y, plot);
+    }
+
+    if let Some(note) = note {
+        let (note, note_scale) = fit_truncated(face, note, max_width, line_sizes, scale);
+        line_y += px(line_gap);
+        draw_oblique_centered(width, face, &note, note_scale, line_y, plot);
+    }
+}
+
+/// Find the largest font size at which a line fits within max_width
+///
+/// Text that doesn't fit even at the smallest size is cut short with an ellipsis.
+pub(crate) fn fit_line(text: &str, max_width: f32, sizes: &[f32]) -> (PxScale, String) {
+    let (text, scale) = fit_truncated(typeface(), text, max_width, sizes, 1.0);
+    (scale, text)
+}
+
+/// Find the largest font size at which a line fits within max_width, cutting
+/// it short with an ellipsis at the smallest size if it still doesn't fit
+///
+/// Sizes are multiplied by `factor`.
+fn fit_truncated(
+    face: &Typeface,
+    text: &str,
+    max_width: f32,
+    sizes: &[f32],
+    factor: f32,
+) -> (String, PxScale) {
+    for &size in sizes {
+        let scale = PxScale::from(size * factor);
+        if face.line_width(text, scale) <= max_width {
+            return (text.to_string(), scale);
+        }
+    }
+
+    let scale = PxScale::from(sizes.last().copied().unwrap_or(20.0) * factor);
+    let mut truncated: String = text.trim_end().to_string();
+    while !truncated.is_empty() && face.line_width(&format!("{}…", truncated), scale) > max_width
+    {
+        truncated.pop();
+        truncated = truncated.trim_end().to_string();
+    }
+    (format!("{}…", truncated), scale)
+}
+
+/// Width of a line of text at a given scale
+pub(crate) fn line_width(text: &str, scale: PxScale) -> f32 {
+    typeface().line_width(text, scale)
+}
+
+/// Draw a line of text with its top-left corner at (x, y) onto an indexed buffer
+pub(crate) fn draw_line(
+    indexed: &mut [u8],
+    width: u32,
+    text: &str,
+    scale: PxScale,
+    x: u32,
+    y: u32,
+    color: u8,
+) {
+    draw_text_indexed(indexed, width, &typeface().font, text, scale, x, y, color);
+}
+
+/// Find the largest font size at which the text or one of its abbreviations fits
+///
+/// Every abbreviation is tried before moving down a size. Falls back to the
+/// most abbreviated form at the smallest size. Sizes are multiplied by `factor`.
+fn fit_abbreviated(
+    face: &Typeface,
+    text: &str,
+    max_width: f32,
+    sizes: &[f32],
+    factor: f32,
+) -> (String, PxScale) {
+    let variants = abbreviate::get().variants(text);
+    for &size in sizes {
+        let scale = PxScale::from(size * factor);
+        if let Some(variant) = variants
+            .iter()
+            .find(|variant| face.line_width(variant, scale) <= max_width)
+        {
+            return (variant.clone(), scale);
+        }
+    }
+
+    let smallest = sizes.last().copied().unwrap_or(16.0);
+    let shortest = variants.last().cloned().unwrap_or_default();
+    (shortest, PxScale::from(smallest * factor))
 }
 
 /// Find the largest font size that fits the text within max_width
-fn fit_text_size(font: &impl Font, text: &str, max_width: f32, sizes: &[f32]) -> (PxScale, u32) {
+///
+/// Sizes and the returned Y offset are multiplied by `factor`.
+fn fit_text_size(
+    face: &Typeface,
+    text: &str,
+    max_width: f32,
+    sizes: &[f32],
+    factor: f32,
+) -> (PxScale, u32) {
     for &size in sizes {
-        let scale = PxScale::from(size);
-        let text_width = measure_text_width(font, text, scale);
+        let scale = PxScale::from(size * factor);
+        let text_width = face.line_width(text, scale);
         if text_width <= max_width {
             // Y offset decreases as font gets smaller to keep text vertically centered
             let y_offset = match size as u32 {
@@ -165,12 +443,12 @@ fn fit_text_size(font: &impl Font, text: &str, max_width: f32, sizes: &[f32]) ->
                 24 => 12,
                 _ => 16,
             };
-            return (scale, y_offset);
+            return (scale, (y_offset as f32 * factor) as u32);
         }
     }
     // Fallback to smallest size
     let smallest = sizes.last().copied().unwrap_or(20.0);
-    (PxScale::from(smallest), 16)
+    (PxScale::from(smallest * factor), (16.0 * factor) as u32)
 }
 
 /// Measure the width of text at a given scale
@@ -184,31 +462,43 @@ fn measure_text_width(font: &impl Font, text: &str, scale: PxScale) -> f32 {
         .sum()
 }
 
-/// Draw text centered horizontally onto indexed buffer
-fn draw_text_indexed_centered(
-    indexed: &mut [u8],
+/// Draw text centered horizontally, passing each glyph pixel to `plot`
+fn draw_text_centered(
     width: u32,
-    font: &impl Font,
+    face: &Typeface,
     text: &str,
     scale: PxScale,
     y: u32,
-    color: u8,
+    plot: &mut impl FnMut(u32, u32, f32),
 ) {
-    let scaled_font = font.as_scaled(scale);
-
-    // Calculate text width
-    let text_width: f32 = text
-        .chars()
-        .map(|c| {
-            let glyph_id = font.glyph_id(c);
-            scaled_font.h_advance(glyph_id)
-        })
-        .sum();
+    let text_width = face.line_width(text, scale);
 
     // Center horizontally
     let x = ((width as f32 - text_width) / 2.0).max(0.0) as u32;
 
-    draw_text_indexed(indexed, width, font, text, scale, x, y, color);
+    draw_text(&face.font, text, scale, x, y, plot);
+}
+
+/// Draw text centered horizontally in a synthetic oblique, passing each glyph
+/// pixel to `plot`
+///
+/// The upright glyphs are sheared about the baseline, rows above it moving
+/// right by [`OBLIQUE_SLANT`] per pixel and rows below it left.
+fn draw_oblique_centered(
+    width: u32,
+    face: &Typeface,
+    text: &str,
+    scale: PxScale,
+    y: u32,
+    plot: &mut impl FnMut(u32, u32, f32),
+) {
+    let baseline = y as f32 + scale.y * 0.8;
+    draw_text_centered(width, face, text, scale, y, &mut |px, py, coverage| {
+        let shift = ((baseline - py as f32) * OBLIQUE_SLANT).round() as i64;
+        if let Ok(px) = u32::try_from(px as i64 + shift) {
+            plot(px, py, coverage);
+        }
+    });
 }
 
 /// Draw text at a specific position onto indexed buffer
@@ -222,10 +512,28 @@ fn draw_text_indexed(
     x: u32,
     y: u32,
     color: u8,
+) {
+    let height = indexed.len() as u32 / width;
+    draw_text(font, text, scale, x, y, &mut |px, py, coverage| {
+        // Hard edge threshold (0.5 for clean edges with bold fonts)
+        if px < width && py < height && coverage > 0.5 {
+            indexed[(py * width + px) as usize] = color;
+        }
+    });
+}
+
+/// Draw text with its top-left corner at (x, y), passing each glyph pixel
+/// and its coverage to `plot`
+fn draw_text(
+    font: &impl Font,
+    text: &str,
+    scale: PxScale,
+    x: u32,
+    y: u32,
+    plot: &mut impl FnMut(u32, u32, f32),
 ) {
     let scaled_font = font.as_scaled(scale);
     let mut cursor_x = x as f32;
-    let height = indexed.len() as u32 / width;
 
     for c in text.chars() {
         let glyph_id = font.glyph_id(c);
@@ -235,19 +543,65 @@ fn draw_text_indexed(
         if let Some(outlined) = font.outline_glyph(glyph) {
             let bounds = outlined.px_bounds();
             outlined.draw(|gx, gy, coverage| {
-                let px = bounds.min.x as u32 + gx;
-                let py = bounds.min.y as u32 + gy;
-
-                // Hard edge threshold (0.5 for clean edges with bold fonts)
-                if px < width && py < height && coverage > 0.5 {
-                    let idx = (py * width + px) as usize;
-                    if idx < indexed.len() {
-                        indexed[idx] = color;
-                    }
-                }
+                plot(bounds.min.x as u32 + gx, bounds.min.y as u32 + gy, coverage);
             });
         }
 
         cursor_x += scaled_font.h_advance(glyph_id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_width_matches_font() {
+        let face = typeface();
+        let lines = [
+            "Phish",
+            "Madison Square Garden, New York",
+            "Sigur Rós — Ágætis",
+            "日本武道館",
+        ];
+        // Caption sizes come from the tables, print sizes from the font
+        for size in [48.0, 24.0, 16.0, 18.0, 24.0 * 6.25] {
+            let scale = PxScale::from(size);
+            for line in lines {
+                let expected = measure_text_width(&face.font, line, scale);
+                assert_eq!(face.line_width(line, scale), expected);
+                // Remembered the second time
+                assert_eq!(face.line_width(line, scale), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_oblique_leans_right() {
+        let face = typeface();
+        let scale = PxScale::from(48.0);
+        // Leftmost pixel of the top and bottom rows of an upright "l"
+        let edges = |oblique: bool| {
+            let mut rows: HashMap<u32, u32> = HashMap::new();
+            let mut plot = |x: u32, y: u32, coverage: f32| {
+                if coverage > 0.5 {
+                    let left = rows.entry(y).or_insert(x);
+                    *left = (*left).min(x);
+                }
+            };
+            if oblique {
+                draw_oblique_centered(200, face, "l", scale, 0, &mut plot);
+            } else {
+                draw_text_centered(200, face, "l", scale, 0, &mut plot);
+            }
+            let top = rows.keys().min().unwrap();
+            let bottom = rows.keys().max().unwrap();
+            (rows[top], rows[bottom])
+        };
+
+        let (upright_top, upright_bottom) = edges(false);
+        let (oblique_top, oblique_bottom) = edges(true);
+        assert!(oblique_top > upright_top);
+        assert!(oblique_top - oblique_bottom > upright_top.abs_diff(upright_bottom));
+    }
+}
@@ -5,16 +5,49 @@
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use crate::experiment::DEFAULT_VARIANT;
+
 /// Available widgets
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum WidgetName {
     /// Concert history from SawThat.band
     Concerts,
+    /// Recently played albums from Spotify
+    Spotify,
+    /// Most played albums from Last.fm
+    Lastfm,
+    /// Upcoming events from an ICS calendar
+    Calendar,
+    /// The user's own photos from a folder, WebDAV or S3
+    Photos,
+}
+
+impl WidgetName {
+    /// Every widget, in display order
+    pub const ALL: [WidgetName; 5] = [
+        WidgetName::Concerts,
+        WidgetName::Spotify,
+        WidgetName::Lastfm,
+        WidgetName::Calendar,
+        WidgetName::Photos,
+    ];
+}
+
+impl std::fmt::Display for WidgetName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WidgetName::Concerts => write!(f, "concerts"),
+            WidgetName::Spotify => write!(f, "spotify"),
+            WidgetName::Lastfm => write!(f, "lastfm"),
+            WidgetName::Calendar => write!(f, "calendar"),
+            WidgetName::Photos => write!(f, "photos"),
+        }
+    }
 }
 
 /// Display orientation
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum Orientation {
     /// Horizontal: 400x480 (half) or 800x480 (full)
@@ -45,7 +78,7 @@ impl std::fmt::Display for Orientation {
 }
 
 /// Widget item width
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(into = "u8", try_from = "u8")]
 pub enum WidgetWidth {
     /// Half width: 400x480 pixels
@@ -101,5 +134,241 @@ impl std::fmt::Display for CachePolicy {
     }
 }
 
-/// Widget data response (array of image paths)
-pub type WidgetData = Vec<String>;
+/// `X-Cache-Policy` header sent with widget data, e.g.
+/// `max-age=900, stale-while-revalidate=900, suggested-sleep=900`
+///
+/// Comma-separated `directive=seconds` pairs, always in this order, with
+/// `immutable` in place of `max-age` for data that never expires. Directives
+/// without a value are left out. Parsers must skip directives they don't know,
+/// so new ones can be added without breaking deployed frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CachePolicyHeader {
+    /// Seconds the item list stays fresh, None if it never expires
+    pub max_age: Option<u32>,
+    /// Seconds past `max_age` a device may keep showing its cached list while
+    /// it fetches a new one
+    pub stale_while_revalidate: Option<u32>,
+    /// Seconds the device should sleep before checking back, when sooner than
+    /// its refresh interval (e.g. after being served stale data)
+    pub suggested_sleep: Option<u32>,
+}
+
+impl CachePolicyHeader {
+    /// Header name
+    pub const NAME: &'static str = "x-cache-policy";
+
+    /// Header for data cached under `policy`, revalidated within one more TTL
+    pub fn new(policy: &CachePolicy) -> Self {
+        let max_age = match policy {
+            CachePolicy::Max => None,
+            CachePolicy::Ttl(secs) => Some(*secs),
+        };
+        Self {
+            max_age,
+            stale_while_revalidate: max_age,
+            suggested_sleep: None,
+        }
+    }
+
+    /// Ask the device to check back within `secs`
+    pub fn with_suggested_sleep(self, secs: u32) -> Self {
+        Self {
+            suggested_sleep: Some(secs),
+            ..self
+        }
+    }
+}
+
+impl std::fmt::Display for CachePolicyHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.max_age {
+            Some(secs) => write!(f, "max-age={}", secs)?,
+            None => write!(f, "immutable")?,
+        }
+        if let Some(secs) = self.stale_while_revalidate {
+            write!(f, ", stale-while-revalidate={}", secs)?;
+        }
+        if let Some(secs) = self.suggested_sleep {
+            write!(f, ", suggested-sleep={}", secs)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for CachePolicyHeader {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut header = Self::default();
+        let mut expires = false;
+        for directive in value.split(',').map(str::trim) {
+            if directive == "immutable" {
+                expires = true;
+                continue;
+            }
+            let Some((name, secs)) = directive.split_once('=') else {
+                continue;
+            };
+            let field = match name {
+                "max-age" => &mut header.max_age,
+                "stale-while-revalidate" => &mut header.stale_while_revalidate,
+                "suggested-sleep" => &mut header.suggested_sleep,
+                _ => continue,
+            };
+            *field = Some(secs.parse().map_err(|_| "Invalid cache policy seconds")?);
+            if name == "max-age" {
+                expires = true;
+            }
+        }
+        if !expires {
+            return Err("Cache policy needs max-age or immutable");
+        }
+        Ok(header)
+    }
+}
+
+/// A widget item as listed to devices, e.g.
+/// `{"path": "2025-01-01-abcd1234", "width": 1, "cache_key": "2025-01-01-abcd1234", "title": "Phish"}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct WidgetItem {
+    /// Image path, as requested from `/{widget}/{orientation}/{path}`
+    pub path: String,
+    /// Width in horizontal mode
+    pub width: WidgetWidth,
+    /// Key the device caches the rendered image under, changes whenever the
+    /// rendering does for reasons other than the path (at most 48 characters)
+    pub cache_key: String,
+    /// Seconds before the image may be re-rendered differently under the same
+    /// cache key, omitted if it never is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    /// What the item shows, for logs and listings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Description of the card for screen readers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt: Option<String>,
+}
+
+impl WidgetItem {
+    /// A half-width item cached under its path
+    pub fn new(path: String) -> Self {
+        Self {
+            cache_key: path.clone(),
+            path,
+            width: WidgetWidth::Half,
+            ttl: None,
+            title: None,
+            alt: None,
+        }
+    }
+
+    pub fn with_width(self, width: WidgetWidth) -> Self {
+        Self { width, ..self }
+    }
+
+    pub fn with_ttl(self, ttl: u32) -> Self {
+        Self {
+            ttl: Some(ttl),
+            ..self
+        }
+    }
+
+    pub fn with_title(self, title: impl Into<String>) -> Self {
+        Self {
+            title: Some(title.into()),
+            ..self
+        }
+    }
+
+    pub fn with_alt(self, alt: impl Into<String>) -> Self {
+        Self {
+            alt: Some(alt.into()),
+            ..self
+        }
+    }
+
+    /// This item as rendered with the named variant
+    ///
+    /// Other variants are cached under a checksum of the path and variant, so a
+    /// device whose variant changes doesn't keep showing its old renders.
+    pub fn for_variant(self, variant: &str) -> Self {
+        if variant == DEFAULT_VARIANT {
+            return self;
+        }
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(self.path.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(variant.as_bytes());
+        Self {
+            cache_key: format!("{:08x}", hasher.finalize()),
+            ..self
+        }
+    }
+}
+
+/// Widget data response (array of items)
+pub type WidgetData = Vec<WidgetItem>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_widget_item() {
+        let items = vec![
+            WidgetItem::new("2025-01-01-half".to_string()).with_title("Phish"),
+            WidgetItem::new("2025-01-01-full".to_string())
+                .with_width(WidgetWidth::Full)
+                .with_ttl(900),
+        ];
+        assert_eq!(
+            serde_json::to_string(&items).unwrap(),
+            concat!(
+                r#"[{"path":"2025-01-01-half","width":1,"cache_key":"2025-01-01-half","title":"Phish"},"#,
+                r#"{"path":"2025-01-01-full","width":2,"cache_key":"2025-01-01-full","ttl":900}]"#
+            )
+        );
+        assert_eq!(Orientation::Horiz.dimensions(WidgetWidth::Full), (800, 480));
+
+        // Other variants get their own cache key
+        let item = WidgetItem::new("2025-01-01-half".to_string());
+        assert_eq!(item.clone().for_variant(DEFAULT_VARIANT), item);
+        let atkinson = item.clone().for_variant("default+atkinson");
+        assert_eq!(atkinson.path, item.path);
+        assert_eq!(atkinson.cache_key.len(), 8);
+        assert_ne!(
+            atkinson.cache_key,
+            item.clone().for_variant("default+bw").cache_key
+        );
+    }
+
+    #[test]
+    fn test_cache_policy_header() {
+        // The exact strings the firmware parses
+        let ttl = CachePolicyHeader::new(&CachePolicy::Ttl(900));
+        assert_eq!(ttl.to_string(), "max-age=900, stale-while-revalidate=900");
+        let stale = ttl.with_suggested_sleep(600);
+        assert_eq!(
+            stale.to_string(),
+            "max-age=900, stale-while-revalidate=900, suggested-sleep=600"
+        );
+        let max = CachePolicyHeader::new(&CachePolicy::Max);
+        assert_eq!(max.to_string(), "immutable");
+        assert_eq!(
+            max.with_suggested_sleep(60).to_string(),
+            "immutable, suggested-sleep=60"
+        );
+
+        for header in [ttl, stale, max] {
+            assert_eq!(header.to_string().parse(), Ok(header));
+        }
+        // Unknown directives and spacing are tolerated, missing freshness isn't
+        assert_eq!(
+            "max-age=900,no-transform, future=1,stale-while-revalidate=900".parse(),
+            Ok(ttl)
+        );
+        assert!("suggested-sleep=600".parse::<CachePolicyHeader>().is_err());
+        assert!("max-age=soon".parse::<CachePolicyHeader>().is_err());
+    }
+}
@@ -0,0 +1,416 @@
+//! End-to-end test of the device-facing contract
+//!
+//! Boots the server binary against mocked upstream APIs (SawThat, Deezer and the
+//! image hosts, served by a local axum app), then replays the requests a frame
+//! makes on a wake: config, widget data, the two images of a horizontal refresh,
+//! and the prefetch of the next one, revalidated with its ETag as the SD cache
+//! would. Run with `cargo test --features integration`.
+//!
+//! The upstreams are a plain axum [`Router`] rather than `wiremock`: the server
+//! already depends on axum, so the feature pulls in no extra crates, and each
+//! mock is an ordinary handler that can count its requests and check the path
+//! it was called with, as the album and image routes do.
+
+#![cfg(feature = "integration")]
+
+use std::collections::HashMap;
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{Path, Query, State};
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use reqwest::{Client, Response, StatusCode};
+use serde_json::{json, Value};
+
+/// Device ID sent with every request, as the firmware derives from its MAC
+const DEVICE_ID: &str = "a1b2c3d4e5f6";
+
+/// Requests received by the mocked upstreams
+#[derive(Default)]
+struct Upstream {
+    base: String,
+    bands: AtomicUsize,
+    images: AtomicUsize,
+}
+
+/// Serve the mocked upstream APIs, returning their base URL and request counts
+async fn mock_upstream() -> Arc<Upstream> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream = Arc::new(Upstream {
+        base: format!("http://{}", listener.local_addr().unwrap()),
+        ..Default::default()
+    });
+
+    let app = Router::new()
+        .route("/api/bands", get(bands))
+        .route("/deezer/search/artist", get(search_artist))
+        .route("/deezer/artist/{id}/albums", get(artist_albums))
+        .route("/musicbrainz/artist", get(search_musicbrainz))
+        .route("/images/{name}", get(image))
+        .with_state(upstream.clone());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    upstream
+}
+
+/// Two bands: one with Deezer album art, one falling back to its picture
+async fn bands(State(upstream): State<Arc<Upstream>>) -> Json<Value> {
+    upstream.bands.fetch_add(1, Ordering::SeqCst);
+    Json(json!([
+        {
+            "band": "Mock Band",
+            "picture": format!("{}/images/mock.png", upstream.base),
+            "concerts": [
+                {"date": "01-06-2024", "location": "Palace Theatre, Albany, NY"},
+                {"date": "15-07-2023", "location": "SPAC, Saratoga, NY"}
+            ],
+            "id": "band-one",
+            "genre": "rock"
+        },
+        {
+            "band": "Other Band",
+            "picture": format!("{}/images/other.png", upstream.base),
+            "concerts": [{"date": "20-03-2022", "location": "Empire Live"}],
+            "id": "band-two",
+            "genre": "jazz"
+        }
+    ]))
+}
+
+async fn search_artist(Query(query): Query<HashMap<String, String>>) -> Json<Value> {
+    match query.get("q").map(String::as_str) {
+        Some("Mock Band") => Json(json!({"data": [{"id": 1}]})),
+        _ => Json(json!({"data": []})),
+    }
+}
+
+async fn artist_albums(State(upstream): State<Arc<Upstream>>, Path(id): Path<u64>) -> Json<Value> {
+    assert_eq!(id, 1);
+    Json(json!({"data": [{
+        "title": "Debut",
+        "release_date": "2020-01-01",
+        "cover_xl": format!("{}/images/debut.png", upstream.base),
+        "cover_big": null
+    }]}))
+}
+
+/// Neither band is on MusicBrainz either
+async fn search_musicbrainz() -> Json<Value> {
+    Json(json!({"artists": []}))
+}
+
+/// A small gradient PNG, different per name
+async fn image(
+    State(upstream): State<Arc<Upstream>>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    upstream.images.fetch_add(1, Ordering::SeqCst);
+    let seed = name.len() as u8;
+    let source = image::RgbImage::from_fn(64, 64, |x, y| {
+        image::Rgb([x as u8 * 4, y as u8 * 4, seed.wrapping_mul(20)])
+    });
+    let mut png = Vec::new();
+    source
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    ([(header::CONTENT_TYPE, "image/png")], png)
+}
+
+/// The server binary, killed when dropped
+struct Server {
+    child: Child,
+    base: String,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Boot the server against the mocked upstreams and wait until it is healthy
+async fn spawn_server(upstream: &Upstream) -> Server {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let child = Command::new(env!("CARGO_BIN_EXE_sawthat-frame-server"))
+        // Nothing from the developer's environment (credentials, cache dirs)
+        .env_clear()
+        .env("PORT", port.to_string())
+        .env("SAWTHAT_API_URL", format!("{}/api/bands", upstream.base))
+        .env("DEEZER_API_URL", format!("{}/deezer", upstream.base))
+        .env(
+            "MUSICBRAINZ_API_URL",
+            format!("{}/musicbrainz", upstream.base),
+        )
+        .env("RUST_LOG", "warn")
+        .spawn()
+        .expect("server binary starts");
+    let server = Server {
+        child,
+        base: format!("http://127.0.0.1:{}", port),
+    };
+
+    let client = Client::new();
+    for _ in 0..100 {
+        if let Ok(response) = client.get(format!("{}/health", server.base)).send().await {
+            if response.status().is_success() {
+                return server;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("server did not become healthy");
+}
+
+/// GET a path with the headers the firmware sends
+async fn device_get(client: &Client, server: &Server, path: &str, etag: Option<&str>) -> Response {
+    let mut request = client
+        .get(format!("{}{}", server.base, path))
+        .header("X-Device-Id", DEVICE_ID)
+        .header("X-Firmware-Version", "0.1.0")
+        .header("X-Battery-Percent", "87");
+    if let Some(etag) = etag {
+        request = request.header("If-None-Match", etag);
+    }
+    request.send().await.unwrap()
+}
+
+/// The ETag the firmware parses: a quoted CRC-32 of the body, in hex
+fn etag(response: &Response) -> String {
+    let etag = response.headers()[header::ETAG]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let hex = etag.trim_matches('"');
+    assert_eq!(etag.len(), 10, "ETag {} is not a quoted CRC-32", etag);
+    assert!(u32::from_str_radix(hex, 16).is_ok());
+    etag
+}
+
+/// Fetch an image like the firmware, checking the payload it will decode and cache
+async fn fetch_image(client: &Client, server: &Server, path: &str) -> String {
+    let response = device_get(client, server, &format!("/concerts/horiz/{}", path), None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+    let etag = etag(&response);
+    let body = response.bytes().await.unwrap();
+
+    // The ETag is the CRC-32 the SD cache stores in the image footer
+    assert_eq!(etag, format!("\"{:08x}\"", crc32fast::hash(&body)));
+
+    // A half of the horizontal panel, as an indexed PNG the firmware can decode
+    let decoder = png::Decoder::new(std::io::Cursor::new(&body));
+    let reader = decoder.read_info().unwrap();
+    let info = reader.info();
+    assert_eq!((info.width, info.height), (400, 480));
+    assert_eq!(info.color_type, png::ColorType::Indexed);
+
+    etag
+}
+
+#[tokio::test]
+async fn test_firmware_wake() {
+    let upstream = mock_upstream().await;
+    let server = spawn_server(&upstream).await;
+    let client = Client::new();
+
+    // Device settings
+    let response = device_get(&client, &server, "/config", None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let config: Value = response.json().await.unwrap();
+    assert!(config["refresh_interval_secs"].as_u64().unwrap() > 0);
+
+    // Widget data: newest concert first, as the firmware's item objects
+    let response = device_get(&client, &server, "/concerts", None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("x-data-stale").is_none());
+    // Directives the firmware parses, with no suggested sleep while fresh
+    assert_eq!(
+        response.headers()["x-cache-policy"],
+        "max-age=86400, stale-while-revalidate=86400"
+    );
+    let data_etag = etag(&response);
+    let items: Vec<Value> = response.json().await.unwrap();
+    let paths: Vec<&str> = items
+        .iter()
+        .map(|item| item["path"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            "2024-06-01-band-one",
+            "2023-07-15-band-one",
+            "2022-03-20-band-two"
+        ]
+    );
+    for item in &items {
+        assert_eq!(item["width"], 1);
+        // Within the firmware's 48-byte cache key and 32-byte title
+        assert_eq!(item["cache_key"], item["path"]);
+        assert!(item["cache_key"].as_str().unwrap().len() <= 48);
+        assert!(item["title"].as_str().unwrap().len() <= 32);
+        assert_eq!(item["ttl"], 86400);
+    }
+    assert_eq!(items[0]["title"], "Mock Band");
+
+    // Unchanged widget data is a bodyless 304, served without asking the upstream
+    let response = device_get(&client, &server, "/concerts", Some(&data_etag)).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert!(response.bytes().await.unwrap().is_empty());
+    assert_eq!(upstream.bands.load(Ordering::SeqCst), 1);
+
+    // Both halves of a horizontal refresh
+    fetch_image(&client, &server, paths[0]).await;
+    fetch_image(&client, &server, paths[1]).await;
+
+    // Prefetch of the next item, then its revalidation on a later wake
+    let next_etag = fetch_image(&client, &server, paths[2]).await;
+    let images_fetched = upstream.images.load(Ordering::SeqCst);
+    let response = device_get(
+        &client,
+        &server,
+        &format!("/concerts/horiz/{}", paths[2]),
+        Some(&next_etag),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert!(response.bytes().await.unwrap().is_empty());
+
+    // Rendered images are cached: revalidating doesn't refetch the source
+    assert_eq!(upstream.images.load(Ordering::SeqCst), images_fetched);
+    assert_eq!(upstream.bands.load(Ordering::SeqCst), 1);
+
+    // Built with RAW_IMAGES, the firmware asks for packed 4bpp halves instead
+    let response = client
+        .get(format!("{}/concerts/horiz/{}", server.base, paths[0]))
+        .header("X-Device-Id", DEVICE_ID)
+        .header("Accept", "application/x-epd-4bpp, image/png;q=0.5")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/x-epd-4bpp"
+    );
+    assert_eq!(response.headers()["x-epd-width"], "400");
+    let body = response.bytes().await.unwrap();
+    assert_eq!(body.len(), 400 / 2 * 480);
+
+    // Vertical images come pre-rotated into panel geometry, rows ready to copy
+    let response = device_get(
+        &client,
+        &server,
+        &format!("/concerts/vert/{}?rotate=ccw90", paths[0]),
+        None,
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.bytes().await.unwrap();
+    let decoder = png::Decoder::new(std::io::Cursor::new(&body));
+    let reader = decoder.read_info().unwrap();
+    assert_eq!((reader.info().width, reader.info().height), (800, 480));
+
+    // A print of a card shares its path with the frame's images
+    let response = client
+        .get(format!(
+            "{}/concerts/{}/print?dpi=100",
+            server.base, paths[0]
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.bytes().await.unwrap();
+    let decoder = png::Decoder::new(std::io::Cursor::new(&body));
+    let reader = decoder.read_info().unwrap();
+    assert_eq!((reader.info().width, reader.info().height), (800, 960));
+    assert_eq!(reader.info().color_type, png::ColorType::Rgb);
+    let response = client
+        .get(format!(
+            "{}/concerts/{}/print?dpi=9000",
+            server.base, paths[0]
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Previews show the same card enlarged in the panel's colors
+    let response = client
+        .get(format!(
+            "{}/concerts/vert/{}/preview?scale=1&texture=true",
+            server.base, paths[0]
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.bytes().await.unwrap();
+    let decoder = png::Decoder::new(std::io::Cursor::new(&body));
+    let reader = decoder.read_info().unwrap();
+    assert_eq!((reader.info().width, reader.info().height), (480, 800));
+    assert_eq!(reader.info().color_type, png::ColorType::Rgb);
+
+    // Companion apps get the same concerts as a JSON:API document
+    let response = client
+        .get(format!(
+            "{}/api/v1/concerts?page[number]=2&page[size]=2",
+            server.base
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/vnd.api+json"
+    );
+    let document: Value = response.json().await.unwrap();
+    assert_eq!(document["meta"]["total"], 3);
+    assert_eq!(document["data"][0]["id"], paths[2]);
+    assert_eq!(document["data"][0]["attributes"]["venue"], "Empire Live");
+    assert!(document["links"].get("next").is_none());
+    let images = document["data"][0]["attributes"]["images"]
+        .as_array()
+        .unwrap();
+    let panel = images
+        .iter()
+        .find(|image| image["name"] == "panel_horiz")
+        .unwrap();
+    let response = client
+        .get(format!(
+            "{}{}",
+            server.base,
+            panel["href"].as_str().unwrap()
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+
+    // Items that are gone upstream are a 404 the firmware skips past
+    let response = device_get(
+        &client,
+        &server,
+        "/concerts/horiz/2024-06-01-no-such-band",
+        None,
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // The wake shows up in the device list
+    let response = device_get(&client, &server, "/devices", None).await;
+    let devices: Vec<Value> = response.json().await.unwrap();
+    assert!(devices.iter().any(|device| device["id"] == DEVICE_ID));
+}